@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::iter::FromIterator;
+
+/// The header lines of an armored block, in the order they appear.
+///
+/// Keys can occur multiple times, like `Comment`, so every line is kept, and written
+/// back unchanged when the headers are passed on to [`write`](crate::armor::write).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Headers {
+    lines: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the value of the first line with the given key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).next()
+    }
+
+    /// Returns the values of all lines with the given key, in order.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.lines
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Appends a line, keeping existing lines with the same key.
+    pub fn push(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.lines.push((key.into(), value.into()));
+    }
+
+    /// Sets the value of `key`, replacing all lines with that key by a single one, at the
+    /// position of the first of them.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let key = key.into();
+        match self.lines.iter().position(|(k, _)| *k == key) {
+            Some(pos) => {
+                self.lines[pos].1 = value.into();
+                let mut i = 0;
+                self.lines.retain(|(k, _)| {
+                    i += 1;
+                    i <= pos + 1 || *k != key
+                });
+            }
+            None => self.lines.push((key, value.into())),
+        }
+    }
+
+    /// Returns all lines, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Headers {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Headers {
+            lines: iter
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        }
+    }
+}
+
+/// One line per entry, sorted by key.
+impl From<BTreeMap<String, String>> for Headers {
+    fn from(map: BTreeMap<String, String>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers() {
+        let mut headers: Headers = vec![("Comment", "one"), ("Version", "1"), ("Comment", "two")]
+            .into_iter()
+            .collect();
+        assert_eq!(headers.get("Comment"), Some("one"));
+        assert_eq!(
+            headers.get_all("Comment").collect::<Vec<_>>(),
+            vec!["one", "two"]
+        );
+        assert_eq!(headers.get("Charset"), None);
+
+        headers.push("Comment", "three");
+        assert_eq!(headers.len(), 4);
+
+        headers.insert("Comment", "only");
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            vec![("Comment", "only"), ("Version", "1")]
+        );
+
+        headers.insert("Charset", "UTF-8");
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            vec![("Comment", "only"), ("Version", "1"), ("Charset", "UTF-8")]
+        );
+    }
+}
@@ -2,8 +2,10 @@
 //!
 //! Armor module provides implementation of ASCII Armor as specified in RFC 4880.
 
+mod headers;
 mod reader;
 mod writer;
 
+pub use self::headers::*;
 pub use self::reader::*;
 pub use self::writer::*;
@@ -1,4 +1,3 @@
-use std::collections::BTreeMap;
 use std::hash::Hasher;
 use std::io::prelude::*;
 use std::{fmt, io, str};
@@ -8,11 +7,13 @@ use byteorder::{BigEndian, ByteOrder};
 
 use nom::{self, digit, line_ending, not_line_ending, InputIter, InputLength, Slice};
 
+use crate::armor::Headers;
 use crate::base64_decoder::Base64Decoder;
 use crate::base64_reader::Base64Reader;
 use crate::errors::Result;
 use crate::line_reader::LineReader;
 use crate::ser::Serialize;
+use crate::types::Tag;
 
 /// Armor block types.
 ///
@@ -57,6 +58,45 @@ impl Serialize for BlockType {
 }
 
 impl BlockType {
+    /// Returns the block type for data starting with a packet of the given tag.
+    pub fn for_tag(tag: Tag) -> Option<Self> {
+        match tag {
+            Tag::PublicKey => Some(BlockType::PublicKey),
+            Tag::SecretKey => Some(BlockType::PrivateKey),
+            Tag::Signature => Some(BlockType::Signature),
+            Tag::PublicKeyEncryptedSessionKey
+            | Tag::SymKeyEncryptedSessionKey
+            | Tag::OnePassSignature
+            | Tag::CompressedData
+            | Tag::SymEncryptedData
+            | Tag::Marker
+            | Tag::LiteralData
+            | Tag::SymEncryptedProtectedData => Some(BlockType::Message),
+            _ => None,
+        }
+    }
+
+    /// Checks if data of this block type may start with a packet of the given tag.
+    pub fn allows_tag(&self, tag: Tag) -> bool {
+        match self {
+            // revocation certificates are exported as public key blocks
+            BlockType::PublicKey => tag == Tag::PublicKey || tag == Tag::Signature,
+            BlockType::PrivateKey => tag == Tag::SecretKey,
+            BlockType::Signature => tag == Tag::Signature,
+            // old style signed messages start with the signature
+            BlockType::Message => {
+                BlockType::for_tag(tag) == Some(BlockType::Message) || tag == Tag::Signature
+            }
+            BlockType::MultiPartMessage(_, _) | BlockType::File => true,
+            BlockType::PublicKeyPKCS1(_)
+            | BlockType::PublicKeyPKCS8
+            | BlockType::PublicKeyOpenssh
+            | BlockType::PrivateKeyPKCS1(_)
+            | BlockType::PrivateKeyPKCS8
+            | BlockType::PrivateKeyOpenssh => false,
+        }
+    }
+
     fn as_string(&self) -> String {
         match self {
             BlockType::PublicKey => "PGP PUBLIC KEY BLOCK".into(),
@@ -213,18 +253,12 @@ named!(
 
 // Parses the full armor header.
 named!(
-    armor_headers<BTreeMap<String, String>>,
-    do_parse!(
-        pairs: key_value_pairs
-            >> (pairs
-                .iter()
-                .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
-                .collect())
-    )
+    armor_headers<Headers>,
+    do_parse!(pairs: key_value_pairs >> (pairs.into_iter().collect()))
 );
 
 // Armor Header
-named!(armor_header(&[u8]) -> (BlockType, BTreeMap<String, String>), do_parse!(
+named!(armor_header(&[u8]) -> (BlockType, Headers), do_parse!(
     typ:     armor_header_line >>
     headers: armor_headers     >>
     (typ, headers)
@@ -246,7 +280,7 @@ fn read_checksum(input: &[u8]) -> ::std::io::Result<u64> {
 }
 
 #[rustfmt::skip]
-named!(header_parser(&[u8]) -> (BlockType, BTreeMap<String, String>), do_parse!(
+named!(header_parser(&[u8]) -> (BlockType, Headers), do_parse!(
                take_until!("-----")
     >>   head: armor_header
     >>         many0!(line_ending)
@@ -288,8 +322,8 @@ named!(armor_footer_line<BlockType>, do_parse!(
 pub struct Dearmor<R> {
     /// The ascii armor parsed block type.
     pub typ: Option<BlockType>,
-    /// The headers found in the armored file.
-    pub headers: BTreeMap<String, String>,
+    /// The headers found in the armored file, in order.
+    pub headers: Headers,
     /// Optional crc checksum
     pub checksum: Option<u64>,
     /// track what we are currently parsing
@@ -317,7 +351,7 @@ impl<R: Read + Seek> Dearmor<R> {
     pub fn new(input: R) -> Self {
         Dearmor {
             typ: None,
-            headers: BTreeMap::new(),
+            headers: Headers::new(),
             checksum: None,
             current_part: Part::Header,
             base_decoder: None,
@@ -506,9 +540,7 @@ mod tests {
     use crate::errors::Result;
 
     // helper function to parse all data at once
-    pub fn parse<R: Read + Seek>(
-        mut input: R,
-    ) -> Result<(BlockType, BTreeMap<String, String>, Vec<u8>)> {
+    pub fn parse<R: Read + Seek>(mut input: R) -> Result<(BlockType, Headers, Vec<u8>)> {
         let mut dearmor = Dearmor::new(input.by_ref());
 
         // estimate size
@@ -538,7 +570,7 @@ mod tests {
 
     #[test]
     fn test_armor_headers() {
-        let mut map = BTreeMap::new();
+        let mut map = Headers::new();
         map.insert("Version".to_string(), "12".to_string());
         map.insert("special-stuff".to_string(), "cool12.0".to_string());
         map.insert("some:colon".to_string(), "with:me".to_string());
@@ -550,11 +582,21 @@ mod tests {
             .unwrap(),
             (&b""[..], map)
         );
+
+        // repeated keys are kept, in order
+        let map: Headers = vec![("Comment", "one"), ("Version", "12"), ("Comment", "two")]
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            armor_headers(&b"Comment: one\nVersion: 12\nComment: two\n"[..]).unwrap(),
+            (&b""[..], map)
+        );
     }
 
     #[test]
     fn test_armor_header() {
-        let mut map = BTreeMap::new();
+        let mut map = Headers::new();
         map.insert("Version".to_string(), "1.0".to_string());
         map.insert("Mode".to_string(), "Test".to_string());
 
@@ -563,7 +605,7 @@ mod tests {
             (&b""[..], (BlockType::Message, map))
         );
 
-        let mut map = BTreeMap::new();
+        let mut map = Headers::new();
         map.insert("Version".to_string(), "GnuPG v1".to_string());
 
         assert_eq!(
@@ -575,7 +617,7 @@ mod tests {
 
     #[test]
     fn test_parse_armor_small() {
-        let mut map = BTreeMap::new();
+        let mut map = Headers::new();
         map.insert("Version".to_string(), "GnuPG v1".to_string());
 
         let c = Cursor::new(
@@ -594,7 +636,7 @@ mod tests {
 
     #[test]
     fn test_parse_armor_missing_header_value() {
-        let mut map = BTreeMap::new();
+        let mut map = Headers::new();
         map.insert("NoVal".to_string(), "".to_string());
 
         let c = Cursor::new(
@@ -616,7 +658,7 @@ mod tests {
 
     #[test]
     fn test_parse_armor_two_entries() {
-        let mut map = BTreeMap::new();
+        let mut map = Headers::new();
         map.insert("hello".to_string(), "world".to_string());
 
         let c = Cursor::new(
@@ -642,7 +684,7 @@ mod tests {
 
     #[test]
     fn test_parse_armor_full() {
-        let mut map = BTreeMap::new();
+        let mut map = Headers::new();
         map.insert("Version".to_string(), "GnuPG v1".to_string());
 
         let c = Cursor::new(
@@ -733,7 +775,7 @@ y5Zgv9TWZlmW9FDTp4XVgn5zQTEN1LdL7vNXWV9aOvfrqPk5ClBkxhndgq7j6MFs
 
     #[test]
     fn test_dearmor_small_stream() {
-        let mut map = BTreeMap::new();
+        let mut map = Headers::new();
         map.insert("Version".to_string(), "GnuPG v1".to_string());
 
         let c = Cursor::new(
@@ -1,12 +1,12 @@
-use std::collections::BTreeMap;
 use std::hash::Hasher;
-use std::io::Write;
+use std::io::{Read, Seek, Write};
 
 use crc24::Crc24Hasher;
 
-use crate::armor::BlockType;
+use crate::armor::{BlockType, Dearmor, Headers};
 use crate::errors::Result;
 use crate::line_writer::{LineBreak, LineWriter};
+use crate::packet::PacketParser;
 use crate::ser::Serialize;
 use crate::util::TeeWriter;
 use generic_array::typenum::U64;
@@ -15,7 +15,7 @@ pub fn write(
     source: &impl Serialize,
     typ: BlockType,
     writer: &mut impl Write,
-    headers: Option<&BTreeMap<String, String>>,
+    headers: Option<&Headers>,
 ) -> Result<()> {
     // write armor header
     writer.write_all(&b"-----BEGIN "[..])?;
@@ -67,6 +67,113 @@ pub fn write(
     Ok(())
 }
 
+/// Number of base64 characters per line.
+const LINE_LEN: usize = 64;
+
+/// Armors `source` as a multipart message, so that each part, including its armor,
+/// is at most `max_size` bytes long.
+///
+/// The parts use the block type [`BlockType::MultiPartMessage`] and each carry the given
+/// headers and their own checksum. Receivers have to dearmor the parts in order and
+/// concatenate the results.
+pub fn write_multipart(
+    source: &impl Serialize,
+    max_size: usize,
+    headers: Option<&Headers>,
+) -> Result<Vec<Vec<u8>>> {
+    let data = source.to_bytes()?;
+    let headers_len: usize = headers
+        .map(|headers| {
+            headers
+                .iter()
+                .map(|(key, value)| key.len() + value.len() + 3)
+                .sum()
+        })
+        .unwrap_or_default();
+
+    // The overhead grows with the number of digits in the part numbers,
+    // so recompute it until the number of parts is stable.
+    let mut count = 1;
+    let chunk_size = loop {
+        let typ_len = BlockType::MultiPartMessage(count, count).to_string().len();
+        // begin and end lines, the empty line after the headers and the checksum line
+        let overhead = 2 * typ_len + 39 + headers_len;
+        // full lines of 64 base64 characters encode 48 bytes
+        let lines = max_size.saturating_sub(overhead) / (LINE_LEN + 1);
+        ensure!(
+            lines > 0,
+            "a part size of {} bytes is too small for the armor",
+            max_size
+        );
+
+        let chunk_size = lines * 48;
+        let needed = std::cmp::max(1, (data.len() + chunk_size - 1) / chunk_size);
+        if needed <= count {
+            break chunk_size;
+        }
+        count = needed;
+    };
+
+    let parts: Vec<_> = if data.is_empty() {
+        vec![&data[..]]
+    } else {
+        data.chunks(chunk_size).collect()
+    };
+    let count = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let mut buf = Vec::with_capacity(max_size);
+            write(
+                &RawData(part),
+                BlockType::MultiPartMessage(i + 1, count),
+                &mut buf,
+                headers,
+            )?;
+            debug_assert!(buf.len() <= max_size);
+
+            Ok(buf)
+        })
+        .collect()
+}
+
+/// Rewrites an armored block, replacing its block type with the one matching its contents.
+/// The armor headers are kept. Returns the new block type.
+///
+/// Note that revocation certificates are relabeled as signatures.
+pub fn relabel<R: Read + Seek>(input: R, writer: &mut impl Write) -> Result<BlockType> {
+    let mut dearmor = Dearmor::new(input);
+    dearmor.read_header()?;
+
+    let mut data = Vec::new();
+    dearmor.read_to_end(&mut data)?;
+
+    let tag = match PacketParser::new(&data[..]).next() {
+        Some(packet) => packet?.tag(),
+        None => bail!("empty armor block"),
+    };
+    let typ = match BlockType::for_tag(tag) {
+        Some(typ) => typ,
+        None => bail!("no armor block type for {:?}", tag),
+    };
+
+    write(&RawData(&data), typ, writer, Some(&dearmor.headers))?;
+
+    Ok(typ)
+}
+
+/// Already serialized data.
+struct RawData<'a>(&'a [u8]);
+
+impl Serialize for RawData<'_> {
+    fn to_writer<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(self.0)?;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,6 +198,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_write_repeated_headers() {
+        let mut headers = Headers::new();
+        headers.push("Comment", "exported by a");
+        headers.push("Version", "1");
+        headers.push("Comment", "signed by b");
+
+        let source = TestSource::new(b"hello world".to_vec());
+        let mut armor = Vec::new();
+        write(&source, BlockType::Message, &mut armor, Some(&headers)).unwrap();
+        let armor = String::from_utf8(armor).unwrap();
+        assert!(armor.starts_with(
+            "-----BEGIN PGP MESSAGE-----\n\
+             Comment: exported by a\n\
+             Version: 1\n\
+             Comment: signed by b\n\n"
+        ));
+
+        // the headers survive a round trip
+        let mut dearmor = Dearmor::new(io::Cursor::new(armor.as_bytes()));
+        dearmor.read_header().unwrap();
+        let mut content = Vec::new();
+        dearmor.read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"hello world");
+        assert_eq!(dearmor.headers, headers);
+    }
+
+    #[test]
+    fn test_write_multipart() {
+        let rng = &mut XorShiftRng::from_seed([
+            0x3, 0x8, 0x3, 0xe, 0x3, 0x8, 0x3, 0xe, 0x3, 0x8, 0x3, 0xe, 0x3, 0x8, 0x3, 0xe,
+        ]);
+        let mut headers = Headers::new();
+        headers.insert("Comment", "split");
+
+        for &(len, max_size) in &[(0, 200), (10, 200), (5000, 1000), (100_000, 500)] {
+            let content: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let source = TestSource::new(content.clone());
+
+            let parts = write_multipart(&source, max_size, Some(&headers)).unwrap();
+            let mut joined = Vec::new();
+            for (i, part) in parts.iter().enumerate() {
+                assert!(part.len() <= max_size, "{} > {}", part.len(), max_size);
+
+                let mut dearmor = Dearmor::new(io::Cursor::new(part));
+                dearmor.read_header().unwrap();
+                dearmor.read_to_end(&mut joined).unwrap();
+                assert_eq!(
+                    dearmor.typ,
+                    Some(BlockType::MultiPartMessage(i + 1, parts.len()))
+                );
+                assert_eq!(dearmor.headers, headers);
+            }
+            assert_eq!(joined, content);
+        }
+
+        // not even a single line fits
+        let source = TestSource::new(vec![0u8; 10]);
+        assert!(write_multipart(&source, 100, None).is_err());
+    }
+
     #[test]
     fn writes_no_doubleline() {
         let rng = &mut XorShiftRng::from_seed([
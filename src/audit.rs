@@ -0,0 +1,294 @@
+//! # Audit log
+//!
+//! A hook, which is invoked every time a secret key is used, so key usage can be
+//! recorded without patching the crate. The hook is attached to a key by wrapping it in
+//! an [`Audited`] key, which can be used everywhere a [`SecretKeyTrait`] is expected.
+//! Messages are decrypted with a hook using `Message::decrypt_audited`.
+//!
+//! ```rust
+//! # use rand::SeedableRng;
+//! # use rand_chacha::ChaCha8Rng;
+//! use pgp::audit::{AuditEvent, Audited};
+//! use pgp::composed::{KeyType, Message, SecretKeyParamsBuilder};
+//! use pgp::crypto::HashAlgorithm;
+//!
+//! # let mut rng = ChaCha8Rng::seed_from_u64(0);
+//! # let key = SecretKeyParamsBuilder::default()
+//! #     .key_type(KeyType::EdDSA)
+//! #     .can_create_certificates(true)
+//! #     .can_sign(true)
+//! #     .primary_user_id("Me <me@mail.com>".into())
+//! #     .build()
+//! #     .unwrap()
+//! #     .generate_with_rng(&mut rng)
+//! #     .unwrap()
+//! #     .sign(|| "".into())
+//! #     .unwrap();
+//! let hook = |event: &AuditEvent| {
+//!     println!("{:?} with {}", event.operation, hex::encode(&event.fingerprint));
+//! };
+//! let msg = Message::new_literal("hello.txt", "hello")
+//!     .sign(&Audited::new(&key, &hook), || "".into(), HashAlgorithm::SHA2_256)
+//!     .unwrap();
+//! ```
+
+use std::io;
+
+use chrono::{DateTime, Utc};
+use rand::{CryptoRng, Rng};
+
+use crate::crypto::hash::HashAlgorithm;
+use crate::crypto::public_key::PublicKeyAlgorithm;
+use crate::errors::Result;
+use crate::packet::SignatureType;
+use crate::types::{
+    KeyId, KeyTrait, KeyVersion, Mpi, PublicKeyTrait, SecretKeyRepr, SecretKeyTrait,
+};
+
+/// The kind of operation, a secret key was used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Signing data, a standalone signature or a third-party confirmation.
+    Sign,
+    /// Signing keys, user ids and their revocations.
+    Certify,
+    /// Decrypting a session key.
+    Decrypt,
+}
+
+impl Operation {
+    fn for_signature(typ: SignatureType) -> Self {
+        match typ {
+            SignatureType::Binary
+            | SignatureType::Text
+            | SignatureType::Standalone
+            | SignatureType::Timestamp
+            | SignatureType::ThirdParty => Operation::Sign,
+            _ => Operation::Certify,
+        }
+    }
+}
+
+/// A single use of a secret key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    /// The fingerprint of the used key, which might be a subkey.
+    pub fingerprint: Vec<u8>,
+    pub key_id: KeyId,
+    pub operation: Operation,
+    /// The type of the created signature, `None` for decryption.
+    pub signature_type: Option<SignatureType>,
+    pub hash_alg: HashAlgorithm,
+    /// The signed digest, or for decryption the digest of the encrypted session key.
+    pub digest: Vec<u8>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Receives an [`AuditEvent`] after each successful operation with a secret key.
+///
+/// Implemented for all matching closures.
+pub trait AuditHook {
+    fn record(&self, event: &AuditEvent);
+}
+
+impl<F> AuditHook for F
+where
+    F: Fn(&AuditEvent),
+{
+    fn record(&self, event: &AuditEvent) {
+        self(event)
+    }
+}
+
+/// A secret key, which reports every use to an [`AuditHook`].
+pub struct Audited<'a, K> {
+    key: &'a K,
+    hook: &'a dyn AuditHook,
+}
+
+impl<'a, K: SecretKeyTrait> Audited<'a, K> {
+    pub fn new(key: &'a K, hook: &'a dyn AuditHook) -> Self {
+        Audited { key, hook }
+    }
+
+    fn record(
+        &self,
+        operation: Operation,
+        signature_type: Option<SignatureType>,
+        hash_alg: HashAlgorithm,
+        digest: Vec<u8>,
+    ) {
+        self.hook.record(&AuditEvent {
+            fingerprint: self.key.fingerprint(),
+            key_id: self.key.key_id(),
+            operation,
+            signature_type,
+            hash_alg,
+            digest,
+            timestamp: Utc::now(),
+        });
+    }
+}
+
+impl<K: SecretKeyTrait> KeyTrait for Audited<'_, K> {
+    fn fingerprint(&self) -> Vec<u8> {
+        self.key.fingerprint()
+    }
+
+    fn key_id(&self) -> KeyId {
+        self.key.key_id()
+    }
+
+    fn algorithm(&self) -> PublicKeyAlgorithm {
+        self.key.algorithm()
+    }
+
+    fn version(&self) -> KeyVersion {
+        self.key.version()
+    }
+}
+
+impl<K: SecretKeyTrait> PublicKeyTrait for Audited<'_, K> {
+    fn verify_signature(&self, hash: HashAlgorithm, data: &[u8], sig: &[Mpi]) -> Result<()> {
+        self.key.verify_signature(hash, data, sig)
+    }
+
+    fn encrypt<R: CryptoRng + Rng>(&self, rng: &mut R, plain: &[u8]) -> Result<Vec<Mpi>> {
+        self.key.encrypt(rng, plain)
+    }
+
+    fn to_writer_old(&self, writer: &mut impl io::Write) -> Result<()> {
+        self.key.to_writer_old(writer)
+    }
+}
+
+impl<K: SecretKeyTrait> SecretKeyTrait for Audited<'_, K> {
+    type PublicKey = K::PublicKey;
+
+    fn unlock<F, G>(&self, pw: F, work: G) -> Result<()>
+    where
+        F: FnOnce() -> String,
+        G: FnOnce(&SecretKeyRepr) -> Result<()>,
+    {
+        self.key.unlock(pw, work)
+    }
+
+    fn create_signature<F>(&self, key_pw: F, hash: HashAlgorithm, data: &[u8]) -> Result<Vec<Mpi>>
+    where
+        F: FnOnce() -> String,
+    {
+        self.key.create_signature(key_pw, hash, data)
+    }
+
+    fn public_key(&self) -> Self::PublicKey {
+        self.key.public_key()
+    }
+
+    fn record_signature(&self, typ: SignatureType, hash_alg: HashAlgorithm, digest: &[u8]) {
+        self.key.record_signature(typ, hash_alg, digest);
+        self.record(
+            Operation::for_signature(typ),
+            Some(typ),
+            hash_alg,
+            digest.to_vec(),
+        );
+    }
+
+    fn record_decryption(&self, mpis: &[Mpi]) {
+        self.key.record_decryption(mpis);
+
+        let hash_alg = HashAlgorithm::SHA2_256;
+        let data: Vec<u8> = mpis
+            .iter()
+            .flat_map(|mpi| mpi.as_bytes().to_vec())
+            .collect();
+        let digest = hash_alg
+            .digest(&data)
+            .expect("SHA2_256 is always available");
+        self.record(Operation::Decrypt, None, hash_alg, digest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::test_keys::gen_key_with_subkeys;
+    use crate::composed::Message;
+    use crate::crypto::SymmetricKeyAlgorithm;
+    use crate::types::CertificationOptions;
+
+    #[test]
+    fn test_audit_hook() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key_with_subkeys(&mut rng, "Me <me@mail.com>", 1);
+        let other = gen_key_with_subkeys(&mut rng, "Other <other@mail.com>", 0);
+        let public_key = key.signed_public_key();
+
+        let events = RefCell::new(Vec::new());
+        let hook = |event: &AuditEvent| events.borrow_mut().push(event.clone());
+
+        // keys without the hook are not recorded
+        let mut other_public_key = other.signed_public_key();
+        other_public_key
+            .certify_user_id(
+                &key,
+                &other.details.users[0].id,
+                || "".into(),
+                &CertificationOptions::default(),
+            )
+            .unwrap();
+        assert!(events.borrow().is_empty());
+
+        other_public_key
+            .certify_user_id(
+                &Audited::new(&key, &hook),
+                &other.details.users[0].id,
+                || "".into(),
+                &CertificationOptions::default(),
+            )
+            .unwrap();
+        let msg = Message::new_literal("hello.txt", "hello")
+            .sign(
+                &Audited::new(&key, &hook),
+                || "".into(),
+                HashAlgorithm::SHA2_256,
+            )
+            .unwrap()
+            .encrypt_to_keys(
+                &mut rng,
+                SymmetricKeyAlgorithm::AES128,
+                &[&public_key.public_subkeys[0]],
+            )
+            .unwrap();
+        let (mut decrypter, _) = msg
+            .decrypt_audited(|| "".into(), || "".into(), &[&key], &hook)
+            .unwrap();
+        decrypter.next().unwrap().unwrap();
+
+        let events = events.into_inner();
+        assert_eq!(events.len(), 3);
+
+        let certify = &events[0];
+        assert_eq!(certify.operation, Operation::Certify);
+        assert_eq!(certify.fingerprint, key.fingerprint());
+        assert_eq!(certify.signature_type, Some(SignatureType::CertGeneric));
+
+        let sign = &events[1];
+        assert_eq!(sign.operation, Operation::Sign);
+        assert_eq!(sign.key_id, key.key_id());
+        assert_eq!(sign.signature_type, Some(SignatureType::Binary));
+        assert_eq!(sign.hash_alg, HashAlgorithm::SHA2_256);
+        assert_eq!(sign.digest.len(), 32);
+
+        let decrypt = &events[2];
+        assert_eq!(decrypt.operation, Operation::Decrypt);
+        assert_eq!(decrypt.fingerprint, key.secret_subkeys[0].key.fingerprint());
+        assert_eq!(decrypt.signature_type, None);
+    }
+}
@@ -0,0 +1,609 @@
+use std::cmp;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use chrono::{DateTime, TimeZone, Utc};
+use filetime::FileTime;
+
+use crate::composed::Message;
+use crate::errors::Result;
+use crate::packet::LiteralData;
+use crate::ser::Serialize;
+
+const MAGIC: &[u8; 4] = b"PGPA";
+const VERSION: u8 = 1;
+
+/// A file stored in an [`Archive`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// The path relative to the root of the archive, using `/` as separator.
+    pub path: String,
+    /// The unix permission bits.
+    pub mode: u32,
+    pub modified: DateTime<Utc>,
+    pub data: Vec<u8>,
+}
+
+impl ArchiveEntry {
+    /// Returns the path of the entry, rejecting paths that would leave the root of the archive.
+    pub fn relative_path(&self) -> Result<PathBuf> {
+        relative_path(&self.path)
+    }
+
+    fn index(&self) -> IndexEntry {
+        IndexEntry {
+            path: self.path.clone(),
+            mode: self.mode,
+            modified: self.modified,
+            len: self.data.len() as u64,
+        }
+    }
+}
+
+/// The description of an entry in the index, without its contents.
+#[derive(Debug, Clone)]
+struct IndexEntry {
+    path: String,
+    mode: u32,
+    modified: DateTime<Utc>,
+    len: u64,
+}
+
+/// A simple container for a tree of files, to encrypt them as a single message.
+///
+/// The serialized form starts with an index of all entries, followed by their contents:
+///
+/// - the magic `PGPA`, followed by a one-octet version, currently 1
+/// - a four-octet number of entries
+/// - for each entry, a two-octet path length, the UTF-8 encoded path, the four-octet mode,
+///   the four-octet modification time in seconds since the epoch and the eight-octet data length
+/// - the data of all entries, in the order of the index
+///
+/// All numbers are big endian.
+///
+/// [`Archive::stream_dir`] and [`Archive::unpack_stream`] work on the serialized form
+/// directly, without holding the files in memory. They can be combined with
+/// [`sign_stream`](crate::composed::sign_stream) and
+/// [`VerifyingReader`](crate::composed::VerifyingReader).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Archive {
+    pub entries: Vec<ArchiveEntry>,
+}
+
+impl Archive {
+    /// Reads all regular files below `dir`, recursively.
+    ///
+    /// Symlinks and other special files are skipped, as are empty directories.
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let entries = scan_dir(dir.as_ref())?
+            .into_iter()
+            .map(|(entry, path)| -> Result<ArchiveEntry> {
+                let data = fs::read(&path)?;
+                ensure_eq!(
+                    data.len() as u64,
+                    entry.len,
+                    "{:?} was modified while reading it",
+                    path
+                );
+
+                Ok(ArchiveEntry {
+                    path: entry.path,
+                    mode: entry.mode,
+                    modified: entry.modified,
+                    data,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Archive { entries })
+    }
+
+    /// Returns a reader over the serialized archive of all regular files below `dir`,
+    /// see [`Archive::from_dir`].
+    ///
+    /// Only the index is kept in memory, the files are read one after the other, while
+    /// the archive is read. Reading fails if a file was truncated in the meantime.
+    pub fn stream_dir(dir: impl AsRef<Path>) -> Result<impl Read> {
+        let files = scan_dir(dir.as_ref())?;
+        let mut index = Vec::new();
+        write_index(
+            &mut index,
+            files.iter().map(|(entry, _)| entry),
+            files.len(),
+        )?;
+
+        Ok(DirReader {
+            index: io::Cursor::new(index),
+            files: files
+                .into_iter()
+                .map(|(entry, path)| (path, entry.len))
+                .collect::<Vec<_>>()
+                .into_iter(),
+            current: None,
+            remaining: 0,
+        })
+    }
+
+    /// Writes all entries below `dir`, creating directories as needed.
+    ///
+    /// Existing files are overwritten, but symlinks are never followed, unpacking fails
+    /// if a path of the archive leads through one. Permissions are restored on unix,
+    /// modification times on all platforms.
+    pub fn unpack(&self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        for entry in &self.entries {
+            entry.relative_path()?;
+        }
+        for entry in &self.entries {
+            unpack_entry(dir, &entry.index(), &mut &entry.data[..])?;
+        }
+
+        Ok(())
+    }
+
+    /// Unpacks the serialized archive read from `bytes` below `dir`, see
+    /// [`Archive::unpack`].
+    ///
+    /// The contents of the files are copied to disk while they are read. All paths are
+    /// checked before the first file is written, but a truncated archive leaves the
+    /// files unpacked so far behind.
+    pub fn unpack_stream(mut bytes: impl Read, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        let index = read_index(&mut bytes)?;
+        for entry in &index {
+            relative_path(&entry.path)?;
+        }
+        for entry in &index {
+            unpack_entry(dir, entry, &mut bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Parses a serialized archive.
+    pub fn from_bytes(mut bytes: impl Read) -> Result<Self> {
+        let index = read_index(&mut bytes)?;
+
+        let mut entries = Vec::with_capacity(index.len());
+        for entry in index {
+            let mut data = Vec::new();
+            bytes.by_ref().take(entry.len).read_to_end(&mut data)?;
+            ensure_eq!(data.len() as u64, entry.len, "truncated archive");
+
+            entries.push(ArchiveEntry {
+                path: entry.path,
+                mode: entry.mode,
+                modified: entry.modified,
+                data,
+            });
+        }
+
+        Ok(Archive { entries })
+    }
+}
+
+impl Serialize for Archive {
+    fn to_writer<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+        let index: Vec<_> = self.entries.iter().map(ArchiveEntry::index).collect();
+        write_index(writer, index.iter(), index.len())?;
+
+        for entry in &self.entries {
+            writer.write_all(&entry.data)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn relative_path(path: &str) -> Result<PathBuf> {
+    let relative = Path::new(path);
+    let is_relative = relative.components().all(|c| match c {
+        Component::Normal(_) => true,
+        _ => false,
+    });
+    ensure!(
+        !path.is_empty() && is_relative,
+        "invalid archive path {:?}",
+        path
+    );
+
+    Ok(relative.to_path_buf())
+}
+
+/// Collects the regular files below `dir`, sorted by their path in the archive.
+fn scan_dir(dir: &Path) -> Result<Vec<(IndexEntry, PathBuf)>> {
+    let mut files = Vec::new();
+    add_dir(&mut files, dir, "")?;
+    files.sort_by(|(a, _), (b, _)| a.path.cmp(&b.path));
+
+    Ok(files)
+}
+
+fn add_dir(files: &mut Vec<(IndexEntry, PathBuf)>, dir: &Path, prefix: &str) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(name) => bail!("file name {:?} is not valid UTF-8", name),
+        };
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            add_dir(files, &entry.path(), &path)?;
+        } else if file_type.is_file() {
+            let metadata = entry.metadata()?;
+            let index = IndexEntry {
+                path,
+                mode: file_mode(&metadata),
+                modified: DateTime::from(metadata.modified()?),
+                len: metadata.len(),
+            };
+            files.push((index, entry.path()));
+        } else {
+            warn!("skipping special file {:?}", entry.path());
+        }
+    }
+
+    Ok(())
+}
+
+fn write_index<'a, W: io::Write>(
+    writer: &mut W,
+    entries: impl Iterator<Item = &'a IndexEntry>,
+    count: usize,
+) -> Result<()> {
+    ensure!(count <= std::u32::MAX as usize, "too many archive entries");
+    writer.write_all(MAGIC)?;
+    writer.write_u8(VERSION)?;
+    writer.write_u32::<BigEndian>(count as u32)?;
+
+    for entry in entries {
+        ensure!(
+            entry.path.len() <= std::u16::MAX as usize,
+            "archive path {:?} is too long",
+            entry.path
+        );
+        let modified = entry.modified.timestamp();
+        ensure!(
+            modified >= 0 && modified <= i64::from(std::u32::MAX),
+            "modification time {} of {:?} is out of range",
+            entry.modified,
+            entry.path
+        );
+
+        writer.write_u16::<BigEndian>(entry.path.len() as u16)?;
+        writer.write_all(entry.path.as_bytes())?;
+        writer.write_u32::<BigEndian>(entry.mode)?;
+        writer.write_u32::<BigEndian>(modified as u32)?;
+        writer.write_u64::<BigEndian>(entry.len)?;
+    }
+
+    Ok(())
+}
+
+fn read_index(bytes: &mut impl Read) -> Result<Vec<IndexEntry>> {
+    let mut magic = [0u8; 4];
+    bytes.read_exact(&mut magic)?;
+    ensure_eq!(&magic, MAGIC, "not an archive");
+    let version = bytes.read_u8()?;
+    ensure_eq!(version, VERSION, "unsupported archive version");
+
+    let count = bytes.read_u32::<BigEndian>()?;
+    let mut index = Vec::new();
+    for _ in 0..count {
+        let mut path = vec![0u8; bytes.read_u16::<BigEndian>()? as usize];
+        bytes.read_exact(&mut path)?;
+        index.push(IndexEntry {
+            path: std::str::from_utf8(&path)?.to_string(),
+            mode: bytes.read_u32::<BigEndian>()?,
+            modified: Utc.timestamp(i64::from(bytes.read_u32::<BigEndian>()?), 0),
+            len: bytes.read_u64::<BigEndian>()?,
+        });
+    }
+
+    Ok(index)
+}
+
+/// Writes the `entry.len` bytes read from `data` to the path of the entry below `dir`.
+fn unpack_entry(dir: &Path, entry: &IndexEntry, data: &mut impl Read) -> Result<()> {
+    let path = create_parents(dir, &relative_path(&entry.path)?)?;
+    let mut file = fs::File::create(&path)?;
+    let written = io::copy(&mut data.take(entry.len), &mut file)?;
+    ensure_eq!(written, entry.len, "truncated archive");
+    drop(file);
+
+    set_file_mode(&path, entry.mode)?;
+    let modified = FileTime::from_unix_time(entry.modified.timestamp(), 0);
+    filetime::set_file_mtime(&path, modified)?;
+
+    Ok(())
+}
+
+/// Creates the missing directories of `relative` below `dir`, and returns the full path.
+///
+/// Fails if any of the existing components is a symlink, or the file itself is not a
+/// regular file.
+fn create_parents(dir: &Path, relative: &Path) -> Result<PathBuf> {
+    let components: Vec<_> = relative.components().collect();
+    let mut path = dir.to_path_buf();
+    for (i, component) in components.iter().enumerate() {
+        path.push(component);
+        let is_file = i + 1 == components.len();
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) => {
+                let file_type = metadata.file_type();
+                ensure!(
+                    !file_type.is_symlink(),
+                    "refusing to unpack through the symlink {:?}",
+                    path
+                );
+                if is_file {
+                    ensure!(file_type.is_file(), "{:?} is not a regular file", path);
+                } else {
+                    ensure!(file_type.is_dir(), "{:?} is not a directory", path);
+                }
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                if !is_file {
+                    fs::create_dir(&path)?;
+                }
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(path)
+}
+
+/// Reads the index, followed by the files, opening them one at a time.
+struct DirReader {
+    index: io::Cursor<Vec<u8>>,
+    files: std::vec::IntoIter<(PathBuf, u64)>,
+    current: Option<fs::File>,
+    remaining: u64,
+}
+
+impl Read for DirReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let read = self.index.read(buf)?;
+        if read > 0 {
+            return Ok(read);
+        }
+
+        loop {
+            if let Some(ref mut file) = self.current {
+                if self.remaining > 0 {
+                    let max = cmp::min(buf.len() as u64, self.remaining) as usize;
+                    let read = file.read(&mut buf[..max])?;
+                    if read == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "file was truncated while reading it",
+                        ));
+                    }
+                    self.remaining -= read as u64;
+                    return Ok(read);
+                }
+            }
+
+            match self.files.next() {
+                Some((path, len)) => {
+                    self.current = Some(fs::File::open(path)?);
+                    self.remaining = len;
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+impl Message {
+    /// Creates a binary literal message, containing the serialized archive.
+    pub fn new_archive(file_name: &str, archive: &Archive) -> Result<Self> {
+        Ok(Message::Literal(LiteralData::from_bytes(
+            file_name,
+            &archive.to_bytes()?,
+        )))
+    }
+
+    /// Reads the archive from the content of a decrypted message.
+    pub fn get_archive(&self) -> Result<Archive> {
+        match self.get_content()? {
+            Some(content) => Archive::from_bytes(&content[..]),
+            None => bail!("the message is encrypted"),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+#[cfg(unix)]
+fn set_file_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // never restore setuid, setgid and sticky bits
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_file_mode(path: &Path, mode: u32) -> Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    fs::set_permissions(path, permissions)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::thread_rng;
+
+    use crate::composed::Deserializable;
+    use crate::crypto::SymmetricKeyAlgorithm;
+    use crate::types::StringToKey;
+
+    fn create_tree(dir: &Path) {
+        fs::create_dir_all(dir.join("src/sub")).unwrap();
+        fs::write(dir.join("README"), b"hello").unwrap();
+        fs::write(dir.join("src/sub/data.bin"), &[0u8, 1, 2, 255][..]).unwrap();
+        let modified = FileTime::from_unix_time(1_500_000_000, 0);
+        filetime::set_file_mtime(dir.join("README"), modified).unwrap();
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        create_tree(dir.path());
+
+        let archive = Archive::from_dir(dir.path()).unwrap();
+        let paths: Vec<_> = archive.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["README", "src/sub/data.bin"]);
+        assert_eq!(archive.entries[0].modified.timestamp(), 1_500_000_000);
+
+        let mut rng = thread_rng();
+        let s2k = StringToKey::new_default(&mut rng);
+        let encrypted = Message::new_archive("backup", &archive)
+            .unwrap()
+            .encrypt_with_password(&mut rng, s2k, SymmetricKeyAlgorithm::AES128, || "pw".into())
+            .unwrap();
+        let armor = encrypted.to_armored_string(None).unwrap();
+
+        let (parsed, _) = Message::from_string(&armor).unwrap();
+        let decrypted = parsed
+            .decrypt_with_password(|| "pw".into())
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        let unpacked = decrypted.get_archive().unwrap();
+        // modification times are stored in seconds
+        for (a, b) in unpacked.entries.iter().zip(&archive.entries) {
+            assert_eq!(a.path, b.path);
+            assert_eq!(a.mode, b.mode);
+            assert_eq!(a.data, b.data);
+            assert_eq!(a.modified.timestamp(), b.modified.timestamp());
+        }
+
+        let out = tempfile::tempdir().unwrap();
+        unpacked.unpack(out.path()).unwrap();
+        assert_eq!(fs::read(out.path().join("README")).unwrap(), b"hello");
+        assert_eq!(
+            fs::read(out.path().join("src/sub/data.bin")).unwrap(),
+            vec![0u8, 1, 2, 255]
+        );
+        let modified = fs::metadata(out.path().join("README"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(DateTime::<Utc>::from(modified).timestamp(), 1_500_000_000);
+
+        // entries must not leave the target directory
+        for path in &["../evil", "/etc/evil", "a/../../evil", ""] {
+            let evil = Archive {
+                entries: vec![ArchiveEntry {
+                    path: path.to_string(),
+                    mode: 0o644,
+                    modified: Utc::now(),
+                    data: Vec::new(),
+                }],
+            };
+            assert!(evil.unpack(out.path()).is_err(), "{:?}", path);
+        }
+
+        // truncated data
+        let bytes = archive.to_bytes().unwrap();
+        assert!(Archive::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_archive_stream() {
+        let dir = tempfile::tempdir().unwrap();
+        create_tree(dir.path());
+
+        let mut bytes = Vec::new();
+        Archive::stream_dir(dir.path())
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        let archive = Archive::from_dir(dir.path()).unwrap();
+        assert_eq!(bytes, archive.to_bytes().unwrap());
+
+        let out = tempfile::tempdir().unwrap();
+        Archive::unpack_stream(&bytes[..], out.path()).unwrap();
+        let unpacked = Archive::from_dir(out.path()).unwrap();
+        assert_eq!(unpacked.entries.len(), archive.entries.len());
+        for (a, b) in unpacked.entries.iter().zip(&archive.entries) {
+            assert_eq!(a.path, b.path);
+            assert_eq!(a.data, b.data);
+            assert_eq!(a.modified.timestamp(), b.modified.timestamp());
+        }
+
+        // nothing is written, if one of the paths is invalid
+        let mut evil = archive.clone();
+        evil.entries.push(ArchiveEntry {
+            path: "../evil".into(),
+            mode: 0o644,
+            modified: Utc::now(),
+            data: Vec::new(),
+        });
+        let out = tempfile::tempdir().unwrap();
+        let bytes = evil.to_bytes().unwrap();
+        assert!(Archive::unpack_stream(&bytes[..], out.path()).is_err());
+        assert_eq!(fs::read_dir(out.path()).unwrap().count(), 0);
+
+        let out = tempfile::tempdir().unwrap();
+        assert!(Archive::unpack_stream(&bytes[..bytes.len() - 1], out.path()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unpack_refuses_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let outside = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        symlink(outside.path(), out.path().join("dir")).unwrap();
+        symlink(outside.path().join("file"), out.path().join("file")).unwrap();
+
+        for path in &["dir/evil", "file"] {
+            let evil = Archive {
+                entries: vec![ArchiveEntry {
+                    path: path.to_string(),
+                    mode: 0o644,
+                    modified: Utc::now(),
+                    data: b"evil".to_vec(),
+                }],
+            };
+            assert!(evil.unpack(out.path()).is_err(), "{:?}", path);
+        }
+        assert_eq!(fs::read_dir(outside.path()).unwrap().count(), 0);
+    }
+}
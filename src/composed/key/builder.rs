@@ -5,10 +5,13 @@ use rand::{thread_rng, CryptoRng, Rng};
 use smallvec::SmallVec;
 
 use crate::composed::{KeyDetails, SecretKey, SecretSubkey};
-use crate::crypto::{ecdh, eddsa, rsa, HashAlgorithm, PublicKeyAlgorithm, SymmetricKeyAlgorithm};
+use crate::crypto::{
+    ecdh, ed25519, ed448, eddsa, rsa, x25519, x448, HashAlgorithm, PublicKeyAlgorithm,
+    SymmetricKeyAlgorithm,
+};
 use crate::errors::Result;
-use crate::packet::{self, KeyFlags, UserAttribute, UserId};
-use crate::types::{self, CompressionAlgorithm, PublicParams, RevocationKey};
+use crate::packet::{self, KeyFlags, Subpacket, UserAttribute, UserId};
+use crate::types::{self, CompressionAlgorithm, PlainSecretParams, PublicParams, RevocationKey};
 
 #[derive(Debug, PartialEq, Eq, Builder)]
 #[builder(build_fn(validate = "Self::validate"))]
@@ -20,8 +23,15 @@ pub struct SecretKeyParams {
     can_sign: bool,
     #[builder(default)]
     can_create_certificates: bool,
+    /// Sets both the encrypt communications and encrypt storage flags.
     #[builder(default)]
     can_encrypt: bool,
+    #[builder(default)]
+    can_encrypt_comms: bool,
+    #[builder(default)]
+    can_encrypt_storage: bool,
+    #[builder(default)]
+    can_authenticate: bool,
 
     // -- Preferences
     /// List of symmetric algorithms that indicate which algorithms the key holder prefers to use.
@@ -36,8 +46,10 @@ pub struct SecretKeyParams {
     #[builder(default)]
     revocation_key: Option<RevocationKey>,
 
-    #[builder]
-    primary_user_id: String,
+    /// The primary user id, which can only be omitted for v6 keys. Keys without user ids
+    /// carry their preferences in a direct key signature instead.
+    #[builder(default, setter(custom))]
+    primary_user_id: Option<String>,
 
     #[builder(default)]
     user_ids: Vec<String>,
@@ -45,6 +57,9 @@ pub struct SecretKeyParams {
     user_attributes: Vec<UserAttribute>,
     #[builder(default)]
     passphrase: Option<String>,
+    /// Normalize the passphrase to Unicode NFC before deriving the key.
+    #[builder(default = "true")]
+    normalize_passphrase: bool,
     #[builder(default = "chrono::Utc::now().trunc_subsecs(0)")]
     created_at: chrono::DateTime<chrono::Utc>,
     #[builder(default)]
@@ -53,9 +68,16 @@ pub struct SecretKeyParams {
     version: types::KeyVersion,
     #[builder(default)]
     expiration: Option<Duration>,
+    /// Existing key material to use, instead of generating a new key.
+    #[builder(default, setter(custom))]
+    key_material: Option<(PublicParams, PlainSecretParams)>,
 
     #[builder(default)]
     subkeys: Vec<SubkeyParams>,
+    /// Create subkeys at least one second after the primary key, as some implementations
+    /// have issues with keys that share the same creation time.
+    #[builder(default)]
+    offset_subkey_creation: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Builder)]
@@ -66,8 +88,15 @@ pub struct SubkeyParams {
     can_sign: bool,
     #[builder(default)]
     can_create_certificates: bool,
+    /// Sets both the encrypt communications and encrypt storage flags.
     #[builder(default)]
     can_encrypt: bool,
+    #[builder(default)]
+    can_encrypt_comms: bool,
+    #[builder(default)]
+    can_encrypt_storage: bool,
+    #[builder(default)]
+    can_authenticate: bool,
 
     #[builder(default)]
     user_ids: Vec<UserId>,
@@ -75,6 +104,9 @@ pub struct SubkeyParams {
     user_attributes: Vec<UserAttribute>,
     #[builder(default)]
     passphrase: Option<String>,
+    /// Normalize the passphrase to Unicode NFC before deriving the key.
+    #[builder(default = "true")]
+    normalize_passphrase: bool,
     #[builder(default = "chrono::Utc::now().trunc_subsecs(0)")]
     created_at: chrono::DateTime<chrono::Utc>,
     #[builder(default)]
@@ -83,6 +115,13 @@ pub struct SubkeyParams {
     version: types::KeyVersion,
     #[builder(default)]
     expiration: Option<Duration>,
+    /// Existing key material to use, instead of generating a new key.
+    #[builder(default, setter(custom))]
+    key_material: Option<(PublicParams, PlainSecretParams)>,
+    /// Additional subpackets for the hashed area of the binding signature,
+    /// such as preferred algorithms or notations.
+    #[builder(default)]
+    hashed_subpackets: Vec<Subpacket>,
 }
 
 impl SecretKeyParamsBuilder {
@@ -93,26 +132,104 @@ impl SecretKeyParamsBuilder {
                     return Err("Keys with less than 2048bits are considered insecure".into());
                 }
             }
-            Some(KeyType::EdDSA) => {
-                if let Some(can_encrypt) = self.can_encrypt {
-                    if can_encrypt {
-                        return Err("EdDSA can only be used for signing keys".into());
-                    }
+            Some(key_type @ KeyType::EdDSA)
+            | Some(key_type @ KeyType::Ed25519)
+            | Some(key_type @ KeyType::Ed448) => {
+                let can_encrypt = self.can_encrypt.unwrap_or_default()
+                    || self.can_encrypt_comms.unwrap_or_default()
+                    || self.can_encrypt_storage.unwrap_or_default();
+                if can_encrypt {
+                    return Err(format!("{:?} can only be used for signing keys", key_type));
                 }
             }
-            Some(KeyType::ECDH) => {
-                if let Some(can_sign) = self.can_sign {
-                    if can_sign {
-                        return Err("ECDH can only be used for encryption keys".into());
-                    }
+            Some(key_type @ KeyType::ECDH)
+            | Some(key_type @ KeyType::X25519)
+            | Some(key_type @ KeyType::X448) => {
+                let can_sign =
+                    self.can_sign.unwrap_or_default() || self.can_authenticate.unwrap_or_default();
+                if can_sign {
+                    return Err(format!(
+                        "{:?} can only be used for encryption keys",
+                        key_type
+                    ));
                 }
             }
             _ => {}
         }
 
+        if let (Some(key_type), Some(Some(material))) = (&self.key_type, &self.key_material) {
+            validate_key_material(*key_type, material)?;
+        }
+        for subkey in self.subkeys.iter().flatten() {
+            if let Some(ref material) = subkey.key_material {
+                validate_key_material(subkey.key_type, material)?;
+            }
+        }
+
+        // Subkeys must not predate their primary key. If no creation time is set for the
+        // primary key, it uses the current time and subkeys are adjusted during generation.
+        if let Some(created_at) = self.created_at {
+            let mut subkeys = self.subkeys.iter().flatten();
+            if subkeys.any(|subkey| subkey.created_at < created_at) {
+                return Err("Subkeys must not be created before the primary key".into());
+            }
+        }
+
+        // Key expiration times are stored as 32 bit offsets in the self-signatures.
+        let expirations = self.expiration.iter().flatten().chain(
+            self.subkeys
+                .iter()
+                .flatten()
+                .filter_map(|subkey| subkey.expiration.as_ref()),
+        );
+        for expiration in expirations {
+            if expiration.as_secs() > u64::from(std::u32::MAX) {
+                return Err("Key expiration times must fit into 32 bits".into());
+            }
+            // an offset of zero means the key never expires
+            if expiration.as_secs() == 0 {
+                return Err("Keys must not expire at their creation time".into());
+            }
+        }
+
+        // Only v6 keys can do without user ids, using a direct key signature instead.
+        if let None | Some(None) = self.primary_user_id {
+            if self.version != Some(types::KeyVersion::V6) {
+                return Err("Missing primary user id, which is only optional for v6 keys".into());
+            }
+            let has_user_ids = self.user_ids.iter().flatten().next().is_some()
+                || self.user_attributes.iter().flatten().next().is_some();
+            if has_user_ids {
+                return Err("User ids and attributes require a primary user id".into());
+            }
+        }
+
+        if let Some(types::KeyVersion::V6) = self.version {
+            // The legacy Curve25519 algorithms must not be used with v6 keys.
+            let key_types = self
+                .key_type
+                .iter()
+                .chain(self.subkeys.iter().flatten().map(|subkey| &subkey.key_type));
+            for key_type in key_types {
+                if *key_type == KeyType::EdDSA || *key_type == KeyType::ECDH {
+                    return Err(format!("{:?} can not be used with v6 keys", key_type));
+                }
+            }
+
+            let mut subkeys = self.subkeys.iter().flatten();
+            if subkeys.any(|subkey| subkey.version != types::KeyVersion::V6) {
+                return Err("Subkeys of a v6 key must be v6 keys".into());
+            }
+        }
+
         Ok(())
     }
 
+    pub fn primary_user_id(&mut self, value: String) -> &mut Self {
+        self.primary_user_id = Some(Some(value));
+        self
+    }
+
     pub fn user_id<VALUE: Into<String>>(&mut self, value: VALUE) -> &mut Self {
         if let Some(ref mut user_ids) = self.user_ids {
             user_ids.push(value.into());
@@ -130,24 +247,120 @@ impl SecretKeyParamsBuilder {
         }
         self
     }
+
+    /// Makes the key valid from `not_before` until `not_after`, setting both the creation
+    /// and the expiration time.
+    pub fn validity(
+        &mut self,
+        not_before: chrono::DateTime<chrono::Utc>,
+        not_after: chrono::DateTime<chrono::Utc>,
+    ) -> &mut Self {
+        // windows that are empty or end before they start are rejected during validation
+        let expiration = (not_after - not_before)
+            .to_std()
+            .unwrap_or_else(|_| Duration::from_secs(0));
+        self.created_at = Some(not_before);
+        self.expiration = Some(Some(expiration));
+        self
+    }
+
+    /// Uses the given key material for the primary key, instead of generating a new key.
+    pub fn key_material(
+        &mut self,
+        public_params: PublicParams,
+        secret_params: PlainSecretParams,
+    ) -> &mut Self {
+        self.key_material = Some(Some((public_params, secret_params)));
+        self
+    }
+}
+
+impl SubkeyParamsBuilder {
+    pub fn hashed_subpacket(&mut self, value: Subpacket) -> &mut Self {
+        if let Some(ref mut subpackets) = self.hashed_subpackets {
+            subpackets.push(value);
+        } else {
+            self.hashed_subpackets = Some(vec![value]);
+        }
+        self
+    }
+
+    /// Makes the key valid from `not_before` until `not_after`, setting both the creation
+    /// and the expiration time.
+    pub fn validity(
+        &mut self,
+        not_before: chrono::DateTime<chrono::Utc>,
+        not_after: chrono::DateTime<chrono::Utc>,
+    ) -> &mut Self {
+        // windows that are empty or end before they start are rejected during validation
+        let expiration = (not_after - not_before)
+            .to_std()
+            .unwrap_or_else(|_| Duration::from_secs(0));
+        self.created_at = Some(not_before);
+        self.expiration = Some(Some(expiration));
+        self
+    }
+
+    /// Uses the given key material for the subkey, instead of generating a new key.
+    pub fn key_material(
+        &mut self,
+        public_params: PublicParams,
+        secret_params: PlainSecretParams,
+    ) -> &mut Self {
+        self.key_material = Some(Some((public_params, secret_params)));
+        self
+    }
+}
+
+fn validate_key_material(
+    key_type: KeyType,
+    material: &(PublicParams, PlainSecretParams),
+) -> std::result::Result<(), String> {
+    let matches = match (key_type, &material.0, &material.1) {
+        (KeyType::Rsa(_), PublicParams::RSA { .. }, PlainSecretParams::RSA { .. }) => true,
+        (KeyType::ECDH, PublicParams::ECDH { .. }, PlainSecretParams::ECDH(_)) => true,
+        (KeyType::EdDSA, PublicParams::EdDSA { .. }, PlainSecretParams::EdDSA(_)) => true,
+        (KeyType::X25519, PublicParams::X25519 { .. }, PlainSecretParams::X25519(_)) => true,
+        (KeyType::Ed25519, PublicParams::Ed25519 { .. }, PlainSecretParams::Ed25519(_)) => true,
+        (KeyType::X448, PublicParams::X448 { .. }, PlainSecretParams::X448(_)) => true,
+        (KeyType::Ed448, PublicParams::Ed448 { .. }, PlainSecretParams::Ed448(_)) => true,
+        _ => false,
+    };
+
+    if matches {
+        Ok(())
+    } else {
+        Err(format!(
+            "The key material does not match the key type {:?}",
+            key_type
+        ))
+    }
 }
 
 impl SecretKeyParams {
+    /// Same as [`generate_with_rng`], but uses [`thread_rng`] for RNG.
+    ///
+    /// [`generate_with_rng`]: SecretKeyParams::generate_with_rng
+    /// [`thread_rng`]: rand::thread_rng
     pub fn generate(self) -> Result<SecretKey> {
         let mut rng = thread_rng();
         self.generate_with_rng(&mut rng)
     }
 
     pub fn generate_with_rng<R: Rng + CryptoRng>(self, rng: &mut R) -> Result<SecretKey> {
-        let passphrase = self.passphrase;
-        let (public_params, secret_params) = self.key_type.generate_with_rng(rng, passphrase)?;
+        let passphrase = prepare_passphrase(self.passphrase, self.normalize_passphrase);
+        let (public_params, plain) = match self.key_material {
+            Some(material) => material,
+            None => self.key_type.generate_plain(rng)?,
+        };
+        let secret_params = protect(rng, plain, passphrase, self.version)?;
         let primary_key = packet::SecretKey {
             details: packet::PublicKey {
                 packet_version: self.packet_version,
                 version: self.version,
                 algorithm: self.key_type.to_alg(),
                 created_at: self.created_at,
-                expiration: self.expiration.map(|v| v.as_secs() as u16),
+                expiration: None,
                 public_params,
             },
             secret_params,
@@ -155,14 +368,22 @@ impl SecretKeyParams {
 
         let mut keyflags = KeyFlags::default();
         keyflags.set_certify(self.can_create_certificates);
-        keyflags.set_encrypt_comms(self.can_encrypt);
-        keyflags.set_encrypt_storage(self.can_encrypt);
+        keyflags.set_encrypt_comms(self.can_encrypt || self.can_encrypt_comms);
+        keyflags.set_encrypt_storage(self.can_encrypt || self.can_encrypt_storage);
         keyflags.set_sign(self.can_sign);
+        keyflags.set_authentication(self.can_authenticate);
+
+        let min_subkey_created_at = if self.offset_subkey_creation {
+            self.created_at + chrono::Duration::seconds(1)
+        } else {
+            self.created_at
+        };
 
         Ok(SecretKey::new(
             primary_key,
             KeyDetails::new(
-                UserId::from_str(Default::default(), &self.primary_user_id),
+                self.primary_user_id
+                    .map(|id| UserId::from_str(Default::default(), &id)),
                 self.user_ids
                     .iter()
                     .map(|m| UserId::from_str(Default::default(), m))
@@ -173,39 +394,78 @@ impl SecretKeyParams {
                 self.preferred_hash_algorithms,
                 self.preferred_compression_algorithms,
                 self.revocation_key,
+                self.expiration.map(to_key_expiration_time),
             ),
             Default::default(),
             self.subkeys
                 .into_iter()
-                .map(|subkey| {
-                    let passphrase = subkey.passphrase;
-                    let (public_params, secret_params) = subkey.key_type.generate(passphrase)?;
-                    let mut keyflags = KeyFlags::default();
-                    keyflags.set_certify(subkey.can_create_certificates);
-                    keyflags.set_encrypt_comms(subkey.can_encrypt);
-                    keyflags.set_encrypt_storage(subkey.can_encrypt);
-                    keyflags.set_sign(subkey.can_sign);
-
-                    Ok(SecretSubkey::new(
-                        packet::SecretSubkey {
-                            details: packet::PublicSubkey {
-                                packet_version: subkey.packet_version,
-                                version: subkey.version,
-                                algorithm: subkey.key_type.to_alg(),
-                                created_at: subkey.created_at,
-                                expiration: subkey.expiration.map(|v| v.as_secs() as u16),
-                                public_params,
-                            },
-                            secret_params,
-                        },
-                        keyflags,
-                    ))
+                .map(|mut subkey| {
+                    subkey.created_at = subkey.created_at.max(min_subkey_created_at);
+                    subkey.generate_with_rng(rng)
                 })
                 .collect::<Result<Vec<_>>>()?,
         ))
     }
 }
 
+impl SubkeyParams {
+    /// Same as [`generate_with_rng`], but uses [`thread_rng`] for RNG.
+    ///
+    /// [`generate_with_rng`]: SubkeyParams::generate_with_rng
+    /// [`thread_rng`]: rand::thread_rng
+    pub fn generate(self) -> Result<SecretSubkey> {
+        let mut rng = thread_rng();
+        self.generate_with_rng(&mut rng)
+    }
+
+    /// Generates the subkey, which still has to be bound to a primary key using
+    /// [`SecretSubkey::sign`].
+    pub fn generate_with_rng<R: Rng + CryptoRng>(self, rng: &mut R) -> Result<SecretSubkey> {
+        let passphrase = prepare_passphrase(self.passphrase, self.normalize_passphrase);
+        let (public_params, plain) = match self.key_material {
+            Some(material) => material,
+            None => self.key_type.generate_plain(rng)?,
+        };
+        let secret_params = protect(rng, plain, passphrase, self.version)?;
+        let mut keyflags = KeyFlags::default();
+        keyflags.set_certify(self.can_create_certificates);
+        keyflags.set_encrypt_comms(self.can_encrypt || self.can_encrypt_comms);
+        keyflags.set_encrypt_storage(self.can_encrypt || self.can_encrypt_storage);
+        keyflags.set_sign(self.can_sign);
+        keyflags.set_authentication(self.can_authenticate);
+
+        Ok(SecretSubkey::new(
+            packet::SecretSubkey {
+                details: packet::PublicSubkey {
+                    packet_version: self.packet_version,
+                    version: self.version,
+                    algorithm: self.key_type.to_alg(),
+                    created_at: self.created_at,
+                    expiration: None,
+                    public_params,
+                },
+                secret_params,
+            },
+            keyflags,
+            self.expiration.map(to_key_expiration_time),
+        )
+        .with_hashed_subpackets(self.hashed_subpackets))
+    }
+}
+
+/// Converts the expiration, which was validated to fit into 32 bits.
+fn to_key_expiration_time(expiration: Duration) -> chrono::Duration {
+    chrono::Duration::seconds(expiration.as_secs() as i64)
+}
+
+fn prepare_passphrase(passphrase: Option<String>, normalize: bool) -> Option<String> {
+    if normalize {
+        passphrase.map(|p| types::normalize_passphrase(&p))
+    } else {
+        passphrase
+    }
+}
+
 #[derive(Clone, Debug, Copy, PartialEq, Eq)]
 pub enum KeyType {
     /// Encryption & Signing with RSA an the given bitsize.
@@ -214,6 +474,14 @@ pub enum KeyType {
     ECDH,
     /// Signing with Curve25519
     EdDSA,
+    /// Encrypting with X25519, in the format of RFC 9580, which v6 keys use
+    X25519,
+    /// Signing with Ed25519, in the format of RFC 9580, which v6 keys use
+    Ed25519,
+    /// Encrypting with X448
+    X448,
+    /// Signing with Ed448
+    Ed448,
 }
 
 impl KeyType {
@@ -222,9 +490,17 @@ impl KeyType {
             KeyType::Rsa(_) => PublicKeyAlgorithm::RSA,
             KeyType::ECDH => PublicKeyAlgorithm::ECDH,
             KeyType::EdDSA => PublicKeyAlgorithm::EdDSA,
+            KeyType::X25519 => PublicKeyAlgorithm::X25519,
+            KeyType::Ed25519 => PublicKeyAlgorithm::Ed25519,
+            KeyType::X448 => PublicKeyAlgorithm::X448,
+            KeyType::Ed448 => PublicKeyAlgorithm::Ed448,
         }
     }
 
+    /// Same as [`generate_with_rng`], but uses [`thread_rng`] for RNG.
+    ///
+    /// [`generate_with_rng`]: KeyType::generate_with_rng
+    /// [`thread_rng`]: rand::thread_rng
     pub fn generate(
         self,
         passphrase: Option<String>,
@@ -233,50 +509,68 @@ impl KeyType {
         self.generate_with_rng(&mut rng, passphrase)
     }
 
+    /// Generates the key material, the secret part is protected the way it is for v4 keys.
     pub fn generate_with_rng<R: Rng + CryptoRng>(
         self,
         rng: &mut R,
         passphrase: Option<String>,
     ) -> Result<(PublicParams, types::SecretParams)> {
-        let (pub_params, plain) = match self {
-            KeyType::Rsa(bit_size) => rsa::generate_key(rng, bit_size as usize)?,
-            KeyType::ECDH => ecdh::generate_key(rng),
-            KeyType::EdDSA => eddsa::generate_key(rng),
-        };
+        let (pub_params, plain) = self.generate_plain(rng)?;
 
-        let secret = match passphrase {
-            Some(passphrase) => {
-                // TODO: make configurable
-                let s2k = types::StringToKey::new_default(rng);
-                let alg = SymmetricKeyAlgorithm::AES256;
-                // encrypted, sha1 checksum
-                let id = 254;
-
-                // TODO: derive from key itself
-                let version = types::KeyVersion::default();
-
-                types::SecretParams::Encrypted(plain.encrypt(
-                    rng,
-                    &passphrase,
-                    alg,
-                    s2k,
-                    version,
-                    id,
-                )?)
-            }
-            None => types::SecretParams::Plain(plain),
-        };
+        Ok((
+            pub_params,
+            protect(rng, plain, passphrase, types::KeyVersion::V4)?,
+        ))
+    }
 
-        Ok((pub_params, secret))
+    fn generate_plain<R: Rng + CryptoRng>(
+        self,
+        rng: &mut R,
+    ) -> Result<(PublicParams, PlainSecretParams)> {
+        match self {
+            KeyType::Rsa(bit_size) => rsa::generate_key(rng, bit_size as usize),
+            KeyType::ECDH => Ok(ecdh::generate_key(rng)),
+            KeyType::EdDSA => Ok(eddsa::generate_key(rng)),
+            KeyType::X25519 => Ok(x25519::generate_key(rng)),
+            KeyType::Ed25519 => Ok(ed25519::generate_key(rng)),
+            KeyType::X448 => Ok(x448::generate_key(rng)),
+            KeyType::Ed448 => Ok(ed448::generate_key(rng)),
+        }
     }
 }
 
+/// Encrypts the secret key material, if a passphrase is given.
+fn protect<R: Rng + CryptoRng>(
+    rng: &mut R,
+    plain: PlainSecretParams,
+    passphrase: Option<String>,
+    version: types::KeyVersion,
+) -> Result<types::SecretParams> {
+    let secret = match passphrase {
+        Some(passphrase) => {
+            // TODO: make configurable
+            let params = types::S2kParams::default();
+
+            types::SecretParams::Encrypted(plain.protect(rng, &passphrase, &params, version)?)
+        }
+        None => types::SecretParams::Plain(plain),
+    };
+
+    Ok(secret)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use crate::composed::{Deserializable, SignedPublicKey, SignedSecretKey};
-    use crate::types::SecretKeyTrait;
+    use crate::composed::test_keys::{
+        gen_key_v6, gen_key_with_signing_subkey, gen_key_with_subkeys,
+    };
+    use crate::composed::{Deserializable, Preferences, SignedPublicKey, SignedSecretKey};
+    use crate::de::Deserialize;
+    use crate::packet::SignatureType;
+    use crate::ser::Serialize;
+    use crate::types::{KeyTrait, SecretKeyTrait, S2K_USAGE_AEAD};
 
     use rand::SeedableRng;
     use rand_chacha::ChaCha8Rng;
@@ -479,4 +773,1745 @@ mod tests {
             SignedPublicKey::from_string(&armor).expect("failed to parse public key");
         signed_key2.verify().expect("invalid public key");
     }
+
+    #[test]
+    fn test_passphrase_normalization() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        // "café", with a decomposed "é"
+        let nfd = "cafe\u{301}".to_string();
+        let nfc = "caf\u{e9}".to_string();
+
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .primary_user_id("Me-X <me-x25519@mail.com>".into())
+            .passphrase(Some(nfd.clone()))
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key");
+
+        let signed_key = key
+            .sign(|| nfd.clone())
+            .expect("failed to sign with NFD passphrase");
+
+        signed_key
+            .unlock(|| nfc.clone(), |_| Ok(()))
+            .expect("failed to unlock with NFC passphrase");
+        signed_key
+            .unlock(|| nfd.clone(), |_| Ok(()))
+            .expect("failed to unlock with NFD passphrase");
+        assert!(signed_key.unlock(|| "cafe".into(), |_| Ok(())).is_err());
+    }
+
+    #[test]
+    #[ignore] // slow in debug mode
+    fn test_key_gen_rsa_2048_v6() {
+        let _ = pretty_env_logger::try_init();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::Rsa(2048))
+            .version(types::KeyVersion::V6)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .passphrase(Some("hello".into()))
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::Rsa(2048))
+                    .version(types::KeyVersion::V6)
+                    .passphrase(Some("hello".into()))
+                    .can_encrypt(true)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key");
+
+        let signed_key = key.sign(|| "hello".into()).expect("failed to sign key");
+        assert_eq!(signed_key.version(), types::KeyVersion::V6);
+        assert_eq!(signed_key.fingerprint().len(), 32);
+
+        let armor = signed_key
+            .to_armored_string(None)
+            .expect("failed to serialize key");
+
+        let (signed_key2, _headers) =
+            SignedSecretKey::from_string(&armor).expect("failed to parse key");
+        signed_key2.verify().expect("invalid key");
+        signed_key2
+            .unlock(|| "hello".into(), |_| Ok(()))
+            .expect("failed to unlock parsed key");
+
+        assert_eq!(signed_key, signed_key2);
+
+        let public_key = signed_key.public_key();
+        let public_signed_key = public_key
+            .sign(&signed_key, || "hello".into())
+            .expect("failed to sign public key");
+        public_signed_key.verify().expect("invalid public key");
+
+        let armor = public_signed_key
+            .to_armored_string(None)
+            .expect("failed to serialize public key");
+        let (public_signed_key2, _headers) =
+            SignedPublicKey::from_string(&armor).expect("failed to parse public key");
+        public_signed_key2.verify().expect("invalid public key");
+        assert_eq!(
+            public_signed_key.fingerprint(),
+            public_signed_key2.fingerprint()
+        );
+    }
+
+    #[test]
+    #[ignore] // slow in debug mode
+    fn test_key_gen_bare_v6() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::Rsa(2048))
+            .version(types::KeyVersion::V6)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .preferred_symmetric_algorithms(smallvec![SymmetricKeyAlgorithm::AES256])
+            .expiration(Some(Duration::from_secs(3600)))
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key");
+        let signed_key = key.sign(|| "".into()).expect("failed to sign key");
+        signed_key.verify().expect("invalid key");
+
+        assert!(signed_key.details.users.is_empty());
+        assert_eq!(signed_key.details.direct_signatures.len(), 1);
+        let sig = &signed_key.details.direct_signatures[0];
+        assert_eq!(sig.typ(), SignatureType::Key);
+        assert!(sig.key_flags().sign());
+        assert_eq!(
+            sig.preferred_symmetric_algs(),
+            &[SymmetricKeyAlgorithm::AES256][..]
+        );
+        assert_eq!(
+            signed_key.key_expiration_time(),
+            Some(chrono::Duration::hours(1))
+        );
+
+        let armor = signed_key.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedSecretKey::from_string(&armor).expect("failed to parse key");
+        assert_eq!(parsed, signed_key);
+
+        let public_key = signed_key.signed_public_key();
+        public_key.verify().expect("invalid public key");
+        let resigned = signed_key
+            .public_key()
+            .sign(&signed_key, || "".into())
+            .expect("failed to sign public key");
+        assert!(resigned.details.users.is_empty());
+        resigned.verify().expect("invalid public key");
+
+        // bare keys are only accepted if the policy allows them
+        let policy = crate::composed::Policy::default();
+        assert!(policy.check_key(&public_key).is_err());
+        assert!(!public_key
+            .health_report(&policy, chrono::Utc::now())
+            .is_healthy());
+        let policy = crate::composed::Policy {
+            accept_bare_keys: true,
+            ..Default::default()
+        };
+        policy.check_key(&public_key).expect("rejected bare key");
+        assert!(public_key
+            .health_report(&policy, chrono::Utc::now())
+            .is_healthy());
+
+        let mut unsigned = public_key;
+        unsigned.details.direct_signatures.clear();
+        assert!(policy.check_key(&unsigned).is_err());
+    }
+
+    #[test]
+    fn test_aead_protected_key() {
+        use crate::crypto::AeadAlgorithm;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut key = gen_key_v6(&mut rng, Some("Me <me@mail.com>"));
+
+        let plain = match key.primary_key.secret_params() {
+            types::SecretParams::Plain(plain) => plain.clone(),
+            params => panic!("unexpected {:?}", params),
+        };
+        let aad = key.primary_key.aead_associated_data().unwrap();
+        let s2k = types::StringToKey::new_iterated(&mut rng, HashAlgorithm::SHA2_256, 96);
+        for aead in &[AeadAlgorithm::Eax, AeadAlgorithm::Ocb, AeadAlgorithm::Gcm] {
+            let encrypted = plain
+                .encrypt_aead(
+                    &mut rng,
+                    "hello",
+                    SymmetricKeyAlgorithm::AES256,
+                    *aead,
+                    s2k.clone(),
+                    &aad,
+                )
+                .unwrap();
+            key.primary_key.secret_params = types::SecretParams::Encrypted(encrypted);
+
+            let armor = key.to_armored_string(None).unwrap();
+            let (parsed, _) = SignedSecretKey::from_string(&armor).expect("failed to parse key");
+            assert_eq!(parsed, key);
+            assert_eq!(
+                parsed.primary_key.secret_params().string_to_key_id(),
+                S2K_USAGE_AEAD
+            );
+
+            parsed
+                .unlock(|| "hello".into(), |_| Ok(()))
+                .expect("failed to unlock key");
+            assert!(parsed.unlock(|| "world".into(), |_| Ok(())).is_err());
+        }
+
+        // the encryption is bound to the public key
+        let mut moved = key.clone();
+        moved.secret_subkeys[0].key.secret_params = key.primary_key.secret_params().clone();
+        assert!(moved.secret_subkeys[0]
+            .unlock(|| "hello".into(), |_| Ok(()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_bare_v6_key_details() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key_v6(&mut rng, None);
+        key.verify().expect("invalid key");
+        assert!(key.details.users.is_empty());
+
+        let resigned = key
+            .public_key()
+            .sign(&key, || "".into())
+            .expect("failed to sign public key");
+        resigned.verify().expect("invalid public key");
+        assert!(resigned.details.direct_signatures[0].key_flags().sign());
+
+        // without a self-signature there is nothing to take the details from
+        let mut unsigned = key.signed_public_key();
+        unsigned.details.direct_signatures.clear();
+        assert_eq!(
+            unsigned.details.as_unsigned(&unsigned.primary_key),
+            crate::composed::KeyDetailsBuilder::default()
+                .build()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_key_gen_without_user_id() {
+        // only v6 keys can be generated without user ids
+        assert!(SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .build()
+            .is_err());
+        assert!(SecretKeyParamsBuilder::default()
+            .key_type(KeyType::Rsa(2048))
+            .version(types::KeyVersion::V6)
+            .user_id("Me <me@mail.com>")
+            .build()
+            .is_err());
+        assert!(SecretKeyParamsBuilder::default()
+            .key_type(KeyType::Rsa(2048))
+            .version(types::KeyVersion::V6)
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::Rsa(2048))
+                    .version(types::KeyVersion::V6)
+                    .can_encrypt(true)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_key_gen_v6_seeded_salts() {
+        let salts = |key: &SignedSecretKey| {
+            key.details.users[0]
+                .signatures
+                .iter()
+                .chain(&key.secret_subkeys[0].signatures)
+                .map(|sig| sig.salt().expect("missing salt").to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        let key = gen_key_v6(&mut ChaCha8Rng::seed_from_u64(0), Some("Me <me@mail.com>"));
+        let same = gen_key_v6(&mut ChaCha8Rng::seed_from_u64(0), Some("Me <me@mail.com>"));
+        let other = gen_key_v6(&mut ChaCha8Rng::seed_from_u64(1), Some("Me <me@mail.com>"));
+        assert_eq!(salts(&key), salts(&same));
+        assert_ne!(salts(&key), salts(&other));
+    }
+
+    #[test]
+    fn test_key_gen_v6_legacy_curve25519() {
+        let res = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .version(types::KeyVersion::V6)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .build();
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_key_gen_ed448_x448() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        for version in &[types::KeyVersion::V4, types::KeyVersion::V6] {
+            let key = SecretKeyParamsBuilder::default()
+                .key_type(KeyType::Ed448)
+                .version(*version)
+                .can_create_certificates(true)
+                .can_sign(true)
+                .primary_user_id("Me <me@mail.com>".into())
+                .preferred_hash_algorithms(smallvec![HashAlgorithm::SHA2_512])
+                .subkey(
+                    SubkeyParamsBuilder::default()
+                        .key_type(KeyType::X448)
+                        .version(*version)
+                        .can_encrypt(true)
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()
+                .generate_with_rng(&mut rng)
+                .expect("failed to generate secret key");
+            let signed_key = key.sign(|| "".into()).expect("failed to sign key");
+            signed_key.verify().expect("invalid key");
+
+            assert_eq!(signed_key.algorithm(), PublicKeyAlgorithm::Ed448);
+            assert_eq!(
+                signed_key.secret_subkeys[0].algorithm(),
+                PublicKeyAlgorithm::X448
+            );
+
+            let armor = signed_key
+                .to_armored_string(None)
+                .expect("failed to serialize key");
+            let (signed_key2, _headers) =
+                SignedSecretKey::from_string(&armor).expect("failed to parse key");
+            signed_key2.verify().expect("invalid key");
+            assert_eq!(signed_key, signed_key2);
+        }
+
+        assert!(SecretKeyParamsBuilder::default()
+            .key_type(KeyType::Ed448)
+            .can_encrypt(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_gen_ed25519_x25519() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        for version in &[types::KeyVersion::V4, types::KeyVersion::V6] {
+            let key = SecretKeyParamsBuilder::default()
+                .key_type(KeyType::Ed25519)
+                .version(*version)
+                .can_create_certificates(true)
+                .can_sign(true)
+                .primary_user_id("Me <me@mail.com>".into())
+                .subkey(
+                    SubkeyParamsBuilder::default()
+                        .key_type(KeyType::X25519)
+                        .version(*version)
+                        .can_encrypt(true)
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()
+                .generate_with_rng(&mut rng)
+                .expect("failed to generate secret key");
+            let signed_key = key.sign(|| "".into()).expect("failed to sign key");
+            signed_key.verify().expect("invalid key");
+
+            assert_eq!(signed_key.algorithm(), PublicKeyAlgorithm::Ed25519);
+            assert_eq!(
+                signed_key.secret_subkeys[0].algorithm(),
+                PublicKeyAlgorithm::X25519
+            );
+
+            let armor = signed_key
+                .to_armored_string(None)
+                .expect("failed to serialize key");
+            let (signed_key2, _headers) =
+                SignedSecretKey::from_string(&armor).expect("failed to parse key");
+            signed_key2.verify().expect("invalid key");
+            assert_eq!(signed_key, signed_key2);
+        }
+
+        assert!(SecretKeyParamsBuilder::default()
+            .key_type(KeyType::X25519)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_gen_authentication_subkey() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::EdDSA)
+                    .can_authenticate(true)
+                    .build()
+                    .unwrap(),
+            )
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt_comms(true)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key");
+        let signed_key = key.sign(|| "".into()).expect("failed to sign key");
+
+        let flags = signed_key.secret_subkeys[0].signatures[0].key_flags();
+        assert!(flags.authentication());
+        assert!(!flags.sign());
+
+        let flags = signed_key.secret_subkeys[1].signatures[0].key_flags();
+        assert!(flags.encrypt_comms());
+        assert!(!flags.encrypt_storage());
+
+        assert!(SecretKeyParamsBuilder::default()
+            .key_type(KeyType::ECDH)
+            .can_authenticate(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_gen_signing_subkey_backsig() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::EdDSA)
+                    .can_sign(true)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key");
+        let signed_key = key.sign(|| "".into()).expect("failed to sign key");
+        signed_key.verify().expect("invalid key");
+
+        let subkey = &signed_key.secret_subkeys[0];
+        let backsig = subkey.signatures[0]
+            .embedded_signature()
+            .expect("missing back signature");
+        assert_eq!(backsig.typ(), SignatureType::KeyBinding);
+        backsig
+            .verify_primary_key_binding(&subkey.key, &signed_key.primary_key)
+            .expect("invalid back signature");
+
+        assert!(subkey.can_sign(&signed_key.primary_key, &Default::default()));
+
+        // the back signature is part of the binding
+        let mut stripped = subkey.clone();
+        stripped.signatures[0]
+            .config
+            .hashed_subpackets
+            .retain(|p| match p {
+                Subpacket::EmbeddedSignature(_) => false,
+                _ => true,
+            });
+        assert!(stripped.verify(&signed_key.primary_key).is_err());
+
+        // a binding without the back signature does not invalidate the key, but the subkey
+        // is not used for signing
+        let unbacked = subkey
+            .public_key()
+            .sign(&signed_key, || "".into())
+            .expect("failed to sign subkey");
+        assert!(unbacked.signatures[0].embedded_signature().is_none());
+        let mut unbacked_key = signed_key.clone();
+        unbacked_key.secret_subkeys[0].signatures = unbacked.signatures;
+        unbacked_key.verify().expect("invalid key");
+        unbacked_key
+            .signed_public_key()
+            .verify()
+            .expect("invalid key");
+        assert!(unbacked_key.signing_subkey().is_none());
+        let unbacked_subkey = &unbacked_key.secret_subkeys[0];
+        assert!(!unbacked_subkey.can_sign(&signed_key.primary_key, &Default::default()));
+
+        // unless the policy allows it
+        let lax = crate::composed::Policy {
+            require_back_signatures: false,
+            ..Default::default()
+        };
+        assert!(unbacked_subkey.can_sign(&signed_key.primary_key, &lax));
+
+        // a back signature, that does not verify, is never accepted
+        let mut forged = unbacked_subkey.clone();
+        let mut backsig = subkey.signatures[0].embedded_signature().unwrap().clone();
+        backsig
+            .config
+            .hashed_subpackets
+            .push(Subpacket::IsPrimary(true));
+        forged.signatures[0]
+            .config
+            .unhashed_subpackets
+            .push(Subpacket::EmbeddedSignature(Box::new(backsig)));
+        forged
+            .verify(&signed_key.primary_key)
+            .expect("invalid binding");
+        assert!(!forged.can_sign(&signed_key.primary_key, &lax));
+    }
+
+    #[test]
+    fn test_key_gen_subkey_subpackets() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .expiration(Some(Duration::from_secs(60 * 60 * 24)))
+                    .hashed_subpacket(Subpacket::PreferredSymmetricAlgorithms(smallvec![
+                        SymmetricKeyAlgorithm::AES256
+                    ]))
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key");
+        let signed_key = key.sign(|| "".into()).expect("failed to sign key");
+        signed_key.verify().expect("invalid key");
+
+        let sig = &signed_key.secret_subkeys[0].signatures[0];
+        assert_eq!(
+            sig.preferred_symmetric_algs(),
+            &[SymmetricKeyAlgorithm::AES256][..]
+        );
+        assert!(sig.key_expiration_time().is_some());
+
+        // the primary key has neither
+        let sig = &signed_key.details.users[0].signatures[0];
+        assert!(sig.preferred_symmetric_algs().is_empty());
+        assert!(sig.key_expiration_time().is_none());
+    }
+
+    #[test]
+    fn test_add_subkey() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let signed_key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key")
+            .sign(|| "".into())
+            .expect("failed to sign key");
+        assert!(signed_key.secret_subkeys.is_empty());
+
+        let signed_key = signed_key
+            .add_subkey_with_rng(
+                &mut rng,
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .build()
+                    .unwrap(),
+                || "".into(),
+            )
+            .expect("failed to add subkey");
+        signed_key.verify().expect("invalid key");
+
+        assert_eq!(signed_key.secret_subkeys.len(), 1);
+        let sig = &signed_key.secret_subkeys[0].signatures[0];
+        assert_eq!(sig.typ(), SignatureType::SubkeyBinding);
+        assert!(sig.key_flags().encrypt_comms());
+
+        // survives a roundtrip
+        let armor = signed_key.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedSecretKey::from_string(&armor).unwrap();
+        parsed.verify().expect("invalid parsed key");
+        assert_eq!(parsed, signed_key);
+    }
+
+    #[test]
+    fn test_signed_public_key() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let mut signed_key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .user_id("Me <me@work.com>")
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key")
+            .sign(|| "".into())
+            .expect("failed to sign key");
+        let work_id = signed_key.details.users[1].id.clone();
+        signed_key
+            .revoke_user_id(
+                &work_id,
+                crate::packet::RevocationReason::new(
+                    crate::packet::RevocationCode::CertUserIdInvalid,
+                    "left",
+                ),
+                || "".into(),
+            )
+            .expect("failed to revoke user id");
+
+        let public_key = SignedPublicKey::from(&signed_key);
+        public_key.verify().expect("invalid public key");
+        assert_eq!(public_key.fingerprint(), signed_key.fingerprint());
+        assert_eq!(public_key.details, signed_key.details);
+        assert!(public_key.details.users[1].is_revoked());
+        assert_eq!(public_key.public_subkeys.len(), 1);
+        assert_eq!(
+            public_key.public_subkeys[0].signatures,
+            signed_key.secret_subkeys[0].signatures
+        );
+        assert_eq!(
+            public_key.public_subkeys[0].key_id(),
+            signed_key.secret_subkeys[0].key_id()
+        );
+
+        let armor = public_key.to_armored_string(None).unwrap();
+        assert!(armor.starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----"));
+        let (parsed, _) = SignedPublicKey::from_string(&armor).unwrap();
+        assert_eq!(parsed, public_key);
+    }
+
+    #[test]
+    fn test_remove_primary_secret() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let mut signed_key = gen_key_with_signing_subkey(&mut rng, "Me <me@mail.com>");
+        let public_key = signed_key.signed_public_key();
+
+        signed_key.remove_primary_secret();
+        assert!(!signed_key.has_primary_secret());
+        signed_key.verify().expect("invalid key");
+
+        // survives a roundtrip, as a gnu-dummy stub
+        let armor = signed_key.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedSecretKey::from_string(&armor).unwrap();
+        assert_eq!(parsed, signed_key);
+        assert_eq!(
+            parsed.primary_key.secret_params(),
+            &types::SecretParams::Stub(types::SecretKeyStub::GnuDummy)
+        );
+
+        // stubs written by older GnuPG versions, and unknown extension modes
+        let alg = signed_key.primary_key.algorithm();
+        assert_eq!(
+            types::SecretParams::from_slice(b"\xfe\x03\x65\x02GNU\x01", alg).unwrap(),
+            types::SecretParams::Stub(types::SecretKeyStub::GnuDummy)
+        );
+        match types::SecretParams::from_slice(b"\xff\x00\x65\x00GNU\x7f", alg) {
+            Err(crate::errors::Error::Unsupported(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // the primary key can not be used anymore
+        let msg = crate::composed::Message::new_literal("hello.txt", "hello");
+        match msg
+            .clone()
+            .sign(&signed_key, || "".into(), HashAlgorithm::SHA2_256)
+        {
+            Err(crate::errors::Error::SecretKeyNotPresent { key_id }) => {
+                assert_eq!(key_id, signed_key.key_id())
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(signed_key
+            .clone()
+            .add_user_id("Me <me@work.com>", || "".into())
+            .is_err());
+
+        // but the subkeys can
+        let signed = msg
+            .sign(
+                &signed_key.secret_subkeys[0],
+                || "".into(),
+                HashAlgorithm::SHA2_256,
+            )
+            .expect("failed to sign with subkey");
+        signed
+            .verify(&public_key.public_subkeys[0])
+            .expect("invalid signature");
+
+        let encrypted = signed
+            .encrypt_to_keys(
+                &mut rng,
+                SymmetricKeyAlgorithm::AES128,
+                &[&public_key.public_subkeys[1]],
+            )
+            .unwrap();
+        let (mut decrypter, _) = encrypted
+            .decrypt(|| "".into(), || "".into(), &[&signed_key])
+            .unwrap();
+        let decrypted = decrypter.next().unwrap().unwrap();
+        assert_eq!(decrypted, signed);
+
+        // passphrases of the remaining secret keys can still be changed
+        let protected = signed_key
+            .change_passphrase(|| "".into(), Some("pw"), &types::S2kParams::default())
+            .expect("failed to change passphrase");
+        assert!(!protected.has_primary_secret());
+        assert!(protected.secret_subkeys[0]
+            .key
+            .secret_params()
+            .is_encrypted());
+
+        // subkeys without secret material are not used for signing
+        assert_eq!(
+            signed_key.signing_subkey().map(|subkey| subkey.key_id()),
+            Some(signed_key.secret_subkeys[0].key_id())
+        );
+        signed_key.secret_subkeys[0].key.remove_secret();
+        assert!(signed_key.signing_subkey().is_none());
+    }
+
+    #[test]
+    fn test_remove_primary_secret_v6() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut signed_key = gen_key_v6(&mut rng, Some("Me <me@mail.com>"));
+
+        signed_key.remove_primary_secret();
+        let bytes = signed_key.to_bytes().unwrap();
+        let parsed = SignedSecretKey::from_bytes(&bytes[..]).unwrap();
+        assert_eq!(parsed, signed_key);
+        assert!(!parsed.has_primary_secret());
+
+        let serial = vec![0xD2, 0x76, 0x00, 0x01, 0x24, 0x01];
+        signed_key.secret_subkeys[0].key.remove_secret();
+        signed_key.secret_subkeys[0].key.secret_params =
+            types::SecretParams::Stub(types::SecretKeyStub::DivertToCard { serial });
+        let bytes = signed_key.to_bytes().unwrap();
+        let parsed = SignedSecretKey::from_bytes(&bytes[..]).unwrap();
+        assert_eq!(parsed, signed_key);
+    }
+
+    #[test]
+    fn test_certify_user_id() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key_with_subkeys(&mut rng, "Alice <alice@mail.com>", 0);
+        let bob = gen_key_with_subkeys(&mut rng, "Bob <bob@mail.com>", 0);
+
+        let mut bob_public = bob.signed_public_key();
+        let bob_id = bob_public.details.users[0].id.clone();
+        bob_public
+            .certify_user_id(
+                &alice,
+                &bob_id,
+                || "".into(),
+                &types::CertificationOptions {
+                    typ: SignatureType::CertPositive,
+                    exportable: false,
+                    ..Default::default()
+                },
+            )
+            .expect("failed to certify");
+        // the self-signatures are still valid
+        bob_public.verify().expect("invalid key");
+
+        let armor = bob_public.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedPublicKey::from_string(&armor).unwrap();
+        assert_eq!(parsed, bob_public);
+
+        let user = &parsed.details.users[0];
+        assert_eq!(user.signatures.len(), 2);
+        let cert = &user.signatures[1];
+        assert_eq!(cert.typ(), SignatureType::CertPositive);
+        assert!(cert.is_issued_by(&alice));
+        assert!(!cert.exportable_certification());
+        user.verify_certifications(&parsed.primary_key, &alice.primary_key)
+            .expect("invalid certification");
+        // the certification is bound to the key
+        assert!(user
+            .verify_certifications(&alice.primary_key, &alice.primary_key)
+            .is_err());
+        assert!(user
+            .verify_certifications(
+                &parsed.primary_key,
+                &gen_key_with_subkeys(&mut rng, "Eve <eve@mail.com>", 0)
+            )
+            .is_err());
+
+        let unknown = crate::packet::UserId::from_str(Default::default(), "Bob <bob@work.com>");
+        assert!(bob_public
+            .certify_user_id(&alice, &unknown, || "".into(), &Default::default())
+            .is_err());
+        let options = types::CertificationOptions {
+            typ: SignatureType::Binary,
+            ..Default::default()
+        };
+        assert!(bob_public
+            .certify_user_id(&alice, &bob_id, || "".into(), &options)
+            .is_err());
+    }
+
+    #[test]
+    fn test_attest_certifications() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key_with_subkeys(&mut rng, "Alice <alice@mail.com>", 0);
+        let mut bob = gen_key_with_subkeys(&mut rng, "Bob <bob@mail.com>", 0);
+        let carol = gen_key_with_subkeys(&mut rng, "Carol <carol@mail.com>", 0);
+        let preferences = Preferences {
+            hash_algorithms: smallvec![HashAlgorithm::SHA1, HashAlgorithm::SHA2_384],
+            ..Default::default()
+        };
+        bob.set_preferences(&preferences, || "".into()).unwrap();
+
+        let mut bob_public = bob.signed_public_key();
+        let bob_id = bob_public.details.users[0].id.clone();
+        for certifier in &[&alice, &carol] {
+            bob_public
+                .certify_user_id(*certifier, &bob_id, || "".into(), &Default::default())
+                .expect("failed to certify");
+        }
+        assert!(bob_public.details.users[0]
+            .attested_certifications(&bob_public.primary_key)
+            .is_empty());
+
+        bob_public
+            .attest_certifications(&bob_id, &bob, || "".into(), |sig| sig.is_issued_by(&alice))
+            .expect("failed to attest");
+        bob_public.verify().expect("invalid key");
+
+        let armor = bob_public.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedPublicKey::from_string(&armor).unwrap();
+        assert_eq!(parsed, bob_public);
+        parsed.verify().expect("invalid key");
+
+        let user = &parsed.details.users[0];
+        let attestation = &user.signatures[3];
+        assert_eq!(attestation.typ(), SignatureType::Attestation);
+        assert!(!attestation.is_certification());
+        // the digests use the strongest hash bob prefers
+        assert_eq!(attestation.config.hash_alg, HashAlgorithm::SHA2_384);
+        assert_eq!(attestation.attested_certifications().len(), 1);
+        let attested = user.attested_certifications(&parsed.primary_key);
+        assert_eq!(attested.len(), 1);
+        assert!(attested[0].is_issued_by(&alice));
+        // the attestation does not replace the self-signature
+        assert_eq!(
+            parsed.details.latest_self_signature(&parsed.primary_key),
+            Some(&user.signatures[0])
+        );
+
+        // a new attestation withdraws the earlier approvals
+        bob_public
+            .attest_certifications(&bob_id, &bob, || "".into(), |_| false)
+            .expect("failed to attest");
+        let user = &bob_public.details.users[0];
+        assert_eq!(
+            user.signatures
+                .iter()
+                .filter(|sig| sig.typ() == SignatureType::Attestation)
+                .count(),
+            1
+        );
+        assert!(user
+            .attested_certifications(&bob_public.primary_key)
+            .is_empty());
+
+        assert!(bob_public
+            .attest_certifications(&bob_id, &alice, || "".into(), |_| true)
+            .is_err());
+
+        // the digests are sorted, and their length is checked when parsing
+        bob_public
+            .attest_certifications(&bob_id, &bob, || "".into(), |_| true)
+            .expect("failed to attest");
+        let mut attestation = bob_public.details.users[0]
+            .signatures
+            .iter()
+            .find(|sig| sig.typ() == SignatureType::Attestation)
+            .unwrap()
+            .clone();
+        let digests = attestation.attested_certifications();
+        assert_eq!(digests.len(), 2);
+        assert!(digests[0] < digests[1]);
+        for p in &mut attestation.config.hashed_subpackets {
+            if let Subpacket::AttestedCertifications(digests) = p {
+                digests.push(0);
+            }
+        }
+        let bytes = attestation.to_bytes().unwrap();
+        assert!(packet::Signature::from_slice(Default::default(), &bytes).is_err());
+    }
+
+    #[test]
+    fn test_primary_user_id() {
+        use chrono::{Duration, SubsecRound, Utc};
+
+        use crate::packet::{SignatureConfigBuilder, SignatureVersion, Subpacket};
+        use crate::types::Tag;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .user_id("Me <me@work.com>")
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key")
+            .sign(|| "".into())
+            .expect("failed to sign key");
+        let home = key.details.users[0].id.clone();
+        let work = key.details.users[1].id.clone();
+        let now = Utc::now();
+
+        let public_key = key.signed_public_key();
+        assert_eq!(public_key.primary_user_id(now), Some(&home));
+        // no self-certification is valid before the key was created
+        assert_eq!(
+            public_key.primary_user_id(*key.primary_key.created_at() - Duration::days(1)),
+            None
+        );
+
+        // a newer self-certification marks the other user id as primary
+        let later = now.trunc_subsecs(0) + Duration::hours(1);
+        let sig = SignatureConfigBuilder::default()
+            .typ(SignatureType::CertPositive)
+            .version(SignatureVersion::for_key(&key.primary_key))
+            .pub_alg(key.primary_key.algorithm())
+            .hashed_subpackets(vec![
+                Subpacket::SignatureCreationTime(later),
+                Subpacket::IsPrimary(true),
+            ])
+            .unhashed_subpackets(vec![])
+            .build()
+            .unwrap()
+            .with_issuer(&key.primary_key)
+            .sign_certificate(&key.primary_key, || "".into(), Tag::UserId, &work)
+            .unwrap();
+        key.details.users[1].signatures.push(sig);
+
+        let public_key = key.signed_public_key();
+        assert_eq!(public_key.primary_user_id(now), Some(&home));
+        assert_eq!(
+            public_key.primary_user_id(later + Duration::minutes(1)),
+            Some(&work)
+        );
+        // the preferences come from the same, currently valid, primary user id
+        assert_eq!(
+            key.details.primary_user(&key.primary_key).map(|u| &u.id),
+            Some(&home)
+        );
+
+        // a revocation only applies from the time it was made
+        let revoked_at = later + Duration::days(1);
+        let sig = SignatureConfigBuilder::default()
+            .typ(SignatureType::CertRevocation)
+            .version(SignatureVersion::for_key(&key.primary_key))
+            .pub_alg(key.primary_key.algorithm())
+            .hashed_subpackets(vec![Subpacket::SignatureCreationTime(revoked_at)])
+            .unhashed_subpackets(vec![])
+            .build()
+            .unwrap()
+            .with_issuer(&key.primary_key)
+            .sign_certificate(&key.primary_key, || "".into(), Tag::UserId, &work)
+            .unwrap();
+        let mut revoked = key.signed_public_key();
+        revoked.details.users[1].signatures.push(sig);
+        assert_eq!(
+            revoked.primary_user_id(later + Duration::minutes(1)),
+            Some(&work)
+        );
+        assert_eq!(
+            revoked.primary_user_id(revoked_at + Duration::minutes(1)),
+            Some(&home)
+        );
+
+        key.revoke_user_id(
+            &work,
+            crate::packet::RevocationReason::new(
+                crate::packet::RevocationCode::CertUserIdInvalid,
+                "",
+            ),
+            || "".into(),
+        )
+        .unwrap();
+        let public_key = key.signed_public_key();
+        assert_eq!(
+            public_key.primary_user_id(later + Duration::minutes(1)),
+            Some(&home)
+        );
+    }
+
+    #[test]
+    fn test_revoke_certification() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key_with_subkeys(&mut rng, "Alice <alice@mail.com>", 0);
+        let bob = gen_key_with_subkeys(&mut rng, "Bob <bob@mail.com>", 0);
+        let eve = gen_key_with_subkeys(&mut rng, "Eve <eve@mail.com>", 0);
+
+        let mut bob_public = bob.signed_public_key();
+        let bob_id = bob_public.details.users[0].id.clone();
+        let reason = || {
+            crate::packet::RevocationReason::new(
+                crate::packet::RevocationCode::NoReason,
+                "signed the wrong key",
+            )
+        };
+        // there is nothing to revoke yet
+        assert!(bob_public
+            .revoke_certification(&alice, &bob_id, reason(), || "".into())
+            .is_err());
+
+        bob_public
+            .certify_user_id(&alice, &bob_id, || "".into(), &Default::default())
+            .expect("failed to certify");
+        bob_public
+            .revoke_certification(&alice, &bob_id, reason(), || "".into())
+            .expect("failed to revoke certification");
+        assert!(bob_public
+            .revoke_certification(&eve, &bob_id, reason(), || "".into())
+            .is_err());
+
+        let armor = bob_public.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedPublicKey::from_string(&armor).unwrap();
+        parsed.verify().expect("invalid key");
+
+        let user = &parsed.details.users[0];
+        let revocation = &user.signatures[2];
+        assert_eq!(revocation.typ(), SignatureType::CertRevocation);
+        assert_eq!(
+            revocation.issuer_fingerprint(),
+            Some((alice.version(), &alice.fingerprint()[..]))
+        );
+        assert_eq!(
+            revocation.revocation_reason_string(),
+            Some("signed the wrong key")
+        );
+        user.verify_certifications(&parsed.primary_key, &alice)
+            .expect("invalid revocation");
+        assert!(user.is_certification_revoked(&parsed.primary_key, &alice));
+        assert!(!user.is_certification_revoked(&parsed.primary_key, &eve));
+        // the user id itself is still valid
+        assert!(!user.is_revoked_by(&parsed.primary_key));
+        assert_eq!(
+            parsed
+                .details
+                .primary_user(&parsed.primary_key)
+                .map(|u| &u.id),
+            Some(&user.id)
+        );
+    }
+
+    #[test]
+    fn test_trust_signature() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let root = gen_key_with_subkeys(&mut rng, "Root <root@mail.com>", 0);
+        let ca = gen_key_with_subkeys(&mut rng, "CA <ca@example.com>", 0);
+
+        let mut ca_public = ca.signed_public_key();
+        let ca_id = ca_public.details.users[0].id.clone();
+        let options = types::CertificationOptions {
+            trust_signature: Some((1, 120)),
+            regular_expression: Some("<[^>]+[@.]example\\.com>$".into()),
+            ..Default::default()
+        };
+        ca_public
+            .certify_user_id(&root, &ca_id, || "".into(), &options)
+            .expect("failed to certify");
+        ca_public.verify().expect("invalid key");
+
+        let bytes = ca_public.to_bytes().unwrap();
+        let needle = b"example\\.com>$\0";
+        assert!(bytes.windows(needle.len()).any(|w| w == &needle[..]));
+
+        let parsed = SignedPublicKey::from_bytes(&bytes[..]).unwrap();
+        assert_eq!(parsed, ca_public);
+        let user = &parsed.details.users[0];
+        user.verify_certifications(&parsed.primary_key, &root)
+            .expect("invalid trust signature");
+        let tsig = &user.signatures[1];
+        assert_eq!(tsig.trust_signature(), Some((1, 120)));
+        assert_eq!(
+            tsig.regular_expression(),
+            Some(&b"<[^>]+[@.]example\\.com>$\0"[..])
+        );
+
+        // regular expressions only make sense in trust signatures
+        let options = types::CertificationOptions {
+            regular_expression: Some("example".into()),
+            ..Default::default()
+        };
+        assert!(ca_public
+            .certify_user_id(&root, &ca_id, || "".into(), &options)
+            .is_err());
+    }
+
+    #[test]
+    fn test_change_passphrase() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let signed_key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .passphrase(Some("hello".into()))
+            .primary_user_id("Me <me@mail.com>".into())
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .passphrase(Some("hello".into()))
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key")
+            .sign(|| "hello".into())
+            .expect("failed to sign key");
+
+        let params = types::S2kParams {
+            sym_alg: SymmetricKeyAlgorithm::AES128,
+            hash_alg: HashAlgorithm::SHA2_256,
+            count: 96,
+        };
+        assert!(signed_key
+            .clone()
+            .change_passphrase_with_rng(&mut rng, || "wrong".into(), Some("world"), &params)
+            .is_err());
+
+        let changed = signed_key
+            .clone()
+            .change_passphrase_with_rng(&mut rng, || "hello".into(), Some("world"), &params)
+            .expect("failed to change passphrase");
+        changed.verify().expect("invalid key");
+        assert_eq!(changed.details, signed_key.details);
+        assert_eq!(changed.public_key(), signed_key.public_key());
+        assert_eq!(
+            changed.secret_subkeys[0].signatures,
+            signed_key.secret_subkeys[0].signatures
+        );
+
+        for params in std::iter::once(changed.primary_key.secret_params())
+            .chain(changed.secret_subkeys.iter().map(|k| k.key.secret_params()))
+        {
+            match params {
+                types::SecretParams::Encrypted(k) => {
+                    assert_eq!(k.encryption_algorithm(), SymmetricKeyAlgorithm::AES128)
+                }
+                _ => panic!("key must be encrypted"),
+            }
+        }
+        assert!(changed
+            .primary_key
+            .unlock(|| "hello".into(), |_| Ok(()))
+            .is_err());
+        changed
+            .primary_key
+            .unlock(|| "world".into(), |_| Ok(()))
+            .expect("failed to unlock with the new passphrase");
+        changed.secret_subkeys[0]
+            .key
+            .unlock(|| "world".into(), |_| Ok(()))
+            .expect("failed to unlock subkey with the new passphrase");
+
+        // survives a roundtrip
+        let armor = changed.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedSecretKey::from_string(&armor).unwrap();
+        assert_eq!(parsed, changed);
+
+        // remove the protection
+        let plain = changed
+            .change_passphrase_with_rng(&mut rng, || "world".into(), None, &params)
+            .expect("failed to remove passphrase");
+        assert!(!plain.primary_key.secret_params().is_encrypted());
+        assert!(!plain.secret_subkeys[0].key.secret_params().is_encrypted());
+    }
+
+    #[test]
+    fn test_reprotect() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let signed_key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .passphrase(Some("hello".into()))
+            .primary_user_id("Me <me@mail.com>".into())
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key")
+            .sign(|| "hello".into())
+            .expect("failed to sign key");
+
+        let params = types::S2kParams {
+            sym_alg: SymmetricKeyAlgorithm::CAST5,
+            hash_alg: HashAlgorithm::SHA1,
+            count: 96,
+        };
+        let exported = signed_key
+            .reprotect_with_rng(&mut rng, || "hello".into(), &params)
+            .expect("failed to reprotect");
+        assert_eq!(exported.public_key(), signed_key.public_key());
+        assert_eq!(exported.details, signed_key.details);
+
+        match exported.primary_key.secret_params() {
+            types::SecretParams::Encrypted(k) => {
+                assert_eq!(k.encryption_algorithm(), SymmetricKeyAlgorithm::CAST5);
+                assert_eq!(k.string_to_key().hash(), HashAlgorithm::SHA1);
+                assert_eq!(k.string_to_key().count(), Some(65536));
+            }
+            _ => panic!("key must be encrypted"),
+        }
+        // unencrypted keys stay unencrypted
+        assert!(!exported.secret_subkeys[0]
+            .key
+            .secret_params()
+            .is_encrypted());
+
+        exported
+            .primary_key
+            .unlock(|| "hello".into(), |_| Ok(()))
+            .expect("failed to unlock");
+        let armor = exported.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedSecretKey::from_string(&armor).unwrap();
+        assert_eq!(parsed, exported);
+
+        assert!(signed_key
+            .reprotect_with_rng(&mut rng, || "wrong".into(), &params)
+            .is_err());
+    }
+
+    #[test]
+    fn test_set_expiration() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let year = Duration::from_secs(365 * 24 * 60 * 60);
+
+        let mut key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .user_id("Me <me@work.com>")
+            .expiration(Some(year))
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key")
+            .sign(|| "".into())
+            .expect("failed to sign key");
+        let created_at = *key.primary_key.created_at();
+        assert_eq!(
+            key.expires_at(),
+            Some(created_at + chrono::Duration::days(365))
+        );
+
+        // shorter expiration times replace longer ones
+        for days in &[730, 30] {
+            key.set_expiration(Some(chrono::Duration::days(*days)), || "".into())
+                .expect("failed to set expiration");
+            key.verify().expect("invalid key");
+            assert_eq!(
+                key.expires_at(),
+                Some(created_at + chrono::Duration::days(*days))
+            );
+            for user in &key.details.users {
+                assert_eq!(user.signatures.len(), 1);
+            }
+        }
+        assert!(key.details.users[0].is_primary());
+        assert!(!key.details.users[1].is_primary());
+
+        let armor = key.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedSecretKey::from_string(&armor).unwrap();
+        assert_eq!(parsed.expires_at(), key.expires_at());
+
+        key.set_expiration(None, || "".into())
+            .expect("failed to remove expiration");
+        key.verify().expect("invalid key");
+        assert_eq!(key.expires_at(), None);
+
+        assert!(key
+            .set_expiration(Some(chrono::Duration::zero()), || "".into())
+            .is_err());
+    }
+
+    #[test]
+    fn test_expiration_accessors() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let days = chrono::Duration::days;
+        let created_at = chrono::Utc::now().trunc_subsecs(0) - days(2);
+
+        let mut key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .created_at(created_at)
+            .expiration(Some(Duration::from_secs(365 * 24 * 60 * 60)))
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .created_at(created_at)
+                    .expiration(Some(Duration::from_secs(7 * 24 * 60 * 60)))
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key")
+            .sign_at(|| "".into(), created_at)
+            .expect("failed to sign key");
+
+        assert_eq!(key.created_at(), &created_at);
+        assert_eq!(key.expires_at(), Some(created_at + days(365)));
+        assert!(!key.is_expired(created_at + days(364)));
+        assert!(key.is_expired(created_at + days(365)));
+
+        let subkey = &key.secret_subkeys[0];
+        assert_eq!(subkey.created_at(), &created_at);
+        assert_eq!(
+            subkey.expires_at(&key.primary_key),
+            Some(created_at + days(7))
+        );
+        assert!(!subkey.is_expired(&key.primary_key, created_at + days(6)));
+        assert!(subkey.is_expired(&key.primary_key, created_at + days(8)));
+
+        let public_key = key.signed_public_key();
+        assert_eq!(public_key.created_at(), &created_at);
+        assert_eq!(public_key.expires_at(), key.expires_at());
+        assert_eq!(
+            public_key.public_subkeys[0].expires_at(&public_key.primary_key),
+            Some(created_at + days(7))
+        );
+        assert!(
+            public_key.public_subkeys[0].is_expired(&public_key.primary_key, created_at + days(8))
+        );
+
+        // the newest self-signature wins, even if an older one has a later expiration
+        let superseded = key.details.users[0].signatures.clone();
+        key.set_expiration(Some(days(30)), || "".into())
+            .expect("failed to set expiration");
+        key.details.users[0].signatures.extend(superseded);
+        assert_eq!(key.expires_at(), Some(created_at + days(30)));
+        assert!(key.is_expired(created_at + days(31)));
+        assert_eq!(key.signed_public_key().expires_at(), key.expires_at());
+
+        key.set_expiration(None, || "".into())
+            .expect("failed to remove expiration");
+        assert_eq!(key.expires_at(), None);
+        assert!(!key.is_expired(created_at + days(10000)));
+    }
+
+    #[test]
+    fn test_key_gen_expiration() {
+        use chrono::TimeZone;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        // longer than what fits into the 16 bit v3 expiration field
+        let year = Duration::from_secs(365 * 24 * 60 * 60);
+
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .expiration(Some(year))
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .expiration(Some(year * 2))
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key");
+        let signed_key = key.sign(|| "".into()).expect("failed to sign key");
+        signed_key.verify().expect("invalid key");
+
+        assert_eq!(signed_key.primary_key.expiration(), None);
+        assert_eq!(
+            signed_key.key_expiration_time(),
+            Some(chrono::Duration::days(365))
+        );
+        assert_eq!(
+            signed_key.expires_at(),
+            Some(*signed_key.primary_key.created_at() + chrono::Duration::days(365))
+        );
+
+        let subkey = &signed_key.secret_subkeys[0];
+        assert_eq!(
+            subkey.key_expiration_time(&signed_key.primary_key),
+            Some(chrono::Duration::days(730))
+        );
+
+        // the expiration survives the conversion to a public key
+        let public_key = signed_key
+            .public_key()
+            .sign(&signed_key, || "".into())
+            .unwrap();
+        assert_eq!(
+            public_key.key_expiration_time(),
+            Some(chrono::Duration::days(365))
+        );
+        assert_eq!(
+            public_key.public_subkeys[0].key_expiration_time(&public_key.primary_key),
+            Some(chrono::Duration::days(730))
+        );
+
+        // a newer binding that does not verify is ignored
+        let mut tampered = public_key.clone();
+        let mut binding = tampered.public_subkeys[0].signatures[0].clone();
+        binding.config.hashed_subpackets.retain(|p| match p {
+            Subpacket::SignatureCreationTime(_) | Subpacket::KeyExpirationTime(_) => false,
+            _ => true,
+        });
+        binding.config.hashed_subpackets.extend(vec![
+            Subpacket::SignatureCreationTime(
+                chrono::Utc::now().trunc_subsecs(0) + chrono::Duration::days(1),
+            ),
+            Subpacket::KeyExpirationTime(chrono::Utc.timestamp(24 * 60 * 60, 0)),
+        ]);
+        tampered.public_subkeys[0].signatures.push(binding);
+        assert_eq!(
+            tampered.public_subkeys[0].key_expiration_time(&tampered.primary_key),
+            Some(chrono::Duration::days(730))
+        );
+
+        assert!(SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .primary_user_id("Me <me@mail.com>".into())
+            .expiration(Some(Duration::from_secs(u64::from(std::u32::MAX) + 1)))
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_from_key_material() {
+        use chrono::TimeZone;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let created_at = chrono::Utc.timestamp(1_500_000_000, 0);
+
+        let (public_params, secret_params) = eddsa::generate_key(&mut rng);
+        let (sub_public_params, sub_secret_params) = ecdh::generate_key(&mut rng);
+
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .created_at(created_at)
+            .key_material(public_params.clone(), secret_params)
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .created_at(created_at)
+                    .key_material(sub_public_params.clone(), sub_secret_params)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to build secret key");
+        let signed_key = key.sign(|| "".into()).expect("failed to sign key");
+        signed_key.verify().expect("invalid key");
+
+        assert_eq!(signed_key.primary_key.public_params(), &public_params);
+        assert_eq!(signed_key.primary_key.created_at(), &created_at);
+        assert_eq!(
+            signed_key.secret_subkeys[0].key.public_params(),
+            &sub_public_params
+        );
+
+        let (public_params, secret_params) = ecdh::generate_key(&mut rng);
+        assert!(SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .primary_user_id("Me <me@mail.com>".into())
+            .key_material(public_params, secret_params)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_gen_validity_window() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let not_before = chrono::Utc::now().trunc_subsecs(0) + chrono::Duration::days(10);
+        let not_after = not_before + chrono::Duration::days(30);
+
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .validity(not_before, not_after)
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .validity(not_before, not_after)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap();
+
+        // signatures must not predate the keys
+        assert!(key
+            .clone()
+            .sign_at(|| "".into(), not_before - chrono::Duration::seconds(1))
+            .is_err());
+
+        let signed_key = key.sign(|| "".into()).unwrap();
+        signed_key.verify().expect("invalid key");
+
+        assert_eq!(signed_key.primary_key.created_at(), &not_before);
+        assert_eq!(signed_key.expires_at(), Some(not_after));
+        assert_eq!(signed_key.secret_subkeys[0].key.created_at(), &not_before);
+
+        // all self-signatures are made at the start of the window
+        let sigs = signed_key
+            .details
+            .users
+            .iter()
+            .flat_map(|user| &user.signatures)
+            .chain(&signed_key.secret_subkeys[0].signatures);
+        for sig in sigs {
+            assert_eq!(sig.created(), Some(&not_before));
+        }
+
+        // empty windows are rejected
+        assert!(SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .primary_user_id("Me <me@mail.com>".into())
+            .validity(not_after, not_before)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_gen_subkey_creation_time() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let created_at = chrono::Utc::now().trunc_subsecs(0);
+        let params = |subkey_created_at, offset| {
+            SecretKeyParamsBuilder::default()
+                .key_type(KeyType::EdDSA)
+                .can_create_certificates(true)
+                .primary_user_id("Me <me@mail.com>".into())
+                .created_at(created_at)
+                .offset_subkey_creation(offset)
+                .subkey(
+                    SubkeyParamsBuilder::default()
+                        .key_type(KeyType::ECDH)
+                        .can_encrypt(true)
+                        .created_at(subkey_created_at)
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+        };
+
+        // subkeys must not predate the primary key
+        assert!(params(created_at - chrono::Duration::seconds(1), false).is_err());
+
+        let key = params(created_at, false)
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+        assert_eq!(key.secret_subkeys[0].key.created_at(), &created_at);
+
+        // equal creation times get offset by one second
+        let key = params(created_at, true)
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+        assert_eq!(
+            key.secret_subkeys[0].key.created_at(),
+            &(created_at + chrono::Duration::seconds(1))
+        );
+        key.verify().expect("invalid key");
+
+        // later subkeys are left alone
+        let later = created_at + chrono::Duration::seconds(10);
+        let key = params(later, true)
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+        assert_eq!(key.secret_subkeys[0].key.created_at(), &later);
+    }
+
+    #[test]
+    fn test_key_gen_photo() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let photo = UserAttribute::new_image(vec![0xFF, 0xD8, 0xFF, 0xE0]).unwrap();
+
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .user_attributes(vec![photo.clone()])
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+        key.verify().expect("invalid key");
+
+        let attrs = &key.details.user_attributes;
+        assert_eq!(attrs.len(), 1);
+        assert_eq!(attrs[0].attr, photo);
+        assert_eq!(attrs[0].signatures.len(), 1);
+    }
+
+    #[test]
+    fn test_public_key_builder() {
+        use crate::composed::{KeyDetailsBuilder, PublicKeyBuilder};
+        use crate::types::CertificationOptions;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key_with_subkeys(&mut rng, "Me <me@mail.com>", 0);
+        let ca = gen_key_with_subkeys(&mut rng, "CA <ca@mail.com>", 0);
+
+        let mut keyflags = KeyFlags::default();
+        keyflags.set_sign(true);
+        let id = UserId::from_str(Default::default(), "Other Me <other@mail.com>");
+        let unsigned = PublicKeyBuilder::default()
+            .primary_key(key.primary_key.public_key())
+            .details(
+                KeyDetailsBuilder::default()
+                    .primary_user_id(id.clone())
+                    .keyflags(keyflags)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+
+        // re-signed by the key itself
+        let signed = unsigned
+            .clone()
+            .sign(&key.primary_key, || "".into())
+            .unwrap();
+        signed.verify().expect("invalid key");
+        assert_eq!(signed.details.users[0].id, id);
+
+        // certified by a different key
+        assert!(unsigned.sign(&ca.primary_key, || "".into()).is_err());
+        let mut certified = signed;
+        certified
+            .certify_user_id(
+                &ca.primary_key,
+                &id,
+                || "".into(),
+                &CertificationOptions::default(),
+            )
+            .unwrap();
+        certified.details.users[0]
+            .verify_certifications(&certified.primary_key, &ca.primary_key)
+            .expect("invalid certification");
+        certified.verify().expect("invalid key");
+    }
+
+    #[test]
+    fn test_key_gen_deterministic() {
+        let created_at = chrono::Utc::now().trunc_subsecs(0);
+        let gen = |seed| {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            SecretKeyParamsBuilder::default()
+                .key_type(KeyType::EdDSA)
+                .can_create_certificates(true)
+                .primary_user_id("Me <me@mail.com>".into())
+                .passphrase(Some("hello".into()))
+                .created_at(created_at)
+                .subkey(
+                    SubkeyParamsBuilder::default()
+                        .key_type(KeyType::ECDH)
+                        .can_encrypt(true)
+                        .passphrase(Some("hello".into()))
+                        .created_at(created_at)
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()
+                .generate_with_rng(&mut rng)
+                .expect("failed to generate secret key")
+        };
+
+        // the key material, salts and IVs all come from the rng
+        assert_eq!(gen(1), gen(1));
+        assert_ne!(gen(1), gen(2));
+    }
 }
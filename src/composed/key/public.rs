@@ -1,27 +1,46 @@
 use std::io;
 
 use chrono::{self, SubsecRound};
-use rand::{CryptoRng, Rng};
-use smallvec::SmallVec;
+use rand::{thread_rng, CryptoRng, Rng};
 
+use crate::composed::key::shared::key_expiration_subpacket;
 use crate::composed::{KeyDetails, SignedPublicKey, SignedPublicSubKey};
 use crate::crypto::{HashAlgorithm, PublicKeyAlgorithm};
 use crate::errors::Result;
-use crate::packet::{self, KeyFlags, SignatureConfigBuilder, SignatureType, Subpacket};
-use crate::types::{KeyId, KeyTrait, Mpi, PublicKeyTrait, SecretKeyTrait};
+use crate::packet::{
+    self, KeyFlags, SignatureConfigBuilder, SignatureType, SignatureVersion, Subpacket,
+};
+use crate::types::{KeyId, KeyTrait, KeyVersion, Mpi, PublicKeyTrait, SecretKeyTrait};
 
 /// User facing interface to work with a public key.
-#[derive(Debug, PartialEq, Eq, Clone)]
+///
+/// Can be assembled from existing packets using the [`PublicKeyBuilder`], for example to
+/// sign a key again with new details.
+#[derive(Debug, PartialEq, Eq, Clone, Builder)]
 pub struct PublicKey {
     primary_key: packet::PublicKey,
     details: KeyDetails,
+    #[builder(default)]
     public_subkeys: Vec<PublicSubkey>,
 }
 
+impl PublicKeyBuilder {
+    pub fn subkey(&mut self, value: PublicSubkey) -> &mut Self {
+        if let Some(ref mut subkeys) = self.public_subkeys {
+            subkeys.push(value);
+        } else {
+            self.public_subkeys = Some(vec![value]);
+        }
+        self
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct PublicSubkey {
     key: packet::PublicSubkey,
     keyflags: KeyFlags,
+    key_expiration_time: Option<chrono::Duration>,
+    hashed_subpackets: Vec<Subpacket>,
 }
 
 impl PublicKey {
@@ -37,10 +56,21 @@ impl PublicKey {
         }
     }
 
+    /// Signs the key details and binds all subkeys, `sec_key` has to be the secret key of
+    /// the primary key.
+    ///
+    /// To certify the user ids of the key with a different key, sign it first, then use
+    /// [`SignedPublicKey::certify_user_id`].
     pub fn sign<F>(self, sec_key: &impl SecretKeyTrait, key_pw: F) -> Result<SignedPublicKey>
     where
         F: (FnOnce() -> String) + Clone,
     {
+        ensure_eq!(
+            sec_key.fingerprint(),
+            self.primary_key.fingerprint(),
+            "self-signatures have to be made by the primary key"
+        );
+
         let primary_key = self.primary_key;
         let details = self.details.sign(sec_key, key_pw.clone())?;
         let public_subkeys = self
@@ -69,6 +99,10 @@ impl KeyTrait for PublicKey {
     fn algorithm(&self) -> PublicKeyAlgorithm {
         self.primary_key.algorithm()
     }
+
+    fn version(&self) -> KeyVersion {
+        self.primary_key.version()
+    }
 }
 
 impl PublicKeyTrait for PublicKey {
@@ -86,30 +120,85 @@ impl PublicKeyTrait for PublicKey {
 }
 
 impl PublicSubkey {
-    pub fn new(key: packet::PublicSubkey, keyflags: KeyFlags) -> Self {
-        PublicSubkey { key, keyflags }
+    pub fn new(
+        key: packet::PublicSubkey,
+        keyflags: KeyFlags,
+        key_expiration_time: Option<chrono::Duration>,
+    ) -> Self {
+        PublicSubkey {
+            key,
+            keyflags,
+            key_expiration_time,
+            hashed_subpackets: Vec::new(),
+        }
+    }
+
+    /// Adds subpackets to the hashed area of the binding signature, after the ones
+    /// generated from the key flags and expiration time.
+    pub fn with_hashed_subpackets(mut self, subpackets: Vec<Subpacket>) -> Self {
+        self.hashed_subpackets.extend(subpackets);
+        self
+    }
+
+    pub fn created_at(&self) -> &chrono::DateTime<chrono::Utc> {
+        self.key.created_at()
     }
 
     pub fn sign<F>(self, sec_key: &impl SecretKeyTrait, key_pw: F) -> Result<SignedPublicSubKey>
     where
         F: (FnOnce() -> String) + Clone,
+    {
+        self.sign_at(sec_key, key_pw, chrono::Utc::now().trunc_subsecs(0))
+    }
+
+    /// Same as [`sign`], but with the given signature creation time.
+    ///
+    /// [`sign`]: PublicSubkey::sign
+    pub fn sign_at<F>(
+        self,
+        sec_key: &impl SecretKeyTrait,
+        key_pw: F,
+        created: chrono::DateTime<chrono::Utc>,
+    ) -> Result<SignedPublicSubKey>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        self.sign_at_with_rng(&mut thread_rng(), sec_key, key_pw, created)
+    }
+
+    /// Same as [`sign_at`], but generates the salt of v6 signatures from `rng`.
+    ///
+    /// [`sign_at`]: PublicSubkey::sign_at
+    pub fn sign_at_with_rng<R, F>(
+        self,
+        rng: &mut R,
+        sec_key: &impl SecretKeyTrait,
+        key_pw: F,
+        created: chrono::DateTime<chrono::Utc>,
+    ) -> Result<SignedPublicSubKey>
+    where
+        R: CryptoRng + Rng,
+        F: (FnOnce() -> String) + Clone,
     {
         let key = self.key;
-        let hashed_subpackets = vec![
-            Subpacket::SignatureCreationTime(chrono::Utc::now().trunc_subsecs(0)),
+        let mut hashed_subpackets = vec![
+            Subpacket::SignatureCreationTime(created),
             Subpacket::KeyFlags(self.keyflags.into()),
-            Subpacket::IssuerFingerprint(
-                Default::default(),
-                SmallVec::from_slice(&sec_key.fingerprint()),
-            ),
         ];
+        if let Some(expiration) = self.key_expiration_time {
+            hashed_subpackets.push(key_expiration_subpacket(expiration));
+        }
+        hashed_subpackets.extend(self.hashed_subpackets);
 
         let config = SignatureConfigBuilder::default()
             .typ(SignatureType::SubkeyBinding)
+            .version(SignatureVersion::for_key(sec_key))
             .pub_alg(sec_key.algorithm())
             .hashed_subpackets(hashed_subpackets)
-            .unhashed_subpackets(vec![Subpacket::Issuer(sec_key.key_id())])
-            .build()?;
+            .unhashed_subpackets(vec![])
+            .build()?
+            .with_issuer(sec_key)
+            .salted(rng)?;
 
         let signatures = vec![config.sign_key_binding(sec_key, key_pw, &key)?];
 
@@ -129,6 +218,10 @@ impl KeyTrait for PublicSubkey {
     fn algorithm(&self) -> PublicKeyAlgorithm {
         self.key.algorithm()
     }
+
+    fn version(&self) -> KeyVersion {
+        self.key.version()
+    }
 }
 
 impl PublicKeyTrait for PublicSubkey {
@@ -1,11 +1,14 @@
 use chrono::{self, SubsecRound};
-use smallvec::SmallVec;
+use rand::{thread_rng, CryptoRng, Rng};
 
+use crate::composed::key::shared::key_expiration_subpacket;
 use crate::composed::{KeyDetails, PublicSubkey, SignedSecretKey, SignedSecretSubKey};
 use crate::crypto::PublicKeyAlgorithm;
 use crate::errors::Result;
-use crate::packet::{self, KeyFlags, SignatureConfigBuilder, SignatureType, Subpacket};
-use crate::types::{KeyId, KeyTrait, SecretKeyTrait};
+use crate::packet::{
+    self, KeyFlags, SignatureConfigBuilder, SignatureType, SignatureVersion, Subpacket,
+};
+use crate::types::{KeyId, KeyTrait, KeyVersion, SecretKeyTrait};
 
 /// User facing interface to work with a secret key.
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -20,6 +23,8 @@ pub struct SecretKey {
 pub struct SecretSubkey {
     key: packet::SecretSubkey,
     keyflags: KeyFlags,
+    key_expiration_time: Option<chrono::Duration>,
+    hashed_subpackets: Vec<Subpacket>,
 }
 
 impl SecretKey {
@@ -37,21 +42,88 @@ impl SecretKey {
         }
     }
 
+    /// Returns the creation time of the newest of the primary key and its subkeys.
+    fn newest_key_created_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.public_subkeys
+            .iter()
+            .map(PublicSubkey::created_at)
+            .chain(self.secret_subkeys.iter().map(SecretSubkey::created_at))
+            .fold(*self.primary_key.created_at(), |newest, created_at| {
+                newest.max(*created_at)
+            })
+    }
+
+    /// Signs the key details and binds all subkeys.
+    ///
+    /// All self-signatures share the same creation time, the current time, or the creation
+    /// time of the newest key, if that lies in the future.
     pub fn sign<F>(self, key_pw: F) -> Result<SignedSecretKey>
     where
         F: (FnOnce() -> String) + Clone,
     {
+        self.sign_with_rng(&mut thread_rng(), key_pw)
+    }
+
+    /// Same as [`sign`], but generates the salts of v6 signatures from `rng`.
+    ///
+    /// [`sign`]: SecretKey::sign
+    pub fn sign_with_rng<R, F>(self, rng: &mut R, key_pw: F) -> Result<SignedSecretKey>
+    where
+        R: CryptoRng + Rng,
+        F: (FnOnce() -> String) + Clone,
+    {
+        let created = chrono::Utc::now()
+            .trunc_subsecs(0)
+            .max(self.newest_key_created_at());
+        self.sign_at_with_rng(rng, key_pw, created)
+    }
+
+    /// Same as [`sign`], but with the given signature creation time, which must not
+    /// be before the creation of any of the keys.
+    ///
+    /// [`sign`]: SecretKey::sign
+    pub fn sign_at<F>(
+        self,
+        key_pw: F,
+        created: chrono::DateTime<chrono::Utc>,
+    ) -> Result<SignedSecretKey>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        self.sign_at_with_rng(&mut thread_rng(), key_pw, created)
+    }
+
+    /// Same as [`sign_at`], but generates the salts of v6 signatures from `rng`.
+    ///
+    /// [`sign_at`]: SecretKey::sign_at
+    pub fn sign_at_with_rng<R, F>(
+        self,
+        rng: &mut R,
+        key_pw: F,
+        created: chrono::DateTime<chrono::Utc>,
+    ) -> Result<SignedSecretKey>
+    where
+        R: CryptoRng + Rng,
+        F: (FnOnce() -> String) + Clone,
+    {
+        ensure!(
+            created >= self.newest_key_created_at(),
+            "Signatures must not be created before the keys they sign"
+        );
+
         let primary_key = self.primary_key;
-        let details = self.details.sign(&primary_key, key_pw.clone())?;
+        let details = self
+            .details
+            .sign_at_with_rng(rng, &primary_key, key_pw.clone(), created)?;
         let public_subkeys = self
             .public_subkeys
             .into_iter()
-            .map(|k| k.sign(&primary_key, key_pw.clone()))
+            .map(|k| k.sign_at_with_rng(rng, &primary_key, key_pw.clone(), created))
             .collect::<Result<Vec<_>>>()?;
         let secret_subkeys = self
             .secret_subkeys
             .into_iter()
-            .map(|k| k.sign(&primary_key, key_pw.clone()))
+            .map(|k| k.sign_at_with_rng(rng, &primary_key, key_pw.clone(), created))
             .collect::<Result<Vec<_>>>()?;
 
         Ok(SignedSecretKey {
@@ -75,33 +147,126 @@ impl KeyTrait for SecretKey {
     fn algorithm(&self) -> PublicKeyAlgorithm {
         self.primary_key.algorithm()
     }
+
+    fn version(&self) -> KeyVersion {
+        self.primary_key.version()
+    }
 }
 
 impl SecretSubkey {
-    pub fn new(key: packet::SecretSubkey, keyflags: KeyFlags) -> Self {
-        SecretSubkey { key, keyflags }
+    pub fn new(
+        key: packet::SecretSubkey,
+        keyflags: KeyFlags,
+        key_expiration_time: Option<chrono::Duration>,
+    ) -> Self {
+        SecretSubkey {
+            key,
+            keyflags,
+            key_expiration_time,
+            hashed_subpackets: Vec::new(),
+        }
     }
 
+    /// Adds subpackets to the hashed area of the binding signature, after the ones
+    /// generated from the key flags and expiration time.
+    pub fn with_hashed_subpackets(mut self, subpackets: Vec<Subpacket>) -> Self {
+        self.hashed_subpackets.extend(subpackets);
+        self
+    }
+
+    pub fn created_at(&self) -> &chrono::DateTime<chrono::Utc> {
+        self.key.created_at()
+    }
+
+    /// Binds this subkey to `sec_key`.
+    ///
+    /// Signing capable subkeys additionally get an embedded primary key binding signature,
+    /// made by the subkey itself. `key_pw` is used to unlock both keys.
     pub fn sign<F>(self, sec_key: &impl SecretKeyTrait, key_pw: F) -> Result<SignedSecretSubKey>
     where
         F: (FnOnce() -> String) + Clone,
+    {
+        self.sign_with_rng(&mut thread_rng(), sec_key, key_pw)
+    }
+
+    /// Same as [`sign`], but generates the salts of v6 signatures from `rng`.
+    ///
+    /// [`sign`]: SecretSubkey::sign
+    pub fn sign_with_rng<R, F>(
+        self,
+        rng: &mut R,
+        sec_key: &impl SecretKeyTrait,
+        key_pw: F,
+    ) -> Result<SignedSecretSubKey>
+    where
+        R: CryptoRng + Rng,
+        F: (FnOnce() -> String) + Clone,
+    {
+        self.sign_at_with_rng(rng, sec_key, key_pw, chrono::Utc::now().trunc_subsecs(0))
+    }
+
+    /// Same as [`sign`], but with the given signature creation time.
+    ///
+    /// [`sign`]: SecretSubkey::sign
+    pub fn sign_at<F>(
+        self,
+        sec_key: &impl SecretKeyTrait,
+        key_pw: F,
+        created: chrono::DateTime<chrono::Utc>,
+    ) -> Result<SignedSecretSubKey>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        self.sign_at_with_rng(&mut thread_rng(), sec_key, key_pw, created)
+    }
+
+    /// Same as [`sign_at`], but generates the salts of v6 signatures from `rng`.
+    ///
+    /// [`sign_at`]: SecretSubkey::sign_at
+    pub fn sign_at_with_rng<R, F>(
+        self,
+        rng: &mut R,
+        sec_key: &impl SecretKeyTrait,
+        key_pw: F,
+        created: chrono::DateTime<chrono::Utc>,
+    ) -> Result<SignedSecretSubKey>
+    where
+        R: CryptoRng + Rng,
+        F: (FnOnce() -> String) + Clone,
     {
         let key = self.key;
-        let hashed_subpackets = vec![
-            Subpacket::SignatureCreationTime(chrono::Utc::now().trunc_subsecs(0)),
+        let can_sign = self.keyflags.sign();
+        let mut hashed_subpackets = vec![
+            Subpacket::SignatureCreationTime(created),
             Subpacket::KeyFlags(self.keyflags.into()),
-            Subpacket::IssuerFingerprint(
-                Default::default(),
-                SmallVec::from_slice(&sec_key.fingerprint()),
-            ),
         ];
+        if let Some(expiration) = self.key_expiration_time {
+            hashed_subpackets.push(key_expiration_subpacket(expiration));
+        }
+        hashed_subpackets.extend(self.hashed_subpackets);
+        if can_sign {
+            let backsig = SignatureConfigBuilder::default()
+                .typ(SignatureType::KeyBinding)
+                .version(SignatureVersion::for_key(&key))
+                .pub_alg(key.algorithm())
+                .hashed_subpackets(vec![Subpacket::SignatureCreationTime(created)])
+                .unhashed_subpackets(vec![])
+                .build()?
+                .with_issuer(&key)
+                .salted(rng)?
+                .sign_primary_key_binding(&key, key_pw.clone(), sec_key)?;
+            hashed_subpackets.push(Subpacket::EmbeddedSignature(Box::new(backsig)));
+        }
 
         let config = SignatureConfigBuilder::default()
             .typ(SignatureType::SubkeyBinding)
+            .version(SignatureVersion::for_key(sec_key))
             .pub_alg(sec_key.algorithm())
             .hashed_subpackets(hashed_subpackets)
-            .unhashed_subpackets(vec![Subpacket::Issuer(sec_key.key_id())])
-            .build()?;
+            .unhashed_subpackets(vec![])
+            .build()?
+            .with_issuer(sec_key)
+            .salted(rng)?;
         let signatures = vec![config.sign_key_binding(sec_key, key_pw, &key)?];
 
         Ok(SignedSecretSubKey { key, signatures })
@@ -120,4 +285,8 @@ impl KeyTrait for SecretSubkey {
     fn algorithm(&self) -> PublicKeyAlgorithm {
         self.key.algorithm()
     }
+
+    fn version(&self) -> KeyVersion {
+        self.key.version()
+    }
 }
@@ -1,4 +1,5 @@
-use chrono::{self, SubsecRound};
+use chrono::{self, SubsecRound, TimeZone};
+use rand::{thread_rng, CryptoRng, Rng};
 use smallvec::SmallVec;
 
 use crate::composed::SignedKeyDetails;
@@ -6,26 +7,58 @@ use crate::crypto::hash::HashAlgorithm;
 use crate::crypto::sym::SymmetricKeyAlgorithm;
 use crate::errors::Result;
 use crate::packet::{
-    KeyFlags, PacketTrait, SignatureConfigBuilder, SignatureType, Subpacket, UserAttribute, UserId,
+    KeyFlags, PacketTrait, SignatureConfigBuilder, SignatureType, SignatureVersion, Subpacket,
+    UserAttribute, UserId,
 };
 use crate::types::{CompressionAlgorithm, RevocationKey, SecretKeyTrait};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Builder)]
 pub struct KeyDetails {
-    primary_user_id: UserId,
+    /// Without a primary user id, the preferences are stored in a direct key signature.
+    #[builder(default)]
+    primary_user_id: Option<UserId>,
+    #[builder(default)]
     user_ids: Vec<UserId>,
+    #[builder(default)]
     user_attributes: Vec<UserAttribute>,
+    #[builder(default)]
     keyflags: KeyFlags,
+    #[builder(default)]
     preferred_symmetric_algorithms: SmallVec<[SymmetricKeyAlgorithm; 8]>,
+    #[builder(default)]
     preferred_hash_algorithms: SmallVec<[HashAlgorithm; 8]>,
+    #[builder(default)]
     preferred_compression_algorithms: SmallVec<[CompressionAlgorithm; 8]>,
+    #[builder(default)]
     revocation_key: Option<RevocationKey>,
+    #[builder(default)]
+    key_expiration_time: Option<chrono::Duration>,
+}
+
+impl KeyDetailsBuilder {
+    pub fn user_id(&mut self, value: UserId) -> &mut Self {
+        if let Some(ref mut user_ids) = self.user_ids {
+            user_ids.push(value);
+        } else {
+            self.user_ids = Some(vec![value]);
+        }
+        self
+    }
+
+    pub fn user_attribute(&mut self, value: UserAttribute) -> &mut Self {
+        if let Some(ref mut user_attributes) = self.user_attributes {
+            user_attributes.push(value);
+        } else {
+            self.user_attributes = Some(vec![value]);
+        }
+        self
+    }
 }
 
 impl KeyDetails {
     #[allow(clippy::too_many_arguments)] // FIXME
     pub fn new(
-        primary_user_id: UserId,
+        primary_user_id: Option<UserId>,
         user_ids: Vec<UserId>,
         user_attributes: Vec<UserAttribute>,
         keyflags: KeyFlags,
@@ -33,6 +66,7 @@ impl KeyDetails {
         preferred_hash_algorithms: SmallVec<[HashAlgorithm; 8]>,
         preferred_compression_algorithms: SmallVec<[CompressionAlgorithm; 8]>,
         revocation_key: Option<RevocationKey>,
+        key_expiration_time: Option<chrono::Duration>,
     ) -> Self {
         KeyDetails {
             primary_user_id,
@@ -43,50 +77,97 @@ impl KeyDetails {
             preferred_hash_algorithms,
             preferred_compression_algorithms,
             revocation_key,
+            key_expiration_time,
         }
     }
 
     pub fn sign<F>(self, key: &impl SecretKeyTrait, key_pw: F) -> Result<SignedKeyDetails>
     where
         F: (FnOnce() -> String) + Clone,
+    {
+        self.sign_at(key, key_pw, chrono::Utc::now().trunc_subsecs(0))
+    }
+
+    /// Same as [`sign`], but with the given signature creation time.
+    ///
+    /// [`sign`]: KeyDetails::sign
+    pub fn sign_at<F>(
+        self,
+        key: &impl SecretKeyTrait,
+        key_pw: F,
+        created: chrono::DateTime<chrono::Utc>,
+    ) -> Result<SignedKeyDetails>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        self.sign_at_with_rng(&mut thread_rng(), key, key_pw, created)
+    }
+
+    /// Same as [`sign_at`], but generates the salts of v6 signatures from `rng`.
+    ///
+    /// [`sign_at`]: KeyDetails::sign_at
+    pub fn sign_at_with_rng<R, F>(
+        self,
+        rng: &mut R,
+        key: &impl SecretKeyTrait,
+        key_pw: F,
+        created: chrono::DateTime<chrono::Utc>,
+    ) -> Result<SignedKeyDetails>
+    where
+        R: CryptoRng + Rng,
+        F: (FnOnce() -> String) + Clone,
     {
         let keyflags: SmallVec<[u8; 1]> = self.keyflags.into();
         let preferred_symmetric_algorithms = self.preferred_symmetric_algorithms;
         let preferred_hash_algorithms = self.preferred_hash_algorithms;
         let preferred_compression_algorithms = self.preferred_compression_algorithms;
         let revocation_key = self.revocation_key;
+        let key_expiration_time = self.key_expiration_time.map(key_expiration_subpacket);
 
         let mut users = vec![];
+        let mut direct_signatures = vec![];
 
-        // primary user id
+        // primary user id, or a direct key signature for keys without user ids
         {
-            let id = self.primary_user_id;
             let mut hashed_subpackets = vec![
-                Subpacket::IsPrimary(true),
-                Subpacket::SignatureCreationTime(chrono::Utc::now().trunc_subsecs(0)),
+                Subpacket::SignatureCreationTime(created),
                 Subpacket::KeyFlags(keyflags.clone()),
                 Subpacket::PreferredSymmetricAlgorithms(preferred_symmetric_algorithms.clone()),
                 Subpacket::PreferredHashAlgorithms(preferred_hash_algorithms.clone()),
                 Subpacket::PreferredCompressionAlgorithms(preferred_compression_algorithms.clone()),
-                Subpacket::IssuerFingerprint(
-                    Default::default(),
-                    SmallVec::from_slice(&key.fingerprint()),
-                ),
             ];
             if let Some(rkey) = revocation_key {
                 hashed_subpackets.push(Subpacket::RevocationKey(rkey));
             }
+            if let Some(ref expiration) = key_expiration_time {
+                hashed_subpackets.push(expiration.clone());
+            }
+
+            let typ = match self.primary_user_id {
+                Some(_) => {
+                    hashed_subpackets.insert(0, Subpacket::IsPrimary(true));
+                    SignatureType::CertGeneric
+                }
+                None => SignatureType::Key,
+            };
 
             let config = SignatureConfigBuilder::default()
-                .typ(SignatureType::CertGeneric)
+                .typ(typ)
+                .version(SignatureVersion::for_key(key))
                 .pub_alg(key.algorithm())
                 .hashed_subpackets(hashed_subpackets)
-                .unhashed_subpackets(vec![Subpacket::Issuer(key.key_id())])
-                .build()?;
+                .unhashed_subpackets(vec![])
+                .build()?
+                .with_issuer(key)
+                .salted(rng)?;
 
-            let sig = config.sign_certificate(key, key_pw.clone(), id.tag(), &id)?;
-
-            users.push(id.into_signed(sig));
+            match self.primary_user_id {
+                Some(id) => {
+                    let sig = config.sign_certificate(key, key_pw.clone(), id.tag(), &id)?;
+                    users.push(id.into_signed(sig));
+                }
+                None => direct_signatures.push(config.sign_key(key, key_pw.clone(), key)?),
+            }
         }
 
         // other user ids
@@ -95,26 +176,30 @@ impl KeyDetails {
             self.user_ids
                 .into_iter()
                 .map(|id| {
+                    let mut hashed_subpackets = vec![
+                        Subpacket::SignatureCreationTime(created),
+                        Subpacket::KeyFlags(keyflags.clone()),
+                        Subpacket::PreferredSymmetricAlgorithms(
+                            preferred_symmetric_algorithms.clone(),
+                        ),
+                        Subpacket::PreferredHashAlgorithms(preferred_hash_algorithms.clone()),
+                        Subpacket::PreferredCompressionAlgorithms(
+                            preferred_compression_algorithms.clone(),
+                        ),
+                    ];
+                    if let Some(ref expiration) = key_expiration_time {
+                        hashed_subpackets.push(expiration.clone());
+                    }
+
                     let config = SignatureConfigBuilder::default()
                         .typ(SignatureType::CertGeneric)
+                        .version(SignatureVersion::for_key(key))
                         .pub_alg(key.algorithm())
-                        .hashed_subpackets(vec![
-                            Subpacket::SignatureCreationTime(chrono::Utc::now().trunc_subsecs(0)),
-                            Subpacket::KeyFlags(keyflags.clone()),
-                            Subpacket::PreferredSymmetricAlgorithms(
-                                preferred_symmetric_algorithms.clone(),
-                            ),
-                            Subpacket::PreferredHashAlgorithms(preferred_hash_algorithms.clone()),
-                            Subpacket::PreferredCompressionAlgorithms(
-                                preferred_compression_algorithms.clone(),
-                            ),
-                            Subpacket::IssuerFingerprint(
-                                Default::default(),
-                                SmallVec::from_slice(&key.fingerprint()),
-                            ),
-                        ])
-                        .unhashed_subpackets(vec![Subpacket::Issuer(key.key_id())])
-                        .build()?;
+                        .hashed_subpackets(hashed_subpackets)
+                        .unhashed_subpackets(vec![])
+                        .build()?
+                        .with_issuer(key)
+                        .salted(rng)?;
 
                     let sig = config.sign_certificate(key, key_pw.clone(), id.tag(), &id)?;
 
@@ -126,14 +211,19 @@ impl KeyDetails {
         let user_attributes = self
             .user_attributes
             .into_iter()
-            .map(|u| u.sign(key, key_pw.clone()))
+            .map(|u| u.sign_at_with_rng(rng, key, key_pw.clone(), created))
             .collect::<Result<Vec<_>>>()?;
 
         Ok(SignedKeyDetails {
             revocation_signatures: Default::default(),
-            direct_signatures: Default::default(),
+            direct_signatures,
             users,
             user_attributes,
         })
     }
 }
+
+/// Creates a `KeyExpirationTime` subpacket, for the given offset from the key creation time.
+pub(crate) fn key_expiration_subpacket(expiration: chrono::Duration) -> Subpacket {
+    Subpacket::KeyExpirationTime(chrono::Utc.timestamp(expiration.num_seconds(), 0))
+}
@@ -2,18 +2,25 @@ use std::boxed::Box;
 use std::io::Cursor;
 
 use num_traits::FromPrimitive;
+use zeroize::Zeroize;
 
 use crate::composed::message::types::{Edata, Message};
+use crate::composed::message::verification::has_unintended_recipient;
 use crate::composed::shared::Deserializable;
-use crate::crypto::{checksum, ecdh, rsa, SymmetricKeyAlgorithm};
-use crate::errors::Result;
-use crate::packet::SymKeyEncryptedSessionKey;
-use crate::types::{KeyTrait, Mpi, SecretKeyRepr, SecretKeyTrait, Tag};
+use crate::crypto::{checksum, ecdh, elgamal, rsa, x25519, x448, SymmetricKeyAlgorithm};
+use crate::errors::{Error, Result};
+use crate::packet::{ProtectedDataConfig, SymKeyEncryptedSessionKey};
+use crate::types::{Argon2Limits, KeyTrait, Mpi, SecretKeyRepr, SecretKeyTrait};
 
+/// Decrypts the session key encrypted to `locked_key`.
+///
+/// `alg` is the algorithm of the session key for version 6 packets, which do not include
+/// it, see [`session_key_algorithm`].
 pub fn decrypt_session_key<F>(
     locked_key: &(impl SecretKeyTrait + KeyTrait),
     key_pw: F,
     mpis: &[Mpi],
+    alg: Option<SymmetricKeyAlgorithm>,
 ) -> Result<(Vec<u8>, SymmetricKeyAlgorithm)>
 where
     F: FnOnce() -> String,
@@ -21,62 +28,154 @@ where
     debug!("decrypting session key");
 
     let mut key: Vec<u8> = Vec::new();
-    let mut alg: Option<SymmetricKeyAlgorithm> = None;
+    let mut key_alg: Option<SymmetricKeyAlgorithm> = None;
     locked_key.unlock(key_pw, |priv_key| {
+        match *priv_key {
+            SecretKeyRepr::X25519(_) | SecretKeyRepr::X448(_) => {
+                // the session key is wrapped without padding and checksum, version 3 packets
+                // prefix it with the algorithm in the clear
+                ensure_eq!(mpis.len(), 2, "invalid encrypted session key");
+                let ephemeral = mpis[0].as_bytes();
+                let encrypted_key = mpis[1].as_bytes();
+                let (algorithm, encrypted_key) = match alg {
+                    Some(alg) => (alg, encrypted_key),
+                    None => {
+                        ensure!(!encrypted_key.is_empty(), "invalid encrypted session key");
+                        let alg = SymmetricKeyAlgorithm::from_u8(encrypted_key[0])
+                            .ok_or_else(|| format_err!("invalid symmetric key algorithm"))?;
+                        (alg, &encrypted_key[1..])
+                    }
+                };
+                let k = match *priv_key {
+                    SecretKeyRepr::X25519(ref priv_key) => {
+                        x25519::decrypt(priv_key, ephemeral, encrypted_key)?
+                    }
+                    SecretKeyRepr::X448(ref priv_key) => {
+                        x448::decrypt(priv_key, ephemeral, encrypted_key)?
+                    }
+                    _ => unreachable!("checked above"),
+                };
+                check_session_key(&k, algorithm)?;
+                key = k;
+                key_alg = Some(algorithm);
+
+                return Ok(());
+            }
+            _ => {}
+        }
+
         let decrypted_key = match *priv_key {
             SecretKeyRepr::RSA(ref priv_key) => {
                 rsa::decrypt(priv_key, mpis, &locked_key.fingerprint())?
             }
             SecretKeyRepr::DSA(_) => bail!("DSA is only used for signing"),
-            SecretKeyRepr::ECDSA => bail!("ECDSA is only used for signing"),
+            SecretKeyRepr::ECDSA(_) | SecretKeyRepr::NistECDSA(_) => {
+                bail!("ECDSA is only used for signing")
+            }
             SecretKeyRepr::ECDH(ref priv_key) => {
                 ecdh::decrypt(priv_key, mpis, &locked_key.fingerprint())?
             }
+            SecretKeyRepr::NistECDH(ref priv_key) => {
+                ecdh::decrypt_nist(priv_key, mpis, &locked_key.fingerprint())?
+            }
             SecretKeyRepr::EdDSA(_) => unimplemented_err!("EdDSA"),
+            SecretKeyRepr::Ed25519(_) => bail!("Ed25519 is only used for signing"),
+            SecretKeyRepr::Ed448(_) => bail!("Ed448 is only used for signing"),
+            SecretKeyRepr::X25519(_) | SecretKeyRepr::X448(_) => unreachable!("handled above"),
+            SecretKeyRepr::Elgamal(ref priv_key) => elgamal::decrypt(priv_key, mpis)?,
+        };
+        ensure!(decrypted_key.len() > 3, "invalid session key");
+        // version 3 packets prefix the key with its algorithm
+        let (algorithm, start) = match alg {
+            Some(alg) => (alg, 0),
+            None => {
+                let alg = SymmetricKeyAlgorithm::from_u8(decrypted_key[0])
+                    .ok_or_else(|| format_err!("invalid symmetric key algorithm"))?;
+                (alg, 1)
+            }
         };
-        let algorithm = SymmetricKeyAlgorithm::from_u8(decrypted_key[0])
-            .ok_or_else(|| format_err!("invalid symmetric key algorithm"))?;
-        alg = Some(algorithm);
-        debug!("alg: {:?}", alg);
+        key_alg = Some(algorithm);
+        debug!("alg: {:?}", algorithm);
 
         let (k, checksum) = match *priv_key {
-            SecretKeyRepr::ECDH(_) => {
+            SecretKeyRepr::ECDH(_) | SecretKeyRepr::NistECDH(_) => {
                 let dec_len = decrypted_key.len();
                 (
-                    &decrypted_key[1..dec_len - 2],
+                    &decrypted_key[start..dec_len - 2],
                     &decrypted_key[dec_len - 2..],
                 )
             }
             _ => {
                 let key_size = algorithm.key_size();
+                ensure_eq!(
+                    decrypted_key.len(),
+                    start + key_size + 2,
+                    "session key length does not match {:?}",
+                    algorithm
+                );
                 (
-                    &decrypted_key[1..=key_size],
-                    &decrypted_key[key_size + 1..key_size + 3],
+                    &decrypted_key[start..start + key_size],
+                    &decrypted_key[start + key_size..],
                 )
             }
         };
 
-        key = k.to_vec();
         checksum::simple(checksum, k)?;
+        check_session_key(k, algorithm)?;
+        key = k.to_vec();
 
         Ok(())
     })?;
+    locked_key.record_decryption(mpis);
 
-    Ok((key, alg.expect("failed to unlock")))
+    Ok((key, key_alg.expect("failed to unlock")))
 }
 
+/// Decrypts the session key protected by the password.
+///
+/// `alg` is the algorithm of the session key for version 6 packets, which do not include
+/// it, see [`session_key_algorithm`]. Argon2 string-to-keys are limited to `limits`.
 pub fn decrypt_session_key_with_password<F>(
     packet: &SymKeyEncryptedSessionKey,
     msg_pw: F,
+    alg: Option<SymmetricKeyAlgorithm>,
+    limits: &Argon2Limits,
 ) -> Result<(Vec<u8>, SymmetricKeyAlgorithm)>
 where
     F: FnOnce() -> String,
 {
     debug!("decrypting session key");
 
-    let key = packet
-        .s2k()
-        .derive_key(&msg_pw(), packet.sym_algorithm().key_size())?;
+    let key = packet.s2k().derive_key_with_limits(
+        msg_pw().as_bytes(),
+        packet.sym_algorithm().key_size(),
+        limits,
+    )?;
+
+    if let Some((aead, nonce)) = packet.aead() {
+        let alg = match alg {
+            Some(alg) => alg,
+            None => bail!("missing session key algorithm"),
+        };
+        let encrypted_key = match packet.encrypted_key() {
+            Some(ref encrypted_key) => encrypted_key,
+            None => bail!("missing encrypted session key"),
+        };
+        let (mut kek, info) = packet.key_encryption_key(&key)?;
+        let mut decrypted_key = encrypted_key.to_vec();
+        let res = aead.decrypt_in_place(
+            packet.sym_algorithm(),
+            &kek,
+            nonce,
+            &info,
+            &mut decrypted_key,
+        );
+        kek.zeroize();
+        res?;
+        check_session_key(&decrypted_key, alg)?;
+
+        return Ok((decrypted_key, alg));
+    }
 
     match packet.encrypted_key() {
         Some(ref encrypted_key) => {
@@ -87,23 +186,74 @@ where
                 .sym_algorithm()
                 .decrypt_with_iv_regular(&key, &iv, &mut decrypted_key)?;
 
+            ensure!(!decrypted_key.is_empty(), "empty session key");
             let alg = SymmetricKeyAlgorithm::from_u8(decrypted_key[0])
                 .ok_or_else(|| format_err!("invalid symmetric key algorithm"))?;
+            check_session_key(&decrypted_key[1..], alg)?;
 
             Ok((decrypted_key[1..].to_vec(), alg))
         }
-        None => Ok((key, packet.sym_algorithm())),
+        None => {
+            check_session_key(&key, packet.sym_algorithm())?;
+            Ok((key, packet.sym_algorithm()))
+        }
+    }
+}
+
+/// Returns the algorithm of the session key, for `version` 6 session key packets.
+///
+/// These leave out the algorithm, it is taken from the version 2 SEIPD packet instead.
+pub(crate) fn session_key_algorithm(
+    version: u8,
+    edata: &[Edata],
+) -> Result<Option<SymmetricKeyAlgorithm>> {
+    if version != 6 {
+        return Ok(None);
+    }
+
+    let alg = edata.iter().find_map(|edata| match edata {
+        Edata::SymEncryptedProtectedData(packet) => match packet.config() {
+            ProtectedDataConfig::V2 { sym_alg, .. } => Some(*sym_alg),
+            ProtectedDataConfig::V1 => None,
+        },
+        Edata::SymEncryptedData(_) => None,
+    });
+
+    match alg {
+        Some(alg) => Ok(Some(alg)),
+        None => bail!("version 6 session keys require version 2 encrypted data"),
     }
 }
 
+/// Checks that the session key is bound to an actual encryption algorithm, and has the
+/// matching size, to reject messages where the algorithm was tampered with.
+pub(crate) fn check_session_key(key: &[u8], alg: SymmetricKeyAlgorithm) -> Result<()> {
+    ensure!(
+        alg != SymmetricKeyAlgorithm::Plaintext,
+        "session key for unencrypted data"
+    );
+    ensure_eq!(
+        key.len(),
+        alg.key_size(),
+        "session key length does not match {:?}",
+        alg
+    );
+
+    Ok(())
+}
+
 pub struct MessageDecrypter<'a> {
     key: Vec<u8>,
     alg: SymmetricKeyAlgorithm,
     edata: &'a [Edata],
+    // decrypt legacy packets without modification detection
+    allow_unprotected: bool,
     // position in the edata slice
     pos: usize,
     // the current msgs that are already decrypted
     current_msgs: Option<Box<dyn Iterator<Item = Result<Message>>>>,
+    // primary key fingerprints of the keys the session key was decrypted with
+    recipients: Vec<Vec<u8>>,
 }
 
 impl<'a> MessageDecrypter<'a> {
@@ -112,10 +262,31 @@ impl<'a> MessageDecrypter<'a> {
             key: session_key,
             alg,
             edata,
+            allow_unprotected: false,
             pos: 0,
             current_msgs: None,
+            recipients: Vec::new(),
         }
     }
+
+    /// Sets the fingerprints of the keys the session key was decrypted with. A warning is
+    /// logged for decrypted messages with signatures, that list other intended recipients.
+    pub(crate) fn with_recipients(mut self, recipients: Vec<Vec<u8>>) -> Self {
+        self.recipients = recipients;
+        self
+    }
+
+    /// Allows decrypting legacy Symmetrically Encrypted Data packets, which have no
+    /// modification detection code.
+    ///
+    /// These are refused by default, as modifications of their contents go unnoticed,
+    /// which allows attacks like EFAIL. Only enable this for old messages from trusted
+    /// sources, and never pass the decrypted contents on, before they were verified
+    /// otherwise.
+    pub fn allow_unprotected(mut self, allow: bool) -> Self {
+        self.allow_unprotected = allow;
+        self
+    }
 }
 
 impl<'a> Iterator for MessageDecrypter<'a> {
@@ -131,26 +302,236 @@ impl<'a> Iterator for MessageDecrypter<'a> {
             let packet = &self.edata[self.pos];
             self.pos += 1;
 
-            let mut res = packet.data()[..].to_vec();
-            let protected = packet.tag() == Tag::SymEncryptedProtectedData;
-
-            debug!("decrypting protected = {:?}", protected);
-
-            let decrypted_packet: &[u8] = if protected {
-                err_opt!(self.alg.decrypt_protected(&self.key, &mut res))
-            } else {
-                err_opt!(self.alg.decrypt(&self.key, &mut res))
+            let decrypted_packet = match packet {
+                Edata::SymEncryptedProtectedData(packet) => {
+                    debug!("decrypting protected, version {}", packet.version());
+                    err_opt!(packet.decrypt(&self.key, self.alg))
+                }
+                Edata::SymEncryptedData(packet) => {
+                    if !self.allow_unprotected {
+                        // stop here, the following packets are not decrypted either
+                        self.pos = self.edata.len();
+                        return Some(Err(Error::UnprotectedData));
+                    }
+                    warn!("decrypting data without integrity protection");
+                    let mut res = packet.data().to_vec();
+                    err_opt!(self.alg.decrypt(&self.key, &mut res)).to_vec()
+                }
             };
 
-            self.current_msgs = Some(Message::from_bytes_many(Cursor::new(
-                decrypted_packet.to_vec(),
-            )));
+            self.current_msgs = Some(Message::from_bytes_many(Cursor::new(decrypted_packet)));
         };
 
         let mut msgs = self.current_msgs.take().expect("just checked");
         let next = msgs.next();
         self.current_msgs = Some(msgs);
 
+        if let Some(Ok(msg)) = &next {
+            if has_unintended_recipient(msg, &self.recipients) {
+                warn!("the decrypting key is not an intended recipient of the signed message");
+            }
+        }
+
         next
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::crypto::AeadAlgorithm;
+    use crate::packet::{SymEncryptedData, SymEncryptedProtectedData, DEFAULT_CHUNK_SIZE};
+    use crate::ser::Serialize;
+    use crate::types::StringToKey;
+
+    #[test]
+    fn test_modification_detection() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alg = SymmetricKeyAlgorithm::AES128;
+        let key = alg.new_session_key(&mut rng);
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let plaintext = msg.to_bytes().unwrap();
+
+        let ciphertext = alg.encrypt_with_rng(&mut rng, &key, &plaintext).unwrap();
+        let edata = vec![Edata::SymEncryptedData(
+            SymEncryptedData::from_slice(Default::default(), &ciphertext).unwrap(),
+        )];
+        let mut decrypter = MessageDecrypter::new(key.clone(), alg, &edata);
+        match decrypter.next() {
+            Some(Err(Error::UnprotectedData)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(decrypter.next().is_none());
+
+        let decrypted = MessageDecrypter::new(key.clone(), alg, &edata)
+            .allow_unprotected(true)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(decrypted, msg);
+
+        let packet =
+            SymEncryptedProtectedData::encrypt_with_rng(&mut rng, alg, &key, &plaintext).unwrap();
+        let mut data = packet.to_bytes().unwrap();
+        let edata = vec![Edata::SymEncryptedProtectedData(
+            SymEncryptedProtectedData::from_slice(Default::default(), &data).unwrap(),
+        )];
+        let decrypted = MessageDecrypter::new(key.clone(), alg, &edata)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(decrypted, msg);
+
+        // flip a bit of the last block, which only the MDC detects
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        let edata = vec![Edata::SymEncryptedProtectedData(
+            SymEncryptedProtectedData::from_slice(Default::default(), &data).unwrap(),
+        )];
+        let res = MessageDecrypter::new(key.clone(), alg, &edata)
+            .allow_unprotected(true)
+            .next()
+            .unwrap();
+        match res {
+            Err(Error::MdcError) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // too short to contain an MDC
+        let edata = vec![Edata::SymEncryptedProtectedData(
+            SymEncryptedProtectedData::from_slice(Default::default(), &data[..20]).unwrap(),
+        )];
+        assert!(MessageDecrypter::new(key, alg, &edata)
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    // from RFC 9580, Appendix A.9 and A.11
+    #[test]
+    fn test_decrypt_v6_skesk_rfc9580() {
+        for &(body, aead, session_key) in &[
+            (
+                "061e07010b0308a5ae579d1fc5d82bff69224f919993b3506fa3b59a6a73cff8c5efc5f41c57fb54\
+                 e1c226815d7828f5f92c454eb65ebe00ab5986c68e6e7c55",
+                AeadAlgorithm::Eax,
+                "3881bafe985412459b86c36f98cb9a5e",
+            ),
+            (
+                "061a07030b0308e9d39785b2070008ffb42e7c483ef4884457cb3726b9b3db9ff776e5f4d9a40952\
+                 e2447298851abfff7526df2dd554417579a7799f",
+                AeadAlgorithm::Gcm,
+                "1936fc8568980274bb900d8319360c77",
+            ),
+        ] {
+            let body = hex::decode(body).unwrap();
+            let packet = SymKeyEncryptedSessionKey::from_slice(Default::default(), &body).unwrap();
+            assert_eq!(packet.version(), 6);
+            assert_eq!(packet.aead().unwrap().0, aead);
+            assert_eq!(packet.to_bytes().unwrap(), body);
+
+            let alg = SymmetricKeyAlgorithm::AES128;
+            let (decrypted, _) = decrypt_session_key_with_password(
+                &packet,
+                || "password".into(),
+                Some(alg),
+                &Default::default(),
+            )
+            .unwrap();
+            assert_eq!(hex::encode(decrypted), session_key);
+        }
+    }
+
+    #[test]
+    fn test_argon2_limits() {
+        // a version 4 packet, with an Argon2 string-to-key that needs 2 GiB of memory
+        let mut body = vec![0x04, 0x09, 0x04];
+        body.extend_from_slice(&[0x42; 16]);
+        body.extend_from_slice(&[1, 4, 21]);
+        let packet = SymKeyEncryptedSessionKey::from_slice(Default::default(), &body).unwrap();
+
+        let err = decrypt_session_key_with_password(
+            &packet,
+            || "password".into(),
+            None,
+            &Default::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("exceed the limits"), "{}", err);
+    }
+
+    #[test]
+    fn test_v6_session_keys() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alg = SymmetricKeyAlgorithm::AES128;
+        let key = alg.new_session_key(&mut rng);
+        let plaintext = Message::new_literal("hello.txt", "hello world")
+            .to_bytes()
+            .unwrap();
+
+        // the session key may use a different algorithm than the key encryption key
+        let s2k = StringToKey::new_default(&mut rng);
+        let packet = SymKeyEncryptedSessionKey::encrypt_v6(
+            &mut rng,
+            || "pw".into(),
+            &key,
+            s2k,
+            SymmetricKeyAlgorithm::AES256,
+            AeadAlgorithm::Ocb,
+        )
+        .unwrap();
+        let packet =
+            SymKeyEncryptedSessionKey::from_slice(Default::default(), &packet.to_bytes().unwrap())
+                .unwrap();
+        assert_eq!(packet.version(), 6);
+        assert_eq!(packet.aead().unwrap().0, AeadAlgorithm::Ocb);
+
+        let (decrypted, decrypted_alg) = decrypt_session_key_with_password(
+            &packet,
+            || "pw".into(),
+            Some(alg),
+            &Default::default(),
+        )
+        .unwrap();
+        assert_eq!(decrypted, key);
+        assert_eq!(decrypted_alg, alg);
+        assert!(decrypt_session_key_with_password(
+            &packet,
+            || "wrong".into(),
+            Some(alg),
+            &Default::default()
+        )
+        .is_err());
+        assert!(decrypt_session_key_with_password(
+            &packet,
+            || "pw".into(),
+            None,
+            &Default::default()
+        )
+        .is_err());
+
+        // the algorithm is taken from version 2 SEIPD packets only
+        let edata = vec![Edata::SymEncryptedProtectedData(
+            SymEncryptedProtectedData::encrypt_with_rng(&mut rng, alg, &key, &plaintext).unwrap(),
+        )];
+        assert_eq!(session_key_algorithm(4, &edata).unwrap(), None);
+        assert!(session_key_algorithm(6, &edata).is_err());
+
+        let edata = vec![Edata::SymEncryptedProtectedData(
+            SymEncryptedProtectedData::encrypt_aead_with_rng(
+                &mut rng,
+                alg,
+                AeadAlgorithm::Ocb,
+                DEFAULT_CHUNK_SIZE,
+                &key,
+                &plaintext,
+            )
+            .unwrap(),
+        )];
+        assert_eq!(session_key_algorithm(6, &edata).unwrap(), Some(alg));
+    }
+}
@@ -1,5 +1,17 @@
 mod decrypt;
 mod parser;
+mod profile;
+mod session_key;
+mod status;
+mod stream;
+mod structure;
 mod types;
+mod verification;
 
+pub use self::profile::*;
+pub use self::session_key::*;
+pub use self::status::*;
+pub use self::stream::*;
+pub use self::structure::*;
 pub use self::types::*;
+pub use self::verification::*;
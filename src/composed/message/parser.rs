@@ -1,12 +1,14 @@
 use std::boxed::Box;
+use std::io::{Cursor, Read};
 use std::iter::Peekable;
 
 use try_from::TryInto;
 
+use crate::armor::{self, BlockType, Headers};
 use crate::composed::message::Message;
 use crate::composed::Deserializable;
-use crate::errors::Result;
-use crate::packet::Packet;
+use crate::errors::{Error, Result};
+use crate::packet::{Packet, PacketParser};
 use crate::types::Tag;
 
 pub struct MessageParser<I: Sized + Iterator<Item = Packet>> {
@@ -161,3 +163,40 @@ impl Deserializable for Message {
         })
     }
 }
+
+impl Message {
+    /// Parses a single binary message, failing on any data after it.
+    ///
+    /// Unlike [`Message::from_bytes`], which ignores anything after the first message and
+    /// skips packets that fail to parse, this rejects concatenated messages and trailing garbage.
+    /// Use [`Message::from_bytes_many`] to read each of a sequence of concatenated messages.
+    pub fn from_bytes_strict(bytes: impl Read) -> Result<Self> {
+        let packets = PacketParser::new(bytes).collect::<Result<Vec<_>>>()?;
+        let mut messages = Self::from_packets(packets.into_iter());
+
+        let message = messages.next().ok_or_else(|| Error::NoMatchingPacket)??;
+        ensure!(
+            messages.next().is_none(),
+            "unexpected data after the end of the message"
+        );
+
+        Ok(message)
+    }
+
+    /// Parses a single armored message, failing on any data after it.
+    ///
+    /// See [`Message::from_bytes_strict`].
+    pub fn from_string_strict(input: &str) -> Result<(Self, Headers)> {
+        let mut dearmor = armor::Dearmor::new(Cursor::new(input));
+        dearmor.read_header()?;
+        let typ = dearmor
+            .typ
+            .ok_or_else(|| format_err!("dearmor failed to retrieve armor type"))?;
+        ensure_eq!(typ, BlockType::Message, "unexpected armor block type");
+        let headers = dearmor.headers.clone();
+
+        let message = Self::from_bytes_strict(&mut dearmor)?;
+
+        Ok((message, headers))
+    }
+}
@@ -0,0 +1,745 @@
+use chrono::Utc;
+use rand::{CryptoRng, Rng};
+use smallvec::SmallVec;
+
+use crate::composed::message::{Esk, Message};
+use crate::composed::signed_key::{SignedPublicKey, SignedSecretKey};
+use crate::crypto::{AeadAlgorithm, HashAlgorithm, SymmetricKeyAlgorithm};
+use crate::errors::Result;
+use crate::packet::{Subpacket, SymKeyEncryptedSessionKey};
+use crate::types::{CompressionAlgorithm, KeyTrait, KeyVersion, PublicKeyTrait, StringToKey};
+
+/// The version of the settings behind [`EncryptionProfile::Modern`] and
+/// [`EncryptionProfile::Compatibility`].
+///
+/// It is incremented whenever these settings change, so applications can detect that
+/// messages they produce will differ from earlier releases.
+pub const ENCRYPTION_PROFILE_VERSION: u32 = 1;
+
+/// The algorithms used to encrypt a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncryptionSettings {
+    pub sym_alg: SymmetricKeyAlgorithm,
+    /// Compress the message before encrypting it, `None` disables compression.
+    pub compression: Option<CompressionAlgorithm>,
+    /// Hide the key ids of the recipients, see [`Message::encrypt_to_keys_anonymous`].
+    pub anonymous_recipients: bool,
+    /// Encrypt using AEAD into version 2 SEIPD packets, with version 6 session key
+    /// packets. `None` uses version 1 packets with a modification detection code.
+    pub aead: Option<AeadAlgorithm>,
+    /// Also encrypt to the key of the signer, so sent messages stay readable by the
+    /// sender, see [`Message::sign_and_encrypt`].
+    pub encrypt_to_self: bool,
+}
+
+/// A set of encryption settings, so callers don't have to pick algorithms themselves.
+///
+/// All profiles encrypt using integrity protected data (SEIPD) packets. Version 2 packets
+/// are only used if all recipients support them, see [`EncryptionProfile::settings_for`],
+/// otherwise version 1 packets, which all implementations understand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionProfile {
+    /// AES-256 with OCB, in version 2 SEIPD packets, without compression.
+    Modern,
+    /// AES-128 and ZIP compression, which is understood by older implementations,
+    /// for example GnuPG 1.4.
+    Compatibility,
+    Custom(EncryptionSettings),
+}
+
+impl Default for EncryptionProfile {
+    fn default() -> Self {
+        EncryptionProfile::Modern
+    }
+}
+
+impl EncryptionProfile {
+    /// Returns the settings of this profile, for [`ENCRYPTION_PROFILE_VERSION`].
+    pub fn settings(&self) -> EncryptionSettings {
+        match self {
+            EncryptionProfile::Modern => EncryptionSettings {
+                sym_alg: SymmetricKeyAlgorithm::AES256,
+                compression: None,
+                anonymous_recipients: false,
+                aead: Some(AeadAlgorithm::Ocb),
+                encrypt_to_self: false,
+            },
+            EncryptionProfile::Compatibility => EncryptionSettings {
+                sym_alg: SymmetricKeyAlgorithm::AES128,
+                compression: Some(CompressionAlgorithm::ZIP),
+                anonymous_recipients: false,
+                aead: None,
+                encrypt_to_self: false,
+            },
+            EncryptionProfile::Custom(settings) => *settings,
+        }
+    }
+
+    /// Returns the settings of this profile, adjusted to what all `recipients` support.
+    ///
+    /// The session key packets of a message all have the same version, which depends on
+    /// the version of the encrypted data packet: version 6 packets with AEAD encrypted
+    /// version 2 SEIPD packets, version 3 packets otherwise. So AEAD is only used, if all
+    /// recipients support it, see [`SignedPublicKey::supports_seipd_v2`]. Otherwise all of
+    /// them get version 3 packets.
+    ///
+    /// Version 6 keys always support AEAD, and can not be used with version 3 packets.
+    /// Fails if there are v6 recipients, and the profile does not use AEAD, or some of the
+    /// other recipients don't support it.
+    pub fn settings_for(&self, recipients: &[&SignedPublicKey]) -> Result<EncryptionSettings> {
+        let mut settings = self.settings();
+        let is_v6 = |r: &&SignedPublicKey| r.primary_key.version() == KeyVersion::V6;
+        if settings.aead.is_some() && !recipients.iter().all(|r| is_v6(r) || r.supports_seipd_v2())
+        {
+            ensure!(
+                !recipients.iter().any(is_v6),
+                "version 6 keys require AEAD, which not all recipients support"
+            );
+            debug!("not all recipients support AEAD, using SEIPD version 1");
+            settings.aead = None;
+        }
+        ensure!(
+            settings.aead.is_some() || !recipients.iter().any(is_v6),
+            "version 6 keys require AEAD, which the profile does not use"
+        );
+
+        Ok(settings)
+    }
+
+    fn prepare(&self, msg: &Message) -> Result<Option<Message>> {
+        match self.settings().compression {
+            Some(alg) => Ok(Some(msg.compress(alg)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Message {
+    /// Encrypts the message to the given public keys, using the algorithms of `profile`.
+    ///
+    /// The settings are used as they are, without checking what the keys support. Use
+    /// [`encrypt_to_recipients`](Message::encrypt_to_recipients) to fall back to version 1
+    /// SEIPD packets for recipients that don't support AEAD.
+    pub fn encrypt_to_keys_with_profile<R: CryptoRng + Rng>(
+        &self,
+        rng: &mut R,
+        profile: EncryptionProfile,
+        pkeys: &[&impl PublicKeyTrait],
+    ) -> Result<Self> {
+        let settings = profile.settings();
+        let msg = profile.prepare(self)?;
+        let msg = msg.as_ref().unwrap_or(self);
+
+        let session_key = settings.sym_alg.new_session_key(rng);
+        let mut esk = match settings.aead {
+            Some(_) => Self::encrypt_session_key_v6(rng, &session_key, pkeys)?,
+            None => Self::encrypt_session_key(rng, &session_key, settings.sym_alg, pkeys)?,
+        };
+        if settings.anonymous_recipients {
+            esk = Self::anonymous_esk(esk);
+        }
+
+        msg.encrypt_symmetric(rng, esk, settings.sym_alg, settings.aead, session_key)
+    }
+
+    /// Signs the message with `signer`, and encrypts the result to the given recipients.
+    ///
+    /// This produces the structure mail clients expect: the one pass signature, the
+    /// message and the signature, compressed if `profile` asks for it, inside the
+    /// encryption. See [`encrypt_to_recipients`](Message::encrypt_to_recipients).
+    ///
+    /// The signature is made by the current signing key of `signer`, see
+    /// [`SignedPublicKey::signing_key`], using the first hash algorithm all recipients
+    /// prefer, or SHA2-256 if they have no common preference.
+    ///
+    /// The signature lists the fingerprints of the recipients as intended recipients, so
+    /// the signed message can not be forwarded to someone else without being noticed, see
+    /// [`decrypt_and_verify`](Message::decrypt_and_verify). They are left out when
+    /// encrypting to anonymous recipients.
+    ///
+    /// If the profile asks to [`encrypt_to_self`](EncryptionSettings::encrypt_to_self),
+    /// the signer is added to the recipients, unless it already is one of them.
+    pub fn sign_and_encrypt<R, F>(
+        &self,
+        rng: &mut R,
+        signer: &SignedSecretKey,
+        signer_pw: F,
+        recipients: &[&SignedPublicKey],
+        profile: EncryptionProfile,
+    ) -> Result<Self>
+    where
+        R: CryptoRng + Rng,
+        F: FnOnce() -> String,
+    {
+        let signer_public = signer.signed_public_key();
+        let mut recipients = recipients.to_vec();
+        if profile.settings().encrypt_to_self
+            && recipients
+                .iter()
+                .all(|recipient| recipient.fingerprint() != signer.fingerprint())
+        {
+            recipients.push(&signer_public);
+        }
+
+        let intended_recipients = if profile.settings().anonymous_recipients {
+            Vec::new()
+        } else {
+            recipients
+                .iter()
+                .map(|recipient| {
+                    Subpacket::IntendedRecipientFingerprint(
+                        recipient.primary_key.version(),
+                        SmallVec::from_slice(&recipient.fingerprint()),
+                    )
+                })
+                .collect()
+        };
+        let hash_alg = preferred_hash_algorithm(&recipients);
+
+        let fingerprint = signer_public
+            .signing_key(Utc::now())
+            .map(|key| key.fingerprint())
+            .ok_or_else(|| format_err!("the signer has no usable signing key"))?;
+        let signed = if fingerprint == signer.fingerprint() {
+            self.clone()
+                .sign_with_subpackets(signer, signer_pw, hash_alg, intended_recipients)?
+        } else {
+            let subkey = signer
+                .secret_subkeys
+                .iter()
+                .find(|subkey| subkey.fingerprint() == fingerprint)
+                .ok_or_else(|| format_err!("the secret signing subkey is missing"))?;
+            self.clone()
+                .sign_with_subpackets(subkey, signer_pw, hash_alg, intended_recipients)?
+        };
+
+        signed.encrypt_to_recipients(rng, &recipients, profile)
+    }
+
+    /// Encrypts the message using the given password, using the algorithms of `profile`.
+    ///
+    /// With AEAD, like in [`EncryptionProfile::Modern`], the message can only be decrypted by
+    /// implementations that support version 2 SEIPD packets.
+    pub fn encrypt_with_password_and_profile<R, F>(
+        &self,
+        rng: &mut R,
+        profile: EncryptionProfile,
+        msg_pw: F,
+    ) -> Result<Self>
+    where
+        R: Rng + CryptoRng,
+        F: FnOnce() -> String + Clone,
+    {
+        let settings = profile.settings();
+        let s2k = StringToKey::new_default(rng);
+        let msg = profile.prepare(self)?;
+        let msg = msg.as_ref().unwrap_or(self);
+
+        let session_key = settings.sym_alg.new_session_key(rng);
+        let skesk = match settings.aead {
+            Some(aead) => SymKeyEncryptedSessionKey::encrypt_v6(
+                rng,
+                msg_pw,
+                &session_key,
+                s2k,
+                settings.sym_alg,
+                aead,
+            )?,
+            None => {
+                SymKeyEncryptedSessionKey::encrypt(msg_pw, &session_key, s2k, settings.sym_alg)?
+            }
+        };
+        let skesk = Esk::SymKeyEncryptedSessionKey(skesk);
+
+        msg.encrypt_symmetric(
+            rng,
+            vec![skesk],
+            settings.sym_alg,
+            settings.aead,
+            session_key,
+        )
+    }
+}
+
+/// Returns the first hash algorithm of at least 256 bits, that all `recipients` list in
+/// their preferences. Recipients without preferences accept any of them.
+fn preferred_hash_algorithm(recipients: &[&SignedPublicKey]) -> HashAlgorithm {
+    let preferences: Vec<&[HashAlgorithm]> = recipients
+        .iter()
+        .map(|recipient| {
+            recipient
+                .details
+                .latest_self_signature(&recipient.primary_key)
+                .map_or(&[][..], |sig| sig.preferred_hash_algs())
+        })
+        .filter(|algs| !algs.is_empty())
+        .collect();
+
+    preferences
+        .first()
+        .and_then(|first| {
+            first.iter().copied().find(|alg| {
+                alg.digest_size() >= 32 && preferences.iter().all(|algs| algs.contains(alg))
+            })
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::SubsecRound;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::message::{Edata, Esk, MessageLayer, StructurePolicy};
+    use crate::composed::test_keys::{gen_key, gen_key_v6, gen_key_with_signing_subkey};
+    use crate::composed::{Deserializable, Preferences, SignatureValidity};
+    use crate::packet::{SignatureConfigBuilder, SignatureType, SignatureVersion};
+    use crate::types::Tag;
+
+    fn seipd_version(msg: &Message) -> u8 {
+        match msg {
+            Message::Encrypted { edata, .. } => match &edata[..] {
+                [Edata::SymEncryptedProtectedData(packet)] => packet.version(),
+                edata => panic!("unexpected edata {:?}", edata),
+            },
+            _ => panic!("not encrypted"),
+        }
+    }
+
+    fn esk_versions(msg: &Message) -> Vec<u8> {
+        match msg {
+            Message::Encrypted { esk, .. } => esk
+                .iter()
+                .map(|esk| match esk {
+                    Esk::PublicKeyEncryptedSessionKey(k) => k.version(),
+                    Esk::SymKeyEncryptedSessionKey(k) => k.version(),
+                })
+                .collect(),
+            _ => panic!("not encrypted"),
+        }
+    }
+
+    /// Adds a newer self-certification to `key`, that advertises support for SEIPD v1 and v2.
+    fn advertise_seipd_v2(key: &mut SignedSecretKey) {
+        let user_id = key.details.users[0].id.clone();
+        let sig = SignatureConfigBuilder::default()
+            .typ(SignatureType::CertPositive)
+            .version(SignatureVersion::for_key(&key.primary_key))
+            .pub_alg(key.primary_key.algorithm())
+            .hashed_subpackets(vec![
+                Subpacket::SignatureCreationTime(Utc::now().trunc_subsecs(0)),
+                Subpacket::Features(smallvec![0x09]),
+            ])
+            .unhashed_subpackets(vec![])
+            .build()
+            .unwrap()
+            .with_issuer(&key.primary_key)
+            .sign_certificate(&key.primary_key, || "".into(), Tag::UserId, &user_id)
+            .unwrap();
+        key.details.users[0].signatures.push(sig);
+    }
+
+    #[test]
+    fn test_encryption_profiles() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let msg = Message::new_literal("hello.txt", "hello world");
+
+        for &(profile, sym_alg, compressed, seipd) in &[
+            (
+                EncryptionProfile::Modern,
+                SymmetricKeyAlgorithm::AES256,
+                false,
+                2,
+            ),
+            (
+                EncryptionProfile::Compatibility,
+                SymmetricKeyAlgorithm::AES128,
+                true,
+                1,
+            ),
+            (
+                EncryptionProfile::Custom(EncryptionSettings {
+                    sym_alg: SymmetricKeyAlgorithm::Camellia256,
+                    compression: Some(CompressionAlgorithm::ZLIB),
+                    anonymous_recipients: false,
+                    aead: None,
+                    encrypt_to_self: false,
+                }),
+                SymmetricKeyAlgorithm::Camellia256,
+                true,
+                1,
+            ),
+        ] {
+            let encrypted = msg
+                .encrypt_with_password_and_profile(&mut rng, profile, || "pw".into())
+                .unwrap();
+            assert_eq!(seipd_version(&encrypted), seipd);
+            match &encrypted {
+                Message::Encrypted { esk, .. } => match &esk[..] {
+                    [Esk::SymKeyEncryptedSessionKey(skesk)] => {
+                        assert_eq!(skesk.sym_algorithm(), sym_alg)
+                    }
+                    esk => panic!("unexpected esk {:?}", esk),
+                },
+                _ => panic!("not encrypted"),
+            }
+
+            let decrypted = encrypted
+                .decrypt_with_password(|| "pw".into())
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap();
+            match &decrypted {
+                Message::Compressed(_) => assert!(compressed),
+                Message::Literal(_) => assert!(!compressed),
+                _ => panic!("unexpected message {:?}", decrypted),
+            }
+            assert_eq!(decrypted.decompress().unwrap(), msg);
+        }
+
+        assert_eq!(EncryptionProfile::default(), EncryptionProfile::Modern);
+    }
+
+    #[test]
+    fn test_anonymous_recipients() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key(&mut rng, "Alice <alice@mail.com>");
+        let bob = gen_key(&mut rng, "Bob <bob@mail.com>");
+        let eve = gen_key(&mut rng, "Eve <eve@mail.com>");
+
+        let profile = EncryptionProfile::Custom(EncryptionSettings {
+            anonymous_recipients: true,
+            ..EncryptionProfile::Modern.settings()
+        });
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let encrypted = msg
+            .encrypt_to_recipients(
+                &mut rng,
+                &[&alice.signed_public_key(), &bob.signed_public_key()],
+                profile,
+            )
+            .unwrap();
+        let recipients = encrypted.get_recipients();
+        assert_eq!(recipients.len(), 2);
+        assert!(recipients.iter().all(|id| id.is_wildcard()));
+
+        // every recipient has to try its keys, and finds the packet for it
+        for key in &[&alice, &bob] {
+            let (mut decrypter, ids) = encrypted
+                .decrypt(|| "".into(), || "".into(), &[*key])
+                .unwrap();
+            assert_eq!(ids, vec![key.secret_subkeys[0].key_id()]);
+            assert_eq!(decrypter.next().unwrap().unwrap(), msg);
+        }
+        assert!(encrypted
+            .decrypt(|| "".into(), || "".into(), &[&eve])
+            .is_err());
+    }
+
+    #[test]
+    fn test_sign_and_encrypt() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key(&mut rng, "Alice <alice@mail.com>");
+        let bob = gen_key(&mut rng, "Bob <bob@mail.com>");
+
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let encrypted = msg
+            .sign_and_encrypt(
+                &mut rng,
+                &alice,
+                || "".into(),
+                &[&bob.signed_public_key()],
+                EncryptionProfile::Compatibility,
+            )
+            .unwrap();
+        let (mut decrypter, _) = encrypted
+            .decrypt(|| "".into(), || "".into(), &[&bob])
+            .unwrap();
+        let decrypted = decrypter.next().unwrap().unwrap();
+
+        let mut layers = encrypted.structure().unwrap();
+        layers.extend(decrypted.structure().unwrap());
+        assert_eq!(layers.len(), 4);
+        assert_eq!(
+            layers[1],
+            MessageLayer::Compressed(CompressionAlgorithm::ZIP)
+        );
+        assert_eq!(
+            layers[2],
+            MessageLayer::Signed {
+                issuer: Some(alice.key_id()),
+                one_pass: true,
+            }
+        );
+        StructurePolicy::SignedThenEncrypted.check(&layers).unwrap();
+
+        let signed = decrypted.decompress().unwrap();
+        signed.verify(&alice.signed_public_key()).unwrap();
+        assert!(signed.verify(&bob.signed_public_key()).is_err());
+        assert_eq!(signed.get_content().unwrap(), Some(b"hello world".to_vec()));
+    }
+
+    #[test]
+    fn test_sign_and_encrypt_to_self() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key(&mut rng, "Alice <alice@mail.com>");
+        let bob = gen_key(&mut rng, "Bob <bob@mail.com>");
+        let (alice_public, bob_public) = (alice.signed_public_key(), bob.signed_public_key());
+        let profile = EncryptionProfile::Custom(EncryptionSettings {
+            encrypt_to_self: true,
+            ..EncryptionProfile::Modern.settings()
+        });
+
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let encrypted = msg
+            .sign_and_encrypt(&mut rng, &alice, || "".into(), &[&bob_public], profile)
+            .unwrap();
+        assert_eq!(encrypted.get_recipients().len(), 2);
+        for key in &[&alice, &bob] {
+            let decrypted = encrypted
+                .decrypt_and_verify(|| "".into(), &[key], &[&alice_public])
+                .unwrap();
+            assert_eq!(decrypted.signatures.len(), 1);
+            assert_eq!(decrypted.signatures[0].validity, SignatureValidity::Valid);
+        }
+
+        // the signer is only added once
+        let encrypted = msg
+            .sign_and_encrypt(
+                &mut rng,
+                &alice,
+                || "".into(),
+                &[&bob_public, &alice_public],
+                profile,
+            )
+            .unwrap();
+        assert_eq!(encrypted.get_recipients().len(), 2);
+
+        let encrypted = msg
+            .sign_and_encrypt(
+                &mut rng,
+                &alice,
+                || "".into(),
+                &[&bob_public],
+                EncryptionProfile::Modern,
+            )
+            .unwrap();
+        assert_eq!(encrypted.get_recipients().len(), 1);
+    }
+
+    #[test]
+    fn test_sign_and_encrypt_algorithms() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key_with_signing_subkey(&mut rng, "Alice <alice@mail.com>");
+        let mut bob = gen_key(&mut rng, "Bob <bob@mail.com>");
+        let preferences = Preferences {
+            hash_algorithms: smallvec![HashAlgorithm::SHA1, HashAlgorithm::SHA2_512],
+            ..Default::default()
+        };
+        bob.set_preferences(&preferences, || "".into()).unwrap();
+
+        let encrypted = Message::new_literal("hello.txt", "hello world")
+            .sign_and_encrypt(
+                &mut rng,
+                &alice,
+                || "".into(),
+                &[&bob.signed_public_key()],
+                EncryptionProfile::Modern,
+            )
+            .unwrap();
+        let (mut decrypter, _) = encrypted
+            .decrypt(|| "".into(), || "".into(), &[&bob])
+            .unwrap();
+        match decrypter.next().unwrap().unwrap() {
+            Message::Signed { signature: sig, .. } => {
+                // signed by the subkey, with the strong hash bob prefers
+                assert_eq!(sig.issuer(), Some(&alice.secret_subkeys[0].key_id()));
+                assert_eq!(sig.config.hash_alg, HashAlgorithm::SHA2_512);
+            }
+            msg => panic!("unexpected message {:?}", msg),
+        }
+
+        assert_eq!(
+            preferred_hash_algorithm(&[&alice.signed_public_key()]),
+            HashAlgorithm::SHA2_256
+        );
+    }
+
+    #[test]
+    fn test_aead_encryption() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let msg = Message::new_literal("hello.txt", "hello world");
+
+        for &aead in &[AeadAlgorithm::Eax, AeadAlgorithm::Ocb, AeadAlgorithm::Gcm] {
+            let profile = EncryptionProfile::Custom(EncryptionSettings {
+                aead: Some(aead),
+                ..EncryptionProfile::Compatibility.settings()
+            });
+            let encrypted = msg
+                .encrypt_with_password_and_profile(&mut rng, profile, || "pw".into())
+                .unwrap();
+            assert_eq!(seipd_version(&encrypted), 2);
+            assert_eq!(esk_versions(&encrypted), vec![6]);
+            assert_eq!(
+                encrypted.structure().unwrap(),
+                vec![MessageLayer::Encrypted {
+                    recipients: Vec::new(),
+                    password: true,
+                    protected: true,
+                    sym_alg: Some(SymmetricKeyAlgorithm::AES128),
+                    aead: Some(aead),
+                }]
+            );
+
+            let armor = encrypted.to_armored_string(None).unwrap();
+            let (encrypted, _) = Message::from_string(&armor).unwrap();
+            let decrypted = encrypted
+                .decrypt_with_password(|| "pw".into())
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap();
+            assert_eq!(decrypted.decompress().unwrap(), msg);
+        }
+
+        let mut key = gen_key(&mut rng, "Me <me@mail.com>");
+        let profile = EncryptionProfile::Modern;
+
+        // the key does not advertise support for AEAD
+        let public_key = key.signed_public_key();
+        assert!(!public_key.supports_seipd_v2());
+        let encrypted = msg
+            .encrypt_to_recipients(&mut rng, &[&public_key], profile)
+            .unwrap();
+        assert_eq!(seipd_version(&encrypted), 1);
+        assert_eq!(esk_versions(&encrypted), vec![3]);
+
+        advertise_seipd_v2(&mut key);
+        let public_key = key.signed_public_key();
+        assert!(public_key.supports_seipd_v2());
+        let encrypted = msg
+            .encrypt_to_recipients(&mut rng, &[&public_key], profile)
+            .unwrap();
+        assert_eq!(seipd_version(&encrypted), 2);
+        assert_eq!(esk_versions(&encrypted), vec![6]);
+
+        // version 6 session keys identify the recipient by fingerprint
+        let armor = encrypted.to_armored_string(None).unwrap();
+        let (encrypted, _) = Message::from_string(&armor).unwrap();
+        match &encrypted {
+            Message::Encrypted { esk, .. } => match &esk[..] {
+                [Esk::PublicKeyEncryptedSessionKey(k)] => {
+                    let subkey = &key.secret_subkeys[0];
+                    assert_eq!(
+                        k.fingerprint(),
+                        Some((subkey.version(), &subkey.fingerprint()[..]))
+                    );
+                    assert!(k.matches(subkey));
+                }
+                esk => panic!("unexpected esk {:?}", esk),
+            },
+            _ => panic!("not encrypted"),
+        }
+        let (mut decrypter, _) = encrypted
+            .decrypt(|| "".into(), || "".into(), &[&key])
+            .unwrap();
+        assert_eq!(decrypter.next().unwrap().unwrap(), msg);
+
+        let profile = EncryptionProfile::Custom(EncryptionSettings {
+            anonymous_recipients: true,
+            ..profile.settings()
+        });
+        let encrypted = msg
+            .encrypt_to_recipients(&mut rng, &[&public_key], profile)
+            .unwrap();
+        assert_eq!(esk_versions(&encrypted), vec![6]);
+        let (mut decrypter, _) = encrypted
+            .decrypt(|| "".into(), || "".into(), &[&key])
+            .unwrap();
+        assert_eq!(decrypter.next().unwrap().unwrap(), msg);
+    }
+
+    #[test]
+    fn test_mixed_recipients() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let mut alice = gen_key(&mut rng, "Alice <alice@mail.com>");
+        let mut bob = gen_key(&mut rng, "Bob <bob@mail.com>");
+        let carol = gen_key(&mut rng, "Carol <carol@mail.com>");
+        advertise_seipd_v2(&mut alice);
+        advertise_seipd_v2(&mut bob);
+        let profile = EncryptionProfile::Modern;
+
+        let (alice_public, bob_public, carol_public) = (
+            alice.signed_public_key(),
+            bob.signed_public_key(),
+            carol.signed_public_key(),
+        );
+        let expected = [
+            (vec![&alice_public, &bob_public], 2, vec![6, 6]),
+            (vec![&alice_public, &carol_public], 1, vec![3, 3]),
+        ];
+        for (recipients, seipd, esk) in &expected {
+            let settings = profile.settings_for(recipients).unwrap();
+            assert_eq!(settings.aead.is_some(), *seipd == 2);
+
+            let encrypted = msg
+                .encrypt_to_recipients(&mut rng, recipients, profile)
+                .unwrap();
+            assert_eq!(seipd_version(&encrypted), *seipd);
+            assert_eq!(&esk_versions(&encrypted), esk);
+
+            let key = if recipients.contains(&&bob_public) {
+                &bob
+            } else {
+                &carol
+            };
+            for key in &[&alice, key] {
+                let (mut decrypter, _) = encrypted
+                    .decrypt(|| "".into(), || "".into(), &[key])
+                    .unwrap();
+                assert_eq!(decrypter.next().unwrap().unwrap(), msg);
+            }
+        }
+    }
+
+    #[test]
+    fn test_v6_recipients() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let alice = gen_key_v6(&mut rng, Some("Alice <alice@mail.com>"));
+        let bob = gen_key(&mut rng, "Bob <bob@mail.com>");
+        let (alice_public, bob_public) = (alice.signed_public_key(), bob.signed_public_key());
+
+        // v6 keys don't need to advertise AEAD
+        assert!(!alice_public.supports_seipd_v2());
+        let encrypted = msg
+            .encrypt_to_recipients(&mut rng, &[&alice_public], EncryptionProfile::Modern)
+            .unwrap();
+        assert_eq!(seipd_version(&encrypted), 2);
+        assert_eq!(esk_versions(&encrypted), vec![6]);
+        let (mut decrypter, _) = encrypted
+            .decrypt(|| "".into(), || "".into(), &[&alice])
+            .unwrap();
+        assert_eq!(decrypter.next().unwrap().unwrap(), msg);
+
+        // but they never get version 3 session key packets
+        assert!(EncryptionProfile::Compatibility
+            .settings_for(&[&alice_public])
+            .is_err());
+        assert!(EncryptionProfile::Modern
+            .settings_for(&[&alice_public, &bob_public])
+            .is_err());
+        assert!(msg
+            .encrypt_to_recipients(
+                &mut rng,
+                &[&alice_public, &bob_public],
+                EncryptionProfile::Modern
+            )
+            .is_err());
+    }
+}
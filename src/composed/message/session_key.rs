@@ -0,0 +1,335 @@
+use std::fmt;
+use std::str::FromStr;
+
+use num_traits::FromPrimitive;
+use rand::{CryptoRng, Rng};
+use zeroize::Zeroize;
+
+use crate::composed::message::decrypt::{
+    check_session_key, decrypt_session_key_with_password, session_key_algorithm, MessageDecrypter,
+};
+use crate::composed::message::types::{decrypt_esk, Edata, Esk, Message};
+use crate::composed::signed_key::SignedSecretKey;
+use crate::crypto::SymmetricKeyAlgorithm;
+use crate::errors::{Error, Result};
+use crate::packet::SymKeyEncryptedSessionKey;
+use crate::types::{Argon2Limits, PublicKeyTrait, StringToKey};
+
+/// The symmetric key, that the data of an encrypted message is encrypted with.
+///
+/// It is formatted and parsed like GnuPG does for `--show-session-key`, as the algorithm
+/// id followed by the hex encoded key, for example `9:0F1C...`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SessionKey {
+    alg: SymmetricKeyAlgorithm,
+    key: Vec<u8>,
+}
+
+impl SessionKey {
+    /// Fails if the key size does not match the algorithm.
+    pub fn new(alg: SymmetricKeyAlgorithm, key: Vec<u8>) -> Result<Self> {
+        check_session_key(&key, alg)?;
+
+        Ok(SessionKey { alg, key })
+    }
+
+    pub fn alg(&self) -> SymmetricKeyAlgorithm {
+        self.alg
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub(crate) fn decrypter<'a>(&self, edata: &'a [Edata]) -> MessageDecrypter<'a> {
+        MessageDecrypter::new(self.key.clone(), self.alg, edata)
+    }
+}
+
+impl Drop for SessionKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionKey")
+            .field("alg", &self.alg)
+            .field("key", &"[..]")
+            .finish()
+    }
+}
+
+impl fmt::Display for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.alg as u8, hex::encode_upper(&self.key))
+    }
+}
+
+impl FromStr for SessionKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.trim().splitn(2, ':');
+        let alg = parts
+            .next()
+            .and_then(|alg| alg.parse().ok())
+            .and_then(SymmetricKeyAlgorithm::from_u8)
+            .ok_or_else(|| format_err!("invalid symmetric key algorithm"))?;
+        let key = match parts.next() {
+            Some(key) => hex::decode(key).map_err(|_| format_err!("invalid session key"))?,
+            None => bail!("missing session key"),
+        };
+
+        SessionKey::new(alg, key)
+    }
+}
+
+impl Message {
+    /// Decrypts only the session key of the message using one of `keys`, without
+    /// decrypting the data, like `gpg --show-session-key`.
+    pub fn extract_session_key<G>(&self, key_pw: G, keys: &[&SignedSecretKey]) -> Result<SessionKey>
+    where
+        G: FnOnce() -> String + Clone,
+    {
+        match self {
+            Message::Encrypted { esk, edata } => {
+                let (session_key, _) = decrypt_esk(esk, edata, key_pw, keys, &mut |_| {}, None)?;
+                Ok(session_key)
+            }
+            Message::Signed {
+                message: Some(message),
+                ..
+            } => message.extract_session_key(key_pw, keys),
+            _ => bail!("not encrypted"),
+        }
+    }
+
+    /// Decrypts only the session key of the message using the given password, see
+    /// [`extract_session_key`](Message::extract_session_key).
+    pub fn extract_session_key_with_password<F>(&self, msg_pw: F) -> Result<SessionKey>
+    where
+        F: FnOnce() -> String,
+    {
+        match self {
+            Message::Encrypted { esk, edata } => {
+                let skesk = esk.iter().find_map(|esk| match esk {
+                    Esk::SymKeyEncryptedSessionKey(k) => Some(k),
+                    _ => None,
+                });
+                match skesk {
+                    Some(skesk) => {
+                        let alg = session_key_algorithm(skesk.version(), edata)?;
+                        let (key, alg) = decrypt_session_key_with_password(
+                            skesk,
+                            msg_pw,
+                            alg,
+                            &Argon2Limits::default(),
+                        )?;
+                        SessionKey::new(alg, key)
+                    }
+                    None => bail!("message is not password protected"),
+                }
+            }
+            Message::Signed {
+                message: Some(message),
+                ..
+            } => message.extract_session_key_with_password(msg_pw),
+            _ => bail!("not encrypted"),
+        }
+    }
+
+    /// Encrypts the message to the given public keys, using `session_key` instead of a
+    /// newly generated one.
+    ///
+    /// This allows creating multiple messages sharing the same session key. Never reuse a
+    /// session key for unrelated messages, and keep it secret like the data itself.
+    pub fn encrypt_to_keys_with_session_key<R: CryptoRng + Rng>(
+        &self,
+        rng: &mut R,
+        session_key: &SessionKey,
+        pkeys: &[&impl PublicKeyTrait],
+    ) -> Result<Self> {
+        let alg = session_key.alg;
+        let esk = Self::encrypt_session_key(rng, &session_key.key, alg, pkeys)?;
+
+        self.encrypt_symmetric(rng, esk, alg, None, session_key.key.clone())
+    }
+
+    /// Encrypts the message using the given password, using `session_key` instead of a
+    /// newly generated one, see
+    /// [`encrypt_to_keys_with_session_key`](Message::encrypt_to_keys_with_session_key).
+    pub fn encrypt_with_password_and_session_key<R, F>(
+        &self,
+        rng: &mut R,
+        s2k: StringToKey,
+        session_key: &SessionKey,
+        msg_pw: F,
+    ) -> Result<Self>
+    where
+        R: Rng + CryptoRng,
+        F: FnOnce() -> String + Clone,
+    {
+        let alg = session_key.alg;
+        let skesk = Esk::SymKeyEncryptedSessionKey(SymKeyEncryptedSessionKey::encrypt(
+            msg_pw,
+            &session_key.key,
+            s2k,
+            alg,
+        )?);
+
+        self.encrypt_symmetric(rng, vec![skesk], alg, None, session_key.key.clone())
+    }
+
+    /// Decrypts the message using a known session key, like `gpg --override-session-key`.
+    ///
+    /// The encrypted session keys of the message are ignored. Use [`SessionKey::new`], or
+    /// parse the output of `gpg --show-session-key`, to create the session key.
+    pub fn decrypt_with_session_key<'a>(
+        &'a self,
+        session_key: &SessionKey,
+    ) -> Result<MessageDecrypter<'a>> {
+        match self {
+            Message::Encrypted { edata, .. } => Ok(session_key.decrypter(edata)),
+            Message::Signed {
+                message: Some(message),
+                ..
+            } => message.decrypt_with_session_key(session_key),
+            _ => bail!("not encrypted"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::test_keys::gen_key;
+    use crate::composed::EncryptionProfile;
+    use crate::types::SecretKeyTrait;
+
+    #[test]
+    fn test_extract_session_key() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key(&mut rng, "Me <me@mail.com>");
+
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let encrypted = msg
+            .encrypt_to_recipients(
+                &mut rng,
+                &[&key.signed_public_key()],
+                EncryptionProfile::Modern,
+            )
+            .unwrap();
+        let session_key = encrypted
+            .extract_session_key(|| "".into(), &[&key])
+            .unwrap();
+        assert_eq!(session_key.alg(), SymmetricKeyAlgorithm::AES256);
+        assert_eq!(session_key.as_bytes().len(), 32);
+        assert!(encrypted
+            .extract_session_key_with_password(|| "".into())
+            .is_err());
+
+        let formatted = session_key.to_string();
+        assert!(formatted.starts_with("9:"));
+        assert_eq!(formatted.len(), 2 + 64);
+        assert_eq!(formatted.parse::<SessionKey>().unwrap(), session_key);
+        assert!(!format!("{:?}", session_key).contains(&formatted[2..]));
+
+        for invalid in &["", "9", "9:00", "0:00", "42:00", "9:xyz"] {
+            assert!(invalid.parse::<SessionKey>().is_err(), "{}", invalid);
+        }
+
+        let encrypted = msg
+            .encrypt_with_password_and_profile(&mut rng, EncryptionProfile::Compatibility, || {
+                "pw".into()
+            })
+            .unwrap();
+        let session_key = encrypted
+            .extract_session_key_with_password(|| "pw".into())
+            .unwrap();
+        assert_eq!(session_key.alg(), SymmetricKeyAlgorithm::AES128);
+        assert!(encrypted
+            .extract_session_key(|| "".into(), &[&key])
+            .is_err());
+        assert!(msg.extract_session_key(|| "".into(), &[&key]).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_with_session_key() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let encrypted = msg
+            .encrypt_with_password_and_profile(&mut rng, EncryptionProfile::Modern, || "pw".into())
+            .unwrap();
+        let session_key = encrypted
+            .extract_session_key_with_password(|| "pw".into())
+            .unwrap();
+
+        // the session key can be passed around in the format of GnuPG
+        let session_key: SessionKey = session_key.to_string().parse().unwrap();
+        let decrypted = encrypted
+            .decrypt_with_session_key(&session_key)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(decrypted, msg);
+
+        let wrong = SessionKey::new(SymmetricKeyAlgorithm::AES256, vec![0; 32]).unwrap();
+        assert!(encrypted
+            .decrypt_with_session_key(&wrong)
+            .unwrap()
+            .next()
+            .unwrap()
+            .is_err());
+        assert!(SessionKey::new(SymmetricKeyAlgorithm::AES256, vec![0; 16]).is_err());
+        assert!(msg.decrypt_with_session_key(&session_key).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_with_session_key() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key(&mut rng, "Me <me@mail.com>");
+        let subkey = key.secret_subkeys[0].public_key();
+
+        let session_key = SessionKey::new(SymmetricKeyAlgorithm::AES128, vec![7; 16]).unwrap();
+        let first = Message::new_literal("first.txt", "first");
+        let second = Message::new_literal("second.txt", "second");
+        let s2k = StringToKey::new_default(&mut rng);
+        let encrypted = vec![
+            first
+                .encrypt_to_keys_with_session_key(&mut rng, &session_key, &[&subkey])
+                .unwrap(),
+            second
+                .encrypt_with_password_and_session_key(&mut rng, s2k, &session_key, || "pw".into())
+                .unwrap(),
+        ];
+
+        assert_eq!(
+            encrypted[0]
+                .extract_session_key(|| "".into(), &[&key])
+                .unwrap(),
+            session_key
+        );
+        assert_eq!(
+            encrypted[1]
+                .extract_session_key_with_password(|| "pw".into())
+                .unwrap(),
+            session_key
+        );
+        for (encrypted, msg) in encrypted.iter().zip(&[first, second]) {
+            let decrypted = encrypted
+                .decrypt_with_session_key(&session_key)
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap();
+            assert_eq!(&decrypted, msg);
+        }
+    }
+}
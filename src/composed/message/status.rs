@@ -0,0 +1,38 @@
+use chrono::{DateTime, Utc};
+
+use crate::types::KeyId;
+
+/// Events emitted while decrypting and verifying messages.
+/// These mirror the status lines emitted by `gpg --status-fd`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatusEvent {
+    /// The message is encrypted to the given key id (`ENC_TO`).
+    EncTo(KeyId),
+    /// There is no secret key available for the given key id (`NO_SECKEY`).
+    NoSecKey(KeyId),
+    /// Decryption of the session key is starting (`BEGIN_DECRYPTION`).
+    BeginDecryption,
+    /// No session key could be decrypted (`DECRYPTION_FAILED`).
+    DecryptionFailed,
+    /// The signature issued by the given key id is valid (`GOODSIG`).
+    GoodSig(KeyId),
+    /// The signature issued by the given key id is invalid (`BADSIG`).
+    BadSig(KeyId),
+    /// The signature issued by the given key id could not be checked (`ERRSIG`).
+    ErrSig(KeyId),
+    /// The key with the given key id had expired at the given time, before it made the
+    /// signature (`KEYEXPIRED`).
+    KeyExpired(KeyId, DateTime<Utc>),
+}
+
+/// Receives [StatusEvent]s, see `Message::decrypt_with_status`, `Message::verify_with_status`
+/// and `Message::verify_signatures_with_status`.
+pub trait StatusObserver {
+    fn on_event(&mut self, event: StatusEvent);
+}
+
+impl<F: FnMut(StatusEvent)> StatusObserver for F {
+    fn on_event(&mut self, event: StatusEvent) {
+        self(event)
+    }
+}
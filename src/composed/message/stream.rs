@@ -0,0 +1,761 @@
+use std::cmp;
+use std::io::{self, Cursor, Read, Write};
+
+use byteorder::ReadBytesExt;
+use chrono::{SubsecRound, Utc};
+use nom::IResult;
+use num_traits::FromPrimitive;
+
+use crate::composed::message::session_key::SessionKey;
+use crate::composed::message::types::{decrypt_esk, Edata, Esk};
+use crate::composed::signature::{signers, VerificationResult};
+use crate::composed::{Policy, SignedPublicKey, SignedSecretKey};
+use crate::crypto::hash::{canonicalize_text, update_text, HashAlgorithm, Hasher};
+use crate::errors::{Error, Result};
+use crate::packet::single;
+use crate::packet::{
+    stream_decompressor, write_packet, DataMode, LiteralData, OnePassSignature,
+    PublicKeyEncryptedSessionKey, Signature, SignatureConfigBuilder, SignatureType,
+    SignatureVersion, Subpacket, SymEncryptedProtectedData, SymKeyEncryptedSessionKey,
+};
+use crate::ser::Serialize;
+use crate::types::{CompressionAlgorithm, KeyTrait, PacketLength, SecretKeyTrait, Tag, Version};
+use crate::util::write_packet_length;
+
+/// Size of the partial body chunks of the literal data, 2^13 octets.
+const CHUNK_POWER: u8 = 13;
+
+/// Maximum size of the packets that are read into memory, one-pass signatures,
+/// signatures, session keys and markers.
+const MAX_PACKET_SIZE: u64 = 64 * 1024;
+
+/// Maximum size of encrypted data. It is decrypted in memory, so no data is returned
+/// before its integrity was checked.
+const MAX_ENCRYPTED_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Decrypts the session key of an encrypted message.
+type SessionKeyDecrypter<'b> = Box<dyn FnOnce(&[Esk], &[Edata]) -> Result<SessionKey> + 'b>;
+
+/// Signs the data read from `data`, and writes it as a signed message to `writer`, in a
+/// single pass and without buffering the data.
+///
+/// The message consists of a one-pass signature packet, the literal data, written with
+/// partial body lengths, and the signature. If `text` is set, the line endings of the
+/// data are converted to `\r\n` and a text signature is made, otherwise the data is
+/// signed as binary. The message can be parsed with
+/// [`Message::from_bytes`](crate::composed::Deserializable::from_bytes), or verified
+/// while reading it with [`VerifyingReader`].
+pub fn sign_stream<F>(
+    writer: &mut impl Write,
+    mut data: impl Read,
+    file_name: &str,
+    text: bool,
+    key: &impl SecretKeyTrait,
+    key_pw: F,
+    hash_alg: HashAlgorithm,
+) -> Result<()>
+where
+    F: FnOnce() -> String,
+{
+    let typ = if text {
+        SignatureType::Text
+    } else {
+        SignatureType::Binary
+    };
+    let mut config = SignatureConfigBuilder::default()
+        .typ(typ)
+        .version(SignatureVersion::for_key(key))
+        .pub_alg(key.algorithm())
+        .hash_alg(hash_alg)
+        .hashed_subpackets(vec![Subpacket::SignatureCreationTime(
+            Utc::now().trunc_subsecs(0),
+        )])
+        .unhashed_subpackets(vec![])
+        .build()?
+        .with_issuer(key);
+    config.ensure_salt()?;
+    let mut hasher = config.new_hasher()?;
+
+    let ops = config.one_pass_signature(key)?;
+    write_packet(writer, &ops)?;
+
+    // the first chunk starts with the header of the literal data
+    writer.write_all(&[0b1100_0000 | Tag::LiteralData as u8])?;
+    let mut body = PartialBodyWriter::new(writer);
+    let literal = if text {
+        LiteralData::from_str(file_name, "")
+    } else {
+        LiteralData::from_bytes(file_name, &[])
+    };
+    literal.to_writer(&mut body)?;
+
+    let mut buf = vec![0; 1 << CHUNK_POWER];
+    let mut canonical = Vec::new();
+    let mut last_was_cr = false;
+    loop {
+        let read = match data.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let chunk = if text {
+            canonical.clear();
+            canonicalize_text(&buf[..read], &mut last_was_cr, |part| {
+                canonical.extend_from_slice(part)
+            });
+            &canonical[..]
+        } else {
+            &buf[..read]
+        };
+        hasher.update(chunk);
+        body.write_all(chunk)?;
+    }
+    body.finish()?;
+
+    let signature = config.sign_hasher(key, key_pw, hasher)?;
+    write_packet(writer, &signature)
+}
+
+/// Writes a packet body as chunks with partial body lengths, see
+/// [RFC 4880, Section 4.2.2.4](https://tools.ietf.org/html/rfc4880.html#section-4.2.2.4).
+struct PartialBodyWriter<'a, W: Write> {
+    writer: &'a mut W,
+    buf: Vec<u8>,
+}
+
+impl<'a, W: Write> PartialBodyWriter<'a, W> {
+    fn new(writer: &'a mut W) -> Self {
+        PartialBodyWriter {
+            writer,
+            buf: Vec::with_capacity(1 << CHUNK_POWER),
+        }
+    }
+
+    /// Writes the remaining data, the last chunk always has a definite length.
+    fn finish(self) -> Result<()> {
+        write_packet_length(self.buf.len(), self.writer)?;
+        self.writer.write_all(&self.buf)?;
+
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> Write for PartialBodyWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let chunk_size = 1 << CHUNK_POWER;
+        let len = cmp::min(buf.len(), chunk_size - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+
+        if self.buf.len() == chunk_size {
+            self.writer.write_all(&[0xE0 | CHUNK_POWER])?;
+            self.writer.write_all(&self.buf)?;
+            self.buf.clear();
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads a signed message, and verifies its signatures while the data is read.
+///
+/// Only messages made of one-pass signatures, literal data and the matching signatures
+/// are supported, as written by [`sign_stream`] and `gpg --sign`. They may be compressed,
+/// and encrypted if the reader is created with [`decrypt`]. Armored messages have to be
+/// dearmored first, for example using [`Dearmor`](crate::armor::Dearmor).
+///
+/// The literal data is returned through [`Read`]. The signatures that follow it are
+/// checked as soon as the end of the data is reached, reading fails if none of them is
+/// valid. The data must not be trusted before that, [`verify`] returns the signer.
+///
+/// Encrypted data is decrypted in memory, as its integrity can only be checked once
+/// all of it was decrypted.
+///
+/// [`decrypt`]: VerifyingReader::decrypt
+/// [`verify`]: VerifyingReader::verify
+pub struct VerifyingReader<'a> {
+    packets: PacketReader<Box<dyn Read + 'a>>,
+    pending: Vec<PendingSignature>,
+    keyring: &'a [SignedPublicKey],
+    policy: &'a Policy,
+    file_name: String,
+    mode: DataMode,
+    /// The mode, file name and date, as they are included in v5 signatures.
+    metadata: Vec<u8>,
+    /// The result of checking the signatures, once all data was read.
+    verified: Option<Result<VerificationResult>>,
+}
+
+/// A signature announced by a one-pass signature packet, that is hashed while reading.
+struct PendingSignature {
+    ops: OnePassSignature,
+    hasher: Box<dyn Hasher>,
+    last_was_cr: bool,
+}
+
+impl PendingSignature {
+    fn update(&mut self, buf: &[u8]) {
+        if self.ops.typ() == SignatureType::Text {
+            update_text(&mut *self.hasher, buf, &mut self.last_was_cr);
+        } else {
+            self.hasher.update(buf);
+        }
+    }
+}
+
+impl<'a> VerifyingReader<'a> {
+    /// Reads the one-pass signatures and the header of the literal data from `inner`.
+    ///
+    /// Like [`verify_detached_reader`](crate::composed::verify_detached_reader), the
+    /// signatures have to be made by one of the keys of `keyring`, or one of their signing
+    /// subkeys, and be acceptable under `policy`.
+    pub fn new(
+        inner: impl Read + 'a,
+        keyring: &'a [SignedPublicKey],
+        policy: &'a Policy,
+    ) -> Result<Self> {
+        Self::open(Box::new(inner), keyring, policy, None)
+    }
+
+    /// Like [`new`](VerifyingReader::new), for messages that may be encrypted to one of
+    /// `keys`.
+    pub fn decrypt<G>(
+        inner: impl Read + 'a,
+        key_pw: G,
+        keys: &[&SignedSecretKey],
+        keyring: &'a [SignedPublicKey],
+        policy: &'a Policy,
+    ) -> Result<Self>
+    where
+        G: FnOnce() -> String + Clone,
+    {
+        let decrypt = move |esk: &[Esk], edata: &[Edata]| -> Result<SessionKey> {
+            let (session_key, _) = decrypt_esk(esk, edata, key_pw, keys, &mut |_| {}, None)?;
+            Ok(session_key)
+        };
+
+        Self::open(Box::new(inner), keyring, policy, Some(Box::new(decrypt)))
+    }
+
+    fn open(
+        inner: Box<dyn Read + 'a>,
+        keyring: &'a [SignedPublicKey],
+        policy: &'a Policy,
+        mut decrypt: Option<SessionKeyDecrypter<'_>>,
+    ) -> Result<Self> {
+        let mut packets = PacketReader::new(inner);
+        let mut pending: Vec<PendingSignature> = Vec::new();
+        let mut esk = Vec::new();
+
+        loop {
+            let (version, tag) = match packets.next_header()? {
+                Some(header) => header,
+                None => bail!("missing literal data"),
+            };
+            match tag {
+                Tag::OnePassSignature => {
+                    if pending.last().map_or(false, |p| p.ops.is_last()) {
+                        unsupported_err!("nested signatures can not be verified while reading");
+                    }
+                    let ops = OnePassSignature::from_slice(version, &packets.read_body()?)?;
+                    match ops.typ() {
+                        SignatureType::Binary | SignatureType::Text => {}
+                        typ => bail!("invalid one-pass signature type {:?}", typ),
+                    }
+                    let mut hasher = ops.hash_algorithm().new_hasher()?;
+                    // v6 signatures are salted, the salt is hashed first
+                    if let Some(salt) = ops.salt() {
+                        hasher.update(salt);
+                    }
+                    pending.push(PendingSignature {
+                        ops,
+                        hasher,
+                        last_was_cr: false,
+                    });
+                }
+                Tag::CompressedData => {
+                    // the compressed data extends to the end of the message
+                    let alg = packets.read_u8()?;
+                    let alg = CompressionAlgorithm::from_u8(alg)
+                        .ok_or_else(|| format_err!("invalid compression algorithm {}", alg))?;
+                    packets = PacketReader::new(stream_decompressor(alg, packets)?);
+                }
+                Tag::PublicKeyEncryptedSessionKey => {
+                    let packet =
+                        PublicKeyEncryptedSessionKey::from_slice(version, &packets.read_body()?)?;
+                    esk.push(Esk::PublicKeyEncryptedSessionKey(packet));
+                }
+                Tag::SymKeyEncryptedSessionKey => {
+                    let packet =
+                        SymKeyEncryptedSessionKey::from_slice(version, &packets.read_body()?)?;
+                    esk.push(Esk::SymKeyEncryptedSessionKey(packet));
+                }
+                Tag::SymEncryptedProtectedData => {
+                    let decrypt = match decrypt.take() {
+                        Some(decrypt) => decrypt,
+                        None => bail!("encrypted messages have to be read with a key"),
+                    };
+                    let body = packets.read_body_limited(MAX_ENCRYPTED_SIZE)?;
+                    let packet = SymEncryptedProtectedData::from_slice(version, &body)?;
+                    let edata = [Edata::SymEncryptedProtectedData(packet)];
+                    let session_key = decrypt(&esk, &edata)?;
+                    let plaintext = match &edata[0] {
+                        Edata::SymEncryptedProtectedData(packet) => {
+                            packet.decrypt(session_key.as_bytes(), session_key.alg())?
+                        }
+                        Edata::SymEncryptedData(_) => unreachable!("checked above"),
+                    };
+                    packets = PacketReader::new(Box::new(Cursor::new(plaintext)));
+                }
+                Tag::SymEncryptedData => return Err(Error::UnprotectedData),
+                Tag::Marker => {
+                    packets.read_body()?;
+                }
+                Tag::LiteralData => break,
+                Tag::Signature => unsupported_err!(
+                    "signatures without one-pass signatures can not be verified while reading"
+                ),
+                _ => unsupported_err!("{:?} packets can not be verified while reading", tag),
+            }
+        }
+        ensure!(!pending.is_empty(), "message is not signed");
+
+        let mode = packets.read_u8()?;
+        let mode = DataMode::from_u8(mode).ok_or_else(|| format_err!("invalid data mode"))?;
+        let mut name = vec![0; usize::from(packets.read_u8()?)];
+        packets.read_exact(&mut name)?;
+        let mut created = [0u8; 4];
+        packets.read_exact(&mut created)?;
+
+        let mut metadata = vec![mode as u8, name.len() as u8];
+        metadata.extend_from_slice(&name);
+        metadata.extend_from_slice(&created);
+
+        Ok(VerifyingReader {
+            packets,
+            pending,
+            keyring,
+            policy,
+            file_name: String::from_utf8_lossy(&name).into_owned(),
+            mode,
+            metadata,
+            verified: None,
+        })
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn mode(&self) -> DataMode {
+        self.mode
+    }
+
+    /// Reads the rest of the literal data, if any, and returns the first valid signature.
+    pub fn verify(mut self) -> Result<VerificationResult> {
+        let copied = io::copy(&mut self, &mut io::sink());
+        if let Some(res) = self.verified.take() {
+            return res;
+        }
+        copied?;
+
+        bail!("the signatures were not checked")
+    }
+
+    /// Reads the signatures following the literal data, and checks them against the
+    /// hashed data.
+    fn check_signatures(&mut self) -> Result<VerificationResult> {
+        let mut signatures = Vec::new();
+        while let Some((version, tag)) = self.packets.next_header()? {
+            match tag {
+                Tag::Signature => {
+                    signatures.push(Signature::from_slice(version, &self.packets.read_body()?)?);
+                }
+                Tag::Marker => {
+                    self.packets.read_body()?;
+                }
+                _ => bail!("unexpected {:?} packet after the literal data", tag),
+            }
+        }
+        ensure_eq!(
+            signatures.len(),
+            self.pending.len(),
+            "signatures do not match the one-pass signatures"
+        );
+
+        // the signatures are stored in the reverse order of the one-pass signatures
+        let pending = std::mem::replace(&mut self.pending, Vec::new());
+        for (pending, sig) in pending.into_iter().rev().zip(&signatures) {
+            let ops = &pending.ops;
+            ensure!(
+                sig.typ() == ops.typ()
+                    && sig.config.hash_alg == ops.hash_algorithm()
+                    && sig.config.pub_alg == ops.pub_algorithm()
+                    && sig.salt() == ops.salt(),
+                "signature does not match its one-pass signature"
+            );
+            if let Err(err) = self.policy.check_signature(sig) {
+                warn!("skipping signature: {:?}", err);
+                continue;
+            }
+
+            let digest = sig.finish_hasher_with(pending.hasher, Some(&self.metadata))?;
+            for signer in signers(sig, self.keyring, self.policy) {
+                if let Err(err) = signer.check(sig, Some(self.policy)) {
+                    warn!("skipping invalid key: {:?}", err);
+                    continue;
+                }
+
+                let res = match signer.subkey {
+                    Some(subkey) => sig.verify_digest(&subkey.key, &digest),
+                    None => sig.verify_digest(&signer.key.primary_key, &digest),
+                };
+                match res {
+                    Ok(()) => return Ok(signer.result(sig)),
+                    Err(err) => warn!("invalid signature: {:?}", err),
+                }
+            }
+        }
+
+        bail!("no valid signature found")
+    }
+}
+
+impl<'a> Read for VerifyingReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.packets.read(buf)?;
+        for pending in &mut self.pending {
+            pending.update(&buf[..read]);
+        }
+
+        if read == 0 && !buf.is_empty() {
+            if self.verified.is_none() {
+                self.verified = Some(self.check_signatures());
+            }
+            if let Some(Err(err)) = &self.verified {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string()));
+            }
+        }
+
+        Ok(read)
+    }
+}
+
+/// Reads packets one after the other, without reading their bodies into memory.
+///
+/// After [`next_header`](PacketReader::next_header), reading returns the body of the packet,
+/// across partial body lengths.
+struct PacketReader<R: Read> {
+    inner: R,
+    /// Remaining length of the current chunk of the body.
+    remaining: usize,
+    /// Another chunk follows the current one.
+    partial: bool,
+    /// The body extends to the end of the input.
+    indeterminate: bool,
+}
+
+impl<R: Read> PacketReader<R> {
+    fn new(inner: R) -> Self {
+        PacketReader {
+            inner,
+            remaining: 0,
+            partial: false,
+            indeterminate: false,
+        }
+    }
+
+    /// Skips the rest of the current packet, and reads the header of the next one.
+    /// Returns `None` at the end of the input.
+    fn next_header(&mut self) -> Result<Option<(Version, Tag)>> {
+        io::copy(self, &mut io::sink())?;
+
+        let mut first = [0u8; 1];
+        if self.inner.read(&mut first)? == 0 {
+            return Ok(None);
+        }
+        let (version, tag, len) =
+            read_parsed(&mut self.inner, first.to_vec(), single::packet_header)?;
+        if let PacketLength::Partial(_) = len {
+            match tag {
+                Tag::LiteralData
+                | Tag::CompressedData
+                | Tag::SymEncryptedData
+                | Tag::SymEncryptedProtectedData => {}
+                _ => bail!("partial lengths are not allowed for {:?}", tag),
+            }
+        }
+        self.start_body(len);
+
+        Ok(Some((version, tag)))
+    }
+
+    fn start_body(&mut self, len: PacketLength) {
+        match len {
+            PacketLength::Fixed(len) => {
+                self.remaining = len;
+                self.partial = false;
+                self.indeterminate = false;
+            }
+            PacketLength::Partial(len) => {
+                self.remaining = len;
+                self.partial = true;
+                self.indeterminate = false;
+            }
+            PacketLength::Indeterminated => {
+                self.remaining = 0;
+                self.partial = false;
+                self.indeterminate = true;
+            }
+        }
+    }
+
+    /// Reads the body of a small packet into memory.
+    fn read_body(&mut self) -> Result<Vec<u8>> {
+        self.read_body_limited(MAX_PACKET_SIZE)
+    }
+
+    /// Reads the body of the packet into memory, failing if it is larger than `limit`.
+    fn read_body_limited(&mut self, limit: u64) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        self.by_ref().take(limit + 1).read_to_end(&mut body)?;
+        ensure!(
+            body.len() as u64 <= limit,
+            "packet too large to be read while streaming"
+        );
+
+        Ok(body)
+    }
+}
+
+impl<R: Read> Read for PacketReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            if self.indeterminate {
+                return self.inner.read(buf);
+            }
+            if self.remaining > 0 {
+                break;
+            }
+            if !self.partial {
+                return Ok(0);
+            }
+
+            let len = read_parsed(&mut self.inner, Vec::new(), single::partial_body_length)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+            self.start_body(len);
+        }
+
+        let len = cmp::min(buf.len(), self.remaining);
+        let read = self.inner.read(&mut buf[..len])?;
+        if read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "packet body is incomplete",
+            ));
+        }
+        self.remaining -= read;
+
+        Ok(read)
+    }
+}
+
+/// Reads from `r` one byte at a time, until `parser` accepts the bytes read so far,
+/// starting with `buf`. Only used for packet headers and lengths, which are a few bytes.
+fn read_parsed<T>(
+    r: &mut impl Read,
+    mut buf: Vec<u8>,
+    parser: impl Fn(&[u8]) -> IResult<&[u8], T>,
+) -> Result<T> {
+    loop {
+        match parser(&buf) {
+            Ok((_, parsed)) => return Ok(parsed),
+            Err(nom::Err::Incomplete(_)) => {}
+            Err(err) => return Err(err.into()),
+        }
+        buf.push(r.read_u8()?);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::test_keys::gen_key_with_subkeys;
+    use crate::composed::{Deserializable, Message};
+    use crate::crypto::SymmetricKeyAlgorithm;
+
+    fn gen_key() -> SignedSecretKey {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        gen_key_with_subkeys(&mut rng, "Me <me@mail.com>", 1)
+    }
+
+    #[test]
+    fn test_sign_stream() {
+        let key = gen_key();
+        let keyring = vec![key.signed_public_key()];
+        let policy = Policy::default();
+
+        // spans multiple partial body chunks
+        let data = b"a line of the release\n".repeat(2_000);
+        let mut signed = Vec::new();
+        sign_stream(
+            &mut signed,
+            &data[..],
+            "release.txt",
+            false,
+            &key,
+            || "".into(),
+            HashAlgorithm::SHA2_256,
+        )
+        .unwrap();
+
+        let mut reader = VerifyingReader::new(&signed[..], &keyring, &policy).unwrap();
+        assert_eq!(reader.file_name(), "release.txt");
+        assert_eq!(reader.mode(), DataMode::Binary);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+        let res = reader.verify().unwrap();
+        assert_eq!(res.signing_key_fingerprint, key.fingerprint());
+
+        // the message can also be parsed as a whole
+        let msg = Message::from_bytes(&signed[..]).unwrap();
+        msg.verify(&key.public_key()).unwrap();
+        assert_eq!(msg.get_content().unwrap(), Some(data.clone()));
+
+        // reading fails at the end of modified data
+        let mut modified = signed.clone();
+        modified[10_000] ^= 1;
+        let mut reader = VerifyingReader::new(&modified[..], &keyring, &policy).unwrap();
+        let mut read = Vec::new();
+        assert!(reader.read_to_end(&mut read).is_err());
+        assert!(reader.verify().is_err());
+
+        let reader = VerifyingReader::new(&signed[..], &[], &policy).unwrap();
+        assert!(reader.verify().is_err());
+        let truncated = &signed[..signed.len() - 10];
+        let reader = VerifyingReader::new(truncated, &keyring, &policy).unwrap();
+        assert!(reader.verify().is_err());
+    }
+
+    #[test]
+    fn test_sign_stream_text() {
+        let key = gen_key();
+        let keyring = vec![key.signed_public_key()];
+        let policy = Policy::default();
+
+        let mut signed = Vec::new();
+        sign_stream(
+            &mut signed,
+            &b"hello\nworld\r\n"[..],
+            "hello.txt",
+            true,
+            &key,
+            || "".into(),
+            HashAlgorithm::SHA2_256,
+        )
+        .unwrap();
+
+        let mut reader = VerifyingReader::new(&signed[..], &keyring, &policy).unwrap();
+        assert_eq!(reader.mode(), DataMode::Utf8);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, b"hello\r\nworld\r\n");
+        reader.verify().unwrap();
+
+        let msg = Message::from_bytes(&signed[..]).unwrap();
+        msg.verify(&key.public_key()).unwrap();
+    }
+
+    #[test]
+    fn test_verify_signed_message() {
+        let key = gen_key();
+        let keyring = vec![key.signed_public_key()];
+        let policy = Policy::default();
+
+        // a text signature over a literal with a definite length
+        let msg = Message::new_literal("hello.txt", "hello\nworld")
+            .sign(&key, || "".into(), HashAlgorithm::SHA2_256)
+            .unwrap();
+        let signed = msg.to_bytes().unwrap();
+
+        let mut reader = VerifyingReader::new(&signed[..], &keyring, &policy).unwrap();
+        assert_eq!(reader.mode(), DataMode::Utf8);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, b"hello\r\nworld");
+        reader.verify().unwrap();
+
+        // only messages with one-pass signatures can be verified in a single pass
+        let literal = Message::new_literal("hello.txt", "hello")
+            .to_bytes()
+            .unwrap();
+        assert!(VerifyingReader::new(&literal[..], &keyring, &policy).is_err());
+
+        // compressed messages are decompressed while reading
+        let compressed = Message::new_literal("hello.txt", "hello")
+            .sign(&key, || "".into(), HashAlgorithm::SHA2_256)
+            .unwrap()
+            .compress(CompressionAlgorithm::ZLIB)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        let reader = VerifyingReader::new(&compressed[..], &keyring, &policy).unwrap();
+        assert_eq!(reader.file_name(), "hello.txt");
+        reader.verify().unwrap();
+    }
+
+    #[test]
+    fn test_verify_encrypted_message() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key();
+        let keyring = vec![key.signed_public_key()];
+        let policy = Policy::default();
+
+        let encrypted = Message::new_literal("hello.txt", "hello")
+            .sign(&key, || "".into(), HashAlgorithm::SHA2_256)
+            .unwrap()
+            .compress(CompressionAlgorithm::ZIP)
+            .unwrap()
+            .encrypt_to_keys(
+                &mut rng,
+                SymmetricKeyAlgorithm::AES128,
+                &[&key.secret_subkeys[0].public_key()][..],
+            )
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+
+        assert!(VerifyingReader::new(&encrypted[..], &keyring, &policy).is_err());
+
+        let mut reader =
+            VerifyingReader::decrypt(&encrypted[..], || "".into(), &[&key], &keyring, &policy)
+                .unwrap();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, b"hello");
+        let res = reader.verify().unwrap();
+        assert_eq!(res.fingerprint, key.fingerprint());
+
+        // the integrity of the encrypted data is checked before anything is returned
+        let mut modified = encrypted.clone();
+        let len = modified.len();
+        modified[len - 5] ^= 1;
+        assert!(
+            VerifyingReader::decrypt(&modified[..], || "".into(), &[&key], &keyring, &policy)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use crate::crypto::{AeadAlgorithm, SymmetricKeyAlgorithm};
+use crate::errors::Result;
+use crate::packet::DataMode;
+use crate::types::{CompressionAlgorithm, KeyId};
+
+/// A single layer of a message, as returned by `Message::structure`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageLayer {
+    Encrypted {
+        /// Key ids of the public key encrypted session keys.
+        recipients: Vec<KeyId>,
+        /// Is there a password encrypted session key.
+        password: bool,
+        /// Is all encrypted data integrity protected.
+        protected: bool,
+        /// The symmetric algorithm of the data, if it is known before decrypting it.
+        ///
+        /// Version 2 SEIPD packets include it, for other packets it is part of the
+        /// encrypted session key.
+        sym_alg: Option<SymmetricKeyAlgorithm>,
+        /// The AEAD algorithm of version 2 SEIPD packets.
+        aead: Option<AeadAlgorithm>,
+    },
+    Compressed(CompressionAlgorithm),
+    Signed {
+        issuer: Option<KeyId>,
+        one_pass: bool,
+    },
+    Literal {
+        file_name: String,
+        mode: DataMode,
+    },
+}
+
+/// Policies for the order of the signature and encryption layers of a message, see
+/// [`Policy::structure`](crate::composed::Policy::structure).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructurePolicy {
+    /// Accept any structure.
+    Any,
+    /// Require a signature inside the encryption, rejecting unsigned messages and
+    /// signatures that wrap the encrypted data.
+    SignedThenEncrypted,
+}
+
+impl Default for StructurePolicy {
+    fn default() -> Self {
+        StructurePolicy::Any
+    }
+}
+
+impl StructurePolicy {
+    /// Checks the full structure of a message against this policy.
+    ///
+    /// For encrypted messages `layers` must include the layers of the decrypted message,
+    /// following the encrypted layer.
+    pub fn check(self, layers: &[MessageLayer]) -> Result<()> {
+        match self {
+            StructurePolicy::Any => Ok(()),
+            StructurePolicy::SignedThenEncrypted => {
+                let encrypted = layers.iter().position(|layer| match layer {
+                    MessageLayer::Encrypted { .. } => true,
+                    _ => false,
+                });
+                let is_signed = |layer: &MessageLayer| match layer {
+                    MessageLayer::Signed { .. } => true,
+                    _ => false,
+                };
+
+                match encrypted {
+                    Some(pos) => {
+                        ensure!(
+                            !layers[..pos].iter().any(is_signed),
+                            "signature outside of the encryption"
+                        );
+                        ensure!(
+                            layers[pos + 1..].iter().any(is_signed),
+                            "missing signature inside of the encryption"
+                        );
+                        Ok(())
+                    }
+                    None => bail!("message is not encrypted"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encrypted() -> MessageLayer {
+        MessageLayer::Encrypted {
+            recipients: Vec::new(),
+            password: true,
+            protected: true,
+            sym_alg: None,
+            aead: None,
+        }
+    }
+
+    fn signed() -> MessageLayer {
+        MessageLayer::Signed {
+            issuer: None,
+            one_pass: true,
+        }
+    }
+
+    fn literal() -> MessageLayer {
+        MessageLayer::Literal {
+            file_name: "".into(),
+            mode: DataMode::Binary,
+        }
+    }
+
+    #[test]
+    fn test_signed_then_encrypted() {
+        let policy = StructurePolicy::SignedThenEncrypted;
+
+        policy
+            .check(&[
+                encrypted(),
+                MessageLayer::Compressed(CompressionAlgorithm::ZLIB),
+                signed(),
+                literal(),
+            ])
+            .unwrap();
+
+        assert!(policy.check(&[signed(), encrypted()]).is_err());
+        assert!(policy.check(&[encrypted(), literal()]).is_err());
+        assert!(policy.check(&[signed(), literal()]).is_err());
+        assert!(policy
+            .check(&[signed(), encrypted(), signed(), literal()])
+            .is_err());
+
+        StructurePolicy::Any.check(&[signed(), literal()]).unwrap();
+    }
+}
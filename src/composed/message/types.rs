@@ -1,30 +1,33 @@
 use std::boxed::Box;
-use std::collections::BTreeMap;
 use std::io;
 
 use chrono::{self, SubsecRound};
-use flate2::write::{DeflateEncoder, ZlibEncoder};
-use flate2::Compression;
 use rand::{CryptoRng, Rng};
-use smallvec::SmallVec;
 use try_from::TryFrom;
 
-use crate::armor;
+use crate::armor::{self, Headers};
+use crate::audit::{AuditHook, Audited};
 use crate::composed::message::decrypt::*;
+use crate::composed::message::profile::EncryptionProfile;
+use crate::composed::message::session_key::SessionKey;
+use crate::composed::message::status::{StatusEvent, StatusObserver};
+use crate::composed::message::structure::MessageLayer;
+use crate::composed::message::verification::recipient_fingerprints;
 use crate::composed::shared::Deserializable;
-use crate::composed::signed_key::SignedSecretKey;
+use crate::composed::signed_key::{SignedPublicKey, SignedSecretKey};
 use crate::composed::StandaloneSignature;
-use crate::crypto::{HashAlgorithm, SymmetricKeyAlgorithm};
+use crate::crypto::{AeadAlgorithm, HashAlgorithm, SymmetricKeyAlgorithm};
 use crate::errors::{Error, Result};
 use crate::packet::{
-    write_packet, CompressedData, LiteralData, OnePassSignature, Packet,
-    PublicKeyEncryptedSessionKey, Signature, SignatureConfig, SignatureType, Subpacket,
-    SymEncryptedData, SymEncryptedProtectedData, SymKeyEncryptedSessionKey,
+    write_packet, CompressedData, CompressionBackend, Flate2Backend, LiteralData, OnePassSignature,
+    Packet, ProtectedDataConfig, PublicKeyEncryptedSessionKey, Signature, SignatureConfigBuilder,
+    SignatureType, SignatureVersion, Subpacket, SymEncryptedData, SymEncryptedProtectedData,
+    SymKeyEncryptedSessionKey, DEFAULT_CHUNK_SIZE, DEFAULT_MAX_DECOMPRESSED_SIZE,
 };
 use crate::ser::Serialize;
 use crate::types::{
-    CompressionAlgorithm, KeyId, KeyTrait, KeyVersion, PublicKeyTrait, SecretKeyTrait, StringToKey,
-    Tag,
+    Argon2Limits, Charset, CompressionAlgorithm, KeyId, KeyTrait, Mpi, PublicKeyTrait,
+    SecretKeyTrait, StringToKey, Tag,
 };
 
 /// An [OpenPGP message](https://tools.ietf.org/html/rfc4880.html#section-11.3)
@@ -203,46 +206,63 @@ impl Message {
         Message::Literal(LiteralData::from_str(file_name, data))
     }
 
+    /// Creates a literal text message, with the text encoded in `charset`.
+    /// The charset should be declared in the `Charset` armor header, see [`Charset::to_headers`].
+    pub fn new_literal_with_charset(file_name: &str, data: &str, charset: Charset) -> Result<Self> {
+        Ok(Message::Literal(LiteralData::from_str_with_charset(
+            file_name, data, charset,
+        )?))
+    }
+
     pub fn new_literal_bytes(file_name: &str, data: &[u8]) -> Self {
         Message::Literal(LiteralData::from_bytes(file_name, data))
     }
 
     /// Compresses the message.
     pub fn compress(&self, alg: CompressionAlgorithm) -> Result<Self> {
-        let data = match alg {
-            CompressionAlgorithm::Uncompressed => {
-                let mut data = Vec::new();
-                self.to_writer(&mut data)?;
-                data
-            }
-            CompressionAlgorithm::ZIP => {
-                let mut enc = DeflateEncoder::new(Vec::new(), Compression::default());
-                self.to_writer(&mut enc)?;
-                enc.finish()?
-            }
-            CompressionAlgorithm::ZLIB => {
-                let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
-                self.to_writer(&mut enc)?;
-                enc.finish()?
-            }
-            CompressionAlgorithm::BZip2 => unimplemented_err!("BZip2"),
-            CompressionAlgorithm::Private10 => unsupported_err!("Private10 should not be used"),
-        };
+        self.compress_with(&Flate2Backend, alg)
+    }
+
+    /// Compresses the message, using the given compression `backend`.
+    pub fn compress_with(
+        &self,
+        backend: &dyn CompressionBackend,
+        alg: CompressionAlgorithm,
+    ) -> Result<Self> {
+        let mut compressor = backend.compressor(alg)?;
+        self.to_writer(&mut compressor)?;
 
         Ok(Message::Compressed(CompressedData::from_compressed(
-            alg, data,
+            alg,
+            compressor.finish()?,
         )))
     }
 
     /// Decompresses the data if compressed.
+    ///
+    /// Fails if the decompressed data is larger than [`DEFAULT_MAX_DECOMPRESSED_SIZE`].
+    ///
+    /// [`DEFAULT_MAX_DECOMPRESSED_SIZE`]: crate::packet::DEFAULT_MAX_DECOMPRESSED_SIZE
     pub fn decompress(self) -> Result<Self> {
+        self.decompress_with(&Flate2Backend, DEFAULT_MAX_DECOMPRESSED_SIZE)
+    }
+
+    /// Decompresses the data if compressed, using the given compression `backend`.
+    ///
+    /// Fails if the decompressed data is larger than `limit` bytes.
+    pub fn decompress_with(self, backend: &dyn CompressionBackend, limit: u64) -> Result<Self> {
         match self {
-            Message::Compressed(data) => Message::from_bytes(data.decompress()?),
+            Message::Compressed(data) => Message::from_bytes(data.decompress_with(backend, limit)?),
             _ => Ok(self),
         }
     }
 
     /// Encrypt the message to the list of passed in public keys.
+    ///
+    /// The keys are used as they are, see [`encrypt_to_recipients`] for encrypting to
+    /// the encryption subkeys of verified certificates.
+    ///
+    /// [`encrypt_to_recipients`]: Message::encrypt_to_recipients
     pub fn encrypt_to_keys<R: CryptoRng + Rng>(
         &self,
         rng: &mut R,
@@ -253,17 +273,128 @@ impl Message {
         let session_key = alg.new_session_key(rng);
 
         // 2. Encrypt (pub) the session key, to each PublicKey.
-        let esk = pkeys
+        let esk = Self::encrypt_session_key(rng, &session_key, alg, pkeys)?;
+
+        // 3. Encrypt (sym) the data using the session key.
+        self.encrypt_symmetric(rng, esk, alg, None, session_key)
+    }
+
+    /// Same as [`encrypt_to_keys`](Message::encrypt_to_keys), but does not identify the
+    /// recipients, see [`PublicKeyEncryptedSessionKey::with_anonymous_recipient`].
+    pub fn encrypt_to_keys_anonymous<R: CryptoRng + Rng>(
+        &self,
+        rng: &mut R,
+        alg: SymmetricKeyAlgorithm,
+        pkeys: &[&impl PublicKeyTrait],
+    ) -> Result<Self> {
+        let session_key = alg.new_session_key(rng);
+        let esk = Self::anonymous_esk(Self::encrypt_session_key(rng, &session_key, alg, pkeys)?);
+
+        self.encrypt_symmetric(rng, esk, alg, None, session_key)
+    }
+
+    /// Encrypt the message to the list of passed in public keys, and additionally to
+    /// `self_key`, so the message stays readable by the sender.
+    /// `self_key` is skipped if it is already one of the recipients.
+    pub fn encrypt_to_keys_and_self<R: CryptoRng + Rng>(
+        &self,
+        rng: &mut R,
+        alg: SymmetricKeyAlgorithm,
+        pkeys: &[&impl PublicKeyTrait],
+        self_key: &impl PublicKeyTrait,
+    ) -> Result<Self> {
+        // 1. Generate a session key.
+        let session_key = alg.new_session_key(rng);
+
+        // 2. Encrypt (pub) the session key, to each PublicKey.
+        let mut esk = Self::encrypt_session_key(rng, &session_key, alg, pkeys)?;
+        if pkeys.iter().all(|pkey| pkey.key_id() != self_key.key_id()) {
+            esk.extend(Self::encrypt_session_key(
+                rng,
+                &session_key,
+                alg,
+                &[self_key],
+            )?);
+        }
+
+        // 3. Encrypt (sym) the data using the session key.
+        self.encrypt_symmetric(rng, esk, alg, None, session_key)
+    }
+
+    /// Encrypts the message to the given recipients, using the algorithms of `profile`.
+    ///
+    /// A single session key is encrypted to all currently usable encryption subkeys of
+    /// each recipient, see [`SignedPublicKey::encryption_subkeys`]. Fails if any of the
+    /// recipients does not verify, or has no usable subkey.
+    ///
+    /// AEAD encryption is only used, if the profile asks for it and all recipients
+    /// support it, see [`EncryptionProfile::settings_for`], which also fails for v6
+    /// recipients that would not get it.
+    ///
+    /// The result can be written in binary form, or armored using
+    /// [`to_armored_string`](Message::to_armored_string).
+    pub fn encrypt_to_recipients<R: CryptoRng + Rng>(
+        &self,
+        rng: &mut R,
+        recipients: &[&SignedPublicKey],
+        profile: EncryptionProfile,
+    ) -> Result<Self> {
+        ensure!(!recipients.is_empty(), "no recipients");
+
+        let now = chrono::Utc::now();
+        let mut subkeys = Vec::new();
+        for recipient in recipients {
+            recipient.verify()?;
+            subkeys.extend(recipient.encryption_subkeys(now)?);
+        }
+
+        let settings = profile.settings_for(recipients)?;
+        self.encrypt_to_keys_with_profile(rng, EncryptionProfile::Custom(settings), &subkeys)
+    }
+
+    pub(crate) fn encrypt_session_key<R: CryptoRng + Rng>(
+        rng: &mut R,
+        session_key: &[u8],
+        alg: SymmetricKeyAlgorithm,
+        pkeys: &[&impl PublicKeyTrait],
+    ) -> Result<Vec<Esk>> {
+        pkeys
             .iter()
             .map(|pkey| {
                 let pkes =
-                    PublicKeyEncryptedSessionKey::from_session_key(rng, &session_key, alg, pkey)?;
+                    PublicKeyEncryptedSessionKey::from_session_key(rng, session_key, alg, pkey)?;
                 Ok(Esk::PublicKeyEncryptedSessionKey(pkes))
             })
-            .collect::<Result<_>>()?;
+            .collect()
+    }
 
-        // 3. Encrypt (sym) the data using the session key.
-        self.encrypt_symmetric(rng, esk, alg, session_key)
+    /// Encrypts the session key to the given public keys, as version 6 packets for use
+    /// with version 2 SEIPD packets.
+    pub(crate) fn encrypt_session_key_v6<R: CryptoRng + Rng>(
+        rng: &mut R,
+        session_key: &[u8],
+        pkeys: &[&impl PublicKeyTrait],
+    ) -> Result<Vec<Esk>> {
+        pkeys
+            .iter()
+            .map(|pkey| {
+                let pkes =
+                    PublicKeyEncryptedSessionKey::from_session_key_v6(rng, session_key, pkey)?;
+                Ok(Esk::PublicKeyEncryptedSessionKey(pkes))
+            })
+            .collect()
+    }
+
+    /// Hides the recipients of the given public key encrypted session keys.
+    pub(crate) fn anonymous_esk(esk: Vec<Esk>) -> Vec<Esk> {
+        esk.into_iter()
+            .map(|esk| match esk {
+                Esk::PublicKeyEncryptedSessionKey(k) => {
+                    Esk::PublicKeyEncryptedSessionKey(k.with_anonymous_recipient())
+                }
+                esk => esk,
+            })
+            .collect()
     }
 
     /// Encrytp the message using the given password.
@@ -290,22 +421,35 @@ impl Message {
         )?);
 
         // 3. Encrypt (sym) the data using the session key.
-        self.encrypt_symmetric(rng, vec![skesk], alg, session_key)
+        self.encrypt_symmetric(rng, vec![skesk], alg, None, session_key)
     }
 
     /// Symmetrically encrypts oneself using the provided `session_key`.
-    fn encrypt_symmetric<R: CryptoRng + Rng>(
+    ///
+    /// With an `aead` algorithm, a version 2 SEIPD packet is created, otherwise
+    /// version 1.
+    pub(crate) fn encrypt_symmetric<R: CryptoRng + Rng>(
         &self,
         rng: &mut R,
         esk: Vec<Esk>,
         alg: SymmetricKeyAlgorithm,
+        aead: Option<AeadAlgorithm>,
         session_key: Vec<u8>,
     ) -> Result<Self> {
         let data = self.to_bytes()?;
 
-        let edata = vec![Edata::SymEncryptedProtectedData(
-            SymEncryptedProtectedData::encrypt_with_rng(rng, alg, &session_key, &data)?,
-        )];
+        let packet = match aead {
+            Some(aead) => SymEncryptedProtectedData::encrypt_aead_with_rng(
+                rng,
+                alg,
+                aead,
+                DEFAULT_CHUNK_SIZE,
+                &session_key,
+                &data,
+            )?,
+            None => SymEncryptedProtectedData::encrypt_with_rng(rng, alg, &session_key, &data)?,
+        };
+        let edata = vec![Edata::SymEncryptedProtectedData(packet)];
 
         Ok(Message::Encrypted { esk, edata })
     }
@@ -320,46 +464,21 @@ impl Message {
     where
         F: FnOnce() -> String,
     {
-        let key_id = key.key_id();
-        let algorithm = key.algorithm();
-        let hashed_subpackets = vec![
-            Subpacket::IssuerFingerprint(KeyVersion::V4, SmallVec::from_slice(&key.fingerprint())),
-            Subpacket::SignatureCreationTime(chrono::Utc::now().trunc_subsecs(0)),
-        ];
-        let unhashed_subpackets = vec![Subpacket::Issuer(key_id.clone())];
+        self.sign_with_subpackets(key, key_pw, hash_algorithm, Vec::new())
+    }
 
-        let (typ, signature) = match self {
-            Message::Literal(ref l) => {
-                let typ = if l.is_binary() {
-                    SignatureType::Binary
-                } else {
-                    SignatureType::Text
-                };
-
-                let signature_config = SignatureConfig::new_v4(
-                    Default::default(),
-                    typ,
-                    algorithm,
-                    hash_algorithm,
-                    hashed_subpackets,
-                    unhashed_subpackets,
-                );
-                (typ, signature_config.sign(key, key_pw, l.data())?)
-            }
-            _ => {
-                let typ = SignatureType::Binary;
-                let signature_config = SignatureConfig::new_v4(
-                    Default::default(),
-                    typ,
-                    algorithm,
-                    hash_algorithm,
-                    hashed_subpackets,
-                    unhashed_subpackets,
-                );
-                (typ, signature_config.sign(key, key_pw, &self.to_bytes()?)?)
-            }
-        };
-        let ops = OnePassSignature::from_details(typ, hash_algorithm, algorithm, key_id);
+    /// Sign this message, adding `subpackets` to the hashed area of the signature.
+    pub(crate) fn sign_with_subpackets<F>(
+        self,
+        key: &impl SecretKeyTrait,
+        key_pw: F,
+        hash_algorithm: HashAlgorithm,
+        subpackets: Vec<Subpacket>,
+    ) -> Result<Self>
+    where
+        F: FnOnce() -> String,
+    {
+        let (ops, signature) = self.create_signature(key, key_pw, hash_algorithm, subpackets)?;
 
         Ok(Message::Signed {
             message: Some(Box::new(self)),
@@ -368,6 +487,86 @@ impl Message {
         })
     }
 
+    /// Sign this message using all of the provided keys, for example when a release is
+    /// co-signed by multiple people.
+    ///
+    /// All signatures are made over the same data, with the one-pass signatures marking them
+    /// as not nested, like `gpg --sign` with multiple `--local-user`s. The first key makes
+    /// the outermost signature. `key_pw` returns the password of each key.
+    pub fn sign_with_keys<K, F>(
+        self,
+        keys: &[&K],
+        key_pw: F,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Self>
+    where
+        K: SecretKeyTrait,
+        F: Fn(&K) -> String,
+    {
+        ensure!(!keys.is_empty(), "no signing keys given");
+
+        let mut signatures = keys
+            .iter()
+            .map(|key| self.create_signature(*key, || key_pw(*key), hash_algorithm, Vec::new()))
+            .collect::<Result<Vec<_>>>()?;
+        // only the innermost one-pass signature is the last one
+        for (ops, _) in signatures.iter_mut().rev().skip(1) {
+            ops.set_last(false);
+        }
+
+        let mut message = self;
+        for (ops, signature) in signatures.into_iter().rev() {
+            message = Message::Signed {
+                message: Some(Box::new(message)),
+                one_pass_signature: Some(ops),
+                signature,
+            };
+        }
+
+        Ok(message)
+    }
+
+    /// Creates the signature of this message, and the matching one-pass signature.
+    fn create_signature<F>(
+        &self,
+        key: &impl SecretKeyTrait,
+        key_pw: F,
+        hash_algorithm: HashAlgorithm,
+        subpackets: Vec<Subpacket>,
+    ) -> Result<(OnePassSignature, Signature)>
+    where
+        F: FnOnce() -> String,
+    {
+        let mut hashed_subpackets = vec![Subpacket::SignatureCreationTime(
+            chrono::Utc::now().trunc_subsecs(0),
+        )];
+        hashed_subpackets.extend(subpackets);
+
+        let typ = match self {
+            Message::Literal(ref l) if !l.is_binary() => SignatureType::Text,
+            _ => SignatureType::Binary,
+        };
+        let mut signature_config = SignatureConfigBuilder::default()
+            .typ(typ)
+            .version(SignatureVersion::for_key(key))
+            .pub_alg(key.algorithm())
+            .hash_alg(hash_algorithm)
+            .hashed_subpackets(hashed_subpackets)
+            .unhashed_subpackets(vec![])
+            .build()?
+            .with_issuer(key);
+        // the one-pass signature of v6 keys includes the salt
+        signature_config.ensure_salt()?;
+        let ops = signature_config.one_pass_signature(key)?;
+
+        let signature = match self {
+            Message::Literal(ref l) => signature_config.sign(key, key_pw, l.data())?,
+            _ => signature_config.sign(key, key_pw, &self.to_bytes()?)?,
+        };
+
+        Ok((ops, signature))
+    }
+
     /// Convert the message to a standalone signature according to the cleartext framework.
     pub fn into_signature(self) -> StandaloneSignature {
         match self {
@@ -380,22 +579,44 @@ impl Message {
     /// For signed messages this verifies the signature and for compressed messages
     /// they are decompressed and checked for signatures to verify.
     pub fn verify(&self, key: &impl PublicKeyTrait) -> Result<()> {
+        self.verify_with_status(key, &mut |_| {})
+    }
+
+    /// Verify this message, reporting the outcome to the given observer.
+    pub fn verify_with_status(
+        &self,
+        key: &impl PublicKeyTrait,
+        observer: &mut impl StatusObserver,
+    ) -> Result<()> {
         match self {
             Message::Signed {
-                signature, message, ..
+                signature,
+                message,
+                one_pass_signature,
             } => {
                 if let Some(message) = message {
-                    match **message {
-                        Message::Literal(ref data) => signature.verify(key, data.data()),
-                        _ => signature.verify(key, &message.to_bytes()?),
+                    let res = match signed_content(message, one_pass_signature.as_ref()) {
+                        Message::Literal(data) => signature.verify_literal(key, data),
+                        message => signature.verify_serialized(key, message),
+                    };
+
+                    let issuer = signature.issuer().cloned().unwrap_or_else(|| key.key_id());
+                    match res {
+                        Ok(_) => observer.on_event(StatusEvent::GoodSig(issuer)),
+                        Err(Error::Unimplemented(_)) | Err(Error::Unsupported(_)) => {
+                            observer.on_event(StatusEvent::ErrSig(issuer))
+                        }
+                        Err(_) => observer.on_event(StatusEvent::BadSig(issuer)),
                     }
+
+                    res
                 } else {
                     unimplemented_err!("no message, what to do?");
                 }
             }
             Message::Compressed(data) => {
-                let msg = Message::from_bytes(data.decompress()?)?;
-                msg.verify(key)
+                let msg = Message::from_bytes(data.decompress_limited()?)?;
+                msg.verify_with_status(key, observer)
             }
             // Nothing to do for others.
             // TODO: should this return an error?
@@ -419,12 +640,65 @@ impl Message {
 
     /// Decrypt the message using the given key.
     /// Returns a message decrypter, and a list of [KeyId]s that are valid recipients of this message.
+    ///
+    /// Data without integrity protection is refused by the decrypter, unless
+    /// [`MessageDecrypter::allow_unprotected`] is set. A warning is logged for signed
+    /// messages, whose signatures list intended recipients that do not include the
+    /// decrypting key, [`decrypt_and_verify`] reports these.
+    ///
+    /// [`decrypt_and_verify`]: Message::decrypt_and_verify
     pub fn decrypt<'a, F, G>(
         &'a self,
         msg_pw: F, // TODO: remove
         key_pw: G,
         keys: &[&SignedSecretKey],
     ) -> Result<(MessageDecrypter<'a>, Vec<KeyId>)>
+    where
+        F: FnOnce() -> String + Clone,
+        G: FnOnce() -> String + Clone,
+    {
+        self.decrypt_with_status(msg_pw, key_pw, keys, &mut |_| {})
+    }
+
+    /// Decrypt the message using the given key, reporting progress to the given observer.
+    pub fn decrypt_with_status<'a, F, G>(
+        &'a self,
+        msg_pw: F, // TODO: remove
+        key_pw: G,
+        keys: &[&SignedSecretKey],
+        observer: &mut impl StatusObserver,
+    ) -> Result<(MessageDecrypter<'a>, Vec<KeyId>)>
+    where
+        F: FnOnce() -> String + Clone,
+        G: FnOnce() -> String + Clone,
+    {
+        self.decrypt_inner(msg_pw, key_pw, keys, observer, None)
+    }
+
+    /// Decrypt the message using the given key, reporting the use of the secret keys to
+    /// `hook`.
+    pub fn decrypt_audited<'a, F, G>(
+        &'a self,
+        msg_pw: F, // TODO: remove
+        key_pw: G,
+        keys: &[&SignedSecretKey],
+        hook: &dyn AuditHook,
+    ) -> Result<(MessageDecrypter<'a>, Vec<KeyId>)>
+    where
+        F: FnOnce() -> String + Clone,
+        G: FnOnce() -> String + Clone,
+    {
+        self.decrypt_inner(msg_pw, key_pw, keys, &mut |_| {}, Some(hook))
+    }
+
+    fn decrypt_inner<'a, F, G>(
+        &'a self,
+        msg_pw: F,
+        key_pw: G,
+        keys: &[&SignedSecretKey],
+        observer: &mut impl StatusObserver,
+        hook: Option<&dyn AuditHook>,
+    ) -> Result<(MessageDecrypter<'a>, Vec<KeyId>)>
     where
         F: FnOnce() -> String + Clone,
         G: FnOnce() -> String + Clone,
@@ -434,108 +708,18 @@ impl Message {
                 bail!("not encrypted");
             }
             Message::Signed { message, .. } => match message {
-                Some(message) => message.as_ref().decrypt(msg_pw, key_pw, keys),
+                Some(message) => message
+                    .as_ref()
+                    .decrypt_inner(msg_pw, key_pw, keys, observer, hook),
                 None => bail!("not encrypted"),
             },
             Message::Encrypted { esk, edata, .. } => {
-                let valid_keys = keys
-                    .iter()
-                    .filter_map(|key| {
-                        // search for a packet with a key id that we have and that key.
-                        let mut packet = None;
-                        let mut encoding_key = None;
-                        let mut encoding_subkey = None;
-
-                        for esk_packet in esk.iter().filter_map(|k| match k {
-                            Esk::PublicKeyEncryptedSessionKey(k) => Some(k),
-                            _ => None,
-                        }) {
-                            debug!("esk packet: {:?}", esk_packet);
-                            debug!("{:?}", key.key_id());
-                            debug!(
-                                "{:?}",
-                                key.secret_subkeys
-                                    .iter()
-                                    .map(KeyTrait::key_id)
-                                    .collect::<Vec<_>>()
-                            );
-
-                            // find the key with the matching key id
-
-                            if &key.primary_key.key_id() == esk_packet.id() {
-                                encoding_key = Some(&key.primary_key);
-                            }
-
-                            if encoding_key.is_none() {
-                                encoding_subkey = key.secret_subkeys.iter().find_map(|subkey| {
-                                    if &subkey.key_id() == esk_packet.id() {
-                                        Some(subkey)
-                                    } else {
-                                        None
-                                    }
-                                });
-                            }
-
-                            if encoding_key.is_some() || encoding_subkey.is_some() {
-                                packet = Some(esk_packet);
-                                break;
-                            }
-                        }
+                let (session_key, ids) = decrypt_esk(esk, edata, key_pw, keys, observer, hook)?;
+                let decrypter = session_key
+                    .decrypter(edata)
+                    .with_recipients(recipient_fingerprints(keys, &ids));
 
-                        if let Some(packet) = packet {
-                            Some((packet, encoding_key, encoding_subkey))
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<Vec<_>>();
-
-                if valid_keys.is_empty() {
-                    return Err(Error::MissingKey);
-                }
-
-                let session_keys = valid_keys
-                    .iter()
-                    .map(|(packet, encoding_key, encoding_subkey)| {
-                        if let Some(ek) = encoding_key {
-                            Ok((
-                                ek.key_id(),
-                                decrypt_session_key(ek, key_pw.clone(), packet.mpis())?,
-                            ))
-                        } else if let Some(ek) = encoding_subkey {
-                            Ok((
-                                ek.key_id(),
-                                decrypt_session_key(ek, key_pw.clone(), packet.mpis())?,
-                            ))
-                        } else {
-                            unreachable!("either a key or a subkey were found");
-                        }
-                    })
-                    .filter(|res| match res {
-                        Ok(_) => true,
-                        Err(err) => {
-                            warn!("failed to decrypt session_key for key: {:?}", err);
-                            false
-                        }
-                    })
-                    .collect::<Result<Vec<_>>>()?;
-
-                ensure!(!session_keys.is_empty(), "failed to decrypt session key");
-
-                // make sure all the keys are the same, otherwise we are in a bad place
-                let (session_key, alg) = {
-                    let k0 = &session_keys[0].1;
-                    if !session_keys.iter().skip(1).all(|(_, k)| k0 == k) {
-                        bail!("found inconsistent session keys, possible message corruption");
-                    }
-
-                    // TODO: avoid cloning
-                    (k0.0.clone(), k0.1)
-                };
-
-                let ids = session_keys.into_iter().map(|(k, _)| k).collect();
-
-                Ok((MessageDecrypter::new(session_key, alg, edata), ids))
+                Ok((decrypter, ids))
             }
         }
     }
@@ -543,6 +727,19 @@ impl Message {
     /// Decrypt the message using the given key.
     /// Returns a message decrypter, and a list of [KeyId]s that are valid recipients of this message.
     pub fn decrypt_with_password<'a, F>(&'a self, msg_pw: F) -> Result<MessageDecrypter<'a>>
+    where
+        F: FnOnce() -> String + Clone,
+    {
+        self.decrypt_with_password_and_limits(msg_pw, &Argon2Limits::default())
+    }
+
+    /// Same as [`decrypt_with_password`](Message::decrypt_with_password), but accepts
+    /// Argon2 string-to-keys up to `limits`, instead of the default [`Argon2Limits`].
+    pub fn decrypt_with_password_and_limits<'a, F>(
+        &'a self,
+        msg_pw: F,
+        limits: &Argon2Limits,
+    ) -> Result<MessageDecrypter<'a>>
     where
         F: FnOnce() -> String + Clone,
     {
@@ -551,7 +748,7 @@ impl Message {
                 bail!("not encrypted");
             }
             Message::Signed { message, .. } => match message {
-                Some(ref message) => message.decrypt_with_password(msg_pw),
+                Some(ref message) => message.decrypt_with_password_and_limits(msg_pw, limits),
                 None => bail!("not encrypted"),
             },
             Message::Encrypted { esk, edata, .. } => {
@@ -562,15 +759,79 @@ impl Message {
                 });
 
                 ensure!(skesk.is_some(), "message is not password protected");
+                let skesk = skesk.expect("checked above");
 
+                let alg = session_key_algorithm(skesk.version(), edata)?;
                 let (session_key, alg) =
-                    decrypt_session_key_with_password(skesk.expect("checked above"), msg_pw)?;
+                    decrypt_session_key_with_password(skesk, msg_pw, alg, limits)?;
 
                 Ok(MessageDecrypter::new(session_key, alg, edata))
             }
         }
     }
 
+    /// Describes the layers of this message, from the outermost to the innermost.
+    ///
+    /// The contents of an encrypted layer are not known before decryption, to get the full
+    /// structure append the structure of the decrypted message.
+    pub fn structure(&self) -> Result<Vec<MessageLayer>> {
+        let mut layers = Vec::new();
+        self.collect_structure(&mut layers)?;
+
+        Ok(layers)
+    }
+
+    fn collect_structure(&self, layers: &mut Vec<MessageLayer>) -> Result<()> {
+        match self {
+            Message::Literal(data) => layers.push(MessageLayer::Literal {
+                file_name: data.file_name().to_string(),
+                mode: data.mode(),
+            }),
+            Message::Compressed(data) => {
+                layers.push(MessageLayer::Compressed(data.compression_algorithm()));
+                let msg = Message::from_bytes(data.decompress_limited()?)?;
+                msg.collect_structure(layers)?;
+            }
+            Message::Signed {
+                message,
+                one_pass_signature,
+                signature,
+            } => {
+                layers.push(MessageLayer::Signed {
+                    issuer: signature.issuer().cloned(),
+                    one_pass: one_pass_signature.is_some(),
+                });
+                if let Some(message) = message {
+                    message.collect_structure(layers)?;
+                }
+            }
+            Message::Encrypted { esk, edata } => {
+                let algs = edata.iter().find_map(|edata| match edata {
+                    Edata::SymEncryptedProtectedData(packet) => match packet.config() {
+                        ProtectedDataConfig::V2 { sym_alg, aead, .. } => Some((*sym_alg, *aead)),
+                        ProtectedDataConfig::V1 => None,
+                    },
+                    Edata::SymEncryptedData(_) => None,
+                });
+
+                layers.push(MessageLayer::Encrypted {
+                    recipients: self.get_recipients().into_iter().cloned().collect(),
+                    password: esk.iter().any(|esk| match esk {
+                        Esk::SymKeyEncryptedSessionKey(_) => true,
+                        _ => false,
+                    }),
+                    protected: edata
+                        .iter()
+                        .all(|edata| edata.tag() == Tag::SymEncryptedProtectedData),
+                    sym_alg: algs.map(|(sym_alg, _)| sym_alg),
+                    aead: algs.map(|(_, aead)| aead),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
     /// Check if this message is a signature, that was signed with a one pass signature.
     pub fn is_one_pass_signed(&self) -> bool {
         match self {
@@ -618,7 +879,7 @@ impl Message {
                 .and_then(|m| m.get_literal())
                 .map(|l| l.data().to_vec())),
             Message::Compressed(data) => {
-                let msg = Message::from_bytes(data.decompress()?)?;
+                let msg = Message::from_bytes(data.decompress_limited()?)?;
                 msg.get_content()
             }
             Message::Encrypted { .. } => Ok(None),
@@ -628,12 +889,12 @@ impl Message {
     pub fn to_armored_writer(
         &self,
         writer: &mut impl io::Write,
-        headers: Option<&BTreeMap<String, String>>,
+        headers: Option<&Headers>,
     ) -> Result<()> {
         armor::write(self, armor::BlockType::Message, writer, headers)
     }
 
-    pub fn to_armored_bytes(&self, headers: Option<&BTreeMap<String, String>>) -> Result<Vec<u8>> {
+    pub fn to_armored_bytes(&self, headers: Option<&Headers>) -> Result<Vec<u8>> {
         let mut buf = Vec::new();
 
         self.to_armored_writer(&mut buf, headers)?;
@@ -641,14 +902,195 @@ impl Message {
         Ok(buf)
     }
 
-    pub fn to_armored_string(&self, headers: Option<&BTreeMap<String, String>>) -> Result<String> {
+    pub fn to_armored_string(&self, headers: Option<&Headers>) -> Result<String> {
         Ok(::std::str::from_utf8(&self.to_armored_bytes(headers)?)?.to_string())
     }
+
+    /// Armors the message in parts of at most `max_size` bytes, see [`armor::write_multipart`].
+    pub fn to_armored_parts(
+        &self,
+        max_size: usize,
+        headers: Option<&Headers>,
+    ) -> Result<Vec<Vec<u8>>> {
+        armor::write_multipart(self, max_size, headers)
+    }
+}
+
+/// Returns the message that a signature covers, given the `message` inside of the signed
+/// layer and its one-pass signature.
+///
+/// A one-pass signature that is not the last one marks a co-signature, which covers the
+/// same data as the signature after it, instead of the nested signed message.
+pub(crate) fn signed_content<'a>(
+    message: &'a Message,
+    one_pass_signature: Option<&OnePassSignature>,
+) -> &'a Message {
+    match (one_pass_signature, message) {
+        (
+            Some(ops),
+            Message::Signed {
+                message: Some(inner),
+                one_pass_signature: inner_ops,
+                ..
+            },
+        ) if !ops.is_last() => signed_content(inner, inner_ops.as_ref()),
+        _ => message,
+    }
+}
+
+/// Decrypts the session key from the public key encrypted session keys in `esk`, using
+/// one of `keys`. Returns the session key, and the ids of the keys that could decrypt it.
+///
+/// The encrypted data `edata` is needed for version 6 packets, see [`session_key_algorithm`].
+pub(crate) fn decrypt_esk<G>(
+    esk: &[Esk],
+    edata: &[Edata],
+    key_pw: G,
+    keys: &[&SignedSecretKey],
+    observer: &mut impl StatusObserver,
+    hook: Option<&dyn AuditHook>,
+) -> Result<(SessionKey, Vec<KeyId>)>
+where
+    G: FnOnce() -> String + Clone,
+{
+    let pkesks = esk
+        .iter()
+        .filter_map(|k| match k {
+            Esk::PublicKeyEncryptedSessionKey(k) => Some(k),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for packet in &pkesks {
+        observer.on_event(StatusEvent::EncTo(packet.id().clone()));
+    }
+
+    // Collect all (packet, key) pairs that could work. Anonymous recipients
+    // can match multiple keys, all of them are tried.
+    // Matching keys without secret material are only reported.
+    let mut valid_keys = Vec::new();
+    let mut stubs = Vec::new();
+    for key in keys {
+        debug!("{:?}", key.key_id());
+        debug!(
+            "{:?}",
+            key.secret_subkeys
+                .iter()
+                .map(KeyTrait::key_id)
+                .collect::<Vec<_>>()
+        );
+
+        for esk_packet in &pkesks {
+            debug!("esk packet: {:?}", esk_packet);
+
+            if esk_packet.matches(&key.primary_key) {
+                if key.primary_key.secret_params().is_stub() {
+                    stubs.push(key.primary_key.key_id());
+                } else {
+                    valid_keys.push((*esk_packet, Some(&key.primary_key), None));
+                }
+            }
+
+            for subkey in &key.secret_subkeys {
+                if esk_packet.matches(subkey) {
+                    if subkey.key.secret_params().is_stub() {
+                        stubs.push(subkey.key_id());
+                    } else {
+                        valid_keys.push((*esk_packet, None, Some(subkey)));
+                    }
+                }
+            }
+        }
+    }
+
+    for id in pkesks.iter().map(|packet| packet.id()) {
+        if !valid_keys.iter().any(|(packet, _, _)| packet.id() == id) {
+            observer.on_event(StatusEvent::NoSecKey(id.clone()));
+        }
+    }
+
+    if valid_keys.is_empty() {
+        observer.on_event(StatusEvent::DecryptionFailed);
+        return match stubs.into_iter().next() {
+            Some(key_id) => Err(Error::SecretKeyNotPresent { key_id }),
+            None => Err(Error::MissingKey),
+        };
+    }
+
+    observer.on_event(StatusEvent::BeginDecryption);
+
+    let session_keys = valid_keys
+        .iter()
+        .map(|(packet, encoding_key, encoding_subkey)| {
+            if packet.version() != 3 && packet.version() != 6 {
+                unsupported_err!("PKESK version {}", packet.version());
+            }
+            let alg = session_key_algorithm(packet.version(), edata)?;
+
+            if let Some(ek) = encoding_key {
+                Ok((
+                    ek.key_id(),
+                    decrypt_session_key_audited(ek, key_pw.clone(), packet.mpis(), alg, hook)?,
+                ))
+            } else if let Some(ek) = encoding_subkey {
+                Ok((
+                    ek.key_id(),
+                    decrypt_session_key_audited(ek, key_pw.clone(), packet.mpis(), alg, hook)?,
+                ))
+            } else {
+                unreachable!("either a key or a subkey were found");
+            }
+        })
+        .filter(|res| match res {
+            Ok(_) => true,
+            Err(err) => {
+                warn!("failed to decrypt session_key for key: {:?}", err);
+                false
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if session_keys.is_empty() {
+        observer.on_event(StatusEvent::DecryptionFailed);
+        bail!("failed to decrypt session key");
+    }
+
+    // make sure all the keys are the same, otherwise we are in a bad place
+    let session_key = {
+        let k0 = &session_keys[0].1;
+        if !session_keys.iter().skip(1).all(|(_, k)| k0 == k) {
+            bail!("found inconsistent session keys, possible message corruption");
+        }
+
+        // TODO: avoid cloning
+        SessionKey::new(k0.1, k0.0.clone())?
+    };
+
+    let ids = session_keys.into_iter().map(|(k, _)| k).collect();
+
+    Ok((session_key, ids))
+}
+
+fn decrypt_session_key_audited<K, G>(
+    key: &K,
+    key_pw: G,
+    mpis: &[Mpi],
+    alg: Option<SymmetricKeyAlgorithm>,
+    hook: Option<&dyn AuditHook>,
+) -> Result<(Vec<u8>, SymmetricKeyAlgorithm)>
+where
+    K: SecretKeyTrait + KeyTrait,
+    G: FnOnce() -> String,
+{
+    match hook {
+        Some(hook) => decrypt_session_key(&Audited::new(key, hook), key_pw, mpis, alg),
+        None => decrypt_session_key(key, key_pw, mpis, alg),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::packet::DataMode;
     use rand::thread_rng;
     use std::fs;
     use std::io::Cursor;
@@ -693,6 +1135,73 @@ mod tests {
         assert_eq!(&lit_msg, &uncompressed_msg);
     }
 
+    #[test]
+    fn test_compression_backend() {
+        use std::cell::Cell;
+        use std::io::Read;
+
+        use crate::packet::Compressor;
+
+        struct Counting(Cell<usize>);
+
+        impl CompressionBackend for Counting {
+            fn decompressor<'a>(
+                &self,
+                alg: CompressionAlgorithm,
+                data: &'a [u8],
+            ) -> Result<Box<dyn Read + 'a>> {
+                self.0.set(self.0.get() + 1);
+                Flate2Backend.decompressor(alg, data)
+            }
+
+            fn compressor(&self, alg: CompressionAlgorithm) -> Result<Box<dyn Compressor>> {
+                self.0.set(self.0.get() + 1);
+                Flate2Backend.compressor(alg)
+            }
+        }
+
+        let backend = Counting(Cell::new(0));
+        let lit_msg = Message::new_literal_bytes("big.bin", &vec![7u8; 1 << 20]);
+        let compressed_msg = lit_msg
+            .compress_with(&backend, CompressionAlgorithm::ZLIB)
+            .unwrap();
+        let uncompressed_msg = compressed_msg
+            .clone()
+            .decompress_with(&backend, DEFAULT_MAX_DECOMPRESSED_SIZE)
+            .unwrap();
+        assert_eq!(backend.0.get(), 2);
+        assert_eq!(lit_msg, uncompressed_msg);
+        assert_eq!(compressed_msg.clone().decompress().unwrap(), lit_msg);
+
+        // the decompressed data is limited
+        assert!(compressed_msg
+            .clone()
+            .decompress_with(&Flate2Backend, 1 << 16)
+            .is_err());
+
+        // the decompressed data can be read in small pieces
+        let data = match compressed_msg {
+            Message::Compressed(data) => data,
+            _ => panic!("not compressed"),
+        };
+        assert!(data.compressed_data().len() < 1 << 16);
+        let len = lit_msg.to_bytes().unwrap().len() as u64;
+        let mut reader = data.decompress_with(&Flate2Backend, len).unwrap();
+        let mut buf = [0u8; 4096];
+        let mut total = 0;
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            total += n;
+        }
+        assert_eq!(total as u64, len);
+
+        let mut reader = data.decompress_with(&Flate2Backend, len - 1).unwrap();
+        assert!(io::copy(&mut reader, &mut io::sink()).is_err());
+    }
+
     #[test]
     fn test_rsa_encryption() {
         use rand::SeedableRng;
@@ -736,6 +1245,110 @@ mod tests {
         assert_eq!(compressed_msg, decrypted);
     }
 
+    #[test]
+    fn test_encrypt_to_recipients() {
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        use crate::composed::test_keys::gen_key_with_subkeys;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key_with_subkeys(&mut rng, "Alice <alice@mail.com>", 1);
+        let bob = gen_key_with_subkeys(&mut rng, "Bob <bob@mail.com>", 2);
+        let eve = gen_key_with_subkeys(&mut rng, "Eve <eve@mail.com>", 0);
+        let recipients = [&alice.signed_public_key(), &bob.signed_public_key()];
+
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let encrypted = msg
+            .encrypt_to_recipients(&mut rng, &recipients, EncryptionProfile::Modern)
+            .unwrap();
+        let expected: Vec<_> = alice
+            .secret_subkeys
+            .iter()
+            .chain(&bob.secret_subkeys)
+            .map(KeyTrait::key_id)
+            .collect();
+        assert_eq!(
+            encrypted.get_recipients(),
+            expected.iter().collect::<Vec<_>>()
+        );
+
+        // the armored message can be decrypted by every recipient
+        let armor = encrypted.to_armored_string(None).unwrap();
+        let (encrypted, _) = Message::from_string(&armor).unwrap();
+        for key in &[&alice, &bob] {
+            let (mut decrypter, ids) = encrypted
+                .decrypt(|| "".into(), || "".into(), &[*key])
+                .unwrap();
+            assert!(!ids.is_empty());
+            assert_eq!(decrypter.next().unwrap().unwrap(), msg);
+        }
+        assert!(encrypted
+            .decrypt(|| "".into(), || "".into(), &[&eve])
+            .is_err());
+
+        let recipients = [&alice.signed_public_key(), &eve.signed_public_key()];
+        match msg.encrypt_to_recipients(&mut rng, &recipients, EncryptionProfile::Modern) {
+            Err(Error::NoEncryptionKey { key_id, .. }) => assert_eq!(key_id, eve.key_id()),
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(msg
+            .encrypt_to_recipients(&mut rng, &[], EncryptionProfile::Modern)
+            .is_err());
+
+        // recipients with broken self-signatures are refused
+        let mut tampered = bob.signed_public_key();
+        tampered.details.users[0].signatures[0]
+            .config
+            .hashed_subpackets
+            .push(Subpacket::IsPrimary(true));
+        assert!(msg
+            .encrypt_to_recipients(&mut rng, &[&tampered], EncryptionProfile::Modern)
+            .is_err());
+    }
+
+    #[test]
+    fn test_anonymous_recipient() {
+        let (skey, _headers) = SignedSecretKey::from_armor_single(
+            fs::File::open("./tests/autocrypt/alice@autocrypt.example.sec.asc").unwrap(),
+        )
+        .unwrap();
+
+        let pkey = skey.secret_subkeys[0].public_key();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(100);
+
+        let lit_msg = Message::new_literal("hello.txt", "hello world\n");
+        let mut encrypted = lit_msg
+            .encrypt_to_keys(&mut rng, SymmetricKeyAlgorithm::AES128, &[&pkey][..])
+            .unwrap();
+
+        // replace the recipient key id with the wildcard id
+        if let Message::Encrypted { ref mut esk, .. } = encrypted {
+            let pkesk = match &esk[0] {
+                Esk::PublicKeyEncryptedSessionKey(k) => k.clone(),
+                _ => panic!("expected a pkesk"),
+            };
+            let mut bytes = pkesk.to_bytes().unwrap();
+            for b in &mut bytes[1..9] {
+                *b = 0;
+            }
+            let pkesk =
+                PublicKeyEncryptedSessionKey::from_slice(pkesk.packet_version(), &bytes).unwrap();
+            assert!(pkesk.is_anonymous());
+            esk[0] = Esk::PublicKeyEncryptedSessionKey(pkesk);
+        } else {
+            panic!("expected an encrypted message");
+        }
+
+        let (decrypter, ids) = encrypted
+            .decrypt(|| "".into(), || "".into(), &[&skey])
+            .unwrap();
+        assert_eq!(ids, vec![skey.secret_subkeys[0].key_id()]);
+
+        let decrypted = decrypter.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(decrypted, vec![lit_msg]);
+    }
+
     #[test]
     fn test_x25519_encryption() {
         let (skey, _headers) = SignedSecretKey::from_armor_single(
@@ -771,6 +1384,189 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_rfc9580_curves() {
+        use crate::composed::{KeyType, SecretKeyParamsBuilder, SubkeyParamsBuilder};
+        use crate::types::KeyVersion;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let curves = [
+            (KeyType::Ed25519, KeyType::X25519, HashAlgorithm::SHA2_256),
+            (KeyType::Ed448, KeyType::X448, HashAlgorithm::SHA2_512),
+        ];
+        let versions = [KeyVersion::V4, KeyVersion::V6];
+        for &(sign_type, encrypt_type, hash) in &curves {
+            for version in &versions {
+                let skey = SecretKeyParamsBuilder::default()
+                    .key_type(sign_type)
+                    .version(*version)
+                    .can_create_certificates(true)
+                    .can_sign(true)
+                    .primary_user_id("Me <me@mail.com>".into())
+                    .subkey(
+                        SubkeyParamsBuilder::default()
+                            .key_type(encrypt_type)
+                            .version(*version)
+                            .can_encrypt(true)
+                            .build()
+                            .unwrap(),
+                    )
+                    .build()
+                    .unwrap()
+                    .generate_with_rng(&mut rng)
+                    .unwrap()
+                    .sign_with_rng(&mut rng, || "".into())
+                    .unwrap();
+
+                let pkey = skey.signed_public_key();
+                let lit_msg = Message::new_literal("hello.txt", "hello world\n");
+                let signed_msg = lit_msg.sign(&skey, || "".into(), hash).unwrap();
+                let armored = signed_msg.to_armored_string(None).unwrap();
+                let parsed = Message::from_string(&armored).unwrap().0;
+                parsed.verify(&pkey).unwrap();
+
+                // v6 recipients get a v6 session key, v4 ones a v3 one
+                let encrypted = parsed
+                    .encrypt_to_recipients(&mut rng, &[&pkey], EncryptionProfile::Modern)
+                    .unwrap();
+                let armored = encrypted.to_armored_bytes(None).unwrap();
+                let parsed = Message::from_armor_single(Cursor::new(&armored)).unwrap().0;
+
+                let decrypted = parsed
+                    .decrypt(|| "".into(), || "".into(), &[&skey])
+                    .unwrap()
+                    .0
+                    .next()
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(decrypted, signed_msg);
+                decrypted.verify(&pkey).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_message() {
+        use std::io::Read;
+
+        let (skey, _headers) = SignedSecretKey::from_armor_single(
+            fs::File::open("./tests/autocrypt/alice@autocrypt.example.sec.asc").unwrap(),
+        )
+        .unwrap();
+        let pkey = skey.secret_subkeys[0].public_key();
+        let mut rng = thread_rng();
+
+        // empty literals are valid, in all modes
+        for lit_msg in &[
+            Message::new_literal("", ""),
+            Message::new_literal_bytes("empty.bin", &[]),
+        ] {
+            assert_eq!(lit_msg.get_content().unwrap(), Some(Vec::new()));
+            let bytes = lit_msg.to_bytes().unwrap();
+            assert_eq!(&Message::from_bytes_strict(&bytes[..]).unwrap(), lit_msg);
+            let armored = lit_msg.to_armored_string(None).unwrap();
+            assert_eq!(&Message::from_string(&armored).unwrap().0, lit_msg);
+
+            // signatures over empty data are valid
+            let signed = lit_msg
+                .clone()
+                .sign(&skey, || "".into(), HashAlgorithm::SHA2_256)
+                .unwrap();
+            let parsed = Message::from_bytes(&signed.to_bytes().unwrap()[..]).unwrap();
+            parsed.verify(&skey).unwrap();
+            assert_eq!(parsed.get_content().unwrap(), Some(Vec::new()));
+
+            let compressed = signed.compress(CompressionAlgorithm::ZIP).unwrap();
+            let encrypted = compressed
+                .encrypt_to_keys(&mut rng, SymmetricKeyAlgorithm::AES128, &[&pkey][..])
+                .unwrap();
+            let decrypted = encrypted
+                .decrypt(|| "".into(), || "".into(), &[&skey])
+                .unwrap()
+                .0
+                .next()
+                .unwrap()
+                .unwrap();
+            assert_eq!(decrypted, compressed);
+            assert_eq!(decrypted.get_content().unwrap(), Some(Vec::new()));
+            decrypted.decompress().unwrap().verify(&skey).unwrap();
+        }
+
+        let signature = Message::new_literal_bytes("", &[])
+            .sign(&skey, || "".into(), HashAlgorithm::SHA2_256)
+            .unwrap()
+            .into_signature();
+        signature.verify(&skey, &[]).unwrap();
+        assert!(signature.verify(&skey, b"\0").is_err());
+
+        // no input is not a message
+        match Message::from_bytes(&b""[..]) {
+            Err(Error::NoMatchingPacket) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        // neither is a compressed packet without content, but the packet itself is valid
+        let empty = CompressedData::from_slice(Default::default(), &[0]).unwrap();
+        assert!(empty
+            .decompress()
+            .unwrap()
+            .read_to_end(&mut Vec::new())
+            .is_ok());
+        match Message::Compressed(empty).decompress() {
+            Err(Error::NoMatchingPacket) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_to_self() {
+        let (skey, _headers) = SignedSecretKey::from_armor_single(
+            fs::File::open("./tests/autocrypt/alice@autocrypt.example.sec.asc").unwrap(),
+        )
+        .unwrap();
+        let (other_skey, _headers) = SignedSecretKey::from_armor_single(
+            fs::File::open("./tests/openpgpjs/x25519.sec.asc").unwrap(),
+        )
+        .unwrap();
+
+        let self_key = skey.secret_subkeys[0].public_key();
+        let pkey = other_skey.secret_subkeys[0].public_key();
+        let mut rng = thread_rng();
+
+        let lit_msg = Message::new_literal("hello.txt", "hello world\n");
+        let encrypted = lit_msg
+            .encrypt_to_keys_and_self(
+                &mut rng,
+                SymmetricKeyAlgorithm::AES128,
+                &[&pkey][..],
+                &self_key,
+            )
+            .unwrap();
+        assert_eq!(encrypted.get_recipients().len(), 2);
+
+        let decrypted = encrypted
+            .decrypt(|| "".into(), || "".into(), &[&skey])
+            .unwrap()
+            .0
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(lit_msg, decrypted);
+
+        // already a recipient
+        let encrypted = lit_msg
+            .encrypt_to_keys_and_self(
+                &mut rng,
+                SymmetricKeyAlgorithm::AES128,
+                &[&self_key][..],
+                &self_key,
+            )
+            .unwrap();
+        assert_eq!(encrypted.get_recipients().len(), 1);
+    }
+
     #[test]
     fn test_password_encryption() {
         let _ = pretty_env_logger::try_init();
@@ -803,6 +1599,72 @@ mod tests {
         assert_eq!(compressed_msg, decrypted);
     }
 
+    #[test]
+    fn test_encryption_deterministic() {
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        let lit_msg = Message::new_literal("hello.txt", "hello world\n");
+        let encrypt = |seed| {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let s2k = StringToKey::new_default(&mut rng);
+            lit_msg
+                .encrypt_with_password(&mut rng, s2k, SymmetricKeyAlgorithm::AES128, || {
+                    "secret".into()
+                })
+                .unwrap()
+        };
+
+        // the s2k salt, session key and IV all come from the rng
+        assert_eq!(encrypt(1), encrypt(1));
+        assert_ne!(encrypt(1), encrypt(2));
+    }
+
+    #[test]
+    fn test_structure() {
+        let mut rng = thread_rng();
+
+        let lit_msg = Message::new_literal("hello.txt", "hello world\n");
+        let compressed_msg = lit_msg.compress(CompressionAlgorithm::ZLIB).unwrap();
+        let encrypted = compressed_msg
+            .encrypt_with_password(
+                &mut rng,
+                StringToKey::new_default(&mut rng),
+                SymmetricKeyAlgorithm::AES128,
+                || "secret".into(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            encrypted.structure().unwrap(),
+            vec![MessageLayer::Encrypted {
+                recipients: Vec::new(),
+                password: true,
+                protected: true,
+                sym_alg: None,
+                aead: None,
+            }]
+        );
+
+        let decrypted = encrypted
+            .decrypt_with_password(|| "secret".into())
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            decrypted.structure().unwrap(),
+            vec![
+                MessageLayer::Compressed(CompressionAlgorithm::ZLIB),
+                MessageLayer::Literal {
+                    file_name: "hello.txt".into(),
+                    mode: DataMode::Utf8,
+                },
+            ]
+        );
+    }
+
     #[test]
     fn test_x25519_signing_string() {
         let (skey, _headers) = SignedSecretKey::from_armor_single(
@@ -945,4 +1807,116 @@ mod tests {
         let parsed = Message::from_armor_single(Cursor::new(&armored)).unwrap().0;
         parsed.verify(&pkey).unwrap();
     }
+
+    #[test]
+    fn test_literal_charset_header() {
+        let text = "Grüße\n";
+        let msg = Message::new_literal_with_charset("", text, Charset::Latin1).unwrap();
+
+        let mut headers = Headers::new();
+        Charset::Latin1.to_headers(&mut headers);
+        let armored = msg.to_armored_bytes(Some(&headers)).unwrap();
+
+        let (parsed, headers) = Message::from_armor_single(Cursor::new(&armored)).unwrap();
+        let charset = Charset::from_headers(&headers).unwrap().unwrap();
+        assert_eq!(charset, Charset::Latin1);
+
+        let literal = parsed.get_literal().unwrap();
+        assert_eq!(literal.mode(), DataMode::Text);
+        assert_eq!(literal.data(), b"Gr\xfc\xdfe\r\n");
+        assert_eq!(literal.to_string(), None);
+        assert_eq!(
+            literal.to_string_with_charset(charset).unwrap(),
+            "Grüße\r\n"
+        );
+    }
+
+    #[test]
+    fn test_concatenated_messages() {
+        let first = Message::new_literal("a.txt", "first");
+        let second = Message::new_literal("b.txt", "second");
+
+        let mut bytes = first.to_bytes().unwrap();
+        assert_eq!(Message::from_bytes_strict(&bytes[..]).unwrap(), first);
+        bytes.extend(second.to_bytes().unwrap());
+
+        // the lenient parser ignores everything after the first message
+        assert_eq!(Message::from_bytes(&bytes[..]).unwrap(), first);
+        let messages = Message::from_bytes_many(&bytes[..])
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(messages, vec![first.clone(), second]);
+        assert!(Message::from_bytes_strict(&bytes[..]).is_err());
+
+        let mut bytes = first.to_bytes().unwrap();
+        bytes.extend_from_slice(b"garbage");
+        assert_eq!(Message::from_bytes(&bytes[..]).unwrap(), first);
+        assert!(Message::from_bytes_strict(&bytes[..]).is_err());
+
+        let armored = first.to_armored_string(None).unwrap();
+        let (parsed, _) = Message::from_string_strict(&armored).unwrap();
+        assert_eq!(parsed, first);
+    }
+
+    #[test]
+    fn test_sign_with_subkey() {
+        use crate::composed::test_keys::gen_key_with_signing_subkey;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let skey = gen_key_with_signing_subkey(&mut rng, "Me <me@mail.com>");
+
+        let subkey = skey.signing_subkey().expect("missing signing subkey");
+        assert_eq!(subkey.key_id(), skey.secret_subkeys[0].key_id());
+
+        let signed_msg = Message::new_literal("hello.txt", "hello world\n")
+            .sign(subkey, || "".into(), HashAlgorithm::SHA2_256)
+            .unwrap();
+
+        let signature = match signed_msg {
+            Message::Signed { ref signature, .. } => signature,
+            _ => panic!("not signed"),
+        };
+        assert_eq!(signature.issuer(), Some(&subkey.key_id()));
+        assert!(signature.is_issued_by(subkey));
+        assert!(!signature.is_issued_by(&skey));
+
+        signed_msg.verify(&subkey.key).unwrap();
+        assert!(signed_msg.verify(&skey.primary_key).is_err());
+    }
+
+    #[test]
+    fn test_sign_with_v6_key() {
+        use crate::composed::test_keys::gen_key_v6;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let skey = gen_key_v6(&mut rng, Some("Me <me@mail.com>"));
+        let pkey = skey.public_key();
+
+        let signed_msg = Message::new_literal("hello.txt", "hello world\n")
+            .sign(&skey, || "".into(), HashAlgorithm::SHA2_256)
+            .unwrap();
+
+        match signed_msg {
+            Message::Signed {
+                one_pass_signature: Some(ref ops),
+                ref signature,
+                ..
+            } => {
+                assert_eq!(ops.version(), 6);
+                assert_eq!(ops.salt(), signature.salt());
+                assert_eq!(ops.fingerprint(), Some(&skey.fingerprint()[..]));
+            }
+            _ => panic!("missing one-pass signature"),
+        }
+        signed_msg.verify(&pkey).unwrap();
+
+        let armored = signed_msg.to_armored_string(None).unwrap();
+        let parsed = Message::from_string(&armored).unwrap().0;
+        assert_eq!(parsed, signed_msg);
+        parsed.verify(&pkey).unwrap();
+    }
 }
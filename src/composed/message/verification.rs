@@ -0,0 +1,657 @@
+use chrono::{DateTime, Utc};
+
+use crate::composed::message::types::signed_content;
+use crate::composed::message::{Message, StatusEvent, StatusObserver};
+use crate::composed::signature::signers;
+use crate::composed::{Deserializable, Policy, SignedPublicKey, SignedSecretKey};
+use crate::errors::Result;
+use crate::packet::Signature;
+use crate::types::{KeyId, KeyTrait, PublicKeyTrait};
+
+/// The outcome of checking a single signature, see [`SignatureVerification`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureValidity {
+    /// The signature was made by one of the senders, and is valid.
+    Valid,
+    /// The signature was issued by one of the senders, but it is not valid.
+    Invalid,
+    /// None of the senders issued the signature.
+    UnknownKey,
+    /// The signature is valid, but it lists intended recipients, and the decrypting key
+    /// is not one of them. The sender did not encrypt the message to us, it might have
+    /// been forwarded surreptitiously by one of the actual recipients.
+    UnintendedRecipient,
+}
+
+/// A signature found inside a message, see [`Message::decrypt_and_verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureVerification {
+    /// Fingerprint of the primary key of the sender that issued the signature,
+    /// `None` if it was not issued by any of the senders.
+    pub fingerprint: Option<Vec<u8>>,
+    /// Key id of the key that made the signature, as stated by the signature.
+    pub key_id: Option<KeyId>,
+    /// Creation time of the signature.
+    pub created: Option<DateTime<Utc>>,
+    pub validity: SignatureValidity,
+}
+
+/// A decrypted message, together with the results of checking its signatures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecryptedMessage {
+    /// The decrypted message, decompressed if it was compressed.
+    pub message: Message,
+    /// The signatures of the message, from the outermost to the innermost.
+    pub signatures: Vec<SignatureVerification>,
+}
+
+impl DecryptedMessage {
+    /// Returns `true` if the message carries at least one valid signature.
+    pub fn is_signed(&self) -> bool {
+        self.signatures
+            .iter()
+            .any(|sig| sig.validity == SignatureValidity::Valid)
+    }
+}
+
+impl Message {
+    /// Decrypts the message using one of `keys`, and checks all signatures inside of the
+    /// encryption against the keys of `senders`, which may be empty.
+    ///
+    /// Invalid signatures, and signatures by unknown keys, do not fail the decryption,
+    /// they are reported in [`DecryptedMessage::signatures`]. Senders whose self-signatures
+    /// are not valid, or that were revoked or expired when they made a signature, are
+    /// ignored. Signatures listing intended recipients, that do not
+    /// include the decrypting key, are reported as
+    /// [`UnintendedRecipient`](SignatureValidity::UnintendedRecipient).
+    ///
+    /// The encrypted data has to contain exactly one message, decryption fails if more
+    /// messages follow it, so none of them goes unchecked.
+    pub fn decrypt_and_verify<G>(
+        &self,
+        key_pw: G,
+        keys: &[&SignedSecretKey],
+        senders: &[&SignedPublicKey],
+    ) -> Result<DecryptedMessage>
+    where
+        G: FnOnce() -> String + Clone,
+    {
+        self.decrypt_and_verify_with_policy(key_pw, keys, senders, &Policy::default())
+    }
+
+    /// Like [`decrypt_and_verify`], but checks the signatures, and the order of the
+    /// signature and encryption layers of the message, against `policy`.
+    ///
+    /// [`decrypt_and_verify`]: Message::decrypt_and_verify
+    pub fn decrypt_and_verify_with_policy<G>(
+        &self,
+        key_pw: G,
+        keys: &[&SignedSecretKey],
+        senders: &[&SignedPublicKey],
+        policy: &Policy,
+    ) -> Result<DecryptedMessage>
+    where
+        G: FnOnce() -> String + Clone,
+    {
+        let (decrypter, ids) = self.decrypt(|| "".into(), key_pw, keys)?;
+        let recipients = recipient_fingerprints(keys, &ids);
+        let mut messages = decrypter.collect::<Result<Vec<_>>>()?;
+        ensure!(messages.len() <= 1, "more than one encrypted message found");
+        let message = match messages.pop() {
+            Some(message) => message,
+            None => bail!("no encrypted data found"),
+        };
+
+        let mut layers = self.structure()?;
+        layers.extend(message.structure()?);
+        policy.structure.check(&layers)?;
+
+        let message = message.decompress()?;
+        let mut signatures = Vec::new();
+        collect_signatures(
+            &message,
+            senders,
+            &recipients,
+            policy,
+            &mut |_| {},
+            &mut signatures,
+        )?;
+
+        Ok(DecryptedMessage {
+            message,
+            signatures,
+        })
+    }
+
+    /// Checks all signatures of the message against the keys of `senders`, and returns the
+    /// result for each of them, from the outermost to the innermost.
+    ///
+    /// This is useful for messages that are signed by multiple keys, see
+    /// [`Message::sign_with_keys`]. Like for [`decrypt_and_verify`], invalid signatures and
+    /// signatures by unknown keys are reported, and do not fail the verification.
+    ///
+    /// [`decrypt_and_verify`]: Message::decrypt_and_verify
+    pub fn verify_signatures(
+        &self,
+        senders: &[&SignedPublicKey],
+    ) -> Result<Vec<SignatureVerification>> {
+        self.verify_signatures_with_status(senders, &mut |_| {})
+    }
+
+    /// Same as [`Message::verify_signatures`], but also reports the outcome for each
+    /// signature to the given observer.
+    ///
+    /// Senders, that had expired when they made a signature, are reported as
+    /// [`StatusEvent::KeyExpired`].
+    pub fn verify_signatures_with_status(
+        &self,
+        senders: &[&SignedPublicKey],
+        observer: &mut impl StatusObserver,
+    ) -> Result<Vec<SignatureVerification>> {
+        let mut signatures = Vec::new();
+        collect_signatures(
+            self,
+            senders,
+            &[],
+            &Policy::default(),
+            observer,
+            &mut signatures,
+        )?;
+
+        Ok(signatures)
+    }
+}
+
+/// Checks the signatures of `msg`. `recipients` are the primary key fingerprints of the
+/// keys the message was decrypted with, empty if it was not decrypted.
+fn collect_signatures(
+    msg: &Message,
+    senders: &[&SignedPublicKey],
+    recipients: &[Vec<u8>],
+    policy: &Policy,
+    observer: &mut impl StatusObserver,
+    signatures: &mut Vec<SignatureVerification>,
+) -> Result<()> {
+    match msg {
+        Message::Signed {
+            message: Some(message),
+            signature,
+            one_pass_signature,
+        } => {
+            let content = signed_content(message, one_pass_signature.as_ref());
+            signatures.push(check_signature(
+                signature, content, senders, recipients, policy, observer,
+            ));
+            collect_signatures(message, senders, recipients, policy, observer, signatures)
+        }
+        Message::Compressed(data) => {
+            let msg = Message::from_bytes(data.decompress_limited()?)?;
+            collect_signatures(&msg, senders, recipients, policy, observer, signatures)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_signature(
+    signature: &Signature,
+    message: &Message,
+    senders: &[&SignedPublicKey],
+    recipients: &[Vec<u8>],
+    policy: &Policy,
+    observer: &mut impl StatusObserver,
+) -> SignatureVerification {
+    let mut fingerprint = None;
+    let mut validity = SignatureValidity::UnknownKey;
+    let mut key_id = signature.issuer().cloned();
+    for signer in signers(signature, senders, policy) {
+        if let Err(err) = signer.check(signature, Some(policy)) {
+            warn!("skipping invalid key: {:?}", err);
+            if let Some((id, expired)) = signer.expired(signature) {
+                observer.on_event(StatusEvent::KeyExpired(id, expired));
+            }
+            continue;
+        }
+
+        fingerprint = Some(signer.key.fingerprint());
+        if key_id.is_none() {
+            key_id = Some(match signer.subkey {
+                Some(subkey) => subkey.key_id(),
+                None => signer.key.key_id(),
+            });
+        }
+        let res = match signer.subkey {
+            Some(subkey) => verify_message(signature, &subkey.key, message),
+            None => verify_message(signature, &signer.key.primary_key, message),
+        };
+        match res {
+            Ok(()) => {
+                validity = SignatureValidity::Valid;
+                break;
+            }
+            Err(err) => {
+                warn!("invalid signature: {:?}", err);
+                validity = SignatureValidity::Invalid;
+            }
+        }
+    }
+
+    if validity == SignatureValidity::Valid && is_unintended_recipient(signature, recipients) {
+        validity = SignatureValidity::UnintendedRecipient;
+    }
+    if let Some(key_id) = key_id {
+        observer.on_event(match validity {
+            SignatureValidity::Valid | SignatureValidity::UnintendedRecipient => {
+                StatusEvent::GoodSig(key_id)
+            }
+            SignatureValidity::Invalid => StatusEvent::BadSig(key_id),
+            SignatureValidity::UnknownKey => StatusEvent::ErrSig(key_id),
+        });
+    }
+
+    SignatureVerification {
+        fingerprint,
+        key_id: signature.issuer().cloned(),
+        created: signature.created().cloned(),
+        validity,
+    }
+}
+
+/// Returns the primary key fingerprints of those `keys`, that one of the decrypted
+/// session keys was encrypted to, see [`Message::decrypt`].
+pub(crate) fn recipient_fingerprints(keys: &[&SignedSecretKey], ids: &[KeyId]) -> Vec<Vec<u8>> {
+    keys.iter()
+        .filter(|key| {
+            ids.iter().any(|id| {
+                *id == key.key_id() || key.secret_subkeys.iter().any(|sk| *id == sk.key_id())
+            })
+        })
+        .map(|key| key.fingerprint())
+        .collect()
+}
+
+/// Returns true if `signature` lists intended recipients, and none of them is one of
+/// `recipients`. Nothing is checked, if `recipients` is empty.
+fn is_unintended_recipient(signature: &Signature, recipients: &[Vec<u8>]) -> bool {
+    let intended = signature.intended_recipients();
+
+    !recipients.is_empty()
+        && !intended.is_empty()
+        && !recipients.iter().any(|fp| intended.contains(&&fp[..]))
+}
+
+/// Returns true if any signature of `msg` lists intended recipients, that do not include
+/// `recipients`. The signatures are not verified.
+pub(crate) fn has_unintended_recipient(msg: &Message, recipients: &[Vec<u8>]) -> bool {
+    match msg {
+        Message::Signed {
+            message, signature, ..
+        } => {
+            is_unintended_recipient(signature, recipients)
+                || message.as_ref().map_or(false, |message| {
+                    has_unintended_recipient(message, recipients)
+                })
+        }
+        Message::Compressed(data) => data
+            .decompress_limited()
+            .and_then(Message::from_bytes)
+            .map_or(false, |message| {
+                has_unintended_recipient(&message, recipients)
+            }),
+        _ => false,
+    }
+}
+
+/// Verifies `signature` over `message`, the same way as [`Message::verify`].
+fn verify_message(
+    signature: &Signature,
+    key: &impl PublicKeyTrait,
+    message: &Message,
+) -> Result<()> {
+    match message {
+        Message::Literal(data) => signature.verify_literal(key, data),
+        _ => signature.verify_serialized(key, message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::SubsecRound;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::test_keys::{gen_key, gen_key_with_subkeys};
+    use crate::composed::{
+        EncryptionProfile, EncryptionSettings, KeyType, SecretKeyParamsBuilder, StructurePolicy,
+        VerifyingReader,
+    };
+    use crate::crypto::HashAlgorithm;
+    use crate::ser::Serialize;
+    use crate::types::{CompressionAlgorithm, SecretKeyTrait};
+
+    #[test]
+    fn test_decrypt_and_verify() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key(&mut rng, "Alice <alice@mail.com>");
+        let bob = gen_key(&mut rng, "Bob <bob@mail.com>");
+        let eve = gen_key(&mut rng, "Eve <eve@mail.com>");
+        let bob_public = bob.signed_public_key();
+
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let encrypted = msg
+            .sign_and_encrypt(
+                &mut rng,
+                &alice,
+                || "".into(),
+                &[&bob_public],
+                EncryptionProfile::Compatibility,
+            )
+            .unwrap();
+
+        let (eve_public, alice_public) = (eve.signed_public_key(), alice.signed_public_key());
+        let senders = [&eve_public, &alice_public];
+        let decrypted = encrypted
+            .decrypt_and_verify(|| "".into(), &[&bob], &senders)
+            .unwrap();
+        assert!(decrypted.is_signed());
+        assert_eq!(
+            decrypted.message.get_content().unwrap(),
+            Some(b"hello world".to_vec())
+        );
+        assert_eq!(decrypted.signatures.len(), 1);
+        let sig = &decrypted.signatures[0];
+        assert_eq!(sig.validity, SignatureValidity::Valid);
+        assert_eq!(sig.fingerprint, Some(alice.fingerprint()));
+        assert_eq!(sig.key_id, Some(alice.key_id()));
+        assert!(sig.created.is_some());
+
+        // the plaintext is returned, even if the sender is not known
+        let decrypted = encrypted
+            .decrypt_and_verify(|| "".into(), &[&bob], &[])
+            .unwrap();
+        assert!(!decrypted.is_signed());
+        assert_eq!(
+            decrypted.signatures[0].validity,
+            SignatureValidity::UnknownKey
+        );
+        assert_eq!(decrypted.signatures[0].fingerprint, None);
+        assert!(encrypted
+            .decrypt_and_verify(|| "".into(), &[&eve], &senders)
+            .is_err());
+
+        // a signature over different data is reported as invalid
+        let forged = match msg.sign(&alice, || "".into(), HashAlgorithm::SHA2_256) {
+            Ok(Message::Signed {
+                one_pass_signature,
+                signature,
+                ..
+            }) => Message::Signed {
+                message: Some(Box::new(Message::new_literal("hello.txt", "bye"))),
+                one_pass_signature,
+                signature,
+            },
+            res => panic!("unexpected result {:?}", res),
+        };
+        let encrypted = forged
+            .encrypt_to_recipients(&mut rng, &[&bob_public], EncryptionProfile::Modern)
+            .unwrap();
+        let decrypted = encrypted
+            .decrypt_and_verify(|| "".into(), &[&bob], &senders)
+            .unwrap();
+        assert!(!decrypted.is_signed());
+        assert_eq!(decrypted.signatures[0].validity, SignatureValidity::Invalid);
+        assert_eq!(
+            decrypted.signatures[0].fingerprint,
+            Some(alice.fingerprint())
+        );
+        assert_eq!(decrypted.message, forged);
+
+        // an unsigned message appended to the signed one is not silently dropped
+        let appended = match encrypted {
+            Message::Encrypted { esk, mut edata } => {
+                edata.push(edata[0].clone());
+                Message::Encrypted { esk, edata }
+            }
+            msg => panic!("unexpected message {:?}", msg),
+        };
+        assert!(appended
+            .decrypt_and_verify(|| "".into(), &[&bob], &senders)
+            .is_err());
+    }
+
+    #[test]
+    fn test_decrypt_and_verify_structure_policy() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key(&mut rng, "Alice <alice@mail.com>");
+        let bob = gen_key(&mut rng, "Bob <bob@mail.com>");
+        let (alice_public, bob_public) = (alice.signed_public_key(), bob.signed_public_key());
+        let policy = Policy {
+            structure: StructurePolicy::SignedThenEncrypted,
+            ..Policy::default()
+        };
+
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let signed = msg
+            .sign_and_encrypt(
+                &mut rng,
+                &alice,
+                || "".into(),
+                &[&bob_public],
+                EncryptionProfile::Modern,
+            )
+            .unwrap();
+        let decrypted = signed
+            .decrypt_and_verify_with_policy(|| "".into(), &[&bob], &[&alice_public], &policy)
+            .unwrap();
+        assert!(decrypted.is_signed());
+
+        // unsigned messages are rejected
+        let unsigned = msg
+            .encrypt_to_recipients(&mut rng, &[&bob_public], EncryptionProfile::Modern)
+            .unwrap();
+        assert!(unsigned
+            .decrypt_and_verify(|| "".into(), &[&bob], &[&alice_public])
+            .is_ok());
+        assert!(unsigned
+            .decrypt_and_verify_with_policy(|| "".into(), &[&bob], &[&alice_public], &policy)
+            .is_err());
+    }
+
+    #[test]
+    fn test_intended_recipients() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key(&mut rng, "Alice <alice@mail.com>");
+        let bob = gen_key(&mut rng, "Bob <bob@mail.com>");
+        let eve = gen_key(&mut rng, "Eve <eve@mail.com>");
+        let alice_public = alice.signed_public_key();
+        let senders = [&alice_public];
+
+        let msg = Message::new_literal("hello.txt", "hello bob");
+        let encrypted = msg
+            .sign_and_encrypt(
+                &mut rng,
+                &alice,
+                || "".into(),
+                &[&bob.signed_public_key()],
+                EncryptionProfile::Modern,
+            )
+            .unwrap();
+        let decrypted = encrypted
+            .decrypt_and_verify(|| "".into(), &[&bob], &senders)
+            .unwrap();
+        assert!(decrypted.is_signed());
+        let signature = match &decrypted.message {
+            Message::Signed { signature, .. } => signature,
+            msg => panic!("unexpected message {:?}", msg),
+        };
+        assert_eq!(
+            signature.intended_recipients(),
+            vec![&bob.fingerprint()[..]]
+        );
+
+        // bob forwards the signed message to eve, as if alice sent it to her
+        let forwarded = decrypted
+            .message
+            .encrypt_to_recipients(
+                &mut rng,
+                &[&eve.signed_public_key()],
+                EncryptionProfile::Modern,
+            )
+            .unwrap();
+        let decrypted = forwarded
+            .decrypt_and_verify(|| "".into(), &[&eve], &senders)
+            .unwrap();
+        assert!(!decrypted.is_signed());
+        assert_eq!(
+            decrypted.signatures[0].validity,
+            SignatureValidity::UnintendedRecipient
+        );
+        // the signature itself is still valid
+        assert_eq!(
+            decrypted.message.verify_signatures(&senders).unwrap()[0].validity,
+            SignatureValidity::Valid
+        );
+        // which is also noticed when decrypting without checking the signatures
+        let eve_fingerprints = vec![eve.fingerprint()];
+        let (mut decrypter, ids) = forwarded
+            .decrypt(|| "".into(), || "".into(), &[&eve])
+            .unwrap();
+        assert_eq!(
+            recipient_fingerprints(&[&eve, &bob], &ids),
+            eve_fingerprints
+        );
+        let message = decrypter.next().unwrap().unwrap();
+        assert!(has_unintended_recipient(&message, &eve_fingerprints));
+        assert!(!has_unintended_recipient(&message, &[bob.fingerprint()]));
+        assert!(!has_unintended_recipient(&message, &[]));
+        let compressed = message.compress(CompressionAlgorithm::ZLIB).unwrap();
+        assert!(has_unintended_recipient(&compressed, &eve_fingerprints));
+
+        // anonymous recipients are not listed
+        let profile = EncryptionProfile::Custom(EncryptionSettings {
+            anonymous_recipients: true,
+            ..EncryptionProfile::Modern.settings()
+        });
+        let encrypted = msg
+            .sign_and_encrypt(
+                &mut rng,
+                &alice,
+                || "".into(),
+                &[&bob.signed_public_key()],
+                profile,
+            )
+            .unwrap();
+        let decrypted = encrypted
+            .decrypt_and_verify(|| "".into(), &[&bob], &senders)
+            .unwrap();
+        assert!(decrypted.is_signed());
+        match &decrypted.message {
+            Message::Signed { signature, .. } => {
+                assert!(signature.intended_recipients().is_empty())
+            }
+            msg => panic!("unexpected message {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_sign_with_keys() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key_with_subkeys(&mut rng, "Alice <alice@mail.com>", 0);
+        let bob = gen_key_with_subkeys(&mut rng, "Bob <bob@mail.com>", 0);
+        let keyring = [alice.signed_public_key(), bob.signed_public_key()];
+        let senders = [&keyring[0], &keyring[1]];
+
+        let msg = Message::new_literal("release.txt", "release 1.0")
+            .sign_with_keys(&[&alice, &bob], |_| "".into(), HashAlgorithm::SHA2_256)
+            .unwrap();
+        let msg = Message::from_bytes(&msg.to_bytes().unwrap()[..]).unwrap();
+        match &msg {
+            Message::Signed {
+                one_pass_signature: Some(ops),
+                message: Some(inner),
+                ..
+            } => {
+                assert!(!ops.is_last());
+                match &**inner {
+                    Message::Signed {
+                        one_pass_signature: Some(ops),
+                        ..
+                    } => assert!(ops.is_last()),
+                    inner => panic!("unexpected message {:?}", inner),
+                }
+            }
+            msg => panic!("unexpected message {:?}", msg),
+        }
+        msg.verify(&alice.public_key()).unwrap();
+
+        let results = msg.verify_signatures(&senders).unwrap();
+        assert_eq!(results.len(), 2);
+        for (res, key) in results.iter().zip(&[&alice, &bob]) {
+            assert_eq!(res.validity, SignatureValidity::Valid);
+            assert_eq!(res.fingerprint, Some(key.fingerprint()));
+        }
+        let results = msg.verify_signatures(&senders[1..]).unwrap();
+        assert_eq!(results[0].validity, SignatureValidity::UnknownKey);
+        assert_eq!(results[1].validity, SignatureValidity::Valid);
+
+        // the signatures can also be checked while reading
+        let bytes = msg.to_bytes().unwrap();
+        let policy = Default::default();
+        let reader = VerifyingReader::new(&bytes[..], &keyring[1..], &policy).unwrap();
+        let res = reader.verify().unwrap();
+        assert_eq!(res.fingerprint, bob.fingerprint());
+
+        // a nested signature covers the signed message inside of it
+        let nested = Message::new_literal("release.txt", "release 1.0")
+            .sign(&bob, || "".into(), HashAlgorithm::SHA2_256)
+            .unwrap()
+            .sign(&alice, || "".into(), HashAlgorithm::SHA2_256)
+            .unwrap();
+        let results = nested.verify_signatures(&senders).unwrap();
+        assert!(results
+            .iter()
+            .all(|res| res.validity == SignatureValidity::Valid));
+    }
+
+    #[test]
+    fn test_verify_signatures_with_status() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let created_at = Utc::now().trunc_subsecs(0) - chrono::Duration::days(2);
+        let expired = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Old <old@mail.com>".into())
+            .created_at(created_at)
+            .expiration(Some(std::time::Duration::from_secs(24 * 60 * 60)))
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .expect("failed to generate secret key")
+            .sign_at(|| "".into(), created_at)
+            .expect("failed to sign key");
+        let alice = gen_key(&mut rng, "Alice <alice@mail.com>");
+        let keyring = [alice.signed_public_key(), expired.signed_public_key()];
+
+        let msg = Message::new_literal("hello.txt", "hello world")
+            .sign_with_keys(&[&alice, &expired], |_| "".into(), HashAlgorithm::SHA2_256)
+            .unwrap();
+
+        let mut events = Vec::new();
+        let results = msg
+            .verify_signatures_with_status(&[&keyring[0], &keyring[1]], &mut |event| {
+                events.push(event)
+            })
+            .unwrap();
+        assert_eq!(results[0].validity, SignatureValidity::Valid);
+        assert_eq!(results[1].validity, SignatureValidity::UnknownKey);
+        assert_eq!(
+            events,
+            vec![
+                StatusEvent::GoodSig(alice.key_id()),
+                StatusEvent::KeyExpired(expired.key_id(), created_at + chrono::Duration::days(1)),
+                StatusEvent::ErrSig(expired.key_id()),
+            ]
+        );
+    }
+}
@@ -2,11 +2,17 @@ pub mod key;
 pub mod message;
 pub mod signed_key;
 
+mod archive;
+mod policy;
 mod shared;
 mod signature;
+#[cfg(test)]
+pub(crate) mod test_keys;
 
+pub use self::archive::*;
 pub use self::key::*;
 pub use self::message::*;
+pub use self::policy::*;
 pub use self::shared::Deserializable;
 pub use self::signature::*;
 pub use self::signed_key::*;
@@ -0,0 +1,139 @@
+use crate::composed::{SignedPublicKey, StructurePolicy};
+use crate::crypto::{HashAlgorithm, MAX_KEY_BITS};
+use crate::errors::{Error, Result};
+use crate::packet::{Signature, SignatureType};
+use crate::types::{KeyTrait, KeyVersion, PublicParams};
+use crate::util::bit_size;
+
+/// Requirements that signatures have to fulfill, to be accepted during verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    /// Hash algorithms that are considered broken, signatures using them are rejected.
+    pub rejected_hash_algorithms: Vec<HashAlgorithm>,
+    /// Minimum size of RSA, DSA and Elgamal keys, in bits.
+    pub min_key_bits: usize,
+    /// Maximum size of RSA, DSA and Elgamal keys, in bits. Larger keys are rejected, which
+    /// guards against the cost of processing many signatures made by very large keys.
+    ///
+    /// Keys larger than [`MAX_KEY_BITS`] are always rejected.
+    pub max_key_bits: usize,
+    /// Accept v6 keys without any user ids, which are bound by a direct key signature only.
+    pub accept_bare_keys: bool,
+    /// Require signing subkeys to cross-certify the primary key with an embedded primary
+    /// key binding signature, as GnuPG does. Subkeys without one are not used for signing
+    /// or verification. Only disable this for old keys, that were created before back
+    /// signatures were introduced.
+    pub require_back_signatures: bool,
+    /// Required order of the signature and encryption layers of decrypted messages.
+    pub structure: StructurePolicy,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            rejected_hash_algorithms: vec![HashAlgorithm::MD5],
+            min_key_bits: 2048,
+            max_key_bits: 8192,
+            accept_bare_keys: false,
+            require_back_signatures: true,
+            structure: StructurePolicy::Any,
+        }
+    }
+}
+
+impl Policy {
+    /// Checks if the given signature is acceptable under this policy.
+    ///
+    /// This does not verify the signature itself.
+    pub fn check_signature(&self, sig: &Signature) -> Result<()> {
+        let hash_alg = sig.config.hash_alg;
+        ensure!(
+            !self.rejected_hash_algorithms.contains(&hash_alg),
+            "signatures using {:?} are rejected by the policy",
+            hash_alg
+        );
+
+        Ok(())
+    }
+
+    /// Checks that the RSA, DSA or Elgamal key material is not larger than
+    /// `max_key_bits`.
+    pub fn check_key_size(&self, params: &PublicParams) -> Result<()> {
+        let bits = match params {
+            PublicParams::RSA { n, .. } => bit_size(n),
+            PublicParams::DSA { p, .. } | PublicParams::Elgamal { p, .. } => bit_size(p),
+            _ => return Ok(()),
+        };
+        if bits > self.max_key_bits {
+            return Err(Error::KeyTooLarge {
+                bits,
+                max: self.max_key_bits,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks if the given key is acceptable under this policy.
+    ///
+    /// Keys without user ids are only accepted, if the policy allows bare keys, and they
+    /// are v6 keys with a valid direct key self-signature. This does not verify the other
+    /// self-signatures of the key.
+    pub fn check_key(&self, key: &SignedPublicKey) -> Result<()> {
+        if !key.details.users.is_empty() {
+            return Ok(());
+        }
+
+        ensure!(
+            self.accept_bare_keys,
+            "keys without user ids are rejected by the policy"
+        );
+        ensure_eq!(
+            key.version(),
+            KeyVersion::V6,
+            "only v6 keys can be used without user ids"
+        );
+        let has_direct_signature = key.details.direct_signatures.iter().any(|sig| {
+            sig.typ() == SignatureType::Key
+                && sig.is_issued_by(&key.primary_key)
+                && self.check_signature(sig).is_ok()
+                && sig.verify_key(&key.primary_key).is_ok()
+        });
+        ensure!(
+            has_direct_signature,
+            "keys without user ids need a valid direct key signature"
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::types::Mpi;
+
+    #[test]
+    fn test_check_key_size() {
+        let params = |bytes: usize| PublicParams::RSA {
+            n: Mpi::from_raw_slice(&vec![0xff; bytes]),
+            e: Mpi::from_raw_slice(&[1, 0, 1]),
+        };
+        let policy = Policy::default();
+        policy.check_key_size(&params(1024)).unwrap();
+        match policy.check_key_size(&params(1025)) {
+            Err(Error::KeyTooLarge { bits, max }) => {
+                assert_eq!(bits, 8200);
+                assert_eq!(max, 8192);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        let policy = Policy {
+            max_key_bits: 4096,
+            ..Policy::default()
+        };
+        assert!(policy.check_key_size(&params(1024)).is_err());
+    }
+}
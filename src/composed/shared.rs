@@ -1,7 +1,7 @@
-use std::collections::BTreeMap;
 use std::io::{Cursor, Read, Seek};
+use std::iter::Peekable;
 
-use crate::armor::{self, BlockType};
+use crate::armor::{self, BlockType, Headers};
 use crate::errors::{Error, Result};
 use crate::packet::{Packet, PacketParser};
 
@@ -13,7 +13,7 @@ pub trait Deserializable: Sized {
     }
 
     /// Parse a single armor encoded composition.
-    fn from_string(input: &str) -> Result<(Self, BTreeMap<String, String>)> {
+    fn from_string(input: &str) -> Result<(Self, Headers)> {
         let (mut el, headers) = Self::from_string_many(input)?;
         Ok((el.next().ok_or_else(|| Error::NoMatchingPacket)??, headers))
     }
@@ -22,15 +22,12 @@ pub trait Deserializable: Sized {
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::type_complexity))]
     fn from_string_many<'a>(
         input: &'a str,
-    ) -> Result<(
-        Box<dyn Iterator<Item = Result<Self>> + 'a>,
-        BTreeMap<String, String>,
-    )> {
+    ) -> Result<(Box<dyn Iterator<Item = Result<Self>> + 'a>, Headers)> {
         Self::from_armor_many(Cursor::new(input))
     }
 
     /// Armored ascii data.
-    fn from_armor_single<R: Read + Seek>(input: R) -> Result<(Self, BTreeMap<String, String>)> {
+    fn from_armor_single<R: Read + Seek>(input: R) -> Result<(Self, Headers)> {
         let (mut el, headers) = Self::from_armor_many(input)?;
         Ok((el.next().ok_or_else(|| Error::NoMatchingPacket)??, headers))
     }
@@ -39,10 +36,7 @@ pub trait Deserializable: Sized {
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::type_complexity))]
     fn from_armor_many<'a, R: Read + Seek + 'a>(
         input: R,
-    ) -> Result<(
-        Box<dyn Iterator<Item = Result<Self>> + 'a>,
-        BTreeMap<String, String>,
-    )> {
+    ) -> Result<(Box<dyn Iterator<Item = Result<Self>> + 'a>, Headers)> {
         let mut dearmor = armor::Dearmor::new(input);
         dearmor.read_header()?;
         // Safe to unwrap, as read_header succeeded.
@@ -61,8 +55,12 @@ pub trait Deserializable: Sized {
             | BlockType::File => {
                 let headers = dearmor.headers.clone(); // FIXME: avoid clone
 
-                // TODO: check that the result is what it actually said.
-                Ok((Self::from_bytes_many(dearmor), headers))
+                let mut packets = PacketParser::new(dearmor)
+                    .filter_map(skip_invalid)
+                    .peekable();
+                check_block_type(typ, &mut packets)?;
+
+                Ok((Self::from_packets(packets), headers))
             }
             BlockType::PublicKeyPKCS1(_)
             | BlockType::PublicKeyPKCS8
@@ -77,15 +75,7 @@ pub trait Deserializable: Sized {
 
     /// Parse a list of compositions in raw byte format.
     fn from_bytes_many<'a>(bytes: impl Read + 'a) -> Box<dyn Iterator<Item = Result<Self>> + 'a> {
-        let packets = PacketParser::new(bytes).filter_map(|p| {
-            // for now we are skipping any packets that we failed to parse
-            if p.is_ok() {
-                p.ok()
-            } else {
-                warn!("skipping packet: {:?}", p);
-                None
-            }
-        });
+        let packets = PacketParser::new(bytes).filter_map(skip_invalid);
 
         Self::from_packets(packets)
     }
@@ -95,3 +85,28 @@ pub trait Deserializable: Sized {
         packets: impl Iterator<Item = Packet> + 'a,
     ) -> Box<dyn Iterator<Item = Result<Self>> + 'a>;
 }
+
+pub(crate) fn skip_invalid(packet: Result<Packet>) -> Option<Packet> {
+    // for now we are skipping any packets that we failed to parse
+    if packet.is_ok() {
+        packet.ok()
+    } else {
+        warn!("skipping packet: {:?}", packet);
+        None
+    }
+}
+
+/// Checks that the first packet matches the type of the armor block it was read from.
+pub(crate) fn check_block_type<I: Iterator<Item = Packet>>(
+    typ: BlockType,
+    packets: &mut Peekable<I>,
+) -> Result<()> {
+    if let Some(packet) = packets.peek() {
+        let tag = packet.tag();
+        if !typ.allows_tag(tag) {
+            return Err(Error::ArmorMismatch { typ, tag });
+        }
+    }
+
+    Ok(())
+}
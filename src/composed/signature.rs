@@ -1,15 +1,39 @@
-use std::collections::BTreeMap;
+use std::borrow::Borrow;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Write};
 use std::iter::Peekable;
+use std::path::Path;
 
+use chrono::{DateTime, SubsecRound, Utc};
 use try_from::TryInto;
 
-use crate::armor;
-use crate::composed::Deserializable;
+use crate::armor::{self, Headers};
+use crate::composed::{
+    Deserializable, Policy, SignatureValidity, SignatureVerification, SignedPublicKey,
+    SignedPublicSubKey,
+};
+use crate::crypto::{HashAlgorithm, Hasher};
 use crate::errors::Result;
-use crate::packet::{Packet, Signature};
+use crate::packet::{
+    Packet, Signature, SignatureConfig, SignatureConfigBuilder, SignatureType, SignatureVersion,
+    Subpacket,
+};
 use crate::ser::Serialize;
-use crate::types::PublicKeyTrait;
-use crate::types::Tag;
+use crate::types::{KeyId, KeyTrait, PublicKeyTrait, SecretKeyTrait, Tag};
+
+/// The explanation GnuPG puts in front of revocation certificates.
+const REVOCATION_CERTIFICATE_NOTICE: &str = "\
+A revocation certificate is a kind of \"kill switch\" to publicly
+declare that a key shall not anymore be used.  It is not possible
+to retract such a revocation certificate once it has been published.
+
+Use it to revoke this key in case of a compromise or loss of
+the secret key.
+
+To avoid an accidental use of this file, a colon has been inserted
+before the 5 dashes below.  Remove this colon with a text editor
+before importing and publishing this revocation certificate.
+";
 
 /// Standalone signature as defined by the cleartext framework.
 #[derive(Debug, Clone)]
@@ -22,15 +46,135 @@ impl StandaloneSignature {
         StandaloneSignature { signature }
     }
 
+    /// Creates a detached binary signature over the data read from `data`, without
+    /// buffering it, for example to sign release artifacts.
+    ///
+    /// The result can be written in binary form, or armored as a `PGP SIGNATURE` block.
+    pub fn new_detached<F>(
+        data: impl Read,
+        signer: &impl SecretKeyTrait,
+        key_pw: F,
+        hash_alg: HashAlgorithm,
+    ) -> Result<Self>
+    where
+        F: FnOnce() -> String,
+    {
+        Self::detached(SignatureType::Binary, data, signer, key_pw, hash_alg)
+    }
+
+    /// Same as [`new_detached`], but creates a text signature, like `gpg --textmode`.
+    ///
+    /// Line endings are canonicalized before hashing, so the signature stays valid if they
+    /// are converted, see [`SignatureConfig::hash_data_to_sign`].
+    ///
+    /// [`new_detached`]: StandaloneSignature::new_detached
+    pub fn new_detached_text<F>(
+        data: impl Read,
+        signer: &impl SecretKeyTrait,
+        key_pw: F,
+        hash_alg: HashAlgorithm,
+    ) -> Result<Self>
+    where
+        F: FnOnce() -> String,
+    {
+        Self::detached(SignatureType::Text, data, signer, key_pw, hash_alg)
+    }
+
+    /// Creates detached binary signatures over the data read from `data`, one for each of
+    /// `keys`, for example when a release is co-signed.
+    ///
+    /// The data is read only once. `key_pw` returns the password of each key. The
+    /// signatures are returned in the order of the keys, and can be stored together, see
+    /// [`verify_detached_signatures`].
+    pub fn new_detached_with_keys<K, F>(
+        data: impl Read,
+        keys: &[&K],
+        key_pw: F,
+        hash_alg: HashAlgorithm,
+    ) -> Result<Vec<Self>>
+    where
+        K: SecretKeyTrait,
+        F: Fn(&K) -> String,
+    {
+        Self::detached_with_keys(SignatureType::Binary, data, keys, key_pw, hash_alg)
+    }
+
+    /// Same as [`new_detached_with_keys`], but creates text signatures, like
+    /// [`new_detached_text`].
+    ///
+    /// [`new_detached_with_keys`]: StandaloneSignature::new_detached_with_keys
+    /// [`new_detached_text`]: StandaloneSignature::new_detached_text
+    pub fn new_detached_text_with_keys<K, F>(
+        data: impl Read,
+        keys: &[&K],
+        key_pw: F,
+        hash_alg: HashAlgorithm,
+    ) -> Result<Vec<Self>>
+    where
+        K: SecretKeyTrait,
+        F: Fn(&K) -> String,
+    {
+        Self::detached_with_keys(SignatureType::Text, data, keys, key_pw, hash_alg)
+    }
+
+    fn detached_with_keys<K, F>(
+        typ: SignatureType,
+        data: impl Read,
+        keys: &[&K],
+        key_pw: F,
+        hash_alg: HashAlgorithm,
+    ) -> Result<Vec<Self>>
+    where
+        K: SecretKeyTrait,
+        F: Fn(&K) -> String,
+    {
+        ensure!(!keys.is_empty(), "no signing keys given");
+
+        let mut configs = keys
+            .iter()
+            .map(|key| detached_config(typ, *key, hash_alg))
+            .collect::<Result<Vec<_>>>()?;
+        for config in &mut configs {
+            config.ensure_salt()?;
+        }
+        let hashers = hash_detached_data(&configs.iter().collect::<Vec<_>>(), data)?;
+
+        configs
+            .into_iter()
+            .zip(hashers)
+            .zip(keys)
+            .map(|((config, hasher), key)| {
+                let signature = config.sign_hasher(*key, || key_pw(*key), hasher)?;
+                Ok(StandaloneSignature::new(signature))
+            })
+            .collect()
+    }
+
+    fn detached<F>(
+        typ: SignatureType,
+        data: impl Read,
+        signer: &impl SecretKeyTrait,
+        key_pw: F,
+        hash_alg: HashAlgorithm,
+    ) -> Result<Self>
+    where
+        F: FnOnce() -> String,
+    {
+        let signature =
+            detached_config(typ, signer, hash_alg)?.sign_reader(signer, key_pw, data)?;
+
+        Ok(StandaloneSignature::new(signature))
+    }
+
     pub fn to_armored_writer(
         &self,
         writer: &mut impl std::io::Write,
-        headers: Option<&BTreeMap<String, String>>,
+        headers: Option<&Headers>,
     ) -> Result<()> {
         armor::write(self, armor::BlockType::Signature, writer, headers)
     }
 
-    pub fn to_armored_bytes(&self, headers: Option<&BTreeMap<String, String>>) -> Result<Vec<u8>> {
+    pub fn to_armored_bytes(&self, headers: Option<&Headers>) -> Result<Vec<u8>> {
         let mut buf = Vec::new();
 
         self.to_armored_writer(&mut buf, headers)?;
@@ -38,7 +182,7 @@ impl StandaloneSignature {
         Ok(buf)
     }
 
-    pub fn to_armored_string(&self, headers: Option<&BTreeMap<String, String>>) -> Result<String> {
+    pub fn to_armored_string(&self, headers: Option<&Headers>) -> Result<String> {
         Ok(::std::str::from_utf8(&self.to_armored_bytes(headers)?)?.to_string())
     }
 
@@ -46,6 +190,116 @@ impl StandaloneSignature {
     pub fn verify(&self, key: &impl PublicKeyTrait, content: &[u8]) -> Result<()> {
         self.signature.verify(key, content)
     }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// Writes this key revocation as a revocation certificate, in the format used by GnuPG.
+    ///
+    /// The armored signature is preceded by a description of the revoked key, and its
+    /// first line is prefixed with a colon, to avoid accidentally importing it.
+    pub fn to_revocation_certificate_writer(
+        &self,
+        key: &SignedPublicKey,
+        writer: &mut impl std::io::Write,
+    ) -> Result<()> {
+        ensure_eq!(
+            self.signature.typ(),
+            SignatureType::KeyRevocation,
+            "not a key revocation"
+        );
+
+        writeln!(
+            writer,
+            "This is a revocation certificate for the OpenPGP key:\n"
+        )?;
+        writeln!(
+            writer,
+            "pub   {:?} {}",
+            key.algorithm(),
+            key.primary_key.created_at().format("%Y-%m-%d")
+        )?;
+        writeln!(writer, "      {}", hex::encode_upper(key.fingerprint()))?;
+        for user in &key.details.users {
+            writeln!(writer, "uid          {}", user.id.id())?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "{}", REVOCATION_CERTIFICATE_NOTICE)?;
+
+        let mut headers = Headers::new();
+        headers.insert("Comment", "This is a revocation certificate");
+
+        writer.write_all(b":")?;
+        self.to_armored_revocation_writer(writer, Some(&headers))
+    }
+
+    /// Writes this key revocation armored as a public key block, without any description,
+    /// so it can be imported directly.
+    pub fn to_armored_revocation_writer(
+        &self,
+        writer: &mut impl std::io::Write,
+        headers: Option<&Headers>,
+    ) -> Result<()> {
+        ensure_eq!(
+            self.signature.typ(),
+            SignatureType::KeyRevocation,
+            "not a key revocation"
+        );
+
+        armor::write(self, armor::BlockType::PublicKey, writer, headers)
+    }
+
+    pub fn to_armored_revocation_string(&self, headers: Option<&Headers>) -> Result<String> {
+        let mut buf = Vec::new();
+        self.to_armored_revocation_writer(&mut buf, headers)?;
+
+        Ok(::std::str::from_utf8(&buf)?.to_string())
+    }
+
+    pub fn to_revocation_certificate_string(&self, key: &SignedPublicKey) -> Result<String> {
+        let mut buf = Vec::new();
+        self.to_revocation_certificate_writer(key, &mut buf)?;
+
+        Ok(::std::str::from_utf8(&buf)?.to_string())
+    }
+
+    /// Parses a revocation certificate, as written by GnuPG.
+    /// Descriptive text before the armor and the colon prefix are skipped.
+    pub fn from_revocation_certificate(input: &str) -> Result<(Self, Headers)> {
+        let start = match input.find("-----BEGIN ") {
+            Some(start) => start,
+            None => bail!("missing armor header"),
+        };
+
+        let (sig, headers) = Self::from_string(&input[start..])?;
+        ensure_eq!(
+            sig.signature.typ(),
+            SignatureType::KeyRevocation,
+            "not a key revocation"
+        );
+
+        Ok((sig, headers))
+    }
+}
+
+/// The configuration of a detached signature by `signer`, created now.
+fn detached_config(
+    typ: SignatureType,
+    signer: &impl SecretKeyTrait,
+    hash_alg: HashAlgorithm,
+) -> Result<SignatureConfig> {
+    Ok(SignatureConfigBuilder::default()
+        .typ(typ)
+        .version(SignatureVersion::for_key(signer))
+        .pub_alg(signer.algorithm())
+        .hash_alg(hash_alg)
+        .hashed_subpackets(vec![Subpacket::SignatureCreationTime(
+            Utc::now().trunc_subsecs(0),
+        )])
+        .unhashed_subpackets(vec![])
+        .build()?
+        .with_issuer(signer))
 }
 
 impl Serialize for StandaloneSignature {
@@ -88,3 +342,937 @@ fn next<I: Iterator<Item = Packet>>(
     }
     None
 }
+
+/// The signer of a successfully verified signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationResult {
+    /// Fingerprint of the primary key of the signer.
+    pub fingerprint: Vec<u8>,
+    /// Fingerprint of the key that made the signature, the primary key or one of its subkeys.
+    pub signing_key_fingerprint: Vec<u8>,
+    /// Creation time of the signature.
+    pub created: Option<DateTime<Utc>>,
+}
+
+impl VerificationResult {
+    fn new(key: &SignedPublicKey, signing_key: &impl KeyTrait, sig: &Signature) -> Self {
+        VerificationResult {
+            fingerprint: key.fingerprint(),
+            signing_key_fingerprint: signing_key.fingerprint(),
+            created: sig.created().cloned(),
+        }
+    }
+}
+
+/// Verifies the detached signature stored at `sig_path` against the contents of `data_path`.
+///
+/// The signature can be armored or binary, and has to be made by one of the keys in
+/// `keyring`, or one of their signing subkeys, and be acceptable under `policy`.
+/// The data is read from disk without buffering it. When there are multiple signatures,
+/// the first valid one is returned.
+pub fn verify_detached_file(
+    sig_path: impl AsRef<Path>,
+    data_path: impl AsRef<Path>,
+    keyring: &[SignedPublicKey],
+    policy: &Policy,
+) -> Result<VerificationResult> {
+    let mut sig_data = Vec::new();
+    File::open(sig_path)?.read_to_end(&mut sig_data)?;
+
+    let is_armored = sig_data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .map(|start| sig_data[start..].starts_with(b"-----BEGIN "))
+        .unwrap_or_default();
+    let sigs = if is_armored {
+        StandaloneSignature::from_armor_many(Cursor::new(&sig_data))?
+            .0
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        StandaloneSignature::from_bytes_many(Cursor::new(&sig_data)).collect::<Result<Vec<_>>>()?
+    };
+
+    verify_detached_reader(&sigs, File::open(data_path)?, keyring, policy)
+}
+
+/// Verifies the detached signatures `sigs` against the data read from `data`.
+///
+/// The data is read only once and never buffered as a whole, so this can be used for
+/// arbitrarily large files. Each signature has to be a binary or text signature made by
+/// one of the keys in `keyring`, or one of their signing subkeys, and be acceptable under
+/// `policy`. The result tells which key made the first valid signature, and when.
+pub fn verify_detached_reader(
+    sigs: &[StandaloneSignature],
+    data: impl Read,
+    keyring: &[SignedPublicKey],
+    policy: &Policy,
+) -> Result<VerificationResult> {
+    ensure!(!sigs.is_empty(), "no signatures found");
+
+    let mut candidates = Vec::new();
+    for sig in sigs.iter().map(StandaloneSignature::signature) {
+        match sig.typ() {
+            SignatureType::Binary | SignatureType::Text => {}
+            typ => {
+                warn!("skipping {:?} signature", typ);
+                continue;
+            }
+        }
+        if let Err(err) = policy.check_signature(sig) {
+            warn!("skipping signature: {:?}", err);
+            continue;
+        }
+
+        let mut valid = Vec::new();
+        for signer in signers(sig, keyring, policy) {
+            if let Err(err) = signer.check(sig, Some(policy)) {
+                warn!("skipping invalid key: {:?}", err);
+                continue;
+            }
+            valid.push(signer);
+        }
+        if !valid.is_empty() {
+            candidates.push((sig, valid));
+        }
+    }
+    ensure!(!candidates.is_empty(), "no valid signature found");
+
+    // hash the data for all signatures at once, instead of reading it again for every one
+    let configs: Vec<_> = candidates.iter().map(|(sig, _)| &sig.config).collect();
+    let hashers = hash_detached_data(&configs, data)?;
+
+    for ((sig, signers), hasher) in candidates.iter().zip(hashers) {
+        let digest = sig.finish_hasher(hasher)?;
+        for signer in signers {
+            let res = match signer.subkey {
+                Some(subkey) => sig.verify_digest(&subkey.key, &digest),
+                None => sig.verify_digest(&signer.key.primary_key, &digest),
+            };
+            match res {
+                Ok(()) => return Ok(signer.result(sig)),
+                Err(err) => warn!("invalid signature: {:?}", err),
+            }
+        }
+    }
+
+    bail!("no valid signature found")
+}
+
+/// Verifies all detached signatures `sigs` against the data read from `data`, and returns
+/// the result for each of them, in the same order.
+///
+/// Like [`verify_detached_reader`], the data is read only once. Signatures that are not
+/// acceptable under `policy`, or not binary or text signatures, are reported as invalid.
+/// Signers whose keys are not valid under `policy` are ignored.
+pub fn verify_detached_signatures(
+    sigs: &[StandaloneSignature],
+    data: impl Read,
+    keyring: &[SignedPublicKey],
+    policy: &Policy,
+) -> Result<Vec<SignatureVerification>> {
+    let acceptable = |sig: &Signature| match sig.typ() {
+        SignatureType::Binary | SignatureType::Text => policy.check_signature(sig),
+        typ => bail!("{:?} signatures can not be used to sign data", typ),
+    };
+    let configs: Vec<_> = sigs.iter().map(|sig| &sig.signature.config).collect();
+    let hashers = hash_detached_data(&configs, data)?;
+
+    let mut results = Vec::new();
+    for (sig, hasher) in sigs.iter().map(StandaloneSignature::signature).zip(hashers) {
+        let mut result = SignatureVerification {
+            fingerprint: None,
+            key_id: sig.issuer().cloned(),
+            created: sig.created().cloned(),
+            validity: SignatureValidity::UnknownKey,
+        };
+        let digest = sig.finish_hasher(hasher)?;
+        for signer in signers(sig, keyring, policy) {
+            if let Err(err) = signer.check(sig, Some(policy)) {
+                warn!("skipping invalid key: {:?}", err);
+                continue;
+            }
+
+            result.fingerprint = Some(signer.key.fingerprint());
+            let res = acceptable(sig).and_then(|_| match signer.subkey {
+                Some(subkey) => sig.verify_digest(&subkey.key, &digest),
+                None => sig.verify_digest(&signer.key.primary_key, &digest),
+            });
+            match res {
+                Ok(()) => {
+                    result.validity = SignatureValidity::Valid;
+                    break;
+                }
+                Err(err) => {
+                    warn!("invalid signature: {:?}", err);
+                    result.validity = SignatureValidity::Invalid;
+                }
+            }
+        }
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// Reads `data` once, and returns the hashers of the signed data for each of `configs`.
+fn hash_detached_data(
+    configs: &[&SignatureConfig],
+    mut data: impl Read,
+) -> Result<Vec<Box<dyn Hasher>>> {
+    let mut hashers = configs
+        .iter()
+        .map(|config| config.new_hasher())
+        .collect::<Result<Vec<_>>>()?;
+
+    {
+        let mut writers: Vec<_> = configs
+            .iter()
+            .zip(hashers.iter_mut())
+            .map(|(config, hasher)| config.data_writer(&mut **hasher))
+            .collect();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let read = match data.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for writer in &mut writers {
+                writer.write_all(&buf[..read])?;
+            }
+        }
+    }
+
+    Ok(hashers)
+}
+
+/// Verifies the detached signature `sig` of `data`, for example of a software update,
+/// only accepting signers from an explicit allow-list.
+///
+/// `pinned` holds the fingerprints of the accepted keys. Pinning a primary key accepts
+/// signatures made by it and by its signing subkeys, pinning a subkey only accepts that
+/// subkey. The keys themselves are taken from `keyring`, have to be valid and acceptable
+/// under `policy`, and must not have been revoked or expired when the signature was made.
+/// Only binary and text signatures are accepted.
+pub fn verify_with_pinned(
+    data: &[u8],
+    sig: &StandaloneSignature,
+    keyring: &[SignedPublicKey],
+    pinned: &[Vec<u8>],
+    policy: &Policy,
+) -> Result<VerificationResult> {
+    let sig = sig.signature();
+    match sig.typ() {
+        SignatureType::Binary | SignatureType::Text => {}
+        typ => bail!("{:?} signatures can not be used to sign data", typ),
+    }
+    policy.check_signature(sig)?;
+
+    let signers = signers(sig, keyring, policy).into_iter().filter(|signer| {
+        pinned.contains(&signer.key.fingerprint())
+            || signer
+                .subkey
+                .map_or(false, |subkey| pinned.contains(&subkey.fingerprint()))
+    });
+    for signer in signers {
+        if let Err(err) = signer.check(sig, Some(policy)) {
+            warn!("skipping invalid key: {:?}", err);
+            continue;
+        }
+
+        let res = match signer.subkey {
+            Some(subkey) => sig.verify(&subkey.key, data),
+            None => sig.verify(&signer.key.primary_key, data),
+        };
+        match res {
+            Ok(()) => return Ok(signer.result(sig)),
+            Err(err) => warn!("invalid signature: {:?}", err),
+        }
+    }
+
+    bail!("no valid signature by a pinned key found")
+}
+
+/// A key of the keyring, which might have made a signature.
+pub(crate) struct Signer<'a> {
+    pub(crate) key: &'a SignedPublicKey,
+    /// The signing subkey, `None` if the signature was made by the primary key.
+    pub(crate) subkey: Option<&'a SignedPublicSubKey>,
+}
+
+impl Signer<'_> {
+    /// Checks that the key is valid, and acceptable under `policy` if given, and that
+    /// neither the primary key nor the signing subkey were revoked or expired when `sig`
+    /// was created.
+    pub(crate) fn check(&self, sig: &Signature, policy: Option<&Policy>) -> Result<()> {
+        let key = self.key;
+        key.verify()?;
+        if let Some(policy) = policy {
+            policy.check_key(key)?;
+            policy.check_key_size(key.primary_key.public_params())?;
+            if let Some(subkey) = self.subkey {
+                policy.check_key_size(subkey.key.public_params())?;
+            }
+        }
+
+        let created = match sig.created() {
+            Some(created) => *created,
+            None => bail!("signature without a creation time"),
+        };
+        let primary_key = &key.primary_key;
+        ensure!(
+            !key.details.is_revoked(primary_key),
+            "key {} is revoked",
+            hex::encode(key.fingerprint())
+        );
+        ensure!(
+            key.created_at() <= &created && !key.is_expired(created),
+            "key {} was not valid at {}",
+            hex::encode(key.fingerprint()),
+            created
+        );
+        if let Some(subkey) = self.subkey {
+            ensure!(
+                !subkey.is_revoked(primary_key),
+                "subkey {} is revoked",
+                hex::encode(subkey.fingerprint())
+            );
+            ensure!(
+                subkey.created_at() <= &created && !subkey.is_expired(primary_key, created),
+                "subkey {} was not valid at {}",
+                hex::encode(subkey.fingerprint()),
+                created
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the key id and expiration time of the primary key or the signing subkey, if
+    /// it had expired when `sig` was created.
+    pub(crate) fn expired(&self, sig: &Signature) -> Option<(KeyId, DateTime<Utc>)> {
+        let created = *sig.created()?;
+        let primary_key = &self.key.primary_key;
+        if self.key.is_expired(created) {
+            return Some((self.key.key_id(), self.key.expires_at()?));
+        }
+
+        match self.subkey {
+            Some(subkey) if subkey.is_expired(primary_key, created) => {
+                Some((subkey.key_id(), subkey.expires_at(primary_key)?))
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn result(&self, sig: &Signature) -> VerificationResult {
+        match self.subkey {
+            Some(subkey) => VerificationResult::new(self.key, &subkey.key, sig),
+            None => VerificationResult::new(self.key, &self.key.primary_key, sig),
+        }
+    }
+}
+
+/// Returns the keys of `keyring` that issued `sig`, the primary keys and the signing
+/// subkeys that are not revoked, together with their primary key.
+///
+/// Subkeys without a valid back signature are only included, if `policy` allows it.
+pub(crate) fn signers<'a, K: Borrow<SignedPublicKey>>(
+    sig: &Signature,
+    keyring: &'a [K],
+    policy: &Policy,
+) -> Vec<Signer<'a>> {
+    let mut signers = Vec::new();
+    for key in keyring {
+        let key = key.borrow();
+        if sig.is_issued_by(&key.primary_key) {
+            signers.push(Signer { key, subkey: None });
+        }
+
+        let subkeys = key.public_subkeys.iter().filter(|subkey| {
+            sig.is_issued_by(&subkey.key)
+                && !subkey.is_revoked(&key.primary_key)
+                && subkey.can_sign(&key.primary_key, policy)
+        });
+        signers.extend(subkeys.map(|subkey| Signer {
+            key,
+            subkey: Some(subkey),
+        }));
+    }
+
+    signers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::test_keys::{gen_key_with_signing_subkey, gen_key_with_subkeys};
+    use crate::composed::{KeyType, SecretKeyParamsBuilder, SignedSecretKey};
+    use crate::packet::{RevocationCode, RevocationReason};
+    use crate::types::S2kParams;
+
+    #[test]
+    fn test_revocation_certificate() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key_with_subkeys(&mut rng, "Me <me@mail.com>", 0);
+        let public_key = key.public_key().sign(&key, || "".into()).unwrap();
+
+        let sig = SignatureConfigBuilder::default()
+            .typ(SignatureType::KeyRevocation)
+            .pub_alg(key.algorithm())
+            .hashed_subpackets(vec![Subpacket::SignatureCreationTime(
+                chrono::Utc::now().trunc_subsecs(0),
+            )])
+            .unhashed_subpackets(vec![Subpacket::Issuer(key.key_id())])
+            .build()
+            .unwrap()
+            .sign_key(&key, || "".into(), &public_key)
+            .unwrap();
+        let sig = StandaloneSignature::new(sig);
+
+        let cert = sig.to_revocation_certificate_string(&public_key).unwrap();
+        assert!(cert.starts_with("This is a revocation certificate for the OpenPGP key:\n"));
+        assert!(cert.contains(&hex::encode_upper(public_key.fingerprint())));
+        assert!(cert.contains("\nuid          Me <me@mail.com>\n"));
+        assert!(cert.contains("\n:-----BEGIN PGP PUBLIC KEY BLOCK-----\n"));
+
+        let (parsed, headers) = StandaloneSignature::from_revocation_certificate(&cert).unwrap();
+        assert_eq!(parsed.signature(), sig.signature());
+        assert_eq!(
+            headers.get("Comment"),
+            Some("This is a revocation certificate")
+        );
+        parsed.signature().verify_key(&public_key).unwrap();
+
+        // after removing the colon, as required for importing into GnuPG
+        let (parsed, _) = StandaloneSignature::from_revocation_certificate(
+            &cert.replace(":-----BEGIN", "-----BEGIN"),
+        )
+        .unwrap();
+        assert_eq!(parsed.signature(), sig.signature());
+
+        // the regular armor parser skips the description as well
+        assert!(StandaloneSignature::from_string(&cert).is_ok());
+        assert!(StandaloneSignature::from_revocation_certificate("hello").is_err());
+    }
+
+    #[test]
+    fn test_revoke_key() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key_with_subkeys(&mut rng, "Me <me@mail.com>", 0);
+        let mut public_key = key.public_key().sign(&key, || "".into()).unwrap();
+
+        let reason = RevocationReason::new(RevocationCode::KeyRetired, "retired");
+        let sig = key.revoke(reason, || "".into()).unwrap();
+        assert_eq!(sig.typ(), SignatureType::KeyRevocation);
+        assert_eq!(
+            sig.revocation_reason_code(),
+            Some(&RevocationCode::KeyRetired)
+        );
+        assert_eq!(sig.revocation_reason_string(), Some("retired"));
+        assert!(sig.is_issued_by(&key));
+
+        let armored = StandaloneSignature::new(sig.clone())
+            .to_armored_revocation_string(None)
+            .unwrap();
+        assert!(armored.starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----\n"));
+        let (parsed, _) = StandaloneSignature::from_string(&armored).unwrap();
+        assert_eq!(parsed.signature(), &sig);
+
+        public_key.details.revocation_signatures.push(sig);
+        public_key.verify().unwrap();
+
+        // only key revocations can be exported as such
+        let standalone =
+            StandaloneSignature::new(public_key.details.users[0].signatures[0].clone());
+        assert!(standalone.to_armored_revocation_string(None).is_err());
+    }
+
+    #[test]
+    fn test_revoke_subkey() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut key = gen_key_with_signing_subkey(&mut rng, "Me <me@mail.com>");
+        let mut public_key = key.signed_public_key();
+
+        let signing_id = key.secret_subkeys[0].key_id();
+        assert!(key.signing_subkey().is_some());
+
+        let reason = RevocationReason::new(RevocationCode::KeyCompromised, "");
+        key.revoke_subkey(&signing_id, reason.clone(), || "".into())
+            .unwrap();
+        key.verify().unwrap();
+        assert!(key.secret_subkeys[0].is_revoked(&key.primary_key));
+        assert!(!key.secret_subkeys[1].is_revoked(&key.primary_key));
+        assert!(key.signing_subkey().is_none());
+
+        let sig = key.secret_subkeys[0].signatures.last().unwrap();
+        assert_eq!(sig.typ(), SignatureType::SubkeyRevocation);
+        assert_eq!(
+            sig.revocation_reason_code(),
+            Some(&RevocationCode::KeyCompromised)
+        );
+
+        // the revocation survives a roundtrip
+        let armor = key.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedSecretKey::from_string(&armor).unwrap();
+        assert!(parsed.secret_subkeys[0].is_revoked(&parsed.primary_key));
+
+        // public keys are revoked with the secret primary key
+        let encryption_id = public_key.public_subkeys[1].key_id();
+        public_key
+            .revoke_subkey(&encryption_id, reason.clone(), &key, || "".into())
+            .unwrap();
+        public_key.verify().unwrap();
+        assert!(public_key.public_subkeys[1].is_revoked(&public_key.primary_key));
+
+        assert!(public_key
+            .revoke_subkey(&public_key.key_id(), reason.clone(), &key, || "".into())
+            .is_err());
+        assert!(public_key
+            .revoke_subkey(&encryption_id, reason, &key.secret_subkeys[0], || "".into())
+            .is_err());
+    }
+
+    #[test]
+    fn test_revoke_user_id() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .user_id("Me <me@work.com>")
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+
+        let primary_id = key.details.users[0].id.clone();
+        assert_eq!(key.details.primary_user().unwrap().id, primary_id);
+
+        let reason = RevocationReason::new(RevocationCode::CertUserIdInvalid, "left");
+        key.revoke_user_id(&primary_id, reason.clone(), || "".into())
+            .unwrap();
+        key.verify().unwrap();
+        assert!(key.details.users[0].is_revoked());
+        assert!(!key.details.users[1].is_revoked());
+        assert_eq!(
+            key.details.primary_user().unwrap().id.id(),
+            "Me <me@work.com>"
+        );
+
+        // the revocation is serialized after the certification of the user id
+        let armor = key.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedSecretKey::from_string(&armor).unwrap();
+        parsed.verify().unwrap();
+        assert_eq!(parsed.details.users[0].signatures.len(), 2);
+        assert_eq!(
+            parsed.details.users[0].signatures[1].typ(),
+            SignatureType::CertRevocation
+        );
+        assert_eq!(
+            parsed.details.primary_user().unwrap().id.id(),
+            "Me <me@work.com>"
+        );
+
+        let unknown = crate::packet::UserId::from_str(Default::default(), "Other <me@mail.com>");
+        assert!(key.revoke_user_id(&unknown, reason, || "".into()).is_err());
+    }
+
+    #[test]
+    fn test_add_remove_user_id() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut key = gen_key_with_subkeys(&mut rng, "Me <me@mail.com>", 0);
+
+        key.add_user_id("Me <me@work.com>", || "".into()).unwrap();
+        key.verify().unwrap();
+        assert!(key.add_user_id("Me <me@work.com>", || "".into()).is_err());
+
+        // the preferences are taken from the primary user id
+        let primary_sig = &key.details.users[0].signatures[0];
+        let sig = &key.details.users[1].signatures[0];
+        assert_eq!(key.details.users[1].id.id(), "Me <me@work.com>");
+        assert!(!sig.is_primary());
+        assert_eq!(sig.key_flags(), primary_sig.key_flags());
+        assert_eq!(
+            sig.preferred_symmetric_algs(),
+            primary_sig.preferred_symmetric_algs()
+        );
+        assert_eq!(
+            key.details.primary_user().unwrap().id.id(),
+            "Me <me@mail.com>"
+        );
+
+        let armor = key.to_armored_string(None).unwrap();
+        let (parsed, _) = SignedSecretKey::from_string(&armor).unwrap();
+        parsed.verify().unwrap();
+        assert_eq!(parsed.details.users.len(), 2);
+
+        let primary_id = key.details.users[0].id.clone();
+        key.remove_user_id(&primary_id).unwrap();
+        key.verify().unwrap();
+        assert_eq!(
+            key.details.primary_user().unwrap().id.id(),
+            "Me <me@work.com>"
+        );
+        assert!(key.remove_user_id(&primary_id).is_err());
+
+        let last_id = key.details.users[0].id.clone();
+        assert!(key.remove_user_id(&last_id).is_err());
+    }
+
+    #[test]
+    fn test_verify_detached_file() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key_with_subkeys(&mut rng, "Me <me@mail.com>", 0);
+        let public_key = key.public_key().sign(&key, || "".into()).unwrap();
+
+        let data = b"hello world\n";
+        let sig = SignatureConfigBuilder::default()
+            .typ(SignatureType::Binary)
+            .pub_alg(key.algorithm())
+            .hashed_subpackets(vec![Subpacket::SignatureCreationTime(
+                chrono::Utc::now().trunc_subsecs(0),
+            )])
+            .unhashed_subpackets(vec![Subpacket::Issuer(key.key_id())])
+            .build()
+            .unwrap()
+            .sign(&key, || "".into(), data)
+            .unwrap();
+        let sig = StandaloneSignature::new(sig);
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let data_path = dir.join("rpgp-detached-data.txt");
+        let sig_path = dir.join("rpgp-detached-data.txt.asc");
+        let bin_sig_path = dir.join("rpgp-detached-data.txt.sig");
+        std::fs::write(&data_path, data).unwrap();
+        std::fs::write(&sig_path, sig.to_armored_bytes(None).unwrap()).unwrap();
+        std::fs::write(&bin_sig_path, sig.to_bytes().unwrap()).unwrap();
+
+        let keyring = vec![public_key];
+        let policy = Policy::default();
+        for path in &[&sig_path, &bin_sig_path] {
+            let res = verify_detached_file(path, &data_path, &keyring, &policy).unwrap();
+            assert_eq!(res.fingerprint, key.fingerprint());
+            assert_eq!(res.signing_key_fingerprint, key.fingerprint());
+            assert_eq!(res.created.as_ref(), sig.signature().created());
+        }
+
+        // unknown signer
+        assert!(verify_detached_file(&sig_path, &data_path, &[], &policy).is_err());
+
+        // rejected hash algorithm
+        let strict = Policy {
+            rejected_hash_algorithms: vec![sig.signature().config.hash_alg],
+            ..Policy::default()
+        };
+        assert!(verify_detached_file(&sig_path, &data_path, &keyring, &strict).is_err());
+
+        // modified data
+        std::fs::write(&data_path, b"hello world!\n").unwrap();
+        assert!(verify_detached_file(&sig_path, &data_path, &keyring, &policy).is_err());
+    }
+
+    #[test]
+    fn test_new_detached() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key_with_signing_subkey(&mut rng, "Me <me@mail.com>");
+        let subkey = &key.secret_subkeys[0];
+
+        let data = b"release 1.0\n";
+        let sig = StandaloneSignature::new_detached(
+            &data[..],
+            subkey,
+            || "".into(),
+            HashAlgorithm::SHA2_512,
+        )
+        .unwrap();
+        let armor = sig.to_armored_string(None).unwrap();
+        assert!(armor.starts_with("-----BEGIN PGP SIGNATURE-----"));
+        let (parsed, _) = StandaloneSignature::from_string(&armor).unwrap();
+        assert_eq!(parsed.to_bytes().unwrap(), sig.to_bytes().unwrap());
+
+        let parsed = parsed.signature();
+        assert_eq!(parsed.typ(), SignatureType::Binary);
+        assert_eq!(parsed.config.hash_alg, HashAlgorithm::SHA2_512);
+        assert!(parsed.is_issued_by(subkey));
+        assert!(parsed.created().is_some());
+        parsed.verify(subkey, data).unwrap();
+        assert!(parsed.verify(subkey, b"release 1.0\r\n").is_err());
+
+        let sig = StandaloneSignature::new_detached_text(
+            &data[..],
+            subkey,
+            || "".into(),
+            HashAlgorithm::SHA2_256,
+        )
+        .unwrap();
+        assert_eq!(sig.signature().typ(), SignatureType::Text);
+        sig.verify(subkey, b"release 1.0\r\n").unwrap();
+
+        // the signature is bound to the signing subkey
+        let keyring = [key.signed_public_key()];
+        let res = verify_with_pinned(
+            &data[..],
+            &sig,
+            &keyring,
+            &[key.fingerprint()],
+            &Policy::default(),
+        )
+        .unwrap();
+        assert_eq!(res.signing_key_fingerprint, subkey.fingerprint());
+    }
+
+    #[test]
+    fn test_verify_detached_reader() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key_with_signing_subkey(&mut rng, "Release <release@mail.com>");
+        let other = gen_key_with_signing_subkey(&mut rng, "Other <other@mail.com>");
+        let subkey = &key.secret_subkeys[0];
+
+        // larger than the internal buffer, so the data is hashed in multiple chunks
+        let data = b"a line of the release\n".repeat(10_000);
+        let sigs = vec![
+            StandaloneSignature::new_detached(
+                &data[..],
+                &other,
+                || "".into(),
+                HashAlgorithm::SHA2_256,
+            )
+            .unwrap(),
+            StandaloneSignature::new_detached_text(
+                &data[..],
+                subkey,
+                || "".into(),
+                HashAlgorithm::SHA2_512,
+            )
+            .unwrap(),
+        ];
+
+        let keyring = vec![key.signed_public_key()];
+        let policy = Policy::default();
+        let res = verify_detached_reader(&sigs, &data[..], &keyring, &policy).unwrap();
+        assert_eq!(res.fingerprint, key.fingerprint());
+        assert_eq!(res.signing_key_fingerprint, subkey.fingerprint());
+        assert_eq!(res.created.as_ref(), sigs[1].signature().created());
+
+        // the text signature does not depend on the line endings
+        let crlf = b"a line of the release\r\n".repeat(10_000);
+        assert!(verify_detached_reader(&sigs, &crlf[..], &keyring, &policy).is_ok());
+
+        let keyring = vec![other.signed_public_key()];
+        let res = verify_detached_reader(&sigs, &data[..], &keyring, &policy).unwrap();
+        assert_eq!(res.signing_key_fingerprint, other.fingerprint());
+        assert!(verify_detached_reader(&sigs, &crlf[..], &keyring, &policy).is_err());
+
+        assert!(verify_detached_reader(&sigs, &data[1..], &keyring, &policy).is_err());
+        assert!(verify_detached_reader(&sigs, &data[..], &[], &policy).is_err());
+        assert!(verify_detached_reader(&[], &data[..], &keyring, &policy).is_err());
+    }
+
+    #[test]
+    fn test_new_detached_with_keys() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let alice = gen_key_with_subkeys(&mut rng, "Alice <alice@mail.com>", 0);
+        let bob = gen_key_with_subkeys(&mut rng, "Bob <bob@mail.com>", 0);
+
+        let data = b"release 1.0\n";
+        let sigs = StandaloneSignature::new_detached_with_keys(
+            &data[..],
+            &[&alice, &bob],
+            |_| "".into(),
+            HashAlgorithm::SHA2_256,
+        )
+        .unwrap();
+        assert_eq!(sigs.len(), 2);
+        sigs[0].verify(&alice, data).unwrap();
+        sigs[1].verify(&bob, data).unwrap();
+
+        let policy = Policy::default();
+        let keyring = vec![alice.signed_public_key(), bob.signed_public_key()];
+        let results = verify_detached_signatures(&sigs, &data[..], &keyring, &policy).unwrap();
+        assert_eq!(results.len(), 2);
+        for (res, key) in results.iter().zip(&[&alice, &bob]) {
+            assert_eq!(res.validity, SignatureValidity::Valid);
+            assert_eq!(res.fingerprint, Some(key.fingerprint()));
+            assert_eq!(res.key_id, Some(key.key_id()));
+        }
+
+        let results = verify_detached_signatures(&sigs, &data[..], &keyring[1..], &policy).unwrap();
+        assert_eq!(results[0].validity, SignatureValidity::UnknownKey);
+        assert_eq!(results[0].fingerprint, None);
+        assert_eq!(results[1].validity, SignatureValidity::Valid);
+
+        let results =
+            verify_detached_signatures(&sigs, &b"release 2.0\n"[..], &keyring, &policy).unwrap();
+        assert!(results
+            .iter()
+            .all(|res| res.validity == SignatureValidity::Invalid));
+
+        // the armored signatures can be stored in one file
+        let mut armored = Vec::new();
+        for sig in &sigs {
+            sig.to_armored_writer(&mut armored, None).unwrap();
+        }
+        let parsed = StandaloneSignature::from_armor_many(Cursor::new(&armored))
+            .unwrap()
+            .0
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(parsed.len(), 2);
+        let res = verify_detached_reader(&parsed, &data[..], &keyring[1..], &policy).unwrap();
+        assert_eq!(res.fingerprint, bob.fingerprint());
+
+        // text signatures stay valid when the line endings are converted
+        let sigs = StandaloneSignature::new_detached_text_with_keys(
+            &data[..],
+            &[&alice, &bob],
+            |_| "".into(),
+            HashAlgorithm::SHA2_256,
+        )
+        .unwrap();
+        let results =
+            verify_detached_signatures(&sigs, &b"release 1.0\r\n"[..], &keyring, &policy).unwrap();
+        for res in &results {
+            assert_eq!(res.validity, SignatureValidity::Valid);
+        }
+        assert_eq!(sigs[0].signature().typ(), SignatureType::Text);
+
+        // each key is unlocked with its own password
+        let bob = bob
+            .change_passphrase(|| "".into(), Some("bob"), &S2kParams::default())
+            .unwrap();
+        let sigs = StandaloneSignature::new_detached_with_keys(
+            &data[..],
+            &[&alice, &bob],
+            |key| {
+                if key.fingerprint() == bob.fingerprint() {
+                    "bob".into()
+                } else {
+                    "".into()
+                }
+            },
+            HashAlgorithm::SHA2_256,
+        )
+        .unwrap();
+        sigs[1].verify(&bob, data).unwrap();
+        assert!(StandaloneSignature::new_detached_with_keys(
+            &data[..],
+            &[&alice, &bob],
+            |_| "".into(),
+            HashAlgorithm::SHA2_256,
+        )
+        .is_err());
+
+        assert!(StandaloneSignature::new_detached_with_keys(
+            &data[..],
+            &[] as &[&SignedSecretKey],
+            |_| "".into(),
+            HashAlgorithm::SHA2_256,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_verify_with_pinned() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = gen_key_with_signing_subkey(&mut rng, "Release <release@mail.com>");
+        let other = gen_key_with_signing_subkey(&mut rng, "Other <other@mail.com>");
+        let subkey = &key.secret_subkeys[0].key;
+
+        let data = b"release 1.0";
+        let config = || {
+            SignatureConfigBuilder::default()
+                .typ(SignatureType::Binary)
+                .pub_alg(key.algorithm())
+                .hashed_subpackets(vec![Subpacket::SignatureCreationTime(
+                    chrono::Utc::now().trunc_subsecs(0),
+                )])
+                .unhashed_subpackets(vec![])
+                .build()
+                .unwrap()
+        };
+        let by_primary = StandaloneSignature::new(
+            config()
+                .with_issuer(&key)
+                .sign(&key, || "".into(), &data[..])
+                .unwrap(),
+        );
+        let by_subkey = StandaloneSignature::new(
+            config()
+                .with_issuer(subkey)
+                .sign(subkey, || "".into(), &data[..])
+                .unwrap(),
+        );
+
+        let mut keyring = vec![other.signed_public_key(), key.signed_public_key()];
+        let policy = Policy::default();
+        let primary_fp = key.fingerprint();
+        let subkey_fp = subkey.fingerprint();
+
+        let res = verify_with_pinned(data, &by_primary, &keyring, &[primary_fp.clone()], &policy)
+            .unwrap();
+        assert_eq!(res.fingerprint, primary_fp);
+        assert_eq!(res.signing_key_fingerprint, primary_fp);
+
+        // pinning the primary key accepts its signing subkeys
+        let res =
+            verify_with_pinned(data, &by_subkey, &keyring, &[primary_fp.clone()], &policy).unwrap();
+        assert_eq!(res.fingerprint, primary_fp);
+        assert_eq!(res.signing_key_fingerprint, subkey_fp);
+
+        // pinning a subkey only accepts that subkey
+        let pinned = vec![subkey_fp];
+        assert!(verify_with_pinned(data, &by_subkey, &keyring, &pinned, &policy).is_ok());
+        assert!(verify_with_pinned(data, &by_primary, &keyring, &pinned, &policy).is_err());
+
+        // keys that are not pinned, or not in the keyring
+        let pinned = vec![other.fingerprint()];
+        assert!(verify_with_pinned(data, &by_primary, &keyring, &pinned, &policy).is_err());
+        let pinned = vec![primary_fp.clone()];
+        assert!(verify_with_pinned(data, &by_primary, &keyring[..1], &pinned, &policy).is_err());
+
+        assert!(
+            verify_with_pinned(b"release 1.1", &by_primary, &keyring, &pinned, &policy).is_err()
+        );
+
+        // only signatures over data are accepted
+        let revocation = key
+            .revoke(
+                RevocationReason::new(RevocationCode::KeyCompromised, ""),
+                || "".into(),
+            )
+            .unwrap();
+        let wrong_type = StandaloneSignature::new(revocation.clone());
+        assert!(verify_with_pinned(data, &wrong_type, &keyring, &pinned, &policy).is_err());
+
+        // as are signatures made before the key existed
+        let backdated = StandaloneSignature::new(
+            SignatureConfigBuilder::default()
+                .typ(SignatureType::Binary)
+                .pub_alg(key.algorithm())
+                .hashed_subpackets(vec![Subpacket::SignatureCreationTime(
+                    *key.created_at() - chrono::Duration::days(1),
+                )])
+                .unhashed_subpackets(vec![])
+                .build()
+                .unwrap()
+                .with_issuer(&key)
+                .sign(&key, || "".into(), &data[..])
+                .unwrap(),
+        );
+        assert!(verify_with_pinned(data, &backdated, &keyring, &pinned, &policy).is_err());
+
+        // revoked keys are rejected
+        keyring[1].details.revocation_signatures.push(revocation);
+        assert!(verify_with_pinned(data, &by_primary, &keyring, &pinned, &policy).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use chrono::Duration;
+
+use crate::armor::Headers;
+use crate::composed::key::SubkeyParams;
+use crate::composed::signed_key::{Preferences, SignedSecretKey};
+use crate::errors::Result;
+use crate::packet::{RevocationReason, UserId};
+use crate::types::{KeyId, S2kParams};
+
+/// Bundles the editing operations of a [`SignedSecretKey`], for frontends that apply
+/// a series of edits to a key.
+///
+/// Each edit is applied to a copy of the key, which only replaces the edited key if the
+/// edit succeeds. The result is verified before it is returned or serialized.
+#[derive(Debug, Clone)]
+pub struct KeyEditor {
+    key: SignedSecretKey,
+}
+
+impl KeyEditor {
+    pub fn new(key: SignedSecretKey) -> Self {
+        KeyEditor { key }
+    }
+
+    /// Returns the key with all edits applied so far.
+    pub fn key(&self) -> &SignedSecretKey {
+        &self.key
+    }
+
+    fn apply<G>(&mut self, edit: G) -> Result<&mut Self>
+    where
+        G: FnOnce(SignedSecretKey) -> Result<SignedSecretKey>,
+    {
+        self.key = edit(self.key.clone())?;
+
+        Ok(self)
+    }
+
+    /// See [`SignedSecretKey::add_subkey`].
+    pub fn add_subkey<F>(&mut self, params: SubkeyParams, key_pw: F) -> Result<&mut Self>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        self.apply(|key| key.add_subkey(params, key_pw))
+    }
+
+    /// See [`SignedSecretKey::revoke_subkey`].
+    pub fn revoke_subkey<F>(
+        &mut self,
+        key_id: &KeyId,
+        reason: RevocationReason,
+        key_pw: F,
+    ) -> Result<&mut Self>
+    where
+        F: FnOnce() -> String,
+    {
+        self.apply(|mut key| {
+            key.revoke_subkey(key_id, reason, key_pw)?;
+            Ok(key)
+        })
+    }
+
+    /// See [`SignedSecretKey::revoke_user_id`].
+    pub fn revoke_user_id<F>(
+        &mut self,
+        id: &UserId,
+        reason: RevocationReason,
+        key_pw: F,
+    ) -> Result<&mut Self>
+    where
+        F: FnOnce() -> String,
+    {
+        self.apply(|mut key| {
+            key.revoke_user_id(id, reason, key_pw)?;
+            Ok(key)
+        })
+    }
+
+    /// See [`SignedSecretKey::add_user_id`].
+    pub fn add_user_id<F>(&mut self, id: &str, key_pw: F) -> Result<&mut Self>
+    where
+        F: FnOnce() -> String,
+    {
+        self.apply(|mut key| {
+            key.add_user_id(id, key_pw)?;
+            Ok(key)
+        })
+    }
+
+    /// See [`SignedSecretKey::remove_user_id`].
+    pub fn remove_user_id(&mut self, id: &UserId) -> Result<&mut Self> {
+        self.apply(|mut key| {
+            key.remove_user_id(id)?;
+            Ok(key)
+        })
+    }
+
+    /// See [`SignedSecretKey::set_expiration`].
+    pub fn set_expiration<F>(
+        &mut self,
+        expiration: Option<Duration>,
+        key_pw: F,
+    ) -> Result<&mut Self>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        self.apply(|mut key| {
+            key.set_expiration(expiration, key_pw)?;
+            Ok(key)
+        })
+    }
+
+    /// See [`SignedSecretKey::set_preferences`].
+    pub fn set_preferences<F>(&mut self, preferences: &Preferences, key_pw: F) -> Result<&mut Self>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        self.apply(|mut key| {
+            key.set_preferences(preferences, key_pw)?;
+            Ok(key)
+        })
+    }
+
+    /// See [`SignedSecretKey::change_passphrase`].
+    pub fn change_passphrase<F>(
+        &mut self,
+        old_pw: F,
+        new_pw: Option<&str>,
+        s2k_params: &S2kParams,
+    ) -> Result<&mut Self>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        self.apply(|key| key.change_passphrase(old_pw, new_pw, s2k_params))
+    }
+
+    /// Verifies the edited key and returns it.
+    pub fn finish(self) -> Result<SignedSecretKey> {
+        self.key.verify()?;
+
+        Ok(self.key)
+    }
+
+    /// Verifies the edited key and returns it armored.
+    pub fn to_armored_string(&self, headers: Option<&Headers>) -> Result<String> {
+        self.key.verify()?;
+        self.key.to_armored_string(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::{Deserializable, KeyType, SecretKeyParamsBuilder, SubkeyParamsBuilder};
+    use crate::packet::RevocationCode;
+    use crate::types::{KeyTrait, SecretKeyTrait};
+
+    #[test]
+    fn test_key_editor() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .user_id("Me <me@work.com>")
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+        let work_id = key.details.users[1].id.clone();
+
+        let mut editor = KeyEditor::new(key.clone());
+        editor
+            .add_subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .build()
+                    .unwrap(),
+                || "".into(),
+            )
+            .unwrap()
+            .revoke_user_id(
+                &work_id,
+                RevocationReason::new(RevocationCode::CertUserIdInvalid, "left"),
+                || "".into(),
+            )
+            .unwrap();
+
+        // a failing edit leaves the key unchanged
+        let before = editor.key().clone();
+        let unknown = UserId::from_str(Default::default(), "Other <me@mail.com>");
+        assert!(editor
+            .revoke_user_id(
+                &unknown,
+                RevocationReason::new(RevocationCode::NoReason, ""),
+                || "".into(),
+            )
+            .is_err());
+        assert_eq!(editor.key(), &before);
+
+        let subkey_id = editor.key().secret_subkeys[0].key_id();
+        editor
+            .revoke_subkey(
+                &subkey_id,
+                RevocationReason::new(RevocationCode::KeySuperseded, ""),
+                || "".into(),
+            )
+            .unwrap()
+            .add_user_id("Me <me@home.com>", || "".into())
+            .unwrap()
+            .set_expiration(Some(Duration::days(30)), || "".into())
+            .unwrap()
+            .change_passphrase(|| "".into(), Some("new"), &S2kParams::default())
+            .unwrap();
+
+        let armor = editor.to_armored_string(None).unwrap();
+        let edited = editor.finish().unwrap();
+        assert_eq!(edited.fingerprint(), key.fingerprint());
+        assert_eq!(
+            edited.expires_at(),
+            Some(*edited.primary_key.created_at() + Duration::days(30))
+        );
+        assert!(edited.details.users[1].is_revoked());
+        assert_eq!(edited.details.users[2].id.id(), "Me <me@home.com>");
+        assert!(edited.secret_subkeys[0].is_revoked(&edited.primary_key));
+        assert!(edited.primary_key.secret_params().is_encrypted());
+        edited
+            .primary_key
+            .unlock(|| "new".into(), |_| Ok(()))
+            .unwrap();
+
+        let (parsed, _) = SignedSecretKey::from_string(&armor).unwrap();
+        assert_eq!(parsed, edited);
+    }
+}
@@ -0,0 +1,316 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use crate::composed::signed_key::{key_expiration_offset, SignedPublicKey, SignedPublicSubKey};
+use crate::crypto::PublicKeyAlgorithm;
+use crate::errors::{Error, Result};
+use crate::types::{KeyId, KeyTrait, PublicKeyTrait};
+
+/// The bit in the first octet of the features subpacket, that signals support for
+/// version 2 SEIPD packets.
+const FEATURE_SEIPD_V2: u8 = 0x08;
+
+/// Why a component key can not be used for encryption.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnusableReason {
+    /// None of the binding signatures allows encrypting communications or storage.
+    MissingEncryptionFlags,
+    /// The key expired at the given time.
+    Expired(DateTime<Utc>),
+    /// The key was created after the time it was checked for, at the given time.
+    NotYetValid(DateTime<Utc>),
+    /// The key carries a revocation signature.
+    Revoked,
+    /// The algorithm of the key can not be used for encryption.
+    UnsupportedAlgorithm(PublicKeyAlgorithm),
+}
+
+impl UnusableReason {
+    /// Returns a hint on how the key has to be fixed to be usable again.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            UnusableReason::MissingEncryptionFlags => "add a subkey that can encrypt",
+            UnusableReason::Expired(_) => "extend the expiration time of the key",
+            UnusableReason::NotYetValid(_) => "check the clock of the system that created the key",
+            UnusableReason::Revoked => "add a new subkey that can encrypt",
+            UnusableReason::UnsupportedAlgorithm(_) => "add an RSA or ECDH subkey that can encrypt",
+        }
+    }
+}
+
+impl fmt::Display for UnusableReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnusableReason::MissingEncryptionFlags => write!(f, "missing encryption key flags"),
+            UnusableReason::Expired(at) => write!(f, "expired at {}", at),
+            UnusableReason::NotYetValid(at) => write!(f, "not valid before {}", at),
+            UnusableReason::Revoked => write!(f, "revoked"),
+            UnusableReason::UnsupportedAlgorithm(alg) => {
+                write!(f, "{:?} can not be used for encryption", alg)
+            }
+        }
+    }
+}
+
+/// A component key that was skipped when looking for encryption keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnusableKey {
+    pub key_id: KeyId,
+    pub reason: UnusableReason,
+}
+
+impl fmt::Display for UnusableKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}: {} ({})",
+            self.key_id,
+            self.reason,
+            self.reason.hint()
+        )
+    }
+}
+
+/// Formats the list of skipped keys, for [`Error::NoEncryptionKey`].
+pub(crate) fn format_unusable(skipped: &[UnusableKey]) -> String {
+    if skipped.is_empty() {
+        return "the key has no subkeys (add a subkey that can encrypt)".to_string();
+    }
+
+    skipped
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns why `subkey` can not be used for encryption at the time `at`, if it can not.
+///
+/// Only signatures made by `primary_key` that verify are considered, anyone can attach
+/// bindings or revocations to a subkey.
+pub(crate) fn check_subkey(
+    primary_key: &impl PublicKeyTrait,
+    subkey: &SignedPublicSubKey,
+    at: DateTime<Utc>,
+) -> Option<UnusableReason> {
+    if subkey.is_revoked(primary_key) {
+        return Some(UnusableReason::Revoked);
+    }
+
+    // only the newest binding signature counts, older ones might grant other flags
+    let binding = match subkey.latest_binding(primary_key) {
+        Some(binding) => binding,
+        None => return Some(UnusableReason::MissingEncryptionFlags),
+    };
+    if let Some(offset) = key_expiration_offset(binding) {
+        let expires_at = *subkey.created_at() + offset;
+        if expires_at <= at {
+            return Some(UnusableReason::Expired(expires_at));
+        }
+    }
+    if subkey.created_at() > &at {
+        return Some(UnusableReason::NotYetValid(*subkey.created_at()));
+    }
+
+    let flags = binding.key_flags();
+    if !flags.encrypt_comms() && !flags.encrypt_storage() {
+        return Some(UnusableReason::MissingEncryptionFlags);
+    }
+
+    match subkey.algorithm() {
+        PublicKeyAlgorithm::RSA
+        | PublicKeyAlgorithm::RSAEncrypt
+        | PublicKeyAlgorithm::ECDH
+        | PublicKeyAlgorithm::X25519
+        | PublicKeyAlgorithm::X448 => None,
+        alg => Some(UnusableReason::UnsupportedAlgorithm(alg)),
+    }
+}
+
+impl SignedPublicKey {
+    /// Returns the subkeys that can be used to encrypt to this key at the time `at`.
+    ///
+    /// Fails with [`Error::NoEncryptionKey`] if there are none, listing why each
+    /// key was skipped.
+    pub fn encryption_subkeys(&self, at: DateTime<Utc>) -> Result<Vec<&SignedPublicSubKey>> {
+        let key_id = self.key_id();
+
+        // if the whole key is unusable, so are all of its subkeys
+        let primary_reason = if self.details.is_revoked(&self.primary_key) {
+            Some(UnusableReason::Revoked)
+        } else {
+            self.expires_at()
+                .filter(|expires_at| expires_at <= &at)
+                .map(UnusableReason::Expired)
+        };
+        if let Some(reason) = primary_reason {
+            return Err(Error::NoEncryptionKey {
+                skipped: vec![UnusableKey {
+                    key_id: key_id.clone(),
+                    reason,
+                }],
+                key_id,
+            });
+        }
+
+        let mut keys = Vec::new();
+        let mut skipped = Vec::new();
+        for subkey in &self.public_subkeys {
+            match check_subkey(&self.primary_key, subkey, at) {
+                Some(reason) => skipped.push(UnusableKey {
+                    key_id: subkey.key_id(),
+                    reason,
+                }),
+                None => keys.push(subkey),
+            }
+        }
+
+        if keys.is_empty() {
+            return Err(Error::NoEncryptionKey { key_id, skipped });
+        }
+
+        Ok(keys)
+    }
+
+    /// Checks if the newest self-signature of the key advertises support for version 2
+    /// SEIPD packets, which are encrypted using AEAD.
+    pub fn supports_seipd_v2(&self) -> bool {
+        self.details
+            .latest_self_signature(&self.primary_key)
+            .and_then(|sig| sig.features().first())
+            .map_or(false, |features| features & FEATURE_SEIPD_V2 != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, SubsecRound};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::{KeyType, SecretKeyParamsBuilder, SubkeyParams, SubkeyParamsBuilder};
+    use crate::packet::{RevocationCode, RevocationReason, Subpacket};
+    use crate::types::SecretKeyTrait;
+
+    fn public_key(subkeys: Vec<SubkeyParams>) -> SignedPublicKey {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .subkeys(subkeys)
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+
+        key.signed_public_key()
+    }
+
+    #[test]
+    fn test_encryption_subkeys() {
+        let now = Utc::now();
+
+        let key = public_key(vec![]);
+        let err = key.encryption_subkeys(now).unwrap_err();
+        assert!(err.to_string().contains("no subkeys"));
+
+        let key = public_key(vec![
+            SubkeyParamsBuilder::default()
+                .key_type(KeyType::EdDSA)
+                .can_sign(true)
+                .build()
+                .unwrap(),
+            SubkeyParamsBuilder::default()
+                .key_type(KeyType::ECDH)
+                .can_encrypt(true)
+                .expiration(Some(std::time::Duration::from_secs(1)))
+                .build()
+                .unwrap(),
+        ]);
+        match key.encryption_subkeys(now + Duration::days(1)) {
+            Err(Error::NoEncryptionKey { key_id, skipped }) => {
+                assert_eq!(key_id, key.key_id());
+                assert_eq!(skipped.len(), 2);
+                assert_eq!(skipped[0].key_id, key.public_subkeys[0].key_id());
+                assert_eq!(skipped[0].reason, UnusableReason::MissingEncryptionFlags);
+                assert_eq!(
+                    skipped[1].reason,
+                    UnusableReason::Expired(
+                        key.public_subkeys[1].expires_at(&key.primary_key).unwrap()
+                    )
+                );
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let keys = key.encryption_subkeys(now).unwrap();
+        assert_eq!(keys, vec![&key.public_subkeys[1]]);
+    }
+
+    #[test]
+    fn test_forged_signatures() {
+        let subkey = || {
+            SubkeyParamsBuilder::default()
+                .key_type(KeyType::ECDH)
+                .can_encrypt(true)
+                .build()
+                .unwrap()
+        };
+        let key = public_key(vec![subkey()]);
+        let attacker = {
+            let mut rng = ChaCha8Rng::seed_from_u64(1);
+            SecretKeyParamsBuilder::default()
+                .key_type(KeyType::EdDSA)
+                .can_create_certificates(true)
+                .primary_user_id("Attacker <attacker@mail.com>".into())
+                .subkey(subkey())
+                .build()
+                .unwrap()
+                .generate_with_rng(&mut rng)
+                .unwrap()
+                .sign(|| "".into())
+                .unwrap()
+        };
+
+        // a subkey of the attacker, with a copy of a real binding claiming to be newer
+        let mut forged = key.clone();
+        let mut binding = key.public_subkeys[0].signatures[0].clone();
+        binding.config.hashed_subpackets.retain(|p| match p {
+            Subpacket::SignatureCreationTime(_) => false,
+            _ => true,
+        });
+        binding
+            .config
+            .hashed_subpackets
+            .push(Subpacket::SignatureCreationTime(
+                Utc::now().trunc_subsecs(0) + Duration::days(1),
+            ));
+        forged.public_subkeys.push(SignedPublicSubKey::new(
+            attacker.secret_subkeys[0].key.public_key(),
+            vec![binding],
+        ));
+        // and a revocation, which was not made by the key
+        forged.details.revocation_signatures.push(
+            attacker
+                .revoke(RevocationReason::new(RevocationCode::NoReason, ""), || {
+                    "".into()
+                })
+                .unwrap(),
+        );
+
+        let keys = forged.encryption_subkeys(Utc::now()).unwrap();
+        assert_eq!(keys, vec![&key.public_subkeys[0]]);
+        assert!(!forged.details.is_revoked(&forged.primary_key));
+        assert_eq!(
+            check_subkey(&forged.primary_key, &forged.public_subkeys[1], Utc::now()),
+            Some(UnusableReason::MissingEncryptionFlags)
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::io;
+
+use rand::{CryptoRng, Rng};
+
+use crate::composed::signed_key::{SignedSecretKey, SignedSecretSubKey};
+use crate::crypto::{HashAlgorithm, PublicKeyAlgorithm};
+use crate::errors::{Error, Result};
+use crate::packet::SignatureType;
+use crate::types::{
+    KeyId, KeyTrait, KeyVersion, Mpi, PublicKeyTrait, SecretKeyRepr, SecretKeyTrait,
+};
+
+/// Creates signatures with secret key material that is not available to this crate,
+/// e.g. keys stored on an OpenPGP smartcard.
+pub trait ExternalSigner {
+    /// Signs `digest`, which was computed using `hash`, with the secret key that has the
+    /// given fingerprint. `card_serial` is the serial number of the card the key was
+    /// diverted to, if it is known.
+    ///
+    /// Returns the signature in the format of the key algorithm.
+    fn sign(
+        &self,
+        fingerprint: &[u8],
+        card_serial: Option<&[u8]>,
+        hash: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<Mpi>>;
+}
+
+impl<'a, T: ExternalSigner> ExternalSigner for &'a T {
+    fn sign(
+        &self,
+        fingerprint: &[u8],
+        card_serial: Option<&[u8]>,
+        hash: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Vec<Mpi>> {
+        (*self).sign(fingerprint, card_serial, hash, digest)
+    }
+}
+
+/// A secret key, which makes its signatures using an [`ExternalSigner`].
+///
+/// It can be used everywhere a [`SecretKeyTrait`] is expected, for example to sign
+/// messages. Operations that need the secret material itself, like decryption, fail with
+/// [`Error::SecretKeyNotPresent`].
+pub struct ExternalKey<'a, K, S> {
+    key: &'a K,
+    card_serial: Option<&'a [u8]>,
+    signer: S,
+}
+
+impl<K: SecretKeyTrait, S: ExternalSigner> KeyTrait for ExternalKey<'_, K, S> {
+    fn fingerprint(&self) -> Vec<u8> {
+        self.key.fingerprint()
+    }
+
+    fn key_id(&self) -> KeyId {
+        self.key.key_id()
+    }
+
+    fn algorithm(&self) -> PublicKeyAlgorithm {
+        self.key.algorithm()
+    }
+
+    fn version(&self) -> KeyVersion {
+        self.key.version()
+    }
+}
+
+impl<K: SecretKeyTrait, S: ExternalSigner> PublicKeyTrait for ExternalKey<'_, K, S> {
+    fn verify_signature(&self, hash: HashAlgorithm, data: &[u8], sig: &[Mpi]) -> Result<()> {
+        self.key.verify_signature(hash, data, sig)
+    }
+
+    fn encrypt<R: CryptoRng + Rng>(&self, rng: &mut R, plain: &[u8]) -> Result<Vec<Mpi>> {
+        self.key.encrypt(rng, plain)
+    }
+
+    fn to_writer_old(&self, writer: &mut impl io::Write) -> Result<()> {
+        self.key.to_writer_old(writer)
+    }
+}
+
+impl<K: SecretKeyTrait, S: ExternalSigner> SecretKeyTrait for ExternalKey<'_, K, S> {
+    type PublicKey = K::PublicKey;
+
+    fn unlock<F, G>(&self, _pw: F, _work: G) -> Result<()>
+    where
+        F: FnOnce() -> String,
+        G: FnOnce(&SecretKeyRepr) -> Result<()>,
+    {
+        Err(Error::SecretKeyNotPresent {
+            key_id: self.key.key_id(),
+        })
+    }
+
+    /// Signs using the external signer, the passphrase is not used.
+    ///
+    /// The signature is verified, to detect cards holding a different key.
+    fn create_signature<F>(&self, _key_pw: F, hash: HashAlgorithm, data: &[u8]) -> Result<Vec<Mpi>>
+    where
+        F: FnOnce() -> String,
+    {
+        let sig = self
+            .signer
+            .sign(&self.key.fingerprint(), self.card_serial, hash, data)?;
+        self.key.verify_signature(hash, data, &sig)?;
+
+        Ok(sig)
+    }
+
+    fn public_key(&self) -> Self::PublicKey {
+        self.key.public_key()
+    }
+
+    fn record_signature(&self, typ: SignatureType, hash_alg: HashAlgorithm, digest: &[u8]) {
+        self.key.record_signature(typ, hash_alg, digest)
+    }
+}
+
+impl SignedSecretKey {
+    /// Returns a key, which makes signatures with the primary key using `signer`,
+    /// e.g. because the primary key was diverted to a smartcard.
+    pub fn with_external_signer<S: ExternalSigner>(&self, signer: S) -> ExternalKey<'_, Self, S> {
+        ExternalKey {
+            key: self,
+            card_serial: self.primary_key.card_serial(),
+            signer,
+        }
+    }
+}
+
+impl SignedSecretSubKey {
+    /// Returns a key, which makes signatures with this subkey using `signer`,
+    /// e.g. because the subkey was diverted to a smartcard.
+    pub fn with_external_signer<S: ExternalSigner>(&self, signer: S) -> ExternalKey<'_, Self, S> {
+        ExternalKey {
+            key: self,
+            card_serial: self.key.card_serial(),
+            signer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use zeroize::Zeroize;
+
+    use crate::composed::test_keys::gen_key_with_signing_subkey;
+    use crate::composed::{Deserializable, Message};
+    use crate::types::{SecretKeyStub, SecretParams};
+
+    /// Pretends to be a smartcard, holding a copy of the secret key material.
+    struct Card {
+        serial: Vec<u8>,
+        key: SignedSecretSubKey,
+    }
+
+    impl ExternalSigner for Card {
+        fn sign(
+            &self,
+            _fingerprint: &[u8],
+            card_serial: Option<&[u8]>,
+            hash: HashAlgorithm,
+            digest: &[u8],
+        ) -> Result<Vec<Mpi>> {
+            ensure_eq!(card_serial, Some(&self.serial[..]), "wrong card");
+            self.key.create_signature(|| "".into(), hash, digest)
+        }
+    }
+
+    #[test]
+    fn test_divert_to_card() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut key = gen_key_with_signing_subkey(&mut rng, "Me <me@mail.com>");
+        let public_key = key.signed_public_key();
+        let serial = vec![0xd2, 0x76, 0x00, 0x01, 0x24, 0x01];
+        let card = Card {
+            serial: serial.clone(),
+            key: key.secret_subkeys[0].clone(),
+        };
+
+        // move the subkey to the card
+        let subkey = &mut key.secret_subkeys[0].key;
+        assert_eq!(subkey.card_serial(), None);
+        subkey.secret_params.zeroize();
+        subkey.secret_params = SecretParams::Stub(SecretKeyStub::DivertToCard {
+            serial: serial.clone(),
+        });
+        let armor = key.to_armored_string(None).unwrap();
+        let (key, _) = SignedSecretKey::from_string(&armor).unwrap();
+        key.verify().unwrap();
+        let subkey = &key.secret_subkeys[0];
+        assert_eq!(subkey.key.card_serial(), Some(&serial[..]));
+
+        let msg = Message::new_literal("hello.txt", "hello world");
+        match msg
+            .clone()
+            .sign(subkey, || "".into(), HashAlgorithm::SHA2_256)
+        {
+            Err(Error::SecretKeyNotPresent { key_id }) => assert_eq!(key_id, subkey.key_id()),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let signed = msg
+            .clone()
+            .sign(
+                &subkey.with_external_signer(&card),
+                || "".into(),
+                HashAlgorithm::SHA2_256,
+            )
+            .unwrap();
+        signed.verify(&public_key.public_subkeys[0]).unwrap();
+
+        // a card with a different key is detected
+        let other = Card {
+            serial,
+            key: gen_key_with_signing_subkey(&mut rng, "Other <other@mail.com>").secret_subkeys[0]
+                .clone(),
+        };
+        assert!(msg
+            .sign(
+                &subkey.with_external_signer(&other),
+                || "".into(),
+                HashAlgorithm::SHA2_256,
+            )
+            .is_err());
+    }
+}
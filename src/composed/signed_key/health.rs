@@ -0,0 +1,324 @@
+use chrono::{DateTime, Utc};
+
+use crate::composed::signed_key::{SignedPublicKey, SignedPublicSubKey};
+use crate::composed::Policy;
+use crate::crypto::{HashAlgorithm, PublicKeyAlgorithm};
+use crate::errors::Result;
+use crate::packet::{Signature, SignatureType};
+use crate::types::{KeyId, KeyTrait, PublicParams, Tag};
+use crate::util::bit_size;
+
+/// A single issue found by [`SignedPublicKey::health_report`].
+///
+/// `key_id` always refers to the primary key or subkey the finding belongs to,
+/// findings about user ids and attributes refer to the primary key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthFinding {
+    /// The key is smaller than allowed by the policy.
+    WeakKey {
+        key_id: KeyId,
+        algorithm: PublicKeyAlgorithm,
+        bits: usize,
+    },
+    /// A self-signature uses a hash algorithm rejected by the policy.
+    RejectedHashAlgorithm {
+        key_id: KeyId,
+        typ: SignatureType,
+        hash_alg: HashAlgorithm,
+    },
+    /// A self-signature uses SHA-1, which should be replaced.
+    Sha1SelfSignature { key_id: KeyId, typ: SignatureType },
+    /// A self-signature does not verify.
+    InvalidSelfSignature {
+        key_id: KeyId,
+        typ: SignatureType,
+        reason: String,
+    },
+    /// A signing subkey has no valid primary key binding signature.
+    MissingBackSignature { key_id: KeyId },
+    /// The key expired before the time the report was made for.
+    Expired {
+        key_id: KeyId,
+        expired_at: DateTime<Utc>,
+    },
+    /// The key carries a revocation signature, which verifies.
+    Revoked { key_id: KeyId },
+    /// The key has no user ids, and is not acceptable as a bare key under the policy.
+    MissingUserIds { key_id: KeyId, reason: String },
+}
+
+/// The result of [`SignedPublicKey::health_report`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct HealthReport {
+    pub findings: Vec<HealthFinding>,
+}
+
+impl HealthReport {
+    /// Returns true if no issues were found.
+    pub fn is_healthy(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    fn check_key(&mut self, key: &impl KeyTrait, params: &PublicParams, policy: &Policy) {
+        let bits = match params {
+            PublicParams::RSA { n, .. } => bit_size(n),
+            PublicParams::DSA { p, .. } | PublicParams::Elgamal { p, .. } => bit_size(p),
+            _ => return,
+        };
+
+        if bits < policy.min_key_bits {
+            self.findings.push(HealthFinding::WeakKey {
+                key_id: key.key_id(),
+                algorithm: key.algorithm(),
+                bits,
+            });
+        }
+    }
+
+    fn check_signature(
+        &mut self,
+        key_id: &KeyId,
+        sig: &Signature,
+        policy: &Policy,
+        verified: Result<()>,
+    ) {
+        if let Err(err) = verified {
+            self.findings.push(HealthFinding::InvalidSelfSignature {
+                key_id: key_id.clone(),
+                typ: sig.typ(),
+                reason: err.to_string(),
+            });
+        }
+
+        let hash_alg = sig.config.hash_alg;
+        if policy.check_signature(sig).is_err() {
+            self.findings.push(HealthFinding::RejectedHashAlgorithm {
+                key_id: key_id.clone(),
+                typ: sig.typ(),
+                hash_alg,
+            });
+        } else if hash_alg == HashAlgorithm::SHA1 {
+            self.findings.push(HealthFinding::Sha1SelfSignature {
+                key_id: key_id.clone(),
+                typ: sig.typ(),
+            });
+        }
+    }
+
+    fn check_expiration(
+        &mut self,
+        key_id: KeyId,
+        expires_at: Option<DateTime<Utc>>,
+        at: DateTime<Utc>,
+    ) {
+        if let Some(expired_at) = expires_at {
+            if expired_at <= at {
+                self.findings
+                    .push(HealthFinding::Expired { key_id, expired_at });
+            }
+        }
+    }
+
+    fn check_subkey(
+        &mut self,
+        primary_key: &SignedPublicKey,
+        subkey: &SignedPublicSubKey,
+        policy: &Policy,
+        at: DateTime<Utc>,
+    ) {
+        let key_id = subkey.key_id();
+        self.check_key(subkey, subkey.key.public_params(), policy);
+
+        for sig in &subkey.signatures {
+            let verified = sig.verify_key_binding(&primary_key.primary_key, &subkey.key);
+            let is_valid = verified.is_ok();
+            self.check_signature(&key_id, sig, policy, verified);
+
+            match sig.typ() {
+                SignatureType::SubkeyRevocation if is_valid => {
+                    self.findings.push(HealthFinding::Revoked {
+                        key_id: key_id.clone(),
+                    });
+                }
+                SignatureType::SubkeyBinding if sig.key_flags().sign() => {
+                    let has_backsig = sig.embedded_signature().map_or(false, |backsig| {
+                        backsig
+                            .verify_primary_key_binding(&subkey.key, &primary_key.primary_key)
+                            .is_ok()
+                    });
+                    if !has_backsig {
+                        self.findings.push(HealthFinding::MissingBackSignature {
+                            key_id: key_id.clone(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.check_expiration(key_id, subkey.expires_at(&primary_key.primary_key), at);
+    }
+}
+
+impl SignedPublicKey {
+    /// Checks the key for issues, such as weak algorithms, broken self-signatures and
+    /// expired or revoked components, at the time `at`.
+    ///
+    /// Only self-signatures are checked, certifications by other keys are ignored.
+    pub fn health_report(&self, policy: &Policy, at: DateTime<Utc>) -> HealthReport {
+        let mut report = HealthReport::default();
+        let key_id = self.key_id();
+        let primary_key = &self.primary_key;
+
+        report.check_key(primary_key, primary_key.public_params(), policy);
+
+        for sig in &self.details.revocation_signatures {
+            let verified = sig.verify_key(primary_key);
+            let is_valid = verified.is_ok();
+            report.check_signature(&key_id, sig, policy, verified);
+            if is_valid {
+                report.findings.push(HealthFinding::Revoked {
+                    key_id: key_id.clone(),
+                });
+            }
+        }
+        for sig in &self.details.direct_signatures {
+            report.check_signature(&key_id, sig, policy, sig.verify_key(primary_key));
+        }
+        for user in &self.details.users {
+            for sig in user
+                .signatures
+                .iter()
+                .filter(|sig| sig.is_issued_by(primary_key))
+            {
+                let verified = sig.verify_certificate(primary_key, Tag::UserId, &user.id);
+                report.check_signature(&key_id, sig, policy, verified);
+            }
+        }
+        for attr in &self.details.user_attributes {
+            for sig in attr
+                .signatures
+                .iter()
+                .filter(|sig| sig.is_issued_by(primary_key))
+            {
+                let verified = sig.verify_certificate(primary_key, Tag::UserAttribute, &attr.attr);
+                report.check_signature(&key_id, sig, policy, verified);
+            }
+        }
+
+        if let Err(err) = policy.check_key(self) {
+            report.findings.push(HealthFinding::MissingUserIds {
+                key_id: key_id.clone(),
+                reason: err.to_string(),
+            });
+        }
+
+        report.check_expiration(key_id, self.expires_at(), at);
+
+        for subkey in &self.public_subkeys {
+            report.check_subkey(self, subkey, policy, at);
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, SubsecRound};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::{KeyType, SecretKeyParamsBuilder, SubkeyParamsBuilder};
+    use crate::packet::{RevocationCode, RevocationReason, Subpacket};
+
+    #[test]
+    fn test_health_report() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let now = Utc::now().trunc_subsecs(0);
+
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .expiration(Some(std::time::Duration::from_secs(24 * 60 * 60)))
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::EdDSA)
+                    .can_sign(true)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+        let mut pkey = key.signed_public_key();
+        let key_id = pkey.key_id();
+
+        let policy = Policy::default();
+        assert!(pkey.health_report(&policy, now).is_healthy());
+
+        let report = pkey.health_report(&policy, now + Duration::days(2));
+        assert_eq!(
+            report.findings,
+            vec![HealthFinding::Expired {
+                key_id: key_id.clone(),
+                expired_at: pkey.expires_at().unwrap(),
+            }]
+        );
+
+        let strict = Policy {
+            rejected_hash_algorithms: vec![HashAlgorithm::SHA2_256],
+            ..Policy::default()
+        };
+        let report = pkey.health_report(&strict, now);
+        assert!(report
+            .findings
+            .contains(&HealthFinding::RejectedHashAlgorithm {
+                key_id: key_id.clone(),
+                typ: SignatureType::CertGeneric,
+                hash_alg: HashAlgorithm::SHA2_256,
+            }));
+
+        // drop the back signature of the signing subkey
+        let subkey_id = pkey.public_subkeys[0].key_id();
+        pkey.public_subkeys[0].signatures[0]
+            .config
+            .hashed_subpackets
+            .retain(|p| match p {
+                Subpacket::EmbeddedSignature(_) => false,
+                _ => true,
+            });
+        let report = pkey.health_report(&policy, now);
+        assert!(report
+            .findings
+            .contains(&HealthFinding::MissingBackSignature {
+                key_id: subkey_id.clone()
+            }));
+
+        // revocations which do not verify are not reported as such
+        let mut forged = pkey.public_subkeys[0].signatures[0].clone();
+        forged.config.typ = SignatureType::SubkeyRevocation;
+        pkey.public_subkeys[0].signatures.push(forged);
+        let report = pkey.health_report(&policy, now);
+        let revoked = HealthFinding::Revoked {
+            key_id: subkey_id.clone(),
+        };
+        assert!(!report.findings.contains(&revoked));
+
+        pkey.public_subkeys[0]
+            .revoke(
+                &key.primary_key,
+                || "".into(),
+                RevocationReason::new(RevocationCode::KeyRetired, "retired"),
+            )
+            .unwrap();
+        let report = pkey.health_report(&policy, now);
+        assert!(report.findings.contains(&revoked));
+    }
+}
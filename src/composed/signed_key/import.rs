@@ -0,0 +1,159 @@
+use std::ops::AddAssign;
+
+use crate::composed::signed_key::{SignedKeyDetails, SignedPublicKey, SignedPublicSubKey};
+use crate::errors::Result;
+use crate::packet::{Signature, SignatureType};
+use crate::types::KeyTrait;
+
+/// Summary of a key import, similar to the statistics reported by `gpg --import`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportStats {
+    /// Number of keys that were processed, including skipped ones.
+    pub processed: usize,
+    /// Keys that were not part of the keyring before.
+    pub new_keys: usize,
+    /// Keys that were already part of the keyring and got new data.
+    pub updated_keys: usize,
+    /// Keys that were already part of the keyring, without any new data.
+    pub unchanged_keys: usize,
+    /// User ids and user attributes added to existing keys.
+    pub new_user_ids: usize,
+    /// Subkeys added to existing keys.
+    pub new_subkeys: usize,
+    /// Signatures added to existing keys, excluding revocations.
+    pub new_signatures: usize,
+    /// Revocation signatures added to existing keys.
+    pub new_revocations: usize,
+    /// Keys that failed to parse or verify.
+    pub skipped: usize,
+}
+
+impl AddAssign for ImportStats {
+    fn add_assign(&mut self, other: Self) {
+        self.processed += other.processed;
+        self.new_keys += other.new_keys;
+        self.updated_keys += other.updated_keys;
+        self.unchanged_keys += other.unchanged_keys;
+        self.new_user_ids += other.new_user_ids;
+        self.new_subkeys += other.new_subkeys;
+        self.new_signatures += other.new_signatures;
+        self.new_revocations += other.new_revocations;
+        self.skipped += other.skipped;
+    }
+}
+
+impl ImportStats {
+    /// Returns true if the import modified the keyring.
+    pub fn is_modified(&self) -> bool {
+        self.new_keys > 0 || self.updated_keys > 0
+    }
+
+    fn add_signatures(&mut self, existing: &mut Vec<Signature>, signatures: Vec<Signature>) {
+        for sig in signatures {
+            if existing.contains(&sig) {
+                continue;
+            }
+
+            match sig.typ() {
+                SignatureType::KeyRevocation
+                | SignatureType::SubkeyRevocation
+                | SignatureType::CertRevocation => self.new_revocations += 1,
+                _ => self.new_signatures += 1,
+            }
+            existing.push(sig);
+        }
+    }
+
+    fn merge_details(&mut self, existing: &mut SignedKeyDetails, details: SignedKeyDetails) {
+        self.add_signatures(
+            &mut existing.revocation_signatures,
+            details.revocation_signatures,
+        );
+        self.add_signatures(&mut existing.direct_signatures, details.direct_signatures);
+
+        for user in details.users {
+            match existing.users.iter_mut().find(|u| u.id == user.id) {
+                Some(u) => self.add_signatures(&mut u.signatures, user.signatures),
+                None => {
+                    self.new_user_ids += 1;
+                    existing.users.push(user);
+                }
+            }
+        }
+
+        for attr in details.user_attributes {
+            match existing
+                .user_attributes
+                .iter_mut()
+                .find(|a| a.attr == attr.attr)
+            {
+                Some(a) => self.add_signatures(&mut a.signatures, attr.signatures),
+                None => {
+                    self.new_user_ids += 1;
+                    existing.user_attributes.push(attr);
+                }
+            }
+        }
+    }
+
+    fn merge_subkeys(
+        &mut self,
+        existing: &mut Vec<SignedPublicSubKey>,
+        subkeys: Vec<SignedPublicSubKey>,
+    ) {
+        for subkey in subkeys {
+            match existing.iter_mut().find(|k| k.key == subkey.key) {
+                Some(k) => self.add_signatures(&mut k.signatures, subkey.signatures),
+                None => {
+                    self.new_subkeys += 1;
+                    existing.push(subkey);
+                }
+            }
+        }
+    }
+}
+
+/// Imports the given keys into the keyring, merging them with the keys already present.
+///
+/// Keys that fail to parse or to verify are skipped.
+pub fn import_public_keys(
+    keyring: &mut Vec<SignedPublicKey>,
+    keys: impl IntoIterator<Item = Result<SignedPublicKey>>,
+) -> ImportStats {
+    let mut stats = ImportStats::default();
+
+    for key in keys {
+        stats.processed += 1;
+
+        let key = match key.and_then(|key| key.verify().map(|_| key)) {
+            Ok(key) => key,
+            Err(err) => {
+                warn!("skipping key: {:?}", err);
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        let fingerprint = key.fingerprint();
+        match keyring.iter_mut().find(|k| k.fingerprint() == fingerprint) {
+            Some(existing) => {
+                let mut key_stats = ImportStats::default();
+                key_stats.merge_details(&mut existing.details, key.details);
+                key_stats.merge_subkeys(&mut existing.public_subkeys, key.public_subkeys);
+
+                if key_stats == ImportStats::default() {
+                    stats.unchanged_keys += 1;
+                } else {
+                    stats.updated_keys += 1;
+                }
+                stats += key_stats;
+            }
+            None => {
+                stats.new_keys += 1;
+                keyring.push(key);
+            }
+        }
+    }
+
+    stats
+}
@@ -0,0 +1,247 @@
+use chrono::{DateTime, Utc};
+
+use crate::composed::signed_key::encryption::check_subkey;
+use crate::composed::signed_key::{
+    newest_signature, valid_self_certification, SignedKeyDetails, SignedPublicKey,
+};
+use crate::errors::Result;
+use crate::packet;
+use crate::types::{SignedUser, SignedUserAttribute, Tag};
+
+/// Settings for [`SignedPublicKey::minimize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinimizePolicy {
+    /// Only keep the primary user id.
+    pub only_primary_user: bool,
+    /// Keep user attributes, like photos. They are dropped by default, as they are usually large.
+    pub keep_user_attributes: bool,
+    /// Keep signing and authentication subkeys, not only the ones usable for encryption.
+    pub keep_signing_subkeys: bool,
+}
+
+impl Default for MinimizePolicy {
+    fn default() -> Self {
+        MinimizePolicy {
+            only_primary_user: false,
+            keep_user_attributes: false,
+            keep_signing_subkeys: true,
+        }
+    }
+}
+
+impl SignedPublicKey {
+    /// Returns the smallest version of this key, which still verifies, for example to embed
+    /// it into Autocrypt headers.
+    ///
+    /// Only self-signatures are kept, and of those only the newest valid one of each
+    /// user id, attribute and subkey. Revoked user ids and attributes are dropped, as are
+    /// subkeys that are revoked, expired at the time `at` or can not be used.
+    /// Valid revocations of the key itself are always kept.
+    pub fn minimize(&self, policy: &MinimizePolicy, at: DateTime<Utc>) -> Result<Self> {
+        let primary_key = &self.primary_key;
+        let is_self_signature = |sig: &packet::Signature| sig.is_issued_by(primary_key);
+
+        let revocation_signatures = self
+            .details
+            .revocation_signatures
+            .iter()
+            .filter(|sig| is_self_signature(sig) && sig.verify_key(primary_key).is_ok())
+            .cloned()
+            .collect();
+        let direct_signatures: Vec<_> = newest_signature(
+            self.details
+                .direct_signatures
+                .iter()
+                .filter(|sig| is_self_signature(sig) && sig.verify_key(primary_key).is_ok()),
+        )
+        .cloned()
+        .into_iter()
+        .collect();
+
+        let mut users: Vec<_> = self
+            .details
+            .users
+            .iter()
+            .filter(|user| !user.is_revoked_by(primary_key))
+            .filter_map(|user| {
+                let sig = valid_self_certification(user, primary_key, at)?;
+
+                Some(SignedUser::new(user.id.clone(), vec![sig.clone()]))
+            })
+            .collect();
+        if policy.only_primary_user {
+            let primary = users.iter().position(SignedUser::is_primary).unwrap_or(0);
+            users = users.into_iter().skip(primary).take(1).collect();
+        }
+        ensure!(
+            !users.is_empty() || (self.details.users.is_empty() && !direct_signatures.is_empty()),
+            "the key has no valid user ids"
+        );
+
+        let user_attributes = if policy.keep_user_attributes {
+            self.details
+                .user_attributes
+                .iter()
+                .filter(|attr| !attr.is_revoked_by(primary_key))
+                .filter_map(|attr| {
+                    let sig = newest_signature(attr.signatures.iter().filter(|sig| {
+                        sig.is_certification()
+                            && is_self_signature(sig)
+                            && sig
+                                .verify_certificate(primary_key, Tag::UserAttribute, &attr.attr)
+                                .is_ok()
+                    }))?;
+
+                    Some(SignedUserAttribute::new(
+                        attr.attr.clone(),
+                        vec![sig.clone()],
+                    ))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let public_subkeys = self
+            .public_subkeys
+            .iter()
+            .filter(|subkey| !subkey.is_revoked(primary_key))
+            .filter_map(|subkey| {
+                let sig = subkey.latest_binding(primary_key)?.clone();
+                let mut subkey = subkey.clone();
+                subkey.signatures = vec![sig];
+
+                let flags = subkey.signatures[0].key_flags();
+                let usable = check_subkey(primary_key, &subkey, at).is_none()
+                    || (policy.keep_signing_subkeys
+                        && (flags.sign() || flags.authentication())
+                        && subkey
+                            .expires_at(primary_key)
+                            .map_or(true, |expires_at| expires_at > at)
+                        && subkey.verify(primary_key).is_ok());
+
+                if usable {
+                    Some(subkey)
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let key = SignedPublicKey::new(
+            self.primary_key.clone(),
+            SignedKeyDetails::new(
+                revocation_signatures,
+                direct_signatures,
+                users,
+                user_attributes,
+            ),
+            public_subkeys,
+        );
+        key.verify()?;
+
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Duration;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::{KeyType, SecretKeyParamsBuilder, SubkeyParamsBuilder};
+    use crate::packet::{RevocationCode, RevocationReason};
+    use crate::ser::Serialize;
+    use crate::types::KeyTrait;
+
+    #[test]
+    fn test_minimize() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut gen_key = |id: &str| {
+            SecretKeyParamsBuilder::default()
+                .key_type(KeyType::EdDSA)
+                .can_create_certificates(true)
+                .can_sign(true)
+                .primary_user_id(id.into())
+                .user_id("Me <me@work.com>")
+                .subkey(
+                    SubkeyParamsBuilder::default()
+                        .key_type(KeyType::ECDH)
+                        .can_encrypt(true)
+                        .build()
+                        .unwrap(),
+                )
+                .subkey(
+                    SubkeyParamsBuilder::default()
+                        .key_type(KeyType::ECDH)
+                        .can_encrypt(true)
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()
+                .generate_with_rng(&mut rng)
+                .unwrap()
+                .sign(|| "".into())
+                .unwrap()
+        };
+        let mut key = gen_key("Me <me@mail.com>");
+        let other = gen_key("Other <other@mail.com>");
+
+        // a superseded self-signature, a revoked user id and subkey
+        let superseded = key.details.users[0].signatures.clone();
+        key.set_expiration(Some(Duration::days(365)), || "".into())
+            .unwrap();
+        key.details.users[0].signatures.splice(0..0, superseded);
+        let work_id = key.details.users[1].id.clone();
+        key.revoke_user_id(
+            &work_id,
+            RevocationReason::new(RevocationCode::CertUserIdInvalid, ""),
+            || "".into(),
+        )
+        .unwrap();
+        let revoked_id = key.secret_subkeys[1].key_id();
+        key.revoke_subkey(
+            &revoked_id,
+            RevocationReason::new(RevocationCode::KeyRetired, ""),
+            || "".into(),
+        )
+        .unwrap();
+
+        let mut public_key = key.signed_public_key();
+        let id = public_key.details.users[0].id.clone();
+        public_key
+            .certify_user_id(&other, &id, || "".into(), &Default::default())
+            .unwrap();
+
+        let minimal = public_key
+            .minimize(&MinimizePolicy::default(), Utc::now())
+            .unwrap();
+        minimal.verify().unwrap();
+        assert_eq!(minimal.fingerprint(), public_key.fingerprint());
+        assert_eq!(minimal.expires_at(), public_key.expires_at());
+        assert!(minimal.to_bytes().unwrap().len() < public_key.to_bytes().unwrap().len());
+
+        assert_eq!(minimal.details.users.len(), 1);
+        assert_eq!(minimal.details.users[0].id, id);
+        assert_eq!(minimal.details.users[0].signatures.len(), 1);
+        assert!(minimal.details.users[0].signatures[0].is_issued_by(&key));
+        assert_eq!(minimal.public_subkeys.len(), 1);
+        assert_eq!(
+            minimal.public_subkeys[0].key_id(),
+            key.secret_subkeys[0].key_id()
+        );
+        assert_eq!(minimal.public_subkeys[0].signatures.len(), 1);
+
+        // minimizing a minimal key changes nothing
+        let policy = MinimizePolicy {
+            only_primary_user: true,
+            ..Default::default()
+        };
+        let minimal = public_key.minimize(&policy, Utc::now()).unwrap();
+        assert_eq!(minimal.minimize(&policy, Utc::now()).unwrap(), minimal);
+    }
+}
@@ -108,12 +108,32 @@
 #[macro_use]
 mod key_parser_macros;
 
+pub mod edit;
+pub mod encryption;
+pub mod external;
+pub mod health;
+pub mod import;
+pub mod minimize;
 pub mod parse;
+pub mod preferences;
 pub mod public;
 pub mod secret;
 pub mod shared;
+pub mod signing;
+pub mod unlock;
+pub mod unlocked;
 
+pub use self::edit::*;
+pub use self::encryption::*;
+pub use self::external::*;
+pub use self::health::*;
+pub use self::import::*;
+pub use self::minimize::*;
 pub use self::parse::*;
+pub use self::preferences::*;
 pub use self::public::*;
 pub use self::secret::*;
 pub use self::shared::*;
+pub use self::signing::*;
+pub use self::unlock::*;
+pub use self::unlocked::*;
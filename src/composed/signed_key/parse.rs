@@ -1,8 +1,7 @@
-use std::collections::BTreeMap;
 use std::{io, iter};
 
-use crate::armor::{self, BlockType};
-use crate::composed::shared::Deserializable;
+use crate::armor::{self, BlockType, Headers};
+use crate::composed::shared::{check_block_type, skip_invalid, Deserializable};
 use crate::composed::signed_key::{PublicOrSecret, SignedPublicKey, SignedSecretKey};
 use crate::errors::Result;
 use crate::packet::{Packet, PacketParser};
@@ -16,7 +15,7 @@ pub fn from_armor_many<'a, R: io::Read + io::Seek + 'a>(
     input: R,
 ) -> Result<(
     Box<dyn Iterator<Item = Result<PublicOrSecret>> + 'a>,
-    BTreeMap<String, String>,
+    Headers,
 )> {
     let mut dearmor = armor::Dearmor::new(input);
     dearmor.read_header()?;
@@ -35,8 +34,13 @@ pub fn from_armor_many<'a, R: io::Read + io::Seek + 'a>(
         | BlockType::Signature
         | BlockType::File => {
             let headers = dearmor.headers.clone(); // FIXME: avoid clone
-                                                   // TODO: check that the result is what it actually said.
-            Ok((from_bytes_many(dearmor), headers))
+
+            let mut packets = PacketParser::new(dearmor)
+                .filter_map(skip_invalid)
+                .peekable();
+            check_block_type(typ, &mut packets)?;
+
+            Ok((Box::new(PubPrivIterator { inner: packets }), headers))
         }
         BlockType::PublicKeyPKCS1(_)
         | BlockType::PublicKeyPKCS8
@@ -53,17 +57,7 @@ pub fn from_armor_many<'a, R: io::Read + io::Seek + 'a>(
 pub fn from_bytes_many<'a>(
     bytes: impl io::Read + 'a,
 ) -> Box<dyn Iterator<Item = Result<PublicOrSecret>> + 'a> {
-    let packets = PacketParser::new(bytes)
-        .filter_map(|p| {
-            // for now we are skipping any packets that we failed to parse
-            if p.is_ok() {
-                p.ok()
-            } else {
-                warn!("skipping packet: {:?}", p);
-                None
-            }
-        })
-        .peekable();
+    let packets = PacketParser::new(bytes).filter_map(skip_invalid).peekable();
 
     Box::new(PubPrivIterator { inner: packets })
 }
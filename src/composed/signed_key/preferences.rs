@@ -0,0 +1,217 @@
+use smallvec::SmallVec;
+
+use crate::composed::signed_key::SignedSecretKey;
+use crate::crypto::aead::AeadAlgorithm;
+use crate::crypto::{HashAlgorithm, SymmetricKeyAlgorithm};
+use crate::errors::Result;
+use crate::packet::{self, Subpacket};
+use crate::types::{CompressionAlgorithm, KeyTrait, KeyVersion};
+
+/// The algorithm preferences of a key, see [`SignedSecretKey::set_preferences`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preferences {
+    pub symmetric_algorithms: SmallVec<[SymmetricKeyAlgorithm; 8]>,
+    pub hash_algorithms: SmallVec<[HashAlgorithm; 8]>,
+    pub compression_algorithms: SmallVec<[CompressionAlgorithm; 8]>,
+    /// Preferred AEAD algorithms, no subpacket is written if this is empty.
+    pub aead_algorithms: SmallVec<[AeadAlgorithm; 2]>,
+}
+
+impl Preferences {
+    /// Reads the preferences stored in the given self-signature.
+    ///
+    /// The AEAD algorithms are taken from the AEAD ciphersuites of version 6
+    /// signatures, if they have any.
+    pub fn from_signature(sig: &packet::Signature) -> Self {
+        let ciphersuites = sig.preferred_aead_ciphersuites();
+        let aead_algorithms = if ciphersuites.is_empty() {
+            sig.preferred_aead_algs().into()
+        } else {
+            let mut algs = SmallVec::new();
+            for (_, aead) in ciphersuites {
+                if !algs.contains(aead) {
+                    algs.push(*aead);
+                }
+            }
+            algs
+        };
+
+        Preferences {
+            symmetric_algorithms: sig.preferred_symmetric_algs().into(),
+            hash_algorithms: sig.preferred_hash_algs().into(),
+            compression_algorithms: sig.preferred_compression_algs().into(),
+            aead_algorithms,
+        }
+    }
+
+    /// Returns the subpackets for a self-signature of a key with the given version.
+    ///
+    /// Version 6 keys state their AEAD preferences as ciphersuites, every AEAD algorithm
+    /// combined with every symmetric algorithm, in the order of preference.
+    fn subpackets(&self, version: KeyVersion) -> Vec<Subpacket> {
+        let mut subpackets = vec![
+            Subpacket::PreferredSymmetricAlgorithms(self.symmetric_algorithms.clone()),
+            Subpacket::PreferredHashAlgorithms(self.hash_algorithms.clone()),
+            Subpacket::PreferredCompressionAlgorithms(self.compression_algorithms.clone()),
+        ];
+        if self.aead_algorithms.is_empty() {
+            return subpackets;
+        }
+
+        match version {
+            KeyVersion::V6 => {
+                let ciphersuites = self
+                    .symmetric_algorithms
+                    .iter()
+                    .flat_map(|sym_alg| {
+                        self.aead_algorithms
+                            .iter()
+                            .map(move |aead| (*sym_alg, *aead))
+                    })
+                    .collect();
+                subpackets.push(Subpacket::PreferredAeadCiphersuites(ciphersuites));
+            }
+            _ => subpackets.push(Subpacket::PreferredAeadAlgorithms(
+                self.aead_algorithms.clone(),
+            )),
+        }
+
+        subpackets
+    }
+}
+
+fn is_preference(p: &Subpacket) -> bool {
+    match p {
+        Subpacket::PreferredSymmetricAlgorithms(_)
+        | Subpacket::PreferredHashAlgorithms(_)
+        | Subpacket::PreferredCompressionAlgorithms(_)
+        | Subpacket::PreferredAeadAlgorithms(_)
+        | Subpacket::PreferredAeadCiphersuites(_) => true,
+        _ => false,
+    }
+}
+
+impl SignedSecretKey {
+    /// Replaces the algorithm preferences of the key.
+    ///
+    /// Like [`set_expiration`], this issues new self-signatures for all user ids, that
+    /// are not revoked, and for the direct key signature if there are no user ids or it
+    /// carries preferences itself. All other subpackets are kept, and the new signatures
+    /// are always created after the ones they replace. The key material is not touched.
+    ///
+    /// [`set_expiration`]: SignedSecretKey::set_expiration
+    pub fn set_preferences<F>(&mut self, preferences: &Preferences, key_pw: F) -> Result<()>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        let subpackets = preferences.subpackets(self.primary_key.version());
+        let has_users = !self.details.users.is_empty();
+
+        self.reissue_self_signatures(
+            key_pw,
+            |p| !is_preference(p),
+            &subpackets,
+            |sig| !has_users || sig.config.hashed_subpackets.iter().any(is_preference),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use smallvec::smallvec;
+
+    use crate::composed::test_keys::gen_key_v6;
+    use crate::composed::{KeyType, SecretKeyParamsBuilder};
+    use crate::types::SecretKeyTrait;
+
+    #[test]
+    fn test_set_preferences() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .user_id("Me <me@work.com>")
+            .preferred_symmetric_algorithms(smallvec![SymmetricKeyAlgorithm::AES128])
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+        let original = key.clone();
+
+        let preferences = Preferences {
+            symmetric_algorithms: smallvec![
+                SymmetricKeyAlgorithm::AES256,
+                SymmetricKeyAlgorithm::AES128
+            ],
+            hash_algorithms: smallvec![HashAlgorithm::SHA2_512, HashAlgorithm::SHA2_256],
+            compression_algorithms: smallvec![CompressionAlgorithm::Uncompressed],
+            aead_algorithms: smallvec![AeadAlgorithm::Ocb, AeadAlgorithm::Eax],
+        };
+        key.set_preferences(&preferences, || "".into()).unwrap();
+        key.verify().unwrap();
+
+        assert_eq!(key.primary_key, original.primary_key);
+        assert_eq!(key.secret_subkeys, original.secret_subkeys);
+        for (user, old) in key.details.users.iter().zip(&original.details.users) {
+            assert_eq!(user.signatures.len(), 1);
+            let sig = &user.signatures[0];
+            assert_eq!(Preferences::from_signature(sig), preferences);
+            assert!(sig.created() > old.signatures[0].created());
+            assert_eq!(sig.key_flags(), old.signatures[0].key_flags());
+            assert_eq!(sig.is_primary(), old.signatures[0].is_primary());
+        }
+
+        // without AEAD algorithms, the subpacket is removed
+        let preferences = Preferences {
+            aead_algorithms: SmallVec::new(),
+            ..preferences
+        };
+        key.set_preferences(&preferences, || "".into()).unwrap();
+        key.verify().unwrap();
+        let sig = &key.details.users[0].signatures[0];
+        assert_eq!(Preferences::from_signature(sig), preferences);
+        assert!(!sig.config.hashed_subpackets.iter().any(|p| match p {
+            Subpacket::PreferredAeadAlgorithms(_) => true,
+            _ => false,
+        }));
+        assert_eq!(key.public_key().fingerprint(), original.fingerprint());
+    }
+
+    #[test]
+    fn test_set_preferences_v6() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut key = gen_key_v6(&mut rng, Some("Me <me@mail.com>"));
+
+        let preferences = Preferences {
+            symmetric_algorithms: smallvec![
+                SymmetricKeyAlgorithm::AES256,
+                SymmetricKeyAlgorithm::AES128
+            ],
+            hash_algorithms: smallvec![HashAlgorithm::SHA2_512],
+            compression_algorithms: smallvec![CompressionAlgorithm::Uncompressed],
+            aead_algorithms: smallvec![AeadAlgorithm::Ocb, AeadAlgorithm::Gcm],
+        };
+        key.set_preferences(&preferences, || "".into()).unwrap();
+        key.verify().unwrap();
+
+        let sig = &key.details.users[0].signatures[0];
+        assert!(sig.preferred_aead_algs().is_empty());
+        assert_eq!(
+            sig.preferred_aead_ciphersuites(),
+            &[
+                (SymmetricKeyAlgorithm::AES256, AeadAlgorithm::Ocb),
+                (SymmetricKeyAlgorithm::AES256, AeadAlgorithm::Gcm),
+                (SymmetricKeyAlgorithm::AES128, AeadAlgorithm::Ocb),
+                (SymmetricKeyAlgorithm::AES128, AeadAlgorithm::Gcm),
+            ][..]
+        );
+        assert_eq!(Preferences::from_signature(sig), preferences);
+    }
+}
@@ -1,18 +1,23 @@
-use std::collections::BTreeMap;
 use std::io;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use rand::{CryptoRng, Rng};
 
-use crate::armor;
+use crate::armor::{self, Headers};
 use crate::composed::key::{PublicKey, PublicSubkey};
-use crate::composed::signed_key::SignedKeyDetails;
+use crate::composed::signed_key::{
+    is_signing_subkey_binding, is_subkey_revoked, key_expiration_offset, latest_subkey_binding,
+    newest_subkey_binding, subkey_revocation, SignedKeyDetails,
+};
+use crate::composed::Policy;
 use crate::crypto::public_key::PublicKeyAlgorithm;
 use crate::crypto::HashAlgorithm;
 use crate::errors::Result;
-use crate::packet::{self, write_packet, SignatureType};
+use crate::packet::{self, write_packet, PacketRef, RevocationReason, SignatureType};
 use crate::ser::Serialize;
-use crate::types::{KeyId, KeyTrait, Mpi, PublicKeyTrait};
+use crate::types::{
+    CertificationOptions, KeyId, KeyTrait, KeyVersion, Mpi, PublicKeyTrait, SecretKeyTrait,
+};
 
 /// Represents a Public PGP key, which is signed and either received or ready to be transferred.
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -57,12 +62,130 @@ impl SignedPublicKey {
         }
     }
 
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        self.primary_key.created_at()
+    }
+
+    /// Get the public key expiration time as a duration, relative to the key creation time.
+    ///
+    /// The expiration time is taken from the newest valid self-signature.
+    /// Returns `None` if the key never expires.
+    pub fn key_expiration_time(&self) -> Option<Duration> {
+        self.details
+            .latest_self_signature(&self.primary_key)
+            .and_then(key_expiration_offset)
+    }
+
     /// Get the public key expiration as a date.
     pub fn expires_at(&self) -> Option<DateTime<Utc>> {
-        let expiration = self.details.key_expiration_time()?;
+        let expiration = self.key_expiration_time()?;
         Some(*self.primary_key.created_at() + expiration)
     }
 
+    /// Returns true if the key is expired at the time `at`.
+    pub fn is_expired(&self, at: DateTime<Utc>) -> bool {
+        self.expires_at()
+            .map_or(false, |expires_at| expires_at <= at)
+    }
+
+    /// Returns the primary user id at the time `at`, see [`SignedKeyDetails::primary_user_at`].
+    pub fn primary_user_id(&self, at: DateTime<Utc>) -> Option<&packet::UserId> {
+        self.details
+            .primary_user_at(&self.primary_key, at)
+            .map(|user| &user.id)
+    }
+
+    /// Certifies the user id `id` of this key with the key `certifier`, for example after
+    /// checking the identity of the owner.
+    pub fn certify_user_id<F>(
+        &mut self,
+        certifier: &impl SecretKeyTrait,
+        id: &packet::UserId,
+        key_pw: F,
+        options: &CertificationOptions,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        let primary_key = &self.primary_key;
+        match self.details.users.iter_mut().find(|user| &user.id == id) {
+            Some(user) => user.certify(primary_key, certifier, key_pw, options),
+            None => bail!("unknown user id {:?}", id),
+        }
+    }
+
+    /// Approves the third-party certifications of the user id `id` of this key, for which
+    /// `approve` returns `true`, with an attestation made by `primary_key`.
+    ///
+    /// See [`SignedUser::attest_certifications`](crate::types::SignedUser::attest_certifications).
+    pub fn attest_certifications<F, G>(
+        &mut self,
+        id: &packet::UserId,
+        primary_key: &impl SecretKeyTrait,
+        key_pw: F,
+        approve: G,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+        G: Fn(&packet::Signature) -> bool,
+    {
+        ensure_eq!(
+            primary_key.fingerprint(),
+            self.fingerprint(),
+            "attestations can only be made by the primary key"
+        );
+
+        match self.details.users.iter_mut().find(|user| &user.id == id) {
+            Some(user) => user.attest_certifications(primary_key, key_pw, approve),
+            None => bail!("unknown user id {:?}", id),
+        }
+    }
+
+    /// Withdraws the certifications of the user id `id` of this key, made by `certifier`.
+    pub fn revoke_certification<F>(
+        &mut self,
+        certifier: &impl SecretKeyTrait,
+        id: &packet::UserId,
+        reason: RevocationReason,
+        key_pw: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        let primary_key = &self.primary_key;
+        match self.details.users.iter_mut().find(|user| &user.id == id) {
+            Some(user) => user.revoke_certification(primary_key, certifier, key_pw, reason),
+            None => bail!("unknown user id {:?}", id),
+        }
+    }
+
+    /// Revokes the subkey with the given key id, using the secret key of the primary key.
+    pub fn revoke_subkey<F>(
+        &mut self,
+        key_id: &KeyId,
+        reason: RevocationReason,
+        primary_key: &impl SecretKeyTrait,
+        key_pw: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        ensure_eq!(
+            primary_key.fingerprint(),
+            self.fingerprint(),
+            "subkeys can only be revoked by their primary key"
+        );
+
+        match self
+            .public_subkeys
+            .iter_mut()
+            .find(|subkey| &subkey.key_id() == key_id)
+        {
+            Some(subkey) => subkey.revoke(primary_key, key_pw, reason),
+            None => bail!("unknown subkey {:?}", key_id),
+        }
+    }
+
     fn verify_public_subkeys(&self) -> Result<()> {
         for subkey in &self.public_subkeys {
             subkey.verify(&self.primary_key)?;
@@ -71,6 +194,21 @@ impl SignedPublicKey {
         Ok(())
     }
 
+    /// Returns all packets of the key, in the order they are serialized.
+    pub fn packets(&self) -> impl Iterator<Item = PacketRef<'_>> {
+        std::iter::once(PacketRef::PublicKey(&self.primary_key))
+            .chain(self.details.packets())
+            .chain(
+                self.public_subkeys
+                    .iter()
+                    .flat_map(SignedPublicSubKey::packets),
+            )
+    }
+
+    /// Verifies the self-signatures and subkey bindings.
+    ///
+    /// Back signatures of signing subkeys are not required, subkeys without a valid one
+    /// are not used for signing, see [`Policy::require_back_signatures`].
     pub fn verify(&self) -> Result<()> {
         self.details.verify(&self.primary_key)?;
         self.verify_public_subkeys()?;
@@ -81,12 +219,12 @@ impl SignedPublicKey {
     pub fn to_armored_writer(
         &self,
         writer: &mut impl io::Write,
-        headers: Option<&BTreeMap<String, String>>,
+        headers: Option<&Headers>,
     ) -> Result<()> {
         armor::write(self, armor::BlockType::PublicKey, writer, headers)
     }
 
-    pub fn to_armored_bytes(&self, headers: Option<&BTreeMap<String, String>>) -> Result<Vec<u8>> {
+    pub fn to_armored_bytes(&self, headers: Option<&Headers>) -> Result<Vec<u8>> {
         let mut buf = Vec::new();
 
         self.to_armored_writer(&mut buf, headers)?;
@@ -94,14 +232,14 @@ impl SignedPublicKey {
         Ok(buf)
     }
 
-    pub fn to_armored_string(&self, headers: Option<&BTreeMap<String, String>>) -> Result<String> {
+    pub fn to_armored_string(&self, headers: Option<&Headers>) -> Result<String> {
         Ok(::std::str::from_utf8(&self.to_armored_bytes(headers)?)?.to_string())
     }
 
     pub fn as_unsigned(&self) -> PublicKey {
         PublicKey::new(
             self.primary_key.clone(),
-            self.details.as_unsigned(),
+            self.details.as_unsigned(&self.primary_key),
             self.public_subkeys
                 .iter()
                 .map(SignedPublicSubKey::as_unsigned)
@@ -122,6 +260,10 @@ impl KeyTrait for SignedPublicKey {
     fn algorithm(&self) -> PublicKeyAlgorithm {
         self.primary_key.algorithm()
     }
+
+    fn version(&self) -> KeyVersion {
+        self.primary_key.version()
+    }
 }
 
 impl PublicKeyTrait for SignedPublicKey {
@@ -176,6 +318,11 @@ impl SignedPublicSubKey {
         SignedPublicSubKey { key, signatures }
     }
 
+    /// Verifies the binding signatures made by `key`.
+    ///
+    /// Embedded back signatures are not checked, see [`can_sign`].
+    ///
+    /// [`can_sign`]: SignedPublicSubKey::can_sign
     pub fn verify(&self, key: &impl PublicKeyTrait) -> Result<()> {
         ensure!(!self.signatures.is_empty(), "missing subkey bindings");
         for sig in &self.signatures {
@@ -185,14 +332,89 @@ impl SignedPublicSubKey {
         Ok(())
     }
 
+    /// Returns true if the newest binding made by `primary_key` allows signing.
+    ///
+    /// Signing subkeys have to cross-certify the primary key with an embedded back
+    /// signature, unless `policy` does not require it. Subkeys without one are ignored
+    /// for signing, but do not invalidate the key.
+    pub fn can_sign(&self, primary_key: &impl PublicKeyTrait, policy: &Policy) -> bool {
+        is_signing_subkey_binding(primary_key, &self.key, &self.signatures, policy)
+    }
+
+    /// Returns true if the subkey has a revocation signature made by `primary_key`, which
+    /// verifies.
+    pub fn is_revoked(&self, primary_key: &impl PublicKeyTrait) -> bool {
+        is_subkey_revoked(primary_key, &self.key, &self.signatures)
+    }
+
+    /// Returns the newest binding signature made by `primary_key`, which verifies.
+    ///
+    /// Bindings that can not be verified are ignored, anyone can attach them.
+    pub fn latest_binding(&self, primary_key: &impl PublicKeyTrait) -> Option<&packet::Signature> {
+        latest_subkey_binding(primary_key, &self.key, &self.signatures)
+    }
+
+    /// Revokes this subkey, attaching a revocation signature made by `primary_key`.
+    pub fn revoke<F>(
+        &mut self,
+        primary_key: &impl SecretKeyTrait,
+        key_pw: F,
+        reason: RevocationReason,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        let sig = subkey_revocation(primary_key, key_pw, &self.key, reason)?;
+        self.signatures.push(sig);
+
+        Ok(())
+    }
+
+    /// Returns the subkey packet, followed by its signatures.
+    pub fn packets(&self) -> impl Iterator<Item = PacketRef<'_>> {
+        std::iter::once(PacketRef::PublicSubkey(&self.key))
+            .chain(self.signatures.iter().map(PacketRef::Signature))
+    }
+
+    /// Returns the subkey with the flags and expiration of the newest binding signature.
+    ///
+    /// The binding signatures are not verified, that requires the primary key, see
+    /// [`verify`](SignedPublicSubKey::verify).
     pub fn as_unsigned(&self) -> PublicSubkey {
-        let keyflags = self
-            .signatures
-            .first()
-            .expect("missing signatures")
-            .key_flags();
+        let binding = newest_subkey_binding(&self.signatures);
+        let keyflags = binding.map(|sig| sig.key_flags()).unwrap_or_default();
 
-        PublicSubkey::new(self.key.clone(), keyflags)
+        PublicSubkey::new(
+            self.key.clone(),
+            keyflags,
+            binding.and_then(key_expiration_offset),
+        )
+    }
+
+    /// Get the subkey expiration time as a duration, relative to the subkey creation time.
+    ///
+    /// Uses the `KeyExpirationTime` offset of the newest binding signature made by
+    /// `primary_key`, which verifies. Returns `None` if the subkey has an infinite
+    /// validity, or no valid binding signature.
+    pub fn key_expiration_time(&self, primary_key: &impl PublicKeyTrait) -> Option<Duration> {
+        self.latest_binding(primary_key)
+            .and_then(key_expiration_offset)
+    }
+
+    /// Get the subkey expiration as a date.
+    pub fn expires_at(&self, primary_key: &impl PublicKeyTrait) -> Option<DateTime<Utc>> {
+        let expiration = self.key_expiration_time(primary_key)?;
+        Some(*self.key.created_at() + expiration)
+    }
+
+    /// Returns true if the subkey is expired at the time `at`.
+    pub fn is_expired(&self, primary_key: &impl PublicKeyTrait, at: DateTime<Utc>) -> bool {
+        self.expires_at(primary_key)
+            .map_or(false, |expires_at| expires_at <= at)
+    }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        self.key.created_at()
     }
 }
 
@@ -210,6 +432,10 @@ impl KeyTrait for SignedPublicSubKey {
     fn algorithm(&self) -> PublicKeyAlgorithm {
         self.key.algorithm()
     }
+
+    fn version(&self) -> KeyVersion {
+        self.key.version()
+    }
 }
 
 impl PublicKeyTrait for SignedPublicSubKey {
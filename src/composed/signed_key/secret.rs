@@ -1,18 +1,28 @@
-use std::collections::BTreeMap;
 use std::io;
 
-use chrono::{DateTime, Utc};
-use rand::{CryptoRng, Rng};
-
-use crate::armor;
-use crate::composed::key::{PublicKey, PublicSubkey};
-use crate::composed::signed_key::{SignedKeyDetails, SignedPublicSubKey};
+use chrono::{DateTime, Duration, SubsecRound, Utc};
+use rand::{thread_rng, CryptoRng, Rng};
+
+use crate::armor::{self, Headers};
+use crate::composed::key::{key_expiration_subpacket, PublicKey, PublicSubkey, SubkeyParams};
+use crate::composed::signed_key::{
+    is_signing_subkey_binding, is_subkey_revoked, key_expiration_offset, latest_self_certification,
+    latest_subkey_binding, newest_subkey_binding, renewed_subpackets, subkey_revocation,
+    SignedKeyDetails, SignedPublicKey, SignedPublicSubKey,
+};
+use crate::composed::Policy;
 use crate::crypto::hash::HashAlgorithm;
 use crate::crypto::public_key::PublicKeyAlgorithm;
 use crate::errors::Result;
-use crate::packet::{self, write_packet, SignatureType};
+use crate::packet::{
+    self, write_packet, PacketRef, RevocationReason, SignatureConfig, SignatureConfigBuilder,
+    SignatureType, SignatureVersion, Subpacket,
+};
 use crate::ser::Serialize;
-use crate::types::{KeyId, KeyTrait, Mpi, PublicKeyTrait, SecretKeyRepr, SecretKeyTrait};
+use crate::types::{
+    KeyId, KeyTrait, KeyVersion, Mpi, PublicKeyTrait, S2kParams, SecretKeyRepr, SecretKeyTrait,
+    SignedUser, Tag,
+};
 
 /// Represents a secret signed PGP key.
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -76,12 +86,442 @@ impl SignedSecretKey {
         }
     }
 
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        self.primary_key.created_at()
+    }
+
+    /// Get the secret key expiration time as a duration, relative to the key creation time.
+    ///
+    /// The expiration time is taken from the newest valid self-signature.
+    /// Returns `None` if the key never expires.
+    pub fn key_expiration_time(&self) -> Option<Duration> {
+        self.details
+            .latest_self_signature(&self.primary_key)
+            .and_then(key_expiration_offset)
+    }
+
     /// Get the secret key expiration as a date.
     pub fn expires_at(&self) -> Option<DateTime<Utc>> {
-        let expiration = self.details.key_expiration_time()?;
+        let expiration = self.key_expiration_time()?;
         Some(*self.primary_key.created_at() + expiration)
     }
 
+    /// Returns true if the key is expired at the time `at`.
+    pub fn is_expired(&self, at: DateTime<Utc>) -> bool {
+        self.expires_at()
+            .map_or(false, |expires_at| expires_at <= at)
+    }
+
+    /// Same as [`add_subkey_with_rng`], but uses [`thread_rng`] for RNG.
+    ///
+    /// [`add_subkey_with_rng`]: SignedSecretKey::add_subkey_with_rng
+    /// [`thread_rng`]: rand::thread_rng
+    pub fn add_subkey<F>(self, params: SubkeyParams, key_pw: F) -> Result<Self>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        let mut rng = thread_rng();
+        self.add_subkey_with_rng(&mut rng, params, key_pw)
+    }
+
+    /// Generates a new subkey and binds it to the primary key.
+    ///
+    /// `key_pw` unlocks the primary key, and for signing subkeys also the new subkey,
+    /// to create the primary key binding signature.
+    pub fn add_subkey_with_rng<R, F>(
+        mut self,
+        rng: &mut R,
+        params: SubkeyParams,
+        key_pw: F,
+    ) -> Result<Self>
+    where
+        R: Rng + CryptoRng,
+        F: (FnOnce() -> String) + Clone,
+    {
+        let subkey = params.generate_with_rng(rng)?;
+        let subkey = subkey.sign_with_rng(rng, &self.primary_key, key_pw)?;
+        ensure!(
+            subkey.key.created_at() >= self.primary_key.created_at(),
+            "Subkeys must not be created before the primary key"
+        );
+
+        self.secret_subkeys.push(subkey);
+
+        Ok(self)
+    }
+
+    /// Same as [`change_passphrase_with_rng`], but uses [`thread_rng`] for RNG.
+    ///
+    /// [`change_passphrase_with_rng`]: SignedSecretKey::change_passphrase_with_rng
+    /// [`thread_rng`]: rand::thread_rng
+    pub fn change_passphrase<F>(
+        self,
+        old_pw: F,
+        new_pw: Option<&str>,
+        s2k_params: &S2kParams,
+    ) -> Result<Self>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        let mut rng = thread_rng();
+        self.change_passphrase_with_rng(&mut rng, old_pw, new_pw, s2k_params)
+    }
+
+    /// Re-encrypts the secret material of the primary key and all secret subkeys with `new_pw`.
+    ///
+    /// All keys have to be unlocked by `old_pw`. Signatures and public material are not changed.
+    pub fn change_passphrase_with_rng<R, F>(
+        mut self,
+        rng: &mut R,
+        old_pw: F,
+        new_pw: Option<&str>,
+        s2k_params: &S2kParams,
+    ) -> Result<Self>
+    where
+        R: Rng + CryptoRng,
+        F: (FnOnce() -> String) + Clone,
+    {
+        self.primary_key
+            .change_passphrase(rng, old_pw.clone(), new_pw, s2k_params)?;
+        for subkey in &mut self.secret_subkeys {
+            subkey
+                .key
+                .change_passphrase(rng, old_pw.clone(), new_pw, s2k_params)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Same as [`reprotect_with_rng`], but uses [`thread_rng`] for RNG.
+    ///
+    /// [`reprotect_with_rng`]: SignedSecretKey::reprotect_with_rng
+    /// [`thread_rng`]: rand::thread_rng
+    pub fn reprotect<F>(&self, key_pw: F, s2k_params: &S2kParams) -> Result<Self>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        let mut rng = thread_rng();
+        self.reprotect_with_rng(&mut rng, key_pw, s2k_params)
+    }
+
+    /// Returns a copy of the key, with the encrypted secret material encrypted again
+    /// under the same passphrase, using the given protection settings.
+    ///
+    /// Used to export keys for implementations, that don't support the default protection.
+    /// Unencrypted secret material and the public material are not changed.
+    pub fn reprotect_with_rng<R, F>(
+        &self,
+        rng: &mut R,
+        key_pw: F,
+        s2k_params: &S2kParams,
+    ) -> Result<Self>
+    where
+        R: Rng + CryptoRng,
+        F: (FnOnce() -> String) + Clone,
+    {
+        let passphrase = key_pw.clone()();
+        let mut key = self.clone();
+
+        if key.primary_key.secret_params().is_encrypted() {
+            key.primary_key.change_passphrase(
+                rng,
+                key_pw.clone(),
+                Some(&passphrase),
+                s2k_params,
+            )?;
+        }
+        for subkey in &mut key.secret_subkeys {
+            if subkey.key.secret_params().is_encrypted() {
+                subkey
+                    .key
+                    .change_passphrase(rng, key_pw.clone(), Some(&passphrase), s2k_params)?;
+            }
+        }
+
+        Ok(key)
+    }
+
+    /// Removes the secret material of the primary key, keeping the secret subkeys.
+    ///
+    /// This is the layout of keys used day to day, while the primary key is kept offline.
+    /// Signing and decrypting with the subkeys works as before, operations that need the
+    /// primary key, like certifying or adding subkeys, fail with
+    /// [`Error::SecretKeyNotPresent`](crate::errors::Error::SecretKeyNotPresent).
+    pub fn remove_primary_secret(&mut self) {
+        self.primary_key.remove_secret();
+    }
+
+    /// Returns `true` if the secret material of the primary key is present.
+    pub fn has_primary_secret(&self) -> bool {
+        !self.primary_key.secret_params().is_stub()
+    }
+
+    /// Creates a revocation signature for the primary key, which revokes the whole key.
+    ///
+    /// The signature can be attached to the key, or be kept as a revocation certificate,
+    /// see `StandaloneSignature::to_revocation_certificate_writer`.
+    pub fn revoke<F>(&self, reason: RevocationReason, key_pw: F) -> Result<packet::Signature>
+    where
+        F: FnOnce() -> String,
+    {
+        let key = &self.primary_key;
+        let config = SignatureConfigBuilder::default()
+            .typ(SignatureType::KeyRevocation)
+            .version(SignatureVersion::for_key(key))
+            .pub_alg(key.algorithm())
+            .hashed_subpackets(vec![
+                Subpacket::SignatureCreationTime(Utc::now().trunc_subsecs(0)),
+                reason.into(),
+            ])
+            .unhashed_subpackets(vec![])
+            .build()?
+            .with_issuer(key);
+
+        config.sign_key(key, key_pw, key)
+    }
+
+    /// Returns the first secret subkey that is bound as a signing key, with a valid back
+    /// signature, and is neither expired nor revoked. Subkeys without secret material
+    /// are skipped.
+    ///
+    /// Data signatures can be made with it, when the secret material of the primary key
+    /// is not available, or the primary key is only used for certifications.
+    pub fn signing_subkey(&self) -> Option<&SignedSecretSubKey> {
+        let now = Utc::now();
+        self.secret_subkeys.iter().find(|subkey| {
+            let can_sign = !subkey.key.secret_params().is_stub()
+                && subkey.can_sign(&self.primary_key, &Policy::default());
+            let expired = subkey
+                .expires_at(&self.primary_key)
+                .map_or(false, |expires_at| expires_at <= now);
+
+            can_sign && !expired && !subkey.is_revoked(&self.primary_key)
+        })
+    }
+
+    /// Revokes the public or secret subkey with the given key id.
+    pub fn revoke_subkey<F>(
+        &mut self,
+        key_id: &KeyId,
+        reason: RevocationReason,
+        key_pw: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        let primary_key = &self.primary_key;
+        if let Some(subkey) = self
+            .public_subkeys
+            .iter_mut()
+            .find(|subkey| &subkey.key_id() == key_id)
+        {
+            return subkey.revoke(primary_key, key_pw, reason);
+        }
+
+        match self
+            .secret_subkeys
+            .iter_mut()
+            .find(|subkey| &subkey.key_id() == key_id)
+        {
+            Some(subkey) => {
+                let sig = subkey_revocation(primary_key, key_pw, &subkey.key, reason)?;
+                subkey.signatures.push(sig);
+                Ok(())
+            }
+            None => bail!("unknown subkey {:?}", key_id),
+        }
+    }
+
+    /// Revokes the given user id.
+    ///
+    /// Revoked user ids are kept in the key, but are skipped when looking for the primary user id.
+    pub fn revoke_user_id<F>(
+        &mut self,
+        id: &packet::UserId,
+        reason: RevocationReason,
+        key_pw: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        let primary_key = &self.primary_key;
+        match self.details.users.iter_mut().find(|user| &user.id == id) {
+            Some(user) => user.revoke(primary_key, key_pw, reason),
+            None => bail!("unknown user id {:?}", id),
+        }
+    }
+
+    /// Adds a new user id, certified with the preferences of the current primary user id.
+    ///
+    /// The new user id is not marked as primary.
+    pub fn add_user_id<F>(&mut self, id: &str, key_pw: F) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        let key = &self.primary_key;
+        let id = packet::UserId::from_str(Default::default(), id);
+        ensure!(
+            self.details.users.iter().all(|user| user.id != id),
+            "user id {:?} exists already",
+            id
+        );
+
+        // use the latest self-certification of the primary user id as template
+        let template = self
+            .details
+            .primary_user(key)
+            .and_then(|user| latest_self_certification(user, key));
+        let hashed_subpackets = match template {
+            Some(sig) => renewed_subpackets(sig, |p| match p {
+                Subpacket::IsPrimary(_) => false,
+                _ => true,
+            }),
+            None => vec![Subpacket::SignatureCreationTime(
+                Utc::now().trunc_subsecs(0),
+            )],
+        };
+
+        let sig = SignatureConfigBuilder::default()
+            .typ(SignatureType::CertGeneric)
+            .version(SignatureVersion::for_key(key))
+            .pub_alg(key.algorithm())
+            .hashed_subpackets(hashed_subpackets)
+            .unhashed_subpackets(vec![])
+            .build()?
+            .with_issuer(key)
+            .sign_certificate(key, key_pw, Tag::UserId, &id)?;
+        self.details.users.push(SignedUser::new(id, vec![sig]));
+
+        Ok(())
+    }
+
+    /// Sets the expiration time of the key, relative to its creation time.
+    /// `None` means the key never expires.
+    ///
+    /// The self-certifications of all user ids that are not revoked, and the direct key
+    /// signatures, are replaced by new ones, carrying the new expiration time.
+    pub fn set_expiration<F>(&mut self, expiration: Option<Duration>, key_pw: F) -> Result<()>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        if let Some(expiration) = expiration {
+            ensure!(
+                expiration > Duration::zero()
+                    && expiration.num_seconds() <= i64::from(std::u32::MAX),
+                "invalid key expiration time {}",
+                expiration
+            );
+        }
+
+        let expiration: Vec<_> = expiration
+            .map(key_expiration_subpacket)
+            .into_iter()
+            .collect();
+        self.reissue_self_signatures(
+            key_pw,
+            |p| match p {
+                Subpacket::KeyExpirationTime(_) => false,
+                _ => true,
+            },
+            &expiration,
+            |_| true,
+        )
+    }
+
+    /// Replaces the newest self-certification of every user id that is not revoked, and
+    /// the newest direct key signature if `renew_direct` accepts it, by a new signature.
+    ///
+    /// The hashed subpackets of the old signature for which `keep` returns true are
+    /// copied, followed by `subpackets`. The new signatures are always created after the
+    /// ones they replace, which are removed.
+    pub(crate) fn reissue_self_signatures<F>(
+        &mut self,
+        key_pw: F,
+        keep: impl Fn(&Subpacket) -> bool,
+        subpackets: &[Subpacket],
+        renew_direct: impl Fn(&packet::Signature) -> bool,
+    ) -> Result<()>
+    where
+        F: (FnOnce() -> String) + Clone,
+    {
+        let key = &self.primary_key;
+        let renew = |template: &packet::Signature| -> Result<SignatureConfig> {
+            // timestamps have a resolution of seconds, make sure the new signature is newer
+            let created = match template.created() {
+                Some(created) if *created >= Utc::now().trunc_subsecs(0) => {
+                    *created + Duration::seconds(1)
+                }
+                _ => Utc::now().trunc_subsecs(0),
+            };
+            let mut hashed_subpackets: Vec<_> = renewed_subpackets(template, &keep)
+                .into_iter()
+                .map(|p| match p {
+                    Subpacket::SignatureCreationTime(_) => {
+                        Subpacket::SignatureCreationTime(created)
+                    }
+                    p => p,
+                })
+                .collect();
+            hashed_subpackets.extend_from_slice(subpackets);
+
+            Ok(SignatureConfigBuilder::default()
+                .typ(template.typ())
+                .version(SignatureVersion::for_key(key))
+                .pub_alg(key.algorithm())
+                .hashed_subpackets(hashed_subpackets)
+                .unhashed_subpackets(vec![])
+                .build()?
+                .with_issuer(key))
+        };
+
+        for user in self
+            .details
+            .users
+            .iter_mut()
+            .filter(|u| !u.is_revoked_by(key))
+        {
+            let sig = match latest_self_certification(user, key) {
+                Some(template) => {
+                    renew(template)?.sign_certificate(key, key_pw.clone(), Tag::UserId, &user.id)?
+                }
+                None => continue,
+            };
+            user.signatures
+                .retain(|sig| !(sig.is_certification() && sig.is_issued_by(key)));
+            user.signatures.push(sig);
+        }
+
+        let direct_signatures = &mut self.details.direct_signatures;
+        let template = direct_signatures
+            .iter()
+            .filter(|sig| sig.is_issued_by(key))
+            .last()
+            .filter(|sig| renew_direct(sig));
+        if let Some(template) = template {
+            let sig = renew(template)?.sign_key(key, key_pw, key)?;
+            direct_signatures.retain(|sig| !sig.is_issued_by(key));
+            direct_signatures.push(sig);
+        }
+
+        Ok(())
+    }
+
+    /// Removes the given user id and its signatures from the key.
+    ///
+    /// Unlike a revocation, this only affects this copy of the key, other copies
+    /// are not informed about the removal.
+    pub fn remove_user_id(&mut self, id: &packet::UserId) -> Result<()> {
+        let users = &mut self.details.users;
+        let index = match users.iter().position(|user| &user.id == id) {
+            Some(index) => index,
+            None => bail!("unknown user id {:?}", id),
+        };
+        ensure!(users.len() > 1, "can not remove the last user id");
+        users.remove(index);
+
+        Ok(())
+    }
+
     fn verify_public_subkeys(&self) -> Result<()> {
         for subkey in &self.public_subkeys {
             subkey.verify(&self.primary_key)?;
@@ -98,6 +538,26 @@ impl SignedSecretKey {
         Ok(())
     }
 
+    /// Returns all packets of the key, in the order they are serialized.
+    pub fn packets(&self) -> impl Iterator<Item = PacketRef<'_>> {
+        std::iter::once(PacketRef::SecretKey(&self.primary_key))
+            .chain(self.details.packets())
+            .chain(
+                self.public_subkeys
+                    .iter()
+                    .flat_map(SignedPublicSubKey::packets),
+            )
+            .chain(
+                self.secret_subkeys
+                    .iter()
+                    .flat_map(SignedSecretSubKey::packets),
+            )
+    }
+
+    /// Verifies the self-signatures and subkey bindings.
+    ///
+    /// Back signatures of signing subkeys are not required, subkeys without a valid one
+    /// are not used for signing, see [`Policy::require_back_signatures`].
     pub fn verify(&self) -> Result<()> {
         self.details.verify(&self.primary_key)?;
         self.verify_public_subkeys()?;
@@ -109,12 +569,12 @@ impl SignedSecretKey {
     pub fn to_armored_writer(
         &self,
         writer: &mut impl io::Write,
-        headers: Option<&BTreeMap<String, String>>,
+        headers: Option<&Headers>,
     ) -> Result<()> {
         armor::write(self, armor::BlockType::PrivateKey, writer, headers)
     }
 
-    pub fn to_armored_bytes(&self, headers: Option<&BTreeMap<String, String>>) -> Result<Vec<u8>> {
+    pub fn to_armored_bytes(&self, headers: Option<&Headers>) -> Result<Vec<u8>> {
         let mut buf = Vec::new();
 
         self.to_armored_writer(&mut buf, headers)?;
@@ -122,9 +582,38 @@ impl SignedSecretKey {
         Ok(buf)
     }
 
-    pub fn to_armored_string(&self, headers: Option<&BTreeMap<String, String>>) -> Result<String> {
+    pub fn to_armored_string(&self, headers: Option<&Headers>) -> Result<String> {
         Ok(::std::str::from_utf8(&self.to_armored_bytes(headers)?)?.to_string())
     }
+
+    /// Returns the public certificate of this key, for publishing.
+    ///
+    /// All user ids, subkeys and signatures are kept, only the secret material is removed.
+    /// Unlike [`SecretKeyTrait::public_key`], this does not create new signatures.
+    pub fn signed_public_key(&self) -> SignedPublicKey {
+        let public_subkeys = self
+            .public_subkeys
+            .iter()
+            .cloned()
+            .chain(
+                self.secret_subkeys
+                    .iter()
+                    .map(SignedSecretSubKey::signed_public_key),
+            )
+            .collect();
+
+        SignedPublicKey::new(
+            self.primary_key.public_key(),
+            self.details.clone(),
+            public_subkeys,
+        )
+    }
+}
+
+impl From<&SignedSecretKey> for SignedPublicKey {
+    fn from(key: &SignedSecretKey) -> Self {
+        key.signed_public_key()
+    }
 }
 
 impl KeyTrait for SignedSecretKey {
@@ -141,6 +630,10 @@ impl KeyTrait for SignedSecretKey {
     fn algorithm(&self) -> PublicKeyAlgorithm {
         self.primary_key.algorithm()
     }
+
+    fn version(&self) -> KeyVersion {
+        self.primary_key.version()
+    }
 }
 
 impl Serialize for SignedSecretKey {
@@ -189,7 +682,7 @@ impl SecretKeyTrait for SignedSecretKey {
 
         PublicKey::new(
             self.primary_key.public_key(),
-            self.details.as_unsigned(),
+            self.details.as_unsigned(&self.primary_key),
             subkeys,
         )
     }
@@ -235,15 +728,84 @@ impl SignedSecretSubKey {
         SignedSecretSubKey { key, signatures }
     }
 
+    /// Verifies the binding signatures made by `key`.
+    ///
+    /// Embedded back signatures are not checked, see [`can_sign`].
+    ///
+    /// [`can_sign`]: SignedSecretSubKey::can_sign
     pub fn verify(&self, key: &impl PublicKeyTrait) -> Result<()> {
         ensure!(!self.signatures.is_empty(), "missing subkey bindings");
-
         for sig in &self.signatures {
             sig.verify_key_binding(key, &self.key)?;
         }
 
         Ok(())
     }
+
+    /// Returns true if the newest binding made by `primary_key` allows signing.
+    ///
+    /// Signing subkeys have to cross-certify the primary key with an embedded back
+    /// signature, unless `policy` does not require it. Subkeys without one are ignored
+    /// for signing, but do not invalidate the key.
+    pub fn can_sign(&self, primary_key: &impl PublicKeyTrait, policy: &Policy) -> bool {
+        is_signing_subkey_binding(primary_key, &self.key, &self.signatures, policy)
+    }
+
+    /// Returns the subkey packet, followed by its signatures.
+    pub fn packets(&self) -> impl Iterator<Item = PacketRef<'_>> {
+        std::iter::once(PacketRef::SecretSubkey(&self.key))
+            .chain(self.signatures.iter().map(PacketRef::Signature))
+    }
+
+    /// Get the subkey expiration time as a duration, relative to the subkey creation time.
+    ///
+    /// Uses the `KeyExpirationTime` offset of the newest binding signature made by
+    /// `primary_key`, which verifies. Returns `None` if the subkey has an infinite
+    /// validity, or no valid binding signature.
+    pub fn key_expiration_time(&self, primary_key: &impl PublicKeyTrait) -> Option<Duration> {
+        self.latest_binding(primary_key)
+            .and_then(key_expiration_offset)
+    }
+
+    /// Get the subkey expiration as a date.
+    pub fn expires_at(&self, primary_key: &impl PublicKeyTrait) -> Option<DateTime<Utc>> {
+        let expiration = self.key_expiration_time(primary_key)?;
+        Some(*self.key.created_at() + expiration)
+    }
+
+    /// Returns true if the subkey is expired at the time `at`.
+    pub fn is_expired(&self, primary_key: &impl PublicKeyTrait, at: DateTime<Utc>) -> bool {
+        self.expires_at(primary_key)
+            .map_or(false, |expires_at| expires_at <= at)
+    }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        self.key.created_at()
+    }
+
+    /// Returns true if the subkey has a revocation signature made by `primary_key`, which
+    /// verifies.
+    pub fn is_revoked(&self, primary_key: &impl PublicKeyTrait) -> bool {
+        is_subkey_revoked(primary_key, &self.key, &self.signatures)
+    }
+
+    /// Returns the newest binding signature made by `primary_key`, which verifies.
+    ///
+    /// Bindings that can not be verified are ignored, anyone can attach them.
+    pub fn latest_binding(&self, primary_key: &impl PublicKeyTrait) -> Option<&packet::Signature> {
+        latest_subkey_binding(primary_key, &self.key, &self.signatures)
+    }
+
+    /// Returns the public subkey, with all of its signatures.
+    pub fn signed_public_key(&self) -> SignedPublicSubKey {
+        SignedPublicSubKey::new(self.key.public_key(), self.signatures.clone())
+    }
+}
+
+impl From<&SignedSecretSubKey> for SignedPublicSubKey {
+    fn from(key: &SignedSecretSubKey) -> Self {
+        key.signed_public_key()
+    }
 }
 
 impl KeyTrait for SignedSecretSubKey {
@@ -260,6 +822,10 @@ impl KeyTrait for SignedSecretSubKey {
     fn algorithm(&self) -> PublicKeyAlgorithm {
         self.key.algorithm()
     }
+
+    fn version(&self) -> KeyVersion {
+        self.key.version()
+    }
 }
 
 impl Serialize for SignedSecretSubKey {
@@ -292,13 +858,14 @@ impl SecretKeyTrait for SignedSecretSubKey {
     }
 
     fn public_key(&self) -> Self::PublicKey {
-        let keyflags = self
-            .signatures
-            .first()
-            .expect("invalid signed subkey")
-            .key_flags();
+        let binding = newest_subkey_binding(&self.signatures);
+        let keyflags = binding.map(|sig| sig.key_flags()).unwrap_or_default();
 
-        PublicSubkey::new(self.key.public_key(), keyflags)
+        PublicSubkey::new(
+            self.key.public_key(),
+            keyflags,
+            binding.and_then(key_expiration_offset),
+        )
     }
 }
 
@@ -1,16 +1,23 @@
-use std::collections::BTreeMap;
 use std::io;
 
-use chrono::Duration;
+use chrono::{DateTime, Duration, SubsecRound, Utc};
 use smallvec::SmallVec;
 
+use crate::armor::Headers;
 use crate::composed::key::KeyDetails;
 use crate::composed::signed_key::{SignedPublicKey, SignedSecretKey};
+use crate::composed::Policy;
 use crate::crypto::public_key::PublicKeyAlgorithm;
 use crate::errors::Result;
-use crate::packet;
+use crate::packet::{
+    self, PacketRef, RevocationReason, SignatureConfigBuilder, SignatureType, SignatureVersion,
+    Subpacket,
+};
 use crate::ser::Serialize;
-use crate::types::{KeyId, KeyTrait, PublicKeyTrait, SignedUser, SignedUserAttribute};
+use crate::types::{
+    KeyId, KeyTrait, KeyVersion, PublicKeyTrait, SecretKeyTrait, SignedUser, SignedUserAttribute,
+    Tag,
+};
 
 /// Shared details between secret and public keys.
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -56,24 +63,25 @@ impl SignedKeyDetails {
 
     /// Get the key expiration time as a duration.
     ///
-    /// This method finds the signature with the maximum
-    /// `KeyExpirationTime` offset (which should only occur in
-    /// self-signed signatures) and converts it into a duration.
-    /// The function returns `None` if the key has an infinite
-    /// validity.
-    pub fn key_expiration_time(&self) -> Option<Duration> {
-        // Find the maximum key_expiration_time in all signatures of all user ids.
-        if let Some(tm) = self
-            .users
+    /// The expiration time is taken from the newest self-signature of `key`, the primary
+    /// key, which verifies. Returns `None` if the key has an infinite validity.
+    pub fn key_expiration_time(&self, key: &impl PublicKeyTrait) -> Option<Duration> {
+        self.latest_self_signature(key)
+            .and_then(key_expiration_offset)
+    }
+
+    /// Returns the packets in the order they are serialized.
+    pub fn packets(&self) -> impl Iterator<Item = PacketRef<'_>> {
+        self.revocation_signatures
             .iter()
-            .flat_map(|user| &user.signatures)
-            .filter_map(|sig| sig.key_expiration_time())
-            .max()
-        {
-            Some(Duration::seconds(tm.timestamp()))
-        } else {
-            None
-        }
+            .chain(self.direct_signatures.iter())
+            .map(PacketRef::Signature)
+            .chain(self.users.iter().flat_map(SignedUser::packets))
+            .chain(
+                self.user_attributes
+                    .iter()
+                    .flat_map(SignedUserAttribute::packets),
+            )
     }
 
     fn verify_users(&self, key: &impl PublicKeyTrait) -> Result<()> {
@@ -117,32 +125,109 @@ impl SignedKeyDetails {
         Ok(())
     }
 
-    pub fn as_unsigned(&self) -> KeyDetails {
-        let primary_user = if let Some(user) = self.users.iter().find(|u| u.is_primary()) {
-            user
-        } else {
-            self.users.first().expect("missing user ids")
-        };
+    /// Returns true if the key carries a revocation signature made by `key`, the primary
+    /// key, which verifies.
+    ///
+    /// Revocations that can not be verified are ignored, anyone can attach them.
+    pub fn is_revoked(&self, key: &impl PublicKeyTrait) -> bool {
+        self.revocation_signatures.iter().any(|sig| {
+            sig.typ() == SignatureType::KeyRevocation
+                && sig.is_issued_by(key)
+                && sig.verify_key(key).is_ok()
+        })
+    }
+
+    /// Returns the primary user id now, see [`primary_user_at`](Self::primary_user_at).
+    pub fn primary_user(&self, key: &impl PublicKeyTrait) -> Option<&SignedUser> {
+        self.primary_user_at(key, Utc::now())
+    }
+
+    /// Returns the newest self-signature of `key`, which verifies.
+    ///
+    /// These are the direct key signatures, and the self-certifications of user ids
+    /// that are not revoked.
+    pub fn latest_self_signature(&self, key: &impl PublicKeyTrait) -> Option<&packet::Signature> {
+        let direct_signatures = self.direct_signatures.iter().filter(|sig| {
+            sig.typ() == SignatureType::Key && sig.is_issued_by(key) && sig.verify_key(key).is_ok()
+        });
+        let certifications = self
+            .users
+            .iter()
+            .filter(|user| !user.is_revoked_by(key))
+            .flat_map(|user| {
+                user.signatures.iter().filter(move |sig| {
+                    sig.is_certification()
+                        && sig.is_issued_by(key)
+                        && sig.verify_certificate(key, Tag::UserId, &user.id).is_ok()
+                })
+            });
+
+        newest_signature(direct_signatures.chain(certifications))
+    }
 
-        let primary_user_id = primary_user.id.clone();
+    /// Returns the primary user id at the time `at`, following the rules of RFC 4880.
+    ///
+    /// Only user ids that are not revoked by `key` and have a self-certification that
+    /// verifies and is valid at `at` are considered. Of those, the user id whose newest
+    /// self-certification is marked as primary wins, the most recent one if there are
+    /// several. Without any user id marked as primary, the first one is returned.
+    pub fn primary_user_at(
+        &self,
+        key: &impl PublicKeyTrait,
+        at: DateTime<Utc>,
+    ) -> Option<&SignedUser> {
+        let candidates: Vec<_> = self
+            .users
+            .iter()
+            .filter(|user| !is_user_revoked_at(user, key, at))
+            .filter_map(|user| Some((user, valid_self_certification(user, key, at)?)))
+            .collect();
+
+        candidates
+            .iter()
+            .filter(|(_, sig)| sig.is_primary())
+            .fold(
+                None,
+                |primary: Option<&(&SignedUser, &packet::Signature)>, c| match primary {
+                    Some(primary) if primary.1.created() >= c.1.created() => Some(primary),
+                    _ => Some(c),
+                },
+            )
+            .or_else(|| candidates.first())
+            .map(|(user, _)| *user)
+    }
+
+    /// Returns the details, taking the preferences from the newest self-certification of
+    /// the primary user id, or from the newest self-signature for keys without user ids.
+    ///
+    /// Keys without any self-signature that verifies get the default details.
+    pub fn as_unsigned(&self, key: &impl PublicKeyTrait) -> KeyDetails {
+        let primary_user = self.primary_user(key).or_else(|| self.users.first());
         let primary_sig = primary_user
-            .signatures
-            .first()
-            .expect("invalid primary user");
-        let keyflags = primary_sig.key_flags();
+            .and_then(|user| {
+                newest_signature(user.signatures.iter().filter(|sig| {
+                    sig.is_certification()
+                        && sig.is_issued_by(key)
+                        && sig.verify_certificate(key, Tag::UserId, &user.id).is_ok()
+                }))
+            })
+            .or_else(|| self.latest_self_signature(key));
+        let keyflags = primary_sig.map(|sig| sig.key_flags()).unwrap_or_default();
 
         let preferred_symmetric_algorithms =
-            SmallVec::from_slice(primary_sig.preferred_symmetric_algs());
-        let preferred_hash_algorithms = SmallVec::from_slice(primary_sig.preferred_hash_algs());
-        let preferred_compression_algorithms =
-            SmallVec::from_slice(primary_sig.preferred_compression_algs());
-        let revocation_key = primary_sig.revocation_key().cloned();
+            SmallVec::from_slice(primary_sig.map_or(&[][..], |sig| sig.preferred_symmetric_algs()));
+        let preferred_hash_algorithms =
+            SmallVec::from_slice(primary_sig.map_or(&[][..], |sig| sig.preferred_hash_algs()));
+        let preferred_compression_algorithms = SmallVec::from_slice(
+            primary_sig.map_or(&[][..], |sig| sig.preferred_compression_algs()),
+        );
+        let revocation_key = primary_sig.and_then(|sig| sig.revocation_key().cloned());
 
         KeyDetails::new(
-            primary_user_id,
+            primary_user.map(|u| u.id.clone()),
             self.users
                 .iter()
-                .filter(|u| !u.is_primary())
+                .filter(|u| primary_user.map_or(true, |p| u.id != p.id))
                 .map(|u| u.id.clone())
                 .collect(),
             self.user_attributes
@@ -154,6 +239,7 @@ impl SignedKeyDetails {
             preferred_hash_algorithms,
             preferred_compression_algorithms,
             revocation_key,
+            self.key_expiration_time(key),
         )
     }
 }
@@ -198,7 +284,7 @@ impl PublicOrSecret {
     pub fn to_armored_writer(
         &self,
         writer: &mut impl io::Write,
-        headers: Option<&BTreeMap<String, String>>,
+        headers: Option<&Headers>,
     ) -> Result<()> {
         match self {
             PublicOrSecret::Public(k) => k.to_armored_writer(writer, headers),
@@ -206,14 +292,14 @@ impl PublicOrSecret {
         }
     }
 
-    pub fn to_armored_bytes(&self, headers: Option<&BTreeMap<String, String>>) -> Result<Vec<u8>> {
+    pub fn to_armored_bytes(&self, headers: Option<&Headers>) -> Result<Vec<u8>> {
         match self {
             PublicOrSecret::Public(k) => k.to_armored_bytes(headers),
             PublicOrSecret::Secret(k) => k.to_armored_bytes(headers),
         }
     }
 
-    pub fn to_armored_string(&self, headers: Option<&BTreeMap<String, String>>) -> Result<String> {
+    pub fn to_armored_string(&self, headers: Option<&Headers>) -> Result<String> {
         match self {
             PublicOrSecret::Public(k) => k.to_armored_string(headers),
             PublicOrSecret::Secret(k) => k.to_armored_string(headers),
@@ -283,4 +369,191 @@ impl KeyTrait for PublicOrSecret {
             PublicOrSecret::Secret(k) => k.algorithm(),
         }
     }
+
+    fn version(&self) -> KeyVersion {
+        match self {
+            PublicOrSecret::Public(k) => k.version(),
+            PublicOrSecret::Secret(k) => k.version(),
+        }
+    }
+}
+
+/// Creates a `SubkeyRevocation` signature by `primary_key` over `subkey`.
+pub(crate) fn subkey_revocation<F>(
+    primary_key: &impl SecretKeyTrait,
+    key_pw: F,
+    subkey: &impl PublicKeyTrait,
+    reason: RevocationReason,
+) -> Result<packet::Signature>
+where
+    F: FnOnce() -> String,
+{
+    SignatureConfigBuilder::default()
+        .typ(SignatureType::SubkeyRevocation)
+        .version(SignatureVersion::for_key(primary_key))
+        .pub_alg(primary_key.algorithm())
+        .hashed_subpackets(vec![
+            Subpacket::SignatureCreationTime(Utc::now().trunc_subsecs(0)),
+            reason.into(),
+        ])
+        .unhashed_subpackets(vec![])
+        .build()?
+        .with_issuer(primary_key)
+        .sign_key_binding(primary_key, key_pw, subkey)
+}
+
+/// Returns the latest certification of `user` made by `key`, ignoring revocations.
+pub(crate) fn latest_self_certification<'a>(
+    user: &'a SignedUser,
+    key: &impl KeyTrait,
+) -> Option<&'a packet::Signature> {
+    user.signatures
+        .iter()
+        .filter(|sig| sig.is_certification() && sig.is_issued_by(key))
+        .last()
+}
+
+/// Returns the newest self-certification of `user`, which verifies and is valid at `at`.
+pub(crate) fn valid_self_certification<'a>(
+    user: &'a SignedUser,
+    key: &impl PublicKeyTrait,
+    at: DateTime<Utc>,
+) -> Option<&'a packet::Signature> {
+    let signatures = user
+        .signatures
+        .iter()
+        .filter(|sig| sig.is_certification() && sig.is_issued_by(key))
+        .filter(|sig| match sig.created() {
+            Some(created) => {
+                let expired = sig
+                    .signature_expiration_time()
+                    .filter(|expiration| expiration.timestamp() > 0)
+                    .map_or(false, |expiration| {
+                        *created + Duration::seconds(expiration.timestamp()) <= at
+                    });
+                created <= &at && !expired
+            }
+            None => false,
+        })
+        .filter(|sig| sig.verify_certificate(key, Tag::UserId, &user.id).is_ok());
+
+    newest_signature(signatures)
+}
+
+/// Returns true if `user` has a revocation made by `key` before `at`, which verifies.
+fn is_user_revoked_at(user: &SignedUser, key: &impl PublicKeyTrait, at: DateTime<Utc>) -> bool {
+    user.signatures.iter().any(|sig| {
+        sig.typ() == SignatureType::CertRevocation
+            && sig.is_issued_by(key)
+            && sig.created().map_or(false, |created| created <= &at)
+            && sig.verify_certificate(key, Tag::UserId, &user.id).is_ok()
+    })
+}
+
+/// Returns the newest binding signature in `signatures` made by `primary_key` over
+/// `subkey`, which verifies.
+pub(crate) fn latest_subkey_binding<'a>(
+    primary_key: &impl PublicKeyTrait,
+    subkey: &impl PublicKeyTrait,
+    signatures: &'a [packet::Signature],
+) -> Option<&'a packet::Signature> {
+    newest_signature(signatures.iter().filter(|sig| {
+        sig.typ() == SignatureType::SubkeyBinding
+            && sig.is_issued_by(primary_key)
+            && sig.verify_key_binding(primary_key, subkey).is_ok()
+    }))
+}
+
+/// Returns true if the newest binding in `signatures` made by `primary_key` allows
+/// signing, and carries a back signature of `subkey` over `primary_key`, which verifies.
+///
+/// A missing back signature is only accepted, if `policy` does not require them.
+pub(crate) fn is_signing_subkey_binding(
+    primary_key: &impl PublicKeyTrait,
+    subkey: &impl PublicKeyTrait,
+    signatures: &[packet::Signature],
+    policy: &Policy,
+) -> bool {
+    let binding = match latest_subkey_binding(primary_key, subkey, signatures) {
+        Some(binding) if binding.key_flags().sign() => binding,
+        _ => return false,
+    };
+
+    match binding.embedded_signature() {
+        Some(backsig) => backsig
+            .verify_primary_key_binding(subkey, primary_key)
+            .is_ok(),
+        None => !policy.require_back_signatures,
+    }
+}
+
+/// Returns true if `signatures` contain a revocation of `subkey` made by `primary_key`,
+/// which verifies.
+pub(crate) fn is_subkey_revoked(
+    primary_key: &impl PublicKeyTrait,
+    subkey: &impl PublicKeyTrait,
+    signatures: &[packet::Signature],
+) -> bool {
+    signatures.iter().any(|sig| {
+        sig.typ() == SignatureType::SubkeyRevocation
+            && sig.is_issued_by(primary_key)
+            && sig.verify_key_binding(primary_key, subkey).is_ok()
+    })
+}
+
+/// Returns the newest binding signature in `signatures`, without verifying it.
+///
+/// Only for subkeys that have been checked by `verify`, which requires the primary key.
+pub(crate) fn newest_subkey_binding(
+    signatures: &[packet::Signature],
+) -> Option<&packet::Signature> {
+    newest_signature(
+        signatures
+            .iter()
+            .filter(|sig| sig.typ() == SignatureType::SubkeyBinding),
+    )
+}
+
+/// Returns the signature with the latest creation time, the last one of those if several
+/// were created at the same time.
+pub(crate) fn newest_signature<'a>(
+    signatures: impl Iterator<Item = &'a packet::Signature>,
+) -> Option<&'a packet::Signature> {
+    signatures.fold(
+        None,
+        |latest: Option<&packet::Signature>, sig| match latest {
+            Some(latest) if latest.created() > sig.created() => Some(latest),
+            _ => Some(sig),
+        },
+    )
+}
+
+/// Returns the `KeyExpirationTime` offset of `sig`, `None` if the key never expires.
+pub(crate) fn key_expiration_offset(sig: &packet::Signature) -> Option<Duration> {
+    sig.key_expiration_time()
+        .map(|tm| Duration::seconds(tm.timestamp()))
+        .filter(|offset| *offset > Duration::zero())
+}
+
+/// Returns the hashed subpackets of `template` for which `keep` returns true,
+/// with a new signature creation time.
+pub(crate) fn renewed_subpackets(
+    template: &packet::Signature,
+    keep: impl Fn(&Subpacket) -> bool,
+) -> Vec<Subpacket> {
+    std::iter::once(Subpacket::SignatureCreationTime(
+        Utc::now().trunc_subsecs(0),
+    ))
+    .chain(
+        template
+            .config
+            .hashed_subpackets
+            .iter()
+            .filter(|p| match p {
+                Subpacket::SignatureCreationTime(_) => false,
+                p => keep(p),
+            })
+            .cloned(),
+    )
+    .collect()
 }
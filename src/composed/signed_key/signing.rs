@@ -0,0 +1,232 @@
+use std::io;
+
+use chrono::{DateTime, Utc};
+use rand::{CryptoRng, Rng};
+
+use crate::composed::signed_key::{SignedPublicKey, SignedPublicSubKey};
+use crate::composed::Policy;
+use crate::crypto::{HashAlgorithm, PublicKeyAlgorithm};
+use crate::errors::Result;
+use crate::packet;
+use crate::types::{KeyId, KeyTrait, KeyVersion, Mpi, PublicKeyTrait};
+
+/// A key that can be used for data signatures, see [`SignedPublicKey::signing_key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningKey<'a> {
+    Primary(&'a packet::PublicKey),
+    Subkey(&'a SignedPublicSubKey),
+}
+
+impl KeyTrait for SigningKey<'_> {
+    fn fingerprint(&self) -> Vec<u8> {
+        match self {
+            SigningKey::Primary(key) => key.fingerprint(),
+            SigningKey::Subkey(key) => key.fingerprint(),
+        }
+    }
+
+    fn key_id(&self) -> KeyId {
+        match self {
+            SigningKey::Primary(key) => key.key_id(),
+            SigningKey::Subkey(key) => key.key_id(),
+        }
+    }
+
+    fn algorithm(&self) -> PublicKeyAlgorithm {
+        match self {
+            SigningKey::Primary(key) => key.algorithm(),
+            SigningKey::Subkey(key) => key.algorithm(),
+        }
+    }
+
+    fn version(&self) -> KeyVersion {
+        match self {
+            SigningKey::Primary(key) => key.version(),
+            SigningKey::Subkey(key) => key.version(),
+        }
+    }
+}
+
+impl PublicKeyTrait for SigningKey<'_> {
+    fn verify_signature(&self, hash: HashAlgorithm, data: &[u8], sig: &[Mpi]) -> Result<()> {
+        match self {
+            SigningKey::Primary(key) => key.verify_signature(hash, data, sig),
+            SigningKey::Subkey(key) => key.verify_signature(hash, data, sig),
+        }
+    }
+
+    fn encrypt<R: CryptoRng + Rng>(&self, rng: &mut R, plain: &[u8]) -> Result<Vec<Mpi>> {
+        match self {
+            SigningKey::Primary(key) => key.encrypt(rng, plain),
+            SigningKey::Subkey(key) => key.encrypt(rng, plain),
+        }
+    }
+
+    fn to_writer_old(&self, writer: &mut impl io::Write) -> Result<()> {
+        match self {
+            SigningKey::Primary(key) => key.to_writer_old(writer),
+            SigningKey::Subkey(key) => key.to_writer_old(writer),
+        }
+    }
+}
+
+impl SignedPublicKey {
+    /// Returns the key that makes data signatures for this key at the time `at`.
+    ///
+    /// Signing subkeys are preferred over the primary key, the most recently created one
+    /// if there are several. Only keys that are neither revoked nor expired, and whose
+    /// newest self-signature allows signing are considered. Subkeys also need a valid
+    /// primary key binding signature.
+    pub fn signing_key(&self, at: DateTime<Utc>) -> Option<SigningKey<'_>> {
+        self.signing_key_with_policy(at, &Policy::default())
+    }
+
+    /// Returns the key that makes data signatures for this key at the time `at`, like
+    /// [`signing_key`], but only requires back signatures of subkeys if `policy` does.
+    ///
+    /// [`signing_key`]: SignedPublicKey::signing_key
+    pub fn signing_key_with_policy(
+        &self,
+        at: DateTime<Utc>,
+        policy: &Policy,
+    ) -> Option<SigningKey<'_>> {
+        let primary_key = &self.primary_key;
+        if self.details.is_revoked(primary_key) || self.is_expired(at) || self.created_at() > &at {
+            return None;
+        }
+
+        let subkey = self
+            .public_subkeys
+            .iter()
+            .filter(|subkey| is_signing_subkey(primary_key, subkey, at, policy))
+            .max_by_key(|subkey| *subkey.created_at());
+        if let Some(subkey) = subkey {
+            return Some(SigningKey::Subkey(subkey));
+        }
+
+        let can_sign = self
+            .details
+            .latest_self_signature(primary_key)
+            .map_or(false, |sig| sig.key_flags().sign());
+        if can_sign && primary_key.is_signing_key() {
+            Some(SigningKey::Primary(primary_key))
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns true if `subkey` is bound as a signing key to `primary_key`, and usable at `at`.
+fn is_signing_subkey(
+    primary_key: &packet::PublicKey,
+    subkey: &SignedPublicSubKey,
+    at: DateTime<Utc>,
+    policy: &Policy,
+) -> bool {
+    !subkey.is_revoked(primary_key)
+        && !subkey.is_expired(primary_key, at)
+        && subkey.created_at() <= &at
+        && subkey.is_signing_key()
+        && subkey.can_sign(primary_key, policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Duration;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::{KeyType, SecretKeyParamsBuilder, SubkeyParamsBuilder};
+    use crate::packet::{RevocationCode, RevocationReason};
+
+    #[test]
+    fn test_signing_key() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let now = Utc::now();
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::ECDH)
+                    .can_encrypt(true)
+                    .build()
+                    .unwrap(),
+            )
+            .subkey(
+                SubkeyParamsBuilder::default()
+                    .key_type(KeyType::EdDSA)
+                    .can_sign(true)
+                    .expiration(Some(std::time::Duration::from_secs(24 * 60 * 60)))
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+        let mut public_key = key.signed_public_key();
+
+        let signing_key = public_key.signing_key(now).unwrap();
+        assert_eq!(
+            signing_key,
+            SigningKey::Subkey(&public_key.public_subkeys[1])
+        );
+        // encryption and signing subkeys are never mixed up
+        assert_eq!(
+            public_key.encryption_subkeys(now).unwrap(),
+            vec![&public_key.public_subkeys[0]]
+        );
+
+        // the primary key is used once the subkey expired
+        match public_key.signing_key(now + Duration::days(2)) {
+            Some(SigningKey::Primary(primary)) => assert_eq!(primary, &public_key.primary_key),
+            res => panic!("unexpected signing key {:?}", res),
+        }
+        assert_eq!(
+            public_key.signing_key(now - Duration::days(1)),
+            None,
+            "the key is not valid before its creation"
+        );
+
+        let subkey_id = public_key.public_subkeys[1].key_id();
+        public_key
+            .revoke_subkey(
+                &subkey_id,
+                RevocationReason::new(RevocationCode::KeyRetired, ""),
+                &key,
+                || "".into(),
+            )
+            .unwrap();
+        let signing_key = public_key.signing_key(now).unwrap();
+        assert_eq!(signing_key.fingerprint(), public_key.fingerprint());
+
+        // a subkey without a primary key binding signature is not usable
+        let mut public_key = key.signed_public_key();
+        for sig in &mut public_key.public_subkeys[1].signatures {
+            sig.config.hashed_subpackets.retain(|p| match p {
+                packet::Subpacket::EmbeddedSignature(_) => false,
+                _ => true,
+            });
+        }
+        match public_key.signing_key(now) {
+            Some(SigningKey::Primary(_)) => {}
+            res => panic!("unexpected signing key {:?}", res),
+        }
+        // unless the policy allows it
+        let lax = Policy {
+            require_back_signatures: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            public_key.signing_key_with_policy(now, &lax),
+            Some(SigningKey::Subkey(&public_key.public_subkeys[1]))
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use std::cmp;
+
+use chrono::{DateTime, Duration, Utc};
+use rand::{thread_rng, CryptoRng, Rng};
+
+use crate::composed::signed_key::SignedSecretKey;
+use crate::errors::{Error, Result};
+use crate::types::{KeyTrait, S2kParams, SecretKeyRepr, SecretKeyTrait, SecretParams};
+
+/// Settings for an [`UnlockLimiter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnlockPolicy {
+    /// Number of failed attempts, after which no more attempts are allowed,
+    /// until the limiter is reset.
+    pub max_attempts: u32,
+    /// Delay after the first failed attempt, doubled with each further failure.
+    pub delay: Duration,
+    /// Upper bound of the delay between two attempts.
+    pub max_delay: Duration,
+    /// If set, keys protected with a weaker string-to-key are protected again
+    /// with these settings, after they were unlocked successfully.
+    pub upgrade: Option<S2kParams>,
+}
+
+impl Default for UnlockPolicy {
+    fn default() -> Self {
+        UnlockPolicy {
+            max_attempts: 10,
+            delay: Duration::seconds(1),
+            max_delay: Duration::hours(1),
+            upgrade: None,
+        }
+    }
+}
+
+/// Counts failed passphrase attempts for a [`SignedSecretKey`], and enforces an
+/// exponential backoff between them.
+///
+/// Applications that store keys on behalf of their users should keep one limiter per key,
+/// and persist its state, see [`UnlockLimiter::with_state`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnlockLimiter {
+    policy: UnlockPolicy,
+    failures: u32,
+    last_failure: Option<DateTime<Utc>>,
+}
+
+impl UnlockLimiter {
+    pub fn new(policy: UnlockPolicy) -> Self {
+        Self::with_state(policy, 0, None)
+    }
+
+    /// Restores a limiter from the values of [`failures`] and [`last_failure`].
+    ///
+    /// [`failures`]: UnlockLimiter::failures
+    /// [`last_failure`]: UnlockLimiter::last_failure
+    pub fn with_state(
+        policy: UnlockPolicy,
+        failures: u32,
+        last_failure: Option<DateTime<Utc>>,
+    ) -> Self {
+        UnlockLimiter {
+            policy,
+            failures,
+            last_failure,
+        }
+    }
+
+    pub fn policy(&self) -> &UnlockPolicy {
+        &self.policy
+    }
+
+    /// Number of failed attempts since the last successful unlock.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn last_failure(&self) -> Option<DateTime<Utc>> {
+        self.last_failure
+    }
+
+    /// Forgets all failed attempts.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+        self.last_failure = None;
+    }
+
+    /// Returns `true` if the maximum number of attempts is reached.
+    pub fn is_locked_out(&self) -> bool {
+        self.failures >= self.policy.max_attempts
+    }
+
+    /// Returns the time before which further attempts are rejected, if any.
+    pub fn retry_at(&self) -> Option<DateTime<Utc>> {
+        if self.failures == 0 {
+            return None;
+        }
+
+        let mut delay = cmp::min(self.policy.delay, self.policy.max_delay);
+        for _ in 1..self.failures {
+            if delay <= Duration::zero() || delay >= self.policy.max_delay {
+                break;
+            }
+            delay = cmp::min(delay + delay, self.policy.max_delay);
+        }
+
+        self.last_failure.map(|at| at + delay)
+    }
+
+    /// Same as [`unlock_with_rng`], but uses [`thread_rng`] for RNG.
+    ///
+    /// [`unlock_with_rng`]: UnlockLimiter::unlock_with_rng
+    /// [`thread_rng`]: rand::thread_rng
+    pub fn unlock<F, G>(&mut self, key: &mut SignedSecretKey, key_pw: F, work: G) -> Result<bool>
+    where
+        F: (FnOnce() -> String) + Clone,
+        G: FnOnce(&SecretKeyRepr) -> Result<()>,
+    {
+        let mut rng = thread_rng();
+        self.unlock_with_rng(&mut rng, key, key_pw, work)
+    }
+
+    /// Unlocks the primary key of `key` and passes it to `work`.
+    ///
+    /// Fails with [`Error::TooManyAttempts`] while the delay after the last failed attempt
+    /// has not passed, or if no more attempts are allowed. Errors of `work` are not counted
+    /// as failed attempts.
+    ///
+    /// After a successful unlock, the key is protected again if the policy asks for a
+    /// stronger string-to-key. Returns `true` if that happened, so the caller can store
+    /// the updated key.
+    pub fn unlock_with_rng<R, F, G>(
+        &mut self,
+        rng: &mut R,
+        key: &mut SignedSecretKey,
+        key_pw: F,
+        work: G,
+    ) -> Result<bool>
+    where
+        R: CryptoRng + Rng,
+        F: (FnOnce() -> String) + Clone,
+        G: FnOnce(&SecretKeyRepr) -> Result<()>,
+    {
+        let now = Utc::now();
+        if self.is_locked_out() {
+            return Err(Error::TooManyAttempts {
+                failures: self.failures,
+                retry_at: None,
+            });
+        }
+        if let Some(retry_at) = self.retry_at().filter(|retry_at| retry_at > &now) {
+            return Err(Error::TooManyAttempts {
+                failures: self.failures,
+                retry_at: Some(retry_at),
+            });
+        }
+
+        // nothing to guess, if there is no secret material
+        if key.primary_key.secret_params().is_stub() {
+            return Err(Error::SecretKeyNotPresent {
+                key_id: key.primary_key.key_id(),
+            });
+        }
+
+        let mut unlocked = false;
+        let res = key.primary_key.unlock(key_pw.clone(), |repr| {
+            unlocked = true;
+            work(repr)
+        });
+        if !unlocked {
+            self.failures = self.failures.saturating_add(1);
+            self.last_failure = Some(now);
+            return res.map(|_| false);
+        }
+
+        self.reset();
+        res?;
+
+        let upgrade = match (self.policy.upgrade, key.primary_key.secret_params()) {
+            (Some(params), SecretParams::Encrypted(k))
+                if params.is_stronger_than(k.string_to_key()) =>
+            {
+                params
+            }
+            _ => return Ok(false),
+        };
+        *key = key.reprotect_with_rng(rng, key_pw, &upgrade)?;
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::{KeyType, SecretKeyParamsBuilder};
+    use crate::crypto::{HashAlgorithm, SymmetricKeyAlgorithm};
+
+    fn s2k_count(key: &SignedSecretKey) -> Option<usize> {
+        match key.primary_key.secret_params() {
+            SecretParams::Encrypted(k) => k.string_to_key().count(),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_unlock_limiter() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let weak = S2kParams {
+            sym_alg: SymmetricKeyAlgorithm::AES128,
+            hash_alg: HashAlgorithm::SHA2_256,
+            count: 96,
+        };
+        let mut key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap()
+            .change_passphrase_with_rng(&mut rng, || "".into(), Some("pw"), &weak)
+            .unwrap();
+        assert_eq!(s2k_count(&key), Some(65536));
+
+        let policy = UnlockPolicy {
+            max_attempts: 3,
+            delay: Duration::hours(1),
+            max_delay: Duration::hours(4),
+            upgrade: Some(S2kParams::default()),
+        };
+        let mut limiter = UnlockLimiter::new(policy);
+        assert!(limiter
+            .unlock_with_rng(&mut rng, &mut key, || "wrong".into(), |_| Ok(()))
+            .is_err());
+        assert_eq!(limiter.failures(), 1);
+
+        // the right passphrase is rejected as well, until the delay has passed
+        match limiter.unlock_with_rng(&mut rng, &mut key, || "pw".into(), |_| Ok(())) {
+            Err(Error::TooManyAttempts { failures, retry_at }) => {
+                assert_eq!(failures, 1);
+                assert_eq!(retry_at, limiter.retry_at());
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // the delay doubles, up to the maximum
+        let last_failure = Utc::now() - Duration::hours(3);
+        let limiter = UnlockLimiter::with_state(policy, 2, Some(last_failure));
+        assert_eq!(limiter.retry_at(), Some(last_failure + Duration::hours(2)));
+        let limiter = UnlockLimiter::with_state(policy, 8, Some(last_failure));
+        assert_eq!(limiter.retry_at(), Some(last_failure + Duration::hours(4)));
+        assert!(limiter.is_locked_out());
+
+        // errors of the work closure are not counted as failed attempts
+        let mut limiter = UnlockLimiter::with_state(policy, 2, Some(last_failure));
+        assert!(limiter
+            .unlock_with_rng(&mut rng, &mut key, || "pw".into(), |_| bail!("failed"))
+            .is_err());
+        assert_eq!(limiter.failures(), 0);
+        assert_eq!(s2k_count(&key), Some(65536));
+
+        let upgraded = limiter
+            .unlock_with_rng(&mut rng, &mut key, || "pw".into(), |_| Ok(()))
+            .unwrap();
+        assert!(upgraded);
+        assert_eq!(s2k_count(&key), Some(65_011_712));
+        key.verify().unwrap();
+
+        let upgraded = limiter
+            .unlock_with_rng(&mut rng, &mut key, || "pw".into(), |_| Ok(()))
+            .unwrap();
+        assert!(!upgraded);
+
+        let mut limiter = UnlockLimiter::with_state(policy, 3, None);
+        match limiter.unlock_with_rng(&mut rng, &mut key, || "pw".into(), |_| Ok(())) {
+            Err(Error::TooManyAttempts { retry_at: None, .. }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
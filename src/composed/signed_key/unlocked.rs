@@ -0,0 +1,185 @@
+use crate::composed::signed_key::SignedSecretKey;
+use crate::errors::Result;
+use crate::types::{KeyId, KeyTrait, SecretParams};
+
+/// How the secret material of a primary key or subkey is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretStatus {
+    /// The secret material is not encrypted, or was unlocked.
+    Unprotected,
+    /// The secret material is encrypted with a passphrase.
+    Encrypted,
+    /// The secret material is not part of the key, e.g. for GnuPG stubs.
+    NotPresent,
+}
+
+impl From<&SecretParams> for SecretStatus {
+    fn from(params: &SecretParams) -> Self {
+        match params {
+            SecretParams::Plain(_) => SecretStatus::Unprotected,
+            SecretParams::Encrypted(_) => SecretStatus::Encrypted,
+            SecretParams::Stub(_) => SecretStatus::NotPresent,
+        }
+    }
+}
+
+/// The [`SecretStatus`] of one component of a key, see [`SignedSecretKey::secret_status`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentStatus {
+    pub key_id: KeyId,
+    pub is_primary: bool,
+    pub status: SecretStatus,
+}
+
+impl SignedSecretKey {
+    /// Reports how the secret material of the primary key and of each subkey is stored,
+    /// in the order of [`secret_subkeys`](SignedSecretKey::secret_subkeys).
+    pub fn secret_status(&self) -> Vec<ComponentStatus> {
+        std::iter::once(ComponentStatus {
+            key_id: self.primary_key.key_id(),
+            is_primary: true,
+            status: self.primary_key.secret_params().into(),
+        })
+        .chain(self.secret_subkeys.iter().map(|subkey| ComponentStatus {
+            key_id: subkey.key_id(),
+            is_primary: false,
+            status: subkey.key.secret_params().into(),
+        }))
+        .collect()
+    }
+}
+
+/// A copy of a [`SignedSecretKey`], whose components can be unlocked one by one,
+/// with different passphrases.
+///
+/// Unlocked components stay decrypted in memory until the handle is dropped, so operations
+/// on [`key`](UnlockedKey::key) do not call the passphrase closure for them. The decrypted
+/// material is zeroed on drop.
+#[derive(Debug, Clone)]
+pub struct UnlockedKey {
+    key: SignedSecretKey,
+}
+
+impl UnlockedKey {
+    pub fn new(key: &SignedSecretKey) -> Self {
+        UnlockedKey { key: key.clone() }
+    }
+
+    /// Returns the key with all unlocked components decrypted.
+    ///
+    /// It must not be stored or exported, as the unlocked components are not protected anymore.
+    pub fn key(&self) -> &SignedSecretKey {
+        &self.key
+    }
+
+    /// See [`SignedSecretKey::secret_status`].
+    pub fn secret_status(&self) -> Vec<ComponentStatus> {
+        self.key.secret_status()
+    }
+
+    /// Returns `true` if the component with the given key id can be used without a passphrase.
+    pub fn is_unlocked(&self, key_id: &KeyId) -> bool {
+        self.secret_status()
+            .iter()
+            .any(|c| &c.key_id == key_id && c.status == SecretStatus::Unprotected)
+    }
+
+    /// Unlocks the primary key using `pw`.
+    pub fn unlock_primary<F>(&mut self, pw: F) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        self.key.primary_key.decrypt_secret(pw)
+    }
+
+    /// Unlocks the subkey with the given key id using `pw`.
+    pub fn unlock_subkey<F>(&mut self, key_id: &KeyId, pw: F) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        match self
+            .key
+            .secret_subkeys
+            .iter_mut()
+            .find(|subkey| &subkey.key_id() == key_id)
+        {
+            Some(subkey) => subkey.key.decrypt_secret(pw),
+            None => bail!("unknown subkey {:?}", key_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::test_keys::gen_key_with_subkeys;
+    use crate::composed::Message;
+    use crate::crypto::{HashAlgorithm, SymmetricKeyAlgorithm};
+    use crate::types::{S2kParams, SecretKeyTrait};
+
+    #[test]
+    fn test_unlock_components() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let s2k = S2kParams {
+            sym_alg: SymmetricKeyAlgorithm::AES128,
+            hash_alg: HashAlgorithm::SHA2_256,
+            count: 96,
+        };
+        let mut key = gen_key_with_subkeys(&mut rng, "Me <me@mail.com>", 2);
+        key.primary_key
+            .change_passphrase(&mut rng, || "".into(), Some("primary"), &s2k)
+            .unwrap();
+        key.secret_subkeys[0]
+            .key
+            .change_passphrase(&mut rng, || "".into(), Some("subkey"), &s2k)
+            .unwrap();
+        key.secret_subkeys[1].key.remove_secret();
+
+        let ids: Vec<_> = key.secret_status().into_iter().map(|c| c.key_id).collect();
+        let statuses: Vec<_> = key.secret_status().into_iter().map(|c| c.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                SecretStatus::Encrypted,
+                SecretStatus::Encrypted,
+                SecretStatus::NotPresent
+            ]
+        );
+        assert!(key.secret_status()[0].is_primary);
+
+        let mut unlocked = UnlockedKey::new(&key);
+        assert!(unlocked.unlock_primary(|| "subkey".into()).is_err());
+        assert!(!unlocked.is_unlocked(&ids[0]));
+        unlocked.unlock_primary(|| "primary".into()).unwrap();
+        unlocked.unlock_subkey(&ids[1], || "subkey".into()).unwrap();
+        assert!(unlocked.unlock_subkey(&ids[2], || "".into()).is_err());
+        assert!(unlocked.is_unlocked(&ids[0]));
+        assert!(unlocked.is_unlocked(&ids[1]));
+        assert!(!unlocked.is_unlocked(&ids[2]));
+        // the original key is not changed
+        assert_eq!(key.secret_status()[0].status, SecretStatus::Encrypted);
+
+        // unlocked components are used without asking for a passphrase
+        let no_pw = || -> String { panic!("the passphrase should not be needed") };
+        let msg = Message::new_literal("hello.txt", "hello world");
+        let signed = msg
+            .clone()
+            .sign(unlocked.key(), no_pw, HashAlgorithm::SHA2_256)
+            .unwrap();
+        signed.verify(&key.public_key()).unwrap();
+
+        let encrypted = msg
+            .encrypt_to_keys(
+                &mut rng,
+                SymmetricKeyAlgorithm::AES128,
+                &[&key.secret_subkeys[0].public_key()],
+            )
+            .unwrap();
+        let (mut decrypter, _) = encrypted.decrypt(no_pw, no_pw, &[unlocked.key()]).unwrap();
+        assert_eq!(decrypter.next().unwrap().unwrap(), msg);
+    }
+}
@@ -0,0 +1,102 @@
+//! Keys shared by the tests of the composed types.
+
+use rand::{CryptoRng, Rng};
+
+use crate::composed::{KeyType, SecretKeyParamsBuilder, SignedSecretKey, SubkeyParamsBuilder};
+use crate::types::KeyVersion;
+
+/// Generates a key for `id`, with an EdDSA primary key that can sign and certify,
+/// and `subkeys` ECDH encryption subkeys.
+pub fn gen_key_with_subkeys<R: CryptoRng + Rng>(
+    rng: &mut R,
+    id: &str,
+    subkeys: usize,
+) -> SignedSecretKey {
+    SecretKeyParamsBuilder::default()
+        .key_type(KeyType::EdDSA)
+        .can_create_certificates(true)
+        .can_sign(true)
+        .primary_user_id(id.into())
+        .subkeys(
+            (0..subkeys)
+                .map(|_| {
+                    SubkeyParamsBuilder::default()
+                        .key_type(KeyType::ECDH)
+                        .can_encrypt(true)
+                        .build()
+                        .unwrap()
+                })
+                .collect(),
+        )
+        .build()
+        .unwrap()
+        .generate_with_rng(rng)
+        .unwrap()
+        .sign_with_rng(rng, || "".into())
+        .unwrap()
+}
+
+/// Same as [`gen_key_with_subkeys`], with a single encryption subkey.
+pub fn gen_key<R: CryptoRng + Rng>(rng: &mut R, id: &str) -> SignedSecretKey {
+    gen_key_with_subkeys(rng, id, 1)
+}
+
+/// Generates a key for `id`, with an EdDSA primary key that can sign and certify, an
+/// EdDSA signing subkey and an ECDH encryption subkey, in this order.
+pub fn gen_key_with_signing_subkey<R: CryptoRng + Rng>(rng: &mut R, id: &str) -> SignedSecretKey {
+    SecretKeyParamsBuilder::default()
+        .key_type(KeyType::EdDSA)
+        .can_create_certificates(true)
+        .can_sign(true)
+        .primary_user_id(id.into())
+        .subkey(
+            SubkeyParamsBuilder::default()
+                .key_type(KeyType::EdDSA)
+                .can_sign(true)
+                .build()
+                .unwrap(),
+        )
+        .subkey(
+            SubkeyParamsBuilder::default()
+                .key_type(KeyType::ECDH)
+                .can_encrypt(true)
+                .build()
+                .unwrap(),
+        )
+        .build()
+        .unwrap()
+        .generate_with_rng(rng)
+        .unwrap()
+        .sign_with_rng(rng, || "".into())
+        .unwrap()
+}
+
+/// Creates a v6 key with an Ed25519 primary key that can sign and certify, and an X25519
+/// encryption subkey. Without `id`, the key is bound by a direct key signature only.
+pub fn gen_key_v6<R: CryptoRng + Rng>(rng: &mut R, id: Option<&str>) -> SignedSecretKey {
+    let mut params = SecretKeyParamsBuilder::default();
+    params
+        .key_type(KeyType::Ed25519)
+        .version(KeyVersion::V6)
+        .can_create_certificates(true)
+        .can_sign(true)
+        .subkey(
+            SubkeyParamsBuilder::default()
+                .key_type(KeyType::X25519)
+                .version(KeyVersion::V6)
+                .can_encrypt(true)
+                .build()
+                .unwrap(),
+        );
+    if let Some(id) = id {
+        params.primary_user_id(id.into());
+    }
+
+    params
+        .build()
+        .unwrap()
+        .generate_with_rng(rng)
+        .unwrap()
+        .sign_with_rng(rng, || "".into())
+        .unwrap()
+}
@@ -1,3 +1,14 @@
+use aead::{AeadInPlace, NewAead};
+use aes::{Aes128, Aes192, Aes256, BlockCipher, NewBlockCipher};
+use aes_gcm::AesGcm;
+use eax::Eax;
+use generic_array::typenum::{Unsigned, U12, U15, U16};
+use generic_array::{ArrayLength, GenericArray};
+use ocb3::Ocb3;
+
+use crate::crypto::sym::SymmetricKeyAlgorithm;
+use crate::errors::{Error, Result};
+
 /// Available AEAD algorithms.
 #[derive(Debug, PartialEq, Eq, Copy, Clone, FromPrimitive)]
 #[repr(u8)]
@@ -6,6 +17,7 @@ pub enum AeadAlgorithm {
     None = 0,
     Eax = 1,
     Ocb = 2,
+    Gcm = 3,
 }
 
 impl Default for AeadAlgorithm {
@@ -13,3 +25,297 @@ impl Default for AeadAlgorithm {
         AeadAlgorithm::None
     }
 }
+
+impl AeadAlgorithm {
+    /// Nonce size used for this AEAD algorithm.
+    pub fn nonce_size(self) -> usize {
+        match self {
+            AeadAlgorithm::None => 0,
+            AeadAlgorithm::Eax => 16,
+            AeadAlgorithm::Ocb => 15,
+            AeadAlgorithm::Gcm => 12,
+        }
+    }
+
+    /// Size of the authentication tag.
+    pub fn tag_size(self) -> usize {
+        match self {
+            AeadAlgorithm::None => 0,
+            AeadAlgorithm::Eax | AeadAlgorithm::Ocb | AeadAlgorithm::Gcm => 16,
+        }
+    }
+
+    /// Encrypts `buffer` in place, and appends the authentication tag.
+    ///
+    /// Only AES is supported as the cipher.
+    pub fn encrypt_in_place(
+        self,
+        sym_alg: SymmetricKeyAlgorithm,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut Vec<u8>,
+    ) -> Result<()> {
+        ensure_eq!(nonce.len(), self.nonce_size(), "invalid nonce size");
+
+        if self == AeadAlgorithm::Ocb {
+            let tag = ocb::seal(sym_alg, key, nonce, aad, buffer)?;
+            buffer.extend_from_slice(&tag);
+            return Ok(());
+        }
+
+        match sym_alg {
+            SymmetricKeyAlgorithm::AES128 => self.encrypt_with::<Aes128>(key, nonce, aad, buffer),
+            SymmetricKeyAlgorithm::AES192 => self.encrypt_with::<Aes192>(key, nonce, aad, buffer),
+            SymmetricKeyAlgorithm::AES256 => self.encrypt_with::<Aes256>(key, nonce, aad, buffer),
+            _ => unsupported_err!("{:?} with {:?}", self, sym_alg),
+        }
+    }
+
+    /// Checks and removes the authentication tag at the end of `buffer`, and decrypts
+    /// it in place.
+    ///
+    /// Fails with [`Error::AeadError`] if the data was modified, the contents of
+    /// `buffer` must not be used in that case.
+    pub fn decrypt_in_place(
+        self,
+        sym_alg: SymmetricKeyAlgorithm,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut Vec<u8>,
+    ) -> Result<()> {
+        ensure_eq!(nonce.len(), self.nonce_size(), "invalid nonce size");
+        ensure!(
+            buffer.len() >= self.tag_size(),
+            "missing authentication tag"
+        );
+
+        let tag = buffer.split_off(buffer.len() - self.tag_size());
+        if self == AeadAlgorithm::Ocb {
+            return ocb::open(sym_alg, key, nonce, aad, buffer, &tag);
+        }
+
+        match sym_alg {
+            SymmetricKeyAlgorithm::AES128 => {
+                self.decrypt_with::<Aes128>(key, nonce, aad, buffer, &tag)
+            }
+            SymmetricKeyAlgorithm::AES192 => {
+                self.decrypt_with::<Aes192>(key, nonce, aad, buffer, &tag)
+            }
+            SymmetricKeyAlgorithm::AES256 => {
+                self.decrypt_with::<Aes256>(key, nonce, aad, buffer, &tag)
+            }
+            _ => unsupported_err!("{:?} with {:?}", self, sym_alg),
+        }
+    }
+
+    fn encrypt_with<C>(
+        self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut Vec<u8>,
+    ) -> Result<()>
+    where
+        C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+        C::ParBlocks: ArrayLength<GenericArray<u8, U16>>,
+    {
+        let tag = match self {
+            AeadAlgorithm::Eax => seal::<Eax<C>>(key, nonce, aad, buffer)?,
+            AeadAlgorithm::Gcm => seal::<AesGcm<C, U12>>(key, nonce, aad, buffer)?,
+            AeadAlgorithm::Ocb | AeadAlgorithm::None => bail!("invalid AEAD algorithm"),
+        };
+        buffer.extend_from_slice(&tag);
+
+        Ok(())
+    }
+
+    fn decrypt_with<C>(
+        self,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8],
+    ) -> Result<()>
+    where
+        C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+        C::ParBlocks: ArrayLength<GenericArray<u8, U16>>,
+    {
+        match self {
+            AeadAlgorithm::Eax => open::<Eax<C>>(key, nonce, aad, buffer, tag),
+            AeadAlgorithm::Gcm => open::<AesGcm<C, U12>>(key, nonce, aad, buffer, tag),
+            AeadAlgorithm::Ocb | AeadAlgorithm::None => bail!("invalid AEAD algorithm"),
+        }
+    }
+}
+
+fn new_aead<A: NewAead>(key: &[u8]) -> Result<A> {
+    if key.len() != A::KeySize::to_usize() {
+        return Err(Error::InvalidKeyLength);
+    }
+
+    Ok(A::new(GenericArray::from_slice(key)))
+}
+
+fn seal<A: NewAead + AeadInPlace>(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    buffer: &mut [u8],
+) -> Result<Vec<u8>> {
+    let tag = new_aead::<A>(key)?
+        .encrypt_in_place_detached(GenericArray::from_slice(nonce), aad, buffer)
+        .map_err(|_| Error::AeadError)?;
+
+    Ok(tag.to_vec())
+}
+
+fn open<A: NewAead + AeadInPlace>(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    buffer: &mut [u8],
+    tag: &[u8],
+) -> Result<()> {
+    new_aead::<A>(key)?
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(nonce),
+            aad,
+            buffer,
+            GenericArray::from_slice(tag),
+        )
+        .map_err(|_| Error::AeadError)
+}
+
+/// OCB, as defined in RFC 7253, provided by the `ocb3` crate.
+///
+/// The crate is built on newer versions of the `aead` and block cipher traits than the
+/// other modes, so it uses its own AES types.
+mod ocb {
+    use aes_ocb::{Aes128, Aes192, Aes256};
+    use ocb3::aead::{AeadInPlace, KeyInit};
+
+    use super::*;
+
+    /// OCB with the 15 byte nonces used by OpenPGP, and 16 byte tags.
+    type Ocb<C> = Ocb3<C, U15>;
+
+    pub fn seal(
+        sym_alg: SymmetricKeyAlgorithm,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<Vec<u8>> {
+        match sym_alg {
+            SymmetricKeyAlgorithm::AES128 => seal_with::<Ocb<Aes128>>(key, nonce, aad, buffer),
+            SymmetricKeyAlgorithm::AES192 => seal_with::<Ocb<Aes192>>(key, nonce, aad, buffer),
+            SymmetricKeyAlgorithm::AES256 => seal_with::<Ocb<Aes256>>(key, nonce, aad, buffer),
+            _ => unsupported_err!("{:?} with {:?}", AeadAlgorithm::Ocb, sym_alg),
+        }
+    }
+
+    pub fn open(
+        sym_alg: SymmetricKeyAlgorithm,
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8],
+    ) -> Result<()> {
+        match sym_alg {
+            SymmetricKeyAlgorithm::AES128 => open_with::<Ocb<Aes128>>(key, nonce, aad, buffer, tag),
+            SymmetricKeyAlgorithm::AES192 => open_with::<Ocb<Aes192>>(key, nonce, aad, buffer, tag),
+            SymmetricKeyAlgorithm::AES256 => open_with::<Ocb<Aes256>>(key, nonce, aad, buffer, tag),
+            _ => unsupported_err!("{:?} with {:?}", AeadAlgorithm::Ocb, sym_alg),
+        }
+    }
+
+    fn seal_with<A: KeyInit + AeadInPlace>(
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<Vec<u8>> {
+        let tag = A::new_from_slice(key)
+            .map_err(|_| Error::InvalidKeyLength)?
+            .encrypt_in_place_detached(GenericArray::from_slice(nonce), aad, buffer)
+            .map_err(|_| Error::AeadError)?;
+
+        Ok(tag.to_vec())
+    }
+
+    fn open_with<A: KeyInit + AeadInPlace>(
+        key: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8],
+    ) -> Result<()> {
+        A::new_from_slice(key)
+            .map_err(|_| Error::InvalidKeyLength)?
+            .decrypt_in_place_detached(
+                GenericArray::from_slice(nonce),
+                aad,
+                buffer,
+                GenericArray::from_slice(tag),
+            )
+            .map_err(|_| Error::AeadError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aead_in_place() {
+        let key = (0u8..16).collect::<Vec<_>>();
+        let aad = hex::decode("d202070210").unwrap();
+        let plaintext = (0u8..35).collect::<Vec<_>>();
+
+        for &(aead, expected) in &[
+            (
+                AeadAlgorithm::Eax,
+                "8e12661f9d0c32ffc895907fe9d6b39674f8cba91800daf1b0b59d326de0567be5cc71e43ae54061dcd28e343721323217b423",
+            ),
+            (
+                AeadAlgorithm::Ocb,
+                "4753d362edc4064dce62508f8db307eb08cb7d593f926c9820ac7885bc85c75d6ff620295ed12a5a43f2f5d677c5e9069ad7a6",
+            ),
+            (
+                AeadAlgorithm::Gcm,
+                "c42f01ac0b4ab0e81fd457fecb2ae5312aad669422e17da89dd2330a7b180fb2f2f803d1e941c0b25a1c5ae1dc89bca3fbfaf7",
+            ),
+        ] {
+            let nonce = (0x10..0x10 + aead.nonce_size() as u8).collect::<Vec<_>>();
+            let alg = SymmetricKeyAlgorithm::AES128;
+
+            let mut buffer = plaintext.clone();
+            aead.encrypt_in_place(alg, &key, &nonce, &aad, &mut buffer)
+                .unwrap();
+            assert_eq!(hex::encode(&buffer), expected, "{:?}", aead);
+
+            aead.decrypt_in_place(alg, &key, &nonce, &aad, &mut buffer)
+                .unwrap();
+            assert_eq!(buffer, plaintext);
+
+            let mut buffer = hex::decode(expected).unwrap();
+            buffer[3] ^= 1;
+            match aead.decrypt_in_place(alg, &key, &nonce, &aad, &mut buffer) {
+                Err(Error::AeadError) => {}
+                res => panic!("unexpected result {:?}", res),
+            }
+
+            let mut buffer = plaintext.clone();
+            assert!(aead
+                .encrypt_in_place(SymmetricKeyAlgorithm::Twofish, &key, &nonce, &aad, &mut buffer)
+                .is_err());
+            assert!(aead
+                .encrypt_in_place(alg, &key[..8], &nonce, &aad, &mut buffer)
+                .is_err());
+        }
+    }
+}
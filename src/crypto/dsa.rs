@@ -0,0 +1,123 @@
+use num_bigint::traits::ModInverse;
+use num_bigint::BigUint;
+use num_traits::Zero;
+
+use crate::crypto::{HashAlgorithm, MAX_KEY_BITS};
+use crate::errors::{Error, Result};
+use crate::types::Mpi;
+
+/// Verify a DSA signature.
+/// Ref: https://tools.ietf.org/html/rfc4880.html#section-5.2.2
+pub fn verify(
+    p: &[u8],
+    q: &[u8],
+    g: &[u8],
+    y: &[u8],
+    _hash: HashAlgorithm,
+    hashed: &[u8],
+    sig: &[Mpi],
+) -> Result<()> {
+    ensure_eq!(sig.len(), 2, "invalid signature");
+
+    let p = BigUint::from_bytes_be(p);
+    if p.bits() > MAX_KEY_BITS {
+        return Err(Error::KeyTooLarge {
+            bits: p.bits(),
+            max: MAX_KEY_BITS,
+        });
+    }
+    let q = BigUint::from_bytes_be(q);
+    let g = BigUint::from_bytes_be(g);
+    let y = BigUint::from_bytes_be(y);
+    ensure!(!q.is_zero() && !p.is_zero(), "invalid DSA key");
+
+    let r = BigUint::from_bytes_be(sig[0].as_bytes());
+    let s = BigUint::from_bytes_be(sig[1].as_bytes());
+    ensure!(!r.is_zero() && r < q, "invalid R");
+    ensure!(!s.is_zero() && s < q, "invalid S");
+
+    // If the hash is larger than q, only its leftmost bits are used.
+    let q_bits = q.bits();
+    let mut z = BigUint::from_bytes_be(hashed);
+    if hashed.len() * 8 > q_bits {
+        z = z >> (hashed.len() * 8 - q_bits);
+    }
+
+    let w = match s.mod_inverse(&q).and_then(|w| w.to_biguint()) {
+        Some(w) => w,
+        None => bail!("invalid S"),
+    };
+    let u1 = (z * &w) % &q;
+    let u2 = (&r * &w) % &q;
+    let v = ((g.modpow(&u1, &p) * y.modpow(&u2, &p)) % &p) % &q;
+
+    ensure!(v == r, "invalid signature");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let p = hex::decode(
+            "ef60c88e760bc170f0bdea5a5975a8d45af072ee672c1a53f17f24377d8b7829\
+             c114848620ba6a163473c9c3dbcc35e13aec6ef1875957feac5973ed87cdf6ef\
+             67d830f7cfa3270af1b0db24e7744ff86edb9490b55e348137f5cb43b6b65ef5\
+             562e608d70642f9c4ccf74ecab071e4f1a1074614a8a1bb563b79b1955a5a37b",
+        )
+        .unwrap();
+        let q = hex::decode("f3821cfdc083b73a473bd358610e6a64e1301617").unwrap();
+        let g = hex::decode(
+            "bf40416a91a33a7c06215c4805da1173e89e9e3b9a3ca7b86f05ecbf5cc2398c\
+             2d671c7a2eeb4675cec065aba5869a12feb75d1ca552165c38a12a44edb9ed5b\
+             7d46396eb637843aaf83ae3543e81509ffe29af99c2915d9ba7820ed65357d44\
+             2b772ca903eecdc464ee967120ae6bc6bfaaf46a87972d383a608c294470d847",
+        )
+        .unwrap();
+        let y = hex::decode(
+            "8a7cfcd395cb3544278389afcc7bad86a27036d5b34f23965ae79e56fb14742c\
+             7d2fcf8164c10de6a97132e5d8b7648f1d52ef97d40bca3370a80980d39296c6\
+             eb915ce7c5b7a3ca914d25556511118e5763fc468ddf004fc15caeaf0fe1babd\
+             57e6ab3282efb6580ee691fa03edf9b0ded49d001e946f299e3107b8c4f9bcf1",
+        )
+        .unwrap();
+        // sha256("hello world")
+        let mut digest =
+            hex::decode("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9")
+                .unwrap();
+        let sig = vec![
+            Mpi::from_raw_slice(&hex::decode("8e76171b642b4860bb29b997c0551792d7c69230").unwrap()),
+            Mpi::from_raw_slice(&hex::decode("264f188776883d120521159f3143bf32c92c4b6d").unwrap()),
+        ];
+
+        verify(&p, &q, &g, &y, HashAlgorithm::SHA2_256, &digest, &sig).unwrap();
+
+        digest[0] ^= 1;
+        assert!(verify(&p, &q, &g, &y, HashAlgorithm::SHA2_256, &digest, &sig).is_err());
+    }
+
+    #[test]
+    fn test_verify_key_too_large() {
+        let p = vec![0xff; MAX_KEY_BITS / 8 + 1];
+        let sig = vec![Mpi::from_raw_slice(&[1]), Mpi::from_raw_slice(&[1])];
+
+        match verify(
+            &p,
+            &[3],
+            &[2],
+            &[2],
+            HashAlgorithm::SHA2_256,
+            &[0u8; 32],
+            &sig,
+        ) {
+            Err(Error::KeyTooLarge { bits, max }) => {
+                assert_eq!(bits, MAX_KEY_BITS + 8);
+                assert_eq!(max, MAX_KEY_BITS);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+}
@@ -4,6 +4,8 @@ use crate::crypto::public_key::PublicKeyAlgorithm;
 pub enum ECCCurve {
     Curve25519,
     Ed25519,
+    Curve448,
+    Ed448,
     P256,
     P384,
     P521,
@@ -19,6 +21,8 @@ impl ECCCurve {
         match *self {
             ECCCurve::Curve25519 => "Curve25519",
             ECCCurve::Ed25519 => "Ed25519",
+            ECCCurve::Curve448 => "Curve448",
+            ECCCurve::Ed448 => "Ed448",
             ECCCurve::P256 => "NIST P-256",
             ECCCurve::P384 => "NIST P-384",
             ECCCurve::P521 => "NIST P-521",
@@ -34,6 +38,8 @@ impl ECCCurve {
         match *self {
             ECCCurve::Curve25519 => "1.3.6.1.4.1.3029.1.5.1",
             ECCCurve::Ed25519 => "1.3.6.1.4.1.11591.15.1",
+            ECCCurve::Curve448 => "1.3.101.111",
+            ECCCurve::Ed448 => "1.3.101.113",
             ECCCurve::P256 => "1.2.840.10045.3.1.7",
             ECCCurve::P384 => "1.3.132.0.34",
             ECCCurve::P521 => "1.3.132.0.35",
@@ -49,6 +55,8 @@ impl ECCCurve {
         match *self {
             ECCCurve::Curve25519 => 255,
             ECCCurve::Ed25519 => 255,
+            ECCCurve::Curve448 => 448,
+            ECCCurve::Ed448 => 448,
             ECCCurve::P256 => 256,
             ECCCurve::P384 => 384,
             ECCCurve::P521 => 521,
@@ -64,6 +72,8 @@ impl ECCCurve {
         match *self {
             ECCCurve::Curve25519 => Some("cv25519"),
             ECCCurve::Ed25519 => Some("ed25519"),
+            ECCCurve::Curve448 => Some("cv448"),
+            ECCCurve::Ed448 => Some("ed448"),
             ECCCurve::P256 => Some("nistp256"),
             ECCCurve::P384 => Some("nistp384"),
             ECCCurve::P521 => Some("nistp521"),
@@ -79,6 +89,8 @@ impl ECCCurve {
         match *self {
             ECCCurve::Curve25519 => Some(PublicKeyAlgorithm::ECDH),
             ECCCurve::Ed25519 => Some(PublicKeyAlgorithm::EdDSA),
+            ECCCurve::Curve448 => Some(PublicKeyAlgorithm::ECDH),
+            ECCCurve::Ed448 => Some(PublicKeyAlgorithm::EdDSA),
             ECCCurve::P256 => None,
             ECCCurve::P384 => None,
             ECCCurve::P521 => None,
@@ -116,6 +128,12 @@ pub fn ecc_curve_from_oid(oid: &[u8]) -> Option<ECCCurve> {
     if ECCCurve::Ed25519.oid().as_slice() == oid {
         return Some(ECCCurve::Ed25519);
     }
+    if ECCCurve::Curve448.oid().as_slice() == oid {
+        return Some(ECCCurve::Curve448);
+    }
+    if ECCCurve::Ed448.oid().as_slice() == oid {
+        return Some(ECCCurve::Ed448);
+    }
     if ECCCurve::P256.oid().as_slice() == oid {
         return Some(ECCCurve::P256);
     }
@@ -171,6 +189,7 @@ mod tests {
             vec![0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07]
         );
         assert_eq!(ECCCurve::P384.oid(), vec![0x2B, 0x81, 0x04, 0x00, 0x22]);
+        assert_eq!(ECCCurve::Ed448.oid(), vec![0x2B, 0x65, 0x71]);
     }
 
     #[test]
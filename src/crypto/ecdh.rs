@@ -3,9 +3,12 @@ use rand::{CryptoRng, Rng};
 use x25519_dalek::{PublicKey, StaticSecret};
 use zeroize::Zeroize;
 
-use crate::crypto::{aes_kw, ECCCurve, HashAlgorithm, PublicKeyAlgorithm, SymmetricKeyAlgorithm};
+use crate::crypto::{
+    aes_kw, ecc_curve_from_oid, nist, ECCCurve, HashAlgorithm, PublicKeyAlgorithm,
+    SymmetricKeyAlgorithm,
+};
 use crate::errors::Result;
-use crate::types::{ECDHSecretKey, Mpi, PlainSecretParams, PublicParams};
+use crate::types::{ECDHSecretKey, Mpi, NistECDHSecretKey, PlainSecretParams, PublicParams};
 
 /// 20 octets representing "Anonymous Sender    ".
 const ANON_SENDER: [u8; 20] = [
@@ -81,9 +84,6 @@ pub fn decrypt(priv_key: &ECDHSecretKey, mpis: &[Mpi], fingerprint: &[u8]) -> Re
     ensure_eq!(mpis[0].len(), 33, "invalid public point");
     ensure_eq!(priv_key.secret.len(), 32, "invalid secret point");
 
-    // encrypted and wrapped value derived from the session key
-    let encrypted_session_key = mpis[2].as_bytes();
-
     let their_public = {
         // public part of the ephemeral key (removes 0x40 prefix)
         let ephemeral_public_key = &mpis[0].as_bytes()[1..];
@@ -119,6 +119,42 @@ pub fn decrypt(priv_key: &ECDHSecretKey, mpis: &[Mpi], fingerprint: &[u8]) -> Re
         &param,
     )?;
 
+    unwrap_session_key(&z, mpis)
+}
+
+/// ECDH decryption, with one of the NIST curves.
+pub fn decrypt_nist(
+    priv_key: &NistECDHSecretKey,
+    mpis: &[Mpi],
+    fingerprint: &[u8],
+) -> Result<Vec<u8>> {
+    debug!("ECDH decrypt");
+
+    let curve = ecc_curve_from_oid(&priv_key.oid).ok_or_else(|| format_err!("invalid curve"))?;
+    let param = build_ecdh_param(&priv_key.oid, priv_key.alg_sym, priv_key.hash, fingerprint);
+
+    ensure_eq!(mpis.len(), 3);
+
+    // derive shared secret, from the ephemeral public point
+    let mut shared_secret = nist::diffie_hellman(&curve, &priv_key.secret, mpis[0].as_bytes())?;
+
+    // Perform key derivation
+    let z = kdf(
+        priv_key.hash,
+        &shared_secret,
+        priv_key.alg_sym.key_size(),
+        &param,
+    );
+    shared_secret.zeroize();
+
+    unwrap_session_key(&z?, mpis)
+}
+
+/// Unwraps and unpads the session key, using the derived key `z`.
+fn unwrap_session_key(z: &[u8], mpis: &[Mpi]) -> Result<Vec<u8>> {
+    // encrypted and wrapped value derived from the session key
+    let encrypted_session_key = mpis[2].as_bytes();
+
     // Peform AES Key Unwrap
     let encrypted_key_len: usize = match mpis[1].first() {
         Some(l) => *l as usize,
@@ -130,7 +166,7 @@ pub fn decrypt(priv_key: &ECDHSecretKey, mpis: &[Mpi], fingerprint: &[u8]) -> Re
     encrypted_session_key_vec[(encrypted_key_len - encrypted_session_key.len())..]
         .copy_from_slice(encrypted_session_key);
 
-    let decrypted_key_padded = aes_kw::unwrap(&z, &encrypted_session_key_vec)?;
+    let decrypted_key_padded = aes_kw::unwrap(z, &encrypted_session_key_vec)?;
 
     // PKCS5 unpadding (PKCS5 is PKCS7 with a blocksize of 8)
     let decrypted_key = Pkcs7::unpad(&decrypted_key_padded)?;
@@ -140,7 +176,7 @@ pub fn decrypt(priv_key: &ECDHSecretKey, mpis: &[Mpi], fingerprint: &[u8]) -> Re
 
 /// Key Derivation Function for ECDH (as defined in RFC 6637).
 /// https://tools.ietf.org/html/rfc6637#section-7
-fn kdf(hash: HashAlgorithm, x: &[u8; 32], length: usize, param: &[u8]) -> Result<Vec<u8>> {
+fn kdf(hash: HashAlgorithm, x: &[u8], length: usize, param: &[u8]) -> Result<Vec<u8>> {
     let prefix = vec![0, 0, 0, 1];
 
     let values: Vec<&[u8]> = vec![&prefix, x, param];
@@ -166,26 +202,47 @@ pub fn encrypt<R: CryptoRng + Rng>(
 
     let param = build_ecdh_param(&curve.oid(), alg_sym, hash, fingerprint);
 
-    ensure_eq!(q.len(), 33, "invalid public key");
+    let (encoded_public, z) = if nist::is_nist_curve(curve) {
+        let mut our_secret = nist::generate_secret(rng, curve)?;
+        let shared_secret = nist::diffie_hellman(curve, &our_secret, q);
+        let encoded_public = nist::public_key(curve, &our_secret);
+        our_secret.zeroize();
 
-    let their_public = {
-        // public part of the ephemeral key (removes 0x40 prefix)
-        let public_key = &q[1..];
+        // Perform key derivation
+        let mut shared_secret = shared_secret?;
+        let z = kdf(hash, &shared_secret, alg_sym.key_size(), &param);
+        shared_secret.zeroize();
 
-        // create montgomery point
-        let mut public_key_arr = [0u8; 32];
-        public_key_arr[..].copy_from_slice(public_key);
+        (encoded_public?, z?)
+    } else {
+        ensure_eq!(q.len(), 33, "invalid public key");
 
-        x25519_dalek::PublicKey::from(public_key_arr)
-    };
+        let their_public = {
+            // public part of the ephemeral key (removes 0x40 prefix)
+            let public_key = &q[1..];
 
-    let our_secret = x25519_dalek::StaticSecret::new(rng);
+            // create montgomery point
+            let mut public_key_arr = [0u8; 32];
+            public_key_arr[..].copy_from_slice(public_key);
 
-    // derive shared secret
-    let shared_secret = our_secret.diffie_hellman(&their_public);
+            x25519_dalek::PublicKey::from(public_key_arr)
+        };
 
-    // Perform key derivation
-    let z = kdf(hash, shared_secret.as_bytes(), alg_sym.key_size(), &param)?;
+        let our_secret = x25519_dalek::StaticSecret::new(rng);
+
+        // derive shared secret
+        let shared_secret = our_secret.diffie_hellman(&their_public);
+
+        // Perform key derivation
+        let z = kdf(hash, shared_secret.as_bytes(), alg_sym.key_size(), &param)?;
+
+        // Encode public point: prefix with 0x40
+        let mut encoded_public = Vec::with_capacity(33);
+        encoded_public.push(0x40);
+        encoded_public.extend(x25519_dalek::PublicKey::from(&our_secret).as_bytes().iter());
+
+        (encoded_public, z)
+    };
 
     // PKCS5 padding (PKCS5 is PKCS7 with a blocksize of 8)
     let len = plain.len();
@@ -196,11 +253,6 @@ pub fn encrypt<R: CryptoRng + Rng>(
     // Peform AES Key Wrap
     let encrypted_key = aes_kw::wrap(&z, plain_padded_ref)?;
 
-    // Encode public point: prefix with 0x40
-    let mut encoded_public = Vec::with_capacity(33);
-    encoded_public.push(0x40);
-    encoded_public.extend(x25519_dalek::PublicKey::from(&our_secret).as_bytes().iter());
-
     let encrypted_key_len = vec![encrypted_key.len() as u8];
 
     Ok(vec![encoded_public, encrypted_key_len, encrypted_key])
@@ -253,4 +305,66 @@ mod tests {
 
         assert_eq!(&plain[..], &decrypted[..]);
     }
+
+    #[test]
+    fn test_encrypt_decrypt_nist() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+
+        for (curve, hash, alg_sym) in &[
+            (
+                ECCCurve::P256,
+                HashAlgorithm::SHA2_256,
+                SymmetricKeyAlgorithm::AES128,
+            ),
+            (
+                ECCCurve::P384,
+                HashAlgorithm::SHA2_384,
+                SymmetricKeyAlgorithm::AES192,
+            ),
+            (
+                ECCCurve::P521,
+                HashAlgorithm::SHA2_512,
+                SymmetricKeyAlgorithm::AES256,
+            ),
+        ] {
+            let secret = nist::generate_secret(&mut rng, curve).unwrap();
+            let pkey = PublicParams::ECDH {
+                curve: curve.clone(),
+                p: nist::public_key(curve, &secret).unwrap().into(),
+                hash: *hash,
+                alg_sym: *alg_sym,
+            };
+            let skey = PlainSecretParams::ECDH(Mpi::from_raw_slice(&secret));
+            let mut fingerprint = vec![0u8; 20];
+            rng.fill_bytes(&mut fingerprint);
+
+            let plain = b"hello world";
+            let mpis = match pkey {
+                PublicParams::ECDH { ref p, .. } => encrypt(
+                    &mut rng,
+                    curve,
+                    *alg_sym,
+                    *hash,
+                    &fingerprint,
+                    p.as_bytes(),
+                    &plain[..],
+                )
+                .unwrap(),
+                _ => unreachable!(),
+            };
+            let mpis = mpis.into_iter().map(Into::into).collect::<Vec<Mpi>>();
+
+            match skey.as_ref().as_repr(&pkey).unwrap() {
+                SecretKeyRepr::NistECDH(ref skey) => {
+                    let decrypted = decrypt_nist(skey, &mpis, &fingerprint).unwrap();
+                    assert_eq!(&plain[..], &decrypted[..]);
+
+                    // the fingerprint is part of the key derivation
+                    fingerprint[0] ^= 1;
+                    assert!(decrypt_nist(skey, &mpis, &fingerprint).is_err());
+                }
+                _ => panic!("unexpected secret key"),
+            }
+        }
+    }
 }
@@ -0,0 +1,223 @@
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey, Signature};
+
+use crate::crypto::{nist, ECCCurve, HashAlgorithm};
+use crate::errors::Result;
+use crate::types::{ECDSASecretKey, Mpi, NistECDSASecretKey};
+
+/// Converts the digest into the message that is signed, truncated or padded to 256 bits.
+///
+/// ECDSA uses the leftmost bits of longer digests, shorter ones are used as they are.
+fn digest_to_message(digest: &[u8]) -> Result<Message> {
+    let mut raw = [0u8; 32];
+    if digest.len() >= 32 {
+        raw.copy_from_slice(&digest[..32]);
+    } else {
+        raw[32 - digest.len()..].copy_from_slice(digest);
+    }
+
+    Ok(Message::from_slice(&raw)?)
+}
+
+/// Decodes an uncompressed SEC1 point (`0x04 || x || y`).
+fn decode_point(p: &[u8]) -> Result<PublicKey> {
+    ensure_eq!(p.len(), 65, "invalid Q (len)");
+    ensure_eq!(p[0], 0x04, "invalid Q (prefix)");
+
+    Ok(PublicKey::from_slice(p)?)
+}
+
+/// Verify an ECDSA signature.
+pub fn verify(
+    curve: &ECCCurve,
+    p: &[u8],
+    _hash: HashAlgorithm,
+    hashed: &[u8],
+    sig: &[Mpi],
+) -> Result<()> {
+    match *curve {
+        ECCCurve::Secp256k1 => {
+            ensure_eq!(sig.len(), 2, "invalid signature");
+
+            let r = sig[0].as_bytes();
+            let s = sig[1].as_bytes();
+            ensure!(r.len() < 33, "invalid R (len)");
+            ensure!(s.len() < 33, "invalid S (len)");
+
+            let mut sig_bytes = [0u8; 64];
+            // add padding if the values were encoded short
+            sig_bytes[(32 - r.len())..32].copy_from_slice(r);
+            sig_bytes[32 + (32 - s.len())..].copy_from_slice(s);
+
+            let mut sig = Signature::from_compact(&sig_bytes)?;
+            // libsecp256k1 only accepts the lower of the two valid S values, other
+            // implementations don't normalize them
+            sig.normalize_s();
+
+            let pk = decode_point(p)?;
+            let msg = digest_to_message(hashed)?;
+            Secp256k1::verification_only().verify(&msg, &sig, &pk)?;
+
+            Ok(())
+        }
+        ECCCurve::P256 | ECCCurve::P384 | ECCCurve::P521 => {
+            ensure_eq!(sig.len(), 2, "invalid signature");
+
+            nist::verify(curve, p, hashed, sig[0].as_bytes(), sig[1].as_bytes())
+        }
+        _ => unsupported_err!("curve {:?} for ECDSA", curve.to_string()),
+    }
+}
+
+/// Sign using ECDSA, with a deterministic nonce (RFC 6979).
+pub fn sign(
+    curve: &ECCCurve,
+    p: &[u8],
+    secret_key: &ECDSASecretKey,
+    _hash: HashAlgorithm,
+    digest: &[u8],
+) -> Result<Vec<Vec<u8>>> {
+    match *curve {
+        ECCCurve::Secp256k1 => {
+            let secp = Secp256k1::new();
+            let sk = SecretKey::from_slice(&secret_key.secret)?;
+            ensure!(
+                PublicKey::from_secret_key(&secp, &sk) == decode_point(p)?,
+                "secret key does not match the public key"
+            );
+
+            let msg = digest_to_message(digest)?;
+            let bytes = secp.sign(&msg, &sk).serialize_compact();
+
+            let r = bytes[..32].to_vec();
+            let s = bytes[32..].to_vec();
+
+            Ok(vec![r, s])
+        }
+        _ => unsupported_err!("curve {:?} for ECDSA", curve.to_string()),
+    }
+}
+
+/// Sign using ECDSA with one of the NIST curves, with a deterministic nonce (RFC 6979).
+pub fn sign_nist(
+    curve: &ECCCurve,
+    p: &[u8],
+    secret_key: &NistECDSASecretKey,
+    _hash: HashAlgorithm,
+    digest: &[u8],
+) -> Result<Vec<Vec<u8>>> {
+    ensure!(
+        nist::public_key(curve, &secret_key.secret)? == p,
+        "secret key does not match the public key"
+    );
+
+    let (r, s) = nist::sign(curve, &secret_key.secret, digest)?;
+
+    Ok(vec![r, s])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_sig() -> (Vec<u8>, Vec<u8>, Vec<Mpi>) {
+        let q = hex::decode(
+            "04bb50e2d89a4ed70663d080659fe0ad4b9bc3e06c17a227433966cb59ceee020d\
+             ecddbf6e00192011648d13b1c00af770c0c1bb609d4d3a5c98a43772e0e18ef4",
+        )
+        .unwrap();
+        // sha256("hello world")
+        let digest =
+            hex::decode("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9")
+                .unwrap();
+        let r = hex::decode("97855f402631f09e602e5ccadc219503f07cdd4c73b2215b5418f52a7fdbfcd9")
+            .unwrap();
+        let s = hex::decode("892fe9bd6d60c379574e48940392e2eba7926400d5d938082388d130375d1343")
+            .unwrap();
+
+        (
+            q,
+            digest,
+            vec![Mpi::from_raw_slice(&r), Mpi::from_raw_slice(&s)],
+        )
+    }
+
+    #[test]
+    fn test_verify_secp256k1() {
+        let (q, digest, sig) = test_sig();
+
+        verify(
+            &ECCCurve::Secp256k1,
+            &q,
+            HashAlgorithm::SHA2_256,
+            &digest,
+            &sig,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_verify_secp256k1_bad_digest() {
+        let (q, mut digest, sig) = test_sig();
+        digest[0] ^= 1;
+
+        assert!(verify(
+            &ECCCurve::Secp256k1,
+            &q,
+            HashAlgorithm::SHA2_256,
+            &digest,
+            &sig,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_sign_verify_secp256k1() {
+        let secret_key = ECDSASecretKey {
+            secret: [0x42; 32],
+            oid: ECCCurve::Secp256k1.oid(),
+        };
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&secret_key.secret).unwrap();
+        let q = PublicKey::from_secret_key(&secp, &sk).serialize_uncompressed();
+
+        for digest in &[&[0x17u8; 20][..], &[0x17u8; 32][..], &[0x17u8; 64][..]] {
+            let sig = sign(
+                &ECCCurve::Secp256k1,
+                &q,
+                &secret_key,
+                HashAlgorithm::SHA2_256,
+                digest,
+            )
+            .unwrap();
+            let sig: Vec<Mpi> = sig.iter().map(|v| Mpi::from_raw_slice(v)).collect();
+
+            verify(
+                &ECCCurve::Secp256k1,
+                &q,
+                HashAlgorithm::SHA2_256,
+                digest,
+                &sig,
+            )
+            .unwrap();
+        }
+
+        // a signature over another digest does not verify
+        let sig = sign(
+            &ECCCurve::Secp256k1,
+            &q,
+            &secret_key,
+            HashAlgorithm::SHA2_256,
+            &[0x17u8; 32],
+        )
+        .unwrap();
+        let sig: Vec<Mpi> = sig.iter().map(|v| Mpi::from_raw_slice(v)).collect();
+        assert!(verify(
+            &ECCCurve::Secp256k1,
+            &q,
+            HashAlgorithm::SHA2_256,
+            &[0x18u8; 32],
+            &sig,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use ed25519_dalek::Keypair;
+use rand::{CryptoRng, Rng};
+use zeroize::Zeroize;
+
+use crate::crypto::HashAlgorithm;
+use crate::errors::Result;
+use crate::types::{Ed25519SecretKey, Mpi, PlainSecretParams, PublicParams};
+
+/// Size of Ed25519 public and secret keys, in octets.
+pub const KEY_SIZE: usize = 32;
+
+/// Size of Ed25519 signatures, in octets.
+pub const SIGNATURE_SIZE: usize = 64;
+
+/// Generate an Ed25519 KeyPair.
+pub fn generate_key<R: Rng + CryptoRng>(rng: &mut R) -> (PublicParams, PlainSecretParams) {
+    let keypair = Keypair::generate(rng);
+    let mut bytes = keypair.to_bytes();
+
+    let public = bytes[KEY_SIZE..].to_vec();
+    let secret = bytes[..KEY_SIZE].to_vec();
+    bytes.zeroize();
+
+    (
+        PublicParams::Ed25519 { public },
+        PlainSecretParams::Ed25519(secret),
+    )
+}
+
+/// Verify an Ed25519 signature.
+pub fn verify(q: &[u8], _hash: HashAlgorithm, hashed: &[u8], sig: &[Mpi]) -> Result<()> {
+    ensure_eq!(sig.len(), 1);
+    ensure_eq!(sig[0].len(), SIGNATURE_SIZE, "invalid signature (len)");
+    ensure_eq!(q.len(), KEY_SIZE, "invalid Q (len)");
+
+    let pk = ed25519_dalek::PublicKey::from_bytes(q)?;
+    let sig = ed25519_dalek::Signature::from_bytes(sig[0].as_bytes())?;
+
+    pk.verify(hashed, &sig)?;
+
+    Ok(())
+}
+
+/// Sign using Ed25519, returns the signature as a single octet string.
+pub fn sign(
+    q: &[u8],
+    secret_key: &Ed25519SecretKey,
+    _hash: HashAlgorithm,
+    digest: &[u8],
+) -> Result<Vec<Vec<u8>>> {
+    ensure_eq!(q.len(), KEY_SIZE, "invalid Q (len)");
+
+    let mut kp_bytes = vec![0u8; 2 * KEY_SIZE];
+    kp_bytes[..KEY_SIZE].copy_from_slice(&secret_key.secret);
+    kp_bytes[KEY_SIZE..].copy_from_slice(q);
+    let kp = ed25519_dalek::Keypair::from_bytes(&kp_bytes);
+    kp_bytes.zeroize();
+
+    let signature = kp?.sign(digest);
+
+    Ok(vec![signature.to_bytes().to_vec()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use crate::types::SecretKeyRepr;
+
+    #[test]
+    fn test_sign_verify() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let (pkey, skey) = generate_key(&mut rng);
+        let q = match pkey {
+            PublicParams::Ed25519 { ref public } => public.clone(),
+            _ => panic!("invalid key generated"),
+        };
+        assert_eq!(q.len(), KEY_SIZE);
+
+        let digest = [0x42u8; 32];
+        let sig = match skey.as_ref().as_repr(&pkey).unwrap() {
+            SecretKeyRepr::Ed25519(ref skey) => {
+                sign(&q, skey, HashAlgorithm::SHA2_256, &digest).unwrap()
+            }
+            _ => panic!("invalid key generated"),
+        };
+        assert_eq!(sig[0].len(), SIGNATURE_SIZE);
+
+        let sig = vec![Mpi::from_slice(&sig[0])];
+        verify(&q, HashAlgorithm::SHA2_256, &digest, &sig).unwrap();
+        assert!(verify(&q, HashAlgorithm::SHA2_256, &[0x43u8; 32], &sig).is_err());
+    }
+}
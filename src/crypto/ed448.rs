@@ -0,0 +1,101 @@
+use std::convert::TryFrom;
+
+use ed448_rust::{PrivateKey, PublicKey};
+use rand::{CryptoRng, Rng};
+use zeroize::Zeroize;
+
+use crate::crypto::HashAlgorithm;
+use crate::errors::Result;
+use crate::types::{Ed448SecretKey, Mpi, PlainSecretParams, PublicParams};
+
+/// Size of Ed448 public and secret keys, in octets.
+pub const KEY_SIZE: usize = 57;
+
+/// Size of Ed448 signatures, in octets.
+pub const SIGNATURE_SIZE: usize = 114;
+
+/// Generate an Ed448 KeyPair.
+pub fn generate_key<R: Rng + CryptoRng>(rng: &mut R) -> (PublicParams, PlainSecretParams) {
+    let mut secret = vec![0u8; KEY_SIZE];
+    rng.fill(&mut secret[..]);
+
+    let key = PrivateKey::try_from(&secret[..]).expect("fixed size secret");
+    let public = PublicKey::from(&key).as_byte().to_vec();
+
+    (
+        PublicParams::Ed448 { public },
+        PlainSecretParams::Ed448(secret),
+    )
+}
+
+/// Verify an Ed448 signature, which is made over the digest, with an empty context.
+pub fn verify(q: &[u8], _hash: HashAlgorithm, hashed: &[u8], sig: &[Mpi]) -> Result<()> {
+    ensure_eq!(sig.len(), 1);
+    ensure_eq!(sig[0].len(), SIGNATURE_SIZE, "invalid signature (len)");
+    ensure_eq!(q.len(), KEY_SIZE, "invalid Q (len)");
+
+    let pk = PublicKey::try_from(q).map_err(|err| format_err!("invalid Q: {:?}", err))?;
+    pk.verify(hashed, sig[0].as_bytes(), None)
+        .map_err(|err| format_err!("invalid Ed448 signature: {:?}", err))?;
+
+    Ok(())
+}
+
+/// Sign using Ed448, returns the signature as a single octet string.
+pub fn sign(
+    q: &[u8],
+    secret_key: &Ed448SecretKey,
+    _hash: HashAlgorithm,
+    digest: &[u8],
+) -> Result<Vec<Vec<u8>>> {
+    ensure_eq!(q.len(), KEY_SIZE, "invalid Q (len)");
+    ensure_eq!(secret_key.secret.len(), KEY_SIZE, "invalid secret (len)");
+
+    let key = PrivateKey::try_from(&secret_key.secret[..])
+        .map_err(|err| format_err!("invalid secret: {:?}", err))?;
+    let mut public = PublicKey::from(&key).as_byte();
+    let matches = &public[..] == q;
+    public.zeroize();
+    ensure!(matches, "inconsistent key state");
+
+    let signature = key
+        .sign(digest, None)
+        .map_err(|err| format_err!("failed to sign: {:?}", err))?;
+
+    Ok(vec![signature.to_vec()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use crate::types::SecretKeyRepr;
+
+    #[test]
+    fn test_sign_verify() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let (pkey, skey) = generate_key(&mut rng);
+        let q = match pkey {
+            PublicParams::Ed448 { ref public } => public.clone(),
+            _ => panic!("invalid key generated"),
+        };
+        assert_eq!(q.len(), KEY_SIZE);
+
+        let digest = [0x42u8; 64];
+        let sig = match skey.as_ref().as_repr(&pkey).unwrap() {
+            SecretKeyRepr::Ed448(ref skey) => {
+                sign(&q, skey, HashAlgorithm::SHA2_512, &digest).unwrap()
+            }
+            _ => panic!("invalid key generated"),
+        };
+        assert_eq!(sig[0].len(), SIGNATURE_SIZE);
+
+        let sig = vec![Mpi::from_slice(&sig[0])];
+        verify(&q, HashAlgorithm::SHA2_512, &digest, &sig).unwrap();
+        assert!(verify(&q, HashAlgorithm::SHA2_512, &[0x43u8; 64], &sig).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use num_bigint::traits::ModInverse;
+use num_bigint::BigUint;
+
+use crate::crypto::MAX_KEY_BITS;
+use crate::errors::{Error, Result};
+use crate::types::{ElgamalSecretKey, Mpi};
+
+/// Elgamal decryption, using PKCS1v15 padding.
+/// Ref: https://tools.ietf.org/html/rfc4880.html#section-5.1
+pub fn decrypt(priv_key: &ElgamalSecretKey, mpis: &[Mpi]) -> Result<Vec<u8>> {
+    // elgamal consists of exactly two mpis, g**k mod p and m * y**k mod p
+    ensure_eq!(mpis.len(), 2, "invalid input");
+
+    let p = &priv_key.p;
+    if p.bits() > MAX_KEY_BITS {
+        return Err(Error::KeyTooLarge {
+            bits: p.bits(),
+            max: MAX_KEY_BITS,
+        });
+    }
+    let c1 = BigUint::from_bytes_be(mpis[0].as_bytes());
+    let c2 = BigUint::from_bytes_be(mpis[1].as_bytes());
+    ensure!(&c1 < p && &c2 < p, "invalid input");
+
+    let s = c1.modpow(&priv_key.x, p);
+    let s_inv = match s.mod_inverse(p).and_then(|v| v.to_biguint()) {
+        Some(v) => v,
+        None => bail!("invalid input"),
+    };
+    let m = (c2 * s_inv) % p;
+
+    // left pad to the size of the modulus
+    let k = (p.bits() + 7) / 8;
+    let raw = m.to_bytes_be();
+    ensure!(raw.len() <= k, "invalid input");
+    let mut em = vec![0u8; k];
+    em[k - raw.len()..].copy_from_slice(&raw);
+
+    // EME-PKCS1-v1_5: 0x00 || 0x02 || PS || 0x00 || M, with at least 8 octets of PS
+    // Ref: https://tools.ietf.org/html/rfc4880.html#section-13.1.2
+    ensure!(k >= 11 && em[0] == 0 && em[1] == 2, "invalid padding");
+    let sep = match em[2..].iter().position(|b| *b == 0) {
+        Some(i) => i + 2,
+        None => bail!("invalid padding"),
+    };
+    ensure!(sep >= 10, "invalid padding");
+
+    Ok(em[sep + 1..].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypt() {
+        let p = hex::decode(
+            "ef60c88e760bc170f0bdea5a5975a8d45af072ee672c1a53f17f24377d8b7829\
+             c114848620ba6a163473c9c3dbcc35e13aec6ef1875957feac5973ed87cdf6ef\
+             67d830f7cfa3270af1b0db24e7744ff86edb9490b55e348137f5cb43b6b65ef5\
+             562e608d70642f9c4ccf74ecab071e4f1a1074614a8a1bb563b79b1955a5a37b",
+        )
+        .unwrap();
+        let x = hex::decode(
+            "a170b33839263059f28c105d1fb17c2390c192cfd3ac94af0f21ddb66cad4a26\
+             8d116ece1738f7d93d9c172411e20b8f6b0d549b6f03675a1600a35a099950d8\
+             36f675cc81e74ef5e8e25d940ed904759531985d5d9dc9f81818e811892f902b\
+             d23f0824128b2f330c5c7fd0a6a3a4506513270e269e0d37f2a74de452e6b43a",
+        )
+        .unwrap();
+        let c1 = hex::decode(
+            "6849cabdb890b92b96c76dde9ba4517264a202a29b1e3e44e5445df4d7b34857\
+             2df831f1ec7d9491273787d8fabd0579239bc23bee602e372fd8bd59501909de\
+             f2df0729b73533b73f983958d4c8e4a719b749e8927f82226d3d19d16977ecdd\
+             49b9ca25f446bac7c319e45f8bbdd6c7661766e585e58ff0bd0d4e1469bd060f",
+        )
+        .unwrap();
+        let c2 = hex::decode(
+            "10fbfb428441325a4e018cad58f486ba6c613e7b31dd903f775595d665cd29d6\
+             36c2bbfd0f556bb728b1fc4bdb017e6bdcbef9c91f6fb6b498d2b505455599a4\
+             68347ff104961ddd0067c90145d66251eb2e7a13249115c0f17d395912b5c23b\
+             361e9eda11ec86dbaa56d52fd240d41a0bedb447d77eced6d2467cf2cf587761",
+        )
+        .unwrap();
+
+        let key = ElgamalSecretKey {
+            p: BigUint::from_bytes_be(&p),
+            x: BigUint::from_bytes_be(&x),
+        };
+        let mpis = vec![Mpi::from_raw_slice(&c1), Mpi::from_raw_slice(&c2)];
+
+        assert_eq!(
+            decrypt(&key, &mpis).unwrap(),
+            hex::decode("0901020304050607080910111213141516").unwrap()
+        );
+    }
+}
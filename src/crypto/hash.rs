@@ -1,4 +1,5 @@
 use std::boxed::Box;
+use std::io;
 
 use rsa::Hash;
 use try_from::TryInto;
@@ -68,6 +69,72 @@ pub trait Hasher {
     fn finish(self: Box<Self>) -> Vec<u8>;
 }
 
+/// Adapter to write into a [`Hasher`], to hash serialized data without buffering it.
+pub struct HashWriter<'a>(pub &'a mut dyn Hasher);
+
+impl<'a> io::Write for HashWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Adapter to write text into a [`Hasher`], converting line endings to `\r\n`, as required
+/// for text signatures.
+///
+/// Like GnuPG, only a `\n` that is not preceded by `\r` is converted. The state is kept
+/// between writes, so line endings that are split across writes are handled.
+pub struct TextHashWriter<'a> {
+    hasher: &'a mut dyn Hasher,
+    last_was_cr: bool,
+}
+
+impl<'a> TextHashWriter<'a> {
+    pub fn new(hasher: &'a mut dyn Hasher) -> Self {
+        TextHashWriter {
+            hasher,
+            last_was_cr: false,
+        }
+    }
+}
+
+impl<'a> io::Write for TextHashWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        update_text(self.hasher, buf, &mut self.last_was_cr);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hashes `buf` like [`TextHashWriter`], for callers that need to keep the hasher
+/// themselves. `last_was_cr` carries the state between calls.
+pub(crate) fn update_text(hasher: &mut dyn Hasher, buf: &[u8], last_was_cr: &mut bool) {
+    canonicalize_text(buf, last_was_cr, |chunk| hasher.update(chunk));
+}
+
+/// Passes `buf` to `out` in chunks, with every `\n` that is not preceded by `\r`
+/// converted to `\r\n`. `last_was_cr` carries the state between calls.
+pub(crate) fn canonicalize_text(buf: &[u8], last_was_cr: &mut bool, mut out: impl FnMut(&[u8])) {
+    let mut start = 0;
+    for (i, b) in buf.iter().enumerate() {
+        if *b == b'\n' && !*last_was_cr {
+            out(&buf[start..i]);
+            out(b"\r");
+            start = i;
+        }
+        *last_was_cr = *b == b'\r';
+    }
+    out(&buf[start..]);
+}
+
 macro_rules! derive_hasher {
     ($name:ident, $struct:path) => {
         #[derive(Default)]
@@ -148,4 +215,16 @@ impl HashAlgorithm {
             _ => 0,
         }
     }
+
+    /// Returns the size of the salt used in v6 signatures, if the algorithm is
+    /// allowed for them.
+    /// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-hash-algorithms
+    pub fn salt_size(self) -> Option<usize> {
+        match self {
+            HashAlgorithm::SHA2_256 | HashAlgorithm::SHA2_224 | HashAlgorithm::SHA3_256 => Some(16),
+            HashAlgorithm::SHA2_384 => Some(24),
+            HashAlgorithm::SHA2_512 | HashAlgorithm::SHA3_512 => Some(32),
+            _ => None,
+        }
+    }
 }
@@ -3,13 +3,21 @@
 pub mod aead;
 pub mod aes_kw;
 pub mod checksum;
+pub mod dsa;
 pub mod ecc_curve;
 pub mod ecdh;
+pub mod ecdsa;
+pub mod ed25519;
+pub mod ed448;
 pub mod eddsa;
+pub mod elgamal;
 pub mod hash;
+pub mod nist;
 pub mod public_key;
 pub mod rsa;
 pub mod sym;
+pub mod x25519;
+pub mod x448;
 
 pub use self::aead::*;
 pub use self::aes_kw::*;
@@ -21,3 +29,10 @@ pub use self::hash::*;
 pub use self::public_key::*;
 pub use self::rsa::*;
 pub use self::sym::*;
+
+/// Hard limit for the size of RSA, DSA and Elgamal keys, in bits.
+///
+/// Larger keys are refused to bound the cost of processing them, the limit applied
+/// during verification is usually lower, see
+/// [`crate::composed::Policy::max_key_bits`].
+pub const MAX_KEY_BITS: usize = 16384;
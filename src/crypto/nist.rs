@@ -0,0 +1,233 @@
+//! Arithmetic on the NIST curves P-256, P-384 and P-521, shared by ECDSA and ECDH.
+//!
+//! Points are uncompressed SEC1 points (`0x04 || x || y`), scalars are big endian and as
+//! long as the field size of the curve.
+
+use rand::{CryptoRng, Rng};
+use zeroize::Zeroize;
+
+use crate::crypto::ECCCurve;
+use crate::errors::Result;
+
+macro_rules! impl_curve {
+    ($name:ident, $krate:ident, $size:expr) => {
+        mod $name {
+            use ::$krate::ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier};
+            use ::$krate::ecdsa::{Signature, SigningKey, VerifyingKey};
+            use ::$krate::elliptic_curve::sec1::ToEncodedPoint;
+            use ::$krate::{PublicKey, SecretKey};
+
+            use crate::errors::Result;
+
+            pub const FIELD_SIZE: usize = $size;
+
+            fn secret_key(secret: &[u8]) -> Result<SecretKey> {
+                ensure_eq!(secret.len(), FIELD_SIZE, "invalid secret (len)");
+                SecretKey::from_slice(secret).map_err(|_| format_err!("invalid secret"))
+            }
+
+            pub fn is_valid_secret(secret: &[u8]) -> bool {
+                secret_key(secret).is_ok()
+            }
+
+            pub fn public_key(secret: &[u8]) -> Result<Vec<u8>> {
+                let public = secret_key(secret)?.public_key();
+                Ok(public.to_encoded_point(false).as_bytes().to_vec())
+            }
+
+            pub fn secret_from_sec1_der(der: &[u8]) -> Result<Vec<u8>> {
+                let secret = SecretKey::from_sec1_der(der)
+                    .map_err(|err| format_err!("invalid EC private key: {}", err))?;
+                Ok(secret.to_bytes().to_vec())
+            }
+
+            pub fn diffie_hellman(secret: &[u8], public: &[u8]) -> Result<Vec<u8>> {
+                let secret = secret_key(secret)?;
+                let public = PublicKey::from_sec1_bytes(public)
+                    .map_err(|_| format_err!("invalid public point"))?;
+                let shared = ::$krate::elliptic_curve::ecdh::diffie_hellman(
+                    secret.to_nonzero_scalar(),
+                    public.as_affine(),
+                );
+                Ok(shared.raw_secret_bytes().to_vec())
+            }
+
+            pub fn sign(secret: &[u8], digest: &[u8]) -> Result<Vec<u8>> {
+                let key =
+                    SigningKey::from_slice(secret).map_err(|_| format_err!("invalid secret"))?;
+                let signature: Signature = key
+                    .sign_prehash(digest)
+                    .map_err(|err| format_err!("failed to sign: {}", err))?;
+                Ok(signature.to_bytes().to_vec())
+            }
+
+            pub fn verify(public: &[u8], digest: &[u8], signature: &[u8]) -> Result<()> {
+                let key = VerifyingKey::from_sec1_bytes(public)
+                    .map_err(|_| format_err!("invalid public point"))?;
+                let signature = Signature::from_slice(signature)
+                    .map_err(|_| format_err!("invalid signature"))?;
+                key.verify_prehash(digest, &signature)
+                    .map_err(|_| format_err!("invalid signature"))
+            }
+        }
+    };
+}
+
+impl_curve!(nist_p256, p256, 32);
+impl_curve!(nist_p384, p384, 48);
+impl_curve!(nist_p521, p521, 66);
+
+macro_rules! dispatch {
+    ($curve:expr, $fn:ident($($arg:expr),*)) => {
+        match *$curve {
+            ECCCurve::P256 => nist_p256::$fn($($arg),*),
+            ECCCurve::P384 => nist_p384::$fn($($arg),*),
+            ECCCurve::P521 => nist_p521::$fn($($arg),*),
+            _ => unsupported_err!("curve {:?}", $curve.to_string()),
+        }
+    };
+}
+
+/// Returns true for P-256, P-384 and P-521.
+pub fn is_nist_curve(curve: &ECCCurve) -> bool {
+    *curve == ECCCurve::P256 || *curve == ECCCurve::P384 || *curve == ECCCurve::P521
+}
+
+/// Size of the scalars and of the coordinates of the points, in octets.
+pub fn field_size(curve: &ECCCurve) -> Result<usize> {
+    match *curve {
+        ECCCurve::P256 => Ok(nist_p256::FIELD_SIZE),
+        ECCCurve::P384 => Ok(nist_p384::FIELD_SIZE),
+        ECCCurve::P521 => Ok(nist_p521::FIELD_SIZE),
+        _ => unsupported_err!("curve {:?}", curve.to_string()),
+    }
+}
+
+/// Pads the big endian value `value` with zeros, to the field size of `curve`.
+pub fn pad_to_field_size(curve: &ECCCurve, value: &[u8]) -> Result<Vec<u8>> {
+    let size = field_size(curve)?;
+    ensure!(value.len() <= size, "invalid value (len)");
+
+    let mut padded = vec![0u8; size];
+    padded[size - value.len()..].copy_from_slice(value);
+
+    Ok(padded)
+}
+
+/// Computes the public point of the secret scalar `secret`.
+pub fn public_key(curve: &ECCCurve, secret: &[u8]) -> Result<Vec<u8>> {
+    dispatch!(curve, public_key(secret))
+}
+
+/// Extracts the secret scalar of a SEC1 `ECPrivateKey`, as defined in RFC 5915.
+pub fn secret_from_sec1_der(curve: &ECCCurve, der: &[u8]) -> Result<Vec<u8>> {
+    dispatch!(curve, secret_from_sec1_der(der))
+}
+
+/// Generates a random secret scalar.
+pub fn generate_secret<R: CryptoRng + Rng>(rng: &mut R, curve: &ECCCurve) -> Result<Vec<u8>> {
+    let size = field_size(curve)?;
+    let mut secret = vec![0u8; size];
+    loop {
+        rng.fill(&mut secret[..]);
+        if *curve == ECCCurve::P521 {
+            // only the lowest bit of the first octet is part of the 521 bits
+            secret[0] &= 0x01;
+        }
+        // values that are zero or not below the order of the curve are drawn again
+        let is_valid = dispatch!(curve, is_valid_secret(&secret));
+        if is_valid {
+            return Ok(secret);
+        }
+        secret.zeroize();
+    }
+}
+
+/// Computes the x coordinate of the point shared by the secret scalar `secret` and the
+/// public point `public`.
+pub fn diffie_hellman(curve: &ECCCurve, secret: &[u8], public: &[u8]) -> Result<Vec<u8>> {
+    dispatch!(curve, diffie_hellman(secret, public))
+}
+
+/// Signs the digest with a deterministic nonce (RFC 6979), returning `r` and `s`.
+///
+/// Like with other ECDSA implementations, digests longer than the field size are
+/// truncated, shorter ones are used as they are.
+pub fn sign(curve: &ECCCurve, secret: &[u8], digest: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let size = field_size(curve)?;
+    let digest = prepare_digest(size, digest);
+    let signature = dispatch!(curve, sign(secret, &digest))?;
+
+    Ok((signature[..size].to_vec(), signature[size..].to_vec()))
+}
+
+/// Verifies the signature `r`, `s` over the digest.
+pub fn verify(curve: &ECCCurve, public: &[u8], digest: &[u8], r: &[u8], s: &[u8]) -> Result<()> {
+    let size = field_size(curve)?;
+    let digest = prepare_digest(size, digest);
+
+    // add padding if the values were encoded short
+    let mut signature = pad_to_field_size(curve, r)?;
+    signature.extend_from_slice(&pad_to_field_size(curve, s)?);
+
+    dispatch!(curve, verify(public, &digest, &signature))
+}
+
+/// The implementations reject digests shorter than half the field size, padding them with
+/// zeros does not change their value.
+fn prepare_digest(size: usize, digest: &[u8]) -> Vec<u8> {
+    if digest.len() >= size {
+        digest.to_vec()
+    } else {
+        let mut padded = vec![0u8; size];
+        padded[size - digest.len()..].copy_from_slice(digest);
+        padded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    const CURVES: [ECCCurve; 3] = [ECCCurve::P256, ECCCurve::P384, ECCCurve::P521];
+
+    #[test]
+    fn test_sign_verify() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+
+        for curve in &CURVES {
+            let secret = generate_secret(&mut rng, curve).unwrap();
+            let public = public_key(curve, &secret).unwrap();
+            assert_eq!(public.len(), 1 + 2 * field_size(curve).unwrap());
+
+            for digest in &[&[0x17u8; 20][..], &[0x17u8; 32][..], &[0x17u8; 64][..]] {
+                let (r, s) = sign(curve, &secret, digest).unwrap();
+                verify(curve, &public, digest, &r, &s).unwrap();
+                assert!(verify(curve, &public, &[0x18u8; 32], &r, &s).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_diffie_hellman() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+
+        for curve in &CURVES {
+            let a = generate_secret(&mut rng, curve).unwrap();
+            let b = generate_secret(&mut rng, curve).unwrap();
+
+            let shared = diffie_hellman(curve, &a, &public_key(curve, &b).unwrap()).unwrap();
+            assert_eq!(shared.len(), field_size(curve).unwrap());
+            assert_eq!(
+                shared,
+                diffie_hellman(curve, &b, &public_key(curve, &a).unwrap()).unwrap()
+            );
+        }
+
+        assert!(diffie_hellman(&ECCCurve::P256, &[0x42; 32], &[0x04; 65]).is_err());
+        assert!(public_key(&ECCCurve::Secp256k1, &[0x42; 32]).is_err());
+    }
+}
@@ -5,10 +5,24 @@ use rsa::padding::PaddingScheme;
 use rsa::{PublicKey, PublicKeyParts, RSAPrivateKey, RSAPublicKey};
 use try_from::TryInto;
 
-use crate::crypto::HashAlgorithm;
-use crate::errors::Result;
+use crate::crypto::{HashAlgorithm, MAX_KEY_BITS};
+use crate::errors::{Error, Result};
 use crate::types::{Mpi, PlainSecretParams, PublicParams};
 
+/// Creates the public key, checking its size against [`MAX_KEY_BITS`].
+fn public_key(n: &[u8], e: &[u8]) -> Result<RSAPublicKey> {
+    let n = BigUint::from_bytes_be(n);
+    let bits = n.bits();
+    if bits > MAX_KEY_BITS {
+        return Err(Error::KeyTooLarge {
+            bits,
+            max: MAX_KEY_BITS,
+        });
+    }
+
+    RSAPublicKey::new(n, BigUint::from_bytes_be(e)).map_err(Into::into)
+}
+
 /// RSA decryption using PKCS1v15 padding.
 pub fn decrypt(priv_key: &RSAPrivateKey, mpis: &[Mpi], _fingerprint: &[u8]) -> Result<Vec<u8>> {
     // rsa consist of exactly one mpi
@@ -27,7 +41,7 @@ pub fn encrypt<R: CryptoRng + Rng>(
     e: &[u8],
     plaintext: &[u8],
 ) -> Result<Vec<Vec<u8>>> {
-    let key = RSAPublicKey::new(BigUint::from_bytes_be(n), BigUint::from_bytes_be(e))?;
+    let key = public_key(n, e)?;
     let data = key.encrypt(rng, PaddingScheme::new_pkcs1v15_encrypt(), plaintext)?;
 
     Ok(vec![data])
@@ -65,7 +79,7 @@ pub fn generate_key<R: Rng + CryptoRng>(
 
 /// Verify a RSA, PKCS1v15 padded signature.
 pub fn verify(n: &[u8], e: &[u8], hash: HashAlgorithm, hashed: &[u8], sig: &[u8]) -> Result<()> {
-    let key = RSAPublicKey::new(BigUint::from_bytes_be(n), BigUint::from_bytes_be(e))?;
+    let key = public_key(n, e)?;
     let rsa_hash: Option<rsa::Hash> = hash.try_into().ok();
 
     key.verify(PaddingScheme::new_pkcs1v15_sign(rsa_hash), &hashed[..], sig)
@@ -79,3 +93,22 @@ pub fn sign(key: &RSAPrivateKey, hash: HashAlgorithm, digest: &[u8]) -> Result<V
 
     Ok(vec![sig])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_key_too_large() {
+        let n = vec![0xff; MAX_KEY_BITS / 8 + 1];
+        let e = [1, 0, 1];
+
+        match verify(&n, &e, HashAlgorithm::SHA2_256, &[0u8; 32], &[1u8; 32]) {
+            Err(Error::KeyTooLarge { bits, max }) => {
+                assert_eq!(bits, MAX_KEY_BITS + 8);
+                assert_eq!(max, MAX_KEY_BITS);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+}
@@ -169,6 +169,9 @@ impl SymmetricKeyAlgorithm {
 
         // MDC is 1 byte packet tag, 1 byte length prefix and 20 bytes SHA1 hash.
         let mdc_len = 22;
+        if res.len() < mdc_len {
+            return Err(Error::MdcError);
+        }
         let (data, mdc) = res.split_at(res.len() - mdc_len);
 
         let sha1 = checksum::calculate_sha1(&[prefix, data, &mdc[0..2]].concat());
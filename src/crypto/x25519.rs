@@ -0,0 +1,127 @@
+use hkdf::Hkdf;
+use rand::{CryptoRng, Rng};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroize;
+
+use crate::crypto::aes_kw;
+use crate::errors::Result;
+use crate::types::{PlainSecretParams, PublicParams, X25519SecretKey};
+
+/// Size of X25519 public keys, secret keys and ephemeral keys, in octets.
+pub const KEY_SIZE: usize = 32;
+
+/// Generate an X25519 KeyPair.
+pub fn generate_key<R: Rng + CryptoRng>(rng: &mut R) -> (PublicParams, PlainSecretParams) {
+    let secret = StaticSecret::new(rng);
+    let public = PublicKey::from(&secret);
+
+    (
+        PublicParams::X25519 {
+            public: public.as_bytes().to_vec(),
+        },
+        PlainSecretParams::X25519(secret.to_bytes().to_vec()),
+    )
+}
+
+/// Derives the key encryption key from the ephemeral and the recipient public key, and the
+/// secret they share.
+/// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-algorithm-specific-fields-for-x
+fn key_encryption_key(ephemeral: &[u8], recipient: &[u8], shared_secret: &[u8]) -> Result<Vec<u8>> {
+    let mut ikm = Vec::with_capacity(3 * KEY_SIZE);
+    ikm.extend_from_slice(ephemeral);
+    ikm.extend_from_slice(recipient);
+    ikm.extend_from_slice(shared_secret);
+
+    // AES-128 is used to wrap the session key
+    let mut kek = vec![0u8; 16];
+    let res = Hkdf::<Sha256>::new(None, &ikm).expand(b"OpenPGP X25519", &mut kek);
+    ikm.zeroize();
+    res.map_err(|_| format_err!("invalid key derivation length"))?;
+
+    Ok(kek)
+}
+
+fn to_array(bytes: &[u8]) -> [u8; KEY_SIZE] {
+    let mut arr = [0u8; KEY_SIZE];
+    arr.copy_from_slice(bytes);
+    arr
+}
+
+/// Encrypts the session key to the public key `q`.
+///
+/// Returns the ephemeral public key and the wrapped session key. Unlike ECDH, the session
+/// key is wrapped as it is, without padding or checksum.
+pub fn encrypt<R: CryptoRng + Rng>(rng: &mut R, q: &[u8], plain: &[u8]) -> Result<Vec<Vec<u8>>> {
+    ensure_eq!(q.len(), KEY_SIZE, "invalid public key");
+
+    let their_public = PublicKey::from(to_array(q));
+    let our_secret = StaticSecret::new(rng);
+    let shared_secret = our_secret.diffie_hellman(&their_public);
+    let ephemeral = PublicKey::from(&our_secret).as_bytes().to_vec();
+
+    let mut kek = key_encryption_key(&ephemeral, q, shared_secret.as_bytes())?;
+    let encrypted_key = aes_kw::wrap(&kek, plain);
+    kek.zeroize();
+
+    Ok(vec![ephemeral, encrypted_key?])
+}
+
+/// Unwraps the session key, that was encrypted using the ephemeral public key `ephemeral`.
+pub fn decrypt(
+    priv_key: &X25519SecretKey,
+    ephemeral: &[u8],
+    encrypted_key: &[u8],
+) -> Result<Vec<u8>> {
+    ensure_eq!(ephemeral.len(), KEY_SIZE, "invalid ephemeral key");
+
+    let their_public = PublicKey::from(to_array(ephemeral));
+    let our_secret = StaticSecret::from(priv_key.secret);
+    let shared_secret = our_secret.diffie_hellman(&their_public);
+    let our_public = PublicKey::from(&our_secret);
+
+    let mut kek = key_encryption_key(ephemeral, our_public.as_bytes(), shared_secret.as_bytes())?;
+    let decrypted_key = aes_kw::unwrap(&kek, encrypted_key);
+    kek.zeroize();
+
+    decrypted_key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use crate::types::SecretKeyRepr;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let (pkey, skey) = generate_key(&mut rng);
+        let public = match pkey {
+            PublicParams::X25519 { ref public } => public.clone(),
+            _ => panic!("invalid key generated"),
+        };
+        assert_eq!(public.len(), KEY_SIZE);
+
+        let plain = [0x42u8; 16];
+        let encrypted = encrypt(&mut rng, &public, &plain).unwrap();
+        assert_eq!(encrypted[0].len(), KEY_SIZE);
+        assert_eq!(encrypted[1].len(), plain.len() + 8);
+
+        match skey.as_ref().as_repr(&pkey).unwrap() {
+            SecretKeyRepr::X25519(ref skey) => {
+                let decrypted = decrypt(skey, &encrypted[0], &encrypted[1]).unwrap();
+                assert_eq!(&decrypted[..], &plain[..]);
+
+                let mut modified = encrypted[1].clone();
+                modified[0] ^= 1;
+                assert!(decrypt(skey, &encrypted[0], &modified).is_err());
+            }
+            _ => panic!("invalid key generated"),
+        }
+    }
+}
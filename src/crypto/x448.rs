@@ -0,0 +1,128 @@
+use hkdf::Hkdf;
+use rand::{CryptoRng, Rng};
+use sha2::Sha512;
+use zeroize::Zeroize;
+
+use crate::crypto::aes_kw;
+use crate::errors::Result;
+use crate::types::{PlainSecretParams, PublicParams, X448SecretKey};
+
+/// Size of X448 public keys, secret keys and ephemeral keys, in octets.
+pub const KEY_SIZE: usize = 56;
+
+/// Generate an X448 KeyPair.
+pub fn generate_key<R: Rng + CryptoRng>(rng: &mut R) -> (PublicParams, PlainSecretParams) {
+    let secret = x448::Secret::new(rng);
+    let public = x448::PublicKey::from(&secret);
+
+    (
+        PublicParams::X448 {
+            public: public.as_bytes().to_vec(),
+        },
+        PlainSecretParams::X448(secret.as_bytes().to_vec()),
+    )
+}
+
+/// Derives the key encryption key from the ephemeral and the recipient public key, and the
+/// secret they share.
+/// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-algorithm-specific-fields-for-x
+fn key_encryption_key(ephemeral: &[u8], recipient: &[u8], shared_secret: &[u8]) -> Result<Vec<u8>> {
+    let mut ikm = Vec::with_capacity(3 * KEY_SIZE);
+    ikm.extend_from_slice(ephemeral);
+    ikm.extend_from_slice(recipient);
+    ikm.extend_from_slice(shared_secret);
+
+    // AES-256 is used to wrap the session key
+    let mut kek = vec![0u8; 32];
+    let res = Hkdf::<Sha512>::new(None, &ikm).expand(b"OpenPGP X448", &mut kek);
+    ikm.zeroize();
+    res.map_err(|_| format_err!("invalid key derivation length"))?;
+
+    Ok(kek)
+}
+
+/// Encrypts the session key to the public key `q`.
+///
+/// Returns the ephemeral public key and the wrapped session key. Unlike ECDH, the session
+/// key is wrapped as it is, without padding or checksum.
+pub fn encrypt<R: CryptoRng + Rng>(rng: &mut R, q: &[u8], plain: &[u8]) -> Result<Vec<Vec<u8>>> {
+    ensure_eq!(q.len(), KEY_SIZE, "invalid public key");
+
+    let their_public =
+        x448::PublicKey::from_bytes(q).ok_or_else(|| format_err!("invalid public key"))?;
+    let our_secret = x448::Secret::new(rng);
+    let shared_secret = our_secret
+        .as_diffie_hellman(&their_public)
+        .ok_or_else(|| format_err!("invalid public key"))?;
+    let ephemeral = x448::PublicKey::from(&our_secret).as_bytes().to_vec();
+
+    let mut kek = key_encryption_key(&ephemeral, q, shared_secret.as_bytes())?;
+    let encrypted_key = aes_kw::wrap(&kek, plain);
+    kek.zeroize();
+
+    Ok(vec![ephemeral, encrypted_key?])
+}
+
+/// Unwraps the session key, that was encrypted using the ephemeral public key `ephemeral`.
+pub fn decrypt(
+    priv_key: &X448SecretKey,
+    ephemeral: &[u8],
+    encrypted_key: &[u8],
+) -> Result<Vec<u8>> {
+    ensure_eq!(ephemeral.len(), KEY_SIZE, "invalid ephemeral key");
+    ensure_eq!(priv_key.secret.len(), KEY_SIZE, "invalid secret key");
+
+    let their_public = x448::PublicKey::from_bytes(ephemeral)
+        .ok_or_else(|| format_err!("invalid ephemeral key"))?;
+    let our_secret = x448::Secret::from_bytes(&priv_key.secret)
+        .ok_or_else(|| format_err!("invalid secret key"))?;
+    let shared_secret = our_secret
+        .as_diffie_hellman(&their_public)
+        .ok_or_else(|| format_err!("invalid ephemeral key"))?;
+    let our_public = x448::PublicKey::from(&our_secret);
+
+    let mut kek = key_encryption_key(ephemeral, our_public.as_bytes(), shared_secret.as_bytes())?;
+    let decrypted_key = aes_kw::unwrap(&kek, encrypted_key);
+    kek.zeroize();
+
+    decrypted_key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    use crate::types::SecretKeyRepr;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+
+        let (pkey, skey) = generate_key(&mut rng);
+        let public = match pkey {
+            PublicParams::X448 { ref public } => public.clone(),
+            _ => panic!("invalid key generated"),
+        };
+        assert_eq!(public.len(), KEY_SIZE);
+
+        let plain = [0x42u8; 32];
+        let encrypted = encrypt(&mut rng, &public, &plain).unwrap();
+        assert_eq!(encrypted[0].len(), KEY_SIZE);
+        assert_eq!(encrypted[1].len(), plain.len() + 8);
+
+        match skey.as_ref().as_repr(&pkey).unwrap() {
+            SecretKeyRepr::X448(ref skey) => {
+                let decrypted = decrypt(skey, &encrypted[0], &encrypted[1]).unwrap();
+                assert_eq!(&decrypted[..], &plain[..]);
+
+                let mut modified = encrypted[1].clone();
+                modified[0] ^= 1;
+                assert!(decrypt(skey, &encrypted[0], &modified).is_err());
+            }
+            _ => panic!("invalid key generated"),
+        }
+    }
+}
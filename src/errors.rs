@@ -6,6 +6,9 @@ pub type Result<T> = ::std::result::Result<T, Error>;
 
 // custom nom error types
 pub const MPI_TOO_LONG: u32 = 1000;
+pub const INVALID_SUBPACKET_LEN: u32 = 1001;
+pub const TOO_MANY_SUBPACKETS: u32 = 1002;
+pub const EMBEDDED_SIGNATURE_TOO_DEEP: u32 = 1003;
 
 /// Error types
 #[derive(Debug, thiserror::Error)]
@@ -64,8 +67,34 @@ pub enum Error {
     InvalidPacketContent(Box<Error>),
     #[error("Ed25519 {0:?}")]
     Ed25519SignatureError(#[from] SignatureError),
+    #[error("secp256k1 {0:?}")]
+    Secp256k1Error(#[from] secp256k1::Error),
     #[error("Modification Detection Code error")]
     MdcError,
+    #[error("key too large: {bits} bits, the limit is {max} bits")]
+    KeyTooLarge { bits: usize, max: usize },
+    #[error("armor block type {typ:?} does not match the contents, starting with {tag:?}")]
+    ArmorMismatch {
+        typ: crate::armor::BlockType,
+        tag: crate::types::Tag,
+    },
+    #[error("no usable encryption key in {key_id:?}: {}", crate::composed::signed_key::encryption::format_unusable(.skipped))]
+    NoEncryptionKey {
+        key_id: crate::types::KeyId,
+        skipped: Vec<crate::composed::UnusableKey>,
+    },
+    #[error("too many failed unlock attempts: {failures}")]
+    TooManyAttempts {
+        failures: u32,
+        /// The time of the next allowed attempt, `None` if no more attempts are allowed.
+        retry_at: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    #[error("secret key material of {key_id:?} is not present")]
+    SecretKeyNotPresent { key_id: crate::types::KeyId },
+    #[error("encrypted data without integrity protection is refused")]
+    UnprotectedData,
+    #[error("AEAD authentication failed")]
+    AeadError,
 }
 
 impl Error {
@@ -99,6 +128,13 @@ impl Error {
             Error::InvalidPacketContent(_) => 25,
             Error::Ed25519SignatureError(_) => 26,
             Error::MdcError => 27,
+            Error::KeyTooLarge { .. } => 28,
+            Error::ArmorMismatch { .. } => 29,
+            Error::NoEncryptionKey { .. } => 30,
+            Error::TooManyAttempts { .. } => 31,
+            Error::SecretKeyNotPresent { .. } => 32,
+            Error::UnprotectedData => 33,
+            Error::AeadError => 34,
         }
     }
 }
@@ -0,0 +1,3 @@
+//! Conversions from and to key formats used outside of OpenPGP.
+
+pub mod pkcs8;
@@ -50,11 +50,13 @@ pub mod util;
 #[macro_use]
 pub mod errors;
 pub mod armor;
+pub mod audit;
 pub mod base64_decoder;
 pub mod base64_reader;
 pub mod composed;
 pub mod crypto;
 pub mod de;
+pub mod interop;
 pub mod line_reader;
 pub mod line_writer;
 pub mod normalize_lines;
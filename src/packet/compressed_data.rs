@@ -1,7 +1,9 @@
 use std::fmt;
-use std::io::{self, Cursor, Read};
+use std::io::{self, Cursor, Read, Write};
 
 use flate2::read::{DeflateDecoder, ZlibDecoder};
+use flate2::write::{DeflateEncoder, ZlibEncoder};
+use flate2::Compression;
 use num_traits::FromPrimitive;
 
 use crate::errors::Result;
@@ -9,6 +11,9 @@ use crate::packet::PacketTrait;
 use crate::ser::Serialize;
 use crate::types::{CompressionAlgorithm, Tag, Version};
 
+/// Default limit for the size of decompressed data, in bytes.
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: u64 = 1024 * 1024 * 1024;
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct CompressedData {
     packet_version: Version,
@@ -34,10 +39,132 @@ impl<'a> Read for Decompressor<&'a [u8]> {
     }
 }
 
+/// A writer, that compresses the data written to it.
+pub trait Compressor: Write {
+    /// Flushes the remaining data and returns the compressed bytes.
+    fn finish(self: Box<Self>) -> Result<Vec<u8>>;
+}
+
+impl Compressor for Vec<u8> {
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        Ok(*self)
+    }
+}
+
+impl Compressor for DeflateEncoder<Vec<u8>> {
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        Ok(DeflateEncoder::finish(*self)?)
+    }
+}
+
+impl Compressor for ZlibEncoder<Vec<u8>> {
+    fn finish(self: Box<Self>) -> Result<Vec<u8>> {
+        Ok(ZlibEncoder::finish(*self)?)
+    }
+}
+
+/// An implementation of the compression algorithms.
+///
+/// The default is [`Flate2Backend`], other implementations can be passed to
+/// [`CompressedData::compress_with`] and [`CompressedData::decompress_with`].
+pub trait CompressionBackend {
+    /// Returns a reader, that decompresses `data` while it is read.
+    fn decompressor<'a>(
+        &self,
+        alg: CompressionAlgorithm,
+        data: &'a [u8],
+    ) -> Result<Box<dyn Read + 'a>>;
+
+    /// Returns a writer, that compresses the data written to it.
+    fn compressor(&self, alg: CompressionAlgorithm) -> Result<Box<dyn Compressor>>;
+}
+
+/// Supports ZIP and ZLIB, using flate2.
+///
+/// Uses miniz_oxide by default, the `zlib` and `zlib-ng-compat` features switch to the
+/// respective C libraries.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Flate2Backend;
+
+impl CompressionBackend for Flate2Backend {
+    fn decompressor<'a>(
+        &self,
+        alg: CompressionAlgorithm,
+        data: &'a [u8],
+    ) -> Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(decompressor(alg, data)?))
+    }
+
+    fn compressor(&self, alg: CompressionAlgorithm) -> Result<Box<dyn Compressor>> {
+        match alg {
+            CompressionAlgorithm::Uncompressed => Ok(Box::new(Vec::new())),
+            CompressionAlgorithm::ZIP => Ok(Box::new(DeflateEncoder::new(
+                Vec::new(),
+                Compression::default(),
+            ))),
+            CompressionAlgorithm::ZLIB => Ok(Box::new(ZlibEncoder::new(
+                Vec::new(),
+                Compression::default(),
+            ))),
+            CompressionAlgorithm::BZip2 => unimplemented_err!("BZip2"),
+            CompressionAlgorithm::Private10 => unsupported_err!("Private10 should not be used"),
+        }
+    }
+}
+
+fn decompressor(alg: CompressionAlgorithm, data: &[u8]) -> Result<Decompressor<&[u8]>> {
+    match alg {
+        CompressionAlgorithm::Uncompressed => Ok(Decompressor::Uncompressed(Cursor::new(data))),
+        CompressionAlgorithm::ZIP => Ok(Decompressor::Zip(DeflateDecoder::new(data))),
+        CompressionAlgorithm::ZLIB => Ok(Decompressor::Zlib(ZlibDecoder::new(data))),
+        CompressionAlgorithm::BZip2 => unimplemented_err!("BZip2"),
+        CompressionAlgorithm::Private10 => unsupported_err!("Private10 should not be used"),
+    }
+}
+
+/// Returns a reader, that decompresses the data read from `reader`, for compressed data
+/// that is not read into memory.
+pub(crate) fn stream_decompressor<'a>(
+    alg: CompressionAlgorithm,
+    reader: impl Read + 'a,
+) -> Result<Box<dyn Read + 'a>> {
+    match alg {
+        CompressionAlgorithm::Uncompressed => Ok(Box::new(reader)),
+        CompressionAlgorithm::ZIP => Ok(Box::new(DeflateDecoder::new(reader))),
+        CompressionAlgorithm::ZLIB => Ok(Box::new(ZlibDecoder::new(reader))),
+        CompressionAlgorithm::BZip2 => unimplemented_err!("BZip2"),
+        CompressionAlgorithm::Private10 => unsupported_err!("Private10 should not be used"),
+    }
+}
+
+/// Fails reading once more than `remaining` bytes were read from `inner`.
+struct LimitedReader<R> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        // read one byte more than allowed, to detect data exceeding the limit
+        let max = std::cmp::min(into.len() as u64, self.remaining.saturating_add(1)) as usize;
+        let n = self.inner.read(&mut into[..max])?;
+        if n as u64 > self.remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "decompressed data exceeds the size limit",
+            ));
+        }
+        self.remaining -= n as u64;
+
+        Ok(n)
+    }
+}
+
 impl CompressedData {
     /// Parses a `CompressedData` packet from the given slice.
     pub fn from_slice(packet_version: Version, input: &[u8]) -> Result<Self> {
-        ensure!(input.len() > 1, "input too short");
+        // an empty payload is valid, it decompresses to nothing
+        ensure!(!input.is_empty(), "input too short");
 
         let alg = CompressionAlgorithm::from_u8(input[0])
             .ok_or_else(|| format_err!("invalid compression algorithm"))?;
@@ -56,20 +183,55 @@ impl CompressedData {
         }
     }
 
+    /// Compresses `data`, using flate2.
+    pub fn compress(alg: CompressionAlgorithm, data: &[u8]) -> Result<Self> {
+        Self::compress_with(&Flate2Backend, alg, data)
+    }
+
+    /// Compresses `data`, using the given `backend`.
+    pub fn compress_with(
+        backend: &dyn CompressionBackend,
+        alg: CompressionAlgorithm,
+        data: &[u8],
+    ) -> Result<Self> {
+        let mut compressor = backend.compressor(alg)?;
+        compressor.write_all(data)?;
+
+        Ok(Self::from_compressed(alg, compressor.finish()?))
+    }
+
+    /// Returns a reader over the decompressed data, using flate2.
+    ///
+    /// The size of the decompressed data is not limited, see [`CompressedData::decompress_with`].
     pub fn decompress(&self) -> Result<Decompressor<&[u8]>> {
-        match self.compression_algorithm {
-            CompressionAlgorithm::Uncompressed => Ok(Decompressor::Uncompressed(Cursor::new(
-                &self.compressed_data[..],
-            ))),
-            CompressionAlgorithm::ZIP => Ok(Decompressor::Zip(DeflateDecoder::new(
-                &self.compressed_data[..],
-            ))),
-            CompressionAlgorithm::ZLIB => Ok(Decompressor::Zlib(ZlibDecoder::new(
-                &self.compressed_data[..],
-            ))),
-            CompressionAlgorithm::BZip2 => unimplemented_err!("BZip2"),
-            CompressionAlgorithm::Private10 => unsupported_err!("Private10 should not be used"),
-        }
+        decompressor(self.compression_algorithm, &self.compressed_data)
+    }
+
+    /// Returns a reader over the decompressed data, using the given `backend`.
+    ///
+    /// The data is decompressed while it is read, so it can be passed on to a parser
+    /// without holding all of it in memory. Reading fails once more than `limit` bytes
+    /// were decompressed.
+    pub fn decompress_with(
+        &self,
+        backend: &dyn CompressionBackend,
+        limit: u64,
+    ) -> Result<Box<dyn Read + '_>> {
+        let inner = backend.decompressor(self.compression_algorithm, &self.compressed_data)?;
+
+        Ok(Box::new(LimitedReader {
+            inner,
+            remaining: limit,
+        }))
+    }
+
+    /// Decompresses using flate2, limited to [`DEFAULT_MAX_DECOMPRESSED_SIZE`] bytes.
+    pub(crate) fn decompress_limited(&self) -> Result<Box<dyn Read + '_>> {
+        self.decompress_with(&Flate2Backend, DEFAULT_MAX_DECOMPRESSED_SIZE)
+    }
+
+    pub fn compression_algorithm(&self) -> CompressionAlgorithm {
+        self.compression_algorithm
     }
 
     pub fn compressed_data(&self) -> &[u8] {
@@ -10,7 +10,7 @@ use crate::line_writer::LineBreak;
 use crate::normalize_lines::Normalized;
 use crate::packet::PacketTrait;
 use crate::ser::Serialize;
-use crate::types::{Tag, Version};
+use crate::types::{Charset, Tag, Version};
 use crate::util::{read_string, write_string};
 
 /// Literal Data Packet
@@ -51,6 +51,31 @@ impl LiteralData {
         }
     }
 
+    /// Creates a literal data packet from the given string, encoded in `charset`.
+    /// Normalizes line endings.
+    ///
+    /// Text in charsets other than UTF-8 is marked as `Text`, receivers have to be told
+    /// about the charset out of band, usually through the `Charset` armor header.
+    pub fn from_str_with_charset(
+        file_name: &str,
+        raw_data: &str,
+        charset: Charset,
+    ) -> Result<Self> {
+        let normalized: String = Normalized::new(raw_data.chars(), LineBreak::Crlf).collect();
+        let mode = match charset {
+            Charset::Utf8 => DataMode::Utf8,
+            _ => DataMode::Text,
+        };
+
+        Ok(LiteralData {
+            packet_version: Version::New,
+            mode,
+            file_name: file_name.to_owned(),
+            created: Utc::now().trunc_subsecs(0),
+            data: charset.encode(&normalized)?,
+        })
+    }
+
     /// Creates a literal data packet from the given bytes.
     pub fn from_bytes(file_name: &str, data: &[u8]) -> Self {
         LiteralData {
@@ -80,6 +105,14 @@ impl LiteralData {
         &self.data
     }
 
+    pub fn mode(&self) -> DataMode {
+        self.mode
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
     /// Convert the data to a UTF-8 string, if appropriate for the type.
     /// Returns `None` if `mode` is `Binary`, or the data is not valid UTF-8.
     pub fn to_string(&self) -> Option<String> {
@@ -88,15 +121,42 @@ impl LiteralData {
             _ => std::str::from_utf8(&self.data).map(str::to_owned).ok(),
         }
     }
-}
 
-impl Serialize for LiteralData {
-    fn to_writer<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+    /// Convert the data to a string, decoding it from the given charset.
+    /// Returns `None` if `mode` is `Binary`, or the data is not valid in the charset.
+    ///
+    /// Data marked as `Utf8` is always decoded as UTF-8.
+    pub fn to_string_with_charset(&self, charset: Charset) -> Option<String> {
+        match self.mode {
+            DataMode::Binary => None,
+            DataMode::Utf8 => self.to_string(),
+            _ => charset.decode(&self.data).ok(),
+        }
+    }
+
+    /// Returns the mode, file name and creation time as serialized in the packet, which
+    /// are included in v5 signatures over the data.
+    pub(crate) fn metadata(&self) -> Vec<u8> {
+        let mut metadata = Vec::new();
+        self.write_metadata(&mut metadata)
+            .expect("writing to a vector");
+        metadata
+    }
+
+    fn write_metadata<W: io::Write>(&self, writer: &mut W) -> Result<()> {
         let name = write_string(&self.file_name);
         writer.write_all(&[self.mode as u8, name.len() as u8])?;
         writer.write_all(&name)?;
         writer.write_u32::<BigEndian>(self.created.timestamp() as u32)?;
 
+        Ok(())
+    }
+}
+
+impl Serialize for LiteralData {
+    fn to_writer<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+        self.write_metadata(writer)?;
+
         // Line endings are stored internally normalized, so we do not need to worry
         // about changing them here.
         writer.write_all(&self.data)?;
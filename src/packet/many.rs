@@ -1,4 +1,4 @@
-use std::io::Read;
+use std::io::{self, Read};
 
 use buf_redux::Buffer;
 use nom::{Needed, Offset};
@@ -47,9 +47,12 @@ impl<R: Read> Iterator for PacketParser<R> {
             // read some data
             let sz = match b.read_from(inner) {
                 Ok(sz) => sz,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
                 Err(err) => {
+                    // only the end of the input ends the packets, not a failing read
                     warn!("failed to read {:?}", err);
-                    return None;
+                    self.failed = true;
+                    return Some(Err(err.into()));
                 }
             };
 
@@ -140,6 +143,47 @@ mod tests {
     use crate::ser::Serialize;
     use crate::types::Tag;
 
+    #[test]
+    fn test_length_encodings_roundtrip() {
+        use crate::packet::{write_packet_with, LiteralData};
+        use crate::types::{LengthEncoding, Version};
+
+        let data = vec![7u8; 2000];
+        let literal = LiteralData::from_bytes("hello.txt", &data);
+
+        for (version, encoding) in &[
+            (Version::New, LengthEncoding::Minimal),
+            (Version::Old, LengthEncoding::Minimal),
+            (Version::New, LengthEncoding::Full),
+            (Version::Old, LengthEncoding::Full),
+            (Version::New, LengthEncoding::Partial(9)),
+            (Version::Old, LengthEncoding::Indeterminate),
+        ] {
+            let mut buf = Vec::new();
+            write_packet_with(&mut buf, &literal, *version, *encoding).unwrap();
+
+            let packets = PacketParser::new(&buf[..])
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(packets.len(), 1, "{:?} {:?}", version, encoding);
+            match &packets[0] {
+                Packet::LiteralData(p) => assert_eq!(p.data(), &data[..]),
+                p => panic!("unexpected packet: {:?}", p),
+            }
+        }
+
+        let mut buf = Vec::new();
+        assert!(
+            write_packet_with(&mut buf, &literal, Version::Old, LengthEncoding::Partial(9))
+                .is_err()
+        );
+
+        // user attributes have tag 17, which does not fit into an old format header
+        let attr = crate::packet::UserAttribute::new_unknown(100, vec![1, 2, 3]);
+        assert!(write_packet_with(&mut buf, &attr, Version::Old, LengthEncoding::Minimal).is_err());
+        write_packet_with(&mut buf, &attr, Version::New, LengthEncoding::Minimal).unwrap();
+    }
+
     #[test]
     #[ignore]
     fn test_packet_roundtrip_0001() {
@@ -225,6 +269,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_read_error() {
+        struct FailingReader;
+
+        impl Read for FailingReader {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::Other, "broken"))
+            }
+        }
+
+        let mut parser = PacketParser::new(FailingReader);
+        assert!(parser.next().expect("missing error").is_err());
+        assert!(parser.next().is_none());
+
+        assert!(PacketParser::new(&[][..]).next().is_none());
+    }
+
     #[test]
     fn test_many_parser() {
         // use pretty_env_logger;
@@ -21,6 +21,10 @@ pub struct OnePassSignature {
     hash_algorithm: HashAlgorithm,
     pub_algorithm: PublicKeyAlgorithm,
     key_id: KeyId,
+    /// The salt of the signature, for version 6 packets.
+    salt: Vec<u8>,
+    /// The fingerprint of the signing key, for version 6 packets.
+    fingerprint: Vec<u8>,
     last: u8,
 }
 
@@ -45,13 +49,95 @@ impl OnePassSignature {
             hash_algorithm,
             pub_algorithm,
             key_id,
+            salt: Vec::new(),
+            fingerprint: Vec::new(),
             last: 1,
         }
     }
 
+    /// Creates a version 6 packet, for signatures of v6 keys.
+    ///
+    /// `salt` has to be the salt of the signature, so that the data can be hashed
+    /// before the signature is read.
+    pub fn from_details_v6(
+        typ: SignatureType,
+        hash_algorithm: HashAlgorithm,
+        pub_algorithm: PublicKeyAlgorithm,
+        salt: Vec<u8>,
+        fingerprint: Vec<u8>,
+    ) -> Result<Self> {
+        ensure_eq!(fingerprint.len(), 32, "invalid v6 fingerprint");
+        ensure_eq!(
+            Some(salt.len()),
+            hash_algorithm.salt_size(),
+            "invalid salt for {:?}",
+            hash_algorithm
+        );
+
+        Ok(OnePassSignature {
+            packet_version: Default::default(),
+            version: 0x06,
+            typ,
+            hash_algorithm,
+            pub_algorithm,
+            key_id: KeyId::from_slice(&fingerprint[..8])?,
+            salt,
+            fingerprint,
+            last: 1,
+        })
+    }
+
     pub fn packet_version(&self) -> Version {
         self.packet_version
     }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn typ(&self) -> SignatureType {
+        self.typ
+    }
+
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    pub fn pub_algorithm(&self) -> PublicKeyAlgorithm {
+        self.pub_algorithm
+    }
+
+    pub fn key_id(&self) -> &KeyId {
+        &self.key_id
+    }
+
+    /// Returns the salt of the signature, for version 6 packets.
+    pub fn salt(&self) -> Option<&[u8]> {
+        if self.version == 0x06 {
+            Some(&self.salt)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the fingerprint of the signing key, for version 6 packets.
+    pub fn fingerprint(&self) -> Option<&[u8]> {
+        if self.version == 0x06 {
+            Some(&self.fingerprint)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `false` if the next packet is another one-pass signature, that is made over
+    /// the same data as this one.
+    pub fn is_last(&self) -> bool {
+        self.last != 0
+    }
+
+    pub fn set_last(&mut self, last: bool) {
+        self.last = if last { 1 } else { 0 };
+    }
 }
 
 #[rustfmt::skip]
@@ -60,15 +146,25 @@ named_args!(parse(packet_version: Version) <OnePassSignature>, do_parse!(
     >>       typ: map_opt!(be_u8, SignatureType::from_u8)
     >>      hash: map_opt!(be_u8, HashAlgorithm::from_u8)
     >>   pub_alg: map_opt!(be_u8, PublicKeyAlgorithm::from_u8)
-    >>    key_id: map_res!(take!(8), KeyId::from_slice)
-    >> last: be_u8
+    >>    issuer: switch!(value!(version),
+                      6 => do_parse!(
+                                salt_len: be_u8
+                             >>     salt: take!(salt_len)
+                             >> fingerprint: take!(32)
+                             >> (salt.to_vec(), fingerprint.to_vec())
+                         ) |
+                      _ => map!(take!(8), |key_id| (Vec::new(), key_id.to_vec()))
+                  )
+    >>      last: be_u8
     >> (OnePassSignature {
         packet_version,
         version,
         typ,
         hash_algorithm: hash,
         pub_algorithm: pub_alg,
-        key_id,
+        key_id: KeyId::from_slice(&issuer.1[..8]).expect("fixed size slice"),
+        salt: issuer.0,
+        fingerprint: if version == 6 { issuer.1 } else { Vec::new() },
         last,
     })
 ));
@@ -81,7 +177,13 @@ impl Serialize for OnePassSignature {
             self.hash_algorithm as u8,
             self.pub_algorithm as u8,
         ])?;
-        writer.write_all(self.key_id.as_ref())?;
+        if self.version == 0x06 {
+            writer.write_all(&[self.salt.len() as u8])?;
+            writer.write_all(&self.salt)?;
+            writer.write_all(&self.fingerprint)?;
+        } else {
+            writer.write_all(self.key_id.as_ref())?;
+        }
         writer.write_all(&[self.last])?;
 
         Ok(())
@@ -97,3 +199,53 @@ impl PacketTrait for OnePassSignature {
         Tag::OnePassSignature
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_v6_roundtrip() {
+        let salt = (0u8..16).collect::<Vec<_>>();
+        let fingerprint = (100u8..132).collect::<Vec<_>>();
+        let ops = OnePassSignature::from_details_v6(
+            SignatureType::Binary,
+            HashAlgorithm::SHA2_256,
+            PublicKeyAlgorithm::RSA,
+            salt.clone(),
+            fingerprint.clone(),
+        )
+        .unwrap();
+        assert_eq!(ops.key_id().as_ref(), &fingerprint[..8]);
+
+        let bytes = ops.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 4 + 1 + 16 + 32 + 1);
+        let parsed = OnePassSignature::from_slice(Default::default(), &bytes).unwrap();
+        assert_eq!(parsed, ops);
+        assert_eq!(parsed.version(), 6);
+        assert_eq!(parsed.salt(), Some(&salt[..]));
+        assert_eq!(parsed.fingerprint(), Some(&fingerprint[..]));
+
+        // the salt size depends on the hash algorithm
+        assert!(OnePassSignature::from_details_v6(
+            SignatureType::Binary,
+            HashAlgorithm::SHA2_512,
+            PublicKeyAlgorithm::RSA,
+            salt,
+            fingerprint,
+        )
+        .is_err());
+
+        let v3 = OnePassSignature::from_details(
+            SignatureType::Binary,
+            HashAlgorithm::SHA2_256,
+            PublicKeyAlgorithm::RSA,
+            KeyId::from_slice(&[1; 8]).unwrap(),
+        );
+        assert_eq!(v3.salt(), None);
+        assert_eq!(
+            OnePassSignature::from_slice(Default::default(), &v3.to_bytes().unwrap()).unwrap(),
+            v3
+        );
+    }
+}
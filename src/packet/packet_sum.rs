@@ -8,7 +8,8 @@ use crate::packet::{
     UserId,
 };
 use crate::ser::Serialize;
-use crate::types::{Tag, Version};
+use crate::types::{LengthEncoding, Tag, Version};
+use crate::util::write_packet_length;
 
 #[derive(Debug)]
 #[cfg_attr(feature = "cargo-clippy", allow(clippy::large_enum_variant))] // TODO: fix me
@@ -125,6 +126,73 @@ impl Serialize for Packet {
     }
 }
 
+/// A borrowed packet, as part of a composed key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketRef<'a> {
+    PublicKey(&'a PublicKey),
+    PublicSubkey(&'a PublicSubkey),
+    SecretKey(&'a SecretKey),
+    SecretSubkey(&'a SecretSubkey),
+    Signature(&'a Signature),
+    UserAttribute(&'a UserAttribute),
+    UserId(&'a UserId),
+}
+
+impl<'a> PacketRef<'a> {
+    /// Returns the tag for this packet type.
+    pub fn tag(&self) -> Tag {
+        match self {
+            PacketRef::PublicKey(_) => Tag::PublicKey,
+            PacketRef::PublicSubkey(_) => Tag::PublicSubkey,
+            PacketRef::SecretKey(_) => Tag::SecretKey,
+            PacketRef::SecretSubkey(_) => Tag::SecretSubkey,
+            PacketRef::Signature(_) => Tag::Signature,
+            PacketRef::UserAttribute(_) => Tag::UserAttribute,
+            PacketRef::UserId(_) => Tag::UserId,
+        }
+    }
+
+    pub fn packet_version(&self) -> Version {
+        match self {
+            PacketRef::PublicKey(p) => p.packet_version(),
+            PacketRef::PublicSubkey(p) => p.packet_version(),
+            PacketRef::SecretKey(p) => p.packet_version(),
+            PacketRef::SecretSubkey(p) => p.packet_version(),
+            PacketRef::Signature(p) => p.packet_version(),
+            PacketRef::UserAttribute(p) => p.packet_version(),
+            PacketRef::UserId(p) => p.packet_version(),
+        }
+    }
+
+    /// Returns an owned copy of the packet.
+    pub fn to_owned(&self) -> Packet {
+        match self {
+            PacketRef::PublicKey(p) => Packet::PublicKey((*p).clone()),
+            PacketRef::PublicSubkey(p) => Packet::PublicSubkey((*p).clone()),
+            PacketRef::SecretKey(p) => Packet::SecretKey((*p).clone()),
+            PacketRef::SecretSubkey(p) => Packet::SecretSubkey((*p).clone()),
+            PacketRef::Signature(p) => Packet::Signature((*p).clone()),
+            PacketRef::UserAttribute(p) => Packet::UserAttribute((*p).clone()),
+            PacketRef::UserId(p) => Packet::UserId((*p).clone()),
+        }
+    }
+}
+
+/// Writes the full packet, including the header.
+impl<'a> Serialize for PacketRef<'a> {
+    fn to_writer<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+        match self {
+            PacketRef::PublicKey(p) => write_packet(writer, p),
+            PacketRef::PublicSubkey(p) => write_packet(writer, p),
+            PacketRef::SecretKey(p) => write_packet(writer, p),
+            PacketRef::SecretSubkey(p) => write_packet(writer, p),
+            PacketRef::Signature(p) => write_packet(writer, p),
+            PacketRef::UserAttribute(p) => write_packet(writer, p),
+            PacketRef::UserId(p) => write_packet(writer, p),
+        }
+    }
+}
+
 pub trait PacketTrait: Serialize {
     fn packet_version(&self) -> Version;
     fn tag(&self) -> Tag;
@@ -141,21 +209,105 @@ impl<'a, T: 'a + PacketTrait> PacketTrait for &'a T {
 }
 
 pub fn write_packet(writer: &mut impl io::Write, packet: &impl PacketTrait) -> Result<()> {
-    let packet_version = packet.packet_version();
+    write_packet_with(
+        writer,
+        packet,
+        packet.packet_version(),
+        LengthEncoding::Minimal,
+    )
+}
+
+/// Writes the packet, using the given header format and length encoding.
+pub fn write_packet_with(
+    writer: &mut impl io::Write,
+    packet: &impl PacketTrait,
+    packet_version: Version,
+    encoding: LengthEncoding,
+) -> Result<()> {
     let mut buf = Vec::new();
     packet.to_writer(&mut buf)?;
     debug!(
-        "write_packet {:?} {:?} (len: {})",
+        "write_packet {:?} {:?} {:?} (len: {})",
         &packet_version,
+        encoding,
         packet.tag(),
         buf.len()
     );
 
-    // header
-    packet_version.write_header(writer, packet.tag() as u8, buf.len())?;
+    let tag = packet.tag() as u8;
+    // old format headers only have four bits for the tag
+    ensure!(
+        packet_version == Version::New || tag < 16,
+        "{:?} packets require a new format header",
+        packet.tag()
+    );
+    ensure!(
+        buf.len() <= std::u32::MAX as usize,
+        "packet of {} octets is too large",
+        buf.len()
+    );
+    match encoding {
+        LengthEncoding::Minimal => {
+            packet_version.write_header(writer, tag, buf.len())?;
+            writer.write_all(&buf)?;
+        }
+        LengthEncoding::Full => {
+            packet_version.write_header_full(writer, tag, buf.len())?;
+            writer.write_all(&buf)?;
+        }
+        LengthEncoding::Indeterminate => {
+            ensure_eq!(
+                packet_version,
+                Version::Old,
+                "indeterminate length requires an old format header"
+            );
+            writer.write_all(&[0b1000_0011 | tag << 2])?;
+            writer.write_all(&buf)?;
+        }
+        LengthEncoding::Partial(power) => {
+            ensure_eq!(
+                packet_version,
+                Version::New,
+                "partial lengths require a new format header"
+            );
+            ensure!(
+                supports_partial_length(packet.tag()),
+                "partial lengths are not allowed for {:?}",
+                packet.tag()
+            );
+            // the first chunk must be at least 512 octets long
+            ensure!(
+                power >= 9 && power <= 30,
+                "invalid partial length 2^{}",
+                power
+            );
+
+            let chunk_size = 1usize << power;
+            writer.write_all(&[0b1100_0000 | tag])?;
 
-    // the actual packet body
-    writer.write_all(&buf)?;
+            let mut rest = &buf[..];
+            while rest.len() >= chunk_size {
+                writer.write_all(&[0xE0 | power])?;
+                writer.write_all(&rest[..chunk_size])?;
+                rest = &rest[chunk_size..];
+            }
+
+            // the last chunk always has a definite length, which may be zero
+            write_packet_length(rest.len(), writer)?;
+            writer.write_all(rest)?;
+        }
+    }
 
     Ok(())
 }
+
+/// Only data packets may use partial body lengths.
+fn supports_partial_length(tag: Tag) -> bool {
+    match tag {
+        Tag::LiteralData
+        | Tag::CompressedData
+        | Tag::SymEncryptedData
+        | Tag::SymEncryptedProtectedData => true,
+        _ => false,
+    }
+}
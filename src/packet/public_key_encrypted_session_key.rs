@@ -9,7 +9,7 @@ use crate::crypto::{checksum, PublicKeyAlgorithm, SymmetricKeyAlgorithm};
 use crate::errors::Result;
 use crate::packet::PacketTrait;
 use crate::ser::Serialize;
-use crate::types::{mpi, KeyId, Mpi, PublicKeyTrait, Tag, Version};
+use crate::types::{mpi, KeyId, KeyTrait, KeyVersion, Mpi, PublicKeyTrait, Tag, Version};
 
 /// Public Key Encrypted Session Key Packet
 /// https://tools.ietf.org/html/rfc4880.html#section-5.1
@@ -18,6 +18,9 @@ pub struct PublicKeyEncryptedSessionKey {
     packet_version: Version,
     version: u8,
     id: KeyId,
+    /// Version and fingerprint of the recipient key, only set for version 6 packets
+    /// that are not anonymous.
+    fingerprint: Option<(KeyVersion, Vec<u8>)>,
     algorithm: PublicKeyAlgorithm,
     mpis: Vec<Mpi>,
 }
@@ -27,45 +30,140 @@ impl PublicKeyEncryptedSessionKey {
     pub fn from_slice(version: Version, input: &[u8]) -> Result<Self> {
         let (_, pk) = parse(input, version)?;
 
-        ensure_eq!(pk.version, 3, "invalid version");
+        ensure!(pk.version == 3 || pk.version == 6, "invalid version");
 
         Ok(pk)
     }
 
     /// Encrypts the given session key to the passed in public key.
+    ///
+    /// This produces a version 3 packet, see
+    /// [`from_session_key_v6`](Self::from_session_key_v6) for version 6. The recipient is
+    /// identified by `KeyTrait::key_id`, which for keys newer than v4 is derived from the
+    /// high-order 64 bits of the fingerprint.
     pub fn from_session_key<R: CryptoRng + Rng>(
         rng: &mut R,
         session_key: &[u8],
         alg: SymmetricKeyAlgorithm,
         pkey: &impl PublicKeyTrait,
     ) -> Result<Self> {
-        // the session key is prefixed with symmetric key algorithm
-        let len = session_key.len();
-        let mut data = vec![0u8; len + 3];
-        data[0] = alg as u8;
-        data[1..=len].copy_from_slice(session_key);
+        let mpis = if wraps_bare_session_key(pkey.algorithm()) {
+            // the algorithm precedes the wrapped session key in the clear
+            let mut mpis = pkey.encrypt(rng, session_key)?;
+            ensure_eq!(mpis.len(), 2, "invalid encrypted session key");
+            let mut encrypted_key = vec![alg as u8];
+            encrypted_key.extend_from_slice(mpis[1].as_bytes());
+            mpis[1] = Mpi::from_slice(&encrypted_key);
+            mpis
+        } else {
+            // the session key is prefixed with symmetric key algorithm
+            let len = session_key.len();
+            let mut data = vec![0u8; len + 3];
+            data[0] = alg as u8;
+            data[1..=len].copy_from_slice(session_key);
 
-        // and appended a checksum
-        BigEndian::write_u16(
-            &mut data[len + 1..],
-            checksum::calculate_simple(session_key),
-        );
+            // and appended a checksum
+            BigEndian::write_u16(
+                &mut data[len + 1..],
+                checksum::calculate_simple(session_key),
+            );
 
-        let mpis = pkey.encrypt(rng, &data)?;
+            pkey.encrypt(rng, &data)?
+        };
 
         Ok(PublicKeyEncryptedSessionKey {
             packet_version: Default::default(),
             version: 3,
             id: pkey.key_id(),
+            fingerprint: None,
             algorithm: pkey.algorithm(),
             mpis,
         })
     }
 
+    /// Encrypts the given session key to the passed in public key, as a version 6 packet.
+    ///
+    /// The recipient is identified by its versioned fingerprint. The algorithm of the
+    /// session key is not included, it must match the one of the version 2 SEIPD packet
+    /// it is used for.
+    pub fn from_session_key_v6<R: CryptoRng + Rng>(
+        rng: &mut R,
+        session_key: &[u8],
+        pkey: &impl PublicKeyTrait,
+    ) -> Result<Self> {
+        let mpis = if wraps_bare_session_key(pkey.algorithm()) {
+            pkey.encrypt(rng, session_key)?
+        } else {
+            // the session key, followed by a checksum
+            let len = session_key.len();
+            let mut data = vec![0u8; len + 2];
+            data[..len].copy_from_slice(session_key);
+            BigEndian::write_u16(&mut data[len..], checksum::calculate_simple(session_key));
+
+            pkey.encrypt(rng, &data)?
+        };
+        let (id, fingerprint) = v6_recipient(Some((pkey.version(), &pkey.fingerprint())))?;
+
+        Ok(PublicKeyEncryptedSessionKey {
+            packet_version: Default::default(),
+            version: 6,
+            id,
+            fingerprint,
+            algorithm: pkey.algorithm(),
+            mpis,
+        })
+    }
+
+    /// Removes the identity of the recipient, by replacing the key id with the wildcard
+    /// key id, like GnuPG's `--throw-keyids`.
+    ///
+    /// Receivers have to try all of their secret keys to decrypt anonymous packets.
+    pub fn with_anonymous_recipient(mut self) -> Self {
+        self.id = KeyId::wildcard();
+        self.fingerprint = None;
+        self
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
     pub fn id(&self) -> &KeyId {
         &self.id
     }
 
+    /// Returns the version and fingerprint of the recipient key, if the packet carries one.
+    pub fn fingerprint(&self) -> Option<(KeyVersion, &[u8])> {
+        self.fingerprint
+            .as_ref()
+            .map(|(version, fp)| (*version, &fp[..]))
+    }
+
+    /// Returns true if the recipient is not identified, using either the wildcard key id
+    /// or an empty fingerprint.
+    pub fn is_anonymous(&self) -> bool {
+        self.fingerprint.is_none() && self.id.is_wildcard()
+    }
+
+    pub fn algorithm(&self) -> PublicKeyAlgorithm {
+        self.algorithm
+    }
+
+    /// Checks if the given key is a possible recipient of this packet.
+    ///
+    /// Packets with a fingerprint are matched against the full, versioned fingerprint,
+    /// others by key id. Anonymous packets match all keys of the same algorithm.
+    pub fn matches(&self, key: &impl KeyTrait) -> bool {
+        if self.is_anonymous() {
+            return key.algorithm() == self.algorithm;
+        }
+
+        match &self.fingerprint {
+            Some((version, fp)) => key.version() == *version && &key.fingerprint() == fp,
+            None => key.key_id() == self.id,
+        }
+    }
+
     pub fn mpis(&self) -> &[Mpi] {
         &self.mpis
     }
@@ -75,6 +173,13 @@ impl PublicKeyEncryptedSessionKey {
     }
 }
 
+/// Returns true for the algorithms that wrap the session key without padding and checksum,
+/// the values of their packets are the ephemeral public key and the wrapped session key.
+/// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-algorithm-specific-fields-for-x
+pub(crate) fn wraps_bare_session_key(alg: PublicKeyAlgorithm) -> bool {
+    alg == PublicKeyAlgorithm::X25519 || alg == PublicKeyAlgorithm::X448
+}
+
 #[rustfmt::skip]
 named_args!(parse_mpis<'a>(alg: &'a PublicKeyAlgorithm) <Vec<Mpi>>, switch!(
     value!(alg),
@@ -98,16 +203,63 @@ named_args!(parse_mpis<'a>(alg: &'a PublicKeyAlgorithm) <Vec<Mpi>>, switch!(
             let v: [u8; 1] = [blen];
             vec![a.to_owned(), (&v[..]).into(), b.into()]
         })
+    ) |
+    // the values are fixed size octet strings, not MPIs
+    &PublicKeyAlgorithm::X25519 => do_parse!(
+           a: take!(32)
+        >> blen: be_u8
+        >> b: take!(blen)
+        >> (vec![Mpi::from_slice(a), Mpi::from_slice(b)])
+    ) |
+    &PublicKeyAlgorithm::X448 => do_parse!(
+           a: take!(56)
+        >> blen: be_u8
+        >> b: take!(blen)
+        >> (vec![Mpi::from_slice(a), Mpi::from_slice(b)])
     )
 ));
 
+/// Derives the key id from the recipient fingerprint of a version 6 packet.
+#[allow(clippy::type_complexity)]
+fn v6_recipient(
+    fingerprint: Option<(KeyVersion, &[u8])>,
+) -> Result<(KeyId, Option<(KeyVersion, Vec<u8>)>)> {
+    match fingerprint {
+        Some((version, fp)) => {
+            ensure!(fp.len() >= 8, "invalid fingerprint");
+            let id = match version {
+                KeyVersion::V4 => KeyId::from_slice(&fp[fp.len() - 8..])?,
+                _ => KeyId::from_slice(&fp[..8])?,
+            };
+
+            Ok((id, Some((version, fp.to_vec()))))
+        }
+        None => Ok((KeyId::wildcard(), None)),
+    }
+}
+
+// Parses the recipient of a version 6 packet, an empty fingerprint denotes an anonymous recipient
+#[rustfmt::skip]
+named!(parse_v6_recipient<Option<(KeyVersion, &[u8])>>, do_parse!(
+        len: be_u8
+    >>   fp: cond!(len > 0, do_parse!(
+                 version: map_opt!(be_u8, KeyVersion::from_u8)
+              >>      fp: take!(len - 1)
+              >> ((version, fp))
+         ))
+    >> (fp)
+));
+
 // Parses a Public-Key Encrypted Session Key Packets
 #[rustfmt::skip]
 named_args!(parse(packet_version: Version) <PublicKeyEncryptedSessionKey>, do_parse!(
-    // version, only 3 is allowed
+    // version, 3 and 6 are allowed
        version: be_u8
-    // the key id this maps to
-    >>     id: map_res!(take!(8), KeyId::from_slice)
+    // the key id, or for version 6 the fingerprint, this maps to
+    >> recipient: switch!(value!(version),
+           6 => map_res!(parse_v6_recipient, v6_recipient) |
+           _ => map!(map_res!(take!(8), KeyId::from_slice), |id| (id, None))
+       )
     // the symmetric key algorithm
     >>    alg: map_opt!(be_u8, PublicKeyAlgorithm::from_u8)
     // key algorithm specific data
@@ -115,7 +267,8 @@ named_args!(parse(packet_version: Version) <PublicKeyEncryptedSessionKey>, do_pa
     >> (PublicKeyEncryptedSessionKey {
         packet_version,
         version,
-        id,
+        id: recipient.0,
+        fingerprint: recipient.1,
         algorithm: alg,
         mpis,
     })
@@ -124,7 +277,17 @@ named_args!(parse(packet_version: Version) <PublicKeyEncryptedSessionKey>, do_pa
 impl Serialize for PublicKeyEncryptedSessionKey {
     fn to_writer<W: io::Write>(&self, writer: &mut W) -> Result<()> {
         writer.write_all(&[self.version])?;
-        writer.write_all(self.id.as_ref())?;
+        if self.version == 6 {
+            match &self.fingerprint {
+                Some((version, fp)) => {
+                    writer.write_all(&[fp.len() as u8 + 1, *version as u8])?;
+                    writer.write_all(fp)?;
+                }
+                None => writer.write_all(&[0])?,
+            }
+        } else {
+            writer.write_all(self.id.as_ref())?;
+        }
         writer.write_all(&[self.algorithm as u8])?;
 
         match self.algorithm {
@@ -152,6 +315,11 @@ impl Serialize for PublicKeyEncryptedSessionKey {
                 }
                 writer.write_all(self.mpis[2].as_bytes())?;
             }
+            PublicKeyAlgorithm::X25519 | PublicKeyAlgorithm::X448 => {
+                writer.write_all(self.mpis[0].as_bytes())?;
+                writer.write_all(&[self.mpis[1].len() as u8])?;
+                writer.write_all(self.mpis[1].as_bytes())?;
+            }
             _ => {
                 unimplemented_err!("writing {:?}", self.algorithm);
             }
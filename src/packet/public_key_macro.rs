@@ -96,6 +96,25 @@ macro_rules! impl_public_key {
                 Ok(())
             }
 
+            /// Serializes a v6 key, v5 keys use the same layout.
+            fn to_writer_v6<W: std::io::Write>(
+                &self,
+                writer: &mut W,
+            ) -> $crate::errors::Result<()> {
+                use byteorder::{BigEndian, WriteBytesExt};
+                use $crate::ser::Serialize;
+
+                writer.write_u32::<BigEndian>(self.created_at.timestamp() as u32)?;
+                writer.write_all(&[self.algorithm as u8])?;
+
+                let mut params = Vec::new();
+                self.public_params.to_writer(&mut params)?;
+                writer.write_u32::<BigEndian>(params.len() as u32)?;
+                writer.write_all(&params)?;
+
+                Ok(())
+            }
+
             pub fn sign<F>(
                 &self,
                 key: &impl $crate::types::SecretKeyTrait,
@@ -118,12 +137,14 @@ macro_rules! impl_public_key {
                 };
 
                 config
+                    .version($crate::packet::SignatureVersion::for_key(key))
                     .pub_alg(key.algorithm())
                     .hashed_subpackets(vec![$crate::packet::Subpacket::SignatureCreationTime(
                         chrono::Utc::now().trunc_subsecs(0),
                     )])
-                    .unhashed_subpackets(vec![$crate::packet::Subpacket::Issuer(key.key_id())])
+                    .unhashed_subpackets(vec![])
                     .build()?
+                    .with_issuer(key)
                     .sign_key(key, key_pw, &self)
             }
         }
@@ -137,7 +158,9 @@ macro_rules! impl_public_key {
                         self.to_writer_old(writer)
                     }
                     $crate::types::KeyVersion::V4 => self.to_writer_new(writer),
-                    $crate::types::KeyVersion::V5 => unimplemented_err!("V5 keys"),
+                    $crate::types::KeyVersion::V5 | $crate::types::KeyVersion::V6 => {
+                        self.to_writer_v6(writer)
+                    }
                 }
             }
         }
@@ -158,12 +181,30 @@ macro_rules! impl_public_key {
                 use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
                 use md5::Md5;
                 use sha1::{Digest, Sha1};
+                use sha2::Sha256;
 
                 use $crate::ser::Serialize;
                 use $crate::types::KeyVersion;
 
                 match self.version() {
-                    KeyVersion::V5 => unimplemented!("V5 keys"),
+                    KeyVersion::V5 | KeyVersion::V6 => {
+                        let version = self.version();
+                        let mut packet = vec![version as u8];
+                        self.to_writer_v6(&mut packet).expect("write to vec");
+
+                        let prefix = if version == KeyVersion::V5 {
+                            0x9A
+                        } else {
+                            0x9B
+                        };
+                        let mut h = Sha256::new();
+                        h.update(&[prefix]);
+                        h.write_u32::<BigEndian>(packet.len() as u32)
+                            .expect("write to hasher");
+                        h.update(&packet);
+
+                        h.finalize().to_vec()
+                    }
                     KeyVersion::V4 => {
                         // A one-octet version number (4).
                         let mut packet = vec![4, 0, 0, 0, 0];
@@ -202,7 +243,12 @@ macro_rules! impl_public_key {
                 use $crate::types::{KeyId, KeyVersion, PublicParams};
 
                 match self.version() {
-                    KeyVersion::V5 => unimplemented!("V5 keys"),
+                    KeyVersion::V5 | KeyVersion::V6 => {
+                        // Upper 64 bits
+                        let f = self.fingerprint();
+
+                        KeyId::from_slice(&f[..8]).expect("fixed size slice")
+                    }
                     KeyVersion::V4 => {
                         // Lower 64 bits
                         let f = self.fingerprint();
@@ -224,6 +270,10 @@ macro_rules! impl_public_key {
             fn algorithm(&self) -> $crate::crypto::public_key::PublicKeyAlgorithm {
                 self.algorithm
             }
+
+            fn version(&self) -> $crate::types::KeyVersion {
+                self.version
+            }
         }
 
         impl $crate::types::PublicKeyTrait for $name {
@@ -249,8 +299,8 @@ macro_rules! impl_public_key {
                     PublicParams::EdDSA { ref curve, ref q } => {
                         $crate::crypto::eddsa::verify(curve, q.as_bytes(), hash, hashed, sig)
                     }
-                    PublicParams::ECDSA { ref curve, .. } => {
-                        unimplemented_err!("verify ECDSA: {:?}", curve);
+                    PublicParams::ECDSA { ref curve, ref p } => {
+                        $crate::crypto::ecdsa::verify(curve, p.as_bytes(), hash, hashed, sig)
                     }
                     PublicParams::ECDH {
                         ref curve,
@@ -263,9 +313,28 @@ macro_rules! impl_public_key {
                     PublicParams::Elgamal { .. } => {
                         unimplemented_err!("verify Elgamal");
                     }
-                    PublicParams::DSA { .. } => {
-                        unimplemented_err!("verify DSA");
+                    PublicParams::Ed25519 { ref public } => {
+                        $crate::crypto::ed25519::verify(public, hash, hashed, sig)
                     }
+                    PublicParams::Ed448 { ref public } => {
+                        $crate::crypto::ed448::verify(public, hash, hashed, sig)
+                    }
+                    PublicParams::X25519 { .. } => bail!("X25519 is only used for encryption"),
+                    PublicParams::X448 { .. } => bail!("X448 is only used for encryption"),
+                    PublicParams::DSA {
+                        ref p,
+                        ref q,
+                        ref g,
+                        ref y,
+                    } => $crate::crypto::dsa::verify(
+                        p.as_bytes(),
+                        q.as_bytes(),
+                        g.as_bytes(),
+                        y.as_bytes(),
+                        hash,
+                        hashed,
+                        sig,
+                    ),
                 }
             }
 
@@ -298,6 +367,24 @@ macro_rules! impl_public_key {
                     ),
                     PublicParams::Elgamal { .. } => unimplemented_err!("encryption with Elgamal"),
                     PublicParams::DSA { .. } => bail!("DSA is only used for signing"),
+                    PublicParams::X25519 { ref public } => {
+                        // the values are fixed size octet strings, not MPIs
+                        let res = $crate::crypto::x25519::encrypt(rng, public, plain)?;
+                        return Ok(res
+                            .iter()
+                            .map(|v| $crate::types::Mpi::from_slice(&v[..]))
+                            .collect::<Vec<_>>());
+                    }
+                    PublicParams::X448 { ref public } => {
+                        // the values are fixed size octet strings, not MPIs
+                        let res = $crate::crypto::x448::encrypt(rng, public, plain)?;
+                        return Ok(res
+                            .iter()
+                            .map(|v| $crate::types::Mpi::from_slice(&v[..]))
+                            .collect::<Vec<_>>());
+                    }
+                    PublicParams::Ed25519 { .. } => bail!("Ed25519 is only used for signing"),
+                    PublicParams::Ed448 { .. } => bail!("Ed448 is only used for signing"),
                 }?;
 
                 Ok(res
@@ -315,8 +402,18 @@ macro_rules! impl_public_key {
                 let mut key_buf = Vec::new();
                 self.to_writer(&mut key_buf)?;
 
-                // old style packet header for the key
-                writer.write_all(&[0x99, (key_buf.len() >> 8) as u8, key_buf.len() as u8])?;
+                if self.version == $crate::types::KeyVersion::V5 {
+                    // v5 keys are prefixed with 0x9A and a four-octet length
+                    writer.write_all(&[0x9A])?;
+                    writer.write_all(&(key_buf.len() as u32).to_be_bytes())?;
+                } else if self.version == $crate::types::KeyVersion::V6 {
+                    // v6 keys are prefixed with 0x9B and a four-octet length
+                    writer.write_all(&[0x9B])?;
+                    writer.write_all(&(key_buf.len() as u32).to_be_bytes())?;
+                } else {
+                    // old style packet header for the key
+                    writer.write_all(&[0x99, (key_buf.len() >> 8) as u8, key_buf.len() as u8])?;
+                }
                 writer.write_all(&key_buf)?;
 
                 Ok(())
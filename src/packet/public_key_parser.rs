@@ -67,6 +67,38 @@ named!(ecdh<PublicParams>, do_parse!(
     })
 ));
 
+// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-algorithm-specific-part-for-x2
+#[rustfmt::skip]
+named!(x25519<PublicParams>, do_parse!(
+    // the native public key
+       public: take!(32)
+    >> (PublicParams::X25519 { public: public.to_vec() })
+));
+
+// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-algorithm-specific-part-for-ed2
+#[rustfmt::skip]
+named!(ed25519<PublicParams>, do_parse!(
+    // the native public key
+       public: take!(32)
+    >> (PublicParams::Ed25519 { public: public.to_vec() })
+));
+
+// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-algorithm-specific-part-for-x4
+#[rustfmt::skip]
+named!(x448<PublicParams>, do_parse!(
+    // the native public key
+       public: take!(56)
+    >> (PublicParams::X448 { public: public.to_vec() })
+));
+
+// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-algorithm-specific-part-for-ed4
+#[rustfmt::skip]
+named!(ed448<PublicParams>, do_parse!(
+    // the native public key
+       public: take!(57)
+    >> (PublicParams::Ed448 { public: public.to_vec() })
+));
+
 #[rustfmt::skip]
 named!(elgamal<PublicParams>, do_parse!(
     // MPI of Elgamal prime p
@@ -105,7 +137,11 @@ named_args!(pub parse_pub_fields(typ: PublicKeyAlgorithm) <PublicParams>, switch
     PublicKeyAlgorithm::ECDH       => call!(ecdh)    |
     PublicKeyAlgorithm::Elgamal    |
     PublicKeyAlgorithm::ElgamalSign => call!(elgamal) |
-    PublicKeyAlgorithm::EdDSA       => call!(eddsa)
+    PublicKeyAlgorithm::EdDSA       => call!(eddsa)   |
+    PublicKeyAlgorithm::X25519      => call!(x25519)  |
+    PublicKeyAlgorithm::Ed25519     => call!(ed25519) |
+    PublicKeyAlgorithm::X448        => call!(x448)    |
+    PublicKeyAlgorithm::Ed448       => call!(ed448)
     // &PublicKeyAlgorithm::DiffieHellman =>
 ));
 
@@ -116,6 +152,17 @@ named_args!(new_public_key_parser<'a>(key_ver: &'a KeyVersion) <(KeyVersion, Pub
     >> (*key_ver, alg, created_at, None, params)
 ));
 
+// Parse a v5 or v6 key, which prefix the key material with its length.
+// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-version-6-public-keys
+named_args!(v6_public_key_parser<'a>(key_ver: &'a KeyVersion) <(KeyVersion, PublicKeyAlgorithm, DateTime<Utc>, Option<u16>, PublicParams)>, do_parse!(
+       created_at: map!(be_u32, |v| Utc.timestamp(i64::from(v), 0))
+    >>        alg: map_opt!(be_u8, |v| PublicKeyAlgorithm::from_u8(v))
+    // four-octet scalar octet count for the following public key material
+    >>        len: be_u32
+    >>     params: flat_map!(take!(len), call!(parse_pub_fields, alg))
+    >> (*key_ver, alg, created_at, None, params)
+));
+
 named_args!(old_public_key_parser<'a>(key_ver: &'a KeyVersion) <(KeyVersion, PublicKeyAlgorithm, DateTime<Utc>, Option<u16>, PublicParams)>, do_parse!(
         created_at: map!(be_u32, |v| Utc.timestamp(i64::from(v), 0))
     >>         exp: be_u16
@@ -138,6 +185,12 @@ named!(pub(crate) parse<(KeyVersion, PublicKeyAlgorithm, DateTime<Utc>, Option<u
                         ) |
                         &KeyVersion::V4 => call!(
                             new_public_key_parser, &key_ver
+                        ) |
+                        &KeyVersion::V5 => call!(
+                            v6_public_key_parser, &key_ver
+                        ) |
+                        &KeyVersion::V6 => call!(
+                            v6_public_key_parser, &key_ver
                         )
         )
     >> (key)
@@ -28,9 +28,15 @@ macro_rules! impl_secret_key {
                 packet_version: $crate::types::Version,
                 input: &[u8],
             ) -> $crate::errors::Result<Self> {
+                use $crate::types::{KeyVersion, SecretParams};
+
                 let (_, details) = $crate::packet::secret_key_parser::parse(input)?;
                 let (version, algorithm, created_at, expiration, public_params, secret_params) =
                     details;
+                let secret_params = match version {
+                    KeyVersion::V6 => SecretParams::from_slice_v6(secret_params, algorithm)?,
+                    _ => SecretParams::from_slice(secret_params, algorithm)?,
+                };
                 Ok($name {
                     details: $crate::packet::$details {
                         packet_version,
@@ -72,10 +78,36 @@ macro_rules! impl_secret_key {
             where
                 F: FnOnce() -> String,
             {
-                let plain = ciphertext.unlock(pw, self.details.algorithm)?;
+                let plain = self.unlock_params(pw, ciphertext)?;
                 self.repr_from_plaintext(&plain)
             }
 
+            fn unlock_params<F>(
+                &self,
+                pw: F,
+                ciphertext: &$crate::types::EncryptedSecretParams,
+            ) -> $crate::errors::Result<$crate::types::PlainSecretParams>
+            where
+                F: FnOnce() -> String,
+            {
+                let aad = match ciphertext.aead_algorithm() {
+                    Some(_) => self.aead_associated_data()?,
+                    None => Vec::new(),
+                };
+                ciphertext.unlock(pw, self.details.algorithm, &aad)
+            }
+
+            /// Returns the data that AEAD protected secret key material is bound to, the
+            /// packet header octet followed by the public key.
+            pub(crate) fn aead_associated_data(&self) -> $crate::errors::Result<Vec<u8>> {
+                use $crate::ser::Serialize;
+
+                let mut aad = vec![0xC0 | $tag as u8];
+                self.details.to_writer(&mut aad)?;
+
+                Ok(aad)
+            }
+
             fn repr_from_plaintext(
                 &self,
                 plaintext: &$crate::types::PlainSecretParams,
@@ -87,9 +119,98 @@ macro_rules! impl_secret_key {
                 &self.secret_params
             }
 
+            /// Returns the serial number of the smartcard holding the secret key material,
+            /// if the key is a GnuPG `gnu-divert-to-card` stub.
+            ///
+            /// Such keys can be used with an [`ExternalSigner`](crate::composed::ExternalSigner).
+            pub fn card_serial(&self) -> Option<&[u8]> {
+                match self.secret_params {
+                    $crate::types::SecretParams::Stub(
+                        $crate::types::SecretKeyStub::DivertToCard { ref serial },
+                    ) => Some(serial),
+                    _ => None,
+                }
+            }
+
+            /// Replaces the secret key material with a stub, so only the public material remains.
+            ///
+            /// Operations that need the secret material fail with
+            /// [`Error::SecretKeyNotPresent`](crate::errors::Error::SecretKeyNotPresent) afterwards.
+            pub fn remove_secret(&mut self) {
+                use zeroize::Zeroize;
+
+                self.secret_params.zeroize();
+                self.secret_params =
+                    $crate::types::SecretParams::Stub($crate::types::SecretKeyStub::GnuDummy);
+            }
+
+            /// Decrypts the secret key material using `old_pw`, and encrypts it again with
+            /// `new_pw` and the given protection settings.
+            ///
+            /// If `new_pw` is `None`, the material is stored unencrypted. Otherwise it is
+            /// normalized to Unicode NFC first, see
+            /// [`normalize_passphrase`](crate::types::normalize_passphrase).
+            /// Stubs are left unchanged.
+            pub fn change_passphrase<R, F>(
+                &mut self,
+                rng: &mut R,
+                old_pw: F,
+                new_pw: Option<&str>,
+                s2k_params: &$crate::types::S2kParams,
+            ) -> $crate::errors::Result<()>
+            where
+                R: rand::CryptoRng + rand::Rng,
+                F: FnOnce() -> String,
+            {
+                use $crate::types::SecretParams;
+
+                let plain = match self.secret_params {
+                    SecretParams::Plain(ref k) => k.clone(),
+                    SecretParams::Encrypted(ref k) => self.unlock_params(old_pw, k)?,
+                    SecretParams::Stub(_) => return Ok(()),
+                };
+
+                self.secret_params = match new_pw {
+                    Some(new_pw) => SecretParams::Encrypted(plain.protect(
+                        rng,
+                        &$crate::types::normalize_passphrase(new_pw),
+                        s2k_params,
+                        self.version(),
+                    )?),
+                    None => SecretParams::Plain(plain),
+                };
+
+                Ok(())
+            }
+
+            /// Decrypts the secret key material using `pw`, and keeps it unencrypted in memory,
+            /// so later operations do not need the passphrase.
+            ///
+            /// Unencrypted keys are left unchanged, stubs fail with
+            /// [`Error::SecretKeyNotPresent`](crate::errors::Error::SecretKeyNotPresent).
+            pub fn decrypt_secret<F>(&mut self, pw: F) -> $crate::errors::Result<()>
+            where
+                F: FnOnce() -> String,
+            {
+                use $crate::types::SecretParams;
+
+                let plain = match self.secret_params {
+                    SecretParams::Plain(_) => return Ok(()),
+                    SecretParams::Encrypted(ref k) => self.unlock_params(pw, k)?,
+                    SecretParams::Stub(_) => {
+                        return Err($crate::errors::Error::SecretKeyNotPresent {
+                            key_id: $crate::types::KeyTrait::key_id(self),
+                        })
+                    }
+                };
+                self.secret_params = SecretParams::Plain(plain);
+
+                Ok(())
+            }
+
             /// Checks if we should expect a SHA1 checksum in the encrypted part.
             pub fn has_sha1_checksum(&self) -> bool {
-                self.secret_params.string_to_key_id() == 254
+                self.secret_params.string_to_key_id() == $crate::types::S2K_USAGE_SHA1
             }
 
             fn to_writer_old<W: std::io::Write>(
@@ -116,6 +237,16 @@ macro_rules! impl_secret_key {
                 Ok(())
             }
 
+            fn to_writer_v6<W: std::io::Write>(
+                &self,
+                writer: &mut W,
+            ) -> $crate::errors::Result<()> {
+                self.details.to_writer_v6(writer)?;
+                self.secret_params.to_writer_v6(writer)?;
+
+                Ok(())
+            }
+
             pub fn sign<F>(
                 &self,
                 key: &impl $crate::types::SecretKeyTrait,
@@ -137,12 +268,14 @@ macro_rules! impl_secret_key {
                 };
 
                 config
+                    .version($crate::packet::SignatureVersion::for_key(key))
                     .pub_alg(key.algorithm())
                     .hashed_subpackets(vec![$crate::packet::Subpacket::SignatureCreationTime(
                         chrono::Utc::now().trunc_subsecs(0),
                     )])
-                    .unhashed_subpackets(vec![$crate::packet::Subpacket::Issuer(key.key_id())])
+                    .unhashed_subpackets(vec![])
                     .build()?
+                    .with_issuer(key)
                     .sign_key(key, key_pw, &self)
             }
         }
@@ -161,6 +294,9 @@ macro_rules! impl_secret_key {
                 let decrypted = match self.secret_params {
                     SecretParams::Plain(ref k) => self.repr_from_plaintext(k),
                     SecretParams::Encrypted(ref k) => self.repr_from_ciphertext(pw, k),
+                    SecretParams::Stub(_) => Err($crate::errors::Error::SecretKeyNotPresent {
+                        key_id: $crate::types::KeyTrait::key_id(self),
+                    }),
                 }?;
 
                 work(&decrypted)
@@ -186,10 +322,36 @@ macro_rules! impl_secret_key {
                             $crate::crypto::rsa::sign(priv_key, hash, data)
                         }
                         SecretKeyRepr::DSA(_) => unimplemented_err!("sign DSA"),
-                        SecretKeyRepr::ECDSA => unimplemented_err!("sign ECDSA"),
-                        SecretKeyRepr::ECDH(_) => {
+                        SecretKeyRepr::ECDSA(ref priv_key) => match self.public_params() {
+                            PublicParams::ECDSA { ref curve, ref p } => {
+                                $crate::crypto::ecdsa::sign(
+                                    curve,
+                                    p.as_bytes(),
+                                    priv_key,
+                                    hash,
+                                    data,
+                                )
+                            }
+                            _ => unreachable!("inconsistent key state"),
+                        },
+                        SecretKeyRepr::NistECDSA(ref priv_key) => match self.public_params() {
+                            PublicParams::ECDSA { ref curve, ref p } => {
+                                $crate::crypto::ecdsa::sign_nist(
+                                    curve,
+                                    p.as_bytes(),
+                                    priv_key,
+                                    hash,
+                                    data,
+                                )
+                            }
+                            _ => unreachable!("inconsistent key state"),
+                        },
+                        SecretKeyRepr::ECDH(_) | SecretKeyRepr::NistECDH(_) => {
                             bail!("ECDH can not be used to for signing operations")
                         }
+                        SecretKeyRepr::Elgamal(_) => {
+                            bail!("Elgamal can not be used to for signing operations")
+                        }
                         SecretKeyRepr::EdDSA(ref priv_key) => match self.public_params() {
                             PublicParams::EdDSA { ref curve, ref q } => match *curve {
                                 ECCCurve::Ed25519 => {
@@ -199,14 +361,38 @@ macro_rules! impl_secret_key {
                             },
                             _ => unreachable!("inconsistent key state"),
                         },
+                        SecretKeyRepr::Ed25519(ref priv_key) => match self.public_params() {
+                            PublicParams::Ed25519 { ref public } => {
+                                $crate::crypto::ed25519::sign(public, priv_key, hash, data)
+                            }
+                            _ => unreachable!("inconsistent key state"),
+                        },
+                        SecretKeyRepr::Ed448(ref priv_key) => match self.public_params() {
+                            PublicParams::Ed448 { ref public } => {
+                                $crate::crypto::ed448::sign(public, priv_key, hash, data)
+                            }
+                            _ => unreachable!("inconsistent key state"),
+                        },
+                        SecretKeyRepr::X25519(_) => {
+                            bail!("X25519 can not be used to for signing operations")
+                        }
+                        SecretKeyRepr::X448(_) => {
+                            bail!("X448 can not be used to for signing operations")
+                        }
                     }?;
 
-                    // strip leading zeros, to match parse results from MPIs
-                    signature = Some(
-                        sig.iter()
+                    signature = Some(match *priv_key {
+                        // native signatures are fixed size octet strings, not MPIs
+                        SecretKeyRepr::Ed25519(_) | SecretKeyRepr::Ed448(_) => sig
+                            .iter()
+                            .map(|v| $crate::types::Mpi::from_slice(&v[..]))
+                            .collect::<Vec<_>>(),
+                        // strip leading zeros, to match parse results from MPIs
+                        _ => sig
+                            .iter()
                             .map(|v| $crate::types::Mpi::from_raw_slice(&v[..]))
                             .collect::<Vec<_>>(),
-                    );
+                    });
                     Ok(())
                 })?;
 
@@ -228,6 +414,7 @@ macro_rules! impl_secret_key {
                     }
                     $crate::types::KeyVersion::V4 => self.to_writer_new(writer),
                     $crate::types::KeyVersion::V5 => unimplemented_err!("V5 keys"),
+                    $crate::types::KeyVersion::V6 => self.to_writer_v6(writer),
                 }
             }
         }
@@ -255,6 +442,10 @@ macro_rules! impl_secret_key {
             fn algorithm(&self) -> $crate::crypto::public_key::PublicKeyAlgorithm {
                 self.details.algorithm()
             }
+
+            fn version(&self) -> $crate::types::KeyVersion {
+                self.details.version()
+            }
         }
 
         impl $crate::types::PublicKeyTrait for $name {
@@ -279,16 +470,7 @@ macro_rules! impl_secret_key {
                 &self,
                 writer: &mut impl std::io::Write,
             ) -> $crate::errors::Result<()> {
-                use $crate::ser::Serialize;
-
-                let mut key_buf = Vec::new();
-                self.details.to_writer(&mut key_buf)?;
-
-                // old style packet header for the key
-                writer.write_all(&[0x99, (key_buf.len() >> 8) as u8, key_buf.len() as u8])?;
-                writer.write_all(&key_buf)?;
-
-                Ok(())
+                $crate::types::PublicKeyTrait::to_writer_old(&self.details, writer)
             }
         }
     };
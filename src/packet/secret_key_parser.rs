@@ -4,26 +4,39 @@ use num_traits::FromPrimitive;
 
 use crate::crypto::PublicKeyAlgorithm;
 use crate::packet::public_key_parser::parse_pub_fields;
-use crate::types::{KeyVersion, PublicParams, SecretParams};
+use crate::types::{KeyVersion, PublicParams};
 
 // Parse the whole private key, both public and private fields.
+// The private fields are parsed outside of nom, so their errors are kept.
 #[rustfmt::skip]
-named_args!(parse_pub_priv_fields(typ: PublicKeyAlgorithm) <(PublicParams, SecretParams)>, do_parse!(
+named_args!(parse_pub_priv_fields(typ: PublicKeyAlgorithm) <(PublicParams, &[u8])>, do_parse!(
       pub_params: call!(parse_pub_fields, typ)
-  >> priv_params: map_res!(rest, |v| SecretParams::from_slice(v, typ))
+  >> priv_params: rest
   >> (pub_params, priv_params)
 ));
 
 #[rustfmt::skip]
-named_args!(new_private_key_parser<'a>(key_ver: &'a KeyVersion) <(KeyVersion, PublicKeyAlgorithm, DateTime<Utc>, Option<u16>, PublicParams, SecretParams)>, do_parse!(
+named_args!(new_private_key_parser<'a>(key_ver: &'a KeyVersion) <(KeyVersion, PublicKeyAlgorithm, DateTime<Utc>, Option<u16>, PublicParams, &'a [u8])>, do_parse!(
         created_at: map!(be_u32, |v| Utc.timestamp(i64::from(v), 0))
     >>         alg: map_opt!(be_u8, |v| PublicKeyAlgorithm::from_u8(v))
     >>      params: call!(parse_pub_priv_fields, alg)
     >> (*key_ver, alg, created_at, None, params.0, params.1)
 ));
 
+// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-secret-key-packet-formats
 #[rustfmt::skip]
-named_args!(old_private_key_parser<'a>(key_ver: &'a KeyVersion) <(KeyVersion, PublicKeyAlgorithm, DateTime<Utc>, Option<u16>, PublicParams, SecretParams)>, do_parse!(
+named_args!(v6_private_key_parser<'a>(key_ver: &'a KeyVersion) <(KeyVersion, PublicKeyAlgorithm, DateTime<Utc>, Option<u16>, PublicParams, &'a [u8])>, do_parse!(
+        created_at: map!(be_u32, |v| Utc.timestamp(i64::from(v), 0))
+    >>         alg: map_opt!(be_u8, |v| PublicKeyAlgorithm::from_u8(v))
+    // four-octet scalar octet count for the following public key material
+    >>         len: be_u32
+    >>  pub_params: flat_map!(take!(len), call!(parse_pub_fields, alg))
+    >> priv_params: rest
+    >> (*key_ver, alg, created_at, None, pub_params, priv_params)
+));
+
+#[rustfmt::skip]
+named_args!(old_private_key_parser<'a>(key_ver: &'a KeyVersion) <(KeyVersion, PublicKeyAlgorithm, DateTime<Utc>, Option<u16>, PublicParams, &'a [u8])>, do_parse!(
        created_at: map!(be_u32, |v| Utc.timestamp(i64::from(v), 0))
     >>        exp: be_u16
     >>        alg: map_opt!(be_u8, PublicKeyAlgorithm::from_u8)
@@ -34,7 +47,7 @@ named_args!(old_private_key_parser<'a>(key_ver: &'a KeyVersion) <(KeyVersion, Pu
 // Parse a private key packet (Tag 5)
 // Ref: https://tpools.ietf.org/html/rfc4880.html#section-5.5.1.3
 #[rustfmt::skip]
-named!(pub(crate) parse<(KeyVersion, PublicKeyAlgorithm, DateTime<Utc>, Option<u16>, PublicParams, SecretParams)>, do_parse!(
+named!(pub(crate) parse<(KeyVersion, PublicKeyAlgorithm, DateTime<Utc>, Option<u16>, PublicParams, &'a [u8])>, do_parse!(
        key_ver: map_opt!(be_u8, KeyVersion::from_u8)
     >>     key: switch!(value!(&key_ver),
                        &KeyVersion::V2 => call!(
@@ -45,6 +58,9 @@ named!(pub(crate) parse<(KeyVersion, PublicKeyAlgorithm, DateTime<Utc>, Option<u
                        ) |
                        &KeyVersion::V4 => call!(
                            new_private_key_parser, &key_ver
+                       ) |
+                       &KeyVersion::V6 => call!(
+                           v6_private_key_parser, &key_ver
                        )
                 )
     >> (key)
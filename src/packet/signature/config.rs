@@ -1,14 +1,18 @@
-use std::fmt;
+use std::{fmt, io};
 
 use byteorder::{BigEndian, ByteOrder};
 use chrono::{DateTime, Utc};
+use rand::{thread_rng, CryptoRng, Rng};
 
-use crate::crypto::hash::{HashAlgorithm, Hasher};
+use crate::crypto::hash::{HashAlgorithm, HashWriter, Hasher, TextHashWriter};
 use crate::crypto::public_key::PublicKeyAlgorithm;
 use crate::errors::Result;
-use crate::packet::{Signature, SignatureType, SignatureVersion, Subpacket};
+use crate::packet::{
+    IssuerPlacement, OnePassSignature, Signature, SignatureType, SignatureVersion, Subpacket,
+};
 use crate::ser::Serialize;
-use crate::types::{KeyId, PublicKeyTrait, SecretKeyTrait, Tag};
+use crate::types::{KeyId, KeyTrait, KeyVersion, Mpi, PublicKeyTrait, SecretKeyTrait, Tag};
+use crate::util::CountingWriter;
 
 #[derive(Clone, PartialEq, Eq, Builder)]
 pub struct SignatureConfig {
@@ -28,6 +32,10 @@ pub struct SignatureConfig {
     pub created: Option<DateTime<Utc>>,
     #[builder(default)]
     pub issuer: Option<KeyId>,
+
+    // only set on V6 signatures
+    #[builder(default)]
+    pub salt: Vec<u8>,
 }
 
 impl SignatureConfig {
@@ -48,28 +56,199 @@ impl SignatureConfig {
             unhashed_subpackets,
             issuer: None,
             created: None,
+            salt: Vec::new(),
+        }
+    }
+
+    /// Creates a new hasher for this signature, which for v6 signatures already
+    /// contains the salt.
+    pub fn new_hasher(&self) -> Result<Box<dyn Hasher>> {
+        let mut hasher = self.hash_alg.new_hasher()?;
+
+        if self.version == SignatureVersion::V6 {
+            ensure_eq!(
+                Some(self.salt.len()),
+                self.hash_alg.salt_size(),
+                "invalid salt for {:?}",
+                self.hash_alg
+            );
+
+            hasher.update(&self.salt);
+        }
+
+        Ok(hasher)
+    }
+
+    /// Adds the subpackets identifying `key` as the issuer, see [`Subpacket::issuer_subpackets`],
+    /// replacing any issuer subpackets in both areas.
+    pub fn set_issuer(&mut self, key: &impl KeyTrait, placement: IssuerPlacement) {
+        self.hashed_subpackets.retain(|p| !p.is_issuer());
+        self.unhashed_subpackets.retain(|p| !p.is_issuer());
+
+        let issuer = Subpacket::issuer_subpackets(key);
+        match placement {
+            IssuerPlacement::Hashed => self.hashed_subpackets.extend(issuer),
+            IssuerPlacement::Unhashed => self.unhashed_subpackets.extend(issuer),
+        }
+    }
+
+    /// Same as [`set_issuer`], using the default placement in the hashed area.
+    ///
+    /// [`set_issuer`]: SignatureConfig::set_issuer
+    pub fn with_issuer(mut self, key: &impl KeyTrait) -> Self {
+        self.set_issuer(key, IssuerPlacement::default());
+        self
+    }
+
+    /// Generates the salt of a v6 signature from `rng`, replacing any salt set before.
+    ///
+    /// The size of the salt depends on the hash algorithm. Signing generates a salt using
+    /// [`thread_rng`] if none was set, this allows using a different source of entropy,
+    /// e.g. a seeded RNG to create reproducible test vectors.
+    ///
+    /// [`thread_rng`]: rand::thread_rng
+    pub fn generate_salt<R: CryptoRng + Rng>(&mut self, rng: &mut R) -> Result<()> {
+        ensure_eq!(
+            self.version,
+            SignatureVersion::V6,
+            "only v6 signatures are salted"
+        );
+        let salt_size = match self.hash_alg.salt_size() {
+            Some(size) => size,
+            None => unsupported_err!("hash algorithm {:?} for v6 signatures", self.hash_alg),
+        };
+
+        let mut salt = vec![0u8; salt_size];
+        rng.fill(&mut salt[..]);
+        self.salt = salt;
+
+        Ok(())
+    }
+
+    /// Generates the salt from `rng` for v6 signatures, other versions are returned
+    /// unchanged.
+    pub(crate) fn salted<R: CryptoRng + Rng>(mut self, rng: &mut R) -> Result<Self> {
+        if self.version == SignatureVersion::V6 {
+            self.generate_salt(rng)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Generates a random salt for v6 signatures, unless one was set already.
+    pub(crate) fn ensure_salt(&mut self) -> Result<()> {
+        if self.version == SignatureVersion::V6 {
+            if self.salt.is_empty() {
+                self.generate_salt(&mut thread_rng())?;
+            }
+        } else {
+            ensure!(self.salt.is_empty(), "only v6 signatures are salted");
+        }
+
+        Ok(())
+    }
+
+    /// Creates the one-pass signature announcing the signature of `key` with this config.
+    ///
+    /// For v6 signatures the salt has to be generated before, it is part of the
+    /// one-pass signature.
+    pub fn one_pass_signature(&self, key: &impl KeyTrait) -> Result<OnePassSignature> {
+        match self.version {
+            SignatureVersion::V6 => OnePassSignature::from_details_v6(
+                self.typ,
+                self.hash_alg,
+                self.pub_alg,
+                self.salt.clone(),
+                key.fingerprint(),
+            ),
+            _ => Ok(OnePassSignature::from_details(
+                self.typ,
+                self.hash_alg,
+                self.pub_alg,
+                key.key_id(),
+            )),
         }
     }
 
     /// Sign the given data.
-    pub fn sign<F>(self, key: &impl SecretKeyTrait, key_pw: F, data: &[u8]) -> Result<Signature>
+    pub fn sign<F>(mut self, key: &impl SecretKeyTrait, key_pw: F, data: &[u8]) -> Result<Signature>
     where
         F: FnOnce() -> String,
     {
-        let mut hasher = self.hash_alg.new_hasher()?;
+        self.ensure_salt()?;
+        let mut hasher = self.new_hasher()?;
 
         self.hash_data_to_sign(&mut *hasher, data)?;
+
+        self.sign_hasher(key, key_pw, hasher)
+    }
+
+    /// Sign the data read from `data`, without buffering it.
+    ///
+    /// Only binary and text signatures are supported, see [`hash_data_to_sign`].
+    ///
+    /// [`hash_data_to_sign`]: SignatureConfig::hash_data_to_sign
+    pub fn sign_reader<F>(
+        mut self,
+        key: &impl SecretKeyTrait,
+        key_pw: F,
+        mut data: impl io::Read,
+    ) -> Result<Signature>
+    where
+        F: FnOnce() -> String,
+    {
+        ensure!(
+            self.typ == SignatureType::Binary || self.typ == SignatureType::Text,
+            "can not sign {:?} signatures over a reader",
+            self.typ
+        );
+
+        self.ensure_salt()?;
+        let mut hasher = self.new_hasher()?;
+
+        self.hash_data_reader(&mut *hasher, &mut data)?;
+
+        self.sign_hasher(key, key_pw, hasher)
+    }
+
+    /// Finishes the signature, after the data to sign was hashed into `hasher`.
+    pub(crate) fn sign_hasher<F>(
+        self,
+        key: &impl SecretKeyTrait,
+        key_pw: F,
+        mut hasher: Box<dyn Hasher>,
+    ) -> Result<Signature>
+    where
+        F: FnOnce() -> String,
+    {
         let len = self.hash_signature_data(&mut *hasher)?;
         hasher.update(&self.trailer(len));
 
         let hash = &hasher.finish()[..];
 
         let signed_hash_value = [hash[0], hash[1]];
-        let signature = key.create_signature(key_pw, self.hash_alg, hash)?;
+        let signature = self.create_signature(key, key_pw, hash)?;
 
         Ok(Signature::from_config(self, signed_hash_value, signature))
     }
 
+    /// Creates the signature over `hash`, and records the use of `key`, see
+    /// [`SecretKeyTrait::record_signature`].
+    fn create_signature<F>(
+        &self,
+        key: &impl SecretKeyTrait,
+        key_pw: F,
+        hash: &[u8],
+    ) -> Result<Vec<Mpi>>
+    where
+        F: FnOnce() -> String,
+    {
+        let signature = key.create_signature(key_pw, self.hash_alg, hash)?;
+        key.record_signature(self.typ, self.hash_alg, hash);
+
+        Ok(signature)
+    }
+
     /// Create a certificate siganture.
     pub fn sign_certificate<F>(
         self,
@@ -78,6 +257,22 @@ impl SignatureConfig {
         tag: Tag,
         id: &impl Serialize,
     ) -> Result<Signature>
+    where
+        F: FnOnce() -> String,
+    {
+        self.sign_third_party_certificate(key, key_pw, key, tag, id)
+    }
+
+    /// Create a certificate signature over a user id or attribute of `certified_key`,
+    /// made by a different key.
+    pub fn sign_third_party_certificate<F>(
+        mut self,
+        signer: &impl SecretKeyTrait,
+        key_pw: F,
+        certified_key: &impl PublicKeyTrait,
+        tag: Tag,
+        id: &impl Serialize,
+    ) -> Result<Signature>
     where
         F: FnOnce() -> String,
     {
@@ -87,28 +282,47 @@ impl SignatureConfig {
         );
         debug!("signing certificate {:#?}", self.typ);
 
-        let mut hasher = self.hash_alg.new_hasher()?;
-        let mut key_buf = Vec::new();
-        key.to_writer_old(&mut key_buf)?;
+        self.ensure_salt()?;
+        let mut hasher = self.new_hasher()?;
+        self.hash_certificate(&mut *hasher, certified_key, tag, id)?;
 
-        let mut packet_buf = Vec::new();
-        id.to_writer(&mut packet_buf)?;
+        self.sign_hasher(signer, key_pw, hasher)
+    }
 
-        hasher.update(&key_buf);
+    /// Hashes the key and the certified packet of a certificate signature.
+    ///
+    /// The packet is serialized directly into the hasher, so large user attributes
+    /// are not copied.
+    pub(crate) fn hash_certificate(
+        &self,
+        hasher: &mut dyn Hasher,
+        key: &impl PublicKeyTrait,
+        tag: Tag,
+        id: &impl Serialize,
+    ) -> Result<()> {
+        key.to_writer_old(&mut HashWriter(&mut *hasher))?;
 
         match self.version {
             SignatureVersion::V2 | SignatureVersion::V3 => {
                 // Nothing to do
             }
-            SignatureVersion::V4 | SignatureVersion::V5 => {
+            SignatureVersion::V4 | SignatureVersion::V5 | SignatureVersion::V6 => {
                 let prefix = match tag {
                     Tag::UserId => 0xB4,
                     Tag::UserAttribute => 0xD1,
                     _ => bail!("invalid tag for certificate validation: {:?}", tag),
                 };
 
+                // the length has to be known upfront, so serialize once without keeping the data
+                let mut counter = CountingWriter::default();
+                id.to_writer(&mut counter)?;
+                ensure!(
+                    counter.count <= u32::max_value() as usize,
+                    "certified packet too large"
+                );
+
                 let mut prefix_buf = [prefix, 0u8, 0u8, 0u8, 0u8];
-                BigEndian::write_u32(&mut prefix_buf[1..], packet_buf.len() as u32);
+                BigEndian::write_u32(&mut prefix_buf[1..], counter.count as u32);
 
                 // prefixes
                 hasher.update(&prefix_buf);
@@ -116,22 +330,14 @@ impl SignatureConfig {
         }
 
         // the packet content
-        hasher.update(&packet_buf);
-
-        let len = self.hash_signature_data(&mut *hasher)?;
-        hasher.update(&self.trailer(len));
-
-        let hash = &hasher.finish()[..];
-
-        let signed_hash_value = [hash[0], hash[1]];
-        let signature = key.create_signature(key_pw, self.hash_alg, hash)?;
+        id.to_writer(&mut HashWriter(hasher))?;
 
-        Ok(Signature::from_config(self, signed_hash_value, signature))
+        Ok(())
     }
 
     /// Sign a key binding.
     pub fn sign_key_binding<F>(
-        self,
+        mut self,
         signing_key: &impl SecretKeyTrait,
         key_pw: F,
         key: &impl PublicKeyTrait,
@@ -144,7 +350,8 @@ impl SignatureConfig {
             self, signing_key, key
         );
 
-        let mut hasher = self.hash_alg.new_hasher()?;
+        self.ensure_salt()?;
+        let mut hasher = self.new_hasher()?;
 
         // Signing Key
         {
@@ -166,14 +373,58 @@ impl SignatureConfig {
 
         let hash = &hasher.finish()[..];
         let signed_hash_value = [hash[0], hash[1]];
-        let signature = signing_key.create_signature(key_pw, self.hash_alg, hash)?;
+        let signature = self.create_signature(signing_key, key_pw, hash)?;
+
+        Ok(Signature::from_config(self, signed_hash_value, signature))
+    }
+
+    /// Signs a primary key binding, the back signature made by a signing subkey
+    /// over the primary key that it is bound to.
+    pub fn sign_primary_key_binding<F>(
+        mut self,
+        signing_key: &impl SecretKeyTrait,
+        key_pw: F,
+        primary_key: &impl PublicKeyTrait,
+    ) -> Result<Signature>
+    where
+        F: FnOnce() -> String,
+    {
+        debug!(
+            "signing primary key binding: {:#?} - {:#?} - {:#?}",
+            self, signing_key, primary_key
+        );
+
+        self.ensure_salt()?;
+        let mut hasher = self.new_hasher()?;
+
+        // Primary Key
+        {
+            let mut key_buf = Vec::new();
+            primary_key.to_writer_old(&mut key_buf)?;
+
+            hasher.update(&key_buf);
+        }
+        // Signing Subkey
+        {
+            let mut key_buf = Vec::new();
+            signing_key.to_writer_old(&mut key_buf)?;
+
+            hasher.update(&key_buf);
+        }
+
+        let len = self.hash_signature_data(&mut *hasher)?;
+        hasher.update(&self.trailer(len));
+
+        let hash = &hasher.finish()[..];
+        let signed_hash_value = [hash[0], hash[1]];
+        let signature = self.create_signature(signing_key, key_pw, hash)?;
 
         Ok(Signature::from_config(self, signed_hash_value, signature))
     }
 
     /// Signs a direct key signature or a revocation.
     pub fn sign_key<F>(
-        self,
+        mut self,
         signing_key: &impl SecretKeyTrait,
         key_pw: F,
         key: &impl PublicKeyTrait,
@@ -183,7 +434,8 @@ impl SignatureConfig {
     {
         debug!("signing key (revocation): {:#?} - {:#?}", self, key);
 
-        let mut hasher = self.hash_alg.new_hasher()?;
+        self.ensure_salt()?;
+        let mut hasher = self.new_hasher()?;
 
         {
             let mut key_buf = Vec::new();
@@ -197,7 +449,7 @@ impl SignatureConfig {
 
         let hash = &hasher.finish()[..];
         let signed_hash_value = [hash[0], hash[1]];
-        let signature = signing_key.create_signature(key_pw, self.hash_alg, hash)?;
+        let signature = self.create_signature(signing_key, key_pw, hash)?;
 
         Ok(Signature::from_config(self, signed_hash_value, signature))
     }
@@ -209,6 +461,17 @@ impl SignatureConfig {
 
     /// Calcluate the serialized version of this packet, but only the part relevant for hashing.
     pub fn hash_signature_data(&self, hasher: &mut dyn Hasher) -> Result<usize> {
+        self.hash_signature_data_with(hasher, None)
+    }
+
+    /// Like [`hash_signature_data`](SignatureConfig::hash_signature_data), `metadata` is
+    /// the mode, file name and date of the signed literal data, which v5 document
+    /// signatures include. Detached signatures use zeros in their place.
+    pub(crate) fn hash_signature_data_with(
+        &self,
+        hasher: &mut dyn Hasher,
+        metadata: Option<&[u8]>,
+    ) -> Result<usize> {
         match self.version {
             SignatureVersion::V2 | SignatureVersion::V3 => {
                 let mut buf = [0u8; 5];
@@ -226,7 +489,6 @@ impl SignatureConfig {
                 Ok(0)
             }
             SignatureVersion::V4 | SignatureVersion::V5 => {
-                // TODO: validate this is the right thing to do for v5
                 // TODO: reduce duplication with serialization code
 
                 let mut res = vec![
@@ -254,11 +516,75 @@ impl SignatureConfig {
 
                 hasher.update(&res);
 
+                let is_document =
+                    self.typ == SignatureType::Binary || self.typ == SignatureType::Text;
+                if self.version == SignatureVersion::V5 && is_document {
+                    // The format, file name and date of the literal data packet,
+                    // which are not part of the length in the trailer.
+                    // These are zero for detached signatures.
+                    hasher.update(metadata.unwrap_or(&[0u8; 6]));
+                }
+
+                Ok(res.len())
+            }
+            SignatureVersion::V6 => {
+                let mut res = vec![
+                    // version
+                    self.version as u8,
+                    // type
+                    self.typ as u8,
+                    // public algorithm
+                    self.pub_alg as u8,
+                    // hash algorithm
+                    self.hash_alg as u8,
+                    // will be filled with the four-octet length
+                    0u8,
+                    0u8,
+                    0u8,
+                    0u8,
+                ];
+
+                // hashed subpackets
+                let mut hashed_subpackets = Vec::new();
+                for packet in &self.hashed_subpackets {
+                    packet.to_writer(&mut hashed_subpackets)?;
+                }
+
+                BigEndian::write_u32(&mut res[4..8], hashed_subpackets.len() as u32);
+                res.extend(hashed_subpackets);
+
+                hasher.update(&res);
+
                 Ok(res.len())
             }
         }
     }
 
+    /// Hashes the signed data read from `data`, like [`hash_data_to_sign`].
+    ///
+    /// [`hash_data_to_sign`]: SignatureConfig::hash_data_to_sign
+    pub(crate) fn hash_data_reader(
+        &self,
+        hasher: &mut dyn Hasher,
+        data: &mut impl io::Read,
+    ) -> Result<()> {
+        io::copy(data, &mut self.data_writer(hasher))?;
+
+        Ok(())
+    }
+
+    /// Returns a writer, that hashes the signed data of binary or text signatures into
+    /// `hasher`, converting line endings for text signatures.
+    pub(crate) fn data_writer<'a>(&self, hasher: &'a mut dyn Hasher) -> Box<dyn io::Write + 'a> {
+        if self.typ == SignatureType::Text {
+            Box::new(TextHashWriter::new(hasher))
+        } else {
+            Box::new(HashWriter(hasher))
+        }
+    }
+
+    /// Hashes the signed data, for text signatures the line endings are converted to `\r\n`
+    /// first, see [`TextHashWriter`].
     pub fn hash_data_to_sign(&self, hasher: &mut dyn Hasher, data: &[u8]) -> Result<usize> {
         match self.typ {
             SignatureType::Binary => {
@@ -266,8 +592,7 @@ impl SignatureConfig {
                 Ok(data.len())
             }
             SignatureType::Text => {
-                // assumes that the passed in text was already valid utf8 and normalized
-                hasher.update(data);
+                io::Write::write_all(&mut TextHashWriter::new(hasher), data)?;
                 Ok(data.len())
             }
             SignatureType::Standalone => {
@@ -278,6 +603,7 @@ impl SignatureConfig {
             | SignatureType::CertPersona
             | SignatureType::CertCasual
             | SignatureType::CertPositive
+            | SignatureType::Attestation
             | SignatureType::CertRevocation => {
                 unimplemented_err!("{:?}", self.typ);
             }
@@ -302,11 +628,22 @@ impl SignatureConfig {
                 // Nothing to do
                 Vec::new()
             }
-            SignatureVersion::V4 | SignatureVersion::V5 => {
+            SignatureVersion::V4 => {
                 let mut trailer = vec![0x04, 0xFF, 0, 0, 0, 0];
                 BigEndian::write_u32(&mut trailer[2..], len as u32);
                 trailer
             }
+            SignatureVersion::V5 => {
+                // eight-octet length
+                let mut trailer = vec![0x05, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0];
+                BigEndian::write_u64(&mut trailer[2..], len as u64);
+                trailer
+            }
+            SignatureVersion::V6 => {
+                let mut trailer = vec![0x06, 0xFF, 0, 0, 0, 0];
+                BigEndian::write_u32(&mut trailer[2..], len as u32);
+                trailer
+            }
         }
     }
 
@@ -324,6 +661,7 @@ impl SignatureConfig {
             | SignatureType::CertPersona
             | SignatureType::CertCasual
             | SignatureType::CertPositive
+            | SignatureType::Attestation
             | SignatureType::CertRevocation => true,
             _ => false,
         }
@@ -340,6 +678,14 @@ impl SignatureConfig {
         })
     }
 
+    /// Returns the version and fingerprint of the issuer, from the `IssuerFingerprint` subpacket.
+    pub fn issuer_fingerprint(&self) -> Option<(KeyVersion, &[u8])> {
+        self.subpackets().find_map(|p| match p {
+            Subpacket::IssuerFingerprint(version, fp) => Some((*version, &fp[..])),
+            _ => None,
+        })
+    }
+
     pub fn issuer(&self) -> Option<&KeyId> {
         if self.issuer.is_some() {
             return self.issuer.as_ref();
@@ -361,6 +707,7 @@ impl fmt::Debug for SignatureConfig {
             .field("hash_alg", &self.hash_alg)
             .field("created", &self.created)
             .field("issuer", &self.issuer)
+            .field("salt", &hex::encode(&self.salt))
             .field("unhashed_subpackets", &self.unhashed_subpackets)
             .field("hashed_subpackets", &self.hashed_subpackets)
             .finish()
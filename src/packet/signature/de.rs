@@ -11,7 +11,7 @@ use crate::crypto::hash::HashAlgorithm;
 use crate::crypto::public_key::PublicKeyAlgorithm;
 use crate::crypto::sym::SymmetricKeyAlgorithm;
 use crate::de::Deserialize;
-use crate::errors::Result;
+use crate::errors::{self, Result};
 use crate::packet::signature::types::*;
 use crate::types::{
     mpi, CompressionAlgorithm, KeyId, KeyVersion, Mpi, MpiRef, RevocationKey, RevocationKeyClass,
@@ -19,11 +19,29 @@ use crate::types::{
 };
 use crate::util::{clone_into_array, packet_length, read_string};
 
+/// Maximum number of subpackets accepted in a single subpacket area.
+const MAX_SUBPACKETS: usize = 512;
+
+/// Maximum nesting level of embedded signatures.
+const MAX_EMBEDDED_DEPTH: usize = 1;
+
 impl Deserialize for Signature {
     /// Parses a `Signature` packet from the given slice.
     fn from_slice(packet_version: Version, input: &[u8]) -> Result<Self> {
         let (_, pk) = parse(input, packet_version)?;
 
+        // attested certifications are a list of digests, made with the signature's hash
+        let size = pk.config.hash_alg.digest_size();
+        for p in &pk.config.hashed_subpackets {
+            if let Subpacket::AttestedCertifications(digests) = p {
+                ensure!(
+                    size > 0 && digests.len() % size == 0,
+                    "invalid attested certifications length {}",
+                    digests.len()
+                );
+            }
+        }
+
         Ok(pk)
     }
 }
@@ -134,12 +152,11 @@ named!(trust_signature<Subpacket>, do_parse!(
     >> (Subpacket::TrustSignature(depth, value))
 ));
 
-// Parse a regular expression subpacket.
-// Ref: https://tools.ietf.org/html/rfc4880.html#section-5.2.3.14
-#[rustfmt::skip]
-named!(regular_expression<Subpacket>, map!(
-    map!(rest, read_string), Subpacket::RegularExpression
-));
+/// Parse a regular expression subpacket, keeping the raw bytes including the terminating NUL.
+/// Ref: https://tools.ietf.org/html/rfc4880.html#section-5.2.3.14
+fn regular_expression(body: &[u8]) -> IResult<&[u8], Subpacket> {
+    Ok((&b""[..], Subpacket::RegularExpression(body.to_vec())))
+}
 
 // Parse a revocation key subpacket
 // Ref: https://tools.ietf.org/html/rfc4880.html#section-5.2.3.15
@@ -240,18 +257,52 @@ named!(sig_target<Subpacket>, do_parse!(
 
 // Ref: https://tools.ietf.org/html/rfc4880.html#section-5.2.3.26
 #[rustfmt::skip]
-named!(embedded_sig<Subpacket>, map!(call!(parse, Version::New), |sig| {
-    Subpacket::EmbeddedSignature(Box::new(sig))
-}));
+fn embedded_sig(body: &[u8], depth: usize) -> IResult<&[u8], Subpacket> {
+    if depth >= MAX_EMBEDDED_DEPTH {
+        return Err(custom_error(body, errors::EMBEDDED_SIGNATURE_TOO_DEEP));
+    }
 
-// Parse an issuer subpacket
-// Ref: https://tools.ietf.org/html/draft-ietf-openpgp-rfc4880bis-05#section-5.2.3.28
-#[rustfmt::skip]
-named!(issuer_fingerprint<Subpacket>, do_parse!(
-           version: map_opt!(be_u8, KeyVersion::from_u8)
-    >> fingerprint: rest
-    >> (Subpacket::IssuerFingerprint(version, SmallVec::from_slice(fingerprint)))
-));
+    let (rest, sig) = parse_nested(body, Version::New, depth + 1)?;
+
+    Ok((rest, Subpacket::EmbeddedSignature(Box::new(sig))))
+}
+
+/// Parse an issuer fingerprint subpacket, the fingerprint length has to match the key version.
+/// Ref: https://tools.ietf.org/html/draft-ietf-openpgp-rfc4880bis-05#section-5.2.3.28
+fn issuer_fingerprint(body: &[u8]) -> IResult<&[u8], Subpacket> {
+    let (rest, (version, fingerprint)) = key_fingerprint(body)?;
+
+    Ok((rest, Subpacket::IssuerFingerprint(version, fingerprint)))
+}
+
+/// Parse an intended recipient fingerprint subpacket.
+/// Ref: https://tools.ietf.org/html/draft-ietf-openpgp-rfc4880bis-10#section-5.2.3.29
+fn intended_recipient_fingerprint(body: &[u8]) -> IResult<&[u8], Subpacket> {
+    let (rest, (version, fingerprint)) = key_fingerprint(body)?;
+
+    Ok((
+        rest,
+        Subpacket::IntendedRecipientFingerprint(version, fingerprint),
+    ))
+}
+
+/// Parse a key version, followed by a fingerprint of the matching length.
+fn key_fingerprint(body: &[u8]) -> IResult<&[u8], (KeyVersion, SmallVec<[u8; 20]>)> {
+    let (fingerprint, version) = map_opt!(body, be_u8, KeyVersion::from_u8)?;
+
+    let expected_len = match version {
+        KeyVersion::V4 => Some(20),
+        KeyVersion::V5 | KeyVersion::V6 => Some(32),
+        KeyVersion::V2 | KeyVersion::V3 => None,
+    };
+    if let Some(len) = expected_len {
+        if fingerprint.len() != len {
+            return Err(custom_error(body, errors::INVALID_SUBPACKET_LEN));
+        }
+    }
+
+    Ok((&b""[..], (version, SmallVec::from_slice(fingerprint))))
+}
 
 /// Parse a preferred aead subpacket
 fn pref_aead_alg(body: &[u8]) -> IResult<&[u8], Subpacket> {
@@ -263,7 +314,27 @@ fn pref_aead_alg(body: &[u8]) -> IResult<&[u8], Subpacket> {
     Ok((&b""[..], Subpacket::PreferredAeadAlgorithms(list)))
 }
 
-fn subpacket<'a>(typ: SubpacketType, body: &'a [u8]) -> IResult<&'a [u8], Subpacket> {
+/// Parse a preferred aead ciphersuites subpacket
+fn pref_aead_ciphersuites(body: &[u8]) -> IResult<&[u8], Subpacket> {
+    if body.len() % 2 != 0 {
+        return Err(custom_error(body, errors::INVALID_SUBPACKET_LEN));
+    }
+
+    let list: SmallVec<[(SymmetricKeyAlgorithm, AeadAlgorithm); 4]> = body
+        .chunks(2)
+        .map(|pair| {
+            let sym_alg = SymmetricKeyAlgorithm::from_u8(pair[0])
+                .ok_or_else(|| format_err!("Invalid SymmetricKeyAlgorithm"))?;
+            let aead = AeadAlgorithm::from_u8(pair[1])
+                .ok_or_else(|| format_err!("Invalid AeadAlgorithm"))?;
+            Ok((sym_alg, aead))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok((&b""[..], Subpacket::PreferredAeadCiphersuites(list)))
+}
+
+fn subpacket<'a>(typ: SubpacketType, body: &'a [u8], depth: usize) -> IResult<&'a [u8], Subpacket> {
     use self::SubpacketType::*;
     debug!("parsing subpacket: {:?} {}", typ, hex::encode(body));
 
@@ -290,9 +361,12 @@ fn subpacket<'a>(typ: SubpacketType, body: &'a [u8]) -> IResult<&'a [u8], Subpac
         RevocationReason => rev_reason(body),
         Features => features(body),
         SignatureTarget => sig_target(body),
-        EmbeddedSignature => embedded_sig(body),
+        EmbeddedSignature => embedded_sig(body, depth),
         IssuerFingerprint => issuer_fingerprint(body),
         PreferredAead => pref_aead_alg(body),
+        IntendedRecipientFingerprint => intended_recipient_fingerprint(body),
+        AttestedCertifications => Ok((&b""[..], Subpacket::AttestedCertifications(body.to_vec()))),
+        PreferredAeadCiphersuites => pref_aead_ciphersuites(body),
         Experimental(n) => Ok((
             &body[..],
             Subpacket::Experimental(n, SmallVec::from_slice(body)),
@@ -307,15 +381,50 @@ fn subpacket<'a>(typ: SubpacketType, body: &'a [u8]) -> IResult<&'a [u8], Subpac
     res
 }
 
-#[rustfmt::skip]
-named!(subpackets(&[u8]) -> Vec<Subpacket>, many0!(complete!(do_parse!(
-    // the subpacket length (1, 2, or 5 octets)
-        len: packet_length
-    // the subpacket type (1 octet)
-    >> typ: map_opt!(be_u8, SubpacketType::from_u8)
-    >>   p: flat_map!(take!(len - 1), |b| subpacket(typ, b))
-    >> (p)
-))));
+fn custom_error(input: &[u8], code: u32) -> nom::Err<&[u8]> {
+    nom::Err::Error(error_position!(input, nom::ErrorKind::Custom(code)))
+}
+
+/// Parses a full subpacket area.
+///
+/// Every subpacket must fit into the remaining area and the number of subpackets is
+/// limited, so hostile input can not trigger large amounts of work or allocations.
+/// Subpackets with an invalid body are kept as [`Subpacket::Other`], so they are not used,
+/// but the signature still serializes to the same bytes.
+fn subpackets(input: &[u8], depth: usize) -> IResult<&[u8], Vec<Subpacket>> {
+    let mut packets = Vec::new();
+    let mut rest = input;
+
+    while !rest.is_empty() {
+        if packets.len() >= MAX_SUBPACKETS {
+            return Err(custom_error(rest, errors::TOO_MANY_SUBPACKETS));
+        }
+
+        // the subpacket length (1, 2, or 5 octets), including the type octet
+        let (body, len) = match packet_length(rest) {
+            Ok(res) => res,
+            Err(_) => return Err(custom_error(rest, errors::INVALID_SUBPACKET_LEN)),
+        };
+        if len == 0 || len > body.len() {
+            return Err(custom_error(rest, errors::INVALID_SUBPACKET_LEN));
+        }
+        let (body, next) = body.split_at(len);
+        rest = next;
+
+        // the subpacket type (1 octet)
+        let typ = match SubpacketType::from_u8(body[0]) {
+            Some(typ) => typ,
+            None => return Err(custom_error(body, errors::INVALID_SUBPACKET_LEN)),
+        };
+
+        match subpacket(typ, &body[1..], depth) {
+            Ok((_, p)) => packets.push(p),
+            Err(_) => packets.push(Subpacket::Other(body[0], body[1..].to_vec())),
+        }
+    }
+
+    Ok((rest, packets))
+}
 
 named_args!(actual_signature<'a>(typ: &PublicKeyAlgorithm) <&'a [u8], Vec<Mpi>>, switch!(
     value!(typ),
@@ -327,6 +436,9 @@ named_args!(actual_signature<'a>(typ: &PublicKeyAlgorithm) <&'a [u8], Vec<Mpi>>,
         acc.push(item.to_owned());
         acc
     }) |
+    // a native signature, not an MPI
+    &PublicKeyAlgorithm::Ed25519   => map!(take!(64), |v| vec![Mpi::from_slice(v)]) |
+    &PublicKeyAlgorithm::Ed448     => map!(take!(114), |v| vec![Mpi::from_slice(v)]) |
     &PublicKeyAlgorithm::Private100 |
     &PublicKeyAlgorithm::Private101 |
     &PublicKeyAlgorithm::Private102 |
@@ -384,7 +496,7 @@ named_args!(v3_parser(packet_version: Version, version: SignatureVersion) <Signa
 // Parse a v4 or v5 signature packet
 // Ref: https://tools.ietf.org/html/rfc4880.html#section-5.2.3
 #[rustfmt::skip]
-named_args!(v4_parser(packet_version: Version, version: SignatureVersion) <Signature>, do_parse!(
+named_args!(v4_parser(packet_version: Version, version: SignatureVersion, depth: usize) <Signature>, do_parse!(
     // One-octet signature type.
             typ: map_opt!(be_u8, SignatureType::from_u8)
     // One-octet public-key algorithm.
@@ -394,11 +506,11 @@ named_args!(v4_parser(packet_version: Version, version: SignatureVersion) <Signa
     // Two-octet scalar octet count for following hashed subpacket data.
     >> hsub_len: be_u16
     // Hashed subpacket data set (zero or more subpackets).
-    >>     hsub: flat_map!(take!(hsub_len), subpackets)
+    >>     hsub: flat_map!(take!(hsub_len), call!(subpackets, depth))
     // Two-octet scalar octet count for the following unhashed subpacket data.
     >> usub_len: be_u16
     // Unhashed subpacket data set (zero or more subpackets).
-    >>     usub: flat_map!(take!(usub_len), subpackets)
+    >>     usub: flat_map!(take!(usub_len), call!(subpackets, depth))
     // Two-octet field holding the left 16 bits of the signed hash value.
     >>  ls_hash: take!(2)
     // One or more multiprecision integers comprising the signature.
@@ -416,20 +528,68 @@ named_args!(v4_parser(packet_version: Version, version: SignatureVersion) <Signa
     ))
 ));
 
+// Parse a v6 signature packet
+// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-versions-4-and-6-signature-
+#[rustfmt::skip]
+named_args!(v6_parser(packet_version: Version, version: SignatureVersion, depth: usize) <Signature>, do_parse!(
+    // One-octet signature type.
+            typ: map_opt!(be_u8, SignatureType::from_u8)
+    // One-octet public-key algorithm.
+    >>  pub_alg: map_opt!(be_u8, PublicKeyAlgorithm::from_u8)
+    // One-octet hash algorithm.
+    >> hash_alg: map_opt!(be_u8, HashAlgorithm::from_u8)
+    // Four-octet scalar octet count for following hashed subpacket data.
+    >> hsub_len: be_u32
+    // Hashed subpacket data set (zero or more subpackets).
+    >>     hsub: flat_map!(take!(hsub_len), call!(subpackets, depth))
+    // Four-octet scalar octet count for the following unhashed subpacket data.
+    >> usub_len: be_u32
+    // Unhashed subpacket data set (zero or more subpackets).
+    >>     usub: flat_map!(take!(usub_len), call!(subpackets, depth))
+    // Two-octet field holding the left 16 bits of the signed hash value.
+    >>  ls_hash: take!(2)
+    // One-octet salt size, followed by the salt.
+    >> salt_len: be_u8
+    >>     salt: take!(salt_len)
+    // One or more multiprecision integers comprising the signature.
+    >>      sig: call!(actual_signature, &pub_alg)
+    >> ({
+        let mut s = Signature::new(
+            packet_version,
+            version,
+            typ,
+            pub_alg,
+            hash_alg,
+            clone_into_array(ls_hash),
+            sig,
+            hsub,
+            usub,
+        );
+
+        s.config.salt = salt.to_vec();
+
+        s
+    })
+));
+
 fn invalid_version<'a>(_body: &'a [u8], version: SignatureVersion) -> IResult<&'a [u8], Signature> {
     unimplemented!("unknown signature version {:?}", version);
 }
 
 // Parse a signature packet (Tag 2)
 // Ref: https://tools.ietf.org/html/rfc4880.html#section-5.2
+named_args!(parse(packet_version: Version) <Signature>, call!(parse_nested, packet_version, 0));
+
+// Parse a signature packet, with `depth` being the nesting level of embedded signatures.
 #[rustfmt::skip]
-named_args!(parse(packet_version: Version) <Signature>, do_parse!(
+named_args!(parse_nested(packet_version: Version, depth: usize) <Signature>, do_parse!(
          version: map_opt!(be_u8, SignatureVersion::from_u8)
     >> signature: switch!(value!(&version),
                       &SignatureVersion::V2 => call!(v3_parser, packet_version, version) |
                       &SignatureVersion::V3 => call!(v3_parser, packet_version, version) |
-                      &SignatureVersion::V4 => call!(v4_parser, packet_version, version) |
-                      &SignatureVersion::V5 => call!(v4_parser, packet_version, version) |
+                      &SignatureVersion::V4 => call!(v4_parser, packet_version, version, depth) |
+                      &SignatureVersion::V5 => call!(v4_parser, packet_version, version, depth) |
+                      &SignatureVersion::V6 => call!(v6_parser, packet_version, version, depth) |
                       _ => call!(invalid_version, version)
     )
     >> (signature)
@@ -439,6 +599,39 @@ named_args!(parse(packet_version: Version) <Signature>, do_parse!(
 mod tests {
     use super::*;
 
+    use crate::ser::Serialize;
+
+    #[test]
+    fn test_subpackets() {
+        // signature creation time
+        let input = vec![5, 2, 0x5e, 0x0b, 0x1c, 0x3d];
+        let (rest, res) = subpackets(&input, 0).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            res,
+            vec![Subpacket::SignatureCreationTime(dt_from_timestamp(
+                0x5e0b_1c3d
+            ))]
+        );
+
+        // length exceeds the area
+        assert!(subpackets(&[6, 2, 0x5e, 0x0b, 0x1c, 0x3d], 0).is_err());
+        // zero length
+        assert!(subpackets(&[0], 0).is_err());
+        // truncated length
+        assert!(subpackets(&[255, 0, 0], 0).is_err());
+
+        // too many subpackets
+        let input = [1u8, 101].repeat(MAX_SUBPACKETS + 1);
+        assert!(subpackets(&input, 0).is_err());
+        assert_eq!(subpackets(&input[2..], 0).unwrap().1.len(), MAX_SUBPACKETS);
+    }
+
+    #[test]
+    fn test_embedded_sig_depth() {
+        assert!(embedded_sig(&[4, 0x19, 1, 8, 0, 0, 0, 0, 0, 0, 0, 1, 0], 1).is_err());
+    }
+
     #[test]
     fn test_subpacket_pref_sym_alg() {
         let input = vec![9, 8, 7, 3, 2];
@@ -453,4 +646,74 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_subpacket_issuer_fingerprint() {
+        let mut input = vec![4];
+        input.extend_from_slice(&[0xaa; 20]);
+        let (_, res) = issuer_fingerprint(&input).unwrap();
+        assert_eq!(
+            res,
+            Subpacket::IssuerFingerprint(KeyVersion::V4, SmallVec::from_slice(&[0xaa; 20]))
+        );
+
+        let mut input = vec![6];
+        input.extend_from_slice(&[0xbb; 32]);
+        let (_, res) = issuer_fingerprint(&input).unwrap();
+        assert_eq!(
+            res,
+            Subpacket::IssuerFingerprint(KeyVersion::V6, SmallVec::from_slice(&[0xbb; 32]))
+        );
+
+        // the length has to match the version
+        assert!(issuer_fingerprint(&input[..21]).is_err());
+        input[0] = 4;
+        assert!(issuer_fingerprint(&input).is_err());
+    }
+
+    #[test]
+    fn test_subpacket_intended_recipient_fingerprint() {
+        let mut input = vec![4];
+        input.extend_from_slice(&[0xcc; 20]);
+        let (_, res) = subpacket(SubpacketType::IntendedRecipientFingerprint, &input, 0).unwrap();
+        assert_eq!(
+            res,
+            Subpacket::IntendedRecipientFingerprint(
+                KeyVersion::V4,
+                SmallVec::from_slice(&[0xcc; 20])
+            )
+        );
+        assert_eq!(res.to_bytes().unwrap()[2..], input[..]);
+        assert!(intended_recipient_fingerprint(&input[..20]).is_err());
+    }
+
+    #[test]
+    fn test_subpacket_regular_expression() {
+        // terminated and unterminated expressions round-trip unchanged
+        for input in &[&b"<[^>]+[@.]example\\.com>$\0"[..], &b"example"[..]] {
+            let (_, res) = subpacket(SubpacketType::RegularExpression, input, 0).unwrap();
+            assert_eq!(res, Subpacket::RegularExpression(input.to_vec()));
+            assert_eq!(res.to_bytes().unwrap()[2..], input[..]);
+        }
+    }
+
+    #[test]
+    fn test_subpackets_invalid_body() {
+        // a signature creation time, that is too short, followed by a valid one
+        let input = [0x03, 0x02, 0xaa, 0xbb, 0x05, 0x02, 0x5e, 0x00, 0x00, 0x00];
+        let (_, res) = subpackets(&input, 0).unwrap();
+        assert_eq!(
+            res,
+            vec![
+                Subpacket::Other(2, vec![0xaa, 0xbb]),
+                Subpacket::SignatureCreationTime(Utc.timestamp(0x5e00_0000, 0)),
+            ]
+        );
+
+        let mut out = Vec::new();
+        for p in &res {
+            p.to_writer(&mut out).unwrap();
+        }
+        assert_eq!(out, input);
+    }
 }
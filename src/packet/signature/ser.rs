@@ -2,6 +2,7 @@ use std::io;
 
 use byteorder::{BigEndian, WriteBytesExt};
 
+use crate::crypto::public_key::PublicKeyAlgorithm;
 use crate::errors::Result;
 use crate::packet::signature::types::*;
 use crate::packet::signature::SignatureConfig;
@@ -15,6 +16,7 @@ impl Serialize for Signature {
         match self.config.version {
             SignatureVersion::V2 | SignatureVersion::V3 => self.to_writer_v3(writer),
             SignatureVersion::V4 | SignatureVersion::V5 => self.to_writer_v4(writer),
+            SignatureVersion::V6 => self.to_writer_v6(writer),
         }
     }
 }
@@ -97,7 +99,7 @@ impl Subpacket {
                 writer.write_all(&[*depth, *value])?;
             }
             Subpacket::RegularExpression(regexp) => {
-                writer.write_all(&write_string(regexp))?;
+                writer.write_all(regexp)?;
             }
             Subpacket::ExportableCertification(is_exportable) => {
                 let val = if *is_exportable { 1 } else { 0 };
@@ -110,6 +112,18 @@ impl Subpacket {
             Subpacket::PreferredAeadAlgorithms(algs) => {
                 writer.write_all(&algs.iter().map(|&alg| alg as u8).collect::<Vec<_>>())?;
             }
+            Subpacket::IntendedRecipientFingerprint(version, fp) => {
+                writer.write_all(&[*version as u8])?;
+                writer.write_all(fp)?;
+            }
+            Subpacket::AttestedCertifications(digests) => {
+                writer.write_all(digests)?;
+            }
+            Subpacket::PreferredAeadCiphersuites(suites) => {
+                for (sym_alg, aead) in suites {
+                    writer.write_all(&[*sym_alg as u8, *aead as u8])?;
+                }
+            }
             Subpacket::Experimental(_, body) => {
                 writer.write_all(body)?;
             }
@@ -155,10 +169,13 @@ impl Subpacket {
             }
             Subpacket::PolicyURI(uri) => uri.as_bytes().len(),
             Subpacket::TrustSignature(_, _) => 2,
-            Subpacket::RegularExpression(regexp) => regexp.as_bytes().len(),
+            Subpacket::RegularExpression(regexp) => regexp.len(),
             Subpacket::ExportableCertification(_) => 1,
             Subpacket::IssuerFingerprint(_, fp) => 1 + fp.len(),
             Subpacket::PreferredAeadAlgorithms(algs) => algs.len(),
+            Subpacket::IntendedRecipientFingerprint(_, fp) => 1 + fp.len(),
+            Subpacket::AttestedCertifications(digests) => digests.len(),
+            Subpacket::PreferredAeadCiphersuites(suites) => 2 * suites.len(),
             Subpacket::Experimental(_, body) => body.len(),
             Subpacket::Other(_, body) => body.len(),
             Subpacket::SignatureTarget(_, _, hash) => 2 + hash.len(),
@@ -197,6 +214,11 @@ impl Subpacket {
             Subpacket::ExportableCertification(_) => SubpacketType::ExportableCertification,
             Subpacket::IssuerFingerprint(_, _) => SubpacketType::IssuerFingerprint,
             Subpacket::PreferredAeadAlgorithms(_) => SubpacketType::PreferredAead,
+            Subpacket::IntendedRecipientFingerprint(_, _) => {
+                SubpacketType::IntendedRecipientFingerprint
+            }
+            Subpacket::AttestedCertifications(_) => SubpacketType::AttestedCertifications,
+            Subpacket::PreferredAeadCiphersuites(_) => SubpacketType::PreferredAeadCiphersuites,
             Subpacket::Experimental(n, _) => SubpacketType::Experimental(*n),
             Subpacket::Other(n, _) => SubpacketType::Other(*n),
             Subpacket::SignatureTarget(_, _, _) => SubpacketType::SignatureTarget,
@@ -279,6 +301,40 @@ impl SignatureConfig {
     }
 }
 
+impl SignatureConfig {
+    /// Serializes a v6 signature, up to the signed hash value.
+    fn to_writer_v6<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&[
+            // type
+            self.typ as u8,
+            // public algorithm
+            self.pub_alg as u8,
+            // hash algorithm
+            self.hash_alg as u8,
+        ])?;
+
+        // hashed subpackets
+        let mut hashed_subpackets = Vec::new();
+        for packet in &self.hashed_subpackets {
+            packet.to_writer(&mut hashed_subpackets)?;
+        }
+
+        writer.write_u32::<BigEndian>(hashed_subpackets.len() as u32)?;
+        writer.write_all(&hashed_subpackets)?;
+
+        // unhashed subpackets
+        let mut unhashed_subpackets = Vec::new();
+        for packet in &self.unhashed_subpackets {
+            packet.to_writer(&mut unhashed_subpackets)?;
+        }
+
+        writer.write_u32::<BigEndian>(unhashed_subpackets.len() as u32)?;
+        writer.write_all(&unhashed_subpackets)?;
+
+        Ok(())
+    }
+}
+
 impl Signature {
     /// Serializes a v2 or v3 signature.
     fn to_writer_v3<W: io::Write>(&self, writer: &mut W) -> Result<()> {
@@ -287,13 +343,7 @@ impl Signature {
         // signed hash value
         writer.write_all(&self.signed_hash_value)?;
 
-        // the actual signature
-        for val in &self.signature {
-            debug!("writing: {}", hex::encode(val));
-            val.to_writer(writer)?;
-        }
-
-        Ok(())
+        self.signature_to_writer(writer)
     }
 
     /// Serializes a v4 or v5 signature.
@@ -303,10 +353,32 @@ impl Signature {
         // signed hash value
         writer.write_all(&self.signed_hash_value)?;
 
-        // the actual signature
+        self.signature_to_writer(writer)
+    }
+
+    /// Serializes a v6 signature.
+    fn to_writer_v6<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+        self.config.to_writer_v6(writer)?;
+
+        // signed hash value
+        writer.write_all(&self.signed_hash_value)?;
+
+        // salt
+        writer.write_all(&[self.config.salt.len() as u8])?;
+        writer.write_all(&self.config.salt)?;
+
+        self.signature_to_writer(writer)
+    }
+
+    /// Serializes the actual signature.
+    fn signature_to_writer<W: io::Write>(&self, writer: &mut W) -> Result<()> {
         for val in &self.signature {
             debug!("writing: {}", hex::encode(val));
-            val.to_writer(writer)?;
+            match self.config.pub_alg {
+                // native signatures are fixed size octet strings
+                PublicKeyAlgorithm::Ed25519 | PublicKeyAlgorithm::Ed448 => writer.write_all(val)?,
+                _ => val.to_writer(writer)?,
+            }
         }
 
         Ok(())
@@ -1,19 +1,18 @@
-use std::fmt;
+use std::{fmt, io};
 
-use byteorder::{BigEndian, ByteOrder};
 use chrono::{DateTime, Utc};
 use num_traits::FromPrimitive;
 
 use crate::crypto::aead::AeadAlgorithm;
-use crate::crypto::hash::HashAlgorithm;
+use crate::crypto::hash::{HashAlgorithm, HashWriter, Hasher, TextHashWriter};
 use crate::crypto::public_key::PublicKeyAlgorithm;
 use crate::crypto::sym::SymmetricKeyAlgorithm;
 use crate::errors::Result;
 use crate::packet::signature::SignatureConfig;
-use crate::packet::PacketTrait;
+use crate::packet::{LiteralData, PacketTrait};
 use crate::ser::Serialize;
 use crate::types::{
-    self, CompressionAlgorithm, KeyId, KeyVersion, Mpi, PublicKeyTrait, Tag, Version,
+    self, CompressionAlgorithm, KeyId, KeyTrait, KeyVersion, Mpi, PublicKeyTrait, Tag, Version,
 };
 use smallvec::SmallVec;
 
@@ -77,24 +76,128 @@ impl Signature {
 
     /// Verify this signature.
     pub fn verify(&self, key: &impl PublicKeyTrait, data: &[u8]) -> Result<()> {
-        if let Some(issuer) = self.issuer() {
-            if &key.key_id() != issuer {
-                // TODO: should this be an actual error?
-                warn!(
-                    "validating signature with a non matching Key ID {:?} != {:?}",
-                    &key.key_id(),
-                    issuer
-                );
-            }
+        if !self.is_issued_by(key) {
+            // TODO: should this be an actual error?
+            warn!(
+                "validating signature with a non matching issuer {:?}",
+                &key.key_id()
+            );
         }
 
-        let mut hasher = self.config.hash_alg.new_hasher()?;
-
+        let mut hasher = self.config.new_hasher()?;
         self.config.hash_data_to_sign(&mut *hasher, data)?;
-        let len = self.config.hash_signature_data(&mut *hasher)?;
+
+        self.verify_hasher(key, hasher)
+    }
+
+    /// Verify this signature over the contents of `literal`.
+    ///
+    /// Unlike [`verify`](Signature::verify), this includes the mode, file name and date of
+    /// the literal data in v5 signatures, as is done for inline signed messages.
+    pub fn verify_literal(&self, key: &impl PublicKeyTrait, literal: &LiteralData) -> Result<()> {
+        if !self.is_issued_by(key) {
+            // TODO: should this be an actual error?
+            warn!(
+                "validating signature with a non matching issuer {:?}",
+                &key.key_id()
+            );
+        }
+
+        let mut hasher = self.config.new_hasher()?;
+        self.config
+            .hash_data_to_sign(&mut *hasher, literal.data())?;
+        let hash = self.finish_hasher_with(hasher, Some(&literal.metadata()))?;
+
+        self.verify_digest(key, &hash)
+    }
+
+    /// Verify this signature over the data read from `data`, without buffering it.
+    ///
+    /// Only binary and text signatures are supported, see [`SignatureConfig::hash_data_to_sign`].
+    pub fn verify_reader(&self, key: &impl PublicKeyTrait, mut data: impl io::Read) -> Result<()> {
+        ensure!(
+            self.typ() == SignatureType::Binary || self.typ() == SignatureType::Text,
+            "can not verify {:?} signatures over a reader",
+            self.typ()
+        );
+
+        if !self.is_issued_by(key) {
+            // TODO: should this be an actual error?
+            warn!(
+                "validating signature with a non matching issuer {:?}",
+                &key.key_id()
+            );
+        }
+
+        let mut hasher = self.config.new_hasher()?;
+        self.config.hash_data_reader(&mut *hasher, &mut data)?;
+
+        self.verify_hasher(key, hasher)
+    }
+
+    /// Verify this signature over the serialized form of `data`.
+    ///
+    /// For binary and text signatures the data is serialized directly into the hasher,
+    /// without buffering it.
+    pub fn verify_serialized(
+        &self,
+        key: &impl PublicKeyTrait,
+        data: &impl Serialize,
+    ) -> Result<()> {
+        if self.typ() != SignatureType::Binary && self.typ() != SignatureType::Text {
+            return self.verify(key, &data.to_bytes()?);
+        }
+
+        if !self.is_issued_by(key) {
+            // TODO: should this be an actual error?
+            warn!(
+                "validating signature with a non matching issuer {:?}",
+                &key.key_id()
+            );
+        }
+
+        let mut hasher = self.config.new_hasher()?;
+        if self.typ() == SignatureType::Text {
+            data.to_writer(&mut TextHashWriter::new(&mut *hasher))?;
+        } else {
+            data.to_writer(&mut HashWriter(&mut *hasher))?;
+        }
+
+        self.verify_hasher(key, hasher)
+    }
+
+    /// Finishes the verification, after the signed data was hashed into `hasher`.
+    fn verify_hasher(&self, key: &impl PublicKeyTrait, hasher: Box<dyn Hasher>) -> Result<()> {
+        let hash = self.finish_hasher(hasher)?;
+
+        self.verify_digest(key, &hash)
+    }
+
+    /// Hashes the signature data into `hasher`, after the signed data, and returns the digest
+    /// that is signed.
+    pub(crate) fn finish_hasher(&self, hasher: Box<dyn Hasher>) -> Result<Vec<u8>> {
+        self.finish_hasher_with(hasher, None)
+    }
+
+    /// Like [`finish_hasher`](Signature::finish_hasher), including the `metadata` of the
+    /// literal data in v5 signatures.
+    pub(crate) fn finish_hasher_with(
+        &self,
+        mut hasher: Box<dyn Hasher>,
+        metadata: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        let len = self
+            .config
+            .hash_signature_data_with(&mut *hasher, metadata)?;
         hasher.update(&self.config.trailer(len));
 
-        let hash = &hasher.finish()[..];
+        Ok(hasher.finish())
+    }
+
+    /// Verifies the signature over a digest returned by [`finish_hasher`].
+    ///
+    /// [`finish_hasher`]: Signature::finish_hasher
+    pub(crate) fn verify_digest(&self, key: &impl PublicKeyTrait, hash: &[u8]) -> Result<()> {
         ensure_eq!(
             &self.signed_hash_value,
             &hash[0..2],
@@ -110,53 +213,71 @@ impl Signature {
         key: &impl PublicKeyTrait,
         tag: Tag,
         id: &impl Serialize,
+    ) -> Result<()> {
+        self.verify_third_party_certificate(key, key, tag, id)
+    }
+
+    /// Verifies a certificate signature over a user id or attribute of `certified_key`,
+    /// made by `signer`.
+    pub fn verify_third_party_certificate(
+        &self,
+        signer: &impl PublicKeyTrait,
+        certified_key: &impl PublicKeyTrait,
+        tag: Tag,
+        id: &impl Serialize,
     ) -> Result<()> {
         debug!("verifying certificate {:#?}", self);
 
-        if let Some(issuer) = self.issuer() {
-            if &key.key_id() != issuer {
-                // TODO: should this be an actual error?
-                warn!(
-                    "validating certificate with a non matching Key ID {:?} != {:?}",
-                    &key.key_id(),
-                    issuer
-                );
-            }
+        if !self.is_issued_by(signer) {
+            // TODO: should this be an actual error?
+            warn!(
+                "validating certificate with a non matching issuer {:?}",
+                &signer.key_id()
+            );
         }
 
-        let mut hasher = self.config.hash_alg.new_hasher()?;
-        let mut key_buf = Vec::new();
-        key.to_writer_old(&mut key_buf)?;
+        let mut hasher = self.config.new_hasher()?;
+        self.config
+            .hash_certificate(&mut *hasher, certified_key, tag, id)?;
 
-        let mut packet_buf = Vec::new();
-        id.to_writer(&mut packet_buf)?;
+        self.verify_hasher(signer, hasher)
+    }
 
-        debug!("packet: {}", hex::encode(&packet_buf));
+    /// Verifies a key binding.
+    pub fn verify_key_binding(
+        &self,
+        signing_key: &impl PublicKeyTrait,
+        key: &impl PublicKeyTrait,
+    ) -> Result<()> {
+        debug!(
+            "verifying key binding: {:#?} - {:#?} - {:#?}",
+            self, signing_key, key
+        );
 
-        hasher.update(&key_buf);
+        if !self.is_issued_by(signing_key) {
+            // TODO: should this be an actual error?
+            warn!(
+                "validating key binding with a non matching issuer {:?}",
+                &signing_key.key_id()
+            );
+        }
 
-        match self.config.version {
-            SignatureVersion::V2 | SignatureVersion::V3 => {
-                // Nothing to do
-            }
-            SignatureVersion::V4 | SignatureVersion::V5 => {
-                let prefix = match tag {
-                    Tag::UserId => 0xB4,
-                    Tag::UserAttribute => 0xD1,
-                    _ => bail!("invalid tag for certificate validation: {:?}", tag),
-                };
+        let mut hasher = self.config.new_hasher()?;
 
-                let mut prefix_buf = [prefix, 0u8, 0u8, 0u8, 0u8];
-                BigEndian::write_u32(&mut prefix_buf[1..], packet_buf.len() as u32);
-                debug!("prefix: {}", hex::encode(&prefix_buf));
+        // Signing Key
+        {
+            let mut key_buf = Vec::new();
+            signing_key.to_writer_old(&mut key_buf)?;
 
-                // prefixes
-                hasher.update(&prefix_buf);
-            }
+            hasher.update(&key_buf);
         }
+        // Key being bound
+        {
+            let mut key_buf = Vec::new();
+            key.to_writer_old(&mut key_buf)?;
 
-        // the packet content
-        hasher.update(&packet_buf);
+            hasher.update(&key_buf);
+        }
 
         let len = self.config.hash_signature_data(&mut *hasher)?;
         hasher.update(&self.config.trailer(len));
@@ -168,44 +289,48 @@ impl Signature {
             "invalid signed hash value"
         );
 
-        key.verify_signature(self.config.hash_alg, hash, &self.signature)
+        signing_key.verify_signature(self.config.hash_alg, hash, &self.signature)
     }
 
-    /// Verifies a key binding.
-    pub fn verify_key_binding(
+    /// Verifies a primary key binding, the back signature made by `signing_key`,
+    /// a signing subkey, over `primary_key`.
+    pub fn verify_primary_key_binding(
         &self,
         signing_key: &impl PublicKeyTrait,
-        key: &impl PublicKeyTrait,
+        primary_key: &impl PublicKeyTrait,
     ) -> Result<()> {
         debug!(
-            "verifying key binding: {:#?} - {:#?} - {:#?}",
-            self, signing_key, key
+            "verifying primary key binding: {:#?} - {:#?} - {:#?}",
+            self, signing_key, primary_key
+        );
+
+        ensure_eq!(
+            self.typ(),
+            SignatureType::KeyBinding,
+            "invalid primary key binding signature type"
         );
 
-        let key_id = signing_key.key_id();
-        if let Some(issuer) = self.issuer() {
-            if &key_id != issuer {
-                // TODO: should this be an actual error?
-                warn!(
-                    "validating key binding with a non matching Key ID {:?} != {:?}",
-                    &key_id, issuer
-                );
-            }
+        if !self.is_issued_by(signing_key) {
+            // TODO: should this be an actual error?
+            warn!(
+                "validating primary key binding with a non matching issuer {:?}",
+                &signing_key.key_id()
+            );
         }
 
-        let mut hasher = self.config.hash_alg.new_hasher()?;
+        let mut hasher = self.config.new_hasher()?;
 
-        // Signing Key
+        // Primary Key
         {
             let mut key_buf = Vec::new();
-            signing_key.to_writer_old(&mut key_buf)?;
+            primary_key.to_writer_old(&mut key_buf)?;
 
             hasher.update(&key_buf);
         }
-        // Key being bound
+        // Signing Subkey
         {
             let mut key_buf = Vec::new();
-            key.to_writer_old(&mut key_buf)?;
+            signing_key.to_writer_old(&mut key_buf)?;
 
             hasher.update(&key_buf);
         }
@@ -227,18 +352,15 @@ impl Signature {
     pub fn verify_key(&self, key: &impl PublicKeyTrait) -> Result<()> {
         debug!("verifying key (revocation): {:#?} - {:#?}", self, key);
 
-        let key_id = key.key_id();
-        if let Some(issuer) = self.issuer() {
-            if &key_id != issuer {
-                // TODO: should this be an actual error?
-                warn!(
-                    "validating key (revocation) with a non matching Key ID {:?} != {:?}",
-                    &key_id, issuer
-                );
-            }
+        if !self.is_issued_by(key) {
+            // TODO: should this be an actual error?
+            warn!(
+                "validating key (revocation) with a non matching issuer {:?}",
+                &key.key_id()
+            );
         }
 
-        let mut hasher = self.config.hash_alg.new_hasher()?;
+        let mut hasher = self.config.new_hasher()?;
 
         {
             let mut key_buf = Vec::new();
@@ -265,11 +387,78 @@ impl Signature {
         self.config.is_certificate()
     }
 
+    /// Returns `true` for certifications of a user id or attribute, but not for their
+    /// revocations or attestations.
+    pub fn is_certification(&self) -> bool {
+        match self.typ() {
+            SignatureType::CertRevocation | SignatureType::Attestation => false,
+            _ => self.is_certificate(),
+        }
+    }
+
+    /// Returns the digests of the certifications approved by this attestation signature,
+    /// from its hashed area.
+    pub fn attested_certifications(&self) -> Vec<&[u8]> {
+        let size = self.config.hash_alg.digest_size();
+        if size == 0 {
+            return Vec::new();
+        }
+
+        self.config
+            .hashed_subpackets
+            .iter()
+            .filter_map(|p| match p {
+                Subpacket::AttestedCertifications(digests) => Some(digests),
+                _ => None,
+            })
+            .flat_map(|digests| digests.chunks_exact(size))
+            .collect()
+    }
+
+    /// Returns the fingerprints of the primary keys the signed message was meant for, from
+    /// the hashed area, see [`Subpacket::IntendedRecipientFingerprint`].
+    pub fn intended_recipients(&self) -> Vec<&[u8]> {
+        self.config
+            .hashed_subpackets
+            .iter()
+            .filter_map(|p| match p {
+                Subpacket::IntendedRecipientFingerprint(_, fp) => Some(&fp[..]),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the digest of this signature, the way attestations refer to it.
+    ///
+    /// It is computed like the hash of a third-party confirmation, over the signature
+    /// packet without its unhashed subpackets.
+    pub fn attestation_digest(&self, hash: HashAlgorithm) -> Result<Vec<u8>> {
+        let mut sig = self.clone();
+        sig.config.unhashed_subpackets.clear();
+        let body = sig.to_bytes()?;
+
+        let mut hasher = hash.new_hasher()?;
+        hasher.update(&[0x88]);
+        hasher.update(&(body.len() as u32).to_be_bytes());
+        hasher.update(&body);
+
+        Ok(hasher.finish())
+    }
+
     /// Returns an iterator over all subpackets of this signature.
     fn subpackets(&self) -> impl Iterator<Item = &Subpacket> {
         self.config.subpackets()
     }
 
+    /// Returns the salt of a v6 signature.
+    pub fn salt(&self) -> Option<&[u8]> {
+        if self.config.version == SignatureVersion::V6 {
+            Some(&self.config.salt)
+        } else {
+            None
+        }
+    }
+
     pub fn key_expiration_time(&self) -> Option<&DateTime<Utc>> {
         self.subpackets().find_map(|p| match p {
             Subpacket::KeyExpirationTime(d) => Some(d),
@@ -292,6 +481,26 @@ impl Signature {
         self.config.issuer()
     }
 
+    /// Returns the version and fingerprint of the issuer, if the signature carries one.
+    pub fn issuer_fingerprint(&self) -> Option<(KeyVersion, &[u8])> {
+        self.config.issuer_fingerprint()
+    }
+
+    /// Checks if this signature could have been issued by the given key.
+    ///
+    /// The issuer fingerprint is preferred over the issuer key id. Signatures without
+    /// any issuer information match all keys.
+    pub fn is_issued_by(&self, key: &impl KeyTrait) -> bool {
+        if let Some((version, fp)) = self.issuer_fingerprint() {
+            return key.version() == version && key.fingerprint() == fp;
+        }
+
+        match self.issuer() {
+            Some(issuer) => &key.key_id() == issuer,
+            None => true,
+        }
+    }
+
     pub fn preferred_symmetric_algs(&self) -> &[SymmetricKeyAlgorithm] {
         self.subpackets()
             .find_map(|p| match p {
@@ -319,6 +528,24 @@ impl Signature {
             .unwrap_or_else(|| &[][..])
     }
 
+    pub fn preferred_aead_algs(&self) -> &[AeadAlgorithm] {
+        self.subpackets()
+            .find_map(|p| match p {
+                Subpacket::PreferredAeadAlgorithms(d) => Some(&d[..]),
+                _ => None,
+            })
+            .unwrap_or_else(|| &[][..])
+    }
+
+    pub fn preferred_aead_ciphersuites(&self) -> &[(SymmetricKeyAlgorithm, AeadAlgorithm)] {
+        self.subpackets()
+            .find_map(|p| match p {
+                Subpacket::PreferredAeadCiphersuites(d) => Some(&d[..]),
+                _ => None,
+            })
+            .unwrap_or_else(|| &[][..])
+    }
+
     pub fn key_server_prefs(&self) -> &[u8] {
         self.subpackets()
             .find_map(|p| match p {
@@ -429,9 +656,10 @@ impl Signature {
         })
     }
 
-    pub fn regular_expression(&self) -> Option<&str> {
+    /// The raw bytes of the regular expression subpacket, including the terminating NUL.
+    pub fn regular_expression(&self) -> Option<&[u8]> {
         self.subpackets().find_map(|p| match p {
-            Subpacket::RegularExpression(d) => Some(d.as_str()),
+            Subpacket::RegularExpression(d) => Some(&d[..]),
             _ => None,
         })
     }
@@ -454,6 +682,7 @@ pub enum SignatureVersion {
     V3 = 3,
     V4 = 4,
     V5 = 5,
+    V6 = 6,
 }
 
 impl Default for SignatureVersion {
@@ -462,6 +691,16 @@ impl Default for SignatureVersion {
     }
 }
 
+impl SignatureVersion {
+    /// Returns the version of signatures made by the given key.
+    pub fn for_key(key: &impl KeyTrait) -> Self {
+        match key.version() {
+            KeyVersion::V6 => SignatureVersion::V6,
+            _ => SignatureVersion::V4,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone, FromPrimitive)]
 #[repr(u8)]
 pub enum SignatureType {
@@ -499,6 +738,11 @@ pub enum SignatureType {
     /// certifications.  Some implementations can issue 0x11-0x13
     /// certifications, but few differentiate between the types.
     CertPositive = 0x13,
+    /// Attestation Key Signature.
+    /// This signature is made by the key owner over one of its User IDs, and
+    /// approves the distribution of the third-party certifications listed in its
+    /// Attested Certifications subpacket. Only the most recent attestation counts.
+    Attestation = 0x16,
     /// Subkey Binding Signature
     /// This signature is a statement by the top-level signing key that
     /// indicates that it owns the subkey.  This signature is calculated
@@ -587,6 +831,9 @@ pub enum SubpacketType {
     EmbeddedSignature,
     IssuerFingerprint,
     PreferredAead,
+    IntendedRecipientFingerprint,
+    AttestedCertifications,
+    PreferredAeadCiphersuites,
     Experimental(u8),
     Other(u8),
 }
@@ -620,6 +867,9 @@ impl Into<u8> for SubpacketType {
             SubpacketType::EmbeddedSignature => 32,
             SubpacketType::IssuerFingerprint => 33,
             SubpacketType::PreferredAead => 34,
+            SubpacketType::IntendedRecipientFingerprint => 35,
+            SubpacketType::AttestedCertifications => 37,
+            SubpacketType::PreferredAeadCiphersuites => 39,
             SubpacketType::Experimental(n) => n,
             SubpacketType::Other(n) => n,
         }
@@ -667,6 +917,9 @@ impl FromPrimitive for SubpacketType {
                 32 => SubpacketType::EmbeddedSignature,
                 33 => SubpacketType::IssuerFingerprint,
                 34 => SubpacketType::PreferredAead,
+                35 => SubpacketType::IntendedRecipientFingerprint,
+                37 => SubpacketType::AttestedCertifications,
+                39 => SubpacketType::PreferredAeadCiphersuites,
                 100..=110 => SubpacketType::Experimental(n as u8),
                 _ => SubpacketType::Other(n as u8),
             };
@@ -705,15 +958,75 @@ pub enum Subpacket {
     SignersUserID(String),
     PolicyURI(String),
     TrustSignature(u8, u8),
-    RegularExpression(String),
+    /// The raw bytes of the regular expression, including the terminating NUL.
+    RegularExpression(Vec<u8>),
     ExportableCertification(bool),
     IssuerFingerprint(KeyVersion, SmallVec<[u8; 20]>),
     PreferredAeadAlgorithms(SmallVec<[AeadAlgorithm; 2]>),
+    /// The version and fingerprint of the primary key of a recipient, that the signed
+    /// message was encrypted to.
+    IntendedRecipientFingerprint(KeyVersion, SmallVec<[u8; 20]>),
+    /// The concatenated digests of the certifications approved by an attestation,
+    /// computed with the hash algorithm of the attestation signature.
+    AttestedCertifications(Vec<u8>),
+    /// Pairs of symmetric and AEAD algorithms, that the key holder prefers to use
+    /// with version 2 SEIPD packets.
+    PreferredAeadCiphersuites(SmallVec<[(SymmetricKeyAlgorithm, AeadAlgorithm); 4]>),
     Experimental(u8, SmallVec<[u8; 2]>),
+    /// A subpacket of an unknown type, or one whose body could not be parsed.
     Other(u8, Vec<u8>),
     SignatureTarget(PublicKeyAlgorithm, HashAlgorithm, Vec<u8>),
 }
 
+impl Subpacket {
+    /// Returns the subpacket identifying `key` as the issuer of a signature.
+    /// This is the issuer fingerprint for v6 keys, which must not use the key id.
+    pub fn issuer_of(key: &impl KeyTrait) -> Self {
+        match key.version() {
+            KeyVersion::V6 => Subpacket::IssuerFingerprint(
+                KeyVersion::V6,
+                SmallVec::from_slice(&key.fingerprint()),
+            ),
+            _ => Subpacket::Issuer(key.key_id()),
+        }
+    }
+
+    /// Returns all subpackets identifying `key` as the issuer of a signature,
+    /// the issuer fingerprint and, for keys before v6, the key id.
+    pub fn issuer_subpackets(key: &impl KeyTrait) -> Vec<Self> {
+        let fingerprint =
+            Subpacket::IssuerFingerprint(key.version(), SmallVec::from_slice(&key.fingerprint()));
+        match key.version() {
+            KeyVersion::V6 => vec![fingerprint],
+            _ => vec![fingerprint, Subpacket::Issuer(key.key_id())],
+        }
+    }
+
+    pub(crate) fn is_issuer(&self) -> bool {
+        match self {
+            Subpacket::Issuer(_) | Subpacket::IssuerFingerprint(..) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Where the issuer subpackets of a new signature are placed, see [`SignatureConfig::set_issuer`].
+///
+/// Signatures used to carry the issuer key id in the unhashed area. Current guidance is to
+/// protect it, together with the issuer fingerprint, by placing both into the hashed area.
+/// Issuer subpackets are found in either area when parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssuerPlacement {
+    Hashed,
+    Unhashed,
+}
+
+impl Default for IssuerPlacement {
+    fn default() -> Self {
+        IssuerPlacement::Hashed
+    }
+}
+
 bitfield! {
     #[derive(Default, PartialEq, Eq, Copy, Clone)]
     pub struct KeyFlags(u8);
@@ -767,6 +1080,29 @@ pub enum RevocationCode {
     CertUserIdInvalid = 32,
 }
 
+/// Reason for a revocation, as stored in the `RevocationReason` subpacket.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RevocationReason {
+    pub code: RevocationCode,
+    /// Human readable explanation, may be empty.
+    pub reason: String,
+}
+
+impl RevocationReason {
+    pub fn new(code: RevocationCode, reason: impl Into<String>) -> Self {
+        RevocationReason {
+            code,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl From<RevocationReason> for Subpacket {
+    fn from(reason: RevocationReason) -> Self {
+        Subpacket::RevocationReason(reason.code, reason.reason)
+    }
+}
+
 impl fmt::Debug for Signature {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Signature")
@@ -798,6 +1134,14 @@ impl PacketTrait for Signature {
 mod tests {
     use super::*;
 
+    use chrono::TimeZone;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::composed::{KeyType, SecretKeyParamsBuilder};
+    use crate::packet::SignatureConfigBuilder;
+    use crate::types::SecretKeyTrait;
+
     #[test]
     fn test_keyflags() {
         let flags: KeyFlags = Default::default();
@@ -832,4 +1176,219 @@ mod tests {
         flags.set_group(true);
         assert_eq!(flags.0, 0x80);
     }
+
+    #[test]
+    fn test_issuer_placement() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+
+        // self-signatures protect the issuer
+        let cert = &key.details.users[0].signatures[0];
+        assert!(cert.config.unhashed_subpackets.is_empty());
+        assert!(cert
+            .config
+            .hashed_subpackets
+            .contains(&Subpacket::Issuer(key.key_id())));
+        assert!(cert.is_issued_by(&key));
+
+        let key = &key.primary_key;
+        let mut config = SignatureConfigBuilder::default()
+            .typ(SignatureType::Binary)
+            .pub_alg(key.algorithm())
+            .hashed_subpackets(vec![
+                Subpacket::SignatureCreationTime(Utc.timestamp(1_600_000_000, 0)),
+                Subpacket::Issuer(KeyId::from_slice(&[0u8; 8]).unwrap()),
+            ])
+            .unhashed_subpackets(vec![Subpacket::issuer_of(key)])
+            .build()
+            .unwrap()
+            .with_issuer(key);
+        assert_eq!(config.hashed_subpackets.len(), 3);
+        assert!(config.unhashed_subpackets.is_empty());
+        assert_eq!(config.issuer(), Some(&key.key_id()));
+
+        // legacy placement
+        config.set_issuer(key, IssuerPlacement::Unhashed);
+        assert_eq!(config.hashed_subpackets.len(), 1);
+        assert_eq!(
+            config.unhashed_subpackets,
+            Subpacket::issuer_subpackets(key)
+        );
+
+        let sig = config.sign(key, || "".into(), b"hello").unwrap();
+        sig.verify(key, b"hello").unwrap();
+        assert_eq!(sig.issuer(), Some(&key.key_id()));
+        assert!(sig.is_issued_by(key));
+    }
+
+    #[test]
+    fn test_text_signature() {
+        use std::io::Read;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+        let key = &key.primary_key;
+
+        let config = |typ| {
+            SignatureConfigBuilder::default()
+                .typ(typ)
+                .pub_alg(key.algorithm())
+                .hashed_subpackets(vec![Subpacket::SignatureCreationTime(
+                    Utc.timestamp(1_600_000_000, 0),
+                )])
+                .unhashed_subpackets(vec![])
+                .build()
+                .unwrap()
+        };
+
+        let sig = config(SignatureType::Text)
+            .sign(key, || "".into(), b"one\ntwo\r\n")
+            .unwrap();
+        sig.verify(key, b"one\r\ntwo\r\n").unwrap();
+        sig.verify(key, b"one\ntwo\n").unwrap();
+        assert!(sig.verify(key, b"one\ntwo").is_err());
+        assert!(sig.verify(key, b"one\r\r\ntwo\n").is_err());
+
+        // line endings split between reads
+        let reader = (&b"one\r"[..]).chain(&b"\ntwo\r"[..]).chain(&b"\n"[..]);
+        sig.verify_reader(key, reader).unwrap();
+        let reader = (&b"one"[..]).chain(&b"\ntwo\n"[..]);
+        let streamed = config(SignatureType::Text)
+            .sign_reader(key, || "".into(), reader)
+            .unwrap();
+        streamed.verify(key, b"one\r\ntwo\r\n").unwrap();
+
+        let sig = config(SignatureType::Binary)
+            .sign(key, || "".into(), b"one\ntwo\n")
+            .unwrap();
+        assert!(sig.verify(key, b"one\r\ntwo\r\n").is_err());
+    }
+
+    #[test]
+    fn test_v5_literal_metadata() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+        let key = &key.primary_key;
+        let literal = LiteralData::from_bytes("hello.txt", b"hello world");
+
+        // sign the way GnuPG signs inline v5 signatures, including the metadata
+        let config = SignatureConfigBuilder::default()
+            .version(SignatureVersion::V5)
+            .typ(SignatureType::Binary)
+            .pub_alg(key.algorithm())
+            .hashed_subpackets(vec![Subpacket::SignatureCreationTime(
+                Utc.timestamp(1_600_000_000, 0),
+            )])
+            .unhashed_subpackets(vec![])
+            .build()
+            .unwrap();
+        let mut hasher = config.new_hasher().unwrap();
+        config
+            .hash_data_to_sign(&mut *hasher, literal.data())
+            .unwrap();
+        let len = config
+            .hash_signature_data_with(&mut *hasher, Some(&literal.metadata()))
+            .unwrap();
+        hasher.update(&config.trailer(len));
+        let hash = hasher.finish();
+        let mpis = key
+            .create_signature(|| "".into(), config.hash_alg, &hash)
+            .unwrap();
+        let sig = Signature::from_config(config, [hash[0], hash[1]], mpis);
+
+        sig.verify_literal(key, &literal).unwrap();
+        // detached verification hashes zeros instead of the metadata
+        assert!(sig.verify(key, literal.data()).is_err());
+        let renamed = LiteralData::from_bytes("other.txt", b"hello world");
+        assert!(sig.verify_literal(key, &renamed).is_err());
+    }
+
+    #[test]
+    fn test_v6_salt() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_sign(true)
+            .primary_user_id("Me <me@mail.com>".into())
+            .build()
+            .unwrap()
+            .generate_with_rng(&mut rng)
+            .unwrap()
+            .sign(|| "".into())
+            .unwrap();
+        let key = &key.primary_key;
+
+        let config = SignatureConfigBuilder::default()
+            .typ(SignatureType::Binary)
+            .version(SignatureVersion::V6)
+            .pub_alg(key.algorithm())
+            .hash_alg(HashAlgorithm::SHA2_512)
+            .hashed_subpackets(vec![Subpacket::SignatureCreationTime(
+                Utc.timestamp(1_600_000_000, 0),
+            )])
+            .unhashed_subpackets(vec![])
+            .build()
+            .unwrap();
+
+        // the same seed creates the same signature
+        let sign = |seed| {
+            let mut config = config.clone();
+            config
+                .generate_salt(&mut ChaCha8Rng::seed_from_u64(seed))
+                .unwrap();
+            config.sign(key, || "".into(), b"hello").unwrap()
+        };
+        let sig = sign(1);
+        assert_eq!(sig.salt().map(<[u8]>::len), Some(32));
+        assert_eq!(sig, sign(1));
+        assert_ne!(sig.salt(), sign(2).salt());
+        sig.verify(key, b"hello").unwrap();
+
+        // the salt is part of the signed data
+        let mut other = sig.clone();
+        other.config.salt[0] ^= 1;
+        assert!(other.verify(key, b"hello").is_err());
+        other.config.salt.pop();
+        assert!(other.verify(key, b"hello").is_err());
+
+        // without a salt, a random one is used
+        let sig = config.clone().sign(key, || "".into(), b"hello").unwrap();
+        assert_eq!(sig.salt().map(<[u8]>::len), Some(32));
+        sig.verify(key, b"hello").unwrap();
+
+        let mut v4_config = config;
+        v4_config.version = SignatureVersion::V4;
+        assert!(v4_config.generate_salt(&mut rng).is_err());
+        assert_eq!(
+            v4_config.sign(key, || "".into(), b"hello").unwrap().salt(),
+            None
+        );
+    }
 }
@@ -117,6 +117,16 @@ named!(new_packet_header(&[u8]) -> (Version, Tag, PacketLength), bits!(do_parse!
     >> ((ver, tag, len))
 )));
 
+/// Parses the header of a packet, without its body.
+pub(crate) fn packet_header(input: &[u8]) -> IResult<&[u8], (Version, Tag, PacketLength)> {
+    alt!(input, new_packet_header | old_packet_header)
+}
+
+/// Parses the length of the next chunk of a body with partial lengths.
+pub(crate) fn partial_body_length(input: &[u8]) -> IResult<&[u8], PacketLength> {
+    read_packet_len(input)
+}
+
 #[derive(Debug)]
 pub enum ParseResult<'a> {
     Fixed(&'a [u8]),
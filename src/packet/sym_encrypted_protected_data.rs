@@ -1,5 +1,11 @@
 use std::{fmt, io};
 
+use hkdf::Hkdf;
+use num_traits::FromPrimitive;
+use sha2::Sha256;
+use zeroize::Zeroize;
+
+use crate::crypto::aead::AeadAlgorithm;
 use crate::crypto::SymmetricKeyAlgorithm;
 use crate::errors::Result;
 use crate::packet::PacketTrait;
@@ -7,23 +13,69 @@ use crate::ser::Serialize;
 use crate::types::{Tag, Version};
 use rand::{thread_rng, CryptoRng, Rng};
 
+/// The chunk size octet used when encrypting, for chunks of 256 KiB.
+pub const DEFAULT_CHUNK_SIZE: u8 = 12;
+
 /// Symmetrically Encrypted Integrity Protected Data Packet
 /// https://tools.ietf.org/html/rfc4880.html#section-5.12
 #[derive(Clone, PartialEq, Eq)]
 pub struct SymEncryptedProtectedData {
     packet_version: Version,
+    config: ProtectedDataConfig,
     data: Vec<u8>,
 }
 
+/// The version specific fields of a [`SymEncryptedProtectedData`] packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtectedDataConfig {
+    /// Version 1, CFB encryption followed by a modification detection code.
+    V1,
+    /// Version 2, chunked AEAD encryption, as defined in RFC 9580.
+    ///
+    /// The symmetric algorithm is part of the packet, instead of the session key.
+    V2 {
+        sym_alg: SymmetricKeyAlgorithm,
+        aead: AeadAlgorithm,
+        /// Chunks are `2^(chunk_size + 6)` bytes long.
+        chunk_size: u8,
+        salt: [u8; 32],
+    },
+}
+
 impl SymEncryptedProtectedData {
-    /// Parses a `SymEncryptedData` packet from the given slice.
+    /// Parses a `SymEncryptedProtectedData` packet from the given slice.
     pub fn from_slice(packet_version: Version, input: &[u8]) -> Result<Self> {
         ensure!(input.len() > 1, "invalid input length");
-        ensure_eq!(input[0], 0x01, "first bytes must be 0x01");
+
+        let (config, data) = match input[0] {
+            0x01 => (ProtectedDataConfig::V1, &input[1..]),
+            0x02 => {
+                ensure!(input.len() > 36, "invalid input length");
+                let sym_alg = SymmetricKeyAlgorithm::from_u8(input[1])
+                    .ok_or_else(|| format_err!("invalid symmetric key algorithm"))?;
+                let aead = AeadAlgorithm::from_u8(input[2])
+                    .filter(|aead| *aead != AeadAlgorithm::None)
+                    .ok_or_else(|| format_err!("invalid AEAD algorithm"))?;
+                let chunk_size = input[3];
+                ensure!(chunk_size <= 16, "invalid chunk size {}", chunk_size);
+                let mut salt = [0u8; 32];
+                salt.copy_from_slice(&input[4..36]);
+
+                let config = ProtectedDataConfig::V2 {
+                    sym_alg,
+                    aead,
+                    chunk_size,
+                    salt,
+                };
+                (config, &input[36..])
+            }
+            version => unsupported_err!("SEIPD version {}", version),
+        };
 
         Ok(SymEncryptedProtectedData {
-            data: input[1..].to_vec(),
             packet_version,
+            config,
+            data: data.to_vec(),
         })
     }
 
@@ -38,6 +90,7 @@ impl SymEncryptedProtectedData {
 
         Ok(SymEncryptedProtectedData {
             packet_version: Default::default(),
+            config: ProtectedDataConfig::V1,
             data,
         })
     }
@@ -50,14 +103,163 @@ impl SymEncryptedProtectedData {
         Self::encrypt_with_rng(&mut thread_rng(), alg, key, plaintext)
     }
 
+    /// Encrypts the data using the given symmetric key into a version 2 packet, using
+    /// `aead` in chunks of `2^(chunk_size + 6)` bytes.
+    pub fn encrypt_aead_with_rng<R: CryptoRng + Rng>(
+        rng: &mut R,
+        alg: SymmetricKeyAlgorithm,
+        aead: AeadAlgorithm,
+        chunk_size: u8,
+        key: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Self> {
+        ensure!(chunk_size <= 16, "invalid chunk size {}", chunk_size);
+        ensure!(aead != AeadAlgorithm::None, "missing AEAD algorithm");
+        let mut salt = [0u8; 32];
+        rng.fill_bytes(&mut salt);
+
+        let mut packet = SymEncryptedProtectedData {
+            packet_version: Default::default(),
+            config: ProtectedDataConfig::V2 {
+                sym_alg: alg,
+                aead,
+                chunk_size,
+                salt,
+            },
+            data: Vec::new(),
+        };
+        packet.data = packet.crypt_chunks(key, plaintext, true)?;
+
+        Ok(packet)
+    }
+
+    /// Decrypts the data using the given session key, returning the plaintext.
+    ///
+    /// Fails if the data was modified. For version 2 packets, `alg` has to match the
+    /// symmetric algorithm of the packet.
+    pub fn decrypt(&self, key: &[u8], alg: SymmetricKeyAlgorithm) -> Result<Vec<u8>> {
+        match &self.config {
+            ProtectedDataConfig::V1 => {
+                let mut data = self.data.clone();
+                let plaintext = alg.decrypt_protected(key, &mut data)?;
+                Ok(plaintext.to_vec())
+            }
+            ProtectedDataConfig::V2 { sym_alg, .. } => {
+                ensure_eq!(alg, *sym_alg, "session key algorithm does not match");
+                self.crypt_chunks(key, &self.data, false)
+            }
+        }
+    }
+
+    pub fn version(&self) -> u8 {
+        match self.config {
+            ProtectedDataConfig::V1 => 1,
+            ProtectedDataConfig::V2 { .. } => 2,
+        }
+    }
+
+    pub fn config(&self) -> &ProtectedDataConfig {
+        &self.config
+    }
+
     pub fn data(&self) -> &[u8] {
         &self.data
     }
+
+    /// Encrypts or decrypts `input` in chunks, including the final authentication tag.
+    fn crypt_chunks(&self, session_key: &[u8], input: &[u8], encrypt: bool) -> Result<Vec<u8>> {
+        let (sym_alg, aead, chunk_size, salt) = match &self.config {
+            ProtectedDataConfig::V2 {
+                sym_alg,
+                aead,
+                chunk_size,
+                salt,
+            } => (*sym_alg, *aead, *chunk_size, salt),
+            ProtectedDataConfig::V1 => unreachable!("only used for version 2 packets"),
+        };
+        ensure_eq!(
+            session_key.len(),
+            sym_alg.key_size(),
+            "session key length does not match {:?}",
+            sym_alg
+        );
+
+        // the header of the packet is the associated data of every chunk
+        let header = [
+            0xC0 | Tag::SymEncryptedProtectedData as u8,
+            2,
+            sym_alg as u8,
+            aead as u8,
+            chunk_size,
+        ];
+
+        // the message key, followed by the leading part of the nonce
+        let key_size = sym_alg.key_size();
+        let mut okm = vec![0u8; key_size + aead.nonce_size() - 8];
+        Hkdf::<Sha256>::new(Some(&salt[..]), session_key)
+            .expand(&header, &mut okm)
+            .map_err(|_| format_err!("invalid key derivation length"))?;
+        let (key, iv) = okm.split_at(key_size);
+        let nonce = |index: u64| [iv, &index.to_be_bytes()[..]].concat();
+
+        let res = (|| -> Result<Vec<u8>> {
+            let tag_size = aead.tag_size();
+            let (chunks, final_tag, size) = if encrypt {
+                (input, &[][..], 1usize << (chunk_size + 6))
+            } else {
+                ensure!(input.len() >= tag_size, "missing final authentication tag");
+                let (chunks, final_tag) = input.split_at(input.len() - tag_size);
+                (chunks, final_tag, (1usize << (chunk_size + 6)) + tag_size)
+            };
+
+            let mut output = Vec::with_capacity(input.len() + tag_size);
+            let mut index = 0;
+            let mut plaintext_len = 0u64;
+            for chunk in chunks.chunks(size) {
+                let mut buffer = chunk.to_vec();
+                if encrypt {
+                    plaintext_len += buffer.len() as u64;
+                    aead.encrypt_in_place(sym_alg, key, &nonce(index), &header, &mut buffer)?;
+                } else {
+                    aead.decrypt_in_place(sym_alg, key, &nonce(index), &header, &mut buffer)?;
+                    plaintext_len += buffer.len() as u64;
+                }
+                output.extend_from_slice(&buffer);
+                index += 1;
+            }
+
+            // the final tag authenticates the number of chunks and the total length
+            let aad = [&header[..], &plaintext_len.to_be_bytes()[..]].concat();
+            let mut buffer = final_tag.to_vec();
+            if encrypt {
+                aead.encrypt_in_place(sym_alg, key, &nonce(index), &aad, &mut buffer)?;
+                output.extend_from_slice(&buffer);
+            } else {
+                aead.decrypt_in_place(sym_alg, key, &nonce(index), &aad, &mut buffer)?;
+            }
+
+            Ok(output)
+        })();
+        okm.zeroize();
+
+        res
+    }
 }
 
 impl Serialize for SymEncryptedProtectedData {
     fn to_writer<W: io::Write>(&self, writer: &mut W) -> Result<()> {
-        writer.write_all(&[0x01])?;
+        match &self.config {
+            ProtectedDataConfig::V1 => writer.write_all(&[0x01])?,
+            ProtectedDataConfig::V2 {
+                sym_alg,
+                aead,
+                chunk_size,
+                salt,
+            } => {
+                writer.write_all(&[0x02, *sym_alg as u8, *aead as u8, *chunk_size])?;
+                writer.write_all(salt)?;
+            }
+        }
         writer.write_all(&self.data)?;
 
         Ok(())
@@ -78,7 +280,141 @@ impl fmt::Debug for SymEncryptedProtectedData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("SymEncryptedProtectedData")
             .field("packet_version", &self.packet_version)
+            .field("config", &self.config)
             .field("data", &hex::encode(&self.data))
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    // from RFC 9580, Appendix A.10
+    #[test]
+    fn test_decrypt_v2_rfc9580() {
+        let body = hex::decode(
+            "020702\
+             0620a661f731fc9a3032b5623326027e3a5d8db5748ebeff0b0c5910d09ecdd641ff9fd38562758035\
+             bc49754ce1bf3fffa7dad0a3b8104f5133cf42a4100a83eef4ca1b4801a8846bf42bcda7c8ce9d65e2\
+             12f301cbcd98fdcade694a877ad4247323f6e857",
+        )
+        .unwrap();
+        let session_key = hex::decode("28e79ab82397d3c63de24ac217d7b791").unwrap();
+
+        let packet = SymEncryptedProtectedData::from_slice(Default::default(), &body).unwrap();
+        assert_eq!(packet.to_bytes().unwrap(), body);
+        // a literal data packet containing "Hello, world!", followed by a padding packet
+        let plaintext = packet
+            .decrypt(&session_key, SymmetricKeyAlgorithm::AES128)
+            .unwrap();
+        assert_eq!(
+            hex::encode(&plaintext[..23]),
+            "cb1362000000000048656c6c6f2c20776f726c6421d50e"
+        );
+        assert_eq!(plaintext.len(), 37);
+    }
+
+    #[test]
+    fn test_decrypt_v2() {
+        let body = hex::decode(
+            "02070200000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f90d3bc42\
+             7047e61c3b40f772ecd7b8a97ea4df4b8cf30687f20ceed485aeb3248a74baa739f68e96aee75170\
+             e2fd8b95522d75972234a6ed67c105c7c562da32f84a87ddac60e61fed2fc4f83d3aec4d65ef2cd3\
+             2fe21492d126b6955a2dff9f61d37af8dce70fd91410f96ab639043c53999dfd159b",
+        )
+        .unwrap();
+        let session_key = (1u8..17).collect::<Vec<_>>();
+        let alg = SymmetricKeyAlgorithm::AES128;
+
+        let packet = SymEncryptedProtectedData::from_slice(Default::default(), &body).unwrap();
+        assert_eq!(packet.version(), 2);
+        match packet.config() {
+            ProtectedDataConfig::V2 {
+                sym_alg,
+                aead,
+                chunk_size,
+                ..
+            } => {
+                assert_eq!(*sym_alg, alg);
+                assert_eq!(*aead, AeadAlgorithm::Ocb);
+                assert_eq!(*chunk_size, 0);
+            }
+            config => panic!("unexpected config {:?}", config),
+        }
+        assert_eq!(packet.to_bytes().unwrap(), body);
+        assert_eq!(
+            packet.decrypt(&session_key, alg).unwrap(),
+            &b"The quick brown fox jumps over the lazy dog, and keeps running for 70."[..]
+        );
+        assert!(packet
+            .decrypt(&session_key, SymmetricKeyAlgorithm::AES256)
+            .is_err());
+
+        // modified chunks, and chunks that were cut off are detected
+        let mut modified = body.clone();
+        modified[40] ^= 1;
+        let packet = SymEncryptedProtectedData::from_slice(Default::default(), &modified).unwrap();
+        assert!(packet.decrypt(&session_key, alg).is_err());
+
+        let mut truncated = body[..36 + 80].to_vec();
+        truncated.extend_from_slice(&body[body.len() - 16..]);
+        let packet = SymEncryptedProtectedData::from_slice(Default::default(), &truncated).unwrap();
+        assert!(packet.decrypt(&session_key, alg).is_err());
+
+        // chunk sizes larger than 16 are invalid
+        let mut invalid = body;
+        invalid[3] = 17;
+        assert!(SymEncryptedProtectedData::from_slice(Default::default(), &invalid).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_aead() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let plaintext = (0..1000).map(|i| (i % 256) as u8).collect::<Vec<_>>();
+
+        for &aead in &[AeadAlgorithm::Eax, AeadAlgorithm::Ocb, AeadAlgorithm::Gcm] {
+            for &alg in &[
+                SymmetricKeyAlgorithm::AES128,
+                SymmetricKeyAlgorithm::AES192,
+                SymmetricKeyAlgorithm::AES256,
+            ] {
+                let key = alg.new_session_key(&mut rng);
+                for &len in &[0, 1, 64, 65, 1000] {
+                    let packet = SymEncryptedProtectedData::encrypt_aead_with_rng(
+                        &mut rng,
+                        alg,
+                        aead,
+                        0,
+                        &key,
+                        &plaintext[..len],
+                    )
+                    .unwrap();
+                    let chunks = (len + 63) / 64;
+                    assert_eq!(packet.data().len(), len + (chunks + 1) * 16);
+
+                    let parsed = SymEncryptedProtectedData::from_slice(
+                        Default::default(),
+                        &packet.to_bytes().unwrap(),
+                    )
+                    .unwrap();
+                    assert_eq!(parsed, packet);
+                    assert_eq!(parsed.decrypt(&key, alg).unwrap(), &plaintext[..len]);
+                }
+            }
+        }
+
+        assert!(SymEncryptedProtectedData::encrypt_aead_with_rng(
+            &mut rng,
+            SymmetricKeyAlgorithm::AES128,
+            AeadAlgorithm::Ocb,
+            17,
+            &[0; 16],
+            &plaintext,
+        )
+        .is_err());
+    }
+}
@@ -1,8 +1,13 @@
 use std::io;
 
+use hkdf::Hkdf;
 use nom::{be_u8, rest};
 use num_traits::FromPrimitive;
+use rand::{CryptoRng, Rng};
+use sha2::Sha256;
+use zeroize::Zeroize;
 
+use crate::crypto::aead::AeadAlgorithm;
 use crate::crypto::sym::SymmetricKeyAlgorithm;
 use crate::errors::Result;
 use crate::packet::PacketTrait;
@@ -17,6 +22,8 @@ pub struct SymKeyEncryptedSessionKey {
     version: u8,
     sym_algorithm: SymmetricKeyAlgorithm,
     s2k: StringToKey,
+    /// The AEAD algorithm and nonce of version 6 packets.
+    aead: Option<(AeadAlgorithm, Vec<u8>)>,
     encrypted_key: Option<Vec<u8>>,
 }
 
@@ -26,13 +33,20 @@ impl SymKeyEncryptedSessionKey {
         let (_, pk) = parse(input, version)?;
 
         ensure!(
-            pk.version == 0x04 || pk.version == 0x05,
-            "Version 4 and 5 are the only known version"
+            pk.version == 0x04 || pk.version == 0x05 || pk.version == 0x06,
+            "Version 4, 5 and 6 are the only known versions"
         );
+        if let Some((aead, _)) = pk.aead {
+            ensure!(aead != AeadAlgorithm::None, "missing AEAD algorithm");
+        }
 
         Ok(pk)
     }
 
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
     pub fn sym_algorithm(&self) -> SymmetricKeyAlgorithm {
         self.sym_algorithm
     }
@@ -41,10 +55,25 @@ impl SymKeyEncryptedSessionKey {
         &self.s2k
     }
 
+    /// The AEAD algorithm and nonce, that the session key of version 6 packets is
+    /// encrypted with.
+    pub fn aead(&self) -> Option<(AeadAlgorithm, &[u8])> {
+        self.aead.as_ref().map(|(aead, nonce)| (*aead, &nonce[..]))
+    }
+
     pub fn encrypted_key(&self) -> &Option<Vec<u8>> {
         &self.encrypted_key
     }
 
+    /// Derives the key that the session key of a version 6 packet is encrypted with from
+    /// the `key` derived by the s2k. Returns it, together with the associated data.
+    pub(crate) fn key_encryption_key(&self, key: &[u8]) -> Result<(Vec<u8>, [u8; 4])> {
+        match self.aead {
+            Some((aead, _)) => key_encryption_key(key, self.sym_algorithm, aead),
+            None => bail!("version {} packets are not AEAD encrypted", self.version),
+        }
+    }
+
     pub fn encrypt<F>(
         msg_pw: F,
         session_key: &[u8],
@@ -74,15 +103,107 @@ impl SymKeyEncryptedSessionKey {
             version: 0x04,
             s2k,
             sym_algorithm: alg,
+            aead: None,
             encrypted_key: Some(encrypted_key),
         })
     }
+
+    /// Creates a version 6 packet, which encrypts the session key using `aead`.
+    ///
+    /// The algorithm of the session key is not included, it must match the one of the
+    /// version 2 SEIPD packet it is used for.
+    pub fn encrypt_v6<R, F>(
+        rng: &mut R,
+        msg_pw: F,
+        session_key: &[u8],
+        s2k: StringToKey,
+        alg: SymmetricKeyAlgorithm,
+        aead: AeadAlgorithm,
+    ) -> Result<Self>
+    where
+        R: CryptoRng + Rng,
+        F: FnOnce() -> String + Clone,
+    {
+        ensure!(
+            s2k.salt().is_some(),
+            "can not use an s2k algorithm without a salt"
+        );
+        ensure!(aead != AeadAlgorithm::None, "missing AEAD algorithm");
+
+        let key = s2k.derive_key(&msg_pw(), alg.key_size())?;
+        let (mut kek, info) = key_encryption_key(&key, alg, aead)?;
+
+        let mut nonce = vec![0u8; aead.nonce_size()];
+        rng.fill(&mut nonce[..]);
+
+        let mut encrypted_key = session_key.to_vec();
+        let res = aead.encrypt_in_place(alg, &kek, &nonce, &info, &mut encrypted_key);
+        kek.zeroize();
+        res?;
+
+        Ok(SymKeyEncryptedSessionKey {
+            packet_version: Default::default(),
+            version: 0x06,
+            s2k,
+            sym_algorithm: alg,
+            aead: Some((aead, nonce)),
+            encrypted_key: Some(encrypted_key),
+        })
+    }
+}
+
+/// HKDF-SHA256 of the s2k derived `key`, with the packet header as the info.
+fn key_encryption_key(
+    key: &[u8],
+    alg: SymmetricKeyAlgorithm,
+    aead: AeadAlgorithm,
+) -> Result<(Vec<u8>, [u8; 4])> {
+    let info = [
+        0xC0 | Tag::SymKeyEncryptedSessionKey as u8,
+        0x06,
+        alg as u8,
+        aead as u8,
+    ];
+    let mut kek = vec![0u8; alg.key_size()];
+    Hkdf::<Sha256>::new(None, key)
+        .expand(&info, &mut kek)
+        .map_err(|_| format_err!("invalid key derivation length"))?;
+
+    Ok((kek, info))
 }
 
 #[rustfmt::skip]
 named_args!(parse(packet_version: Version) <SymKeyEncryptedSessionKey>, do_parse!(
               version: be_u8
+    >>          skesk: switch!(value!(version),
+                           6 => call!(parse_v6, packet_version) |
+                           _ => call!(parse_v4, packet_version, version)
+                       )
+    >> (skesk)
+));
+
+#[rustfmt::skip]
+named_args!(parse_v6(packet_version: Version) <SymKeyEncryptedSessionKey>, do_parse!(
+               _count: be_u8
     >>        sym_alg: map_opt!(be_u8, SymmetricKeyAlgorithm::from_u8)
+    >>           aead: map_opt!(be_u8, AeadAlgorithm::from_u8)
+    >>        s2k_len: be_u8
+    >>            s2k: flat_map!(take!(s2k_len), s2k_parser)
+    >>          nonce: take!(aead.nonce_size())
+    >>  encrypted_key: rest
+    >> (SymKeyEncryptedSessionKey {
+        packet_version,
+        version: 6,
+        sym_algorithm: sym_alg,
+        s2k,
+        aead: Some((aead, nonce.to_vec())),
+        encrypted_key: Some(encrypted_key.to_vec()),
+    })
+));
+
+#[rustfmt::skip]
+named_args!(parse_v4(packet_version: Version, version: u8) <SymKeyEncryptedSessionKey>, do_parse!(
+              sym_alg: map_opt!(be_u8, SymmetricKeyAlgorithm::from_u8)
     >>            s2k: s2k_parser
     >>  encrypted_key: rest
     >> ({
@@ -97,6 +218,7 @@ named_args!(parse(packet_version: Version) <SymKeyEncryptedSessionKey>, do_parse
             version,
             sym_algorithm: sym_alg,
             s2k,
+            aead: None,
             encrypted_key,
         }
     })
@@ -104,8 +226,24 @@ named_args!(parse(packet_version: Version) <SymKeyEncryptedSessionKey>, do_parse
 
 impl Serialize for SymKeyEncryptedSessionKey {
     fn to_writer<W: io::Write>(&self, writer: &mut W) -> Result<()> {
-        writer.write_all(&[self.version, self.sym_algorithm as u8])?;
-        self.s2k.to_writer(writer)?;
+        match self.aead {
+            Some((aead, ref nonce)) => {
+                let s2k = self.s2k.to_bytes()?;
+                writer.write_all(&[
+                    self.version,
+                    (3 + s2k.len() + nonce.len()) as u8,
+                    self.sym_algorithm as u8,
+                    aead as u8,
+                    s2k.len() as u8,
+                ])?;
+                writer.write_all(&s2k)?;
+                writer.write_all(nonce)?;
+            }
+            None => {
+                writer.write_all(&[self.version, self.sym_algorithm as u8])?;
+                self.s2k.to_writer(writer)?;
+            }
+        }
 
         if let Some(ref key) = self.encrypted_key {
             writer.write_all(key)?;
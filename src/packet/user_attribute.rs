@@ -1,12 +1,15 @@
 use std::{fmt, io};
 
-use chrono::{SubsecRound, Utc};
+use chrono::{DateTime, SubsecRound, Utc};
 
 use byteorder::{LittleEndian, WriteBytesExt};
 use nom::{be_u8, le_u16, rest};
+use rand::{thread_rng, CryptoRng, Rng};
 
 use crate::errors::Result;
-use crate::packet::{PacketTrait, Signature, SignatureConfigBuilder, SignatureType, Subpacket};
+use crate::packet::{
+    PacketTrait, Signature, SignatureConfigBuilder, SignatureType, SignatureVersion, Subpacket,
+};
 use crate::ser::Serialize;
 use crate::types::{SecretKeyTrait, SignedUserAttribute, Tag, Version};
 use crate::util::{packet_length, write_packet_length};
@@ -35,6 +38,43 @@ impl UserAttribute {
         Ok(pk)
     }
 
+    /// Creates a new image attribute, containing the given JPEG image.
+    pub fn new_image(data: Vec<u8>) -> Result<Self> {
+        ensure!(
+            data.starts_with(&[0xFF, 0xD8, 0xFF]),
+            "image is not a JPEG image"
+        );
+
+        // header version 1, encoding 1 (JPEG), followed by 12 reserved octets
+        let mut header = vec![0u8; 14];
+        header[0] = 0x01;
+        header[1] = 0x01;
+
+        Ok(UserAttribute::Image {
+            packet_version: Version::New,
+            header,
+            data,
+        })
+    }
+
+    /// Creates a new user attribute with a custom subpacket type, such as one
+    /// from the private/experimental range (100-110).
+    pub fn new_unknown(typ: u8, data: Vec<u8>) -> Self {
+        UserAttribute::Unknown {
+            packet_version: Version::New,
+            typ,
+            data,
+        }
+    }
+
+    /// Returns the raw subpacket body, excluding the type.
+    pub fn data(&self) -> &[u8] {
+        match self {
+            UserAttribute::Image { ref data, .. } => data,
+            UserAttribute::Unknown { ref data, .. } => data,
+        }
+    }
+
     pub fn to_u8(&self) -> u8 {
         match *self {
             UserAttribute::Image { .. } => 1,
@@ -44,9 +84,13 @@ impl UserAttribute {
 
     pub fn packet_len(&self) -> usize {
         match self {
-            UserAttribute::Image { ref data, .. } => {
-                // typ + image header + data length
-                1 + 16 + data.len()
+            UserAttribute::Image {
+                ref header,
+                ref data,
+                ..
+            } => {
+                // typ + image header length + image header + data length
+                1 + 2 + header.len() + data.len()
             }
             UserAttribute::Unknown { ref data, .. } => {
                 // typ + data length
@@ -58,15 +102,48 @@ impl UserAttribute {
     pub fn sign<F>(&self, key: &impl SecretKeyTrait, key_pw: F) -> Result<SignedUserAttribute>
     where
         F: FnOnce() -> String,
+    {
+        self.sign_at(key, key_pw, Utc::now().trunc_subsecs(0))
+    }
+
+    /// Same as [`sign`], but with the given signature creation time.
+    ///
+    /// [`sign`]: UserAttribute::sign
+    pub fn sign_at<F>(
+        &self,
+        key: &impl SecretKeyTrait,
+        key_pw: F,
+        created: DateTime<Utc>,
+    ) -> Result<SignedUserAttribute>
+    where
+        F: FnOnce() -> String,
+    {
+        self.sign_at_with_rng(&mut thread_rng(), key, key_pw, created)
+    }
+
+    /// Same as [`sign_at`], but generates the salt of v6 signatures from `rng`.
+    ///
+    /// [`sign_at`]: UserAttribute::sign_at
+    pub fn sign_at_with_rng<R, F>(
+        &self,
+        rng: &mut R,
+        key: &impl SecretKeyTrait,
+        key_pw: F,
+        created: DateTime<Utc>,
+    ) -> Result<SignedUserAttribute>
+    where
+        R: CryptoRng + Rng,
+        F: FnOnce() -> String,
     {
         let config = SignatureConfigBuilder::default()
             .typ(SignatureType::CertGeneric)
+            .version(SignatureVersion::for_key(key))
             .pub_alg(key.algorithm())
-            .hashed_subpackets(vec![Subpacket::SignatureCreationTime(
-                Utc::now().trunc_subsecs(0),
-            )])
-            .unhashed_subpackets(vec![Subpacket::Issuer(key.key_id())])
-            .build()?;
+            .hashed_subpackets(vec![Subpacket::SignatureCreationTime(created)])
+            .unhashed_subpackets(vec![])
+            .build()?
+            .with_issuer(key)
+            .salted(rng)?;
 
         let sig = config.sign_certificate(key, key_pw, self.tag(), &self)?;
 
@@ -94,8 +171,8 @@ impl fmt::Display for UserAttribute {
 #[rustfmt::skip]
 named_args!(image(packet_version: Version) <UserAttribute>, do_parse!(
     // little endian, for historical reasons..
-       header_len: le_u16
-    >>     header: take!(header_len - 2)
+       header_len: map_opt!(le_u16, |len: u16| len.checked_sub(2))
+    >>     header: take!(header_len)
     // the actual image is the rest
     >>         img: rest
     >> (UserAttribute::Image {
@@ -166,7 +243,7 @@ impl fmt::Debug for UserAttribute {
                 .field("data", &hex::encode(data))
                 .finish(),
             UserAttribute::Unknown { typ, ref data, .. } => f
-                .debug_struct("UserAttribute::Image")
+                .debug_struct("UserAttribute::Unknown")
                 .field("type", &hex::encode(&[*typ]))
                 .field("data", &hex::encode(data))
                 .finish(),
@@ -186,3 +263,53 @@ impl PacketTrait for UserAttribute {
         Tag::UserAttribute
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_roundtrip() {
+        let attr = UserAttribute::new_unknown(100, vec![1, 2, 3, 4, 5]);
+        let bytes = attr.to_bytes().unwrap();
+        assert_eq!(bytes, vec![6, 100, 1, 2, 3, 4, 5]);
+
+        let parsed = UserAttribute::from_slice(Version::New, &bytes).unwrap();
+        assert_eq!(parsed, attr);
+        assert_eq!(parsed.to_u8(), 100);
+        assert_eq!(parsed.data(), &[1, 2, 3, 4, 5][..]);
+    }
+
+    #[test]
+    fn test_image_roundtrip() {
+        let attr = UserAttribute::Image {
+            packet_version: Version::New,
+            header: vec![0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            data: vec![0xFF, 0xD8, 0xFF],
+        };
+        let bytes = attr.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 1 + attr.packet_len());
+
+        let parsed = UserAttribute::from_slice(Version::New, &bytes).unwrap();
+        assert_eq!(parsed, attr);
+    }
+
+    #[test]
+    fn test_new_image() {
+        let attr = UserAttribute::new_image(vec![0xFF, 0xD8, 0xFF, 0xE0]).unwrap();
+        let bytes = attr.to_bytes().unwrap();
+        assert_eq!(
+            bytes,
+            vec![
+                21, 0x01, 0x10, 0x00, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xD8,
+                0xFF, 0xE0
+            ]
+        );
+
+        let parsed = UserAttribute::from_slice(Version::New, &bytes).unwrap();
+        assert_eq!(parsed, attr);
+        assert_eq!(parsed.to_u8(), 1);
+
+        assert!(UserAttribute::new_image(vec![0x89, b'P', b'N', b'G']).is_err());
+    }
+}
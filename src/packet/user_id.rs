@@ -3,7 +3,9 @@ use std::{fmt, io, str};
 use chrono::{SubsecRound, Utc};
 
 use crate::errors::Result;
-use crate::packet::{PacketTrait, Signature, SignatureConfigBuilder, SignatureType, Subpacket};
+use crate::packet::{
+    PacketTrait, Signature, SignatureConfigBuilder, SignatureType, SignatureVersion, Subpacket,
+};
 use crate::ser::Serialize;
 use crate::types::{SecretKeyTrait, SignedUser, Tag, Version};
 use crate::util::{read_string, write_string};
@@ -41,12 +43,14 @@ impl UserId {
     {
         let config = SignatureConfigBuilder::default()
             .typ(SignatureType::CertGeneric)
+            .version(SignatureVersion::for_key(key))
             .pub_alg(key.algorithm())
             .hashed_subpackets(vec![Subpacket::SignatureCreationTime(
                 Utc::now().trunc_subsecs(0),
             )])
-            .unhashed_subpackets(vec![Subpacket::Issuer(key.key_id())])
-            .build()?;
+            .unhashed_subpackets(vec![])
+            .build()?
+            .with_issuer(key);
 
         let sig = config.sign_certificate(key, key_pw, self.tag(), &self)?;
 
@@ -0,0 +1,117 @@
+use crate::armor::Headers;
+use crate::errors::Result;
+
+/// Character sets of text data, as declared by the `Charset` armor header.
+///
+/// Used for interoperability with old clients, that send text in encodings other than UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Utf8,
+    /// ISO-8859-1
+    Latin1,
+}
+
+impl Default for Charset {
+    fn default() -> Self {
+        Charset::Utf8
+    }
+}
+
+impl Charset {
+    /// The name of the armor header.
+    pub const HEADER: &'static str = "Charset";
+
+    /// Looks up a charset by name, ignoring case.
+    /// US-ASCII is treated as UTF-8, as it is a subset of it.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" | "us-ascii" | "ascii" => Some(Charset::Utf8),
+            "iso-8859-1" | "iso8859-1" | "latin1" | "latin-1" => Some(Charset::Latin1),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Charset::Utf8 => "UTF-8",
+            Charset::Latin1 => "ISO-8859-1",
+        }
+    }
+
+    /// Reads the charset from the given armor headers, `None` if there is no `Charset` header.
+    pub fn from_headers(headers: &Headers) -> Result<Option<Self>> {
+        match headers.get(Self::HEADER) {
+            Some(name) => match Self::from_name(name) {
+                Some(charset) => Ok(Some(charset)),
+                None => unsupported_err!("charset {:?}", name),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Declares this charset in the given armor headers.
+    pub fn to_headers(self, headers: &mut Headers) {
+        headers.insert(Self::HEADER, self.name());
+    }
+
+    /// Decodes text in this charset.
+    pub fn decode(self, data: &[u8]) -> Result<String> {
+        match self {
+            Charset::Utf8 => Ok(std::str::from_utf8(data)?.to_string()),
+            // the first 256 unicode code points are the same as in ISO-8859-1
+            Charset::Latin1 => Ok(data.iter().map(|b| char::from(*b)).collect()),
+        }
+    }
+
+    /// Encodes text in this charset.
+    pub fn encode(self, text: &str) -> Result<Vec<u8>> {
+        match self {
+            Charset::Utf8 => Ok(text.as_bytes().to_vec()),
+            Charset::Latin1 => text
+                .chars()
+                .map(|c| {
+                    let code = u32::from(c);
+                    ensure!(code <= 0xFF, "{:?} can not be encoded in ISO-8859-1", c);
+                    Ok(code as u8)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_charset_from_headers() {
+        let mut headers = Headers::new();
+        assert_eq!(Charset::from_headers(&headers).unwrap(), None);
+
+        Charset::Latin1.to_headers(&mut headers);
+        assert_eq!(headers.get("Charset"), Some("ISO-8859-1"));
+        assert_eq!(
+            Charset::from_headers(&headers).unwrap(),
+            Some(Charset::Latin1)
+        );
+
+        headers.insert("Charset", "utf-8");
+        assert_eq!(
+            Charset::from_headers(&headers).unwrap(),
+            Some(Charset::Utf8)
+        );
+
+        headers.insert("Charset", "KOI8-R");
+        assert!(Charset::from_headers(&headers).is_err());
+    }
+
+    #[test]
+    fn test_charset_latin1() {
+        let data = Charset::Latin1.encode("Grüße").unwrap();
+        assert_eq!(data, b"Gr\xfc\xdfe".to_vec());
+        assert_eq!(Charset::Latin1.decode(&data).unwrap(), "Grüße");
+        assert!(Charset::Utf8.decode(&data).is_err());
+
+        assert!(Charset::Latin1.encode("€").is_err());
+    }
+}
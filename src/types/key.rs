@@ -1,5 +1,5 @@
 use crate::crypto::public_key::PublicKeyAlgorithm;
-use crate::types::KeyId;
+use crate::types::{KeyId, KeyVersion};
 
 pub trait KeyTrait: ::std::fmt::Debug {
     fn fingerprint(&self) -> Vec<u8>;
@@ -9,11 +9,13 @@ pub trait KeyTrait: ::std::fmt::Debug {
 
     fn algorithm(&self) -> PublicKeyAlgorithm;
 
+    fn version(&self) -> KeyVersion;
+
     fn is_signing_key(&self) -> bool {
         use crate::crypto::PublicKeyAlgorithm::*;
 
         match self.algorithm() {
-            RSA | RSASign | ElgamalSign | DSA | ECDSA | EdDSA => true,
+            RSA | RSASign | ElgamalSign | DSA | ECDSA | EdDSA | Ed25519 | Ed448 => true,
             _ => false,
         }
     }
@@ -22,7 +24,7 @@ pub trait KeyTrait: ::std::fmt::Debug {
         use crate::crypto::PublicKeyAlgorithm::*;
 
         match self.algorithm() {
-            RSA | RSAEncrypt | ECDH | DiffieHellman | Elgamal => true,
+            RSA | RSAEncrypt | ECDH | DiffieHellman | Elgamal | X25519 | X448 => true,
             _ => false,
         }
     }
@@ -41,4 +43,8 @@ impl<'a, T: KeyTrait> KeyTrait for &'a T {
     fn algorithm(&self) -> PublicKeyAlgorithm {
         (*self).algorithm()
     }
+
+    fn version(&self) -> KeyVersion {
+        (*self).version()
+    }
 }
@@ -24,6 +24,15 @@ impl KeyId {
     pub fn to_vec(&self) -> Vec<u8> {
         self.0.to_vec()
     }
+
+    /// The all zero key id, used for anonymous recipients.
+    pub fn wildcard() -> KeyId {
+        KeyId([0u8; 8])
+    }
+
+    pub fn is_wildcard(&self) -> bool {
+        self.0.iter().all(|b| *b == 0)
+    }
 }
 
 impl fmt::Debug for KeyId {
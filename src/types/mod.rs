@@ -1,3 +1,4 @@
+mod charset;
 mod compression;
 mod key;
 mod key_id;
@@ -11,6 +12,7 @@ mod secret_key;
 mod secret_key_repr;
 mod user;
 
+pub use self::charset::*;
 pub use self::compression::*;
 pub use self::key::*;
 pub use self::key_id::*;
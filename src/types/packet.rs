@@ -1,8 +1,10 @@
-use std::io;
+use std::convert::TryFrom;
+use std::{fmt, io};
 
 use byteorder::{BigEndian, WriteBytesExt};
+use num_traits::FromPrimitive;
 
-use crate::errors::Result;
+use crate::errors::{Error, Result};
 
 /// Represents a Packet. A packet is the record structure used to encode a chunk of data in OpenPGP.
 /// Ref: https://tools.ietf.org/html/rfc4880.html#section-4
@@ -70,6 +72,138 @@ pub enum Tag {
     ModDetectionCode = 19,
 }
 
+/// The role of a packet, see [`Tag::kind`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PacketKind {
+    /// Primary keys and subkeys, public or secret.
+    KeyMaterial,
+    /// User ids and user attributes.
+    Identity,
+    /// Signatures and one-pass signatures.
+    Signature,
+    /// The content of messages, including encrypted session keys.
+    Data,
+    /// Marker and trust packets, which carry no information.
+    Other,
+}
+
+impl Tag {
+    /// All known tags, ordered by their number.
+    pub const ALL: [Tag; 17] = [
+        Tag::PublicKeyEncryptedSessionKey,
+        Tag::Signature,
+        Tag::SymKeyEncryptedSessionKey,
+        Tag::OnePassSignature,
+        Tag::SecretKey,
+        Tag::PublicKey,
+        Tag::SecretSubkey,
+        Tag::CompressedData,
+        Tag::SymEncryptedData,
+        Tag::Marker,
+        Tag::LiteralData,
+        Tag::Trust,
+        Tag::UserId,
+        Tag::PublicSubkey,
+        Tag::UserAttribute,
+        Tag::SymEncryptedProtectedData,
+        Tag::ModDetectionCode,
+    ];
+
+    /// The name of the packet, as used in RFC 4880.
+    pub fn name(self) -> &'static str {
+        match self {
+            Tag::PublicKeyEncryptedSessionKey => "Public-Key Encrypted Session Key",
+            Tag::Signature => "Signature",
+            Tag::SymKeyEncryptedSessionKey => "Symmetric-Key Encrypted Session Key",
+            Tag::OnePassSignature => "One-Pass Signature",
+            Tag::SecretKey => "Secret-Key",
+            Tag::PublicKey => "Public-Key",
+            Tag::SecretSubkey => "Secret-Subkey",
+            Tag::CompressedData => "Compressed Data",
+            Tag::SymEncryptedData => "Symmetrically Encrypted Data",
+            Tag::Marker => "Marker",
+            Tag::LiteralData => "Literal Data",
+            Tag::Trust => "Trust",
+            Tag::UserId => "User ID",
+            Tag::PublicSubkey => "Public-Subkey",
+            Tag::UserAttribute => "User Attribute",
+            Tag::SymEncryptedProtectedData => "Sym. Encrypted and Integrity Protected Data",
+            Tag::ModDetectionCode => "Modification Detection Code",
+        }
+    }
+
+    pub fn kind(self) -> PacketKind {
+        match self {
+            Tag::SecretKey | Tag::PublicKey | Tag::SecretSubkey | Tag::PublicSubkey => {
+                PacketKind::KeyMaterial
+            }
+            Tag::UserId | Tag::UserAttribute => PacketKind::Identity,
+            Tag::Signature | Tag::OnePassSignature => PacketKind::Signature,
+            Tag::PublicKeyEncryptedSessionKey
+            | Tag::SymKeyEncryptedSessionKey
+            | Tag::CompressedData
+            | Tag::SymEncryptedData
+            | Tag::LiteralData
+            | Tag::SymEncryptedProtectedData
+            | Tag::ModDetectionCode => PacketKind::Data,
+            Tag::Marker | Tag::Trust => PacketKind::Other,
+        }
+    }
+
+    pub fn is_key_material(self) -> bool {
+        self.kind() == PacketKind::KeyMaterial
+    }
+
+    pub fn is_signature(self) -> bool {
+        self.kind() == PacketKind::Signature
+    }
+
+    pub fn is_data(self) -> bool {
+        self.kind() == PacketKind::Data
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} Packet", self.name())
+    }
+}
+
+impl TryFrom<u8> for Tag {
+    type Error = Error;
+
+    fn try_from(tag: u8) -> Result<Self> {
+        Tag::from_u8(tag).ok_or_else(|| format_err!("unknown packet tag {}", tag))
+    }
+}
+
+impl From<Tag> for u8 {
+    fn from(tag: Tag) -> Self {
+        tag as u8
+    }
+}
+
+/// Controls how the length of a packet body is encoded when serializing.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LengthEncoding {
+    /// The shortest definite length encoding.
+    Minimal,
+    /// Always use the four octet definite length encoding.
+    Full,
+    /// Partial body lengths with chunks of `2^n` octets (new format only).
+    /// Ref: https://tools.ietf.org/html/rfc4880.html#section-4.2.2.4
+    Partial(u8),
+    /// Indeterminate length, the packet extends to the end of the input (old format only).
+    /// Ref: https://tools.ietf.org/html/rfc4880.html#section-4.2.1
+    Indeterminate,
+}
+
+impl Default for LengthEncoding {
+    fn default() -> Self {
+        LengthEncoding::Minimal
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy, FromPrimitive)]
 #[repr(u8)]
 pub enum Version {
@@ -120,6 +254,23 @@ impl Version {
 
         Ok(())
     }
+
+    /// Writes a header using the four octet length encoding, independent of `len`.
+    pub fn write_header_full(self, writer: &mut impl io::Write, tag: u8, len: usize) -> Result<()> {
+        debug!("write_header_full {:?} {} {}", self, tag, len);
+
+        match self {
+            Version::Old => {
+                writer.write_all(&[0b1000_0010 | tag << 2])?;
+            }
+            Version::New => {
+                writer.write_all(&[0b1100_0000 | tag, 255])?;
+            }
+        }
+        writer.write_u32::<BigEndian>(len as u32)?;
+
+        Ok(())
+    }
 }
 
 // TODO: find a better place for this
@@ -130,6 +281,7 @@ pub enum KeyVersion {
     V3 = 3,
     V4 = 4,
     V5 = 5,
+    V6 = 6,
 }
 
 impl Default for KeyVersion {
@@ -142,6 +294,27 @@ impl Default for KeyVersion {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_tag_conversions() {
+        for &tag in Tag::ALL.iter() {
+            assert_eq!(Tag::try_from(u8::from(tag)).unwrap(), tag);
+        }
+        for &unknown in &[0u8, 15, 16, 20, 255] {
+            assert!(Tag::try_from(unknown).is_err());
+        }
+        assert_eq!(
+            Tag::ALL.iter().filter(|tag| tag.is_key_material()).count(),
+            4
+        );
+
+        assert_eq!(Tag::UserId.to_string(), "User ID Packet");
+        assert_eq!(Tag::SecretSubkey.kind(), PacketKind::KeyMaterial);
+        assert_eq!(Tag::UserAttribute.kind(), PacketKind::Identity);
+        assert!(Tag::OnePassSignature.is_signature());
+        assert!(Tag::SymEncryptedProtectedData.is_data());
+        assert!(!Tag::Marker.is_data());
+    }
+
     #[test]
     fn test_write_header() {
         let mut buf = Vec::new();
@@ -165,4 +338,21 @@ mod tests {
 
         assert_eq!(hex::encode(buf), "c2c06f");
     }
+
+    #[test]
+    fn test_write_header_full() {
+        let mut buf = Vec::new();
+        Version::New
+            .write_header_full(&mut buf, Tag::Signature as u8, 303)
+            .unwrap();
+
+        assert_eq!(hex::encode(buf), "c2ff0000012f");
+
+        let mut buf = Vec::new();
+        Version::Old
+            .write_header_full(&mut buf, Tag::Signature as u8, 303)
+            .unwrap();
+
+        assert_eq!(hex::encode(buf), "8a0000012f");
+    }
 }
@@ -1,7 +1,11 @@
 use std::{fmt, io};
 
 use byteorder::{BigEndian, ByteOrder};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use zeroize::Zeroize;
 
+use crate::crypto::aead::AeadAlgorithm;
 use crate::crypto::checksum;
 use crate::crypto::public_key::PublicKeyAlgorithm;
 use crate::crypto::sym::SymmetricKeyAlgorithm;
@@ -13,10 +17,12 @@ use crate::types::*;
 pub struct EncryptedSecretParams {
     /// The encrypted data.
     data: Vec<u8>,
-    /// IV.
+    /// IV, or the nonce when using AEAD.
     iv: Vec<u8>,
     /// The encryption algorithm used.
     encryption_algorithm: SymmetricKeyAlgorithm,
+    /// The AEAD mode, only set for string-to-key usage 253.
+    aead_algorithm: Option<AeadAlgorithm>,
     /// The string-to-key method and its parameters.
     string_to_key: StringToKey,
     /// The identifier for how this data is stored.
@@ -31,16 +37,37 @@ impl EncryptedSecretParams {
         s2k: StringToKey,
         id: u8,
     ) -> Self {
-        assert_ne!(id, 0, "invalid string to key id");
+        assert_ne!(id, S2K_USAGE_UNPROTECTED, "invalid string to key id");
+        assert_ne!(id, S2K_USAGE_AEAD, "use new_aead for AEAD protected keys");
         EncryptedSecretParams {
             data,
             iv,
             encryption_algorithm: alg,
+            aead_algorithm: None,
             string_to_key: s2k,
             string_to_key_id: id,
         }
     }
 
+    /// Creates AEAD protected params (string-to-key usage 253).
+    /// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#section-5.5.3
+    pub fn new_aead(
+        data: Vec<u8>,
+        nonce: Vec<u8>,
+        alg: SymmetricKeyAlgorithm,
+        aead: AeadAlgorithm,
+        s2k: StringToKey,
+    ) -> Self {
+        EncryptedSecretParams {
+            data,
+            iv: nonce,
+            encryption_algorithm: alg,
+            aead_algorithm: Some(aead),
+            string_to_key: s2k,
+            string_to_key_id: S2K_USAGE_AEAD,
+        }
+    }
+
     pub fn data(&self) -> &[u8] {
         &self.data
     }
@@ -53,6 +80,10 @@ impl EncryptedSecretParams {
         self.encryption_algorithm
     }
 
+    pub fn aead_algorithm(&self) -> Option<AeadAlgorithm> {
+        self.aead_algorithm
+    }
+
     pub fn string_to_key(&self) -> &StringToKey {
         &self.string_to_key
     }
@@ -62,7 +93,7 @@ impl EncryptedSecretParams {
     }
 
     pub fn compare_checksum(&self, other: Option<&[u8]>) -> Result<()> {
-        if self.string_to_key_id < 254 {
+        if self.string_to_key_id < S2K_USAGE_AEAD {
             if let Some(other) = other {
                 ensure_eq!(
                     BigEndian::read_u16(other),
@@ -80,7 +111,7 @@ impl EncryptedSecretParams {
     }
 
     pub fn checksum(&self) -> Option<Vec<u8>> {
-        if self.string_to_key_id < 254 {
+        if self.string_to_key_id < S2K_USAGE_AEAD {
             Some(
                 checksum::calculate_simple(self.data())
                     .to_be_bytes()
@@ -91,20 +122,166 @@ impl EncryptedSecretParams {
         }
     }
 
-    pub fn unlock<F>(&self, pw: F, alg: PublicKeyAlgorithm) -> Result<PlainSecretParams>
+    /// Decrypts the secret key material.
+    ///
+    /// `aad` is only used by AEAD protected keys, it is the packet header octet of the
+    /// secret key packet, followed by the serialized public key, which the encryption is
+    /// bound to.
+    pub fn unlock<F>(&self, pw: F, alg: PublicKeyAlgorithm, aad: &[u8]) -> Result<PlainSecretParams>
     where
         F: FnOnce() -> String,
     {
-        let key = self
-            .string_to_key
-            .derive_key(&pw(), self.encryption_algorithm.key_size())?;
+        self.unlock_with_limits(pw, alg, aad, &Argon2Limits::default())
+    }
+
+    /// Same as `unlock`, but accepts Argon2 string-to-keys up to `limits`, instead of the
+    /// default [`Argon2Limits`].
+    pub fn unlock_with_limits<F>(
+        &self,
+        pw: F,
+        alg: PublicKeyAlgorithm,
+        aad: &[u8],
+        limits: &Argon2Limits,
+    ) -> Result<PlainSecretParams>
+    where
+        F: FnOnce() -> String,
+    {
+        let pw = pw();
+        let res = self.unlock_with(pw.as_bytes(), alg, aad, limits);
+        if res.is_ok() {
+            return res;
+        }
+
+        // The key might have been protected using a differently normalized passphrase,
+        // or by an old GnuPG version, using the Latin-1 encoding of the passphrase.
+        for alternative in alternative_passphrases(&pw, &self.string_to_key) {
+            if let Ok(params) = self.unlock_with(&alternative, alg, aad, limits) {
+                debug!("unlocked using an alternative passphrase encoding");
+                return Ok(params);
+            }
+        }
+
+        res
+    }
+
+    fn unlock_with(
+        &self,
+        pw: &[u8],
+        alg: PublicKeyAlgorithm,
+        aad: &[u8],
+        limits: &Argon2Limits,
+    ) -> Result<PlainSecretParams> {
+        let key = self.string_to_key.derive_key_with_limits(
+            pw,
+            self.encryption_algorithm.key_size(),
+            limits,
+        )?;
+
+        if let Some(aead) = self.aead_algorithm {
+            let mut kek = secret_key_encryption_key(&key, self.encryption_algorithm, aead, aad)?;
+            let mut plaintext = self.data.clone();
+            let res = aead.decrypt_in_place(
+                self.encryption_algorithm,
+                &kek,
+                &self.iv,
+                aad,
+                &mut plaintext,
+            );
+            kek.zeroize();
+            if res.is_err() {
+                bail!("invalid secret key data, wrong passphrase?");
+            }
+
+            let params = PlainSecretParams::from_slice(&plaintext, alg);
+            plaintext.zeroize();
+            return params;
+        }
 
         // Actual decryption
         let mut plaintext = self.data.clone();
         self.encryption_algorithm
             .decrypt_with_iv_regular(&key, &self.iv, &mut plaintext)?;
 
-        PlainSecretParams::from_slice(&plaintext, alg)
+        let data = match self.string_to_key_id {
+            S2K_USAGE_SHA1 => {
+                ensure!(plaintext.len() > 20, "invalid secret key data");
+                let (data, cs) = plaintext.split_at(plaintext.len() - 20);
+                ensure!(
+                    checksum::calculate_sha1(data) == cs,
+                    "invalid checksum, wrong passphrase?"
+                );
+                data
+            }
+            S2K_USAGE_CHECKSUM => {
+                ensure!(plaintext.len() > 2, "invalid secret key data");
+                let (data, cs) = plaintext.split_at(plaintext.len() - 2);
+                ensure!(
+                    checksum::calculate_simple(data).to_be_bytes() == cs,
+                    "invalid checksum, wrong passphrase?"
+                );
+                data
+            }
+            _ => &plaintext[..],
+        };
+
+        PlainSecretParams::from_slice(data, alg)
+    }
+}
+
+/// Derives the key that AEAD protected secret key material is encrypted with, from the
+/// string-to-key derived `key`, using HKDF-SHA256.
+///
+/// The info is taken from `aad`, the packet header octet and the key version, followed
+/// by the algorithms.
+/// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#section-5.5.3-3.6
+pub(crate) fn secret_key_encryption_key(
+    key: &[u8],
+    alg: SymmetricKeyAlgorithm,
+    aead: AeadAlgorithm,
+    aad: &[u8],
+) -> Result<Vec<u8>> {
+    ensure!(
+        aad.len() >= 2,
+        "missing the public key of AEAD protected key material"
+    );
+    ensure!(aead != AeadAlgorithm::None, "missing AEAD algorithm");
+
+    let info = [aad[0], aad[1], alg as u8, aead as u8];
+    let mut kek = vec![0u8; alg.key_size()];
+    Hkdf::<Sha256>::new(None, key)
+        .expand(&info, &mut kek)
+        .map_err(|_| format_err!("invalid key derivation length"))?;
+
+    Ok(kek)
+}
+
+impl EncryptedSecretParams {
+    /// Serializes the params in the v6 key format, which prefixes the
+    /// algorithm specific fields and the string-to-key specifier with their length.
+    /// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-secret-key-packet-formats
+    pub fn to_writer_v6<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+        ensure!(
+            self.string_to_key_id == S2K_USAGE_AEAD || self.string_to_key_id == S2K_USAGE_SHA1,
+            "string to key usage {} is not allowed for v6 keys",
+            self.string_to_key_id
+        );
+
+        let mut s2k = Vec::new();
+        self.string_to_key.to_writer(&mut s2k)?;
+
+        let mut fields = vec![self.encryption_algorithm as u8];
+        if let Some(aead) = self.aead_algorithm {
+            fields.push(aead as u8);
+        }
+        fields.push(s2k.len() as u8);
+        fields.extend_from_slice(&s2k);
+        fields.extend_from_slice(&self.iv);
+
+        writer.write_all(&[self.string_to_key_id, fields.len() as u8])?;
+        writer.write_all(&fields)?;
+        writer.write_all(&self.data)?;
+
+        Ok(())
     }
 }
 
@@ -113,11 +290,20 @@ impl Serialize for EncryptedSecretParams {
         writer.write_all(&[self.string_to_key_id])?;
 
         match self.string_to_key_id {
-            0 => panic!("encrypted secret params should not have an unecrypted identifier"),
-            1..=253 => {
+            S2K_USAGE_UNPROTECTED => {
+                panic!("encrypted secret params should not have an unecrypted identifier")
+            }
+            1..=252 => {
+                writer.write_all(&self.iv)?;
+            }
+            S2K_USAGE_AEAD => {
+                let aead = self.aead_algorithm.expect("aead protected");
+
+                writer.write_all(&[self.encryption_algorithm as u8, aead as u8])?;
+                self.string_to_key.to_writer(writer)?;
                 writer.write_all(&self.iv)?;
             }
-            254..=255 => {
+            S2K_USAGE_SHA1..=S2K_USAGE_CHECKSUM => {
                 let s2k = &self.string_to_key;
 
                 writer.write_all(&[self.encryption_algorithm as u8])?;
@@ -142,6 +328,7 @@ impl fmt::Debug for EncryptedSecretParams {
             .field("checksum", &self.checksum().map(hex::encode))
             .field("iv", &hex::encode(&self.iv))
             .field("encryption_algorithm", &self.encryption_algorithm)
+            .field("aead_algorithm", &self.aead_algorithm)
             .field("string_to_key", &self.string_to_key)
             .field("string_to_key_id", &self.string_to_key_id)
             .finish()
@@ -6,7 +6,9 @@ use rand::{CryptoRng, Rng};
 use rsa::RSAPrivateKey;
 use zeroize::Zeroize;
 
-use crate::crypto::{checksum, ECCCurve, PublicKeyAlgorithm, SymmetricKeyAlgorithm};
+use crate::crypto::{
+    checksum, nist, AeadAlgorithm, ECCCurve, PublicKeyAlgorithm, SymmetricKeyAlgorithm,
+};
 use crate::errors::Result;
 use crate::ser::Serialize;
 use crate::types::*;
@@ -15,12 +17,25 @@ use crate::util::TeeWriter;
 #[derive(Clone, PartialEq, Eq, Zeroize)]
 #[zeroize(drop)]
 pub enum PlainSecretParams {
-    RSA { d: Mpi, p: Mpi, q: Mpi, u: Mpi },
+    RSA {
+        d: Mpi,
+        p: Mpi,
+        q: Mpi,
+        u: Mpi,
+    },
     DSA(Mpi),
     ECDSA(Mpi),
     ECDH(Mpi),
     Elgamal(Mpi),
     EdDSA(Mpi),
+    /// The native 32 octets secret key.
+    X25519(Vec<u8>),
+    /// The native 32 octets secret key.
+    Ed25519(Vec<u8>),
+    /// The native 56 octets secret key.
+    X448(Vec<u8>),
+    /// The native 57 octets secret key.
+    Ed448(Vec<u8>),
 }
 
 #[derive(Clone, PartialEq, Eq)]
@@ -36,6 +51,10 @@ pub enum PlainSecretParamsRef<'a> {
     ECDH(MpiRef<'a>),
     Elgamal(MpiRef<'a>),
     EdDSA(MpiRef<'a>),
+    X25519(&'a [u8]),
+    Ed25519(&'a [u8]),
+    X448(&'a [u8]),
+    Ed448(&'a [u8]),
 }
 
 impl<'a> PlainSecretParamsRef<'a> {
@@ -58,14 +77,18 @@ impl<'a> PlainSecretParamsRef<'a> {
             PlainSecretParamsRef::ECDH(v) => PlainSecretParams::ECDH((*v).to_owned()),
             PlainSecretParamsRef::Elgamal(v) => PlainSecretParams::Elgamal((*v).to_owned()),
             PlainSecretParamsRef::EdDSA(v) => PlainSecretParams::EdDSA((*v).to_owned()),
+            PlainSecretParamsRef::X25519(v) => PlainSecretParams::X25519(v.to_vec()),
+            PlainSecretParamsRef::Ed25519(v) => PlainSecretParams::Ed25519(v.to_vec()),
+            PlainSecretParamsRef::X448(v) => PlainSecretParams::X448(v.to_vec()),
+            PlainSecretParamsRef::Ed448(v) => PlainSecretParams::Ed448(v.to_vec()),
         }
     }
 
     pub fn string_to_key_id(&self) -> u8 {
-        0
+        S2K_USAGE_UNPROTECTED
     }
 
-    fn to_writer_raw<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+    pub(crate) fn to_writer_raw<W: io::Write>(&self, writer: &mut W) -> Result<()> {
         match self {
             PlainSecretParamsRef::RSA { d, p, q, u } => {
                 (*d).to_writer(writer)?;
@@ -88,6 +111,12 @@ impl<'a> PlainSecretParamsRef<'a> {
             PlainSecretParamsRef::EdDSA(x) => {
                 (*x).to_writer(writer)?;
             }
+            PlainSecretParamsRef::X25519(x)
+            | PlainSecretParamsRef::Ed25519(x)
+            | PlainSecretParamsRef::X448(x)
+            | PlainSecretParamsRef::Ed448(x) => {
+                writer.write_all(x)?;
+            }
         }
 
         Ok(())
@@ -155,6 +184,14 @@ impl<'a> PlainSecretParamsRef<'a> {
                             secret,
                         }))
                     }
+                    ECCCurve::P256 | ECCCurve::P384 | ECCCurve::P521 => {
+                        Ok(SecretKeyRepr::NistECDH(NistECDHSecretKey {
+                            oid: curve.oid(),
+                            hash: *hash,
+                            alg_sym: *alg_sym,
+                            secret: nist::pad_to_field_size(curve, d.as_bytes())?,
+                        }))
+                    }
                     _ => unsupported_err!("curve {:?} for ECDH", curve.to_string()),
                 },
                 _ => unreachable!("inconsistent key state"),
@@ -176,15 +213,67 @@ impl<'a> PlainSecretParamsRef<'a> {
                 },
                 _ => unreachable!("inconsistent key state"),
             },
-            PlainSecretParamsRef::DSA(_) => {
-                unimplemented_err!("DSA");
+            PlainSecretParamsRef::X25519(x) => {
+                ensure_eq!(x.len(), 32, "invalid secret");
+
+                let mut secret = [0u8; 32];
+                secret.copy_from_slice(x);
+
+                Ok(SecretKeyRepr::X25519(X25519SecretKey { secret }))
+            }
+            PlainSecretParamsRef::Ed25519(x) => {
+                ensure_eq!(x.len(), 32, "invalid secret");
+
+                let mut secret = [0u8; 32];
+                secret.copy_from_slice(x);
+
+                Ok(SecretKeyRepr::Ed25519(Ed25519SecretKey { secret }))
             }
-            PlainSecretParamsRef::Elgamal(_) => {
-                unimplemented_err!("Elgamal");
+            PlainSecretParamsRef::X448(x) => {
+                ensure_eq!(x.len(), 56, "invalid secret");
+
+                Ok(SecretKeyRepr::X448(X448SecretKey { secret: x.to_vec() }))
             }
-            PlainSecretParamsRef::ECDSA(_) => {
-                unimplemented_err!("ECDSA");
+            PlainSecretParamsRef::Ed448(x) => {
+                ensure_eq!(x.len(), 57, "invalid secret");
+
+                Ok(SecretKeyRepr::Ed448(Ed448SecretKey { secret: x.to_vec() }))
             }
+            PlainSecretParamsRef::DSA(_) => {
+                unimplemented_err!("DSA");
+            }
+            PlainSecretParamsRef::Elgamal(x) => match public_params {
+                PublicParams::Elgamal { ref p, .. } => {
+                    Ok(SecretKeyRepr::Elgamal(ElgamalSecretKey {
+                        p: p.into(),
+                        x: x.into(),
+                    }))
+                }
+                _ => unreachable!("inconsistent key state"),
+            },
+            PlainSecretParamsRef::ECDSA(d) => match public_params {
+                PublicParams::ECDSA { ref curve, .. } => match *curve {
+                    ECCCurve::Secp256k1 => {
+                        ensure!(d.len() <= 32, "invalid secret");
+
+                        let mut secret = [0u8; 32];
+                        secret[32 - d.len()..].copy_from_slice(d.as_bytes());
+
+                        Ok(SecretKeyRepr::ECDSA(ECDSASecretKey {
+                            oid: curve.oid(),
+                            secret,
+                        }))
+                    }
+                    ECCCurve::P256 | ECCCurve::P384 | ECCCurve::P521 => {
+                        Ok(SecretKeyRepr::NistECDSA(NistECDSASecretKey {
+                            oid: curve.oid(),
+                            secret: nist::pad_to_field_size(curve, d.as_bytes())?,
+                        }))
+                    }
+                    _ => unsupported_err!("curve {:?} for ECDSA", curve.to_string()),
+                },
+                _ => unreachable!("inconsistent key state"),
+            },
         }
     }
 }
@@ -220,9 +309,32 @@ impl PlainSecretParams {
             PlainSecretParams::ECDH(v) => PlainSecretParamsRef::ECDH(v.as_ref()),
             PlainSecretParams::Elgamal(v) => PlainSecretParamsRef::Elgamal(v.as_ref()),
             PlainSecretParams::EdDSA(v) => PlainSecretParamsRef::EdDSA(v.as_ref()),
+            PlainSecretParams::X25519(v) => PlainSecretParamsRef::X25519(v),
+            PlainSecretParams::Ed25519(v) => PlainSecretParamsRef::Ed25519(v),
+            PlainSecretParams::X448(v) => PlainSecretParamsRef::X448(v),
+            PlainSecretParams::Ed448(v) => PlainSecretParamsRef::Ed448(v),
         }
     }
 
+    /// Encrypts the secret key material with the given passphrase and protection settings.
+    pub fn protect<R: CryptoRng + Rng>(
+        self,
+        rng: &mut R,
+        passphrase: &str,
+        params: &S2kParams,
+        version: KeyVersion,
+    ) -> Result<EncryptedSecretParams> {
+        let s2k = params.to_s2k(rng);
+        self.encrypt(
+            rng,
+            passphrase,
+            params.sym_alg,
+            s2k,
+            version,
+            S2K_USAGE_SHA1,
+        )
+    }
+
     pub fn encrypt<R: CryptoRng + Rng>(
         self,
         rng: &mut R,
@@ -239,13 +351,13 @@ impl PlainSecretParams {
         let enc_data = match version {
             KeyVersion::V2 => unsupported_err!("Encryption for V2 keys is not available"),
             KeyVersion::V3 => unimplemented_err!("v3 encryption"),
-            KeyVersion::V4 => {
+            KeyVersion::V4 | KeyVersion::V6 => {
                 let mut data = Vec::new();
                 self.as_ref()
                     .to_writer_raw(&mut data)
                     .expect("preallocated vector");
                 match id {
-                    254 => {
+                    S2K_USAGE_SHA1 => {
                         data.extend_from_slice(&self.checksum_sha1()[..]);
                     }
                     _ => unimplemented_err!("id: {} not implemented yet", id),
@@ -260,6 +372,39 @@ impl PlainSecretParams {
 
         Ok(EncryptedSecretParams::new(enc_data, iv, alg, s2k, id))
     }
+
+    /// Encrypts the secret key material using `aead` (string-to-key usage 253).
+    ///
+    /// `aad` is the packet header octet of the secret key packet, followed by the
+    /// serialized public key, the encryption is bound to it.
+    pub fn encrypt_aead<R: CryptoRng + Rng>(
+        &self,
+        rng: &mut R,
+        passphrase: &str,
+        alg: SymmetricKeyAlgorithm,
+        aead: AeadAlgorithm,
+        s2k: StringToKey,
+        aad: &[u8],
+    ) -> Result<EncryptedSecretParams> {
+        let key = s2k.derive_key(passphrase, alg.key_size())?;
+        let mut kek = secret_key_encryption_key(&key, alg, aead, aad)?;
+
+        let mut nonce = vec![0u8; aead.nonce_size()];
+        rng.fill(&mut nonce[..]);
+
+        let mut data = Vec::new();
+        self.as_ref()
+            .to_writer_raw(&mut data)
+            .expect("preallocated vector");
+        let res = aead.encrypt_in_place(alg, &kek, &nonce, aad, &mut data);
+        kek.zeroize();
+        if let Err(err) = res {
+            data.zeroize();
+            return Err(err);
+        }
+
+        Ok(EncryptedSecretParams::new_aead(data, nonce, alg, aead, s2k))
+    }
 }
 
 impl Serialize for PlainSecretParams {
@@ -297,6 +442,10 @@ impl<'a> fmt::Debug for PlainSecretParamsRef<'a> {
             PlainSecretParamsRef::ECDSA(_) => write!(f, "PlainSecretParams(ECDSA)"),
             PlainSecretParamsRef::ECDH(_) => write!(f, "PlainSecretParams(ECDH)"),
             PlainSecretParamsRef::EdDSA(_) => write!(f, "PlainSecretParams(EdDSA)"),
+            PlainSecretParamsRef::X25519(_) => write!(f, "PlainSecretParams(X25519)"),
+            PlainSecretParamsRef::Ed25519(_) => write!(f, "PlainSecretParams(Ed25519)"),
+            PlainSecretParamsRef::X448(_) => write!(f, "PlainSecretParams(X448)"),
+            PlainSecretParamsRef::Ed448(_) => write!(f, "PlainSecretParams(Ed448)"),
         }
     }
 }
@@ -310,7 +459,11 @@ named_args!(parse_secret_params(alg: PublicKeyAlgorithm) <PlainSecretParamsRef<'
     PublicKeyAlgorithm::Elgamal => do_parse!(x: mpi >> (PlainSecretParamsRef::Elgamal(x)))  |
     PublicKeyAlgorithm::ECDH    => do_parse!(x: mpi >> (PlainSecretParamsRef::ECDH(x)))  |
     PublicKeyAlgorithm::ECDSA   => do_parse!(x: mpi >> (PlainSecretParamsRef::ECDSA(x))) |
-    PublicKeyAlgorithm::EdDSA   => do_parse!(x: mpi >> (PlainSecretParamsRef::EdDSA(x)))
+    PublicKeyAlgorithm::EdDSA   => do_parse!(x: mpi >> (PlainSecretParamsRef::EdDSA(x))) |
+    PublicKeyAlgorithm::X25519  => map!(take!(32), PlainSecretParamsRef::X25519)         |
+    PublicKeyAlgorithm::Ed25519 => map!(take!(32), PlainSecretParamsRef::Ed25519)        |
+    PublicKeyAlgorithm::X448    => map!(take!(56), PlainSecretParamsRef::X448)           |
+    PublicKeyAlgorithm::Ed448   => map!(take!(57), PlainSecretParamsRef::Ed448)
 ));
 
 // Parse the decrpyted private params of an RSA private key.
@@ -39,6 +39,22 @@ pub enum PublicParams {
         curve: ECCCurve,
         q: Mpi,
     },
+    /// The native 32 octets public key.
+    X25519 {
+        public: Vec<u8>,
+    },
+    /// The native 32 octets public key.
+    Ed25519 {
+        public: Vec<u8>,
+    },
+    /// The native 56 octets public key.
+    X448 {
+        public: Vec<u8>,
+    },
+    /// The native 57 octets public key.
+    Ed448 {
+        public: Vec<u8>,
+    },
 }
 
 impl Serialize for PublicParams {
@@ -103,6 +119,12 @@ impl Serialize for PublicParams {
 
                 q.to_writer(writer)?;
             }
+            PublicParams::X25519 { ref public }
+            | PublicParams::Ed25519 { ref public }
+            | PublicParams::X448 { ref public }
+            | PublicParams::Ed448 { ref public } => {
+                writer.write_all(public)?;
+            }
         }
 
         Ok(())
@@ -162,6 +184,22 @@ impl fmt::Debug for PublicParams {
                 .field("curve", curve)
                 .field("q", &q)
                 .finish(),
+            PublicParams::X25519 { ref public } => f
+                .debug_struct("PublicParams::X25519")
+                .field("public", &hex::encode(public))
+                .finish(),
+            PublicParams::Ed25519 { ref public } => f
+                .debug_struct("PublicParams::Ed25519")
+                .field("public", &hex::encode(public))
+                .finish(),
+            PublicParams::X448 { ref public } => f
+                .debug_struct("PublicParams::X448")
+                .field("public", &hex::encode(public))
+                .finish(),
+            PublicParams::Ed448 { ref public } => f
+                .debug_struct("PublicParams::Ed448")
+                .field("public", &hex::encode(public))
+                .finish(),
         }
     }
 }
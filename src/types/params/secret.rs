@@ -1,9 +1,10 @@
 use std::io;
 
-use nom::{be_u8, rest_len};
+use nom::{be_u8, rest, rest_len};
 use num_traits::FromPrimitive;
 use zeroize::Zeroize;
 
+use crate::crypto::aead::AeadAlgorithm;
 use crate::crypto::public_key::PublicKeyAlgorithm;
 use crate::crypto::sym::SymmetricKeyAlgorithm;
 use crate::errors::Result;
@@ -16,6 +17,162 @@ use crate::types::*;
 pub enum SecretParams {
     Plain(PlainSecretParams),
     Encrypted(EncryptedSecretParams),
+    /// The secret material is not present.
+    Stub(SecretKeyStub),
+}
+
+/// A placeholder for secret key material that is stored elsewhere, or not at all,
+/// e.g. the primary key of a key that is kept offline.
+///
+/// Stubs are always written the way current GnuPG versions write them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretKeyStub {
+    /// The GnuPG `gnu-dummy` extension, a private string-to-key type without any data.
+    GnuDummy,
+    /// The GnuPG `gnu-divert-to-card` extension, the secret key material is stored
+    /// on the smartcard with the given serial number.
+    DivertToCard { serial: Vec<u8> },
+}
+
+/// The marker of GnuPG extensions, following the private string-to-key type 101.
+const GNU_EXTENSION: &[u8] = b"GNU";
+const GNU_DUMMY: u8 = 1;
+const GNU_DIVERT_TO_CARD: u8 = 2;
+
+/// String-to-key usage of unprotected secret key material.
+/// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-secret-key-encryption
+pub const S2K_USAGE_UNPROTECTED: u8 = 0;
+/// String-to-key usage of secret key material protected using AEAD.
+pub const S2K_USAGE_AEAD: u8 = 253;
+/// String-to-key usage of CFB encrypted secret key material, with a SHA-1 hash.
+pub const S2K_USAGE_SHA1: u8 = 254;
+/// String-to-key usage of CFB encrypted secret key material, with a two octet checksum.
+/// Not allowed for v6 keys.
+pub const S2K_USAGE_CHECKSUM: u8 = 255;
+/// The length of the string-to-key specifier of GnuPG extensions, including the mode.
+const GNU_S2K_LEN: u8 = 6;
+/// GnuPG stores at most 16 bytes of the card serial number.
+const MAX_SERIAL_LEN: usize = 16;
+
+impl Serialize for SecretKeyStub {
+    fn to_writer<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+        // usage, unencrypted, s2k type and hash
+        writer.write_all(&[S2K_USAGE_CHECKSUM, 0, 101, 0])?;
+        self.write_extension(writer)
+    }
+}
+
+/// Parses the header of a GnuPG extension, returning the extension mode.
+///
+/// The usage, symmetric algorithm and hash bytes are ignored, different GnuPG versions
+/// write different values there.
+#[rustfmt::skip]
+named!(parse_gnu_extension<u8>, do_parse!(
+          alt!(tag!(&[S2K_USAGE_SHA1][..]) | tag!(&[S2K_USAGE_CHECKSUM][..]))
+    >>    be_u8
+    >>    tag!(&[101u8][..])
+    >>    be_u8
+    >>    tag!(GNU_EXTENSION)
+    >> mode: be_u8
+    >> (mode)
+));
+
+/// Parses the header of a GnuPG extension in a v6 key, returning the extension mode.
+///
+/// The string-to-key fields are prefixed with their length, the mode is the last byte of
+/// the string-to-key specifier.
+#[rustfmt::skip]
+named!(parse_gnu_extension_v6<u8>, do_parse!(
+          tag!(&[S2K_USAGE_SHA1][..])
+    >>    be_u8
+    >>    be_u8
+    >>    tag!(&[GNU_S2K_LEN][..])
+    >>    tag!(&[101u8][..])
+    >>    be_u8
+    >>    tag!(GNU_EXTENSION)
+    >> mode: be_u8
+    >> (mode)
+));
+
+impl SecretKeyStub {
+    /// Parses a stub, returns `None` if `data` is not a GnuPG extension.
+    fn from_slice(data: &[u8]) -> Result<Option<Self>> {
+        match parse_gnu_extension(data) {
+            Ok((rest, mode)) => Self::from_mode(mode, rest).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Parses a stub of a v6 key, returns `None` if `data` is not a GnuPG extension.
+    fn from_slice_v6(data: &[u8]) -> Result<Option<Self>> {
+        match parse_gnu_extension_v6(data) {
+            Ok((rest, mode)) => Self::from_mode(mode, rest).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Parses the data following the extension `mode`.
+    fn from_mode(mode: u8, rest: &[u8]) -> Result<Self> {
+        match mode {
+            GNU_DUMMY => {
+                ensure!(
+                    rest.is_empty(),
+                    "unexpected {} bytes after gnu-dummy secret key stub",
+                    rest.len()
+                );
+                Ok(SecretKeyStub::GnuDummy)
+            }
+            GNU_DIVERT_TO_CARD => {
+                // older versions did not write the serial number
+                let serial = match rest.split_first() {
+                    Some((&len, serial)) => {
+                        ensure_eq!(
+                            serial.len(),
+                            usize::from(len),
+                            "invalid card serial number length"
+                        );
+                        ensure!(
+                            serial.len() <= MAX_SERIAL_LEN,
+                            "card serial number too long: {} bytes",
+                            serial.len()
+                        );
+                        serial.to_vec()
+                    }
+                    None => Vec::new(),
+                };
+                Ok(SecretKeyStub::DivertToCard { serial })
+            }
+            _ => unsupported_err!("GnuPG secret key extension mode {}", mode),
+        }
+    }
+
+    /// Serializes the stub in the v6 key format, using usage 254, as 255 is not allowed.
+    fn to_writer_v6<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+        // usage, length of the following fields, unencrypted, s2k length, s2k type and hash
+        writer.write_all(&[S2K_USAGE_SHA1, 2 + GNU_S2K_LEN, 0, GNU_S2K_LEN, 101, 0])?;
+        self.write_extension(writer)
+    }
+
+    /// Writes the extension marker, the mode and its data.
+    fn write_extension<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(GNU_EXTENSION)?;
+        match self {
+            SecretKeyStub::GnuDummy => {
+                writer.write_all(&[GNU_DUMMY])?;
+            }
+            SecretKeyStub::DivertToCard { serial } => {
+                ensure!(
+                    serial.len() <= MAX_SERIAL_LEN,
+                    "card serial number too long: {} bytes",
+                    serial.len()
+                );
+                writer.write_all(&[GNU_DIVERT_TO_CARD, serial.len() as u8])?;
+                writer.write_all(serial)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Zeroize for SecretParams {
@@ -23,6 +180,7 @@ impl Zeroize for SecretParams {
         match self {
             SecretParams::Plain(p) => p.zeroize(),
             SecretParams::Encrypted(_) => { /* encrypted params do not need zeroing */ }
+            SecretParams::Stub(_) => {}
         }
     }
 }
@@ -30,12 +188,24 @@ impl Zeroize for SecretParams {
 impl SecretParams {
     pub fn is_encrypted(&self) -> bool {
         match self {
-            SecretParams::Plain(_) => false,
+            SecretParams::Plain(_) | SecretParams::Stub(_) => false,
             SecretParams::Encrypted(_) => true,
         }
     }
 
+    /// Returns `true` if the secret material is not present.
+    pub fn is_stub(&self) -> bool {
+        match self {
+            SecretParams::Stub(_) => true,
+            _ => false,
+        }
+    }
+
     pub fn from_slice(data: &[u8], alg: PublicKeyAlgorithm) -> Result<Self> {
+        if let Some(stub) = SecretKeyStub::from_slice(data)? {
+            return Ok(SecretParams::Stub(stub));
+        }
+
         let (_, (params, cs)) = parse_secret_fields(data, alg)?;
 
         params.compare_checksum(cs)?;
@@ -43,10 +213,34 @@ impl SecretParams {
         Ok(params)
     }
 
+    /// Parses the secret fields of a v6 key, which have no checksum when unencrypted.
+    pub fn from_slice_v6(data: &[u8], alg: PublicKeyAlgorithm) -> Result<Self> {
+        if let Some(stub) = SecretKeyStub::from_slice_v6(data)? {
+            return Ok(SecretParams::Stub(stub));
+        }
+
+        let (_, params) = parse_secret_fields_v6(data, alg)?;
+
+        Ok(params)
+    }
+
+    /// Serializes the secret fields in the v6 key format.
+    pub fn to_writer_v6<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+        match self {
+            SecretParams::Plain(k) => {
+                writer.write_all(&[0])?;
+                k.as_ref().to_writer_raw(writer)
+            }
+            SecretParams::Encrypted(k) => k.to_writer_v6(writer),
+            SecretParams::Stub(k) => k.to_writer_v6(writer),
+        }
+    }
+
     pub fn string_to_key_id(&self) -> u8 {
         match self {
             SecretParams::Plain(k) => k.string_to_key_id(),
             SecretParams::Encrypted(k) => k.string_to_key_id(),
+            SecretParams::Stub(_) => S2K_USAGE_CHECKSUM,
         }
     }
 
@@ -54,6 +248,10 @@ impl SecretParams {
         match self {
             SecretParams::Plain(k) => k.as_ref().compare_checksum_simple(other),
             SecretParams::Encrypted(k) => k.compare_checksum(other),
+            SecretParams::Stub(_) => {
+                ensure!(other.is_none(), "Expected no checksum, but found one");
+                Ok(())
+            }
         }
     }
 
@@ -61,6 +259,7 @@ impl SecretParams {
         match self {
             SecretParams::Plain(k) => Some(k.checksum_simple()),
             SecretParams::Encrypted(k) => k.checksum(),
+            SecretParams::Stub(_) => None,
         }
     }
 }
@@ -70,6 +269,7 @@ impl Serialize for SecretParams {
         match self {
             SecretParams::Plain(k) => k.to_writer(writer),
             SecretParams::Encrypted(k) => k.to_writer(writer),
+            SecretParams::Stub(k) => k.to_writer(writer),
         }
     }
 }
@@ -80,56 +280,124 @@ named_args!(parse_secret_fields(alg: PublicKeyAlgorithm) <(SecretParams, Option<
           s2k_typ: be_u8
     >> enc_params: switch!(value!(s2k_typ),
                    // 0 is no encryption
-                   0       => value!((None, None, None)) |
+                   S2K_USAGE_UNPROTECTED => value!((None, None, None, None)) |
                    // symmetric key algorithm
-                   1..=253 => do_parse!(
+                   1..=252 => do_parse!(
                           sym_alg: map_opt!(
                                     value!(s2k_typ),
                                     SymmetricKeyAlgorithm::from_u8
                                 )
                        >>      iv: take!(sym_alg.block_size())
-                       >> (Some(sym_alg), Some(iv), None)
+                       >> (Some(sym_alg), None, Some(iv), None)
+                   ) |
+                   // symmetric key + aead + string-to-key
+                   S2K_USAGE_AEAD => do_parse!(
+                             sym_alg: map_opt!(
+                                        be_u8,
+                                        SymmetricKeyAlgorithm::from_u8
+                                      )
+                       >>       aead: map_opt!(
+                                        be_u8,
+                                        AeadAlgorithm::from_u8
+                                      )
+                       >>        s2k: s2k_parser
+                       >>      nonce: take!(aead.nonce_size())
+                       >> (Some(sym_alg), Some(aead), Some(nonce), Some(s2k))
                    ) |
                    // symmetric key + string-to-key
-                   254..=255 => do_parse!(
+                   S2K_USAGE_SHA1..=S2K_USAGE_CHECKSUM => do_parse!(
                              sym_alg: map_opt!(
                                         be_u8,
                                         SymmetricKeyAlgorithm::from_u8
                                       )
                        >>        s2k: s2k_parser
                        >>         iv: take!(sym_alg.block_size())
-                       >> (Some(sym_alg), Some(iv), Some(s2k))
+                       >> (Some(sym_alg), None, Some(iv), Some(s2k))
                    )
     )
     >> checksum_len: switch!(value!(s2k_typ),
+        // authentication tag at the end, part of the encrypted part
+        S2K_USAGE_AEAD => value!(0) |
         // 20 octect hash at the end, but part of the encrypted part
-        254 => value!(0) |
+        S2K_USAGE_SHA1 => value!(0) |
         // 2 octet checksum at the end
         _   => value!(2)
     )
     >> data_len: map!(rest_len, |r| r - checksum_len)
     >>     data: take!(data_len)
     >> checksum: cond!(checksum_len > 0, take!(checksum_len))
-    >> ({
-        let encryption_algorithm = enc_params.0;
-        let iv = enc_params.1.map(|iv| iv.to_vec());
-        let string_to_key = enc_params.2;
-
-        let res = match s2k_typ {
-            0 => {
-                let repr = PlainSecretParams::from_slice(data, alg)?;
-                SecretParams::Plain(repr)
-            }
-            _ => {
-                SecretParams::Encrypted(EncryptedSecretParams::new(
-                    data.to_vec(),
-                    iv.expect("encrypted"),
-                    encryption_algorithm.expect("encrypted"),
-                    string_to_key.expect("encrypted"),
-                    s2k_typ,
-                ))
-            }
-        };
-        (res, checksum)
-    })
+    >> (build_secret_params(s2k_typ, enc_params, data, alg)?, checksum)
+));
+
+type EncParams<'a> = (
+    Option<SymmetricKeyAlgorithm>,
+    Option<AeadAlgorithm>,
+    Option<&'a [u8]>,
+    Option<StringToKey>,
+);
+
+fn build_secret_params(
+    s2k_typ: u8,
+    enc_params: EncParams<'_>,
+    data: &[u8],
+    alg: PublicKeyAlgorithm,
+) -> Result<SecretParams> {
+    let (encryption_algorithm, aead_algorithm, iv, string_to_key) = enc_params;
+    let iv = iv.map(|iv| iv.to_vec());
+
+    let res = match s2k_typ {
+        S2K_USAGE_UNPROTECTED => {
+            let repr = PlainSecretParams::from_slice(data, alg)?;
+            SecretParams::Plain(repr)
+        }
+        S2K_USAGE_AEAD => SecretParams::Encrypted(EncryptedSecretParams::new_aead(
+            data.to_vec(),
+            iv.expect("encrypted"),
+            encryption_algorithm.expect("encrypted"),
+            aead_algorithm.expect("aead"),
+            string_to_key.expect("encrypted"),
+        )),
+        _ => SecretParams::Encrypted(EncryptedSecretParams::new(
+            data.to_vec(),
+            iv.expect("encrypted"),
+            encryption_algorithm.expect("encrypted"),
+            string_to_key.expect("encrypted"),
+            s2k_typ,
+        )),
+    };
+
+    Ok(res)
+}
+
+// Parse possibly encrypted private fields of a v6 key.
+// Usage 255 and the legacy usages 1 to 252 are not allowed for v6 keys.
+// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-secret-key-packet-formats
+#[rustfmt::skip]
+named_args!(parse_secret_fields_v6(alg: PublicKeyAlgorithm) <SecretParams>, do_parse!(
+          s2k_typ: be_u8
+    >> enc_params: switch!(value!(s2k_typ),
+                   // 0 is no encryption
+                   S2K_USAGE_UNPROTECTED => value!((None, None, None, None)) |
+                   // symmetric key + aead + string-to-key
+                   S2K_USAGE_AEAD => do_parse!(
+                                   be_u8
+                       >>  sym_alg: map_opt!(be_u8, SymmetricKeyAlgorithm::from_u8)
+                       >>     aead: map_opt!(be_u8, AeadAlgorithm::from_u8)
+                       >>  s2k_len: be_u8
+                       >>      s2k: flat_map!(take!(s2k_len), s2k_parser)
+                       >>    nonce: take!(aead.nonce_size())
+                       >> (Some(sym_alg), Some(aead), Some(nonce), Some(s2k))
+                   ) |
+                   // symmetric key + string-to-key
+                   S2K_USAGE_SHA1 => do_parse!(
+                                   be_u8
+                       >>  sym_alg: map_opt!(be_u8, SymmetricKeyAlgorithm::from_u8)
+                       >>  s2k_len: be_u8
+                       >>      s2k: flat_map!(take!(s2k_len), s2k_parser)
+                       >>       iv: take!(sym_alg.block_size())
+                       >> (Some(sym_alg), None, Some(iv), Some(s2k))
+                   )
+    )
+    >>       data: rest
+    >> (build_secret_params(s2k_typ, enc_params, data, alg)?)
 ));
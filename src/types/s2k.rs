@@ -3,8 +3,10 @@ use std::io;
 use nom::be_u8;
 use num_traits::FromPrimitive;
 use rand::{CryptoRng, Rng};
+use unicode_normalization::UnicodeNormalization;
 
 use crate::crypto::hash::HashAlgorithm;
+use crate::crypto::sym::SymmetricKeyAlgorithm;
 use crate::errors::Result;
 use crate::ser::Serialize;
 
@@ -13,9 +15,97 @@ const EXPBIAS: u32 = 6;
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StringToKey {
     typ: StringToKeyType,
+    /// Unused (`HashAlgorithm::None`) for Argon2.
     hash: HashAlgorithm,
     salt: Option<Vec<u8>>,
     count: Option<u8>,
+    argon2: Option<Argon2Params>,
+}
+
+/// Parameters of the Argon2 string-to-key type.
+/// Ref: https://www.rfc-editor.org/rfc/rfc9580.html#name-argon2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Params {
+    /// Number of passes.
+    pub t: u8,
+    /// Degree of parallelism.
+    pub p: u8,
+    /// Exponent of the memory size in KiB, the memory size is `2^m_enc`.
+    pub m_enc: u8,
+}
+
+/// Upper bounds for the parameters of Argon2 string-to-keys, that are accepted when
+/// deriving keys.
+///
+/// The parameters are chosen by whoever created the packet, without limits a single
+/// packet could make the derivation allocate up to 2 TiB of memory. The default allows
+/// up to 256 MiB, larger limits have to be passed explicitly, for example to
+/// [`Message::decrypt_with_password_and_limits`].
+///
+/// [`Message::decrypt_with_password_and_limits`]: crate::composed::Message::decrypt_with_password_and_limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Limits {
+    /// Maximum number of passes.
+    pub max_t: u8,
+    /// Maximum degree of parallelism.
+    pub max_p: u8,
+    /// Maximum exponent of the memory size in KiB, 18 allows up to 256 MiB.
+    pub max_m_enc: u8,
+}
+
+impl Default for Argon2Limits {
+    fn default() -> Self {
+        Argon2Limits {
+            max_t: 16,
+            max_p: 16,
+            max_m_enc: 18,
+        }
+    }
+}
+
+/// Settings used to protect secret key material with a passphrase,
+/// using an iterated and salted string-to-key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct S2kParams {
+    pub sym_alg: SymmetricKeyAlgorithm,
+    pub hash_alg: HashAlgorithm,
+    /// The coded iteration count, as passed to [`StringToKey::new_iterated`].
+    pub count: u8,
+}
+
+impl Default for S2kParams {
+    fn default() -> Self {
+        S2kParams {
+            sym_alg: SymmetricKeyAlgorithm::AES256,
+            hash_alg: HashAlgorithm::default(),
+            count: 224,
+        }
+    }
+}
+
+impl S2kParams {
+    /// Creates a string-to-key with a fresh salt.
+    pub fn to_s2k<R: CryptoRng + Rng>(&self, rng: &mut R) -> StringToKey {
+        StringToKey::new_iterated(rng, self.hash_alg, self.count)
+    }
+
+    /// Returns `true` if `s2k` is weaker than these settings, because it is not iterated
+    /// or uses fewer iterations.
+    ///
+    /// Argon2 and private string-to-key types are never considered weaker.
+    pub fn is_stronger_than(&self, s2k: &StringToKey) -> bool {
+        match s2k.typ() {
+            StringToKeyType::Simple | StringToKeyType::Salted => true,
+            StringToKeyType::IteratedAndSalted => {
+                s2k.count().unwrap_or_default() < decode_count(self.count)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn decode_count(c: u8) -> usize {
+    ((16u32 + u32::from(c & 15)) << (u32::from(c >> 4) + EXPBIAS)) as usize
 }
 
 impl StringToKey {
@@ -32,6 +122,25 @@ impl StringToKey {
             hash,
             salt: Some(salt),
             count: Some(count),
+            argon2: None,
+        }
+    }
+
+    /// Creates an Argon2 string-to-key, with a random 16 octet salt.
+    /// RFC 9580 recommends `t = 1, p = 4, m_enc = 21` (2 GiB), or
+    /// `t = 3, p = 4, m_enc = 16` (64 MiB) for memory constrained environments.
+    /// Memory sizes above the default [`Argon2Limits`] can only be derived with explicit
+    /// limits.
+    pub fn new_argon2<R: CryptoRng + Rng>(rng: &mut R, t: u8, p: u8, m_enc: u8) -> Self {
+        let mut salt = vec![0u8; 16];
+        rng.fill(&mut salt[..]);
+
+        StringToKey {
+            typ: StringToKeyType::Argon2,
+            hash: HashAlgorithm::None,
+            salt: Some(salt),
+            count: None,
+            argon2: Some(Argon2Params { t, p, m_enc }),
         }
     }
 }
@@ -40,13 +149,7 @@ impl StringToKey {
     /// Converts a coded count into the count.
     /// Ref: https://tools.ietf.org/html/rfc4880#section-3.7.1.3
     pub fn count(&self) -> Option<usize> {
-        match self.count {
-            Some(c) => {
-                let res = ((16u32 + u32::from(c & 15)) << (u32::from(c >> 4) + EXPBIAS)) as usize;
-                Some(res)
-            }
-            None => None,
-        }
+        self.count.map(decode_count)
     }
 
     pub fn salt(&self) -> Option<&[u8]> {
@@ -61,9 +164,36 @@ impl StringToKey {
         self.typ
     }
 
+    pub fn argon2_params(&self) -> Option<&Argon2Params> {
+        self.argon2.as_ref()
+    }
+
     /// String-To-Key methods are used to convert a given password string into a key.
     /// Ref: https://tools.ietf.org/html/rfc4880#section-3.7
     pub fn derive_key(&self, passphrase: &str, key_size: usize) -> Result<Vec<u8>> {
+        self.derive_key_bytes(passphrase.as_bytes(), key_size)
+    }
+
+    /// Same as `derive_key`, but for a passphrase that is already encoded.
+    ///
+    /// Argon2 string-to-keys are limited to the default [`Argon2Limits`].
+    pub fn derive_key_bytes(&self, passphrase: &[u8], key_size: usize) -> Result<Vec<u8>> {
+        self.derive_key_with_limits(passphrase, key_size, &Argon2Limits::default())
+    }
+
+    /// Same as `derive_key_bytes`, but fails if the parameters of an Argon2
+    /// string-to-key exceed `limits`.
+    pub fn derive_key_with_limits(
+        &self,
+        passphrase: &[u8],
+        key_size: usize,
+        limits: &Argon2Limits,
+    ) -> Result<Vec<u8>> {
+        if let Some(ref params) = self.argon2 {
+            let salt = self.salt.as_ref().expect("missing salt");
+            return derive_argon2(params, limits, salt, passphrase, key_size);
+        }
+
         let digest_size = self.hash.digest_size();
         let rounds = (key_size as f32 / digest_size as f32).ceil() as usize;
 
@@ -79,15 +209,15 @@ impl StringToKey {
 
             match self.typ {
                 StringToKeyType::Simple => {
-                    hasher.update(passphrase.as_bytes());
+                    hasher.update(passphrase);
                 }
                 StringToKeyType::Salted => {
                     hasher.update(self.salt.as_ref().expect("missing salt"));
-                    hasher.update(passphrase.as_bytes());
+                    hasher.update(passphrase);
                 }
                 StringToKeyType::IteratedAndSalted => {
                     let salt = self.salt.as_ref().expect("missing salt");
-                    let pw = passphrase.as_bytes();
+                    let pw = passphrase;
                     let data_size = salt.len() + pw.len();
                     // how many bytes are supposed to be hashed
                     let mut count = self.count().expect("missing count");
@@ -126,6 +256,103 @@ impl StringToKey {
     }
 }
 
+fn derive_argon2(
+    params: &Argon2Params,
+    limits: &Argon2Limits,
+    salt: &[u8],
+    passphrase: &[u8],
+    key_size: usize,
+) -> Result<Vec<u8>> {
+    ensure!(params.t > 0, "invalid argon2 passes");
+    ensure!(params.p > 0, "invalid argon2 parallelism");
+    ensure!(
+        params.m_enc < 32 && (1u32 << params.m_enc) >= 8 * u32::from(params.p),
+        "invalid argon2 memory size"
+    );
+    ensure!(
+        params.t <= limits.max_t && params.p <= limits.max_p && params.m_enc <= limits.max_m_enc,
+        "argon2 parameters {:?} exceed the limits {:?}",
+        params,
+        limits
+    );
+
+    let config = argon2::Config {
+        variant: argon2::Variant::Argon2id,
+        version: argon2::Version::Version13,
+        mem_cost: 1 << params.m_enc,
+        time_cost: u32::from(params.t),
+        lanes: u32::from(params.p),
+        thread_mode: argon2::ThreadMode::Sequential,
+        secret: &[],
+        ad: &[],
+        hash_length: key_size as u32,
+    };
+
+    argon2::hash_raw(passphrase, salt, &config).map_err(|err| format_err!("argon2: {}", err))
+}
+
+/// Normalizes the passphrase to Unicode NFC, so that the same passphrase entered on
+/// different platforms derives the same key.
+pub fn normalize_passphrase(passphrase: &str) -> String {
+    passphrase.nfc().collect()
+}
+
+/// The maximum number of alternative passphrases, that are tried when unlocking a key.
+const MAX_ALTERNATIVE_PASSPHRASES: usize = 2;
+
+/// Other encodings of the passphrase, to retry with when unlocking a key protected by
+/// `s2k` fails: the NFC form, which new keys are protected with, the Latin-1 encoding
+/// used by old GnuPG versions, and the NFD form.
+///
+/// Every attempt derives the key again, so at most [`MAX_ALTERNATIVE_PASSPHRASES`] are
+/// returned, and only the NFC form for Argon2, which is expensive to derive, and newer
+/// than Latin-1 encoded passphrases.
+pub(crate) fn alternative_passphrases(passphrase: &str, s2k: &StringToKey) -> Vec<Vec<u8>> {
+    let nfc: String = passphrase.nfc().collect();
+    if s2k.typ() == StringToKeyType::Argon2 {
+        return if nfc != passphrase {
+            vec![nfc.into_bytes()]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let nfd: String = passphrase.nfd().collect();
+    let candidates = vec![
+        Some(nfc.into_bytes()),
+        latin1_passphrase(passphrase),
+        Some(nfd.into_bytes()),
+    ];
+    let mut res = Vec::new();
+    for candidate in candidates.into_iter().flatten() {
+        if candidate != passphrase.as_bytes() && !res.contains(&candidate) {
+            res.push(candidate);
+        }
+    }
+    res.truncate(MAX_ALTERNATIVE_PASSPHRASES);
+
+    res
+}
+
+/// Latin-1 encoding of the passphrase, as used by old GnuPG versions in a Latin-1 locale.
+/// Returns `None` if the encoding would not differ from UTF-8, or is not possible.
+pub(crate) fn latin1_passphrase(passphrase: &str) -> Option<Vec<u8>> {
+    if passphrase.is_ascii() {
+        return None;
+    }
+
+    passphrase
+        .nfc()
+        .map(|c| {
+            if (c as u32) < 256 {
+                Some(c as u8)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
 /// Available String-To-Key types
 #[repr(u8)]
 #[derive(Debug, PartialEq, Eq, Copy, Clone, FromPrimitive)]
@@ -134,6 +361,7 @@ pub enum StringToKeyType {
     Salted = 1,
     Reserved = 2,
     IteratedAndSalted = 3,
+    Argon2 = 4,
     Private100 = 100,
     Private101 = 101,
     Private102 = 102,
@@ -162,6 +390,8 @@ impl StringToKeyType {
             StringToKeyType::Salted => 9,
             // Salted and iterated has 1 octet hash algorithm, 8 octets salt value and 1 octet count.
             StringToKeyType::IteratedAndSalted => 10,
+            // Argon2 has a 16 octets salt value and 1 octet each for t, p and encoded m.
+            StringToKeyType::Argon2 => 19,
             _ => 0,
         }
     }
@@ -170,11 +400,24 @@ impl StringToKeyType {
 /// Has the given s2k type a salt?
 fn has_salt(typ: StringToKeyType) -> bool {
     match typ {
-        StringToKeyType::Salted | StringToKeyType::IteratedAndSalted => true,
+        StringToKeyType::Salted | StringToKeyType::IteratedAndSalted | StringToKeyType::Argon2 => {
+            true
+        }
         _ => false,
     }
 }
 
+fn salt_len(typ: StringToKeyType) -> usize {
+    match typ {
+        StringToKeyType::Argon2 => 16,
+        _ => 8,
+    }
+}
+
+fn has_hash(typ: StringToKeyType) -> bool {
+    typ != StringToKeyType::Argon2
+}
+
 /// Has the given s2k type a count?
 fn has_count(typ: StringToKeyType) -> bool {
     match typ {
@@ -185,21 +428,31 @@ fn has_count(typ: StringToKeyType) -> bool {
 
 #[rustfmt::skip]
 named!(pub s2k_parser<StringToKey>, do_parse!(
-         typ: map_opt!(be_u8, StringToKeyType::from_u8)
-    >>  hash: map_opt!(be_u8, HashAlgorithm::from_u8)
-    >>  salt: cond!(has_salt(typ), map!(take!(8), |v| v.to_vec()))
-    >> count: cond!(has_count(typ), be_u8)
+          typ: map_opt!(be_u8, StringToKeyType::from_u8)
+    >>   hash: cond!(has_hash(typ), map_opt!(be_u8, HashAlgorithm::from_u8))
+    >>   salt: cond!(has_salt(typ), map!(take!(salt_len(typ)), |v| v.to_vec()))
+    >>  count: cond!(has_count(typ), be_u8)
+    >> argon2: cond!(typ == StringToKeyType::Argon2, do_parse!(
+                     t: be_u8
+                  >> p: be_u8
+                  >> m_enc: be_u8
+                  >> (Argon2Params { t, p, m_enc })
+               ))
     >> (StringToKey {
         typ,
-        hash,
+        hash: hash.unwrap_or(HashAlgorithm::None),
         salt,
         count,
+        argon2,
     })
 ));
 
 impl Serialize for StringToKey {
     fn to_writer<W: io::Write>(&self, writer: &mut W) -> Result<()> {
-        writer.write_all(&[self.typ as u8, self.hash as u8])?;
+        writer.write_all(&[self.typ as u8])?;
+        if has_hash(self.typ) {
+            writer.write_all(&[self.hash as u8])?;
+        }
 
         if let Some(ref salt) = self.salt {
             writer.write_all(salt)?;
@@ -209,6 +462,97 @@ impl Serialize for StringToKey {
             writer.write_all(&[count])?;
         }
 
+        if let Some(ref params) = self.argon2 {
+            writer.write_all(&[params.t, params.p, params.m_enc])?;
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latin1_passphrase() {
+        assert_eq!(latin1_passphrase("hello"), None);
+        assert_eq!(latin1_passphrase("caf\u{e9}"), Some(b"caf\xe9".to_vec()));
+        assert_eq!(latin1_passphrase("cafe\u{301}"), Some(b"caf\xe9".to_vec()));
+        assert_eq!(latin1_passphrase("\u{20ac}"), None);
+    }
+
+    #[test]
+    fn test_alternative_passphrases() {
+        let s2k = StringToKey::new_default(&mut rand::thread_rng());
+        assert!(alternative_passphrases("hello", &s2k).is_empty());
+        assert_eq!(
+            alternative_passphrases("cafe\u{301}", &s2k),
+            vec!["caf\u{e9}".as_bytes().to_vec(), b"caf\xe9".to_vec()]
+        );
+        assert_eq!(
+            alternative_passphrases("caf\u{e9}", &s2k),
+            vec![b"caf\xe9".to_vec(), "cafe\u{301}".as_bytes().to_vec()]
+        );
+
+        // Argon2 is only retried with the NFC form
+        let s2k = StringToKey::new_argon2(&mut rand::thread_rng(), 1, 4, 10);
+        assert_eq!(
+            alternative_passphrases("cafe\u{301}", &s2k),
+            vec!["caf\u{e9}".as_bytes().to_vec()]
+        );
+        assert!(alternative_passphrases("caf\u{e9}", &s2k).is_empty());
+    }
+
+    #[test]
+    fn test_argon2_roundtrip() {
+        let s2k = StringToKey::new_argon2(&mut rand::thread_rng(), 1, 4, 10);
+
+        let mut buf = Vec::new();
+        s2k.to_writer(&mut buf).unwrap();
+        assert_eq!(buf.len(), 1 + StringToKeyType::Argon2.param_len());
+
+        let (rest, parsed) = s2k_parser(&buf).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(s2k, parsed);
+    }
+
+    #[test]
+    fn test_argon2_derive() {
+        let mut rng = rand::thread_rng();
+        let s2k = StringToKey::new_argon2(&mut rng, 1, 4, 10);
+
+        let key = s2k.derive_key("password", 32).unwrap();
+        assert_eq!(key.len(), 32);
+        assert_eq!(key, s2k.derive_key("password", 32).unwrap());
+        assert_ne!(key, s2k.derive_key("passw0rd", 32).unwrap());
+
+        // memory size below 8 * p KiB
+        let invalid = StringToKey::new_argon2(&mut rng, 1, 4, 4);
+        assert!(invalid.derive_key("password", 32).is_err());
+
+        // parameters above the limits are refused before allocating anything
+        for &(t, p, m_enc) in &[
+            (1, 4, 31),
+            (1, 4, 21),
+            (1, 4, 19),
+            (255, 4, 10),
+            (1, 255, 10),
+        ] {
+            let expensive = StringToKey::new_argon2(&mut rng, t, p, m_enc);
+            assert!(expensive.derive_key("password", 32).is_err());
+        }
+        let limits = Argon2Limits {
+            max_m_enc: 9,
+            ..Default::default()
+        };
+        assert!(s2k
+            .derive_key_with_limits(b"password", 32, &limits)
+            .is_err());
+        assert_eq!(
+            s2k.derive_key_with_limits(b"password", 32, &Default::default())
+                .unwrap(),
+            key
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::crypto::hash::HashAlgorithm;
 use crate::errors::Result;
+use crate::packet::SignatureType;
 use crate::types::{Mpi, PublicKeyTrait, SecretKeyRepr};
 
 pub trait SecretKeyTrait: PublicKeyTrait {
@@ -15,6 +16,16 @@ pub trait SecretKeyTrait: PublicKeyTrait {
         F: FnOnce() -> String;
 
     fn public_key(&self) -> Self::PublicKey;
+
+    /// Called after a signature of type `typ` was created over `digest`.
+    ///
+    /// Does nothing by default, see [`crate::audit::Audited`].
+    fn record_signature(&self, _typ: SignatureType, _hash_alg: HashAlgorithm, _digest: &[u8]) {}
+
+    /// Called after the session key in `mpis` was decrypted.
+    ///
+    /// Does nothing by default, see [`crate::audit::Audited`].
+    fn record_decryption(&self, _mpis: &[Mpi]) {}
 }
 
 impl<'a, T: SecretKeyTrait> SecretKeyTrait for &'a T {
@@ -38,4 +49,12 @@ impl<'a, T: SecretKeyTrait> SecretKeyTrait for &'a T {
     fn public_key(&self) -> Self::PublicKey {
         (*self).public_key()
     }
+
+    fn record_signature(&self, typ: SignatureType, hash_alg: HashAlgorithm, digest: &[u8]) {
+        (*self).record_signature(typ, hash_alg, digest)
+    }
+
+    fn record_decryption(&self, mpis: &[Mpi]) {
+        (*self).record_decryption(mpis)
+    }
 }
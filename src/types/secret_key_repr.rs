@@ -13,9 +13,16 @@ use crate::crypto::sym::SymmetricKeyAlgorithm;
 pub enum SecretKeyRepr {
     RSA(RSAPrivateKey),
     DSA(DSASecretKey),
-    ECDSA,
+    ECDSA(ECDSASecretKey),
     ECDH(ECDHSecretKey),
+    NistECDSA(NistECDSASecretKey),
+    NistECDH(NistECDHSecretKey),
     EdDSA(EdDSASecretKey),
+    Elgamal(ElgamalSecretKey),
+    X25519(X25519SecretKey),
+    Ed25519(Ed25519SecretKey),
+    X448(X448SecretKey),
+    Ed448(Ed448SecretKey),
 }
 
 /// Secret key for ECDH with Curve25519, the only combination we currently support.
@@ -40,6 +47,46 @@ impl fmt::Debug for ECDHSecretKey {
     }
 }
 
+/// Secret key for ECDSA with the NIST curves P-256, P-384 and P-521.
+#[derive(Clone, PartialEq, Eq, Zeroize)]
+#[zeroize(drop)]
+pub struct NistECDSASecretKey {
+    /// The secret scalar, padded to the field size of the curve.
+    pub secret: Vec<u8>,
+    pub oid: Vec<u8>,
+}
+
+impl fmt::Debug for NistECDSASecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NistECDSASecretKey")
+            .field("secret", &"[..]".to_string())
+            .field("oid", &hex::encode(&self.oid))
+            .finish()
+    }
+}
+
+/// Secret key for ECDH with the NIST curves P-256, P-384 and P-521.
+#[derive(Clone, PartialEq, Eq, Zeroize)]
+#[zeroize(drop)]
+pub struct NistECDHSecretKey {
+    /// The secret scalar, padded to the field size of the curve.
+    pub secret: Vec<u8>,
+    pub hash: HashAlgorithm,
+    pub oid: Vec<u8>,
+    pub alg_sym: SymmetricKeyAlgorithm,
+}
+
+impl fmt::Debug for NistECDHSecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NistECDHSecretKey")
+            .field("secret", &"[..]".to_string())
+            .field("hash", &self.hash)
+            .field("oid", &hex::encode(&self.oid))
+            .field("alg_sym", &self.alg_sym)
+            .finish()
+    }
+}
+
 /// Secret key for EdDSA with Curve25519, the only combination we currently support.
 #[derive(Clone, PartialEq, Eq, Zeroize)]
 #[zeroize(drop)]
@@ -58,6 +105,88 @@ impl fmt::Debug for EdDSASecretKey {
     }
 }
 
+/// Secret key for X25519.
+#[derive(Clone, PartialEq, Eq, Zeroize)]
+#[zeroize(drop)]
+pub struct X25519SecretKey {
+    /// The native 32 octets secret key.
+    pub secret: [u8; 32],
+}
+
+impl fmt::Debug for X25519SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("X25519SecretKey")
+            .field("secret", &"[..]".to_string())
+            .finish()
+    }
+}
+
+/// Secret key for Ed25519.
+#[derive(Clone, PartialEq, Eq, Zeroize)]
+#[zeroize(drop)]
+pub struct Ed25519SecretKey {
+    /// The native 32 octets secret key.
+    pub secret: [u8; 32],
+}
+
+impl fmt::Debug for Ed25519SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ed25519SecretKey")
+            .field("secret", &"[..]".to_string())
+            .finish()
+    }
+}
+
+/// Secret key for X448.
+#[derive(Clone, PartialEq, Eq, Zeroize)]
+#[zeroize(drop)]
+pub struct X448SecretKey {
+    /// The native 56 octets secret key.
+    pub secret: Vec<u8>,
+}
+
+impl fmt::Debug for X448SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("X448SecretKey")
+            .field("secret", &"[..]".to_string())
+            .finish()
+    }
+}
+
+/// Secret key for Ed448.
+#[derive(Clone, PartialEq, Eq, Zeroize)]
+#[zeroize(drop)]
+pub struct Ed448SecretKey {
+    /// The native 57 octets secret key.
+    pub secret: Vec<u8>,
+}
+
+impl fmt::Debug for Ed448SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ed448SecretKey")
+            .field("secret", &"[..]".to_string())
+            .finish()
+    }
+}
+
+/// Secret key for ECDSA with secp256k1, the only curve we currently support.
+#[derive(Clone, PartialEq, Eq, Zeroize)]
+#[zeroize(drop)]
+pub struct ECDSASecretKey {
+    /// The secret scalar.
+    pub secret: [u8; 32],
+    pub oid: Vec<u8>,
+}
+
+impl fmt::Debug for ECDSASecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ECDSASecretKey")
+            .field("secret", &"[..]".to_string())
+            .field("oid", &hex::encode(&self.oid))
+            .finish()
+    }
+}
+
 /// Secret key for DSA.
 #[derive(Clone, PartialEq, Eq, Zeroize)]
 #[zeroize(drop)]
@@ -72,3 +201,22 @@ impl fmt::Debug for DSASecretKey {
             .finish()
     }
 }
+
+/// Secret key for Elgamal, only used for decryption.
+#[derive(Clone, PartialEq, Eq, Zeroize)]
+#[zeroize(drop)]
+pub struct ElgamalSecretKey {
+    /// The prime of the group.
+    pub p: BigUint,
+    /// The secret exponent.
+    pub x: BigUint,
+}
+
+impl fmt::Debug for ElgamalSecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ElgamalSecretKey")
+            .field("p", &self.p)
+            .field("x", &"[..]".to_string())
+            .finish()
+    }
+}
@@ -1,9 +1,77 @@
 use std::io;
 
+use chrono::{SubsecRound, Utc};
+
+use crate::composed::signed_key::newest_signature;
 use crate::errors::Result;
-use crate::packet::{write_packet, Signature, UserAttribute, UserId};
+use crate::packet::{
+    write_packet, PacketRef, RevocationReason, Signature, SignatureConfigBuilder, SignatureType,
+    SignatureVersion, Subpacket, UserAttribute, UserId,
+};
 use crate::ser::Serialize;
-use crate::types::{PublicKeyTrait, Tag};
+use crate::types::{PublicKeyTrait, SecretKeyTrait, Tag};
+
+/// Settings for certifications of user ids of other keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificationOptions {
+    /// One of the certification types, [`SignatureType::CertGeneric`] by default.
+    pub typ: SignatureType,
+    /// If `false`, the certification is marked as local, and should not be exported.
+    pub exportable: bool,
+    /// Makes the certification a trust signature, with the given depth and trust amount.
+    ///
+    /// A depth of 1 makes the certified key a trusted introducer, which can certify
+    /// other keys itself, higher levels allow further delegation.
+    /// An amount of 60 means partial trust, 120 complete trust.
+    pub trust_signature: Option<(u8, u8)>,
+    /// Limits a trust signature to user ids matching this regular expression,
+    /// for example `<[^>]+[@.]example\.com>$`.
+    pub regular_expression: Option<String>,
+}
+
+impl Default for CertificationOptions {
+    fn default() -> Self {
+        CertificationOptions {
+            typ: SignatureType::CertGeneric,
+            exportable: true,
+            trust_signature: None,
+            regular_expression: None,
+        }
+    }
+}
+
+impl CertificationOptions {
+    fn append_subpackets(&self, subpackets: &mut Vec<Subpacket>) -> Result<()> {
+        match self.typ {
+            SignatureType::CertGeneric
+            | SignatureType::CertPersona
+            | SignatureType::CertCasual
+            | SignatureType::CertPositive => {}
+            typ => bail!("{:?} is not a certification type", typ),
+        }
+        if !self.exportable {
+            subpackets.push(Subpacket::ExportableCertification(false));
+        }
+        if let Some((depth, amount)) = self.trust_signature {
+            subpackets.push(Subpacket::TrustSignature(depth, amount));
+        }
+        if let Some(regex) = &self.regular_expression {
+            ensure!(
+                self.trust_signature.is_some(),
+                "regular expressions are only allowed in trust signatures"
+            );
+            ensure!(
+                !regex.contains('\0'),
+                "regular expressions must not contain NUL characters"
+            );
+            let mut regex = regex.as_bytes().to_vec();
+            regex.push(0);
+            subpackets.push(Subpacket::RegularExpression(regex));
+        }
+
+        Ok(())
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct SignedUser {
@@ -31,21 +99,285 @@ impl SignedUser {
         SignedUser { id, signatures }
     }
 
-    /// Verify all signatures. If signatures is empty, this fails.
+    /// Verify all self-signatures made by `key`. If there are none, this fails.
+    ///
+    /// Certifications by other keys are not verified, see [`verify_certifications`].
+    ///
+    /// [`verify_certifications`]: SignedUser::verify_certifications
     pub fn verify(&self, key: &impl PublicKeyTrait) -> Result<()> {
         debug!("verify signed user {:#?}", self);
-        ensure!(!self.signatures.is_empty(), "no signatures found");
+        let mut signatures = self
+            .signatures
+            .iter()
+            .filter(|sig| sig.is_issued_by(key))
+            .peekable();
+        ensure!(signatures.peek().is_some(), "no signatures found");
 
-        for signature in &self.signatures {
+        for signature in signatures {
             signature.verify_certificate(key, Tag::UserId, &self.id)?;
         }
 
         Ok(())
     }
 
+    /// Verify all certifications of this user id of `key`, made by `certifier`.
+    /// If there are none, this fails.
+    pub fn verify_certifications(
+        &self,
+        key: &impl PublicKeyTrait,
+        certifier: &impl PublicKeyTrait,
+    ) -> Result<()> {
+        let mut signatures = self
+            .signatures
+            .iter()
+            .filter(|sig| sig.is_issued_by(certifier))
+            .peekable();
+        ensure!(
+            signatures.peek().is_some(),
+            "no certifications by {:?} found",
+            certifier.key_id()
+        );
+
+        for signature in signatures {
+            signature.verify_third_party_certificate(certifier, key, Tag::UserId, &self.id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Certifies this user id of `key`, attaching a certification made by `certifier`.
+    pub fn certify<F>(
+        &mut self,
+        key: &impl PublicKeyTrait,
+        certifier: &impl SecretKeyTrait,
+        key_pw: F,
+        options: &CertificationOptions,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        let mut hashed_subpackets = vec![Subpacket::SignatureCreationTime(
+            Utc::now().trunc_subsecs(0),
+        )];
+        options.append_subpackets(&mut hashed_subpackets)?;
+
+        let sig = SignatureConfigBuilder::default()
+            .typ(options.typ)
+            .version(SignatureVersion::for_key(certifier))
+            .pub_alg(certifier.algorithm())
+            .hashed_subpackets(hashed_subpackets)
+            .unhashed_subpackets(vec![])
+            .build()?
+            .with_issuer(certifier)
+            .sign_third_party_certificate(certifier, key_pw, key, Tag::UserId, &self.id)?;
+        self.signatures.push(sig);
+
+        Ok(())
+    }
+
     pub fn is_primary(&self) -> bool {
         self.signatures.iter().any(Signature::is_primary)
     }
+
+    /// Returns true if the user id has a revocation signature.
+    ///
+    /// This includes revocations of certifications by other keys, and revocations that
+    /// do not verify. Use [`is_revoked_by`] to check if the user id itself is revoked.
+    ///
+    /// [`is_revoked_by`]: SignedUser::is_revoked_by
+    pub fn is_revoked(&self) -> bool {
+        self.signatures
+            .iter()
+            .any(|sig| sig.typ() == SignatureType::CertRevocation)
+    }
+
+    /// Returns true if the user id has a revocation signature made by `key`, the key
+    /// owning the user id, which verifies.
+    ///
+    /// Revocations that can not be verified are ignored, anyone can attach them.
+    pub fn is_revoked_by(&self, key: &impl PublicKeyTrait) -> bool {
+        self.signatures.iter().any(|sig| {
+            sig.typ() == SignatureType::CertRevocation
+                && sig.is_issued_by(key)
+                && sig.verify_certificate(key, Tag::UserId, &self.id).is_ok()
+        })
+    }
+
+    /// Returns true if `certifier` withdrew its certifications of this user id of `key`,
+    /// with a revocation signature which verifies.
+    pub fn is_certification_revoked(
+        &self,
+        key: &impl PublicKeyTrait,
+        certifier: &impl PublicKeyTrait,
+    ) -> bool {
+        self.signatures.iter().any(|sig| {
+            sig.typ() == SignatureType::CertRevocation
+                && sig.is_issued_by(certifier)
+                && sig
+                    .verify_third_party_certificate(certifier, key, Tag::UserId, &self.id)
+                    .is_ok()
+        })
+    }
+
+    /// Revokes this user id, attaching a revocation signature made by `key`.
+    pub fn revoke<F>(
+        &mut self,
+        key: &impl SecretKeyTrait,
+        key_pw: F,
+        reason: RevocationReason,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        self.add_revocation(key, key, key_pw, reason)
+    }
+
+    /// Withdraws the certifications of this user id of `key`, made by `certifier`, by
+    /// attaching a revocation signature made by `certifier`.
+    pub fn revoke_certification<F>(
+        &mut self,
+        key: &impl PublicKeyTrait,
+        certifier: &impl SecretKeyTrait,
+        key_pw: F,
+        reason: RevocationReason,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        ensure!(
+            self.signatures.iter().any(|sig| {
+                sig.typ() != SignatureType::CertRevocation && sig.is_issued_by(certifier)
+            }),
+            "no certifications by {:?} found",
+            certifier.key_id()
+        );
+
+        self.add_revocation(key, certifier, key_pw, reason)
+    }
+
+    /// Approves the third-party certifications of this user id, for which `approve`
+    /// returns `true`, by attaching an attestation signature made by `key`.
+    ///
+    /// `key` has to be the key owning the user id. The attestation replaces earlier ones
+    /// made by `key`, so certifications which are not approved again are withdrawn.
+    ///
+    /// The digests are made with the first hash algorithm of at least 256 bits, that the
+    /// newest self-certification of the user id prefers, or SHA2-256.
+    pub fn attest_certifications<F, G>(
+        &mut self,
+        key: &impl SecretKeyTrait,
+        key_pw: F,
+        approve: G,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+        G: Fn(&Signature) -> bool,
+    {
+        let hash_alg = newest_signature(self.signatures.iter().filter(|sig| {
+            sig.is_certification()
+                && sig.is_issued_by(key)
+                && sig.verify_certificate(key, Tag::UserId, &self.id).is_ok()
+        }))
+        .and_then(|sig| {
+            sig.preferred_hash_algs()
+                .iter()
+                .copied()
+                .find(|alg| alg.digest_size() >= 32)
+        })
+        .unwrap_or_default();
+
+        // the digests are listed in sorted order
+        let mut digests = self
+            .signatures
+            .iter()
+            .filter(|sig| sig.is_certification() && !sig.is_issued_by(key) && approve(sig))
+            .map(|sig| sig.attestation_digest(hash_alg))
+            .collect::<Result<Vec<_>>>()?;
+        digests.sort();
+        digests.dedup();
+        let digests = digests.concat();
+
+        let sig = SignatureConfigBuilder::default()
+            .typ(SignatureType::Attestation)
+            .version(SignatureVersion::for_key(key))
+            .pub_alg(key.algorithm())
+            .hash_alg(hash_alg)
+            .hashed_subpackets(vec![
+                Subpacket::SignatureCreationTime(Utc::now().trunc_subsecs(0)),
+                Subpacket::AttestedCertifications(digests),
+            ])
+            .unhashed_subpackets(vec![])
+            .build()?
+            .with_issuer(key)
+            .sign_certificate(key, key_pw, Tag::UserId, &self.id)?;
+        self.signatures
+            .retain(|sig| !(sig.typ() == SignatureType::Attestation && sig.is_issued_by(key)));
+        self.signatures.push(sig);
+
+        Ok(())
+    }
+
+    /// Returns the third-party certifications of this user id, which are approved by the
+    /// newest valid attestation made by `key`, the key owning the user id.
+    pub fn attested_certifications(&self, key: &impl PublicKeyTrait) -> Vec<&Signature> {
+        let attestation = self
+            .signatures
+            .iter()
+            .filter(|sig| {
+                sig.typ() == SignatureType::Attestation
+                    && sig.is_issued_by(key)
+                    && sig.verify_certificate(key, Tag::UserId, &self.id).is_ok()
+            })
+            .max_by_key(|sig| sig.created());
+        let attestation = match attestation {
+            Some(attestation) => attestation,
+            None => return Vec::new(),
+        };
+
+        let hash_alg = attestation.config.hash_alg;
+        let digests = attestation.attested_certifications();
+        self.signatures
+            .iter()
+            .filter(|sig| sig.is_certification() && !sig.is_issued_by(key))
+            .filter(|sig| {
+                sig.attestation_digest(hash_alg)
+                    .map_or(false, |digest| digests.contains(&&digest[..]))
+            })
+            .collect()
+    }
+
+    fn add_revocation<F>(
+        &mut self,
+        key: &impl PublicKeyTrait,
+        signer: &impl SecretKeyTrait,
+        key_pw: F,
+        reason: RevocationReason,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        let sig = SignatureConfigBuilder::default()
+            .typ(SignatureType::CertRevocation)
+            .version(SignatureVersion::for_key(signer))
+            .pub_alg(signer.algorithm())
+            .hashed_subpackets(vec![
+                Subpacket::SignatureCreationTime(Utc::now().trunc_subsecs(0)),
+                reason.into(),
+            ])
+            .unhashed_subpackets(vec![])
+            .build()?
+            .with_issuer(signer)
+            .sign_third_party_certificate(signer, key_pw, key, Tag::UserId, &self.id)?;
+        self.signatures.push(sig);
+
+        Ok(())
+    }
+
+    /// Returns the User ID packet, followed by its signatures.
+    pub fn packets(&self) -> impl Iterator<Item = PacketRef<'_>> {
+        std::iter::once(PacketRef::UserId(&self.id))
+            .chain(self.signatures.iter().map(PacketRef::Signature))
+    }
 }
 
 impl Serialize for SignedUser {
@@ -85,17 +417,40 @@ impl SignedUserAttribute {
         SignedUserAttribute { attr, signatures }
     }
 
-    /// Verify all signatures. If signatures is empty, this fails.
+    /// Verify all self-signatures made by `key`. If there are none, this fails.
     pub fn verify(&self, key: &impl PublicKeyTrait) -> Result<()> {
         debug!("verify signed attribute {:?}", self);
-        ensure!(!self.signatures.is_empty(), "no signatures found");
+        let mut signatures = self
+            .signatures
+            .iter()
+            .filter(|sig| sig.is_issued_by(key))
+            .peekable();
+        ensure!(signatures.peek().is_some(), "no signatures found");
 
-        for signature in &self.signatures {
+        for signature in signatures {
             signature.verify_certificate(key, Tag::UserAttribute, &self.attr)?;
         }
 
         Ok(())
     }
+
+    /// Returns true if the user attribute has a revocation signature made by `key`, the
+    /// key owning it, which verifies.
+    pub fn is_revoked_by(&self, key: &impl PublicKeyTrait) -> bool {
+        self.signatures.iter().any(|sig| {
+            sig.typ() == SignatureType::CertRevocation
+                && sig.is_issued_by(key)
+                && sig
+                    .verify_certificate(key, Tag::UserAttribute, &self.attr)
+                    .is_ok()
+        })
+    }
+
+    /// Returns the User Attribute packet, followed by its signatures.
+    pub fn packets(&self) -> impl Iterator<Item = PacketRef<'_>> {
+        std::iter::once(PacketRef::UserAttribute(&self.attr))
+            .chain(self.signatures.iter().map(PacketRef::Signature))
+    }
 }
 
 impl Serialize for SignedUserAttribute {
@@ -217,6 +217,23 @@ impl<'a, A: hash::Hasher, B: io::Write> io::Write for TeeWriter<'a, A, B> {
     }
 }
 
+/// Writer that discards all data, only counting the number of written bytes.
+#[derive(Debug, Default)]
+pub struct CountingWriter {
+    pub count: usize,
+}
+
+impl io::Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.count += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 /// The same as the std lib, but doesn't choke on write 0. This is a hack, to be compatible with
 /// rust-base64.
 pub fn write_all(writer: &mut impl io::Write, mut buf: &[u8]) -> io::Result<()> {
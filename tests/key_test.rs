@@ -17,6 +17,7 @@ use rsa::padding::PaddingScheme;
 use rsa::{PublicKey as PublicKeyTrait, PublicKeyParts, RSAPrivateKey, RSAPublicKey};
 use smallvec::SmallVec;
 
+use pgp::armor::{self, BlockType};
 use pgp::composed::signed_key::*;
 use pgp::composed::Deserializable;
 use pgp::crypto::{ECCCurve, HashAlgorithm, PublicKeyAlgorithm, SymmetricKeyAlgorithm};
@@ -27,7 +28,7 @@ use pgp::packet::{
 use pgp::ser::Serialize;
 use pgp::types::{
     CompressionAlgorithm, KeyId, KeyTrait, KeyVersion, Mpi, PublicParams, SecretKeyRepr,
-    SecretKeyTrait, SecretParams, SignedUser, StringToKeyType, Version,
+    SecretKeyTrait, SecretParams, SignedUser, StringToKeyType, Tag, Version,
 };
 
 fn read_file<P: AsRef<Path> + ::std::fmt::Debug>(path: P) -> File {
@@ -589,10 +590,37 @@ fn test_fingerprint_rsa() {
 #[test]
 fn test_fingerprint_dsa() {
     let (json, key) = get_test_fingerprint("gnupg-v1-001");
+    key.verify().expect("invalid key");
 
     assert_eq!(json["expected_fingerprint"], hex::encode(key.fingerprint()));
 }
 
+#[test]
+fn test_fingerprint_v5() {
+    // v5 Ed25519 key packet body
+    let body = hex::decode(
+        "055c9a4ff81600\
+         00002d092b060104\
+         01da470f01010740\
+         3f098994bdd916ed\
+         4053197934e4a87c\
+         80733a1280d62f80\
+         10992e43ee3b2406",
+    )
+    .unwrap();
+
+    let key = pgp::packet::PublicKey::from_slice(Version::New, &body).expect("failed to parse key");
+    assert_eq!(key.version(), KeyVersion::V5);
+    assert_eq!(key.algorithm(), PublicKeyAlgorithm::EdDSA);
+    assert_eq!(key.to_bytes().unwrap(), body);
+
+    assert_eq!(
+        hex::encode(key.fingerprint()),
+        "36c8281f40f2c2989a33c28312543a985dc60a2cc50d615e27dc4c4599f29d8c"
+    );
+    assert_eq!(hex::encode(&key.key_id()), "36c8281f40f2c298");
+}
+
 #[test]
 fn test_fingerprint_ecdsa() {
     let (json, key) = get_test_fingerprint("e2e-001");
@@ -603,7 +631,6 @@ fn test_fingerprint_ecdsa() {
 #[test]
 fn test_fingerprint_ecdh() {
     let (json, key) = get_test_fingerprint("gnupg-v1-001");
-    // can't verify: DSA
 
     assert_eq!(
         json["expected_subkeys"].as_array().unwrap()[0]
@@ -973,3 +1000,198 @@ fn test_handle_incomplete_packets_end() {
     let key = SignedSecretKey::from_bytes(Cursor::new(raw)).expect("failed");
     key.verify().expect("invalid key");
 }
+
+#[test]
+fn test_import_stats() {
+    let f = read_file("./tests/opengpg-interop/testcases/keys/gnupg-v1-003.asc");
+    let (key, _headers) = SignedPublicKey::from_armor_single(f).expect("failed to parse key");
+
+    let mut keyring = Vec::new();
+    let stats = import_public_keys(&mut keyring, vec![Ok(key.clone())]);
+    assert_eq!(stats.processed, 1);
+    assert_eq!(stats.new_keys, 1);
+    assert!(stats.is_modified());
+
+    // importing the same key again does not change anything
+    let stats = import_public_keys(&mut keyring, vec![Ok(key.clone())]);
+    assert_eq!(stats.unchanged_keys, 1);
+    assert!(!stats.is_modified());
+
+    // a stripped copy of the key gets its subkeys back, junk is skipped
+    let mut stripped = key.clone();
+    stripped.public_subkeys.clear();
+    let mut keyring = vec![stripped];
+    let stats = import_public_keys(
+        &mut keyring,
+        vec![Ok(key.clone()), Err(Error::Message("junk".into()))],
+    );
+    assert_eq!(stats.processed, 2);
+    assert_eq!(stats.updated_keys, 1);
+    assert_eq!(stats.new_subkeys, key.public_subkeys.len());
+    assert_eq!(stats.skipped, 1);
+    assert_eq!(keyring, vec![key]);
+}
+
+#[test]
+fn test_packets() {
+    let f = read_file("./tests/openpgpjs/x25519.sec.asc");
+    let (sk, _headers) = SignedSecretKey::from_armor_single(f).expect("failed to parse key");
+
+    let tags = sk.packets().map(|p| p.tag()).collect::<Vec<_>>();
+    assert_eq!(tags[0], Tag::SecretKey);
+    assert_eq!(tags.iter().filter(|t| **t == Tag::SecretSubkey).count(), 1);
+
+    // the packets serialize to the same bytes as the key
+    let mut bytes = Vec::new();
+    for packet in sk.packets() {
+        packet.to_writer(&mut bytes).unwrap();
+    }
+    assert_eq!(bytes, sk.to_bytes().unwrap());
+
+    let pk = sk.public_key().sign(&sk, || "moon".to_string()).unwrap();
+    let mut bytes = Vec::new();
+    for packet in pk.packets() {
+        packet.to_writer(&mut bytes).unwrap();
+    }
+    assert_eq!(bytes, pk.to_bytes().unwrap());
+}
+
+#[test]
+fn test_signature_is_issued_by() {
+    let f = read_file("./tests/openpgpjs/x25519.sec.asc");
+    let (sk, _headers) = SignedSecretKey::from_armor_single(f).expect("failed to parse key");
+
+    let sig = &sk.details.users[0].signatures[0];
+    assert!(sig.is_issued_by(&sk.primary_key));
+    assert!(!sig.is_issued_by(&sk.secret_subkeys[0].key));
+}
+
+#[test]
+fn test_armor_block_type_mismatch() {
+    let mut armored = String::new();
+    read_file("./tests/openpgpjs/x25519.sec.asc")
+        .read_to_string(&mut armored)
+        .unwrap();
+    let mislabeled = armored.replace("PGP PRIVATE KEY BLOCK", "PGP PUBLIC KEY BLOCK");
+
+    match SignedSecretKey::from_string(&mislabeled) {
+        Err(Error::ArmorMismatch { typ, tag }) => {
+            assert_eq!(typ, BlockType::PublicKey);
+            assert_eq!(tag, Tag::SecretKey);
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert!(from_armor_many(Cursor::new(&mislabeled)).is_err());
+
+    let mut relabeled = Vec::new();
+    let typ = armor::relabel(Cursor::new(&mislabeled), &mut relabeled).unwrap();
+    assert_eq!(typ, BlockType::PrivateKey);
+
+    let (key, _) = SignedSecretKey::from_armor_single(Cursor::new(&relabeled)).unwrap();
+    let (orig, _) = SignedSecretKey::from_string(&armored).unwrap();
+    assert_eq!(key, orig);
+}
+
+#[test]
+fn test_gnupg_offline_primary() {
+    // exported with `gpg --export-secret-subkeys`, the subkeys are protected with "pw"
+    let mut raw = Vec::new();
+    armor::Dearmor::new(read_file("./tests/gnupg/offline-primary.sec.asc"))
+        .read_to_end(&mut raw)
+        .unwrap();
+    let key = SignedSecretKey::from_bytes(Cursor::new(&raw)).expect("failed to parse key");
+    key.verify().expect("invalid key");
+
+    assert!(key.primary_key.secret_params().is_stub());
+    assert_eq!(key.secret_subkeys.len(), 2);
+    for subkey in &key.secret_subkeys {
+        assert!(subkey.key.secret_params().is_encrypted());
+    }
+    assert_eq!(key.to_bytes().unwrap(), raw, "stubs must roundtrip");
+
+    match key.create_signature(|| "pw".into(), HashAlgorithm::SHA2_256, &[0u8; 32]) {
+        Err(Error::SecretKeyNotPresent { key_id }) => assert_eq!(key_id, key.key_id()),
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    // without the encryption subkey, decryption reports the missing secret material
+    let mut key = key;
+    key.secret_subkeys[1].key.remove_secret();
+    let (msg, _) = pgp::composed::Message::from_armor_single(read_file(
+        "./tests/gnupg/offline-primary.msg.asc",
+    ))
+    .unwrap();
+    match msg.decrypt(|| "".into(), || "pw".into(), &[&key]) {
+        Err(Error::SecretKeyNotPresent { key_id }) => {
+            assert_eq!(key_id, key.secret_subkeys[1].key_id())
+        }
+        res => panic!("unexpected result {:?}", res.map(|(_, ids)| ids)),
+    }
+}
+
+#[test]
+fn test_gnupg_textmode_signature() {
+    // made with `gpg --textmode --detach-sign` by the signing subkey, over a file with
+    // mixed line endings
+    let (key, _) =
+        SignedSecretKey::from_armor_single(read_file("./tests/gnupg/offline-primary.sec.asc"))
+            .unwrap();
+    let (sig, _) = pgp::composed::StandaloneSignature::from_armor_single(read_file(
+        "./tests/gnupg/textmode.txt.asc",
+    ))
+    .unwrap();
+    let sig = sig.signature();
+    assert_eq!(sig.typ(), SignatureType::Text);
+    let subkey = &key.secret_subkeys[0];
+
+    let mut data = Vec::new();
+    read_file("./tests/gnupg/textmode.txt")
+        .read_to_end(&mut data)
+        .unwrap();
+    sig.verify(subkey, &data).expect("invalid signature");
+    sig.verify_reader(subkey, read_file("./tests/gnupg/textmode.txt"))
+        .expect("invalid signature");
+
+    // only the line endings are canonicalized
+    let crlf = String::from_utf8(data.clone())
+        .unwrap()
+        .replace("\r\n", "\n")
+        .replace('\n', "\r\n");
+    sig.verify(subkey, crlf.as_bytes())
+        .expect("invalid signature");
+    let lf = crlf.replace("\r\n", "\n");
+    sig.verify(subkey, lf.as_bytes())
+        .expect("invalid signature");
+    assert!(sig.verify(subkey, lf.trim_end().as_bytes()).is_err());
+}
+
+#[test]
+fn test_gnupg_one_pass_signature() {
+    // made with `gpg -z0 --sign` by the signing subkey, reading from stdin, so the
+    // literal data uses partial body lengths
+    let (key, _) =
+        SignedSecretKey::from_armor_single(read_file("./tests/gnupg/offline-primary.sec.asc"))
+            .unwrap();
+    let keyring = vec![key.signed_public_key()];
+    let mut expected = Vec::new();
+    read_file("./tests/gnupg/onepass.txt")
+        .read_to_end(&mut expected)
+        .unwrap();
+
+    let policy = pgp::composed::Policy::default();
+    let mut reader = pgp::composed::VerifyingReader::new(
+        read_file("./tests/gnupg/onepass.txt.gpg"),
+        &keyring,
+        &policy,
+    )
+    .unwrap();
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).unwrap();
+    assert_eq!(data, expected);
+    let res = reader.verify().expect("invalid signature");
+    assert_eq!(res.fingerprint, key.fingerprint());
+    assert_eq!(
+        res.signing_key_fingerprint,
+        key.secret_subkeys[0].fingerprint()
+    );
+}
@@ -10,10 +10,13 @@ extern crate pretty_env_logger;
 extern crate log;
 
 use std::fs::File;
-use std::io::{Cursor, Read};
+use std::io::{self, Cursor, Read};
 
-use pgp::composed::{Deserializable, Message, SignedPublicKey, SignedSecretKey};
-use pgp::types::KeyTrait;
+use pgp::composed::{Deserializable, Message, SignedPublicKey, SignedSecretKey, StatusEvent};
+use pgp::crypto::HashAlgorithm;
+use pgp::packet::{SignatureConfig, SignatureType, SignatureVersion, Subpacket, UserAttribute};
+use pgp::ser::Serialize;
+use pgp::types::{KeyTrait, SecretKeyTrait, Tag};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -356,3 +359,177 @@ fn msg_literal_signature() {
 
     msg.verify(&pkey).unwrap();
 }
+
+#[test]
+fn msg_literal_signature_status() {
+    let (pkey, _) = SignedPublicKey::from_armor_single(
+        File::open("./tests/autocrypt/alice@autocrypt.example.pub.asc").unwrap(),
+    )
+    .unwrap();
+    let mut msg_file = File::open("./tests/literal-text-signed.asc").unwrap();
+    let (msg, _) = Message::from_armor_single(&mut msg_file).expect("failed to parse message");
+
+    let mut events = Vec::new();
+    msg.verify_with_status(&pkey, &mut |event| events.push(event))
+        .unwrap();
+
+    assert_eq!(events.len(), 1);
+    match events[0] {
+        StatusEvent::GoodSig(_) => {}
+        ref event => panic!("unexpected event: {:?}", event),
+    }
+}
+
+#[test]
+fn msg_decrypt_status() {
+    let mut msg_file = File::open("./tests/indeterminated.asc").unwrap();
+    let (message, _headers) =
+        Message::from_armor_single(&mut msg_file).expect("failed to parse message");
+
+    let mut key_file = File::open("./tests/openpgpjs/x25519.sec.asc").unwrap();
+    let (decrypt_key, _headers) =
+        SignedSecretKey::from_armor_single(&mut key_file).expect("failed to parse key");
+
+    let mut events = Vec::new();
+    message
+        .decrypt_with_status(
+            || "".to_string(),
+            || "moon".to_string(),
+            &[&decrypt_key],
+            &mut |event| events.push(event),
+        )
+        .expect("failed to decrypt message");
+
+    let recipients = message.get_recipients();
+    assert_eq!(events.len(), recipients.len() + 1);
+    assert_eq!(events.last(), Some(&StatusEvent::BeginDecryption));
+}
+
+fn large_test_key() -> SignedSecretKey {
+    let (skey, _headers) = SignedSecretKey::from_armor_single(
+        File::open("./tests/opengpg-interop/testcases/messages/gnupg-v1-001-decrypt.asc").unwrap(),
+    )
+    .unwrap();
+
+    skey
+}
+
+/// Generates `remaining` pseudo random bytes while it is read, without holding them
+/// in memory.
+struct Generator {
+    state: u32,
+    remaining: u64,
+    /// The largest buffer a single read asked for.
+    max_read: usize,
+}
+
+impl Generator {
+    fn new(seed: u32, len: u64) -> Self {
+        Generator {
+            state: seed,
+            remaining: len,
+            max_read: 0,
+        }
+    }
+}
+
+impl Read for Generator {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.max_read = std::cmp::max(self.max_read, buf.len());
+        let n = std::cmp::min(buf.len() as u64, self.remaining) as usize;
+        for b in &mut buf[..n] {
+            self.state = self.state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            *b = (self.state >> 16) as u8;
+        }
+        self.remaining -= n as u64;
+
+        Ok(n)
+    }
+}
+
+/// A packet body, that is generated while it is serialized.
+struct GeneratedPacket(u32, u64);
+
+impl Serialize for GeneratedPacket {
+    fn to_writer<W: io::Write>(&self, writer: &mut W) -> pgp::errors::Result<()> {
+        let mut generator = Generator::new(self.0, self.1);
+        io::copy(&mut generator, writer)?;
+        assert!(generator.max_read <= 64 * 1024);
+
+        Ok(())
+    }
+}
+
+#[test]
+fn sign_verify_reader_large() {
+    const LEN: u64 = 16 << 20;
+
+    let skey = large_test_key();
+    let pkey = skey.public_key();
+
+    let config = SignatureConfig::new_v4(
+        SignatureVersion::V4,
+        SignatureType::Binary,
+        skey.algorithm(),
+        HashAlgorithm::SHA2_256,
+        vec![Subpacket::Issuer(skey.key_id())],
+        vec![],
+    );
+    let mut data = Generator::new(1, LEN);
+    let sig = config
+        .sign_reader(&skey, || "test".into(), &mut data)
+        .unwrap();
+    // the data was consumed in small pieces, not read into a buffer
+    assert_eq!(data.remaining, 0);
+    assert!(data.max_read <= 64 * 1024);
+
+    let mut data = Generator::new(1, LEN);
+    sig.verify_reader(&pkey, &mut data).unwrap();
+    assert_eq!(data.remaining, 0);
+    assert!(data.max_read <= 64 * 1024);
+
+    assert!(sig
+        .verify_reader(&pkey, Generator::new(1, LEN - 1))
+        .is_err());
+    assert!(sig.verify_reader(&pkey, Generator::new(2, LEN)).is_err());
+}
+
+#[test]
+fn certify_verify_user_attribute_large() {
+    let skey = large_test_key();
+    let pkey = skey.public_key();
+
+    let config = SignatureConfig::new_v4(
+        SignatureVersion::V4,
+        SignatureType::CertGeneric,
+        skey.algorithm(),
+        HashAlgorithm::SHA2_256,
+        vec![Subpacket::Issuer(skey.key_id())],
+        vec![],
+    );
+
+    let mut data = vec![0u8; 1 << 20];
+    data[..3].copy_from_slice(&[0xFF, 0xD8, 0xFF]);
+    let attr = UserAttribute::new_image(data).unwrap();
+    let sig = config
+        .clone()
+        .sign_certificate(&skey, || "test".into(), Tag::UserAttribute, &attr)
+        .unwrap();
+    sig.verify_certificate(&pkey, Tag::UserAttribute, &attr)
+        .unwrap();
+
+    // the certified packet is serialized into the hasher, while it is generated
+    let packet = GeneratedPacket(1, 16 << 20);
+    let sig = config
+        .sign_certificate(&skey, || "test".into(), Tag::UserAttribute, &packet)
+        .unwrap();
+    sig.verify_certificate(&pkey, Tag::UserAttribute, &packet)
+        .unwrap();
+    assert!(sig
+        .verify_certificate(
+            &pkey,
+            Tag::UserAttribute,
+            &GeneratedPacket(1, (16 << 20) - 1)
+        )
+        .is_err());
+}